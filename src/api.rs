@@ -0,0 +1,530 @@
+//! Querying a database from Rust without going through SQL. Tables are
+//! opened by name and read or written through builders, which make the
+//! queries the parser would make out of the same SQL:
+//!
+//! ```text
+//! let mut db = Database::open("mydb")?;
+//! let mut table = db.table("users")?;
+//! let rows = table.select().where_eq("id", b"5").limit(10).run()?;
+//! table.insert(Row { data: vec![b"6".to_vec(), b"Ada".to_vec()] })?;
+//! ```
+//!
+//! Reads run as a `SELECT`, through the optimizer, the page cache and the
+//! grants of the user the database was opened as. Writes go through
+//! `Table::add_rows` like an `INSERT`, and deletes through
+//! `Table::delete_at`, constraints and indexes included.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+use crate::cache::query_cache;
+use crate::config::Config;
+use crate::durability::table::{
+    create_table, table_exists, writeable_table_file, Page, Table, TableBuilder,
+};
+use crate::durability::{DatabaseConfig, Durable};
+use crate::query::{
+    expression::SelectExpr, predicate::Predicate, Filter, Query, QuerySource, Scope,
+};
+
+pub use crate::durability::table::{ColumnDefinition, ColumnType, Row};
+pub use crate::query::predicate::Operator;
+
+/// A database kept in a directory, a file per table.
+pub struct Database {
+    database: DatabaseConfig,
+    user: Option<String>,
+}
+
+impl Database {
+    /// Opens the database in `directory`, created when missing.
+    pub fn open(directory: &str) -> Result<Database, String> {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| format!("Error opening database {}: {:?}", directory, e))?;
+        let name = Path::new(directory)
+            .file_name()
+            .map_or("database".into(), |name| name.to_string_lossy());
+        Ok(Database {
+            database: DatabaseConfig {
+                name: name.to_string(),
+                file_path: directory.to_string(),
+                auth_file: None,
+            },
+            user: None,
+        })
+    }
+
+    /// Reads as `user` of `auth_file`, restricted to the columns they were
+    /// granted like a connection of the server.
+    pub fn as_user(mut self, auth_file: &str, user: &str) -> Database {
+        self.database.auth_file = Some(auth_file.to_string());
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// The file of the table `name`.
+    fn table_path(&self, name: &str) -> String {
+        Path::new(&self.database.file_path)
+            .join(name)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Creates the table `name` with `columns` and opens it.
+    pub fn create_table(
+        &mut self,
+        name: &str,
+        columns: Vec<ColumnDefinition>,
+    ) -> Result<DatabaseTable, String> {
+        create_table(TableBuilder::new(&self.table_path(name)).columns(columns))?;
+        self.table(name)
+    }
+
+    /// Opens the table `name`.
+    pub fn table(&mut self, name: &str) -> Result<DatabaseTable, String> {
+        let path = self.table_path(name);
+        if !table_exists(&path) {
+            return Err(format!("Table {} does not exist", name));
+        }
+        let mut file = writeable_table_file(path).map_err(|e| format!("{:?}", e))?;
+        let table = Table::read_from_disk(&mut file)?;
+        Ok(DatabaseTable {
+            table,
+            file,
+            page_cache: HashMap::new(),
+            database: self.database.clone(),
+            config: Config {
+                user: self.user.clone(),
+                ..Config::default()
+            },
+        })
+    }
+}
+
+/// An open table of a `Database`, with the pages read from it cached.
+pub struct DatabaseTable {
+    table: Table,
+    file: File,
+    page_cache: HashMap<String, Page>,
+    database: DatabaseConfig,
+    config: Config,
+}
+
+impl DatabaseTable {
+    pub fn columns(&self) -> &[ColumnDefinition] {
+        &self.table.columns
+    }
+
+    /// Starts a select of the rows of the table, every row until filtered.
+    pub fn select(&mut self) -> SelectBuilder<'_> {
+        SelectBuilder {
+            table: self,
+            columns: vec![],
+            predicates: vec![],
+            limit: None,
+        }
+    }
+
+    /// Starts an insert of rows, written together by `InsertBuilder::run`.
+    pub fn insert_rows(&mut self) -> InsertBuilder<'_> {
+        InsertBuilder {
+            table: self,
+            rows: vec![],
+        }
+    }
+
+    /// Appends `row`, holding a value for every column in their order.
+    pub fn insert(&mut self, row: Row) -> Result<(), String> {
+        self.insert_rows().row(row).run().map(|_| ())
+    }
+
+    /// Starts a delete of the rows of the table, every row until filtered.
+    pub fn delete(&mut self) -> DeleteBuilder<'_> {
+        DeleteBuilder {
+            table: self,
+            predicates: vec![],
+        }
+    }
+
+    /// Drops the cached pages and results of the table once its rows
+    /// changed.
+    fn invalidate_caches(&mut self) {
+        self.page_cache.clear();
+        query_cache()
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .invalidate(self.table.name_str());
+    }
+}
+
+/// The rows of a table matching every comparison added, `SELECT columns
+/// FROM table WHERE ...`.
+pub struct SelectBuilder<'a> {
+    table: &'a mut DatabaseTable,
+    columns: Vec<String>,
+    predicates: Vec<Predicate>,
+    limit: Option<usize>,
+}
+
+impl SelectBuilder<'_> {
+    /// Reads only `columns`, in their order, instead of every column.
+    pub fn columns(mut self, columns: &[&str]) -> Self {
+        self.columns = columns.iter().map(|column| column.to_string()).collect();
+        self
+    }
+
+    /// Keeps the rows whose `column` compares to `value` with `operator`.
+    /// The value is compared as the type of the column.
+    pub fn where_op(mut self, column: &str, operator: Operator, value: &[u8]) -> Self {
+        self.predicates.push(Predicate {
+            expr: SelectExpr::Column(column.to_string()),
+            operator,
+            literal: String::from_utf8_lossy(value).to_string(),
+        });
+        self
+    }
+
+    /// Keeps the rows whose `column` equals `value`.
+    pub fn where_eq(self, column: &str, value: &[u8]) -> Self {
+        self.where_op(column, Operator::Eq, value)
+    }
+
+    /// Keeps the rows whose `column` differs from `value`.
+    pub fn where_not_eq(self, column: &str, value: &[u8]) -> Self {
+        self.where_op(column, Operator::NotEq, value)
+    }
+
+    /// Keeps the rows whose `column` is less than `value`.
+    pub fn where_lt(self, column: &str, value: &[u8]) -> Self {
+        self.where_op(column, Operator::Lt, value)
+    }
+
+    /// Keeps the rows whose `column` is greater than `value`.
+    pub fn where_gt(self, column: &str, value: &[u8]) -> Self {
+        self.where_op(column, Operator::Gt, value)
+    }
+
+    /// Returns at most `limit` rows, the first ones in table order. The scan
+    /// does not stop early, the rows past `limit` are dropped once the select
+    /// ran.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// The `SELECT` the same columns and comparisons parse to.
+    fn query(&self) -> Query {
+        let scope = match self.columns.is_empty() {
+            true => Scope::All,
+            false => Scope::Expressions(
+                self.columns
+                    .iter()
+                    .map(|column| SelectExpr::Column(column.clone()))
+                    .collect(),
+            ),
+        };
+        let filter = match self.predicates.is_empty() {
+            true => Filter::All,
+            false => Filter::Where(self.predicates.clone()),
+        };
+        let source = QuerySource::Table(self.table.table.name_str().to_string());
+        Query::Select(source, scope, filter)
+    }
+
+    /// Runs the select, through an index when the optimizer finds one for
+    /// the comparisons. Values come as text, the way a `SELECT` returns
+    /// them, and deleted rows are left out.
+    pub fn run(self) -> Result<Vec<Row>, String> {
+        let query = self.query();
+        let table = self.table;
+        let result_set = crate::get_result_set(
+            &mut table.table,
+            &mut table.file,
+            query,
+            &mut table.page_cache,
+            &table.database,
+            &mut None,
+            &mut table.config,
+            &mut std::io::empty(),
+        );
+        if result_set.execution_status != 1 {
+            return Err(result_set.rows.concat().join(" "));
+        }
+        let limit = self.limit.unwrap_or(usize::MAX);
+        Ok(result_set
+            .rows
+            .into_iter()
+            .take(limit)
+            .map(|row| Row {
+                data: row.into_iter().map(String::into_bytes).collect(),
+            })
+            .collect())
+    }
+}
+
+/// Rows to append to a table, `INSERT INTO table VALUES ...`. Either every
+/// row is written or none is.
+pub struct InsertBuilder<'a> {
+    table: &'a mut DatabaseTable,
+    rows: Vec<Row>,
+}
+
+impl InsertBuilder<'_> {
+    /// Adds `row`, holding a value for every column in their order.
+    pub fn row(mut self, row: Row) -> Self {
+        self.rows.push(row);
+        self
+    }
+
+    /// Adds a row with `values` for the `columns` named and the defaults of
+    /// the others, `INSERT INTO table (columns) VALUES (values)`.
+    pub fn values(mut self, columns: &[&str], values: &[&[u8]]) -> Result<Self, String> {
+        let columns: Vec<String> = columns.iter().map(|column| column.to_string()).collect();
+        let values: Vec<Vec<u8>> = values.iter().map(|value| value.to_vec()).collect();
+        let row = self.table.table.row_for_columns(&columns, &values)?;
+        self.rows.push(row);
+        Ok(self)
+    }
+
+    /// Writes the rows, returning how many were.
+    pub fn run(self) -> Result<usize, String> {
+        let table = self.table;
+        table
+            .table
+            .add_rows(&self.rows, &mut table.file)
+            .map_err(|e| format!("{:?}", e))?;
+        table.invalidate_caches();
+        Ok(self.rows.len())
+    }
+}
+
+/// The rows of a table to delete, `DELETE FROM table WHERE ...`.
+pub struct DeleteBuilder<'a> {
+    table: &'a mut DatabaseTable,
+    predicates: Vec<Predicate>,
+}
+
+impl DeleteBuilder<'_> {
+    /// Deletes the rows whose `column` compares to `value` with `operator`.
+    /// The value is compared as the type of the column.
+    pub fn where_op(mut self, column: &str, operator: Operator, value: &[u8]) -> Self {
+        self.predicates.push(Predicate {
+            expr: SelectExpr::Column(column.to_string()),
+            operator,
+            literal: String::from_utf8_lossy(value).to_string(),
+        });
+        self
+    }
+
+    /// Deletes the rows whose `column` equals `value`.
+    pub fn where_eq(self, column: &str, value: &[u8]) -> Self {
+        self.where_op(column, Operator::Eq, value)
+    }
+
+    /// Deletes the matching rows where they are stored with
+    /// `Table::delete_at`, returning how many were. A row another table
+    /// references stops the delete, the rows before it stay deleted.
+    pub fn run(self) -> Result<usize, String> {
+        let table = self.table;
+        let deleted = delete_matching(table, &self.predicates);
+        table.invalidate_caches();
+        deleted
+    }
+}
+
+fn delete_matching(table: &mut DatabaseTable, predicates: &[Predicate]) -> Result<usize, String> {
+    let rows_per_page = table.table.page_size() / table.table.row_size();
+    let mut deleted = 0;
+    for page_number in 0..table.table.page_count() {
+        let page = table.table.page_at(&table.file, page_number)?;
+        for (row_index, row) in table.table.page_entries(&page) {
+            let mut matches = true;
+            for predicate in predicates {
+                matches = matches && predicate.matches(&row, &table.table.columns)?;
+            }
+            if !matches {
+                continue;
+            }
+            table.table.delete_at(
+                &mut table.file,
+                row_index / rows_per_page,
+                row_index % rows_per_page,
+            )?;
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::grant::{grant, grants_file, Grant, GrantOperation};
+    use crate::durability::user::create_user;
+
+    fn user(id: &str, name: &str) -> Row {
+        Row {
+            data: vec![id.as_bytes().to_vec(), name.as_bytes().to_vec()],
+        }
+    }
+
+    fn ids(rows: &[Row]) -> Vec<String> {
+        rows.iter()
+            .map(|row| String::from_utf8_lossy(&row.data[0]).to_string())
+            .collect()
+    }
+
+    fn create_users(db: &mut Database) -> DatabaseTable {
+        db.create_table(
+            "users",
+            vec![
+                ColumnDefinition::new("id".to_string(), ColumnType::Int, 11),
+                ColumnDefinition::new("name".to_string(), ColumnType::Varchar, 20),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_select_and_insert() {
+        let tmp_dir = tempdir().unwrap();
+        let directory = tmp_dir.path().join("mydb");
+        let mut db = Database::open(directory.to_str().unwrap()).unwrap();
+        let mut table = create_users(&mut db);
+        assert!(table.select().run().unwrap().is_empty());
+
+        for i in 0..20 {
+            table.insert(user(&i.to_string(), "Ada")).unwrap();
+        }
+        let rows = table.select().where_eq("id", b"5").limit(10).run().unwrap();
+        assert_eq!(ids(&rows), ["5"]);
+        assert_eq!(rows[0].data[1], b"Ada");
+
+        // Compared as numbers, not as text.
+        let rows = table
+            .select()
+            .where_gt("id", b"8")
+            .where_lt("id", b"12")
+            .run()
+            .unwrap();
+        assert_eq!(ids(&rows), ["9", "10", "11"]);
+        let rows = table
+            .select()
+            .where_not_eq("id", b"0")
+            .limit(3)
+            .run()
+            .unwrap();
+        assert_eq!(ids(&rows), ["1", "2", "3"]);
+        assert_eq!(table.select().run().unwrap().len(), 20);
+        assert!(table.select().where_eq("missing", b"1").run().is_err());
+
+        // Opened again, the rows were written to the table file.
+        let mut table = db.table("users").unwrap();
+        assert_eq!(table.select().run().unwrap().len(), 20);
+        assert!(db.table("orders").is_err());
+    }
+
+    #[test]
+    fn test_select_columns_as_user() {
+        let tmp_dir = tempdir().unwrap();
+        let directory = tmp_dir.path().join("mydb");
+        let mut db = Database::open(directory.to_str().unwrap()).unwrap();
+        let mut table = create_users(&mut db);
+        table.insert(user("1", "Ada")).unwrap();
+        let rows = table.select().columns(&["name", "id"]).run().unwrap();
+        assert_eq!(rows[0].data, [b"Ada".to_vec(), b"1".to_vec()]);
+
+        let auth_file = tmp_dir.path().join("users.auth");
+        let auth_file = auth_file.to_str().unwrap();
+        create_user(auth_file, "alice", "secret").unwrap();
+        create_user(auth_file, "bob", "secret").unwrap();
+        let granted = Grant {
+            user: "bob".to_string(),
+            table: directory.join("users").to_str().unwrap().to_string(),
+            operation: GrantOperation::Select,
+            columns: vec!["id".to_string()],
+        };
+        grant(&grants_file(&db.database), granted).unwrap();
+        let mut bob = Database::open(directory.to_str().unwrap())
+            .unwrap()
+            .as_user(auth_file, "bob");
+        let rows = bob.table("users").unwrap().select().run().unwrap();
+        assert_eq!(ids(&rows), ["1"]);
+        assert_eq!(rows[0].data.len(), 1);
+        assert!(bob
+            .table("users")
+            .unwrap()
+            .select()
+            .columns(&["name"])
+            .run()
+            .is_err());
+        let mut alice = Database::open(directory.to_str().unwrap())
+            .unwrap()
+            .as_user(auth_file, "alice");
+        let rows = alice.table("users").unwrap().select().run().unwrap();
+        assert_eq!(rows[0].data.len(), 2);
+    }
+
+    #[test]
+    fn test_delete_builder() {
+        let tmp_dir = tempdir().unwrap();
+        let mut db = Database::open(tmp_dir.path().to_str().unwrap()).unwrap();
+        let mut table = create_users(&mut db);
+        for i in 0..10 {
+            table.insert(user(&i.to_string(), "Ada")).unwrap();
+        }
+        assert_eq!(table.select().run().unwrap().len(), 10);
+
+        assert_eq!(table.delete().where_eq("id", b"3").run().unwrap(), 1);
+        assert_eq!(table.delete().where_eq("id", b"3").run().unwrap(), 0);
+        let deleted = table
+            .delete()
+            .where_op("id", Operator::Gt, b"6")
+            .run()
+            .unwrap();
+        assert_eq!(deleted, 3);
+        let rows = table.select().run().unwrap();
+        assert_eq!(ids(&rows), ["0", "1", "2", "4", "5", "6"]);
+        assert!(table.delete().where_eq("missing", b"1").run().is_err());
+
+        // New rows go after the deleted ones.
+        table.insert(user("10", "Grace")).unwrap();
+        let mut table = db.table("users").unwrap();
+        let rows = table.select().run().unwrap();
+        assert_eq!(ids(&rows), ["0", "1", "2", "4", "5", "6", "10"]);
+        assert_eq!(table.delete().run().unwrap(), 7);
+        assert!(table.select().run().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_insert_builder() {
+        let tmp_dir = tempdir().unwrap();
+        let mut db = Database::open(tmp_dir.path().to_str().unwrap()).unwrap();
+        let mut table = create_users(&mut db);
+        let inserted = table
+            .insert_rows()
+            .row(user("1", "Ada"))
+            .row(user("2", "Grace"))
+            .values(&["name", "id"], &[b"Edsger", b"3"])
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(inserted, 3);
+        let rows = table.select().where_eq("name", b"Edsger").run().unwrap();
+        assert_eq!(ids(&rows), ["3"]);
+        assert!(table.insert_rows().values(&["missing"], &[b"1"]).is_err());
+
+        // A row with a value too long for its column writes none of them.
+        let long = "x".repeat(21);
+        assert!(table
+            .insert_rows()
+            .row(user("4", "Barbara"))
+            .row(user("5", &long))
+            .run()
+            .is_err());
+        assert_eq!(table.select().run().unwrap().len(), 3);
+    }
+}
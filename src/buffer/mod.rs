@@ -0,0 +1,3 @@
+mod write_buffer;
+
+pub use write_buffer::WriteBuffer;
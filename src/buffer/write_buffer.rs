@@ -0,0 +1,180 @@
+use std::{collections::BTreeMap, os::unix::fs::FileExt};
+
+/// The bytes held back before `WriteBuffer::push` asks for a flush.
+pub const WRITE_BUFFER_CAPACITY: usize = 4 * 1024 * 1024;
+
+/// Writes to a file held back in memory until they are flushed together.
+/// Overlapping and adjacent writes are merged into a single extent, the later
+/// bytes winning, so a run of writes to neighbouring pages reaches the file
+/// as one sequential `write_all_at`. Extents are written in offset order.
+#[derive(Debug, Default)]
+pub struct WriteBuffer {
+    extents: BTreeMap<u64, Vec<u8>>,
+    size: usize,
+}
+
+impl WriteBuffer {
+    pub fn is_empty(&self) -> bool {
+        self.extents.is_empty()
+    }
+
+    /// Buffers `data` to be written at `offset`. Tells whether the buffer
+    /// reached `WRITE_BUFFER_CAPACITY` and should be flushed.
+    pub fn push(&mut self, offset: u64, data: &[u8]) -> bool {
+        let mut start = offset;
+        let mut end = offset + data.len() as u64;
+        // Extents never overlap, going back from the last one starting by the
+        // end of the write, those touching it come first.
+        let touching: Vec<u64> = self
+            .extents
+            .range(..=end)
+            .rev()
+            .take_while(|(extent_start, extent)| **extent_start + extent.len() as u64 >= offset)
+            .map(|(extent_start, _)| *extent_start)
+            .collect();
+        for extent_start in touching.iter() {
+            let extent_end = extent_start + self.extents[extent_start].len() as u64;
+            start = start.min(*extent_start);
+            end = end.max(extent_end);
+        }
+
+        // The extent the write starts in grows in place, so appending to it
+        // does not copy what it already holds.
+        let mut merged = match touching.last() {
+            Some(first) if *first == start => self.extents.remove(first).unwrap(),
+            _ => vec![],
+        };
+        self.size -= merged.len();
+        merged.resize((end - start) as usize, 0);
+        for extent_start in touching.into_iter().filter(|s| *s != start) {
+            let extent = self.extents.remove(&extent_start).unwrap();
+            self.size -= extent.len();
+            let at = (extent_start - start) as usize;
+            merged[at..at + extent.len()].copy_from_slice(&extent);
+        }
+        let at = (offset - start) as usize;
+        merged[at..at + data.len()].copy_from_slice(data);
+        self.size += merged.len();
+        self.extents.insert(start, merged);
+        self.size >= WRITE_BUFFER_CAPACITY
+    }
+
+    /// Copies the buffered bytes falling in `buffer`, read from the file at
+    /// `offset`, over it.
+    pub fn overlay(&self, offset: u64, buffer: &mut [u8]) {
+        let end = offset + buffer.len() as u64;
+        for (extent_start, extent) in self.extents.range(..end) {
+            let extent_end = extent_start + extent.len() as u64;
+            if extent_end <= offset {
+                continue;
+            }
+            let from = offset.max(*extent_start);
+            let to = end.min(extent_end);
+            buffer[(from - offset) as usize..(to - offset) as usize].copy_from_slice(
+                &extent[(from - extent_start) as usize..(to - extent_start) as usize],
+            );
+        }
+    }
+
+    /// Writes every extent to `file` in offset order and empties the buffer.
+    /// Returns the number of writes made.
+    pub fn flush(&mut self, file: &std::fs::File) -> std::io::Result<usize> {
+        let extents = std::mem::take(&mut self.extents);
+        self.size = 0;
+        let writes = extents.len();
+        for (offset, data) in extents {
+            file.write_all_at(&data, offset)?;
+        }
+        Ok(writes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempfile;
+
+    use super::*;
+
+    #[test]
+    fn test_merges_writes() {
+        let mut buffer = WriteBuffer::default();
+        assert!(buffer.is_empty());
+        assert!(!buffer.push(10, b"bbbb"));
+        assert!(!buffer.push(14, b"cc"));
+        // Starts before the extent and ends within it.
+        assert!(!buffer.push(8, b"aaa"));
+        assert!(!buffer.push(40, b"zz"));
+        assert_eq!(buffer.extents.len(), 2);
+        assert_eq!(buffer.extents[&8], b"aaabbbcc");
+        assert_eq!(buffer.size, 10);
+
+        let mut read = *b"0123456789";
+        buffer.overlay(5, &mut read);
+        assert_eq!(&read, b"012aaabbbc");
+
+        let file = tempfile().unwrap();
+        file.write_all_at(&[b'.'; 44], 0).unwrap();
+        assert_eq!(buffer.flush(&file).unwrap(), 2);
+        assert!(buffer.is_empty());
+        let mut contents = vec![0; 44];
+        file.read_exact_at(&mut contents, 0).unwrap();
+        assert_eq!(
+            contents,
+            b"........aaabbbcc........................zz..".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_asks_for_flush_at_capacity() {
+        let mut buffer = WriteBuffer::default();
+        let page = vec![1; WRITE_BUFFER_CAPACITY / 4];
+        for i in 0..3 {
+            assert!(!buffer.push(i * page.len() as u64 * 2, &page));
+        }
+        // Rewriting buffered bytes takes no more room.
+        assert!(!buffer.push(0, &page));
+        assert!(buffer.push(page.len() as u64, &page));
+    }
+
+    /// The writes of 100000 rows appended one at a time, each a row and the
+    /// row count in the header, made straight to a file against through a
+    /// buffer, run with
+    /// `cargo test --release bench_write_buffer -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_write_buffer() {
+        const ROWS: u64 = 100_000;
+        const ROW_SIZE: u64 = 27;
+        let row = [b'x'; ROW_SIZE as usize];
+        for buffered in [false, true] {
+            let file = tempfile().unwrap();
+            let mut buffer = WriteBuffer::default();
+            let start = std::time::Instant::now();
+            for i in 0..ROWS {
+                let writes = [
+                    (128 + i * ROW_SIZE, &row[..]),
+                    (64, &(i + 1).to_ne_bytes()[..]),
+                ];
+                for (offset, data) in writes {
+                    match buffered {
+                        true if buffer.push(offset, data) => {
+                            buffer.flush(&file).unwrap();
+                        }
+                        true => {}
+                        false => file.write_all_at(data, offset).unwrap(),
+                    }
+                }
+            }
+            buffer.flush(&file).unwrap();
+            file.sync_all().unwrap();
+            let elapsed = start.elapsed();
+            println!(
+                "{}: {} rows in {:?}, {:.0} rows/s",
+                if buffered { "buffered" } else { "unbuffered" },
+                ROWS,
+                elapsed,
+                ROWS as f64 / elapsed.as_secs_f64()
+            );
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::ResultSet;
+
+/// Results kept before the least recently used one is evicted.
+pub const QUERY_CACHE_CAPACITY: usize = 32;
+
+struct CachedResult {
+    table: String,
+    /// The literals of the query, only a query with the same ones is
+    /// answered from the entry.
+    literals: Vec<String>,
+    result_set: ResultSet,
+    /// The table's row count when the result was cached.
+    row_count: u64,
+    /// The value of the cache's clock when the result was last served.
+    used_at: u64,
+}
+
+/// The results of recent SELECT queries, shared by every connection of the
+/// process. Results are keyed by the fingerprint of their query so queries
+/// differing only in their literals share one entry, holding the result of
+/// the last one run. A result is only served while the table's row count is
+/// the one it was cached at, every other change to the table has to
+/// invalidate it.
+pub struct QueryCache {
+    capacity: usize,
+    entries: HashMap<u64, CachedResult>,
+    /// Ticks on every insert and hit, orders the entries by last use.
+    clock: u64,
+}
+
+pub fn query_cache() -> &'static Mutex<QueryCache> {
+    static QUERY_CACHE: OnceLock<Mutex<QueryCache>> = OnceLock::new();
+    QUERY_CACHE.get_or_init(|| Mutex::new(QueryCache::new(QUERY_CACHE_CAPACITY)))
+}
+
+impl QueryCache {
+    pub fn new(capacity: usize) -> QueryCache {
+        QueryCache {
+            capacity,
+            entries: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// The result cached under `key` for the table, dropped instead when
+    /// rows were added or removed since. `None` when the cached result is
+    /// for other `literals`.
+    pub fn get(
+        &mut self,
+        key: u64,
+        literals: &[String],
+        table: &str,
+        row_count: u64,
+    ) -> Option<ResultSet> {
+        let entry = self.entries.get_mut(&key)?;
+        if entry.table != table || entry.row_count != row_count {
+            self.entries.remove(&key);
+            return None;
+        }
+        if entry.literals != literals {
+            return None;
+        }
+        self.clock += 1;
+        entry.used_at = self.clock;
+        Some(entry.result_set.clone())
+    }
+
+    pub fn insert(
+        &mut self,
+        key: u64,
+        literals: Vec<String>,
+        table: &str,
+        row_count: u64,
+        result_set: ResultSet,
+    ) {
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used_at)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        let entry = CachedResult {
+            table: table.to_string(),
+            literals,
+            result_set,
+            row_count,
+            used_at: self.clock,
+        };
+        self.entries.insert(key, entry);
+    }
+
+    /// Drops every result cached for the table.
+    pub fn invalidate(&mut self, table: &str) {
+        self.entries.retain(|_, entry| entry.table != table);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        config::Config,
+        durability::{
+            table::{
+                create_table, pages_read, writeable_table_file, ColumnType, Table, TableBuilder,
+            },
+            DatabaseConfig, Durable,
+        },
+        get_result_set,
+    };
+
+    fn result_set(value: &str) -> ResultSet {
+        ResultSet {
+            rows: vec![vec![value.to_string()]],
+            execution_time: 0,
+            execution_status: 1,
+        }
+    }
+
+    #[test]
+    fn test_get_and_invalidate() {
+        let mut cache = QueryCache::new(4);
+        cache.insert(1, vec![], "users", 10, result_set("a"));
+        cache.insert(2, vec![], "orders", 10, result_set("b"));
+        assert_eq!(cache.get(1, &[], "users", 10).unwrap().rows, [["a"]]);
+        assert!(cache.get(1, &[], "orders", 10).is_none());
+
+        // A changed row count drops the result for good.
+        assert!(cache.get(2, &[], "orders", 11).is_none());
+        assert!(cache.get(2, &[], "orders", 10).is_none());
+
+        cache.insert(3, vec![], "users", 10, result_set("c"));
+        cache.invalidate("users");
+        assert!(cache.get(1, &[], "users", 10).is_none());
+        assert!(cache.get(3, &[], "users", 10).is_none());
+    }
+
+    #[test]
+    fn test_literals_share_an_entry() {
+        let literals = |literal: &str| vec![literal.to_string()];
+        let mut cache = QueryCache::new(4);
+        cache.insert(1, literals("1"), "users", 10, result_set("a"));
+        assert!(cache.get(1, &literals("999"), "users", 10).is_none());
+        assert_eq!(
+            cache.get(1, &literals("1"), "users", 10).unwrap().rows,
+            [["a"]]
+        );
+
+        cache.insert(1, literals("999"), "users", 10, result_set("b"));
+        assert!(cache.get(1, &literals("1"), "users", 10).is_none());
+        assert_eq!(
+            cache.get(1, &literals("999"), "users", 10).unwrap().rows,
+            [["b"]]
+        );
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = QueryCache::new(2);
+        cache.insert(1, vec![], "users", 0, result_set("a"));
+        cache.insert(2, vec![], "users", 0, result_set("b"));
+        cache.get(1, &[], "users", 0).unwrap();
+        cache.insert(3, vec![], "users", 0, result_set("c"));
+        assert!(cache.get(2, &[], "users", 0).is_none());
+        assert!(cache.get(1, &[], "users", 0).is_some());
+        assert!(cache.get(3, &[], "users", 0).is_some());
+    }
+
+    #[test]
+    fn test_cached_select_skips_scan() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(TableBuilder::new(&name).column("id", ColumnType::Int, 8)).unwrap();
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let database = DatabaseConfig {
+            name: "city_db".to_string(),
+            file_path: tmp_dir.path().to_str().unwrap().to_string(),
+            auth_file: None,
+        };
+        let mut config = Config::default();
+        // A fresh page cache every time, so only the query cache saves a scan.
+        let mut execute = |query: &str| {
+            get_result_set(
+                &mut table,
+                &mut file,
+                query.into(),
+                &mut HashMap::new(),
+                &database,
+                &mut None,
+                &mut config,
+                &mut std::io::empty(),
+            )
+            .rows
+        };
+
+        execute("INSERT INTO users (id) VALUES (1) (2)");
+        let scanned = pages_read();
+        assert_eq!(execute("SELECT * FROM users"), [["1"], ["2"]]);
+        assert!(pages_read() > scanned);
+
+        let scanned = pages_read();
+        assert_eq!(execute("SELECT  *  FROM users"), [["1"], ["2"]]);
+        assert_eq!(pages_read(), scanned);
+
+        // Set operations only read the table.
+        for query in [
+            "SELECT id FROM users UNION SELECT id FROM users",
+            "SELECT id FROM users INTERSECT SELECT id FROM users",
+            "SELECT id FROM users EXCEPT SELECT id FROM users",
+        ] {
+            execute(query);
+            let scanned = pages_read();
+            assert_eq!(execute("SELECT * FROM users"), [["1"], ["2"]], "{}", query);
+            assert_eq!(pages_read(), scanned, "{}", query);
+        }
+
+        let scanned = pages_read();
+        execute("INSERT INTO users (id) VALUES (3)");
+        assert_eq!(execute("SELECT * FROM users"), [["1"], ["2"], ["3"]]);
+        assert!(pages_read() > scanned);
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex, OnceLock, RwLock},
+    time::{Duration, Instant},
+};
+
+use super::{TableId, TableLock};
+
+/// Seconds `LOCK TABLE`, and queries on a table another connection locked,
+/// wait for the lock before giving up.
+pub const DEFAULT_LOCK_TIMEOUT: u64 = 30;
+
+/// The connection holding a lock, 0 for the REPL.
+pub type ConnectionId = u64;
+
+/// What `LOCK TABLE` holds on a table.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum TableLockState {
+    #[default]
+    Unlocked,
+    /// `LOCK TABLE name READ` by every connection holding it.
+    Read(Vec<ConnectionId>),
+    /// `LOCK TABLE name WRITE`
+    Write(ConnectionId),
+}
+
+impl TableLockState {
+    /// Whether `connection` can take `lock`, and so whether it can read,
+    /// `Shared`, or write, `Exclusive`, the table. A read lock only keeps
+    /// other connections from writing, and a connection that alone holds the
+    /// table can switch between the modes.
+    fn allows(&self, connection: ConnectionId, lock: TableLock) -> bool {
+        match (self, lock) {
+            (TableLockState::Unlocked, _) | (TableLockState::Read(_), TableLock::Shared) => true,
+            (TableLockState::Read(holders), TableLock::Exclusive) => {
+                holders.iter().all(|holder| *holder == connection)
+            }
+            (TableLockState::Write(holder), _) => *holder == connection,
+        }
+    }
+}
+
+/// The `LOCK TABLE` locks of every connection of the process by table.
+/// Unlike the locks of `RowLockManager` they belong to a connection, which
+/// keeps using the table while others wait.
+#[derive(Default)]
+pub struct ExplicitLockRegistry {
+    tables: RwLock<HashMap<TableId, Arc<RwLock<TableLockState>>>>,
+    waiting: Mutex<()>,
+    released: Condvar,
+}
+
+pub fn explicit_locks() -> &'static ExplicitLockRegistry {
+    static EXPLICIT_LOCKS: OnceLock<ExplicitLockRegistry> = OnceLock::new();
+    EXPLICIT_LOCKS.get_or_init(ExplicitLockRegistry::default)
+}
+
+impl ExplicitLockRegistry {
+    /// Takes `lock` on `table` for `connection`, waiting up to `timeout` for
+    /// other connections to unlock it.
+    pub fn lock(
+        &self,
+        table: &str,
+        lock: TableLock,
+        connection: ConnectionId,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        let state = self.state(table);
+        let acquired = self.wait(timeout, || {
+            let mut state = state.write().unwrap_or_else(|e| e.into_inner());
+            if !state.allows(connection, lock) {
+                return false;
+            }
+            *state = match (lock, &*state) {
+                (TableLock::Shared, TableLockState::Read(holders)) => {
+                    let mut holders = holders.clone();
+                    if !holders.contains(&connection) {
+                        holders.push(connection);
+                    }
+                    TableLockState::Read(holders)
+                }
+                (TableLock::Shared, _) => TableLockState::Read(vec![connection]),
+                (TableLock::Exclusive, _) => TableLockState::Write(connection),
+            };
+            true
+        });
+        if !acquired {
+            return Err(format!("Timed out waiting for the lock on {}", table));
+        }
+        // Switching from a write to a read lock lets readers in.
+        self.notify();
+        Ok(())
+    }
+
+    pub fn unlock(&self, table: &str, connection: ConnectionId) -> Result<(), String> {
+        let not_locked = || format!("Table {} is not locked by this connection", table);
+        let state = self
+            .tables
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(table)
+            .cloned()
+            .ok_or_else(not_locked)?;
+        {
+            let mut state = state.write().unwrap_or_else(|e| e.into_inner());
+            *state = match &*state {
+                TableLockState::Read(holders) if holders.contains(&connection) => {
+                    let holders: Vec<ConnectionId> = holders
+                        .iter()
+                        .copied()
+                        .filter(|holder| *holder != connection)
+                        .collect();
+                    if holders.is_empty() {
+                        TableLockState::Unlocked
+                    } else {
+                        TableLockState::Read(holders)
+                    }
+                }
+                TableLockState::Write(holder) if *holder == connection => TableLockState::Unlocked,
+                _ => return Err(not_locked()),
+            };
+        }
+        self.notify();
+        Ok(())
+    }
+
+    /// Unlocks every table `connection` locked, when it disconnects.
+    pub fn release_all(&self, connection: ConnectionId) {
+        let tables: Vec<TableId> = self
+            .tables
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys()
+            .cloned()
+            .collect();
+        for table in tables {
+            let _ = self.unlock(&table, connection);
+        }
+    }
+
+    /// Waits up to `timeout` until the locks of other connections let
+    /// `connection` read, `Shared`, or write, `Exclusive`, the table.
+    pub fn wait_for_access(
+        &self,
+        table: &str,
+        access: TableLock,
+        connection: ConnectionId,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        let Some(state) = self
+            .tables
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(table)
+            .cloned()
+        else {
+            return Ok(());
+        };
+        let allowed = self.wait(timeout, || {
+            let state = state.read().unwrap_or_else(|e| e.into_inner());
+            state.allows(connection, access)
+        });
+        if !allowed {
+            return Err(format!("Timed out waiting for {} to be unlocked", table));
+        }
+        Ok(())
+    }
+
+    fn state(&self, table: &str) -> Arc<RwLock<TableLockState>> {
+        let mut tables = self.tables.write().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(tables.entry(table.to_string()).or_default())
+    }
+
+    /// Retries `try_acquire` whenever a lock is released, false once
+    /// `timeout` passed without it succeeding.
+    fn wait(&self, timeout: Duration, try_acquire: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut waiting = self.waiting.lock().unwrap_or_else(|e| e.into_inner());
+        while !try_acquire() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            waiting = self
+                .released
+                .wait_timeout(waiting, remaining)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        true
+    }
+
+    fn notify(&self) {
+        let _waiting = self.waiting.lock().unwrap_or_else(|e| e.into_inner());
+        self.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn write_lock_blocks_other_connections() {
+        let registry = Arc::new(ExplicitLockRegistry::default());
+        registry
+            .lock("users", TableLock::Exclusive, 1, TIMEOUT)
+            .unwrap();
+        registry
+            .wait_for_access("users", TableLock::Exclusive, 1, TIMEOUT)
+            .unwrap();
+        registry
+            .wait_for_access("orders", TableLock::Exclusive, 2, TIMEOUT)
+            .unwrap();
+        assert!(registry
+            .wait_for_access("users", TableLock::Shared, 2, Duration::from_millis(20))
+            .is_err());
+        assert!(registry
+            .lock("users", TableLock::Shared, 2, Duration::from_millis(20))
+            .is_err());
+
+        let (sender, receiver) = mpsc::channel();
+        let waiter = {
+            let registry = Arc::clone(&registry);
+            thread::spawn(move || {
+                registry
+                    .lock("users", TableLock::Shared, 2, TIMEOUT)
+                    .unwrap();
+                sender.send(()).unwrap();
+            })
+        };
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+        registry.unlock("users", 1).unwrap();
+        receiver.recv_timeout(TIMEOUT).unwrap();
+        waiter.join().unwrap();
+        assert!(registry.unlock("users", 1).is_err());
+    }
+
+    #[test]
+    fn read_lock_blocks_writers_only() {
+        let registry = ExplicitLockRegistry::default();
+        let short = Duration::from_millis(20);
+        registry
+            .lock("users", TableLock::Shared, 1, TIMEOUT)
+            .unwrap();
+        registry
+            .lock("users", TableLock::Shared, 2, TIMEOUT)
+            .unwrap();
+        registry
+            .wait_for_access("users", TableLock::Shared, 3, TIMEOUT)
+            .unwrap();
+        assert!(registry
+            .wait_for_access("users", TableLock::Exclusive, 3, short)
+            .is_err());
+        // Neither reader can upgrade while the other holds the table.
+        assert!(registry
+            .lock("users", TableLock::Exclusive, 1, short)
+            .is_err());
+
+        registry.release_all(2);
+        registry
+            .lock("users", TableLock::Exclusive, 1, TIMEOUT)
+            .unwrap();
+        registry.release_all(1);
+        registry
+            .wait_for_access("users", TableLock::Exclusive, 3, TIMEOUT)
+            .unwrap();
+    }
+}
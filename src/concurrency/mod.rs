@@ -0,0 +1,263 @@
+use std::{
+    collections::HashMap,
+    sync::{Condvar, Mutex, OnceLock, RwLock},
+};
+
+mod explicit_lock;
+
+pub use explicit_lock::{explicit_locks, ConnectionId, DEFAULT_LOCK_TIMEOUT};
+
+pub type TableId = String;
+/// The index of a row in its table.
+pub type RowId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockMode {
+    /// Held by the given number of readers.
+    Read(usize),
+    Write,
+}
+
+/// Locks covering a whole table. Row locks act as shared locks on their
+/// table, so an exclusive table lock waits for them and blocks new ones. A
+/// shared table lock only waits for and blocks row write locks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TableLock {
+    Shared,
+    Exclusive,
+}
+
+/// Row and table locks shared by every connection of the process. Acquiring
+/// a lock blocks until it is compatible with the locks already held.
+#[derive(Default)]
+pub struct RowLockManager {
+    rows: RwLock<HashMap<(TableId, RowId), LockMode>>,
+    /// The lock held on each table and how many holders share it.
+    tables: RwLock<HashMap<TableId, (TableLock, usize)>>,
+    // Acquisitions check and wait while holding this mutex, releases notify
+    // through it, so a release can not slip in between the check and the wait.
+    waiting: Mutex<()>,
+    released: Condvar,
+}
+
+pub fn lock_manager() -> &'static RowLockManager {
+    static LOCK_MANAGER: OnceLock<RowLockManager> = OnceLock::new();
+    LOCK_MANAGER.get_or_init(RowLockManager::default)
+}
+
+impl RowLockManager {
+    pub fn acquire_read_lock(&self, table: &str, row: RowId) {
+        self.acquire(|manager| {
+            if manager.table_lock(table) == Some(TableLock::Exclusive) {
+                return false;
+            }
+            let mut rows = manager.rows.write().unwrap_or_else(|e| e.into_inner());
+            match rows.get_mut(&(table.to_string(), row)) {
+                Some(LockMode::Write) => false,
+                Some(LockMode::Read(readers)) => {
+                    *readers += 1;
+                    true
+                }
+                None => {
+                    rows.insert((table.to_string(), row), LockMode::Read(1));
+                    true
+                }
+            }
+        });
+    }
+
+    pub fn acquire_write_lock(&self, table: &str, row: RowId) {
+        self.acquire(|manager| {
+            if manager.table_lock(table).is_some() {
+                return false;
+            }
+            let mut rows = manager.rows.write().unwrap_or_else(|e| e.into_inner());
+            if rows.contains_key(&(table.to_string(), row)) {
+                return false;
+            }
+            rows.insert((table.to_string(), row), LockMode::Write);
+            true
+        });
+    }
+
+    /// Releases a write lock or one reader of a read lock.
+    pub fn release_lock(&self, table: &str, row: RowId) {
+        {
+            let mut rows = self.rows.write().unwrap_or_else(|e| e.into_inner());
+            let key = (table.to_string(), row);
+            match rows.get_mut(&key) {
+                Some(LockMode::Read(readers)) if *readers > 1 => *readers -= 1,
+                Some(_) => {
+                    rows.remove(&key);
+                }
+                None => return,
+            }
+        }
+        self.notify();
+    }
+
+    /// Waits for every conflicting row lock on the table to be released, for
+    /// operations such as TRUNCATE that touch all rows at once. Shared locks
+    /// let readers through, for operations such as BACKUP that only need the
+    /// rows to stay put.
+    pub fn acquire_table_lock(&self, table: &str, lock: TableLock) {
+        self.acquire(|manager| {
+            let mut tables = manager.tables.write().unwrap_or_else(|e| e.into_inner());
+            match (tables.get_mut(table), lock) {
+                (Some((TableLock::Shared, holders)), TableLock::Shared) => {
+                    *holders += 1;
+                    return true;
+                }
+                (Some(_), _) => return false,
+                (None, _) => {}
+            }
+
+            let rows = manager.rows.read().unwrap_or_else(|e| e.into_inner());
+            let conflicting = rows.iter().any(|((locked_table, _), mode)| {
+                locked_table == table && (lock == TableLock::Exclusive || *mode == LockMode::Write)
+            });
+            if conflicting {
+                return false;
+            }
+            tables.insert(table.to_string(), (lock, 1));
+            true
+        });
+    }
+
+    /// Releases an exclusive lock or one holder of a shared lock.
+    pub fn release_table_lock(&self, table: &str) {
+        {
+            let mut tables = self.tables.write().unwrap_or_else(|e| e.into_inner());
+            match tables.get_mut(table) {
+                Some((_, holders)) if *holders > 1 => *holders -= 1,
+                Some(_) => {
+                    tables.remove(table);
+                }
+                None => return,
+            }
+        }
+        self.notify();
+    }
+
+    fn table_lock(&self, table: &str) -> Option<TableLock> {
+        let tables = self.tables.read().unwrap_or_else(|e| e.into_inner());
+        tables.get(table).map(|(lock, _)| *lock)
+    }
+
+    fn acquire(&self, try_acquire: impl Fn(&Self) -> bool) {
+        let mut waiting = self.waiting.lock().unwrap_or_else(|e| e.into_inner());
+        while !try_acquire(self) {
+            waiting = self
+                .released
+                .wait(waiting)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn notify(&self) {
+        let _waiting = self.waiting.lock().unwrap_or_else(|e| e.into_inner());
+        self.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{mpsc, Arc},
+        thread,
+        time::Duration,
+    };
+
+    use super::{LockMode, RowLockManager, TableLock};
+
+    #[test]
+    fn write_lock_waits_for_release() {
+        let manager = Arc::new(RowLockManager::default());
+        manager.acquire_write_lock("users", 0);
+
+        let (sender, receiver) = mpsc::channel();
+        let waiter = {
+            let manager = Arc::clone(&manager);
+            thread::spawn(move || {
+                manager.acquire_write_lock("users", 0);
+                sender.send(()).unwrap();
+                manager.release_lock("users", 0);
+            })
+        };
+
+        // Other rows and tables are not affected.
+        manager.acquire_write_lock("users", 1);
+        manager.acquire_write_lock("accounts", 0);
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+
+        manager.release_lock("users", 0);
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        waiter.join().unwrap();
+    }
+
+    #[test]
+    fn read_locks_are_shared() {
+        let manager = RowLockManager::default();
+        manager.acquire_read_lock("users", 0);
+        manager.acquire_read_lock("users", 0);
+        assert_eq!(
+            manager.rows.read().unwrap()[&("users".to_string(), 0)],
+            LockMode::Read(2)
+        );
+
+        manager.release_lock("users", 0);
+        manager.release_lock("users", 0);
+        assert!(manager.rows.read().unwrap().is_empty());
+        manager.acquire_write_lock("users", 0);
+    }
+
+    #[test]
+    fn table_lock_excludes_row_locks() {
+        let manager = Arc::new(RowLockManager::default());
+        manager.acquire_write_lock("users", 3);
+
+        let (sender, receiver) = mpsc::channel();
+        let truncate = {
+            let manager = Arc::clone(&manager);
+            thread::spawn(move || {
+                manager.acquire_table_lock("users", TableLock::Exclusive);
+                sender.send(()).unwrap();
+                thread::sleep(Duration::from_millis(20));
+                manager.release_table_lock("users");
+            })
+        };
+
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+        manager.release_lock("users", 3);
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        manager.acquire_write_lock("users", 0);
+        assert!(manager.tables.read().unwrap().is_empty());
+        truncate.join().unwrap();
+    }
+
+    #[test]
+    fn shared_table_lock_blocks_writers_only() {
+        let manager = Arc::new(RowLockManager::default());
+        manager.acquire_read_lock("users", 0);
+        manager.acquire_table_lock("users", TableLock::Shared);
+        manager.acquire_table_lock("users", TableLock::Shared);
+        manager.acquire_read_lock("users", 1);
+
+        let (sender, receiver) = mpsc::channel();
+        let writer = {
+            let manager = Arc::clone(&manager);
+            thread::spawn(move || {
+                manager.acquire_write_lock("users", 2);
+                sender.send(()).unwrap();
+                manager.release_lock("users", 2);
+            })
+        };
+
+        manager.release_table_lock("users");
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+        manager.release_table_lock("users");
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        writer.join().unwrap();
+    }
+}
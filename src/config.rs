@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+
+use crate::{
+    concurrency::{ConnectionId, DEFAULT_LOCK_TIMEOUT},
+    durability::{table::DEFAULT_PAGE_SIZE, wal::DEFAULT_AUTO_CHECKPOINT_SIZE, DurabilityError},
+    query::prepared::PreparedQuery,
+    slow_query_log::DEFAULT_THRESHOLD_US,
+};
+
+/// How query results are rendered to the client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// One column per value.
+    Text,
+    /// Every row as a single JSON array of strings.
+    Json,
+}
+
+impl OutputFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+/// The variables a session changes with `SET name = value` and reads back
+/// with `SHOW name` or `SHOW ALL`. Every connection starts from the defaults,
+/// or from the database configuration file when there is one.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Pages kept in the page cache before older ones are evicted.
+    pub page_cache_size: usize,
+    /// Queries running longer than this many microseconds are logged.
+    pub slow_query_threshold: u64,
+    pub output_format: OutputFormat,
+    /// Re-analyze the table after inserts so the optimizer's estimates
+    /// follow the data.
+    pub auto_analyze: bool,
+    /// Rows written between two analyses when `auto_analyze` is on. Only set
+    /// from the configuration file, where 0 turns `auto_analyze` off.
+    pub auto_analyze_interval_rows: u64,
+    /// Rows written since the session last analyzed the table. Not a
+    /// variable.
+    pub rows_since_analyze: u64,
+    /// Writes that grow the redo log past this many bytes trigger a
+    /// background checkpoint, 0 turns automatic checkpoints off.
+    pub wal_autocheckpoint: u64,
+    /// Seconds to wait for a table another connection locked with
+    /// `LOCK TABLE`.
+    pub lock_timeout: u64,
+    /// Whether writes go through the redo log, for the whole process. Only
+    /// set from the configuration file.
+    pub wal_enabled: bool,
+    /// The size pages are cut to, for the whole process. Only set from the
+    /// configuration file.
+    pub default_page_size: u64,
+    /// The server connection this session belongs to, 0 in the REPL. Not a
+    /// variable either.
+    pub connection_id: ConnectionId,
+    /// Who the connection authenticated as, `None` when the server runs
+    /// without an auth file. Not a variable, `SET` cannot change it.
+    pub user: Option<String>,
+    /// The statements `PREPARE` parsed, by name. Like the variables they
+    /// last as long as the connection.
+    pub prepared: HashMap<String, PreparedQuery>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            page_cache_size: 1024,
+            slow_query_threshold: DEFAULT_THRESHOLD_US,
+            output_format: OutputFormat::Text,
+            auto_analyze: false,
+            auto_analyze_interval_rows: 1,
+            rows_since_analyze: 0,
+            wal_autocheckpoint: DEFAULT_AUTO_CHECKPOINT_SIZE,
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+            wal_enabled: true,
+            default_page_size: DEFAULT_PAGE_SIZE,
+            connection_id: 0,
+            user: None,
+            prepared: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub const VARIABLES: [&'static str; 6] = [
+        "page_cache_size",
+        "slow_query_threshold",
+        "output_format",
+        "auto_analyze",
+        "wal_autocheckpoint",
+        "lock_timeout",
+    ];
+
+    /// The current value of a variable, `None` if there is no such variable.
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "page_cache_size" => Some(self.page_cache_size.to_string()),
+            "slow_query_threshold" => Some(self.slow_query_threshold.to_string()),
+            "output_format" => Some(self.output_format.name().to_string()),
+            "auto_analyze" => Some(self.auto_analyze.to_string()),
+            "wal_autocheckpoint" => Some(self.wal_autocheckpoint.to_string()),
+            "lock_timeout" => Some(self.lock_timeout.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Every variable with its current value, in the order of `VARIABLES`.
+    pub fn all(&self) -> Vec<(&'static str, String)> {
+        Config::VARIABLES
+            .iter()
+            .map(|name| (*name, self.get(name).unwrap()))
+            .collect()
+    }
+
+    /// Parses `value` for the variable and stores it. The variable keeps its
+    /// value when `value` is invalid.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let invalid = || format!("Invalid value {} for {}", value, name);
+        match name {
+            "page_cache_size" => match value.parse::<usize>() {
+                Ok(size) if size > 0 => self.page_cache_size = size,
+                _ => return Err(invalid()),
+            },
+            "slow_query_threshold" => {
+                self.slow_query_threshold = value.parse().map_err(|_| invalid())?;
+            }
+            "output_format" => {
+                self.output_format = match value.to_lowercase().as_str() {
+                    "text" => OutputFormat::Text,
+                    "json" => OutputFormat::Json,
+                    _ => return Err(invalid()),
+                }
+            }
+            "auto_analyze" => self.auto_analyze = parse_bool(value).ok_or_else(invalid)?,
+            "wal_autocheckpoint" => {
+                self.wal_autocheckpoint = value.parse().map_err(|_| invalid())?;
+            }
+            "lock_timeout" => {
+                self.lock_timeout = value.parse().map_err(|_| invalid())?;
+            }
+            _ => return Err(format!("Unknown variable {}", name)),
+        }
+        Ok(())
+    }
+
+    /// The keys of the database configuration file, see `load_from_file`.
+    pub const FILE_KEYS: [&'static str; 5] = [
+        "max_page_cache_size",
+        "slow_query_threshold_us",
+        "wal_enabled",
+        "auto_analyze_interval_rows",
+        "default_page_size",
+    ];
+
+    /// Reads the `key = value` lines of a database configuration file over
+    /// the defaults. Blank lines and lines starting with `#` are skipped,
+    /// unknown keys are ignored with a warning.
+    pub fn load_from_file(path: &str) -> Result<Config, DurabilityError> {
+        let contents = std::fs::read_to_string(path).map_err(DurabilityError::IoError)?;
+        let mut config = Config::default();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid_line = |reason: String| {
+                DurabilityError::DbError(format!("{} on line {} of {}", reason, number + 1, path))
+            };
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid_line(format!("Expected key = value, got {}", line)))?;
+            let (key, value) = (key.trim(), value.trim());
+            if !Config::FILE_KEYS.contains(&key) {
+                println!("Warning: unknown key {} in {}, ignoring it", key, path);
+                continue;
+            }
+            config.set_file_key(key, value).map_err(invalid_line)?;
+        }
+        Ok(config)
+    }
+
+    fn set_file_key(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let invalid = || format!("Invalid value {} for {}", value, key);
+        let positive = || match value.parse::<u64>() {
+            Ok(value) if value > 0 => Ok(value),
+            _ => Err(invalid()),
+        };
+        match key {
+            "max_page_cache_size" => self.page_cache_size = positive()? as usize,
+            "slow_query_threshold_us" => {
+                self.slow_query_threshold = value.parse().map_err(|_| invalid())?;
+            }
+            "wal_enabled" => self.wal_enabled = parse_bool(value).ok_or_else(invalid)?,
+            "auto_analyze_interval_rows" => {
+                let interval: u64 = value.parse().map_err(|_| invalid())?;
+                self.auto_analyze = interval > 0;
+                self.auto_analyze_interval_rows = interval.max(1);
+            }
+            "default_page_size" => self.default_page_size = positive()?,
+            _ => unreachable!("checked against FILE_KEYS"),
+        }
+        Ok(())
+    }
+
+    /// Every key of the configuration file with its current value, in the
+    /// order of `FILE_KEYS`.
+    pub fn file_settings(&self) -> Vec<(&'static str, String)> {
+        let auto_analyze_interval_rows = match self.auto_analyze {
+            true => self.auto_analyze_interval_rows,
+            false => 0,
+        };
+        vec![
+            ("max_page_cache_size", self.page_cache_size.to_string()),
+            (
+                "slow_query_threshold_us",
+                self.slow_query_threshold.to_string(),
+            ),
+            ("wal_enabled", self.wal_enabled.to_string()),
+            (
+                "auto_analyze_interval_rows",
+                auto_analyze_interval_rows.to_string(),
+            ),
+            ("default_page_size", self.default_page_size.to_string()),
+        ]
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "on" | "1" => Some(true),
+        "false" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Renders a row as a JSON array of strings.
+pub fn json_array(row: &[String]) -> String {
+    let values: Vec<String> = row.iter().map(|value| json_string(value)).collect();
+    format!("[{}]", values.join(","))
+}
+
+/// Quotes and escapes `value` as a JSON string.
+pub fn json_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_get() {
+        let mut config = Config::default();
+        assert_eq!(config.get("page_cache_size").unwrap(), "1024");
+
+        config.set("page_cache_size", "256").unwrap();
+        config.set("output_format", "JSON").unwrap();
+        config.set("auto_analyze", "on").unwrap();
+        assert_eq!(
+            config.all(),
+            vec![
+                ("page_cache_size", "256".to_string()),
+                ("slow_query_threshold", DEFAULT_THRESHOLD_US.to_string()),
+                ("output_format", "json".to_string()),
+                ("auto_analyze", "true".to_string()),
+                ("wal_autocheckpoint", "1048576".to_string()),
+                ("lock_timeout", "30".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            config.set("page_cache_size", "0"),
+            Err("Invalid value 0 for page_cache_size".to_string())
+        );
+        assert_eq!(
+            config.set("output_format", "xml"),
+            Err("Invalid value xml for output_format".to_string())
+        );
+        assert_eq!(
+            config.set("work_mem", "4"),
+            Err("Unknown variable work_mem".to_string())
+        );
+        assert_eq!(config.page_cache_size, 256);
+        assert_eq!(config.get("work_mem"), None);
+    }
+
+    #[test]
+    fn test_load_from_file() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("city_db.conf");
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "# Tuned for the tests\n\
+             max_page_cache_size = 64\n\
+             \n\
+             wal_enabled=off\n\
+             auto_analyze_interval_rows = 500\n\
+             work_mem = 4MB\n",
+        )
+        .unwrap();
+
+        let config = Config::load_from_file(path).unwrap();
+        assert_eq!(config.page_cache_size, 64);
+        assert!(!config.wal_enabled);
+        assert!(config.auto_analyze);
+        assert_eq!(config.auto_analyze_interval_rows, 500);
+        assert_eq!(config.slow_query_threshold, DEFAULT_THRESHOLD_US);
+        assert_eq!(config.default_page_size, DEFAULT_PAGE_SIZE);
+        assert_eq!(
+            config.file_settings(),
+            vec![
+                ("max_page_cache_size", "64".to_string()),
+                ("slow_query_threshold_us", DEFAULT_THRESHOLD_US.to_string()),
+                ("wal_enabled", "false".to_string()),
+                ("auto_analyze_interval_rows", "500".to_string()),
+                ("default_page_size", DEFAULT_PAGE_SIZE.to_string()),
+            ]
+        );
+
+        std::fs::write(path, "").unwrap();
+        let defaults = Config::default();
+        let config = Config::load_from_file(path).unwrap();
+        assert_eq!(config.all(), defaults.all());
+        assert_eq!(config.file_settings(), defaults.file_settings());
+        assert_eq!(
+            config.file_settings()[3],
+            ("auto_analyze_interval_rows", "0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_load_from_invalid_file() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("city_db.conf");
+        let path = path.to_str().unwrap();
+        let error = |contents: &str| {
+            std::fs::write(path, contents).unwrap();
+            Config::load_from_file(path).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            error("default_page_size = 0"),
+            format!(
+                "Invalid value 0 for default_page_size on line 1 of {}",
+                path
+            )
+        );
+        assert_eq!(
+            error("# pages\nmax_page_cache_size"),
+            format!(
+                "Expected key = value, got max_page_cache_size on line 2 of {}",
+                path
+            )
+        );
+        assert!(matches!(
+            Config::load_from_file(&format!("{}.missing", path)),
+            Err(DurabilityError::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn test_json_array() {
+        let row = vec!["1".to_string(), "say \"hi\"\n".to_string()];
+        assert_eq!(json_array(&row), r#"["1","say \"hi\"\n"]"#);
+        assert_eq!(json_array(&[]), "[]");
+    }
+}
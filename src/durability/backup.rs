@@ -0,0 +1,149 @@
+use std::{io::Write, path::Path};
+
+use super::DurabilityError;
+
+/// filename_len and file_len.
+const ENTRY_HEADER_SIZE: usize = 4 + 8;
+
+/// Writes `files` to a single backup file at `path`. Every file is stored as
+/// its name length, its length, its name and then its bytes, so the backup is
+/// a plain concatenation of entries. Only the file name is stored, without
+/// the directory, restoring puts every file in the database directory.
+/// Files that do not exist are skipped. Returns the number of files written.
+pub fn backup(path: &str, files: &[String]) -> Result<usize, DurabilityError> {
+    let mut backup = std::fs::File::create(path).map_err(DurabilityError::IoError)?;
+    let mut written = 0;
+    for name in files {
+        let data = match std::fs::read(name) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(DurabilityError::IoError(e)),
+        };
+
+        let name = match Path::new(name).file_name() {
+            Some(file_name) => file_name.to_string_lossy(),
+            None => return Err(format!("Invalid file name {}", name).into()),
+        };
+        let mut entry = Vec::with_capacity(ENTRY_HEADER_SIZE + name.len() + data.len());
+        entry.extend((name.len() as u32).to_le_bytes());
+        entry.extend((data.len() as u64).to_le_bytes());
+        entry.extend(name.as_bytes());
+        entry.extend(data);
+        backup.write_all(&entry).map_err(DurabilityError::IoError)?;
+        written += 1;
+    }
+    backup.sync_all().map_err(DurabilityError::IoError)?;
+    Ok(written)
+}
+
+/// Whether `name` is a plain file name, so a backup can not write outside
+/// the directory it is restored to.
+fn is_file_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\', '\0'])
+}
+
+/// Reads a backup written by `backup` and writes every file it holds back to
+/// `directory`. The whole backup is validated before anything is written, a
+/// name that is not a plain file name is rejected. Returns the paths of the
+/// restored files.
+pub fn restore(path: &str, directory: &str) -> Result<Vec<String>, DurabilityError> {
+    let data = std::fs::read(path).map_err(DurabilityError::IoError)?;
+    let invalid = || DurabilityError::DbError(format!("Invalid backup file {}", path));
+
+    let mut entries = vec![];
+    let mut rest = data.as_slice();
+    while !rest.is_empty() {
+        if rest.len() < ENTRY_HEADER_SIZE {
+            return Err(invalid());
+        }
+        let name_len = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+        let file_len = u64::from_le_bytes(rest[4..12].try_into().unwrap()) as usize;
+        let rest_len = rest.len() - ENTRY_HEADER_SIZE;
+        if name_len > rest_len || file_len > rest_len - name_len {
+            return Err(invalid());
+        }
+
+        let name_end = ENTRY_HEADER_SIZE + name_len;
+        let name =
+            std::str::from_utf8(&rest[ENTRY_HEADER_SIZE..name_end]).map_err(|_| invalid())?;
+        if !is_file_name(name) {
+            return Err(DurabilityError::DbError(format!(
+                "Invalid file name {} in backup file {}",
+                name, path
+            )));
+        }
+        let name = Path::new(directory)
+            .join(name)
+            .to_string_lossy()
+            .to_string();
+        entries.push((name, &rest[name_end..name_end + file_len]));
+        rest = &rest[name_end + file_len..];
+    }
+
+    for (name, data) in entries.iter() {
+        std::fs::write(name, data).map_err(DurabilityError::IoError)?;
+    }
+    Ok(entries.into_iter().map(|(name, _)| name).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_backup_and_restore() {
+        let tmp_dir = tempdir().unwrap();
+        let path = |name: &str| tmp_dir.path().join(name).to_str().unwrap().to_string();
+        std::fs::write(path("users"), b"table data").unwrap();
+        std::fs::write(path("users.fk"), b"").unwrap();
+
+        let files = vec![path("users"), path("users.fk"), path("users.wal")];
+        assert_eq!(backup(&path("backup.db"), &files).unwrap(), 2);
+
+        std::fs::write(path("users"), b"changed").unwrap();
+        std::fs::remove_file(path("users.fk")).unwrap();
+        assert_eq!(
+            restore(&path("backup.db"), tmp_dir.path().to_str().unwrap()).unwrap(),
+            vec![path("users"), path("users.fk")]
+        );
+        assert_eq!(std::fs::read(path("users")).unwrap(), b"table data");
+        assert_eq!(std::fs::read(path("users.fk")).unwrap(), b"");
+        assert!(!std::path::Path::new(&path("users.wal")).exists());
+    }
+
+    #[test]
+    fn test_restore_truncated_backup() {
+        let tmp_dir = tempdir().unwrap();
+        let path = |name: &str| tmp_dir.path().join(name).to_str().unwrap().to_string();
+        std::fs::write(path("users"), b"table data").unwrap();
+        backup(&path("backup.db"), &[path("users")]).unwrap();
+
+        let data = std::fs::read(path("backup.db")).unwrap();
+        std::fs::write(path("backup.db"), &data[..data.len() - 1]).unwrap();
+        std::fs::write(path("users"), b"changed").unwrap();
+        assert!(restore(&path("backup.db"), tmp_dir.path().to_str().unwrap()).is_err());
+        assert_eq!(std::fs::read(path("users")).unwrap(), b"changed");
+    }
+
+    #[test]
+    fn test_restore_rejects_paths() {
+        let tmp_dir = tempdir().unwrap();
+        let directory = tmp_dir.path().join("db");
+        std::fs::create_dir(&directory).unwrap();
+        let directory = directory.to_str().unwrap();
+        let outside = tmp_dir.path().join("outside").to_str().unwrap().to_string();
+        for name in [outside.as_str(), "../outside", "..", "", "a/b"] {
+            let mut entry = (name.len() as u32).to_le_bytes().to_vec();
+            entry.extend(4u64.to_le_bytes());
+            entry.extend(name.as_bytes());
+            entry.extend(b"evil");
+            let path = tmp_dir.path().join("backup.db");
+            std::fs::write(&path, &entry).unwrap();
+            assert!(restore(path.to_str().unwrap(), directory).is_err());
+        }
+        assert!(!std::path::Path::new(&outside).exists());
+        assert_eq!(std::fs::read_dir(directory).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,587 @@
+use std::{
+    io::{BufReader, BufWriter, Error, ErrorKind, Read, Write},
+    os::unix::fs::FileExt,
+};
+
+use crate::query::predicate::Predicate;
+
+use super::{
+    table::{
+        create_table_from, drop_table, table_exists, writeable_table_file, ColumnType, Row,
+        ScanHint, Table, TableScanner,
+    },
+    DurabilityError, Durable,
+};
+
+/// Rows buffered before they are appended with a single write.
+pub const COPY_BATCH_SIZE: usize = 1000;
+
+/// Starts a file written by `COPY ... TO 'path' BINARY`, followed by the
+/// column count as a little endian u32 and the row count as a u64.
+pub const COPY_FILE_MAGIC: [u8; 8] = *b"CITYDB\0\0";
+
+/// Largest field accepted, guards against allocating whatever a corrupt
+/// length prefix asks for.
+const MAX_FIELD_SIZE: u32 = 1024 * 1024;
+
+/// Reads one row of the `COPY ... BINARY` format: a little endian u32 field
+/// count followed by every field as a u32 length and its bytes. A row of
+/// zero fields ends the copy and reads as `None`.
+pub fn read_binary_row<R: Read + ?Sized>(reader: &mut R) -> std::io::Result<Option<Row>> {
+    let field_count = read_u32(reader)?;
+    let mut data = Vec::with_capacity(field_count.min(256) as usize);
+    for _ in 0..field_count {
+        let field_len = read_u32(reader)?;
+        if field_len > MAX_FIELD_SIZE {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Field of {} bytes is too large", field_len),
+            ));
+        }
+        let mut field = vec![0; field_len as usize];
+        reader.read_exact(&mut field)?;
+        data.push(field);
+    }
+    Ok((field_count > 0).then_some(Row { data }))
+}
+
+fn read_u32<R: Read + ?Sized>(reader: &mut R) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Writes one row in the format `read_binary_row` reads.
+pub fn write_binary_row<W: Write + ?Sized>(writer: &mut W, row: &Row) -> std::io::Result<()> {
+    writer.write_all(&(row.data.len() as u32).to_le_bytes())?;
+    for field in row.data.iter() {
+        writer.write_all(&(field.len() as u32).to_le_bytes())?;
+        writer.write_all(field)?;
+    }
+    Ok(())
+}
+
+/// Reads rows until the terminator without storing them, so the stream stays
+/// in step when a copy is refused.
+pub fn skip_binary_rows<R: Read + ?Sized>(reader: &mut R) -> std::io::Result<()> {
+    while read_binary_row(reader)?.is_some() {}
+    Ok(())
+}
+
+/// Appends the rows read from `reader` to the table in batches of
+/// `COPY_BATCH_SIZE`. A batch that fails its constraints stops the copy and
+/// leaves the batches before it in place, the rest of the stream is still
+/// read up to the terminator. Returns the number of rows copied.
+pub fn copy_binary<R: Read + ?Sized>(
+    table: &mut Table,
+    file: &mut std::fs::File,
+    reader: &mut R,
+) -> Result<u64, DurabilityError> {
+    let mut batch = Vec::with_capacity(COPY_BATCH_SIZE);
+    let mut copied = 0;
+    let mut failed = None;
+    while let Some(row) = read_binary_row(reader).map_err(DurabilityError::IoError)? {
+        if failed.is_some() {
+            continue;
+        }
+        batch.push(row);
+        if batch.len() == COPY_BATCH_SIZE {
+            match table.add_rows(&batch, file) {
+                Ok(()) => copied += batch.len() as u64,
+                Err(e) => failed = Some(e),
+            }
+            batch.clear();
+        }
+    }
+
+    if let Some(e) = failed {
+        return Err(e);
+    }
+    table.add_rows(&batch, file)?;
+    Ok(copied + batch.len() as u64)
+}
+
+/// Writes every row of the table to a new file at `path`: the file header,
+/// the rows as stored with their fixed width fields and the terminator.
+/// Returns the number of rows written.
+pub fn export_binary(
+    table: &Table,
+    file: &std::fs::File,
+    path: &str,
+) -> Result<u64, DurabilityError> {
+    let output = std::fs::File::create(path).map_err(DurabilityError::IoError)?;
+    let mut writer = BufWriter::new(output);
+    let mut header = COPY_FILE_MAGIC.to_vec();
+    header.extend(table.column_count.to_le_bytes());
+    header.extend(table.row_count.to_le_bytes());
+    writer
+        .write_all(&header)
+        .map_err(DurabilityError::IoError)?;
+
+    let mut scanner = TableScanner::with_hint(table, file, ScanHint::Sequential);
+    let mut row_count = 0u64;
+    for page_number in 0..table.page_count() {
+        let page = scanner
+            .page(page_number)
+            .map_err(DurabilityError::DbError)?;
+        for row in table.page_rows(&page) {
+            write_binary_row(&mut writer, &row).map_err(DurabilityError::IoError)?;
+            row_count += 1;
+        }
+    }
+    writer
+        .write_all(&0u32.to_le_bytes())
+        .and_then(|()| writer.flush())
+        .map_err(DurabilityError::IoError)?;
+    // Deleted rows still count in the table's row count, the header gets
+    // the number of rows actually written.
+    if row_count != table.row_count {
+        writer
+            .get_ref()
+            .write_all_at(&row_count.to_le_bytes(), COPY_FILE_MAGIC.len() as u64 + 4)
+            .map_err(DurabilityError::IoError)?;
+    }
+    Ok(row_count)
+}
+
+/// Appends the rows of a file written by `export_binary`, see `copy_binary`.
+/// The file is refused before any row is read unless it was exported from a
+/// table with as many columns, and a file ending before its header's row
+/// count is reported once the rows it holds are copied.
+pub fn import_binary(
+    table: &mut Table,
+    file: &mut std::fs::File,
+    path: &str,
+) -> Result<u64, DurabilityError> {
+    let input = std::fs::File::open(path).map_err(DurabilityError::IoError)?;
+    let mut reader = BufReader::new(input);
+    let mut header = [0; 20];
+    reader
+        .read_exact(&mut header)
+        .map_err(DurabilityError::IoError)?;
+    if header[..8] != COPY_FILE_MAGIC {
+        return Err(DurabilityError::DbError(format!(
+            "{} is not a binary copy",
+            path
+        )));
+    }
+    let column_count = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    let row_count = u64::from_le_bytes(header[12..].try_into().unwrap());
+    if column_count != table.column_count {
+        return Err(DurabilityError::DbError(format!(
+            "{} holds {} column(s), table {} has {}",
+            path,
+            column_count,
+            table.name_str(),
+            table.column_count
+        )));
+    }
+
+    let copied = copy_binary(table, file, &mut reader)?;
+    if copied != row_count {
+        return Err(DurabilityError::DbError(format!(
+            "{} holds {} of its {} row(s)",
+            path, copied, row_count
+        )));
+    }
+    Ok(copied)
+}
+
+/// Creates the table `destination` with the columns of `source` and copies
+/// the rows matching every one of `predicates` into it, in batches of
+/// `COPY_BATCH_SIZE`. Foreign keys are not copied. A row failing the
+/// constraints of the copy removes it again. Returns the number of rows
+/// copied.
+pub fn copy_table(
+    source: &Table,
+    file: &std::fs::File,
+    destination: &str,
+    predicates: &[Predicate],
+) -> Result<u64, DurabilityError> {
+    if table_exists(destination) {
+        return Err(format!("Table {} already exists", destination).into());
+    }
+    create_table_from(source.clone_schema(destination)?)?;
+    let copied = copy_rows(source, file, destination, predicates);
+    if copied.is_err() {
+        drop_table(destination)?;
+    }
+    copied
+}
+
+fn copy_rows(
+    source: &Table,
+    file: &std::fs::File,
+    destination: &str,
+    predicates: &[Predicate],
+) -> Result<u64, DurabilityError> {
+    let mut destination_file = writeable_table_file(destination.to_string())?;
+    let mut copy = Table::read_from_disk(&mut destination_file)?;
+    let mut scanner = TableScanner::with_hint(source, file, ScanHint::Sequential);
+    let mut batch = Vec::with_capacity(COPY_BATCH_SIZE);
+    let mut copied = 0;
+    for page_number in 0..source.page_count() {
+        let page = scanner.page(page_number)?;
+        for row in source.page_rows(&page) {
+            if !matches_all(&row, source, predicates)? {
+                continue;
+            }
+            batch.push(row);
+            if batch.len() == COPY_BATCH_SIZE {
+                copy.add_rows(&batch, &mut destination_file)?;
+                copied += batch.len() as u64;
+                batch.clear();
+            }
+        }
+    }
+    copy.add_rows(&batch, &mut destination_file)?;
+    Ok(copied + batch.len() as u64)
+}
+
+fn matches_all(
+    row: &Row,
+    table: &Table,
+    predicates: &[Predicate],
+) -> Result<bool, DurabilityError> {
+    for predicate in predicates {
+        if !predicate.matches(row, &table.columns)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// The rows of `source` matching every one of `predicates`, as stored.
+pub fn select_rows(
+    source: &Table,
+    file: &std::fs::File,
+    predicates: &[Predicate],
+) -> Result<Vec<Row>, DurabilityError> {
+    let mut scanner = TableScanner::with_hint(source, file, ScanHint::Sequential);
+    let mut rows = vec![];
+    for page_number in 0..source.page_count() {
+        let page = scanner.page(page_number)?;
+        for row in source.page_rows(&page) {
+            if matches_all(&row, source, predicates)? {
+                rows.push(row);
+            }
+        }
+    }
+    Ok(rows)
+}
+
+/// Fits rows read from `source` to the columns of `destination` for
+/// `INSERT ... SELECT`. The tables need as many columns with the same types,
+/// a `Varchar` column no narrower than the one it is copied from. Values are
+/// padded to the width of their destination column, or cut when only their
+/// zero padding is lost.
+pub fn fit_rows(
+    rows: Vec<Row>,
+    source: &Table,
+    destination: &Table,
+) -> Result<Vec<Row>, DurabilityError> {
+    // Rows of the same layout go in as they are.
+    if destination.is_schema_compatible(source) {
+        return Ok(rows);
+    }
+    if source.columns.len() != destination.columns.len() {
+        return Err(format!(
+            "Table {} has {} column(s), table {} has {}",
+            source.name_str(),
+            source.columns.len(),
+            destination.name_str(),
+            destination.columns.len()
+        )
+        .into());
+    }
+    for (from, to) in source.columns.iter().zip(destination.columns.iter()) {
+        let compatible = from.column_type == to.column_type
+            && (from.column_type != ColumnType::Varchar || from.length <= to.length);
+        if !compatible {
+            return Err(format!(
+                "Column {} of type {}({}) cannot be copied into column {} of type {}({})",
+                from.name_str(),
+                from.column_type.sql_name(),
+                from.length,
+                to.name_str(),
+                to.column_type.sql_name(),
+                to.length
+            )
+            .into());
+        }
+    }
+
+    rows.into_iter()
+        .map(|row| {
+            let data = row
+                .data
+                .into_iter()
+                .zip(destination.columns.iter())
+                .map(|(mut value, column)| {
+                    let length = column.length as usize;
+                    if value.iter().skip(length).any(|b| *b != 0) {
+                        return Err(DurabilityError::DbError(format!(
+                            "Value too long for column {}",
+                            column.name_str()
+                        )));
+                    }
+                    value.resize(length, 0);
+                    Ok(value)
+                })
+                .collect::<Result<Vec<Vec<u8>>, DurabilityError>>()?;
+            Ok(Row { data })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::table::{create_table, TableBuilder};
+
+    fn binary_rows(rows: &[&[&str]]) -> Vec<u8> {
+        let mut bytes = vec![];
+        for row in rows {
+            bytes.extend((row.len() as u32).to_le_bytes());
+            for field in row.iter() {
+                bytes.extend((field.len() as u32).to_le_bytes());
+                bytes.extend(field.as_bytes());
+            }
+        }
+        bytes.extend(0u32.to_le_bytes());
+        bytes
+    }
+
+    fn create_users(dir: &std::path::Path) -> (Table, std::fs::File) {
+        create_users_named(dir, "users")
+    }
+
+    fn create_users_named(dir: &std::path::Path, name: &str) -> (Table, std::fs::File) {
+        let name = dir.join(name).to_str().unwrap().to_string();
+        let table = TableBuilder::new(&name)
+            .column("id", ColumnType::Int, 8)
+            .column("city", ColumnType::Varchar, 8)
+            .primary_key("id");
+        create_table(table).unwrap();
+        let mut file = writeable_table_file(name).unwrap();
+        (Table::read_from_disk(&mut file).unwrap(), file)
+    }
+
+    #[test]
+    fn test_copy_table() {
+        let tmp_dir = tempdir().unwrap();
+        let (mut table, mut file) = create_users(tmp_dir.path());
+        let rows: Vec<Row> = (0..2500)
+            .map(|id| Row {
+                data: vec![
+                    id.to_string().into_bytes(),
+                    if id % 5 == 0 { "Bergen" } else { "Oslo" }.into(),
+                ],
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+        let path = |name: &str| tmp_dir.path().join(name).to_str().unwrap().to_string();
+
+        let backup = path("users_backup");
+        assert_eq!(copy_table(&table, &file, &backup, &[]).unwrap(), 2500);
+        assert!(copy_table(&table, &file, &backup, &[]).is_err());
+        let bergen = path("users_bergen");
+        let predicates = [Predicate::parse("city = 'Bergen'").unwrap()];
+        assert_eq!(
+            copy_table(&table, &file, &bergen, &predicates).unwrap(),
+            500
+        );
+
+        table
+            .add_row(
+                &Row {
+                    data: vec![b"2500".to_vec(), b"Oslo".to_vec()],
+                },
+                &mut file,
+            )
+            .unwrap();
+        table.delete_at(&mut file, 0, 0).unwrap();
+
+        let (copy, copy_file) = {
+            let mut file = writeable_table_file(backup).unwrap();
+            (Table::read_from_disk(&mut file).unwrap(), file)
+        };
+        assert_eq!(copy.row_count, 2500);
+        assert_eq!(copy.primary_key_column(), Some(0));
+        let copied: Vec<Row> = (0..copy.page_count())
+            .flat_map(|page| copy.page_rows(&copy.page_at(&copy_file, page).unwrap()))
+            .collect();
+        assert_eq!(copied.len(), 2500);
+        assert_eq!(&copied[0].data[0][..2], b"0\0");
+        assert_eq!(&copied[2499].data[0][..5], b"2499\0");
+    }
+
+    #[test]
+    fn test_select_and_fit_rows() {
+        let tmp_dir = tempdir().unwrap();
+        let (mut table, mut file) = create_users(tmp_dir.path());
+        let rows: Vec<Row> = (0..20)
+            .map(|id| Row {
+                data: vec![
+                    id.to_string().into_bytes(),
+                    if id % 4 == 0 { "Bergen" } else { "Oslo" }.into(),
+                ],
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+
+        let archive_name = tmp_dir.path().join("archive").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&archive_name)
+                .column("id", ColumnType::Int, 4)
+                .column("city", ColumnType::Varchar, 16),
+        )
+        .unwrap();
+        let mut archive_file = writeable_table_file(archive_name).unwrap();
+        let mut archive = Table::read_from_disk(&mut archive_file).unwrap();
+
+        let predicates = [Predicate::parse("city = 'Bergen'").unwrap()];
+        let selected = select_rows(&table, &file, &predicates).unwrap();
+        let fitted = fit_rows(selected, &table, &archive).unwrap();
+        archive.add_rows(&fitted, &mut archive_file).unwrap();
+        assert_eq!(archive.row_count, 5);
+        let copied = archive.page_rows(&archive.page_at(&archive_file, 0).unwrap());
+        assert_eq!(copied[1].data[0], b"4\0\0\0");
+        assert_eq!(&copied[1].data[1][..7], b"Bergen\0");
+        assert_eq!(copied[1].data[1].len(), 16);
+        assert_eq!(select_rows(&table, &file, &[]).unwrap().len(), 20);
+
+        // Cutting an id wider than the archive column would lose digits, the
+        // narrower city column cannot hold every value of the wider one.
+        let wide = vec![Row {
+            data: vec![b"12345\0\0\0".to_vec(), b"Oslo\0\0\0\0".to_vec()],
+        }];
+        assert!(fit_rows(wide, &table, &archive).is_err());
+        assert!(fit_rows(vec![], &archive, &table).is_err());
+        let (other, _) = create_users_named(tmp_dir.path(), "other");
+        assert!(other.is_schema_compatible(&table));
+        let same = select_rows(&table, &file, &predicates).unwrap();
+        assert_eq!(fit_rows(same.clone(), &table, &other).unwrap(), same);
+        let ids_name = tmp_dir.path().join("ids").to_str().unwrap().to_string();
+        create_table(TableBuilder::new(&ids_name).column("id", ColumnType::Int, 8)).unwrap();
+        let ids = Table::read_from_disk(&mut writeable_table_file(ids_name).unwrap()).unwrap();
+        assert!(fit_rows(vec![], &table, &ids).is_err());
+    }
+
+    #[test]
+    fn test_copy_binary() {
+        let tmp_dir = tempdir().unwrap();
+        let (mut table, mut file) = create_users(tmp_dir.path());
+
+        let ids: Vec<String> = (0..2500).map(|id| id.to_string()).collect();
+        let rows: Vec<[&str; 2]> = ids.iter().map(|id| [id.as_str(), "Oslo"]).collect();
+        let rows: Vec<&[&str]> = rows.iter().map(|row| row.as_slice()).collect();
+        let mut stream = Cursor::new(binary_rows(&rows));
+        stream.get_mut().extend(b"next");
+
+        assert_eq!(
+            copy_binary(&mut table, &mut file, &mut stream).unwrap(),
+            2500
+        );
+        let mut rest = vec![];
+        stream.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"next");
+
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.row_count, 2500);
+        let last_page = table.page_at(&file, table.page_count() - 1).unwrap();
+        let last_row = table.page_rows(&last_page).pop().unwrap();
+        assert_eq!(last_row.data[0], b"2499\0\0\0\0");
+    }
+
+    #[test]
+    fn test_copy_binary_stops_at_failed_batch() {
+        let tmp_dir = tempdir().unwrap();
+        let (mut table, mut file) = create_users(tmp_dir.path());
+
+        let mut stream = Cursor::new(binary_rows(&[&["1", "Oslo"], &["1", "Bergen"]]));
+        stream.get_mut().extend(binary_rows(&[&["2", "Oslo"]]));
+        assert!(matches!(
+            copy_binary(&mut table, &mut file, &mut stream),
+            Err(DurabilityError::ConstraintViolation(_))
+        ));
+        assert_eq!(Table::read_from_disk(&mut file).unwrap().row_count, 0);
+
+        // The stream is read up to the terminator of the failed copy.
+        assert_eq!(copy_binary(&mut table, &mut file, &mut stream).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_truncated_stream() {
+        let mut stream = Cursor::new(binary_rows(&[&["1", "Oslo"]]));
+        stream.get_mut().truncate(10);
+        assert!(read_binary_row(&mut stream).is_err());
+    }
+
+    #[test]
+    fn test_export_and_import_binary() {
+        let tmp_dir = tempdir().unwrap();
+        let (mut table, mut file) = create_users(tmp_dir.path());
+        let rows: Vec<Row> = (1..=100)
+            .map(|id| Row {
+                data: vec![id.to_string().into_bytes(), b"Oslo".to_vec()],
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+
+        let path = tmp_dir
+            .path()
+            .join("users.bin")
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(export_binary(&table, &file, &path).unwrap(), 100);
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes[..8], COPY_FILE_MAGIC);
+        assert_eq!(bytes[8..12], 2u32.to_le_bytes());
+        assert_eq!(bytes[12..20], 100u64.to_le_bytes());
+
+        // An empty table with the same columns gets every row back as stored.
+        let (mut copy, mut copy_file) = create_users_named(tmp_dir.path(), "users_copy");
+        assert_eq!(
+            import_binary(&mut copy, &mut copy_file, &path).unwrap(),
+            100
+        );
+        assert_eq!(copy.row_count, 100);
+        for page_number in 0..table.page_count() {
+            let page = table.page_at(&file, page_number).unwrap();
+            let copied = copy.page_at(&copy_file, page_number).unwrap();
+            assert_eq!(copy.page_rows(&copied), table.page_rows(&page));
+        }
+    }
+
+    #[test]
+    fn test_import_binary_checks_header() {
+        let tmp_dir = tempdir().unwrap();
+        let (mut table, mut file) = create_users(tmp_dir.path());
+        let path = tmp_dir
+            .path()
+            .join("users.bin")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let mut bytes = COPY_FILE_MAGIC.to_vec();
+        bytes.extend(3u32.to_le_bytes());
+        bytes.extend(1u64.to_le_bytes());
+        bytes.extend(binary_rows(&[&["1", "Oslo", "Norway"]]));
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            import_binary(&mut table, &mut file, &path),
+            Err(DurabilityError::DbError(e)) if e.contains("holds 3 column(s)")
+        ));
+
+        std::fs::write(&path, binary_rows(&[&["1", "Oslo"]])).unwrap();
+        assert!(import_binary(&mut table, &mut file, &path).is_err());
+        assert_eq!(Table::read_from_disk(&mut file).unwrap().row_count, 0);
+    }
+}
@@ -23,26 +23,30 @@ impl Durable for DatabaseFile {
 pub struct DatabaseFileHeader {
     pub name: [u8; 64],
     pub table_count: u32,
+    /// The redo log sequence of the last `CHECKPOINT`. Headers written
+    /// before it was added end after `table_count` and read it as 0.
+    pub checkpoint_lsn: u64,
 }
 
 impl Durable for DatabaseFileHeader {
     fn write_to_disk(&mut self, file: &mut std::fs::File) -> Result<(), DurabilityError> {
         let bytes_written = file.write_at(&self.name, 0);
         if bytes_written.unwrap() != 64 {
-            return Err(DurabilityError::IoError(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            return Err(DurabilityError::IoError(std::io::Error::other(
                 "Failed to write header name",
             )));
         }
 
         let bytes_written = file.write_at(&self.table_count.to_ne_bytes(), 64);
         if bytes_written.unwrap() != 4 {
-            return Err(DurabilityError::IoError(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            return Err(DurabilityError::IoError(std::io::Error::other(
                 "Failed to write header column count",
             )));
         }
 
+        file.write_all_at(&self.checkpoint_lsn.to_ne_bytes(), 68)
+            .map_err(DurabilityError::IoError)?;
+
         Ok(())
     }
 
@@ -50,22 +54,33 @@ impl Durable for DatabaseFileHeader {
     where
         Self: Sized,
     {
-        const name_size: usize = 64;
-        const count_size: usize = 4;
-        const header_size: usize = name_size + count_size;
+        const NAME_SIZE: usize = 64;
+        const COUNT_SIZE: usize = 4;
+        const HEADER_SIZE: usize = NAME_SIZE + COUNT_SIZE;
 
-        let mut header_buffer = [0; header_size];
+        let mut header_buffer = [0; HEADER_SIZE];
         file.read_exact(&mut header_buffer).unwrap();
 
-        let mut name = [0; name_size];
-        name.copy_from_slice(&header_buffer[..name_size]);
+        let mut name = [0; NAME_SIZE];
+        name.copy_from_slice(&header_buffer[..NAME_SIZE]);
 
         let table_count = u32::from_ne_bytes(
-            header_buffer[name_size..name_size + count_size]
+            header_buffer[NAME_SIZE..NAME_SIZE + COUNT_SIZE]
                 .try_into()
                 .unwrap(),
         );
 
-        Ok(DatabaseFileHeader { name, table_count })
+        let mut checkpoint_lsn = [0; 8];
+        let checkpoint_lsn = match file.read_exact_at(&mut checkpoint_lsn, HEADER_SIZE as u64) {
+            Ok(()) => u64::from_ne_bytes(checkpoint_lsn),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => 0,
+            Err(e) => return Err(DurabilityError::IoError(e)),
+        };
+
+        Ok(DatabaseFileHeader {
+            name,
+            table_count,
+            checkpoint_lsn,
+        })
     }
 }
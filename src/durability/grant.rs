@@ -0,0 +1,278 @@
+use std::os::unix::fs::FileExt;
+
+use super::{DatabaseConfig, DurabilityError};
+
+const NAME_SIZE: usize = 64;
+const COLUMNS_SIZE: usize = 256;
+const RECORD_SIZE: usize = NAME_SIZE + NAME_SIZE + 1 + COLUMNS_SIZE;
+
+/// What a grant allows a user to do with the columns of a table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GrantOperation {
+    Select = 1,
+    Insert = 2,
+}
+
+impl GrantOperation {
+    fn from_u8(value: u8) -> Option<GrantOperation> {
+        match value {
+            1 => Some(GrantOperation::Select),
+            2 => Some(GrantOperation::Insert),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GrantOperation::Select => "SELECT",
+            GrantOperation::Insert => "INSERT",
+        }
+    }
+}
+
+/// The columns of `table` that `user` may use for `operation`, `*` for all
+/// of them. Stored in the `{database}.grants` file as the user, the table,
+/// the operation as a byte and the comma separated columns, each padded with
+/// zeros.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grant {
+    pub user: String,
+    pub table: String,
+    pub operation: GrantOperation,
+    pub columns: Vec<String>,
+}
+
+impl Grant {
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = self.user.as_bytes().to_vec();
+        bytes.resize(NAME_SIZE, 0);
+        bytes.extend(self.table.as_bytes());
+        bytes.resize(NAME_SIZE + NAME_SIZE, 0);
+        bytes.push(self.operation as u8);
+        bytes.extend(self.columns.join(",").as_bytes());
+        bytes.resize(RECORD_SIZE, 0);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let text = |bytes: &[u8]| {
+            let text = bytes.split(|b| *b == 0).next().unwrap_or_default();
+            String::from_utf8_lossy(text).to_string()
+        };
+        Some(Grant {
+            user: text(&bytes[..NAME_SIZE]),
+            table: text(&bytes[NAME_SIZE..NAME_SIZE + NAME_SIZE]),
+            operation: GrantOperation::from_u8(bytes[NAME_SIZE + NAME_SIZE])?,
+            columns: text(&bytes[NAME_SIZE + NAME_SIZE + 1..RECORD_SIZE])
+                .split(',')
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+
+    pub fn all_columns(&self) -> bool {
+        self.columns.iter().any(|column| column == "*")
+    }
+}
+
+pub fn grants_file(database: &DatabaseConfig) -> String {
+    format!("{}/{}.grants", database.file_path, database.name)
+}
+
+fn open_grants_file(path: &str) -> Result<std::fs::File, DurabilityError> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(DurabilityError::IoError)
+}
+
+fn read_grants(file: &std::fs::File) -> Result<Vec<Grant>, DurabilityError> {
+    let length = file.metadata().map_err(DurabilityError::IoError)?.len();
+    let mut data = vec![0; length as usize];
+    file.read_exact_at(&mut data, 0)
+        .map_err(DurabilityError::IoError)?;
+    data.chunks_exact(RECORD_SIZE)
+        .map(|record| {
+            Grant::from_bytes(record)
+                .ok_or_else(|| DurabilityError::DbError("Invalid grants file".to_string()))
+        })
+        .collect()
+}
+
+fn write_grants(file: &std::fs::File, grants: &[Grant]) -> Result<(), DurabilityError> {
+    let bytes: Vec<u8> = grants.iter().flat_map(|grant| grant.bytes()).collect();
+    file.set_len(0).map_err(DurabilityError::IoError)?;
+    file.write_all_at(&bytes, 0)
+        .map_err(DurabilityError::IoError)
+}
+
+/// Adds the columns of `grant` to what the user was already granted for the
+/// table and operation.
+pub fn grant(path: &str, grant: Grant) -> Result<(), DurabilityError> {
+    for (kind, name) in [("user", &grant.user), ("table", &grant.table)] {
+        if name.is_empty() || name.len() > 63 {
+            return Err(DurabilityError::DbError(format!(
+                "Invalid {} name {}, must be between 1 and 63 bytes",
+                kind, name
+            )));
+        }
+    }
+
+    let file = open_grants_file(path)?;
+    let mut grants = read_grants(&file)?;
+    let existing = grants.iter_mut().find(|existing| {
+        existing.user == grant.user
+            && existing.table == grant.table
+            && existing.operation == grant.operation
+    });
+    let merged = match existing {
+        Some(existing) => {
+            if grant.all_columns() {
+                existing.columns = vec!["*".to_string()];
+            } else if !existing.all_columns() {
+                for column in grant.columns {
+                    if !existing.columns.contains(&column) {
+                        existing.columns.push(column);
+                    }
+                }
+            }
+            existing.clone()
+        }
+        None => {
+            grants.push(grant);
+            grants.last().unwrap().clone()
+        }
+    };
+    if merged.columns.join(",").len() > COLUMNS_SIZE {
+        return Err(DurabilityError::DbError(format!(
+            "The columns granted to {} on {} are longer than {} bytes",
+            merged.user, merged.table, COLUMNS_SIZE
+        )));
+    }
+    write_grants(&file, &grants)
+}
+
+/// Takes back everything `user` was granted for `operation` on `table`.
+pub fn revoke(
+    path: &str,
+    user: &str,
+    table: &str,
+    operation: GrantOperation,
+) -> Result<(), DurabilityError> {
+    let file = open_grants_file(path)?;
+    let mut grants = read_grants(&file)?;
+    let position = grants
+        .iter()
+        .position(|grant| {
+            grant.user == user && grant.table == table && grant.operation == operation
+        })
+        .ok_or_else(|| {
+            DurabilityError::DbError(format!(
+                "User {} has no {} grant on {}",
+                user,
+                operation.name(),
+                table
+            ))
+        })?;
+    grants.remove(position);
+    write_grants(&file, &grants)
+}
+
+/// Everything granted to `user`, in the order it was first granted.
+pub fn user_grants(path: &str, user: &str) -> Result<Vec<Grant>, DurabilityError> {
+    Ok(read_grants(&open_grants_file(path)?)?
+        .into_iter()
+        .filter(|grant| grant.user == user)
+        .collect())
+}
+
+/// The columns `user` may use for `operation` on `table`. `None` when nobody
+/// was granted `operation` on the table, which leaves it open to every user,
+/// and an empty list when others were but `user` was not.
+pub fn permitted_columns(
+    path: &str,
+    user: &str,
+    table: &str,
+    operation: GrantOperation,
+) -> Result<Option<Vec<String>>, DurabilityError> {
+    let grants: Vec<Grant> = read_grants(&open_grants_file(path)?)?
+        .into_iter()
+        .filter(|grant| grant.table == table && grant.operation == operation)
+        .collect();
+    if grants.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(
+        grants
+            .into_iter()
+            .find(|grant| grant.user == user)
+            .map(|grant| grant.columns)
+            .unwrap_or_default(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn select(user: &str, columns: &[&str]) -> Grant {
+        Grant {
+            user: user.to_string(),
+            table: "users".to_string(),
+            operation: GrantOperation::Select,
+            columns: columns.iter().map(|column| column.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_grant_and_revoke() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("test.grants");
+        let path = path.to_str().unwrap();
+        let permitted =
+            |user: &str, operation| permitted_columns(path, user, "users", operation).unwrap();
+
+        assert_eq!(permitted("alice", GrantOperation::Select), None);
+        grant(path, select("alice", &["id", "name"])).unwrap();
+        grant(path, select("alice", &["name", "city"])).unwrap();
+        grant(path, select("bob", &["*"])).unwrap();
+        assert!(grant(path, select("", &["id"])).is_err());
+        assert!(grant(path, select("carol", &[&"x".repeat(COLUMNS_SIZE + 1)])).is_err());
+
+        assert_eq!(
+            permitted("alice", GrantOperation::Select),
+            Some(vec![
+                "id".to_string(),
+                "name".to_string(),
+                "city".to_string()
+            ])
+        );
+        assert_eq!(
+            permitted("bob", GrantOperation::Select),
+            Some(vec!["*".to_string()])
+        );
+        assert_eq!(permitted("carol", GrantOperation::Select), Some(vec![]));
+        assert_eq!(permitted("carol", GrantOperation::Insert), None);
+        assert_eq!(
+            user_grants(path, "alice").unwrap(),
+            [select("alice", &["id", "name", "city"])]
+        );
+
+        grant(path, select("alice", &["*"])).unwrap();
+        assert_eq!(
+            permitted("alice", GrantOperation::Select),
+            Some(vec!["*".to_string()])
+        );
+
+        revoke(path, "alice", "users", GrantOperation::Select).unwrap();
+        assert!(revoke(path, "alice", "users", GrantOperation::Select).is_err());
+        assert_eq!(permitted("alice", GrantOperation::Select), Some(vec![]));
+        revoke(path, "bob", "users", GrantOperation::Select).unwrap();
+        assert_eq!(permitted("alice", GrantOperation::Select), None);
+    }
+}
@@ -0,0 +1,509 @@
+use std::{collections::HashMap, os::unix::fs::FileExt};
+
+use super::{
+    index::index_key,
+    table::{ColumnType, Table},
+    DurabilityError,
+};
+
+const NAME_SIZE: usize = 64;
+/// name, column, key_len and indexed_rows, followed by the global depth.
+const HEADER_SIZE: usize = NAME_SIZE * 2 + 8 + 8 + 1;
+/// Entries held by a bucket before it is split or chained.
+const BUCKET_CAPACITY: usize = 8;
+/// local_depth, entry count and the offset of the overflow bucket.
+const BUCKET_HEADER_SIZE: usize = 1 + 4 + 8;
+/// Deepest the directory grows, 2^16 bucket pointers.
+const MAX_DEPTH: u8 = 16;
+
+pub fn hash_index_file(table: &str, column: &str) -> String {
+    format!("{}.{}.hash", table, column)
+}
+
+/// FNV-1a, stable across builds unlike the std hasher so it can be stored.
+pub fn hash(key: &[u8]) -> u64 {
+    key.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Debug, Clone, Default)]
+struct Bucket {
+    local_depth: u8,
+    entries: Vec<(Vec<u8>, u64)>,
+    overflow: Option<usize>,
+}
+
+/// An extendible hash index over one column, kept in `{table}.{column}.hash`
+/// and loaded into memory whole. Only answers equality lookups, in exchange
+/// a lookup reads a single bucket chain whatever the size of the index.
+///
+/// The directory holds 2^depth bucket pointers and the low `depth` bits of a
+/// key's hash pick the pointer. A full bucket is split in two, doubling the
+/// directory when the bucket was already as deep as it. Keys sharing every
+/// hash bit the directory can use, such as the rows of a repeated value, can
+/// not be split apart and are chained in overflow buckets instead. Buckets
+/// are never merged back.
+///
+/// On disk the header is followed by the global depth as one byte, the
+/// directory as 8 byte bucket offsets and the buckets, each holding its
+/// local depth, entry count, the offset of its overflow bucket or 0 and
+/// `BUCKET_CAPACITY` `(key, row_index)` slots of `key_len + 8` bytes.
+#[derive(Debug)]
+pub struct HashIndex {
+    pub name: String,
+    pub column: String,
+    /// Keys are padded or cut to this length.
+    pub key_len: u64,
+    pub indexed_rows: u64,
+    depth: u8,
+    directory: Vec<usize>,
+    buckets: Vec<Bucket>,
+}
+
+impl HashIndex {
+    pub fn new(name: &str, column: &str, key_len: u64) -> HashIndex {
+        HashIndex {
+            name: name.to_string(),
+            column: column.to_string(),
+            key_len,
+            indexed_rows: 0,
+            depth: 0,
+            directory: vec![0],
+            buckets: vec![Bucket::default()],
+        }
+    }
+
+    /// Scans the table to index every row of `column`.
+    pub fn build(
+        name: &str,
+        table: &Table,
+        file: &std::fs::File,
+        column: &str,
+    ) -> Result<HashIndex, DurabilityError> {
+        if name.is_empty() || name.len() >= NAME_SIZE {
+            return Err(DurabilityError::DbError(format!(
+                "Invalid index name {}, must be between 1 and 63 bytes",
+                name
+            )));
+        }
+        let position = table
+            .columns
+            .iter()
+            .position(|c| c.name_str() == column)
+            .ok_or_else(|| DurabilityError::DbError(format!("Column {} does not exist", column)))?;
+        let column_type = &table.columns[position].column_type;
+
+        let mut index = HashIndex::new(name, column, table.columns[position].length);
+        for page_number in 0..table.page_count() {
+            let page = table.page_at(file, page_number)?;
+            for (row_index, row) in table.page_entries(&page) {
+                let key = index.key(&row.data[position], column_type);
+                index.insert(&key, row_index);
+            }
+        }
+        index.indexed_rows = table.row_count;
+        Ok(index)
+    }
+
+    /// The key a stored value or a literal of the column is hashed by.
+    pub fn key(&self, value: &[u8], column_type: &ColumnType) -> Vec<u8> {
+        let mut key = index_key(value, column_type);
+        key.resize(self.key_len as usize, 0);
+        key
+    }
+
+    fn slot(&self, key: &[u8]) -> usize {
+        (hash(key) & ((1 << self.depth) - 1)) as usize
+    }
+
+    /// The bucket ids of the chain starting at `bucket`.
+    fn chain(&self, bucket: usize) -> Vec<usize> {
+        let mut chain = vec![bucket];
+        while let Some(next) = self.buckets[*chain.last().unwrap()].overflow {
+            chain.push(next);
+        }
+        chain
+    }
+
+    /// The rows holding `key`, in the order they were inserted.
+    pub fn lookup(&self, key: &[u8]) -> Vec<u64> {
+        self.chain(self.directory[self.slot(key)])
+            .into_iter()
+            .flat_map(|bucket| self.buckets[bucket].entries.iter())
+            .filter(|(entry, _)| entry == key)
+            .map(|(_, row)| *row)
+            .collect()
+    }
+
+    pub fn insert(&mut self, key: &[u8], row_index: u64) {
+        loop {
+            let bucket = self.directory[self.slot(key)];
+            let chain = self.chain(bucket);
+            let has_room = chain
+                .iter()
+                .any(|id| self.buckets[*id].entries.len() < BUCKET_CAPACITY);
+            if has_room || !self.splittable(&chain, key) {
+                self.append(bucket, key.to_vec(), row_index);
+                return;
+            }
+            self.split(bucket);
+        }
+    }
+
+    /// Removes the entry of `row_index` under `key`, false if there is none.
+    pub fn delete(&mut self, key: &[u8], row_index: u64) -> bool {
+        for bucket in self.chain(self.directory[self.slot(key)]) {
+            let entries = &mut self.buckets[bucket].entries;
+            if let Some(position) = entries
+                .iter()
+                .position(|(entry, row)| entry == key && *row == row_index)
+            {
+                entries.remove(position);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether splitting the chain would spread its keys and `key` over more
+    /// than one bucket.
+    fn splittable(&self, chain: &[usize], key: &[u8]) -> bool {
+        if self.buckets[chain[0]].local_depth >= MAX_DEPTH {
+            return false;
+        }
+        let mask = (1 << MAX_DEPTH) - 1;
+        let bits = hash(key) & mask;
+        chain
+            .iter()
+            .flat_map(|bucket| self.buckets[*bucket].entries.iter())
+            .any(|(entry, _)| hash(entry) & mask != bits)
+    }
+
+    /// Stores the entry in the first bucket of the chain with room, chaining
+    /// a new overflow bucket when every one is full.
+    fn append(&mut self, bucket: usize, key: Vec<u8>, row_index: u64) {
+        let chain = self.chain(bucket);
+        let target = match chain
+            .iter()
+            .find(|id| self.buckets[**id].entries.len() < BUCKET_CAPACITY)
+        {
+            Some(target) => *target,
+            None => {
+                let overflow = self.buckets.len();
+                self.buckets.push(Bucket {
+                    local_depth: self.buckets[bucket].local_depth,
+                    ..Bucket::default()
+                });
+                self.buckets[*chain.last().unwrap()].overflow = Some(overflow);
+                overflow
+            }
+        };
+        self.buckets[target].entries.push((key, row_index));
+    }
+
+    /// Splits the chain starting at `bucket` on the next hash bit, its
+    /// overflow buckets are dropped and their entries redistributed.
+    fn split(&mut self, bucket: usize) {
+        let entries: Vec<(Vec<u8>, u64)> = self
+            .chain(bucket)
+            .into_iter()
+            .flat_map(|id| std::mem::take(&mut self.buckets[id].entries))
+            .collect();
+        let local_depth = self.buckets[bucket].local_depth + 1;
+        if local_depth > self.depth {
+            self.directory.extend_from_within(..);
+            self.depth += 1;
+        }
+
+        let sibling = self.buckets.len();
+        self.buckets[bucket] = Bucket {
+            local_depth,
+            ..Bucket::default()
+        };
+        self.buckets.push(Bucket {
+            local_depth,
+            ..Bucket::default()
+        });
+        let bit = 1 << (local_depth - 1);
+        for (slot, pointer) in self.directory.iter_mut().enumerate() {
+            if *pointer == bucket && slot & bit != 0 {
+                *pointer = sibling;
+            }
+        }
+        for (key, row_index) in entries {
+            let target = self.directory[self.slot(&key)];
+            self.append(target, key, row_index);
+        }
+    }
+
+    /// Buckets reachable from the directory, in the order they are written,
+    /// with the offset each is written at.
+    fn layout(&self) -> (Vec<usize>, HashMap<usize, u64>) {
+        let bucket_size =
+            (BUCKET_HEADER_SIZE + BUCKET_CAPACITY * (self.key_len as usize + 8)) as u64;
+        let mut offset = (HEADER_SIZE + self.directory.len() * 8) as u64;
+        let mut order = vec![];
+        let mut offsets = HashMap::new();
+        for pointer in self.directory.iter() {
+            for bucket in self.chain(*pointer) {
+                if offsets.contains_key(&bucket) {
+                    continue;
+                }
+                offsets.insert(bucket, offset);
+                order.push(bucket);
+                offset += bucket_size;
+            }
+        }
+        (order, offsets)
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for name in [&self.name, &self.column] {
+            let mut name_buffer = name.as_bytes().to_vec();
+            name_buffer.resize(NAME_SIZE, 0);
+            bytes.extend(name_buffer.iter());
+        }
+        bytes.extend(self.key_len.to_ne_bytes().iter());
+        bytes.extend(self.indexed_rows.to_ne_bytes().iter());
+        bytes.push(self.depth);
+
+        let (order, offsets) = self.layout();
+        for pointer in self.directory.iter() {
+            bytes.extend(offsets[pointer].to_ne_bytes().iter());
+        }
+        for bucket in order {
+            let bucket = &self.buckets[bucket];
+            let overflow = bucket.overflow.map_or(0, |overflow| offsets[&overflow]);
+            bytes.push(bucket.local_depth);
+            bytes.extend((bucket.entries.len() as u32).to_ne_bytes().iter());
+            bytes.extend(overflow.to_ne_bytes().iter());
+            for slot in 0..BUCKET_CAPACITY {
+                let mut entry = vec![0; self.key_len as usize + 8];
+                if let Some((key, row_index)) = bucket.entries.get(slot) {
+                    entry[..key.len()].copy_from_slice(key);
+                    entry[key.len()..].copy_from_slice(&row_index.to_ne_bytes());
+                }
+                bytes.extend(entry);
+            }
+        }
+        bytes
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), DurabilityError> {
+        std::fs::write(path, self.bytes()).map_err(DurabilityError::IoError)
+    }
+
+    pub fn read(path: &str) -> Result<HashIndex, DurabilityError> {
+        let file = std::fs::File::open(path).map_err(DurabilityError::IoError)?;
+        let length = file.metadata().map_err(DurabilityError::IoError)?.len();
+        let mut data = vec![0; length as usize];
+        file.read_exact_at(&mut data, 0)
+            .map_err(DurabilityError::IoError)?;
+        HashIndex::from_bytes(&data)
+            .ok_or_else(|| DurabilityError::DbError(format!("Invalid index file {}", path)))
+    }
+
+    /// Reads the header of an index file without its buckets, enough to
+    /// rebuild an index whose buckets are unreadable.
+    pub fn read_header(path: &str) -> Result<HashIndex, DurabilityError> {
+        let data = std::fs::read(path).map_err(DurabilityError::IoError)?;
+        let invalid = || DurabilityError::DbError(format!("Invalid index file {}", path));
+        let header = data.get(..HEADER_SIZE).ok_or_else(invalid)?;
+        let name = |bytes: &[u8]| {
+            let name = bytes.split(|b| *b == 0).next().unwrap_or_default();
+            String::from_utf8_lossy(name).to_string()
+        };
+        let key_len =
+            u64::from_ne_bytes(header[NAME_SIZE * 2..NAME_SIZE * 2 + 8].try_into().unwrap());
+        Ok(HashIndex::new(
+            &name(&header[..NAME_SIZE]),
+            &name(&header[NAME_SIZE..NAME_SIZE * 2]),
+            key_len,
+        ))
+    }
+
+    /// The number of rows in the index.
+    pub fn entry_count(&self) -> u64 {
+        let (buckets, _) = self.layout();
+        buckets
+            .into_iter()
+            .map(|bucket| self.buckets[bucket].entries.len() as u64)
+            .sum()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<HashIndex> {
+        let u64_at = |offset: usize| -> Option<u64> {
+            Some(u64::from_ne_bytes(
+                bytes.get(offset..offset + 8)?.try_into().ok()?,
+            ))
+        };
+        let name = |bytes: &[u8]| {
+            let name = bytes.split(|b| *b == 0).next().unwrap_or_default();
+            String::from_utf8_lossy(name).to_string()
+        };
+
+        let header = bytes.get(..HEADER_SIZE)?;
+        let mut index = HashIndex {
+            name: name(&header[..NAME_SIZE]),
+            column: name(&header[NAME_SIZE..NAME_SIZE * 2]),
+            key_len: u64_at(NAME_SIZE * 2)?,
+            indexed_rows: u64_at(NAME_SIZE * 2 + 8)?,
+            depth: header[HEADER_SIZE - 1],
+            directory: vec![],
+            buckets: vec![],
+        };
+        if index.depth > MAX_DEPTH {
+            return None;
+        }
+
+        let key_len = index.key_len as usize;
+        let mut ids: HashMap<u64, usize> = HashMap::new();
+        for slot in 0..1usize << index.depth {
+            let mut offset = u64_at(HEADER_SIZE + slot * 8)?;
+            index.directory.push(match ids.get(&offset) {
+                Some(id) => *id,
+                None => ids.len(),
+            });
+            // Read the chain unless an earlier pointer already did.
+            let mut previous: Option<usize> = None;
+            while !ids.contains_key(&offset) {
+                let id = ids.len();
+                ids.insert(offset, id);
+                let start = offset as usize;
+                let header = bytes.get(start..start + BUCKET_HEADER_SIZE)?;
+                let count = u32::from_ne_bytes(header[1..5].try_into().ok()?) as usize;
+                if count > BUCKET_CAPACITY {
+                    return None;
+                }
+                let mut entries = vec![];
+                for slot in 0..count {
+                    let entry_start = start + BUCKET_HEADER_SIZE + slot * (key_len + 8);
+                    let key = bytes.get(entry_start..entry_start + key_len)?.to_vec();
+                    entries.push((key, u64_at(entry_start + key_len)?));
+                }
+                index.buckets.push(Bucket {
+                    local_depth: header[0],
+                    entries,
+                    overflow: None,
+                });
+                if let Some(previous) = previous {
+                    index.buckets[previous].overflow = Some(id);
+                }
+                previous = Some(id);
+                offset = u64::from_ne_bytes(header[5..].try_into().ok()?);
+                if offset == 0 {
+                    break;
+                }
+            }
+        }
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::table::{create_table, writeable_table_file, Row, TableBuilder};
+    use crate::durability::Durable;
+
+    fn key(value: u64) -> Vec<u8> {
+        let mut key = value.to_string().into_bytes();
+        key.resize(8, 0);
+        key
+    }
+
+    #[test]
+    fn test_insert_and_lookup() {
+        let mut index = HashIndex::new("idx_id", "id", 8);
+        for row_index in 0..1000 {
+            index.insert(&key(row_index), row_index);
+        }
+        assert!(index.depth > 0);
+        for row_index in 0..1000 {
+            assert_eq!(index.lookup(&key(row_index)), [row_index]);
+        }
+        assert!(index.lookup(&key(1000)).is_empty());
+
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("users.id.hash");
+        let path = path.to_str().unwrap();
+        index.write(path).unwrap();
+        let read = HashIndex::read(path).unwrap();
+        assert_eq!(read.depth, index.depth);
+        for row_index in 0..1000 {
+            assert_eq!(read.lookup(&key(row_index)), [row_index]);
+        }
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut index = HashIndex::new("idx_id", "id", 8);
+        for row_index in 0..20 {
+            index.insert(&key(row_index % 10), row_index);
+        }
+        assert!(index.delete(&key(3), 13));
+        assert!(!index.delete(&key(3), 13));
+        assert!(!index.delete(&key(4), 13));
+        assert_eq!(index.lookup(&key(3)), [3]);
+        assert_eq!(index.lookup(&key(4)), [4, 14]);
+    }
+
+    #[test]
+    fn test_overflow_bucket_chaining() {
+        let mut index = HashIndex::new("idx_city", "city", 8);
+        let oslo = b"Oslo\0\0\0\0";
+        for row_index in 0..BUCKET_CAPACITY as u64 * 3 {
+            index.insert(oslo, row_index);
+        }
+        // Splitting can not separate equal keys, the bucket is chained.
+        assert_eq!(index.depth, 0);
+        assert_eq!(index.chain(index.directory[0]).len(), 3);
+
+        // A different key splits the chain.
+        index.insert(b"Bergen\0\0", 100);
+        let rows: Vec<u64> = (0..BUCKET_CAPACITY as u64 * 3).collect();
+        assert_eq!(index.lookup(oslo), rows);
+        assert_eq!(index.lookup(b"Bergen\0\0"), [100]);
+
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("users.city.hash");
+        let path = path.to_str().unwrap();
+        index.write(path).unwrap();
+        assert_eq!(HashIndex::read(path).unwrap().lookup(oslo), rows);
+    }
+
+    #[test]
+    fn test_build_hash_index() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 4)
+                .column("city", ColumnType::Varchar, 8),
+        )
+        .unwrap();
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        for (id, city) in [("1", "Oslo"), ("02", "Bergen"), ("3", "Oslo")] {
+            let row = Row {
+                data: vec![id.as_bytes().to_vec(), city.as_bytes().to_vec()],
+            };
+            table.add_row(&row, &mut file).unwrap();
+        }
+
+        let index = HashIndex::build("idx_id", &table, &file, "id").unwrap();
+        assert_eq!(index.indexed_rows, 3);
+        assert_eq!(index.lookup(&index.key(b"2", &ColumnType::Int)), [1]);
+        let index = HashIndex::build("idx_city", &table, &file, "city").unwrap();
+        assert_eq!(
+            index.lookup(&index.key(b"Oslo", &ColumnType::Varchar)),
+            [0, 2]
+        );
+        assert!(HashIndex::build("idx_missing", &table, &file, "missing").is_err());
+    }
+}
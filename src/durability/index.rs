@@ -0,0 +1,858 @@
+use std::{collections::BTreeMap, os::unix::fs::FileExt, path::Path};
+
+use super::{
+    hash_index::{hash_index_file, HashIndex},
+    table::{ColumnType, Row, Table},
+    DurabilityError,
+};
+use crate::query::predicate::Predicate;
+
+const NAME_SIZE: usize = 64;
+/// The predicate of a partial index, null padded like a `CHECK` expression.
+const PREDICATE_SIZE: usize = 128;
+
+/// The structure of an index, `CREATE HASH INDEX` builds a `HashIndex` and
+/// `CREATE INDEX` a `BTreeIndex`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndexKind {
+    BTree,
+    Hash,
+}
+
+impl IndexKind {
+    pub fn file(&self, table: &str, column: &str) -> String {
+        match self {
+            IndexKind::BTree => index_file(table, column),
+            IndexKind::Hash => hash_index_file(table, column),
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            IndexKind::BTree => ".idx",
+            IndexKind::Hash => ".hash",
+        }
+    }
+}
+
+/// The file of the index over `column`, the columns of a composite index are
+/// joined by commas.
+pub fn index_file(table: &str, column: &str) -> String {
+    format!("{}.{}.idx", table, column)
+}
+
+/// The index files of a table of either kind, in name order.
+pub fn all_table_indexes(table: &str) -> Result<Vec<String>, DurabilityError> {
+    let mut indexes = table_indexes(table, IndexKind::BTree)?;
+    indexes.extend(table_indexes(table, IndexKind::Hash)?);
+    Ok(indexes)
+}
+
+/// The index files of a table of one kind, in name order.
+pub fn table_indexes(table: &str, kind: IndexKind) -> Result<Vec<String>, DurabilityError> {
+    let path = Path::new(table);
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!(
+        "{}.",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+
+    let mut indexes = vec![];
+    for entry in std::fs::read_dir(directory).map_err(DurabilityError::IoError)? {
+        let file_name = entry.map_err(DurabilityError::IoError)?.file_name();
+        let file_name = file_name.to_string_lossy();
+        let column = file_name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(kind.extension()));
+        if let Some(column) = column.filter(|c| !c.is_empty() && !c.contains('.')) {
+            indexes.push(kind.file(table, column));
+        }
+    }
+    indexes.sort();
+    Ok(indexes)
+}
+
+/// The tables in `directory` with an index of either kind, found from the
+/// index files kept next to them, in name order. Index files whose table is
+/// gone are left out.
+pub fn indexed_tables(directory: &str) -> Result<Vec<String>, DurabilityError> {
+    let mut tables = vec![];
+    for entry in std::fs::read_dir(directory).map_err(DurabilityError::IoError)? {
+        let file_name = entry.map_err(DurabilityError::IoError)?.file_name();
+        let file_name = file_name.to_string_lossy();
+        let table = [IndexKind::BTree, IndexKind::Hash]
+            .iter()
+            .find_map(|kind| file_name.strip_suffix(kind.extension()))
+            .and_then(|rest| rest.rsplit_once('.'))
+            .map(|(table, _)| match directory {
+                "." => table.to_string(),
+                _ => format!("{}/{}", directory.trim_end_matches('/'), table),
+            });
+        if let Some(table) = table.filter(|table| Path::new(table).is_file()) {
+            tables.push(table);
+        }
+    }
+    tables.sort();
+    tables.dedup();
+    Ok(tables)
+}
+
+/// A column of an index with the length its values are padded to in keys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexColumn {
+    pub name: String,
+    pub length: u64,
+}
+
+/// A sorted index over one or more columns of a table, kept in
+/// `{table}.{columns}.idx` and loaded into memory whole. Maps each key to the
+/// indexes of the rows holding it. Rows appended after the index was built
+/// are not in it, a scan using the index also has to read the rows from
+/// `indexed_rows` onwards.
+///
+/// A key is the `index_key` of every column in order, each but the last
+/// padded to the column length, so the rows matching the leading columns
+/// are a contiguous range of keys.
+///
+/// A partial index only holds the rows matching its `predicate`.
+#[derive(Debug, PartialEq)]
+pub struct BTreeIndex {
+    pub name: String,
+    pub columns: Vec<IndexColumn>,
+    pub indexed_rows: u64,
+    pub predicate: Option<String>,
+    entries: BTreeMap<Vec<u8>, Vec<u64>>,
+}
+
+impl BTreeIndex {
+    /// Scans the table to index `columns` of the rows matching `predicate`,
+    /// every row when there is none.
+    pub fn build(
+        name: &str,
+        table: &Table,
+        file: &std::fs::File,
+        columns: &[&str],
+        predicate: Option<&str>,
+    ) -> Result<BTreeIndex, DurabilityError> {
+        if name.is_empty() || name.len() >= NAME_SIZE {
+            return Err(DurabilityError::DbError(format!(
+                "Invalid index name {}, must be between 1 and 63 bytes",
+                name
+            )));
+        }
+        if let Some(predicate) = predicate {
+            if predicate.len() >= PREDICATE_SIZE || Predicate::parse(predicate).is_none() {
+                return Err(DurabilityError::DbError(format!(
+                    "Invalid index predicate {}, must be a comparison of at most 127 bytes",
+                    predicate
+                )));
+            }
+        }
+        if columns.is_empty() {
+            return Err(DurabilityError::DbError(
+                "An index needs at least one column".to_string(),
+            ));
+        }
+        let mut positions = vec![];
+        for column in columns {
+            let position = table
+                .columns
+                .iter()
+                .position(|c| c.name_str() == *column)
+                .ok_or_else(|| {
+                    DurabilityError::DbError(format!("Column {} does not exist", column))
+                })?;
+            if positions.contains(&position) {
+                return Err(DurabilityError::DbError(format!(
+                    "Column {} is indexed twice",
+                    column
+                )));
+            }
+            positions.push(position);
+        }
+
+        let mut index = BTreeIndex {
+            name: name.to_string(),
+            columns: columns
+                .iter()
+                .zip(positions)
+                .map(|(column, position)| IndexColumn {
+                    name: column.to_string(),
+                    length: table.columns[position].length,
+                })
+                .collect(),
+            indexed_rows: 0,
+            predicate: predicate.map(str::to_string),
+            entries: BTreeMap::new(),
+        };
+        for page_number in 0..table.page_count() {
+            let page = table.page_at(file, page_number)?;
+            for (row_index, row) in table.page_entries(&page) {
+                if index.covers(table, &row) {
+                    let key = index.row_key(table, &row);
+                    index.entries.entry(key).or_default().push(row_index);
+                }
+            }
+        }
+        index.indexed_rows = table.row_count;
+        Ok(index)
+    }
+
+    /// The key of `values`, already in `index_key` form, for the leading
+    /// columns of the index.
+    pub fn key(&self, values: &[Vec<u8>]) -> Vec<u8> {
+        let mut key = vec![];
+        for (i, (value, column)) in values.iter().zip(self.columns.iter()).enumerate() {
+            let start = key.len();
+            key.extend(value.iter());
+            if i + 1 < self.columns.len() {
+                key.resize(start + column.length as usize, 0);
+            }
+        }
+        key
+    }
+
+    /// Whether `row` belongs in the index, a row the predicate can not be
+    /// evaluated on does not.
+    pub fn covers(&self, table: &Table, row: &Row) -> bool {
+        match &self.predicate {
+            Some(predicate) => Predicate::parse(predicate)
+                .is_some_and(|predicate| predicate.matches(row, &table.columns) == Ok(true)),
+            None => true,
+        }
+    }
+
+    /// The key `row` of `table` is indexed under.
+    pub fn row_key(&self, table: &Table, row: &Row) -> Vec<u8> {
+        let values: Vec<Vec<u8>> = self
+            .columns
+            .iter()
+            .map(|column| {
+                let position = table
+                    .columns
+                    .iter()
+                    .position(|c| c.name_str() == column.name)
+                    .unwrap();
+                index_key(&row.data[position], &table.columns[position].column_type)
+            })
+            .collect();
+        self.key(&values)
+    }
+
+    /// The rows holding `key`, as produced by `key`.
+    pub fn lookup(&self, key: &[u8]) -> &[u64] {
+        self.entries.get(key).map_or(&[], |rows| rows.as_slice())
+    }
+
+    /// The rows of every key starting with `prefix`, the key of fewer than
+    /// all of the columns, in row order.
+    pub fn lookup_prefix(&self, prefix: &[u8]) -> Vec<u64> {
+        let mut rows: Vec<u64> = self
+            .entries
+            .range(prefix.to_vec()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .flat_map(|(_, rows)| rows.iter().copied())
+            .collect();
+        rows.sort_unstable();
+        rows
+    }
+
+    /// Moves a row from the entry of `old_key` to the entry of `new_key`.
+    pub fn move_row(&mut self, row_index: u64, old_key: &[u8], new_key: Vec<u8>) {
+        self.remove_row(row_index, old_key);
+        self.insert_row(row_index, new_key);
+    }
+
+    pub fn insert_row(&mut self, row_index: u64, key: Vec<u8>) {
+        let rows = self.entries.entry(key).or_default();
+        if let Err(position) = rows.binary_search(&row_index) {
+            rows.insert(position, row_index);
+        }
+    }
+
+    pub fn remove_row(&mut self, row_index: u64, key: &[u8]) {
+        if let Some(rows) = self.entries.get_mut(key) {
+            rows.retain(|row| *row != row_index);
+            if rows.is_empty() {
+                self.entries.remove(key);
+            }
+        }
+    }
+
+    /// The name, the number of columns, every column name with its length,
+    /// `indexed_rows` and the predicate, followed by the entries.
+    pub fn bytes(&self) -> Vec<u8> {
+        let name_bytes = |name: &str| {
+            let mut name_buffer = name.as_bytes().to_vec();
+            name_buffer.resize(NAME_SIZE, 0);
+            name_buffer
+        };
+        let mut bytes = name_bytes(&self.name);
+        bytes.extend((self.columns.len() as u32).to_ne_bytes().iter());
+        for column in self.columns.iter() {
+            bytes.extend(name_bytes(&column.name));
+            bytes.extend(column.length.to_ne_bytes().iter());
+        }
+        bytes.extend(self.indexed_rows.to_ne_bytes().iter());
+        let mut predicate = self.predicate.clone().unwrap_or_default().into_bytes();
+        predicate.resize(PREDICATE_SIZE, 0);
+        bytes.extend(predicate);
+        for (key, rows) in self.entries.iter() {
+            bytes.extend((key.len() as u32).to_ne_bytes().iter());
+            bytes.extend(key.iter());
+            bytes.extend((rows.len() as u32).to_ne_bytes().iter());
+            for row in rows {
+                bytes.extend(row.to_ne_bytes().iter());
+            }
+        }
+        bytes
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), DurabilityError> {
+        std::fs::write(path, self.bytes()).map_err(DurabilityError::IoError)
+    }
+
+    pub fn read(path: &str) -> Result<BTreeIndex, DurabilityError> {
+        let file = std::fs::File::open(path).map_err(DurabilityError::IoError)?;
+        let length = file.metadata().map_err(DurabilityError::IoError)?.len();
+        let mut data = vec![0; length as usize];
+        file.read_exact_at(&mut data, 0)
+            .map_err(DurabilityError::IoError)?;
+        BTreeIndex::from_bytes(&data)
+            .ok_or_else(|| DurabilityError::DbError(format!("Invalid index file {}", path)))
+    }
+
+    /// Reads the header of an index file without its entries, enough to
+    /// rebuild an index whose entries are unreadable.
+    pub fn read_header(path: &str) -> Result<BTreeIndex, DurabilityError> {
+        let data = std::fs::read(path).map_err(DurabilityError::IoError)?;
+        BTreeIndex::header_from_bytes(&mut data.as_slice())
+            .ok_or_else(|| DurabilityError::DbError(format!("Invalid index file {}", path)))
+    }
+
+    /// Parses the header at the start of `rest` into an index without
+    /// entries, leaving `rest` at the first entry.
+    fn header_from_bytes(rest: &mut &[u8]) -> Option<BTreeIndex> {
+        let name = take_name(rest)?;
+        let column_count = take_u32(rest)?;
+        let mut columns = vec![];
+        for _ in 0..column_count {
+            columns.push(IndexColumn {
+                name: take_name(rest)?,
+                length: u64::from_ne_bytes(take(rest, 8)?.try_into().ok()?),
+            });
+        }
+        let indexed_rows = u64::from_ne_bytes(take(rest, 8)?.try_into().ok()?);
+        let predicate = take(rest, PREDICATE_SIZE)?;
+        let predicate = predicate.split(|b| *b == 0).next().unwrap_or_default();
+        let predicate =
+            (!predicate.is_empty()).then(|| String::from_utf8_lossy(predicate).to_string());
+
+        Some(BTreeIndex {
+            name,
+            columns,
+            indexed_rows,
+            predicate,
+            entries: BTreeMap::new(),
+        })
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<BTreeIndex> {
+        let mut rest = bytes;
+        let mut index = BTreeIndex::header_from_bytes(&mut rest)?;
+        while !rest.is_empty() {
+            let key_len = take_u32(&mut rest)?;
+            let key = take(&mut rest, key_len as usize)?;
+            let row_count = take_u32(&mut rest)?;
+            let rows = take(&mut rest, row_count as usize * 8)?
+                .chunks_exact(8)
+                .map(|row| u64::from_ne_bytes(row.try_into().unwrap()))
+                .collect();
+            index.entries.insert(key, rows);
+        }
+        Some(index)
+    }
+
+    /// The number of rows in the index.
+    pub fn entry_count(&self) -> u64 {
+        self.entries.values().map(|rows| rows.len() as u64).sum()
+    }
+
+    /// The names of the indexed columns, in key order.
+    pub fn column_names(&self) -> Vec<&str> {
+        self.columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect()
+    }
+}
+
+fn take(rest: &mut &[u8], length: usize) -> Option<Vec<u8>> {
+    let taken = rest.get(..length)?.to_vec();
+    *rest = &rest[length..];
+    Some(taken)
+}
+
+fn take_u32(rest: &mut &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(take(rest, 4)?.try_into().ok()?))
+}
+
+fn take_name(rest: &mut &[u8]) -> Option<String> {
+    let name = take(rest, NAME_SIZE)?;
+    let name = name.split(|b| *b == 0).next().unwrap_or_default();
+    Some(String::from_utf8_lossy(name).to_string())
+}
+
+/// The file and kind of the index called `name` on the table. Files whose
+/// header can not be read are skipped.
+pub fn find_index(table: &str, name: &str) -> Result<Option<(String, IndexKind)>, DurabilityError> {
+    for kind in [IndexKind::BTree, IndexKind::Hash] {
+        for path in table_indexes(table, kind)? {
+            let index_name = match kind {
+                IndexKind::BTree => BTreeIndex::read_header(&path).map(|index| index.name),
+                IndexKind::Hash => HashIndex::read_header(&path).map(|index| index.name),
+            };
+            if index_name.is_ok_and(|index_name| index_name == name) {
+                return Ok(Some((path, kind)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// An index of a table as `EXPLAIN INDEXES` shows it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexDescription {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub kind: IndexKind,
+    pub file_size: u64,
+    pub entry_count: u64,
+}
+
+/// The indexes of the table read from their files, B-tree ones first, each
+/// kind in file name order.
+pub fn describe_indexes(table: &str) -> Result<Vec<IndexDescription>, DurabilityError> {
+    let mut descriptions = vec![];
+    for kind in [IndexKind::BTree, IndexKind::Hash] {
+        for path in table_indexes(table, kind)? {
+            let (name, columns, entry_count) = match kind {
+                IndexKind::BTree => {
+                    let index = BTreeIndex::read(&path)?;
+                    let columns = index.column_names().iter().map(|c| c.to_string()).collect();
+                    (index.name.clone(), columns, index.entry_count())
+                }
+                IndexKind::Hash => {
+                    let index = HashIndex::read(&path)?;
+                    (
+                        index.name.clone(),
+                        vec![index.column.clone()],
+                        index.entry_count(),
+                    )
+                }
+            };
+            descriptions.push(IndexDescription {
+                name,
+                columns,
+                kind,
+                file_size: std::fs::metadata(&path)
+                    .map_err(DurabilityError::IoError)?
+                    .len(),
+                entry_count,
+            });
+        }
+    }
+    Ok(descriptions)
+}
+
+/// Replaces the index file at `path` with an index built from a scan of the
+/// table, with the name, columns and predicate in its header. Returns the
+/// name of the index and the number of rows it holds.
+pub fn rebuild_index(
+    table: &Table,
+    file: &std::fs::File,
+    path: &str,
+    kind: IndexKind,
+) -> Result<(String, u64), DurabilityError> {
+    match kind {
+        IndexKind::BTree => {
+            let header = BTreeIndex::read_header(path)?;
+            let index = BTreeIndex::build(
+                &header.name,
+                table,
+                file,
+                &header.column_names(),
+                header.predicate.as_deref(),
+            )?;
+            index.write(path)?;
+            Ok((index.name.clone(), index.entry_count()))
+        }
+        IndexKind::Hash => {
+            let header = HashIndex::read_header(path)?;
+            let index = HashIndex::build(&header.name, table, file, &header.column)?;
+            index.write(path)?;
+            Ok((index.name.clone(), index.entry_count()))
+        }
+    }
+}
+
+/// Rebuilds every index on the table, see `rebuild_index`.
+pub fn rebuild_indexes(
+    table: &Table,
+    file: &std::fs::File,
+) -> Result<Vec<(String, u64)>, DurabilityError> {
+    let name = table.name_str();
+    let mut rebuilt = vec![];
+    for kind in [IndexKind::BTree, IndexKind::Hash] {
+        for path in table_indexes(name, kind)? {
+            rebuilt.push(rebuild_index(table, file, &path, kind)?);
+        }
+    }
+    Ok(rebuilt)
+}
+
+/// Updates every index on the table after the row at `row_index` was
+/// overwritten from `old` to `new`. Indexes built before the row was appended
+/// do not cover it and are left alone.
+pub fn reindex_row(
+    table: &Table,
+    row_index: u64,
+    old: &Row,
+    new: &Row,
+) -> Result<(), DurabilityError> {
+    let name = table.name_str();
+    for path in table_indexes(name, IndexKind::BTree)? {
+        let mut index = BTreeIndex::read(&path)?;
+        if row_index >= index.indexed_rows {
+            continue;
+        }
+        let old_key = index.covers(table, old).then(|| index.row_key(table, old));
+        let new_key = index.covers(table, new).then(|| index.row_key(table, new));
+        if old_key == new_key {
+            continue;
+        }
+        // A partial index gains or loses the row when the predicate's answer
+        // changes.
+        match (old_key, new_key) {
+            (Some(old_key), Some(new_key)) => index.move_row(row_index, &old_key, new_key),
+            (Some(old_key), None) => index.remove_row(row_index, &old_key),
+            (None, Some(new_key)) => index.insert_row(row_index, new_key),
+            (None, None) => {}
+        }
+        index.write(&path)?;
+    }
+
+    for path in table_indexes(name, IndexKind::Hash)? {
+        let mut index = HashIndex::read(&path)?;
+        let position = table
+            .columns
+            .iter()
+            .position(|c| c.name_str() == index.column)
+            .ok_or_else(|| DurabilityError::DbError(format!("Invalid index file {}", path)))?;
+        let column_type = &table.columns[position].column_type;
+        let old_key = index.key(&old.data[position], column_type);
+        let new_key = index.key(&new.data[position], column_type);
+        if old_key != new_key && row_index < index.indexed_rows {
+            index.delete(&old_key, row_index);
+            index.insert(&new_key, row_index);
+            index.write(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Drops the deleted row at `row_index` from every index of the table that
+/// holds it.
+pub fn unindex_row(table: &Table, row_index: u64, row: &Row) -> Result<(), DurabilityError> {
+    let name = table.name_str();
+    for path in table_indexes(name, IndexKind::BTree)? {
+        let mut index = BTreeIndex::read(&path)?;
+        if row_index >= index.indexed_rows || !index.covers(table, row) {
+            continue;
+        }
+        index.remove_row(row_index, &index.row_key(table, row));
+        index.write(&path)?;
+    }
+
+    for path in table_indexes(name, IndexKind::Hash)? {
+        let mut index = HashIndex::read(&path)?;
+        let position = table
+            .columns
+            .iter()
+            .position(|c| c.name_str() == index.column)
+            .ok_or_else(|| DurabilityError::DbError(format!("Invalid index file {}", path)))?;
+        let key = index.key(&row.data[position], &table.columns[position].column_type);
+        if row_index < index.indexed_rows && index.delete(&key, row_index) {
+            index.write(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// The form a value is indexed under. Numbers are indexed by their parsed
+/// value so `7` and `007` land on the same key, anything else by its bytes
+/// without the zero padding. Binary values are indexed by their stored bytes,
+/// which may hold zeros.
+pub fn index_key(value: &[u8], column_type: &ColumnType) -> Vec<u8> {
+    if column_type.is_binary() {
+        return value.to_vec();
+    }
+    let value = value.split(|b| *b == 0).next().unwrap_or_default();
+    let text = String::from_utf8_lossy(value);
+    let normalized = match column_type {
+        ColumnType::Int => text.trim().parse::<i64>().ok().map(|v| v.to_string()),
+        ColumnType::Float => text.trim().parse::<f64>().ok().map(|v| v.to_string()),
+        ColumnType::Varchar
+        | ColumnType::Date
+        | ColumnType::Timestamp
+        | ColumnType::Decimal(..) => None,
+    };
+    normalized.map_or_else(|| value.to_vec(), String::into_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::table::{create_table, writeable_table_file, Row, TableBuilder};
+    use crate::durability::Durable;
+
+    #[test]
+    fn test_build_and_read_index() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 4)
+                .column("city", ColumnType::Varchar, 8),
+        )
+        .unwrap();
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        for (id, city) in [("1", "Oslo"), ("02", "Bergen"), ("3", "Oslo")] {
+            let row = Row {
+                data: vec![id.as_bytes().to_vec(), city.as_bytes().to_vec()],
+            };
+            table.add_row(&row, &mut file).unwrap();
+        }
+
+        let index = BTreeIndex::build("idx_city", &table, &file, &["city"], None).unwrap();
+        assert_eq!(index.indexed_rows, 3);
+        assert_eq!(index.lookup(b"Oslo"), [0, 2]);
+        assert_eq!(index.lookup(b"Bergen"), [1]);
+        assert!(index.lookup(b"Trondheim").is_empty());
+
+        let path = index_file(&name, "city");
+        index.write(&path).unwrap();
+        assert_eq!(BTreeIndex::read(&path).unwrap(), index);
+
+        let index = BTreeIndex::build("idx_id", &table, &file, &["id"], None).unwrap();
+        assert_eq!(index.lookup(&index_key(b"2", &ColumnType::Int)), [1]);
+        assert!(BTreeIndex::build("idx_missing", &table, &file, &["missing"], None).is_err());
+        assert!(BTreeIndex::build("idx_twice", &table, &file, &["id", "id"], None).is_err());
+        assert_eq!(table_indexes(&name, IndexKind::BTree).unwrap(), vec![path]);
+        assert!(table_indexes(&name, IndexKind::Hash).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_partial_index() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 4)
+                .column("active", ColumnType::Int, 1),
+        )
+        .unwrap();
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let row = |id: &str, active: &str| Row {
+            data: vec![id.as_bytes().to_vec(), active.as_bytes().to_vec()],
+        };
+        for (id, active) in [("1", "1"), ("2", "0"), ("3", "1"), ("4", "0")] {
+            table.add_row(&row(id, active), &mut file).unwrap();
+        }
+
+        let path = index_file(&name, "id");
+        let index =
+            BTreeIndex::build("idx_active", &table, &file, &["id"], Some("active = 1")).unwrap();
+        assert_eq!(index.indexed_rows, 4);
+        assert_eq!(index.lookup(b"1"), [0]);
+        assert_eq!(index.lookup(b"3"), [2]);
+        assert!(index.lookup(b"2").is_empty());
+        assert!(index.lookup(b"4").is_empty());
+        index.write(&path).unwrap();
+        assert_eq!(BTreeIndex::read(&path).unwrap(), index);
+
+        // Rows are added and removed as the predicate's answer changes.
+        reindex_row(&table, 1, &row("2", "0"), &row("2", "1")).unwrap();
+        reindex_row(&table, 2, &row("3", "1"), &row("3", "0")).unwrap();
+        let index = BTreeIndex::read(&path).unwrap();
+        assert_eq!(index.lookup(b"2"), [1]);
+        assert!(index.lookup(b"3").is_empty());
+
+        assert!(BTreeIndex::build("idx", &table, &file, &["id"], Some("active")).is_err());
+    }
+
+    #[test]
+    fn test_composite_index() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("last_name", ColumnType::Varchar, 8)
+                .column("first_name", ColumnType::Varchar, 8),
+        )
+        .unwrap();
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        for (last_name, first_name) in [
+            ("Smith", "John"),
+            ("Smithson", "John"),
+            ("Smith", "Jane"),
+            ("Jones", "John"),
+            ("Smith", "Johnny"),
+        ] {
+            let row = Row {
+                data: vec![
+                    last_name.as_bytes().to_vec(),
+                    first_name.as_bytes().to_vec(),
+                ],
+            };
+            table.add_row(&row, &mut file).unwrap();
+        }
+
+        let path = index_file(&name, "last_name,first_name");
+        BTreeIndex::build(
+            "idx_last_first",
+            &table,
+            &file,
+            &["last_name", "first_name"],
+            None,
+        )
+        .unwrap()
+        .write(&path)
+        .unwrap();
+        let index = BTreeIndex::read(&path).unwrap();
+        assert_eq!(index.column_names(), ["last_name", "first_name"]);
+        assert_eq!(index.columns[0].length, 8);
+
+        let key = index.key(&[b"Smith".to_vec(), b"John".to_vec()]);
+        assert_eq!(key, b"Smith\0\0\0John");
+        assert_eq!(index.lookup(&key), [0]);
+
+        let prefix = index.key(&[b"Smith".to_vec()]);
+        assert_eq!(index.lookup_prefix(&prefix), [0, 2, 4]);
+        assert!(index
+            .lookup_prefix(&index.key(&[b"Brown".to_vec()]))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_rebuild_index() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 4)
+                .column("city", ColumnType::Varchar, 8),
+        )
+        .unwrap();
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        for (id, city) in [("1", "Oslo"), ("2", "Bergen"), ("3", "Oslo")] {
+            let row = Row {
+                data: vec![id.as_bytes().to_vec(), city.as_bytes().to_vec()],
+            };
+            table.add_row(&row, &mut file).unwrap();
+        }
+
+        // Entries pointing at the wrong rows.
+        let city_path = index_file(&name, "city");
+        let mut index =
+            BTreeIndex::build("idx_city", &table, &file, &["city"], Some("id > 1")).unwrap();
+        index.move_row(2, b"Oslo", b"Bergen".to_vec());
+        index.write(&city_path).unwrap();
+        // Directory and buckets cut off after the header.
+        let id_path = hash_index_file(&name, "id");
+        let mut bytes = HashIndex::build("idx_id", &table, &file, "id")
+            .unwrap()
+            .bytes();
+        bytes.truncate(NAME_SIZE * 2 + 8 + 8 + 1);
+        std::fs::write(&id_path, bytes).unwrap();
+        assert!(HashIndex::read(&id_path).is_err());
+
+        assert_eq!(
+            find_index(&name, "idx_id").unwrap(),
+            Some((id_path.clone(), IndexKind::Hash))
+        );
+        assert_eq!(find_index(&name, "idx_missing").unwrap(), None);
+        assert_eq!(
+            rebuild_index(&table, &file, &city_path, IndexKind::BTree).unwrap(),
+            ("idx_city".to_string(), 2)
+        );
+        let index = BTreeIndex::read(&city_path).unwrap();
+        assert_eq!(index.predicate.as_deref(), Some("id > 1"));
+        assert_eq!(index.lookup(b"Oslo"), [2]);
+        assert_eq!(index.lookup(b"Bergen"), [1]);
+
+        assert_eq!(
+            rebuild_indexes(&table, &file).unwrap(),
+            [("idx_city".to_string(), 2), ("idx_id".to_string(), 3)]
+        );
+        let index = HashIndex::read(&id_path).unwrap();
+        assert_eq!(index.lookup(&index.key(b"3", &ColumnType::Int)), [2]);
+
+        let described = describe_indexes(&name).unwrap();
+        assert_eq!(
+            described
+                .iter()
+                .map(|index| (index.name.as_str(), index.columns.clone(), index.kind))
+                .collect::<Vec<_>>(),
+            [
+                ("idx_city", vec!["city".to_string()], IndexKind::BTree),
+                ("idx_id", vec!["id".to_string()], IndexKind::Hash),
+            ]
+        );
+        assert_eq!(described[0].entry_count, 2);
+        assert_eq!(described[1].entry_count, 3);
+        assert_eq!(
+            described[1].file_size,
+            std::fs::metadata(&id_path).unwrap().len()
+        );
+
+        // An index left behind by a dropped table is not found.
+        std::fs::write(index_file(&format!("{}_old", name), "id"), b"").unwrap();
+        let directory = tmp_dir.path().to_str().unwrap();
+        assert_eq!(indexed_tables(directory).unwrap(), [name]);
+    }
+
+    #[test]
+    fn test_move_row() {
+        let mut index = BTreeIndex {
+            name: "idx_city".to_string(),
+            columns: vec![IndexColumn {
+                name: "city".to_string(),
+                length: 8,
+            }],
+            indexed_rows: 3,
+            predicate: None,
+            entries: BTreeMap::from([
+                (b"Oslo".to_vec(), vec![0, 2]),
+                (b"Bergen".to_vec(), vec![1]),
+            ]),
+        };
+        index.move_row(1, b"Bergen", b"Oslo".to_vec());
+        assert_eq!(index.lookup(b"Oslo"), [0, 1, 2]);
+        assert!(index.lookup(b"Bergen").is_empty());
+        assert_eq!(index.entries.len(), 1);
+    }
+}
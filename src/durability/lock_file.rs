@@ -0,0 +1,232 @@
+use std::{
+    os::unix::{fs::FileExt, io::AsRawFd},
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::DurabilityError;
+
+const NAME_SIZE: usize = 64;
+const RECORD_SIZE: usize = 8 + NAME_SIZE + 8 + 1 + 8 + 8;
+
+/// The mode of a lock taken while rows are written.
+pub const LOCK_MODE_WRITE: u8 = 2;
+
+/// A row lock kept in the table's lock file for as long as the write it
+/// covers runs, so a lock left behind by a crash can be found and removed.
+/// Stored as the id, the table name padded with zeros, the offset of the
+/// first locked row, the mode, the holder and when it was acquired in
+/// microseconds since the epoch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockRecord {
+    pub lock_id: u64,
+    pub table_name: String,
+    pub row_offset: u64,
+    pub lock_mode: u8,
+    /// The process holding the lock, the connection to the files.
+    pub holder_connection_id: u64,
+    pub acquired_at: u64,
+}
+
+impl LockRecord {
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = self.lock_id.to_le_bytes().to_vec();
+        bytes.extend(self.table_name.as_bytes());
+        bytes.resize(8 + NAME_SIZE, 0);
+        bytes.extend(self.row_offset.to_le_bytes());
+        bytes.push(self.lock_mode);
+        bytes.extend(self.holder_connection_id.to_le_bytes());
+        bytes.extend(self.acquired_at.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        let name = bytes[8..8 + NAME_SIZE].split(|b| *b == 0).next();
+        LockRecord {
+            lock_id: u64_at(0),
+            table_name: String::from_utf8_lossy(name.unwrap_or_default()).to_string(),
+            row_offset: u64_at(8 + NAME_SIZE),
+            lock_mode: bytes[8 + NAME_SIZE + 8],
+            holder_connection_id: u64_at(8 + NAME_SIZE + 9),
+            acquired_at: u64_at(8 + NAME_SIZE + 17),
+        }
+    }
+
+    /// Whether the holder may still be writing: it is this process and the
+    /// lock was taken since it started, or it is another process that is
+    /// still running.
+    fn is_active(&self) -> bool {
+        if self.holder_connection_id == holder_id() {
+            return self.acquired_at >= process_started_at();
+        }
+        let Ok(pid @ 1..) = libc::pid_t::try_from(self.holder_connection_id) else {
+            return false;
+        };
+        let alive = unsafe { libc::kill(pid, 0) } == 0;
+        alive || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+pub fn lock_file(table: &str) -> String {
+    format!("{}.locks", table)
+}
+
+fn holder_id() -> u64 {
+    std::process::id() as u64
+}
+
+fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_micros() as u64)
+}
+
+/// When this process first used a lock file. A lock of a crashed process
+/// that had the same pid was acquired before.
+fn process_started_at() -> u64 {
+    static STARTED_AT: OnceLock<u64> = OnceLock::new();
+    *STARTED_AT.get_or_init(now_us)
+}
+
+/// The lock file opened and held with `flock` until it is dropped, so the
+/// read, change and rewrite of the records by other processes can not
+/// interleave.
+struct LockedFile(std::fs::File);
+
+impl LockedFile {
+    fn open(path: &str) -> Result<LockedFile, DurabilityError> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(DurabilityError::IoError)?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(DurabilityError::IoError(std::io::Error::last_os_error()));
+        }
+        Ok(LockedFile(file))
+    }
+
+    fn read(&self) -> Result<Vec<LockRecord>, DurabilityError> {
+        let length = self.0.metadata().map_err(DurabilityError::IoError)?.len();
+        let mut data = vec![0; length as usize];
+        self.0
+            .read_exact_at(&mut data, 0)
+            .map_err(DurabilityError::IoError)?;
+        Ok(data
+            .chunks_exact(RECORD_SIZE)
+            .map(LockRecord::from_bytes)
+            .collect())
+    }
+
+    fn write(&self, records: &[LockRecord]) -> Result<(), DurabilityError> {
+        let bytes: Vec<u8> = records.iter().flat_map(|record| record.bytes()).collect();
+        self.0.set_len(0).map_err(DurabilityError::IoError)?;
+        self.0
+            .write_all_at(&bytes, 0)
+            .map_err(DurabilityError::IoError)
+    }
+}
+
+impl Drop for LockedFile {
+    fn drop(&mut self) {
+        unsafe { libc::flock(self.0.as_raw_fd(), libc::LOCK_UN) };
+    }
+}
+
+/// Records a lock on the rows of `table_name` from `row_offset`, the id to
+/// release it with.
+pub fn acquire_lock(
+    path: &str,
+    table_name: &str,
+    row_offset: u64,
+    lock_mode: u8,
+) -> Result<u64, DurabilityError> {
+    let file = LockedFile::open(path)?;
+    let mut records = file.read()?;
+    let lock_id = records
+        .iter()
+        .map(|record| record.lock_id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    // Taken before the record so it is never counted as a crashed process.
+    process_started_at();
+    records.push(LockRecord {
+        lock_id,
+        table_name: table_name.to_string(),
+        row_offset,
+        lock_mode,
+        holder_connection_id: holder_id(),
+        acquired_at: now_us(),
+    });
+    file.write(&records)?;
+    Ok(lock_id)
+}
+
+pub fn release_lock(path: &str, lock_id: u64) -> Result<(), DurabilityError> {
+    let file = LockedFile::open(path)?;
+    let mut records = file.read()?;
+    records.retain(|record| record.lock_id != lock_id);
+    file.write(&records)
+}
+
+/// Removes the locks whose holder is gone, the number removed. A missing lock
+/// file holds no locks.
+pub fn remove_stale_locks(path: &str) -> Result<usize, DurabilityError> {
+    if !std::path::Path::new(path).exists() {
+        return Ok(0);
+    }
+    let file = LockedFile::open(path)?;
+    let mut records = file.read()?;
+    let count = records.len();
+    records.retain(LockRecord::is_active);
+    if records.len() == count {
+        return Ok(0);
+    }
+    file.write(&records)?;
+    Ok(count - records.len())
+}
+
+/// Every lock in the file, in the order they were acquired.
+#[cfg(test)]
+pub fn read_locks(path: &str) -> Result<Vec<LockRecord>, DurabilityError> {
+    if !std::path::Path::new(path).exists() {
+        return Ok(vec![]);
+    }
+    LockedFile::open(path)?.read()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_acquire_and_release_lock() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("users.locks");
+        let path = path.to_str().unwrap();
+
+        assert!(read_locks(path).unwrap().is_empty());
+        let first = acquire_lock(path, "users", 1024, LOCK_MODE_WRITE).unwrap();
+        let second = acquire_lock(path, "users", 2048, LOCK_MODE_WRITE).unwrap();
+        assert_eq!((first, second), (1, 2));
+        let locks = read_locks(path).unwrap();
+        assert_eq!(locks.len(), 2);
+        assert_eq!(locks[1].table_name, "users");
+        assert_eq!(locks[1].row_offset, 2048);
+        assert_eq!(locks[1].holder_connection_id, holder_id());
+
+        // Locks of this process are not stale.
+        assert_eq!(remove_stale_locks(path).unwrap(), 0);
+        release_lock(path, first).unwrap();
+        assert_eq!(read_locks(path).unwrap()[0].lock_id, second);
+        release_lock(path, second).unwrap();
+        assert!(read_locks(path).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+use crate::query::MergeValue;
+
+use super::{
+    table::{ColumnDefinition, Row, Table},
+    DurabilityError,
+};
+
+/// What a `MERGE` wrote to its target.
+#[derive(Debug, PartialEq)]
+pub struct Merged {
+    pub updated: u64,
+    pub inserted: Vec<Row>,
+}
+
+fn column_position(columns: &[ColumnDefinition], name: &str, table: &str) -> Result<usize, String> {
+    columns
+        .iter()
+        .position(|column| column.name_str() == name)
+        .ok_or_else(|| format!("Column {} does not exist in table {}", name, table))
+}
+
+/// The value of every column of `row` as text, the form `row_for_columns`
+/// takes.
+fn row_values(row: &Row, columns: &[ColumnDefinition]) -> Vec<Vec<u8>> {
+    row.data
+        .iter()
+        .zip(columns.iter())
+        .map(|(value, column)| column.column_type.format(value).into_bytes())
+        .collect()
+}
+
+/// Merges `source_rows`, read from `source`, into `target`. The source rows are joined to the target rows as they were
+/// before the merge with a nested loop over both, comparing the values of the
+/// `join_condition` columns as text; null values match nothing. Every target
+/// row a source row matches is overwritten in place with the `matched_action`
+/// assignments, and the source rows matching no target row are built from the
+/// `not_matched_action` and appended together. Either action may be missing.
+pub fn merge_rows(
+    target: &mut Table,
+    file: &mut std::fs::File,
+    source: &Table,
+    source_rows: &[Row],
+    join_condition: &(String, String),
+    matched_action: Option<&[(String, MergeValue)]>,
+    not_matched_action: Option<&(Vec<String>, Vec<MergeValue>)>,
+) -> Result<Merged, DurabilityError> {
+    let target_name = target.name_str().to_string();
+    let target_columns = target.columns.clone();
+    let source_columns = &source.columns;
+    let target_key = column_position(&target_columns, &join_condition.0, &target_name)?;
+    let source_key = column_position(source_columns, &join_condition.1, source.name_str())?;
+    let value_of = |value: &MergeValue, row: &Row| -> Result<Vec<u8>, String> {
+        match value {
+            MergeValue::Source(name) => {
+                let position = column_position(source_columns, name, source.name_str())?;
+                let column = &source_columns[position];
+                Ok(column.column_type.format(&row.data[position]).into_bytes())
+            }
+            MergeValue::Literal(literal) => Ok(literal.clone()),
+        }
+    };
+    if let Some(assignments) = matched_action {
+        for (name, _) in assignments {
+            column_position(&target_columns, name, &target_name)?;
+        }
+    }
+
+    let mut target_rows = vec![];
+    for page_number in 0..target.page_count() {
+        let page = target.page_at(file, page_number)?;
+        for (row_index, row) in target.page_entries(&page) {
+            let key = target_columns[target_key]
+                .column_type
+                .format(&row.data[target_key]);
+            target_rows.push((row_index, key, row));
+        }
+    }
+
+    let names: Vec<String> = target_columns
+        .iter()
+        .map(|column| column.name_str().to_string())
+        .collect();
+    let mut updated = 0;
+    let mut inserted = vec![];
+    for source_row in source_rows {
+        let key = source_columns[source_key]
+            .column_type
+            .format(&source_row.data[source_key]);
+        let mut matched = false;
+        for (row_index, target_key, target_row) in target_rows.iter() {
+            if key.is_empty() || *target_key != key {
+                continue;
+            }
+            matched = true;
+            let Some(assignments) = matched_action else {
+                continue;
+            };
+            let mut values = row_values(target_row, &target_columns);
+            for (name, value) in assignments {
+                let position = names.iter().position(|column| column == name).unwrap();
+                values[position] = value_of(value, source_row)?;
+            }
+            let row = target.row_for_columns(&names, &values)?;
+            target.replace_row(file, *row_index, &row)?;
+            updated += 1;
+        }
+        if let (false, Some((columns, values))) = (matched, not_matched_action) {
+            let values = values
+                .iter()
+                .map(|value| value_of(value, source_row))
+                .collect::<Result<Vec<Vec<u8>>, String>>()?;
+            inserted.push(target.row_for_columns(columns, &values)?);
+        }
+    }
+    target.add_rows(&inserted, file)?;
+    Ok(Merged { updated, inserted })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::{
+        table::{create_table, writeable_table_file, ColumnType, TableBuilder},
+        Durable,
+    };
+
+    fn create_accounts(dir: &std::path::Path, name: &str, rows: &[(&str, &str)]) -> Table {
+        let name = dir.join(name).to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 8)
+                .column("name", ColumnType::Varchar, 8),
+        )
+        .unwrap();
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let rows: Vec<Row> = rows
+            .iter()
+            .map(|(id, name)| Row {
+                data: vec![id.as_bytes().to_vec(), name.as_bytes().to_vec()],
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+        table
+    }
+
+    fn rows(table: &Table) -> Vec<Vec<String>> {
+        let file = writeable_table_file(table.name_str().to_string()).unwrap();
+        (0..table.page_count())
+            .flat_map(|page| table.page_rows(&table.page_at(&file, page).unwrap()))
+            .map(|row| {
+                row_values(&row, &table.columns)
+                    .into_iter()
+                    .map(|value| String::from_utf8(value).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_rows() {
+        let tmp_dir = tempdir().unwrap();
+        let mut target = create_accounts(tmp_dir.path(), "target", &[("1", "Ann"), ("2", "")]);
+        let source = create_accounts(
+            tmp_dir.path(),
+            "source",
+            &[("1", "Bo"), ("3", "Cy"), ("", "Di")],
+        );
+        let source_file = writeable_table_file(source.name_str().to_string()).unwrap();
+        let source_rows: Vec<Row> = source.page_rows(&source.page_at(&source_file, 0).unwrap());
+        let mut file = writeable_table_file(target.name_str().to_string()).unwrap();
+
+        let join_condition = ("id".to_string(), "id".to_string());
+        let assignments = vec![("name".to_string(), MergeValue::Source("name".to_string()))];
+        let insert = (
+            vec!["id".to_string(), "name".to_string()],
+            vec![
+                MergeValue::Source("id".to_string()),
+                MergeValue::Literal(b"new".to_vec()),
+            ],
+        );
+        let merged = merge_rows(
+            &mut target,
+            &mut file,
+            &source,
+            &source_rows,
+            &join_condition,
+            Some(&assignments),
+            Some(&insert),
+        )
+        .unwrap();
+        assert_eq!(merged.updated, 1);
+        // The source row with a null id matches nothing and is inserted.
+        assert_eq!(merged.inserted.len(), 2);
+        assert_eq!(
+            rows(&target),
+            vec![
+                vec!["1", "Bo"],
+                vec!["2", ""],
+                vec!["3", "new"],
+                vec!["", "new"]
+            ]
+        );
+
+        let missing = ("id".to_string(), "missing".to_string());
+        assert!(merge_rows(
+            &mut target,
+            &mut file,
+            &source,
+            &source_rows,
+            &missing,
+            Some(&assignments),
+            None,
+        )
+        .is_err());
+    }
+}
@@ -1,7 +1,21 @@
 use database::{DatabaseFile, DatabaseFileHeader};
 
+pub mod backup;
+pub mod copy;
 pub mod database;
+pub mod grant;
+pub mod hash_index;
+pub mod index;
+pub mod lock_file;
+pub mod merge;
+pub mod procedure;
+pub mod sequence;
+pub mod skiplist;
 pub mod table;
+pub mod trigger;
+pub mod user;
+pub mod view;
+pub mod wal;
 
 pub trait Durable {
     fn write_to_disk(&mut self, file: &mut std::fs::File) -> Result<(), DurabilityError>;
@@ -14,11 +28,69 @@ pub trait Durable {
 pub enum DurabilityError {
     IoError(std::io::Error),
     DbError(String),
+    ConstraintViolation(String),
+    ForeignKeyViolation {
+        table: String,
+        column: String,
+        value: String,
+    },
+    CheckConstraintViolation {
+        column: String,
+        expr: String,
+    },
+    /// Another process changed the schema of the table since it was read.
+    SchemaMismatch {
+        cached_hash: u64,
+        disk_hash: u64,
+    },
 }
 
+impl std::fmt::Display for DurabilityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DurabilityError::IoError(e) => write!(f, "{}", e),
+            DurabilityError::DbError(message) | DurabilityError::ConstraintViolation(message) => {
+                write!(f, "{}", message)
+            }
+            DurabilityError::ForeignKeyViolation {
+                table,
+                column,
+                value,
+            } => write!(f, "Value {} is referenced by {}.{}", value, table, column),
+            DurabilityError::CheckConstraintViolation { column, expr } => {
+                write!(f, "Column {} violates the check {}", column, expr)
+            }
+            DurabilityError::SchemaMismatch {
+                cached_hash,
+                disk_hash,
+            } => write!(
+                f,
+                "Schema changed on disk from {:016x} to {:016x}, read the table again",
+                cached_hash, disk_hash
+            ),
+        }
+    }
+}
+
+impl From<String> for DurabilityError {
+    fn from(message: String) -> Self {
+        DurabilityError::DbError(message)
+    }
+}
+
+impl From<DurabilityError> for String {
+    fn from(error: DurabilityError) -> Self {
+        error.to_string()
+    }
+}
+
+#[derive(Clone)]
 pub struct DatabaseConfig {
     pub name: String,
     pub file_path: String,
+    /// The users allowed to connect, given with `--auth-file`. Without one
+    /// the server accepts every connection.
+    pub auth_file: Option<String>,
 }
 
 fn database_exists(database: &DatabaseConfig) -> bool {
@@ -43,30 +115,46 @@ fn write_to_disk(database: &DatabaseConfig) -> Result<(), DurabilityError> {
         header: DatabaseFileHeader {
             name,
             table_count: 0,
+            checkpoint_lsn: 0,
         },
     };
 
-    if let Err(e) = database.write_to_disk(&mut file) {
-        return Err(e);
-    }
+    database.write_to_disk(&mut file)?;
 
     Ok(())
 }
 
+/// Records the sequence of the last checkpoint in the database header,
+/// creating the database file when it does not exist yet.
+pub fn write_checkpoint_lsn(database: &DatabaseConfig, lsn: u64) -> Result<(), DurabilityError> {
+    if !database_exists(database) {
+        write_to_disk(database)?;
+    }
+    let full_file_path = format!("{}/{}", &database.file_path, &database.name);
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(full_file_path)
+        .map_err(DurabilityError::IoError)?;
+    let mut header = DatabaseFileHeader::read_from_disk(&mut file)?;
+    header.checkpoint_lsn = lsn;
+    header.write_to_disk(&mut file)?;
+    file.sync_all().map_err(DurabilityError::IoError)
+}
+
+#[cfg(test)]
 pub fn init_db(database: &DatabaseConfig) -> Result<(), DurabilityError> {
-    if database_exists(&database) {
+    if database_exists(database) {
         return Err(DurabilityError::DbError(
             "DatabaseConfig already exists".to_string(),
         ));
     }
 
-    write_to_disk(&database)
+    write_to_disk(database)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::env;
-
     use tempfile::tempdir;
 
     use super::*;
@@ -84,6 +172,7 @@ mod tests {
         let result = init_db(&DatabaseConfig {
             name,
             file_path: temp_dir.path().to_str().unwrap().to_string(),
+            auth_file: None,
         });
 
         if result.is_err() {
@@ -99,5 +188,73 @@ mod tests {
             format!("{:\0<64}", "test")
         );
         assert_eq!(0, header.table_count);
+        assert_eq!(0, header.checkpoint_lsn);
+    }
+
+    #[test]
+    fn test_write_checkpoint_lsn() {
+        let temp_dir = tempdir().unwrap();
+        let database = DatabaseConfig {
+            name: String::from("test"),
+            file_path: temp_dir.path().to_str().unwrap().to_string(),
+            auth_file: None,
+        };
+
+        write_checkpoint_lsn(&database, 42).unwrap();
+        let mut file = std::fs::File::open(temp_dir.path().join("test")).unwrap();
+        let header = DatabaseFileHeader::read_from_disk(&mut file).unwrap();
+        assert_eq!(header.checkpoint_lsn, 42);
+        assert_eq!(header.table_count, 0);
+    }
+
+    #[test]
+    fn test_error_conversions() {
+        fn durable(fail: bool) -> Result<u64, DurabilityError> {
+            if fail {
+                return Err(DurabilityError::ConstraintViolation(
+                    "Duplicate value for unique column id".to_string(),
+                ));
+            }
+            Ok(1)
+        }
+        fn message(fail: bool) -> Result<u64, String> {
+            if fail {
+                return Err("Column id does not exist".to_string());
+            }
+            Ok(2)
+        }
+        fn as_string(fail: bool) -> Result<u64, String> {
+            Ok(durable(fail)? + 1)
+        }
+        fn as_durability_error(fail: bool) -> Result<u64, DurabilityError> {
+            Ok(message(fail)? + as_string(false)?)
+        }
+
+        assert_eq!(as_string(false), Ok(2));
+        assert_eq!(
+            as_string(true),
+            Err("Duplicate value for unique column id".to_string())
+        );
+        assert_eq!(as_durability_error(false).unwrap(), 4);
+        assert!(matches!(
+            as_durability_error(true),
+            Err(DurabilityError::DbError(message)) if message == "Column id does not exist"
+        ));
+
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        let table = table::TableBuilder::new(&name)
+            .column("id", table::ColumnType::Int, 11)
+            .build()
+            .unwrap();
+        let missing = || -> Result<table::Row, String> {
+            Ok(table.row_for_columns(&["email".to_string()], &[b"a@b.c".to_vec()])?)
+        };
+        assert_eq!(missing().err().unwrap(), "Column email does not exist");
+        let file = std::fs::File::create(&name).unwrap();
+        assert!(matches!(
+            table.page_at(&file, 1),
+            Err(DurabilityError::DbError(_))
+        ));
     }
 }
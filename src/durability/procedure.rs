@@ -0,0 +1,113 @@
+use super::{DatabaseConfig, DurabilityError};
+
+const NAME_SIZE: usize = 64;
+
+/// A named Lua script run by `CALL`. Stored one after the other in the
+/// `{database}.procedures` file as the name, the length of the body as a u32
+/// and the body.
+#[derive(Debug, PartialEq)]
+pub struct Procedure {
+    pub name: String,
+    pub body: String,
+}
+
+impl Procedure {
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = self.name.as_bytes().to_vec();
+        bytes.resize(NAME_SIZE, 0);
+        bytes.extend((self.body.len() as u32).to_ne_bytes().iter());
+        bytes.extend(self.body.as_bytes());
+        bytes
+    }
+}
+
+pub fn procedures_file(database: &DatabaseConfig) -> String {
+    format!("{}/{}.procedures", database.file_path, database.name)
+}
+
+fn read_procedures(path: &str) -> Result<Vec<Procedure>, DurabilityError> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(DurabilityError::IoError(e)),
+    };
+    let invalid = || DurabilityError::DbError(format!("Invalid procedures file {}", path));
+
+    let mut procedures = vec![];
+    let mut rest = data.as_slice();
+    while !rest.is_empty() {
+        let header = rest.get(..NAME_SIZE + 4).ok_or_else(invalid)?;
+        let name = header[..NAME_SIZE].split(|b| *b == 0).next();
+        let body_len = u32::from_ne_bytes(header[NAME_SIZE..].try_into().unwrap()) as usize;
+        let body = rest
+            .get(NAME_SIZE + 4..NAME_SIZE + 4 + body_len)
+            .ok_or_else(invalid)?;
+        procedures.push(Procedure {
+            name: String::from_utf8_lossy(name.unwrap_or_default()).to_string(),
+            body: String::from_utf8_lossy(body).to_string(),
+        });
+        rest = &rest[NAME_SIZE + 4 + body_len..];
+    }
+    Ok(procedures)
+}
+
+pub fn create_procedure(path: &str, name: &str, body: &str) -> Result<(), DurabilityError> {
+    if name.is_empty() || name.len() > 63 {
+        return Err(DurabilityError::DbError(format!(
+            "Invalid procedure name {}, must be between 1 and 63 bytes",
+            name
+        )));
+    }
+    if read_procedures(path)?
+        .iter()
+        .any(|procedure| procedure.name == name)
+    {
+        return Err(DurabilityError::DbError(format!(
+            "Procedure {} already exists",
+            name
+        )));
+    }
+
+    let procedure = Procedure {
+        name: name.to_string(),
+        body: body.to_string(),
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .map_err(DurabilityError::IoError)?;
+    std::io::Write::write_all(&mut file, &procedure.bytes()).map_err(DurabilityError::IoError)
+}
+
+pub fn find_procedure(path: &str, name: &str) -> Result<Procedure, DurabilityError> {
+    read_procedures(path)?
+        .into_iter()
+        .find(|procedure| procedure.name == name)
+        .ok_or_else(|| DurabilityError::DbError(format!("Procedure {} does not exist", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_create_and_find_procedure() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("test.procedures");
+        let path = path.to_str().unwrap();
+
+        assert!(find_procedure(path, "add_user").is_err());
+        create_procedure(path, "add_user", "db.execute('SELECT 1')").unwrap();
+        create_procedure(path, "noop", "").unwrap();
+        assert!(create_procedure(path, "noop", "return 1").is_err());
+
+        assert_eq!(
+            find_procedure(path, "add_user").unwrap().body,
+            "db.execute('SELECT 1')"
+        );
+        assert_eq!(find_procedure(path, "noop").unwrap().body, "");
+    }
+}
@@ -0,0 +1,217 @@
+use std::os::unix::fs::FileExt;
+
+use super::{DatabaseConfig, DurabilityError};
+
+const NAME_SIZE: usize = 64;
+const RECORD_SIZE: usize = NAME_SIZE + 8 + 8 + 8;
+
+/// A named counter shared between tables. `current_value` is the last value
+/// handed out by `NEXTVAL`, `start` is kept around for `RESTART`.
+#[derive(Debug)]
+pub struct Sequence {
+    pub name: [u8; 64],
+    pub current_value: i64,
+    pub increment: i64,
+    pub start: i64,
+}
+
+impl Sequence {
+    pub fn new(name: &str, start: i64, increment: i64) -> Self {
+        let name_bytes = name.as_bytes();
+        let mut name_buffer = [0; NAME_SIZE];
+        name_buffer[..name_bytes.len()].copy_from_slice(name_bytes);
+        Sequence {
+            name: name_buffer,
+            current_value: start - increment,
+            increment,
+            start,
+        }
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend(self.name.iter());
+        bytes.extend(self.current_value.to_ne_bytes().iter());
+        bytes.extend(self.increment.to_ne_bytes().iter());
+        bytes.extend(self.start.to_ne_bytes().iter());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut name = [0; NAME_SIZE];
+        name.copy_from_slice(&bytes[..NAME_SIZE]);
+        let read_i64 =
+            |offset: usize| i64::from_ne_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        Sequence {
+            name,
+            current_value: read_i64(NAME_SIZE),
+            increment: read_i64(NAME_SIZE + 8),
+            start: read_i64(NAME_SIZE + 16),
+        }
+    }
+
+    fn has_name(&self, name: &str) -> bool {
+        self.name.split(|b| *b == 0).next() == Some(name.as_bytes())
+    }
+}
+
+pub fn sequences_file(database: &DatabaseConfig) -> String {
+    format!("{}/{}.sequences", database.file_path, database.name)
+}
+
+fn open_sequences_file(path: &str) -> Result<std::fs::File, DurabilityError> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(DurabilityError::IoError)
+}
+
+fn read_sequences(file: &std::fs::File) -> Result<Vec<Sequence>, DurabilityError> {
+    let length = file.metadata().map_err(DurabilityError::IoError)?.len();
+    let mut data = vec![0; length as usize];
+    file.read_exact_at(&mut data, 0)
+        .map_err(DurabilityError::IoError)?;
+    Ok(data
+        .chunks_exact(RECORD_SIZE)
+        .map(Sequence::from_bytes)
+        .collect())
+}
+
+fn find_sequence<'a>(
+    sequences: &'a [Sequence],
+    name: &str,
+) -> Result<(usize, &'a Sequence), DurabilityError> {
+    sequences
+        .iter()
+        .enumerate()
+        .find(|(_, sequence)| sequence.has_name(name))
+        .ok_or_else(|| DurabilityError::DbError(format!("Sequence {} does not exist", name)))
+}
+
+pub fn create_sequence(
+    path: &str,
+    name: &str,
+    start: i64,
+    increment: i64,
+) -> Result<(), DurabilityError> {
+    if name.is_empty() || name.len() > 63 {
+        return Err(DurabilityError::DbError(format!(
+            "Invalid sequence name {}, must be between 1 and 63 bytes",
+            name
+        )));
+    }
+    if increment == 0 {
+        return Err(DurabilityError::DbError(
+            "Sequence increment cannot be 0".to_string(),
+        ));
+    }
+
+    let file = open_sequences_file(path)?;
+    let sequences = read_sequences(&file)?;
+    if sequences.iter().any(|sequence| sequence.has_name(name)) {
+        return Err(DurabilityError::DbError(format!(
+            "Sequence {} already exists",
+            name
+        )));
+    }
+
+    let offset = (sequences.len() * RECORD_SIZE) as u64;
+    file.write_all_at(&Sequence::new(name, start, increment).bytes(), offset)
+        .map_err(DurabilityError::IoError)
+}
+
+pub fn drop_sequence(path: &str, name: &str) -> Result<(), DurabilityError> {
+    let file = open_sequences_file(path)?;
+    let mut sequences = read_sequences(&file)?;
+    let (position, _) = find_sequence(&sequences, name)?;
+    sequences.remove(position);
+
+    let bytes: Vec<u8> = sequences
+        .iter()
+        .flat_map(|sequence| sequence.bytes())
+        .collect();
+    file.set_len(0).map_err(DurabilityError::IoError)?;
+    file.write_all_at(&bytes, 0)
+        .map_err(DurabilityError::IoError)
+}
+
+/// Makes the next `NEXTVAL` return `value`, or the sequence's start value.
+pub fn restart_sequence(path: &str, name: &str, value: Option<i64>) -> Result<(), DurabilityError> {
+    let file = open_sequences_file(path)?;
+    let sequences = read_sequences(&file)?;
+    let (position, sequence) = find_sequence(&sequences, name)?;
+
+    let current_value = value.unwrap_or(sequence.start) - sequence.increment;
+    let offset = (position * RECORD_SIZE + NAME_SIZE) as u64;
+    file.write_all_at(&current_value.to_ne_bytes(), offset)
+        .map_err(DurabilityError::IoError)
+}
+
+/// Increments the sequence and returns the new value. The value is written
+/// back before it is returned so it is never handed out twice.
+pub fn next_value(path: &str, name: &str) -> Result<i64, DurabilityError> {
+    let file = open_sequences_file(path)?;
+    let sequences = read_sequences(&file)?;
+    let (position, sequence) = find_sequence(&sequences, name)?;
+
+    let next_value = sequence
+        .current_value
+        .checked_add(sequence.increment)
+        .ok_or_else(|| DurabilityError::DbError(format!("Sequence {} overflowed", name)))?;
+    let offset = (position * RECORD_SIZE + NAME_SIZE) as u64;
+    file.write_all_at(&next_value.to_ne_bytes(), offset)
+        .map_err(DurabilityError::IoError)?;
+    file.sync_data().map_err(DurabilityError::IoError)?;
+
+    Ok(next_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_next_value_increments_serially() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("test.sequences");
+        let path = path.to_str().unwrap();
+
+        create_sequence(path, "seq_events", 1, 1).unwrap();
+        create_sequence(path, "seq_orders", 100, 10).unwrap();
+
+        for expected in 1..=5 {
+            assert_eq!(next_value(path, "seq_events").unwrap(), expected);
+        }
+        assert_eq!(next_value(path, "seq_orders").unwrap(), 100);
+        assert_eq!(next_value(path, "seq_orders").unwrap(), 110);
+        assert_eq!(next_value(path, "seq_events").unwrap(), 6);
+    }
+
+    #[test]
+    fn test_restart_and_drop_sequence() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("test.sequences");
+        let path = path.to_str().unwrap();
+
+        create_sequence(path, "seq_a", 10, 2).unwrap();
+        create_sequence(path, "seq_b", 1, 1).unwrap();
+        assert!(create_sequence(path, "seq_a", 1, 1).is_err());
+
+        assert_eq!(next_value(path, "seq_a").unwrap(), 10);
+        assert_eq!(next_value(path, "seq_a").unwrap(), 12);
+        restart_sequence(path, "seq_a", None).unwrap();
+        assert_eq!(next_value(path, "seq_a").unwrap(), 10);
+        restart_sequence(path, "seq_a", Some(50)).unwrap();
+        assert_eq!(next_value(path, "seq_a").unwrap(), 50);
+
+        drop_sequence(path, "seq_a").unwrap();
+        assert!(next_value(path, "seq_a").is_err());
+        assert!(drop_sequence(path, "seq_a").is_err());
+        assert_eq!(next_value(path, "seq_b").unwrap(), 1);
+    }
+}
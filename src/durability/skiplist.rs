@@ -0,0 +1,363 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use super::{
+    table::{decode_decimal, decode_timestamp, ColumnType, Table},
+    DurabilityError,
+};
+use crate::query::{expression::parse_date, predicate::Operator, unquote};
+
+const MAX_LEVEL: usize = 32;
+
+/// Tables of up to this many rows get a skip list for range predicates.
+pub const SKIP_LIST_MAX_ROWS: u64 = 100_000;
+
+struct Node<K> {
+    key: K,
+    values: Vec<u64>,
+    /// The next node on every level the node is linked on.
+    next: Vec<Option<usize>>,
+}
+
+/// An ordered in-memory index mapping each key to the values inserted under
+/// it. Nodes are linked on a random number of levels, each level skipping
+/// over about 4 times as many nodes as the one below, so inserts and lookups
+/// take O(log n). Never written to disk, see `range_rows`.
+pub struct SkipListIndex<K: Ord> {
+    head: Vec<Option<usize>>,
+    /// Removed nodes leave a `None` behind, reused by the next insert.
+    nodes: Vec<Option<Node<K>>>,
+    free: Vec<usize>,
+    level: usize,
+    rng: u64,
+}
+
+impl<K: Ord> Default for SkipListIndex<K> {
+    fn default() -> Self {
+        SkipListIndex::new()
+    }
+}
+
+impl<K: Ord> SkipListIndex<K> {
+    pub fn new() -> SkipListIndex<K> {
+        SkipListIndex {
+            head: vec![None; MAX_LEVEL],
+            nodes: vec![],
+            free: vec![],
+            level: 1,
+            rng: 0x2545f4914f6cdd1d,
+        }
+    }
+
+    fn node(&self, id: usize) -> &Node<K> {
+        self.nodes[id].as_ref().unwrap()
+    }
+
+    fn next(&self, from: Option<usize>, level: usize) -> Option<usize> {
+        match from {
+            Some(id) => self.node(id).next[level],
+            None => self.head[level],
+        }
+    }
+
+    fn set_next(&mut self, from: Option<usize>, level: usize, to: Option<usize>) {
+        match from {
+            Some(id) => self.nodes[id].as_mut().unwrap().next[level] = to,
+            None => self.head[level] = to,
+        }
+    }
+
+    /// A level between 1 and `MAX_LEVEL`, each one more with probability
+    /// 1/4, from a xorshift generator.
+    fn random_level(&mut self) -> usize {
+        let mut level = 1;
+        while level < MAX_LEVEL {
+            self.rng ^= self.rng << 13;
+            self.rng ^= self.rng >> 7;
+            self.rng ^= self.rng << 17;
+            if self.rng & 3 != 0 {
+                break;
+            }
+            level += 1;
+        }
+        level
+    }
+
+    /// The last node before `key` on every level, `None` standing for the head.
+    fn predecessors(&self, key: &K) -> Vec<Option<usize>> {
+        let mut predecessors = vec![None; MAX_LEVEL];
+        let mut current = None;
+        for level in (0..self.level).rev() {
+            while let Some(next) = self.next(current, level) {
+                if self.node(next).key >= *key {
+                    break;
+                }
+                current = Some(next);
+            }
+            predecessors[level] = current;
+        }
+        predecessors
+    }
+
+    pub fn insert(&mut self, key: K, value: u64) {
+        let predecessors = self.predecessors(&key);
+        if let Some(id) = self.next(predecessors[0], 0) {
+            if self.node(id).key == key {
+                self.nodes[id].as_mut().unwrap().values.push(value);
+                return;
+            }
+        }
+
+        let level = self.random_level();
+        self.level = self.level.max(level);
+        let node = Node {
+            key,
+            values: vec![value],
+            next: (0..level).map(|l| self.next(predecessors[l], l)).collect(),
+        };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = Some(node);
+                id
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        for (l, predecessor) in predecessors.into_iter().enumerate().take(level) {
+            self.set_next(predecessor, l, Some(id));
+        }
+    }
+
+    /// Removes `key` with every value inserted under it, returning them.
+    #[allow(dead_code)]
+    pub fn remove(&mut self, key: &K) -> Vec<u64> {
+        let predecessors = self.predecessors(key);
+        let id = match self.next(predecessors[0], 0) {
+            Some(id) if self.node(id).key == *key => id,
+            _ => return vec![],
+        };
+        let node = self.nodes[id].take().unwrap();
+        for (l, next) in node.next.into_iter().enumerate() {
+            self.set_next(predecessors[l], l, next);
+        }
+        self.free.push(id);
+        node.values
+    }
+
+    /// Every `(key, value)` with `lower <= key <= upper`, in key order and
+    /// then in insertion order.
+    pub fn range<'a>(&'a self, lower: &K, upper: &'a K) -> impl Iterator<Item = (K, u64)> + 'a
+    where
+        K: Clone,
+    {
+        let first = self.next(self.predecessors(lower)[0], 0);
+        std::iter::successors(first, move |id| self.node(*id).next[0])
+            .map(move |id| self.node(id))
+            .take_while(move |node| node.key <= *upper)
+            .flat_map(|node| node.values.iter().map(|value| (node.key.clone(), *value)))
+    }
+}
+
+/// The form a value is ordered by in a skip list: numbers and dates as big
+/// endian bytes with the sign flipped so they compare like the values do,
+/// text without its padding. `None` for nulls and values that do not parse,
+/// which no range predicate matches.
+pub fn ordered_key(value: &[u8], column_type: &ColumnType) -> Option<Vec<u8>> {
+    let signed = |v: i64| ((v as u64) ^ (1 << 63)).to_be_bytes();
+    match column_type {
+        ColumnType::Timestamp => {
+            return decode_timestamp(value).map(|micros| micros.to_be_bytes().to_vec())
+        }
+        ColumnType::Decimal(..) => return decode_decimal(value).map(|v| signed(v).to_vec()),
+        _ => {}
+    }
+    let value = value.split(|b| *b == 0).next().unwrap_or_default();
+    if value.is_empty() {
+        return None;
+    }
+    let text = std::str::from_utf8(value).ok()?;
+    Some(match column_type {
+        ColumnType::Int => signed(text.trim().parse().ok()?).to_vec(),
+        ColumnType::Float => {
+            let bits = text
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|v| !v.is_nan())?
+                .to_bits();
+            let bits = match bits >> 63 {
+                1 => !bits,
+                _ => bits ^ (1 << 63),
+            };
+            bits.to_be_bytes().to_vec()
+        }
+        ColumnType::Date => {
+            let (year, month, day) = parse_date(text)?;
+            let mut key = signed(year).to_vec();
+            key.extend(month.to_be_bytes());
+            key.extend(day.to_be_bytes());
+            key
+        }
+        ColumnType::Varchar => value.to_vec(),
+        ColumnType::Timestamp | ColumnType::Decimal(..) => {
+            unreachable!("binary values are keyed above")
+        }
+    })
+}
+
+impl SkipListIndex<Vec<u8>> {
+    /// Scans the table to index every row of the column at `position` by its
+    /// `ordered_key`.
+    pub fn build(
+        table: &Table,
+        file: &std::fs::File,
+        position: usize,
+    ) -> Result<SkipListIndex<Vec<u8>>, DurabilityError> {
+        let column_type = &table.columns[position].column_type;
+        let mut index = SkipListIndex::new();
+        for page_number in 0..table.page_count() {
+            let page = table.page_at(file, page_number)?;
+            for (row_index, row) in table.page_entries(&page) {
+                if let Some(key) = ordered_key(&row.data[position], column_type) {
+                    index.insert(key, row_index);
+                }
+            }
+        }
+        Ok(index)
+    }
+}
+
+/// A skip list along with the state of the table file it was built from.
+struct CachedSkipList {
+    modified: SystemTime,
+    length: u64,
+    index: SkipListIndex<Vec<u8>>,
+}
+
+/// The rows whose value in the column at `position` may satisfy
+/// `column operator literal`, in row order. Bounds are inclusive, the
+/// predicate still has to be checked. The skip list is kept per table and
+/// column for the life of the process and rebuilt when the table file
+/// changed since it was built.
+pub fn range_rows(
+    table: &Table,
+    file: &std::fs::File,
+    position: usize,
+    operator: &Operator,
+    literal: &str,
+) -> Result<Vec<u64>, DurabilityError> {
+    static SKIP_LISTS: OnceLock<Mutex<HashMap<(String, usize), CachedSkipList>>> = OnceLock::new();
+
+    let column = &table.columns[position];
+    let key = match ordered_key(unquote(literal).as_bytes(), &column.column_type) {
+        Some(key) => key,
+        None => return Ok(vec![]),
+    };
+    // Above every key of the column.
+    let max = vec![0xff; (column.length as usize).max(16) + 1];
+    let (lower, upper) = match operator {
+        Operator::Lt | Operator::LtEq => (vec![], key),
+        Operator::Gt | Operator::GtEq => (key, max),
+        Operator::Eq => (key.clone(), key),
+        Operator::NotEq => (vec![], max),
+    };
+
+    let metadata = file.metadata().map_err(DurabilityError::IoError)?;
+    let modified = metadata.modified().map_err(DurabilityError::IoError)?;
+    let mut skip_lists = SKIP_LISTS.get_or_init(Mutex::default).lock().unwrap();
+    let cache_key = (table.name_str().to_string(), position);
+    let fresh = matches!(
+        skip_lists.get(&cache_key),
+        Some(cached) if cached.modified == modified && cached.length == metadata.len()
+    );
+    if !fresh {
+        let cached = CachedSkipList {
+            modified,
+            length: metadata.len(),
+            index: SkipListIndex::build(table, file, position)?,
+        };
+        skip_lists.insert(cache_key.clone(), cached);
+    }
+
+    let mut rows: Vec<u64> = skip_lists[&cache_key]
+        .index
+        .range(&lower, &upper)
+        .map(|(_, row_index)| row_index)
+        .collect();
+    rows.sort_unstable();
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ordering() {
+        let mut index = SkipListIndex::new();
+        for (i, key) in [5, 3, 9, 1, 7, 3].into_iter().enumerate() {
+            index.insert(key, i as u64);
+        }
+        let keys: Vec<(i32, u64)> = index.range(&i32::MIN, &i32::MAX).collect();
+        assert_eq!(keys, [(1, 3), (3, 1), (3, 5), (5, 0), (7, 4), (9, 2)]);
+
+        let mut index = SkipListIndex::new();
+        for i in (0..1000u64).rev() {
+            index.insert(i, i);
+        }
+        let values: Vec<u64> = index.range(&0, &u64::MAX).map(|(_, v)| v).collect();
+        assert_eq!(values, (0..1000).collect::<Vec<u64>>());
+        assert!(index.level > 1);
+    }
+
+    #[test]
+    fn test_range() {
+        let mut index = SkipListIndex::new();
+        for i in 0..100u64 {
+            index.insert(i * 2, i);
+        }
+        let keys: Vec<u64> = index.range(&15, &21).map(|(k, _)| k).collect();
+        assert_eq!(keys, [16, 18, 20]);
+        assert_eq!(index.range(&20, &20).count(), 1);
+        assert_eq!(index.range(&21, &15).count(), 0);
+        assert_eq!(index.range(&500, &600).count(), 0);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut index = SkipListIndex::new();
+        for i in 0..10u64 {
+            index.insert(i, i);
+        }
+        index.insert(4, 40);
+        assert_eq!(index.remove(&4), [4, 40]);
+        assert!(index.remove(&4).is_empty());
+        assert!(index.remove(&100).is_empty());
+        let keys: Vec<u64> = index.range(&0, &9).map(|(k, _)| k).collect();
+        assert_eq!(keys, [0, 1, 2, 3, 5, 6, 7, 8, 9]);
+
+        // The removed node is reused.
+        index.insert(11, 11);
+        assert_eq!(index.nodes.len(), 10);
+        assert_eq!(index.range(&10, &12).collect::<Vec<_>>(), [(11, 11)]);
+    }
+
+    #[test]
+    fn test_ordered_key() {
+        let int = |v: &[u8]| ordered_key(v, &ColumnType::Int).unwrap();
+        assert!(int(b"-5") < int(b"3"));
+        assert!(int(b"3") < int(b"10\0\0"));
+        let float = |v: &[u8]| ordered_key(v, &ColumnType::Float).unwrap();
+        assert!(float(b"-2.5") < float(b"-1"));
+        assert!(float(b"-1") < float(b"0.5"));
+        let date = |v: &[u8]| ordered_key(v, &ColumnType::Date).unwrap();
+        assert!(date(b"2022-12-31") < date(b"2023-01-01"));
+        assert_eq!(ordered_key(b"\0\0", &ColumnType::Int), None);
+        assert_eq!(ordered_key(b"abc", &ColumnType::Int), None);
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashSet;
+
+use super::{
+    dictionary::DICTIONARY_INDEX_LENGTH, ColumnDefinition, ColumnType, Table, MAX_PRECISION,
+};
+
+/// The size of the table and column name buffers.
+const NAME_SIZE: usize = 64;
+/// The table header keeps the index of the primary key column in a byte, with
+/// 0xFF meaning there is none.
+const MAX_COLUMNS: usize = 255;
+
+/// Builds a `Table` from its name and columns, checking them before anything
+/// is written to disk.
+///
+/// ```ignore
+/// let table = TableBuilder::new("users")
+///     .column("id", ColumnType::Int, 11)
+///     .column("name", ColumnType::Varchar, 64)
+///     .primary_key("id")
+///     .build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct TableBuilder {
+    name: String,
+    columns: Vec<ColumnDefinition>,
+    primary_key: Option<String>,
+    compression: bool,
+    /// A column name too long for its buffer, reported by `build`.
+    invalid_column: Option<String>,
+}
+
+impl TableBuilder {
+    pub fn new(name: &str) -> Self {
+        TableBuilder {
+            name: name.to_string(),
+            columns: vec![],
+            primary_key: None,
+            compression: false,
+            invalid_column: None,
+        }
+    }
+
+    pub fn column(mut self, name: &str, column_type: ColumnType, length: u64) -> Self {
+        if name.len() > NAME_SIZE {
+            self.invalid_column.get_or_insert(name.to_string());
+            return self;
+        }
+        self.column_definition(ColumnDefinition::new(name.to_string(), column_type, length))
+    }
+
+    /// Adds a column with its constraints, as parsed from `CREATE TABLE`.
+    pub fn column_definition(mut self, column: ColumnDefinition) -> Self {
+        self.columns.push(column);
+        self
+    }
+
+    pub fn columns(mut self, columns: Vec<ColumnDefinition>) -> Self {
+        self.columns.extend(columns);
+        self
+    }
+
+    /// Makes `column` the primary key, checked to exist by `build`.
+    #[allow(dead_code)]
+    pub fn primary_key(mut self, column: &str) -> Self {
+        self.primary_key = Some(column.to_string());
+        self
+    }
+
+    /// Stores the pages of the table LZ4 compressed.
+    #[allow(dead_code)]
+    pub fn compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    pub fn build(mut self) -> Result<Table, String> {
+        if self.name.is_empty() || self.name.len() > NAME_SIZE {
+            return Err(format!(
+                "Invalid table name {}, must be between 1 and {} bytes",
+                self.name, NAME_SIZE
+            ));
+        }
+        if self.columns.is_empty() || self.columns.len() > MAX_COLUMNS {
+            return Err(format!(
+                "Table {} must have between 1 and {} columns",
+                self.name, MAX_COLUMNS
+            ));
+        }
+
+        let invalid_column_name = |name: &str| {
+            format!(
+                "Invalid column name {}, must be between 1 and {} bytes",
+                name, NAME_SIZE
+            )
+        };
+        if let Some(name) = &self.invalid_column {
+            return Err(invalid_column_name(name));
+        }
+        let mut names = HashSet::new();
+        for column in self.columns.iter() {
+            let name = column.name_str();
+            if name.is_empty() {
+                return Err(invalid_column_name(name));
+            }
+            if !names.insert(name) {
+                return Err(format!("Duplicate column name {}", name));
+            }
+            if column.length == 0 {
+                return Err(format!("Column {} must have a length above 0", name));
+            }
+            if column.column_type.is_binary() && column.length != 8 {
+                return Err(format!(
+                    "Column {} of type {} must have a length of 8",
+                    name,
+                    column.column_type.sql_name()
+                ));
+            }
+            if let ColumnType::Decimal(precision, scale) = column.column_type {
+                if precision == 0 || precision > MAX_PRECISION || scale > precision {
+                    return Err(format!(
+                        "Invalid precision and scale for column {}, the precision must be \
+                         between 1 and {} and the scale at most the precision",
+                        name, MAX_PRECISION
+                    ));
+                }
+            }
+        }
+
+        if let Some(primary_key) = &self.primary_key {
+            let position = self
+                .columns
+                .iter()
+                .position(|column| column.name_str() == primary_key)
+                .ok_or_else(|| format!("Column {} does not exist", primary_key))?;
+            self.columns[position].primary_key = true;
+        }
+        if self.columns.iter().filter(|c| c.primary_key).count() > 1 {
+            return Err(format!("Table {} has more than one primary key", self.name));
+        }
+        for column in self.columns.iter().filter(|c| c.dictionary_encoded) {
+            let name = column.name_str();
+            if column.column_type != ColumnType::Varchar || column.length != DICTIONARY_INDEX_LENGTH
+            {
+                return Err(format!(
+                    "Dictionary encoded column {} must be of type VARCHAR with a length of {}",
+                    name, DICTIONARY_INDEX_LENGTH
+                ));
+            }
+            // Values are only compared once decoded, not as stored.
+            if column.primary_key || column.unique {
+                return Err(format!(
+                    "Dictionary encoded column {} cannot be a primary key or unique",
+                    name
+                ));
+            }
+        }
+
+        let mut table = Table::new(self.name, self.columns);
+        table.compression_enabled = self.compression;
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_matches_table_new() {
+        let mut id = ColumnDefinition::new("id".to_string(), ColumnType::Int, 11);
+        id.primary_key = true;
+        let name = ColumnDefinition::new("name".to_string(), ColumnType::Varchar, 64);
+        let expected = Table::new("users".to_string(), vec![id, name]);
+
+        let table = TableBuilder::new("users")
+            .column("id", ColumnType::Int, 11)
+            .column("name", ColumnType::Varchar, 64)
+            .primary_key("id")
+            .build()
+            .unwrap();
+
+        assert_eq!(table.name, expected.name);
+        assert_eq!(table.column_count, expected.column_count);
+        assert_eq!(table.primary_key, expected.primary_key);
+        assert_eq!(table.primary_key, 0);
+        assert_eq!(table.table_type, expected.table_type);
+        for (column, expected) in table.columns.iter().zip(expected.columns.iter()) {
+            assert_eq!(column.bytes(), expected.bytes());
+            assert_eq!(column.primary_key, expected.primary_key);
+        }
+    }
+
+    #[test]
+    fn test_builder_validation() {
+        let users = || TableBuilder::new("users").column("id", ColumnType::Int, 11);
+
+        assert!(users()
+            .column("id", ColumnType::Varchar, 8)
+            .build()
+            .is_err());
+        assert!(TableBuilder::new(&"t".repeat(65))
+            .column("id", ColumnType::Int, 11)
+            .build()
+            .is_err());
+        assert!(TableBuilder::new(&"t".repeat(64))
+            .column("id", ColumnType::Int, 11)
+            .build()
+            .is_ok());
+        assert!(TableBuilder::new("")
+            .column("id", ColumnType::Int, 11)
+            .build()
+            .is_err());
+        assert!(users().column("", ColumnType::Int, 11).build().is_err());
+        assert!(users()
+            .column(&"c".repeat(65), ColumnType::Int, 11)
+            .build()
+            .is_err());
+        assert!(users()
+            .column("email", ColumnType::Varchar, 0)
+            .build()
+            .is_err());
+        assert!(users()
+            .column("created_at", ColumnType::Timestamp, 11)
+            .build()
+            .is_err());
+        assert!(users().primary_key("missing").build().is_err());
+        assert!(TableBuilder::new("users").build().is_err());
+
+        let mut email = ColumnDefinition::new("email".to_string(), ColumnType::Varchar, 32);
+        email.primary_key = true;
+        assert!(users()
+            .column_definition(email)
+            .primary_key("id")
+            .build()
+            .is_err());
+
+        let dictionary_column = |column_type, length, unique| {
+            let mut status = ColumnDefinition::new("status".to_string(), column_type, length);
+            status.dictionary_encoded = true;
+            status.unique = unique;
+            users().column_definition(status).build()
+        };
+        assert!(dictionary_column(ColumnType::Varchar, 2, false).is_ok());
+        assert!(dictionary_column(ColumnType::Varchar, 16, false).is_err());
+        assert!(dictionary_column(ColumnType::Int, 2, false).is_err());
+        assert!(dictionary_column(ColumnType::Varchar, 2, true).is_err());
+    }
+}
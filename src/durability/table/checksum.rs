@@ -0,0 +1,78 @@
+use crate::durability::{wal::crc32_update, DurabilityError};
+
+use super::Table;
+
+impl Table {
+    /// A CRC32 of the rows of the table, `TABLE CHECKSUM`, to tell whether
+    /// two copies hold the same data. Rows are read in page order, deleted
+    /// ones left out, and each goes in a column at a time as its value with
+    /// the null bytes padding it to the length of the column. A dictionary
+    /// encoded column goes in as its value followed by a null byte, not as
+    /// the index it stores, and delta encoded or compressed pages as they
+    /// decode, so the checksum follows the data and not how it is stored.
+    pub fn checksum(&self, file: &std::fs::File) -> Result<u32, DurabilityError> {
+        let mut crc = !0;
+        for page_number in 0..self.page_count() {
+            let page = self.page_at(file, page_number)?;
+            for (_, row) in self.page_entries(&page) {
+                for (column, value) in self.columns.iter().zip(row.data.iter()) {
+                    crc = crc32_update(crc, value);
+                    if column.dictionary_encoded {
+                        crc = crc32_update(crc, &[0]);
+                    }
+                }
+            }
+        }
+        Ok(!crc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::durability::{
+        table::{create_table, writeable_table_file, ColumnType, Row, TableBuilder},
+        Durable,
+    };
+
+    use super::*;
+
+    fn row(id: &str, name: &str) -> Row {
+        Row {
+            data: vec![id.as_bytes().to_vec(), name.as_bytes().to_vec()],
+        }
+    }
+
+    #[test]
+    fn test_checksum() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column("name", ColumnType::Varchar, 16),
+        )
+        .unwrap();
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let empty = table.checksum(&file).unwrap();
+        assert_eq!(empty, 0);
+
+        let rows: Vec<Row> = (0..10).map(|i| row(&i.to_string(), "Ada")).collect();
+        table.add_rows(&rows, &mut file).unwrap();
+        let checksum = table.checksum(&file).unwrap();
+        assert_ne!(checksum, empty);
+        // The same from another read of the file.
+        let table_again = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table_again.checksum(&file).unwrap(), checksum);
+
+        table.add_row(&row("10", "Grace"), &mut file).unwrap();
+        assert_ne!(table.checksum(&file).unwrap(), checksum);
+        let rows_per_page = table.page_size() / table.row_size();
+        table
+            .delete_at(&mut file, 10 / rows_per_page, 10 % rows_per_page)
+            .unwrap();
+        assert_eq!(table.checksum(&file).unwrap(), checksum);
+    }
+}
@@ -1,9 +1,38 @@
-use super::ColumnType;
+use super::{dictionary::Dictionary, name_str, ColumnType};
 
+pub const UNIQUE_FLAG: u8 = 1;
+pub const DELTA_ENCODED_FLAG: u8 = 2;
+pub const DICTIONARY_ENCODED_FLAG: u8 = 4;
+
+#[derive(Debug, Clone)]
 pub struct ColumnDefinition {
     pub name: [u8; 64],
     pub column_type: ColumnType,
     pub length: u64,
+    pub default_value: Option<Vec<u8>>,
+    /// Enforced on insert with a full table scan. Adding the constraint to an
+    /// existing column through `ALTER TABLE` would first need to scan the
+    /// stored rows to make sure they are already unique.
+    pub unique: bool,
+    /// Int values are stored as the difference to the value of the row
+    /// before them in the page, see `Table::encode_deltas`. Kept in the byte
+    /// of the unique flag.
+    pub delta_encoded: bool,
+    /// Varchar values are stored as their index in `dictionary`, see
+    /// `Table::add_dictionary_entries`. Kept in the byte of the unique flag.
+    pub dictionary_encoded: bool,
+    /// A `column op literal` expression checked on insert, stored as a null
+    /// padded text blob.
+    pub check_expr: Option<[u8; 128]>,
+    /// Not part of the column definition bytes, the table header stores the
+    /// index of the primary key column instead.
+    pub primary_key: bool,
+    /// The `(table, column)` this column references through a foreign key.
+    /// Stored in the `{table}.fk` files rather than the column definition bytes.
+    pub references: Option<(String, String)>,
+    /// The values of a dictionary encoded column, stored in the
+    /// `{table}.{column}.dict` file.
+    pub dictionary: Dictionary,
 }
 
 impl ColumnDefinition {
@@ -15,11 +44,42 @@ impl ColumnDefinition {
             name: name_buffer,
             column_type,
             length,
+            default_value: None,
+            unique: false,
+            delta_encoded: false,
+            dictionary_encoded: false,
+            check_expr: None,
+            primary_key: false,
+            references: None,
+            dictionary: Dictionary::default(),
         }
     }
 
-    pub fn size() -> u64 {
-        76
+    pub fn name_str(&self) -> &str {
+        name_str(&self.name)
+    }
+
+    pub fn size(&self) -> u64 {
+        206 + self.length
+    }
+
+    /// Where the flags byte is in the column definition bytes.
+    pub fn flags_offset(&self) -> u64 {
+        77 + self.length
+    }
+
+    pub fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.unique {
+            flags |= UNIQUE_FLAG;
+        }
+        if self.delta_encoded {
+            flags |= DELTA_ENCODED_FLAG;
+        }
+        if self.dictionary_encoded {
+            flags |= DICTIONARY_ENCODED_FLAG;
+        }
+        flags
     }
 
     pub fn bytes(&self) -> Vec<u8> {
@@ -27,7 +87,15 @@ impl ColumnDefinition {
         let column_type = &self.column_type;
         bytes.extend(self.name.iter());
         bytes.extend(column_type.bytes().iter());
-        bytes.extend(self.length.to_ne_bytes().iter());
+        let length = column_type.header_length(self.length);
+        bytes.extend(length.to_ne_bytes().iter());
+
+        let mut default_value = self.default_value.clone().unwrap_or_default();
+        default_value.resize(self.length as usize, 0);
+        bytes.push(self.default_value.is_some() as u8);
+        bytes.extend(default_value.iter());
+        bytes.push(self.flags());
+        bytes.extend(self.check_expr.unwrap_or([0; 128]).iter());
         bytes
     }
 }
@@ -1,9 +1,25 @@
+use super::decimal::{decode_decimal, encode_decimal, format_decimal};
+use super::timestamp::{decode_timestamp, encode_timestamp, format_timestamp};
+
 const COLUMN_TYPE_INT: u32 = 1;
 const COLUMN_TYPE_VARCHAR: u32 = 2;
+const COLUMN_TYPE_FLOAT: u32 = 3;
+const COLUMN_TYPE_DATE: u32 = 4;
+const COLUMN_TYPE_TIMESTAMP: u32 = 6;
+const COLUMN_TYPE_DECIMAL: u32 = 7;
 
+/// Values of every type are stored as text, `Date` as `YYYY-MM-DD`, except
+/// `Timestamp` which is stored as the microseconds since the Unix epoch in a
+/// u64 and `Decimal(precision, scale)` which is stored as an i64 scaled by
+/// `10^scale`.
+#[derive(Debug, Clone, PartialEq)]
 pub enum ColumnType {
     Int,
     Varchar,
+    Float,
+    Date,
+    Timestamp,
+    Decimal(u16, u16),
 }
 
 impl ColumnType {
@@ -12,13 +28,89 @@ impl ColumnType {
         let code: u32 = self.into();
         code.to_ne_bytes().to_vec()
     }
-}
 
-impl Into<u32> for &ColumnType {
-    fn into(self) -> u32 {
+    /// The type as written in `CREATE TABLE`.
+    pub fn sql_name(&self) -> String {
+        match self {
+            ColumnType::Int => "INT".to_string(),
+            ColumnType::Varchar => "VARCHAR".to_string(),
+            ColumnType::Float => "FLOAT".to_string(),
+            ColumnType::Date => "DATE".to_string(),
+            ColumnType::Timestamp => "TIMESTAMP".to_string(),
+            ColumnType::Decimal(precision, scale) => format!("DECIMAL({},{})", precision, scale),
+        }
+    }
+
+    /// Whether values are stored in binary rather than as text. Such columns
+    /// are always 8 bytes long.
+    pub fn is_binary(&self) -> bool {
+        matches!(self, ColumnType::Timestamp | ColumnType::Decimal(..))
+    }
+
+    /// What the column header stores in place of the length. Decimal columns
+    /// store `(precision << 16) | scale` there.
+    pub fn header_length(&self, length: u64) -> u64 {
+        match self {
+            ColumnType::Decimal(precision, scale) => ((*precision as u64) << 16) | *scale as u64,
+            _ => length,
+        }
+    }
+
+    /// The type and length of a column from the code and length stored in
+    /// its header, `None` for an unknown code.
+    pub fn from_header(code: u32, header_length: u64) -> Option<(ColumnType, u64)> {
+        let column_type = match code {
+            COLUMN_TYPE_INT => ColumnType::Int,
+            COLUMN_TYPE_VARCHAR => ColumnType::Varchar,
+            COLUMN_TYPE_FLOAT => ColumnType::Float,
+            COLUMN_TYPE_DATE => ColumnType::Date,
+            COLUMN_TYPE_TIMESTAMP => ColumnType::Timestamp,
+            COLUMN_TYPE_DECIMAL => {
+                let precision = (header_length >> 16) as u16;
+                let scale = (header_length & 0xFFFF) as u16;
+                return Some((ColumnType::Decimal(precision, scale), 8));
+            }
+            _ => return None,
+        };
+        Some((column_type, header_length))
+    }
+
+    /// The stored form of a value given as text, `None` when it is not a
+    /// valid value of a binary type. Text types store the value as is.
+    pub fn encode(&self, value: &[u8]) -> Option<Vec<u8>> {
         match self {
+            ColumnType::Timestamp => encode_timestamp(value),
+            ColumnType::Decimal(precision, scale) => encode_decimal(value, *precision, *scale),
+            _ => Some(value.to_vec()),
+        }
+    }
+
+    /// A stored value as text, empty for nulls.
+    pub fn format(&self, value: &[u8]) -> String {
+        match self {
+            ColumnType::Timestamp => decode_timestamp(value)
+                .map(format_timestamp)
+                .unwrap_or_default(),
+            ColumnType::Decimal(_, scale) => decode_decimal(value)
+                .map(|value| format_decimal(value, *scale))
+                .unwrap_or_default(),
+            _ => {
+                let value = value.split(|b| *b == 0).next().unwrap_or_default();
+                String::from_utf8_lossy(value).to_string()
+            }
+        }
+    }
+}
+
+impl From<&ColumnType> for u32 {
+    fn from(column_type: &ColumnType) -> u32 {
+        match column_type {
             ColumnType::Int => COLUMN_TYPE_INT,
             ColumnType::Varchar => COLUMN_TYPE_VARCHAR,
+            ColumnType::Float => COLUMN_TYPE_FLOAT,
+            ColumnType::Date => COLUMN_TYPE_DATE,
+            ColumnType::Timestamp => COLUMN_TYPE_TIMESTAMP,
+            ColumnType::Decimal(..) => COLUMN_TYPE_DECIMAL,
         }
     }
 }
@@ -0,0 +1,97 @@
+/// The flag and the size of the stored bytes ahead of every page of a
+/// compressed table.
+pub const PAGE_SLOT_HEADER_SIZE: u64 = 5;
+/// The page size of compressed tables, kept fixed as the slots are laid out
+/// by it. Large enough for the unwritten end of a slot to leave whole blocks
+/// of the file unallocated.
+pub const COMPRESSED_PAGE_SIZE: u64 = 64 * 1024;
+
+const RAW_PAGE: u8 = 0;
+const LZ4_PAGE: u8 = 1;
+
+/// The slot of `page`: a flag, the size of what follows and the page, LZ4
+/// compressed unless that does not make it smaller. A page of zeros, like a
+/// newly allocated one, is not compressed and stored as nothing.
+pub fn encode_page(page: &[u8]) -> Vec<u8> {
+    let (flag, data) = match page.iter().all(|b| *b == 0) {
+        true => (RAW_PAGE, vec![]),
+        false => {
+            let compressed = lz4_flex::compress(page);
+            match compressed.len() < page.len() {
+                true => (LZ4_PAGE, compressed),
+                false => (RAW_PAGE, page.to_vec()),
+            }
+        }
+    };
+    let mut slot = Vec::with_capacity(PAGE_SLOT_HEADER_SIZE as usize + data.len());
+    slot.push(flag);
+    slot.extend((data.len() as u32).to_ne_bytes());
+    slot.extend(data);
+    slot
+}
+
+/// The flag and the size of the stored bytes from the start of a slot.
+pub fn slot_header(header: &[u8; PAGE_SLOT_HEADER_SIZE as usize]) -> (u8, usize) {
+    let size = u32::from_ne_bytes(header[1..].try_into().unwrap());
+    (header[0], size as usize)
+}
+
+/// The page of `page_size` bytes stored as `data` in a slot with `flag`.
+/// Raw pages are padded with zeros.
+pub fn decode_page(flag: u8, data: &[u8], page_size: usize) -> Result<Vec<u8>, String> {
+    match flag {
+        RAW_PAGE if data.len() <= page_size => {
+            let mut page = data.to_vec();
+            page.resize(page_size, 0);
+            Ok(page)
+        }
+        LZ4_PAGE => {
+            let mut page = vec![0; page_size];
+            match lz4_flex::decompress_into(data, &mut page) {
+                Ok(size) if size == page_size => Ok(page),
+                Ok(size) => Err(format!(
+                    "Compressed page holds {} bytes, expected {}",
+                    size, page_size
+                )),
+                Err(e) => Err(format!("Invalid compressed page: {}", e)),
+            }
+        }
+        _ => Err(format!("Invalid page slot with flag {}", flag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(page: &[u8]) -> (u8, usize) {
+        let slot = encode_page(page);
+        let (flag, size) = slot_header(slot[..5].try_into().unwrap());
+        assert_eq!(slot.len(), 5 + size);
+        assert_eq!(decode_page(flag, &slot[5..], page.len()).unwrap(), page);
+        (flag, size)
+    }
+
+    #[test]
+    fn test_page_slots() {
+        assert_eq!(roundtrip(&[0; 4096]), (RAW_PAGE, 0));
+
+        let mut padded = vec![0; 4096];
+        for (i, row) in padded.chunks_mut(64).enumerate() {
+            let value = format!("user{}", i);
+            row[..value.len()].copy_from_slice(value.as_bytes());
+        }
+        let (flag, size) = roundtrip(&padded);
+        assert_eq!(flag, LZ4_PAGE);
+        assert!(size < 1024);
+
+        // Bytes that do not compress are kept as they are.
+        let noise: Vec<u8> = (0..256u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        assert_eq!(roundtrip(&noise), (RAW_PAGE, 256));
+
+        assert!(decode_page(LZ4_PAGE, &[0xFF, 1, 2], 4096).is_err());
+        assert!(decode_page(7, &[], 4096).is_err());
+    }
+}
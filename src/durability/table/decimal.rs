@@ -0,0 +1,170 @@
+use std::cmp::Ordering;
+
+use crate::query::unquote;
+
+/// The most digits an i64 holds for any value.
+pub const MAX_PRECISION: u16 = 18;
+
+/// Splits a decimal literal into its digits read as an integer and the
+/// number of digits after the point.
+fn parse_unscaled(value: &str) -> Option<(i128, u32)> {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let all_digits = integer
+        .bytes()
+        .chain(fraction.bytes())
+        .all(|b| b.is_ascii_digit());
+    if !all_digits || integer.len() + fraction.len() == 0 || integer.len() + fraction.len() > 36 {
+        return None;
+    }
+    let unscaled: i128 = format!("{}{}", integer, fraction).parse().ok()?;
+    let unscaled = if negative { -unscaled } else { unscaled };
+    Some((unscaled, fraction.len() as u32))
+}
+
+/// Parses `value` scaled by `10^scale`, rejecting values with more than
+/// `precision` digits or with non-zero digits past the scale.
+pub fn parse_decimal(value: &str, precision: u16, scale: u16) -> Option<i64> {
+    let (unscaled, digits_scale) = parse_unscaled(value.trim())?;
+    let scale = scale as u32;
+    let scaled = match digits_scale.cmp(&scale) {
+        Ordering::Greater => {
+            let divisor = 10i128.pow(digits_scale - scale);
+            if unscaled % divisor != 0 {
+                return None;
+            }
+            unscaled / divisor
+        }
+        _ => unscaled.checked_mul(10i128.pow(scale - digits_scale))?,
+    };
+    if scaled.unsigned_abs() >= 10u128.pow(precision as u32) {
+        return None;
+    }
+    Some(scaled as i64)
+}
+
+/// Formats a value scaled by `10^scale` with `scale` decimal places.
+pub fn format_decimal(value: i64, scale: u16) -> String {
+    let divisor = 10u64.pow(scale as u32);
+    let sign = if value < 0 { "-" } else { "" };
+    let integer = value.unsigned_abs() / divisor;
+    match scale {
+        0 => format!("{}{}", sign, integer),
+        _ => format!(
+            "{}{}.{:0width$}",
+            sign,
+            integer,
+            value.unsigned_abs() % divisor,
+            width = scale as usize
+        ),
+    }
+}
+
+/// Compares a stored value scaled by `10^scale` to a literal of any scale.
+pub fn compare_decimal(value: i64, scale: u16, literal: &str) -> Option<Ordering> {
+    let (literal, literal_scale) = parse_unscaled(unquote(literal.trim()))?;
+    let scale = scale as u32;
+    let common_scale = scale.max(literal_scale);
+    let value = (value as i128).checked_mul(10i128.checked_pow(common_scale - scale)?)?;
+    let literal = literal.checked_mul(10i128.checked_pow(common_scale - literal_scale)?)?;
+    Some(value.cmp(&literal))
+}
+
+/// The stored form of a `Decimal` value, its scaled i64 big-endian with the
+/// sign bit flipped. Values of at most `MAX_PRECISION` digits then never
+/// start with the byte 0xFF marking deleted rows. An empty value is stored
+/// as zero.
+pub fn encode_decimal(value: &[u8], precision: u16, scale: u16) -> Option<Vec<u8>> {
+    let value = value.split(|b| *b == 0).next().unwrap_or_default();
+    let value = std::str::from_utf8(value).ok()?.trim();
+    let scaled = match unquote(value) {
+        "" => 0,
+        value => parse_decimal(value, precision, scale)?,
+    };
+    Some(((scaled as u64) ^ SIGN_BIT).to_be_bytes().to_vec())
+}
+
+const SIGN_BIT: u64 = 1 << 63;
+
+/// Reads a stored `Decimal` value. The value may be missing its zero padding,
+/// like default values read from the header, and is zero when every byte
+/// is.
+pub fn decode_decimal(value: &[u8]) -> Option<i64> {
+    if value.len() > 8 {
+        return None;
+    }
+    if value.iter().all(|b| *b == 0) {
+        return Some(0);
+    }
+    let mut bytes = [0; 8];
+    bytes[..value.len()].copy_from_slice(value);
+    Some((u64::from_be_bytes(bytes) ^ SIGN_BIT) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decimal_roundtrip() {
+        assert_eq!(parse_decimal("123.45", 10, 2), Some(12345));
+        assert_eq!(parse_decimal("-0.5", 10, 2), Some(-50));
+        assert_eq!(parse_decimal("7", 10, 2), Some(700));
+        assert_eq!(parse_decimal("1.250", 10, 2), Some(125));
+        assert_eq!(format_decimal(12345, 2), "123.45");
+        assert_eq!(format_decimal(-50, 2), "-0.50");
+        assert_eq!(format_decimal(-1205, 0), "-1205");
+        assert_eq!(format_decimal(5, 3), "0.005");
+
+        assert_eq!(parse_decimal("1.255", 10, 2), None);
+        assert_eq!(parse_decimal("12345.6", 6, 2), None);
+        assert_eq!(parse_decimal("9999.99", 6, 2), Some(999_999));
+        assert_eq!(parse_decimal("1e5", 10, 2), None);
+        assert_eq!(parse_decimal(".", 10, 2), None);
+    }
+
+    #[test]
+    fn test_decimal_addition_is_exact() {
+        let price = encode_decimal(b"'123.45'", 10, 2).unwrap();
+        let tax = encode_decimal(b"0.1", 10, 2).unwrap();
+        let total = decode_decimal(&price).unwrap() + decode_decimal(&tax).unwrap();
+        assert_eq!(
+            Some(total),
+            decode_decimal(&encode_decimal(b"123.55", 10, 2).unwrap())
+        );
+        assert_eq!(format_decimal(total, 2), "123.55");
+    }
+
+    #[test]
+    fn test_encoded_decimal_never_starts_with_0xff() {
+        for value in ["2.55", "-0.01", "-99999999.99", "99999999.99", "0", ""] {
+            let encoded = encode_decimal(value.as_bytes(), 10, 2).unwrap();
+            assert_ne!(encoded[0], 0xFF, "{}", value);
+            let decoded = decode_decimal(&encoded).unwrap();
+            assert_eq!(
+                format_decimal(decoded, 2),
+                format_decimal(parse_decimal(value, 10, 2).unwrap_or(0), 2)
+            );
+        }
+        let max = "9".repeat(MAX_PRECISION as usize);
+        for value in [max.clone(), format!("-{}", max)] {
+            assert_ne!(encode_decimal(value.as_bytes(), 18, 0).unwrap()[0], 0xFF);
+        }
+        assert_eq!(decode_decimal(&[]), Some(0));
+    }
+
+    #[test]
+    fn test_compare_decimal() {
+        assert_eq!(compare_decimal(12345, 2, "123.45"), Some(Ordering::Equal));
+        assert_eq!(
+            compare_decimal(12345, 2, "'123.4'"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(compare_decimal(12345, 2, "123.451"), Some(Ordering::Less));
+        assert_eq!(compare_decimal(-1, 2, "0"), Some(Ordering::Less));
+        assert_eq!(compare_decimal(12345, 2, "abc"), None);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::concurrency::{lock_manager, TableLock};
+use crate::durability::DurabilityError;
+
+use super::table::TOMBSTONE;
+use super::{ColumnDefinition, ColumnType, Table};
+
+/// Where each delta encoded column starts in a row.
+fn delta_columns(columns: &[ColumnDefinition]) -> Vec<(usize, &ColumnDefinition)> {
+    let mut start = 0;
+    let mut delta_columns = vec![];
+    for column in columns {
+        if column.delta_encoded {
+            delta_columns.push((start, column));
+        }
+        start += column.length as usize;
+    }
+    delta_columns
+}
+
+/// The integer a stored value holds, `None` for a null value.
+fn integer(value: &[u8], column: &ColumnDefinition) -> Result<Option<i64>, DurabilityError> {
+    let value = value.split(|b| *b == 0).next().unwrap_or_default();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let text = String::from_utf8_lossy(value);
+    match text.trim().parse() {
+        Ok(integer) => Ok(Some(integer)),
+        Err(_) => Err(DurabilityError::DbError(format!(
+            "Column {} is delta encoded and only holds integers, got {}",
+            column.name_str(),
+            text
+        ))),
+    }
+}
+
+fn write_integer(
+    value: &mut [u8],
+    integer: i64,
+    column: &ColumnDefinition,
+) -> Result<(), DurabilityError> {
+    let text = integer.to_string();
+    if text.len() > value.len() {
+        return Err(DurabilityError::DbError(format!(
+            "Value {} does not fit delta encoded column {}",
+            text,
+            column.name_str()
+        )));
+    }
+    value.fill(0);
+    value[..text.len()].copy_from_slice(text.as_bytes());
+    Ok(())
+}
+
+impl Table {
+    pub fn has_delta_columns(&self) -> bool {
+        self.columns.iter().any(|column| column.delta_encoded)
+    }
+
+    /// Rewrites every value of the delta encoded columns of the rows of
+    /// `page` with `code`, given the value and the value of the row before it
+    /// in the page. Deleted rows and null values are skipped, the first value
+    /// of a column in the page has no value before it.
+    fn map_deltas(
+        &self,
+        page: &mut [u8],
+        code: impl Fn(i64, Option<i64>) -> Option<(i64, i64)>,
+    ) -> Result<(), DurabilityError> {
+        let delta_columns = delta_columns(&self.columns);
+        if delta_columns.is_empty() {
+            return Ok(());
+        }
+        let mut previous = vec![None; delta_columns.len()];
+        for row in page.chunks_exact_mut(self.row_size() as usize) {
+            if row[0] == TOMBSTONE {
+                continue;
+            }
+            for ((start, column), previous) in delta_columns.iter().zip(previous.iter_mut()) {
+                let value = &mut row[*start..*start + column.length as usize];
+                let Some(integer) = integer(value, column)? else {
+                    continue;
+                };
+                let (stored, current) = code(integer, *previous).ok_or_else(|| {
+                    DurabilityError::DbError(format!(
+                        "Value out of range in delta encoded column {}",
+                        column.name_str()
+                    ))
+                })?;
+                write_integer(value, stored, column)?;
+                *previous = Some(current);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the values of the delta encoded columns of `page`, read as
+    /// they are, by their difference to the value of the row before them.
+    /// The first value of a column in the page is kept, so every page
+    /// decodes on its own. Values are written back in their shortest form.
+    pub(super) fn encode_deltas(&self, page: &mut [u8]) -> Result<(), DurabilityError> {
+        self.map_deltas(page, |value, previous| match previous {
+            Some(previous) => Some((value.checked_sub(previous)?, value)),
+            None => Some((value, value)),
+        })
+    }
+
+    /// Adds up the values of the delta encoded columns of `page` as stored,
+    /// the inverse of `encode_deltas`.
+    pub(super) fn decode_deltas(&self, page: &mut [u8]) -> Result<(), DurabilityError> {
+        self.map_deltas(page, |delta, previous| {
+            let value = match previous {
+                Some(previous) => previous.checked_add(delta)?,
+                None => delta,
+            };
+            Some((value, value))
+        })
+    }
+
+    /// Turns on delta encoding of the Int column `name` and rewrites every
+    /// page with it, along with the flag in the header, through the redo
+    /// log. `COMPRESS COLUMN`.
+    pub fn set_delta_encoded(
+        &mut self,
+        file: &mut std::fs::File,
+        name: &str,
+    ) -> Result<(), DurabilityError> {
+        let position = self
+            .columns
+            .iter()
+            .position(|column| column.name_str() == name)
+            .ok_or_else(|| format!("Column {} does not exist", name))?;
+        let column = &self.columns[position];
+        if column.column_type != ColumnType::Int {
+            return Err(format!(
+                "Column {} of type {} cannot be delta encoded, only INT columns can",
+                name,
+                column.column_type.sql_name()
+            )
+            .into());
+        }
+        if column.delta_encoded {
+            return Err(format!("Column {} is already delta encoded", name).into());
+        }
+
+        let table_name = self.name_str().to_string();
+        let locks = lock_manager();
+        locks.acquire_table_lock(&table_name, TableLock::Exclusive);
+        let result = self.rewrite_delta_encoded(file, position);
+        locks.release_table_lock(&table_name);
+        result
+    }
+
+    fn rewrite_delta_encoded(
+        &mut self,
+        file: &mut std::fs::File,
+        position: usize,
+    ) -> Result<(), DurabilityError> {
+        let mut writes = vec![];
+        for page_number in 0..self.page_count() {
+            let page = self.page_at(file, page_number)?;
+            let offset = self.header_size() + page_number * self.page_size();
+            writes.push((offset, page.data.to_vec()));
+        }
+        let flags_offset = self.column_definition_offset(position);
+        let column = &mut self.columns[position];
+        column.delta_encoded = true;
+        writes.push((flags_offset + column.flags_offset(), vec![column.flags()]));
+
+        let written = self.write_logged(&writes, file);
+        if written.is_err() {
+            self.columns[position].delta_encoded = false;
+        }
+        written?;
+        self.record_modified(self.row_count, file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::{
+        table::{create_table, writeable_table_file, Row, TableBuilder},
+        Durable,
+    };
+
+    fn ids(table: &Table, file: &std::fs::File) -> Vec<String> {
+        (0..table.page_count())
+            .flat_map(|page| table.decode_rows_batch(&table.page_at(file, page).unwrap()))
+            .map(|row| row[0].clone())
+            .collect()
+    }
+
+    fn stored_ids(table: &Table, file: &std::fs::File) -> Vec<String> {
+        let mut plain = Table::new(table.name_str().to_string(), table.columns.to_vec());
+        plain.row_count = table.row_count;
+        plain.columns[0].delta_encoded = false;
+        ids(&plain, file)
+    }
+
+    #[test]
+    fn test_delta_encoding_roundtrip() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("events").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column("city", ColumnType::Varchar, 16),
+        )
+        .unwrap();
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let row = |id: &str| Row {
+            data: vec![id.as_bytes().to_vec(), b"Oslo".to_vec()],
+        };
+        let sequential: Vec<Row> = (1..=9).map(|i| row(&i.to_string())).collect();
+        table.add_rows(&sequential, &mut file).unwrap();
+
+        table.set_delta_encoded(&mut file, "id").unwrap();
+        let sequential_ids: Vec<String> = (1..=9).map(|i: i64| i.to_string()).collect();
+        assert_eq!(ids(&table, &file), sequential_ids);
+        // The first row of every page keeps its value.
+        let rows_per_page = (table.page_size() / table.row_size()) as usize;
+        let stored = stored_ids(&table, &file);
+        for (i, id) in stored.iter().enumerate() {
+            let expected = if i % rows_per_page == 0 { i + 1 } else { 1 };
+            assert_eq!(id, &expected.to_string());
+        }
+
+        let scattered = ["-5", "1000000", "", "42", "-999999999", "7"];
+        table
+            .add_rows(
+                &scattered.iter().map(|id| row(id)).collect::<Vec<Row>>(),
+                &mut file,
+            )
+            .unwrap();
+        let mut expected = sequential_ids.clone();
+        expected.extend(scattered.iter().map(|id| id.to_string()));
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        assert!(table.columns[0].delta_encoded);
+        assert_eq!(ids(&table, &file), expected);
+
+        // Deleting or changing a row keeps the values after it.
+        table.delete_at(&mut file, 0, 1).unwrap();
+        table.replace_row(&mut file, 2, &row("100")).unwrap();
+        expected.remove(1);
+        expected[1] = "100".to_string();
+        assert_eq!(ids(&table, &file), expected);
+        assert_eq!(
+            table.find_row(&file, 0, b"42\0\0\0\0\0\0\0\0\0").unwrap(),
+            Some(12)
+        );
+
+        assert!(table.add_row(&row("abc"), &mut file).is_err());
+        assert_eq!(ids(&table, &file), expected);
+        assert!(table.set_delta_encoded(&mut file, "id").is_err());
+        assert!(table.set_delta_encoded(&mut file, "city").is_err());
+        assert!(table.set_delta_encoded(&mut file, "missing").is_err());
+    }
+
+    #[test]
+    fn test_delta_encoding_rejects_text() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("events").to_str().unwrap().to_string();
+        create_table(TableBuilder::new(&name).column("id", ColumnType::Int, 11)).unwrap();
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let rows: Vec<Row> = ["1", "two", "3"]
+            .iter()
+            .map(|id| Row {
+                data: vec![id.as_bytes().to_vec()],
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+
+        assert!(table.set_delta_encoded(&mut file, "id").is_err());
+        assert!(!table.columns[0].delta_encoded);
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert!(!table.columns[0].delta_encoded);
+        assert_eq!(ids(&table, &file), ["1", "two", "3"]);
+    }
+}
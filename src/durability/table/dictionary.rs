@@ -0,0 +1,322 @@
+use std::{collections::HashMap, io::Write, path::Path};
+
+use crate::durability::DurabilityError;
+
+use super::{ColumnDefinition, Row, Table};
+
+/// The length of a dictionary encoded column: the big endian index of its
+/// value in the dictionary, 0 for null.
+pub const DICTIONARY_INDEX_LENGTH: u64 = 2;
+/// Indexes stay below 0xFF00, a row starting with 0xFF reads as deleted.
+const MAX_DICTIONARY_ENTRIES: usize = 0xFEFF;
+
+/// The dictionary of a dictionary encoded column.
+pub fn dictionary_file(table: &str, column: &str) -> String {
+    format!("{}.{}.dict", table, column)
+}
+
+/// The dictionary files of a table, whichever columns they belong to.
+pub fn table_dictionaries(table: &str) -> Result<Vec<String>, DurabilityError> {
+    let path = Path::new(table);
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!(
+        "{}.",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+
+    let mut dictionaries = vec![];
+    for entry in std::fs::read_dir(directory).map_err(DurabilityError::IoError)? {
+        let file_name = entry.map_err(DurabilityError::IoError)?.file_name();
+        let file_name = file_name.to_string_lossy();
+        let column = file_name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".dict"));
+        if let Some(column) = column.filter(|c| !c.is_empty() && !c.contains('.')) {
+            dictionaries.push(dictionary_file(table, column));
+        }
+    }
+    dictionaries.sort();
+    Ok(dictionaries)
+}
+
+/// The distinct values of a dictionary encoded column, in the order they
+/// were first inserted. Stored in the `{table}.{column}.dict` file as a u16
+/// length followed by the value for each of them. Values are never removed,
+/// so the index a row stores keeps pointing at its value.
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    values: Vec<Vec<u8>>,
+    indexes: HashMap<Vec<u8>, u16>,
+}
+
+/// The value up to its first null byte, as stored in the dictionary.
+fn trimmed(value: &[u8]) -> &[u8] {
+    value.split(|b| *b == 0).next().unwrap_or_default()
+}
+
+impl Dictionary {
+    /// The dictionary in `path`, empty when nothing was inserted yet.
+    pub fn read(path: &str) -> Result<Self, DurabilityError> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(DurabilityError::IoError(e)),
+        };
+        let mut dictionary = Self::default();
+        let mut rest = bytes.as_slice();
+        while !rest.is_empty() {
+            let length = rest
+                .get(..2)
+                .map(|length| u16::from_be_bytes(length.try_into().unwrap()) as usize);
+            let value = length.and_then(|length| rest.get(2..2 + length));
+            let Some(value) = value else {
+                return Err(format!("Invalid dictionary {}", path).into());
+            };
+            dictionary.push(value.to_vec());
+            rest = &rest[2 + value.len()..];
+        }
+        Ok(dictionary)
+    }
+
+    fn push(&mut self, value: Vec<u8>) {
+        self.indexes
+            .insert(value.clone(), self.values.len() as u16 + 1);
+        self.values.push(value);
+    }
+
+    pub fn value_count(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The stored form of `value`, `None` when it is not in the dictionary.
+    pub fn encode(&self, value: &[u8]) -> Option<Vec<u8>> {
+        let value = trimmed(value);
+        if value.is_empty() {
+            return Some(vec![0; DICTIONARY_INDEX_LENGTH as usize]);
+        }
+        self.indexes
+            .get(value)
+            .map(|index| index.to_be_bytes().to_vec())
+    }
+
+    /// The value a stored index points at, empty for null or an index past
+    /// the end of the dictionary.
+    pub fn decode(&self, stored: &[u8]) -> Vec<u8> {
+        let index = match stored.try_into() {
+            Ok(index) => u16::from_be_bytes(index) as usize,
+            Err(_) => 0,
+        };
+        match index {
+            0 => vec![],
+            index => self.values.get(index - 1).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+impl ColumnDefinition {
+    /// The value stored as `stored`, looked up in the dictionary of a
+    /// dictionary encoded column.
+    pub fn decoded_value(&self, stored: &[u8]) -> Vec<u8> {
+        match self.dictionary_encoded {
+            true => self.dictionary.decode(stored),
+            false => stored.to_vec(),
+        }
+    }
+}
+
+impl Table {
+    /// Adds the values of the dictionary encoded columns of `rows` that are
+    /// missing from their dictionary, which is read again first to see the
+    /// values other connections added. The dictionary is appended to
+    /// outside the redo log, a value no row ends up holding is harmless.
+    pub(super) fn add_dictionary_entries(&mut self, rows: &[Row]) -> Result<(), DurabilityError> {
+        for position in 0..self.columns.len() {
+            if !self.columns[position].dictionary_encoded {
+                continue;
+            }
+            let column = self.columns[position].name_str().to_string();
+            let path = dictionary_file(self.name_str(), &column);
+            let mut dictionary = Dictionary::read(&path)?;
+
+            let mut bytes = vec![];
+            for row in rows {
+                let value = match row.data.get(position) {
+                    Some(value) => trimmed(value),
+                    None => continue,
+                };
+                // Too long values are rejected by `row_bytes`.
+                if dictionary.encode(value).is_some() || value.len() > u16::MAX as usize {
+                    continue;
+                }
+                if dictionary.value_count() == MAX_DICTIONARY_ENTRIES {
+                    return Err(format!("Dictionary of column {} is full", column).into());
+                }
+                bytes.extend((value.len() as u16).to_be_bytes());
+                bytes.extend(value);
+                dictionary.push(value.to_vec());
+            }
+
+            if !bytes.is_empty() {
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .and_then(|mut file| file.write_all(&bytes))
+                    .map_err(DurabilityError::IoError)?;
+            }
+            self.columns[position].dictionary = dictionary;
+        }
+        Ok(())
+    }
+
+    /// Loads the dictionaries of the dictionary encoded columns.
+    pub(super) fn read_dictionaries(&mut self) -> Result<(), DurabilityError> {
+        let name = self.name_str().to_string();
+        for column in self.columns.iter_mut() {
+            if column.dictionary_encoded {
+                column.dictionary = Dictionary::read(&dictionary_file(&name, column.name_str()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::{
+        table::{create_table, drop_table, writeable_table_file, ColumnType, TableBuilder},
+        Durable,
+    };
+
+    fn create_orders(dir: &std::path::Path) -> (Table, std::fs::File) {
+        let name = dir.join("orders").to_str().unwrap().to_string();
+        let mut status = ColumnDefinition::new("status".to_string(), ColumnType::Varchar, 2);
+        status.dictionary_encoded = true;
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column_definition(status),
+        )
+        .unwrap();
+        let mut file = writeable_table_file(name).unwrap();
+        let table = Table::read_from_disk(&mut file).unwrap();
+        (table, file)
+    }
+
+    fn row(id: &str, status: &str) -> Row {
+        Row {
+            data: vec![id.as_bytes().to_vec(), status.as_bytes().to_vec()],
+        }
+    }
+
+    fn rows(table: &Table, file: &std::fs::File) -> Vec<Vec<String>> {
+        (0..table.page_count())
+            .flat_map(|page| table.decode_rows_batch(&table.page_at(file, page).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_dictionary_insert() {
+        let tmp_dir = tempdir().unwrap();
+        let (mut table, mut file) = create_orders(tmp_dir.path());
+        let statuses = ["shipped", "pending", "shipped", "", "cancelled", "pending"];
+        let inserted: Vec<Row> = statuses
+            .iter()
+            .enumerate()
+            .map(|(i, status)| row(&i.to_string(), status))
+            .collect();
+        table.add_rows(&inserted, &mut file).unwrap();
+
+        // Rows only hold the index, null is not in the dictionary.
+        assert_eq!(table.row_size(), 11 + DICTIONARY_INDEX_LENGTH);
+        let path = dictionary_file(table.name_str(), "status");
+        let dictionary = Dictionary::read(&path).unwrap();
+        assert_eq!(dictionary.value_count(), 3);
+        assert_eq!(dictionary.encode(b"pending"), Some(vec![0, 2]));
+        assert_eq!(dictionary.encode(b"\0\0"), Some(vec![0, 0]));
+        assert_eq!(dictionary.encode(b"returned"), None);
+        assert_eq!(dictionary.decode(&[0, 7]), b"");
+
+        let page = table.page_at(&file, 0).unwrap();
+        let stored = table.page_rows(&page);
+        assert_eq!(stored[0].data[1], b"shipped");
+        assert_eq!(stored[3].data[1], b"");
+
+        // Read back from disk, the dictionary is loaded with the header.
+        table.add_row(&row("6", "shipped"), &mut file).unwrap();
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.columns[1].dictionary.value_count(), 3);
+        let statuses: Vec<String> = rows(&table, &file)
+            .into_iter()
+            .map(|row| row[1].clone())
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                "shipped",
+                "pending",
+                "shipped",
+                "",
+                "cancelled",
+                "pending",
+                "shipped"
+            ]
+        );
+        // The dictionary follows the column and goes with the table.
+        let mut table = table;
+        table.rename_column("status", "state", &mut file).unwrap();
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(rows(&table, &file)[4][1], "cancelled");
+        let path = dictionary_file(table.name_str(), "state");
+        assert_eq!(
+            table_dictionaries(table.name_str()).unwrap(),
+            std::slice::from_ref(&path)
+        );
+        drop_table(table.name_str()).unwrap();
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    #[test]
+    fn test_dictionary_update() {
+        let tmp_dir = tempdir().unwrap();
+        let (mut table, mut file) = create_orders(tmp_dir.path());
+        table
+            .add_rows(&[row("1", "pending"), row("2", "pending")], &mut file)
+            .unwrap();
+
+        table
+            .replace_row(&mut file, 1, &row("2", "delivered"))
+            .unwrap();
+        table.replace_row(&mut file, 0, &row("1", "")).unwrap();
+        assert_eq!(rows(&table, &file), [["1", ""], ["2", "delivered"]]);
+        assert_eq!(table.columns[1].dictionary.value_count(), 2);
+        assert_eq!(table.find_row(&file, 1, b"delivered").unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_dictionary_full_scan() {
+        let tmp_dir = tempdir().unwrap();
+        let (mut table, mut file) = create_orders(tmp_dir.path());
+        let cities = ["Oslo", "Bergen", "Trondheim"];
+        let inserted: Vec<Row> = (0..301)
+            .map(|i| row(&i.to_string(), cities[i % cities.len()]))
+            .collect();
+        table.add_rows(&inserted, &mut file).unwrap();
+        assert!(table.page_count() > 1);
+
+        let scanned = rows(&table, &file);
+        assert_eq!(scanned.len(), 301);
+        for (i, row) in scanned.iter().enumerate() {
+            assert_eq!(row, &[i.to_string(), cities[i % cities.len()].to_string()]);
+        }
+        let stats = table.analyze(&file).unwrap();
+        assert_eq!(stats[1].distinct_count, 3);
+        assert_eq!(table.load_stats().unwrap().unwrap()[1].min, b"Be");
+    }
+}
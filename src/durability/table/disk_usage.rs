@@ -0,0 +1,126 @@
+use crate::durability::{
+    index::all_table_indexes,
+    wal::{wal_archive_file, wal_file},
+    DurabilityError,
+};
+
+use super::dictionary::table_dictionaries;
+
+/// The bytes the files of a table take on disk, `SHOW DISK USAGE`. Values
+/// are only kept out of the rows in the dictionaries of dictionary encoded
+/// columns, so those make up the overflow. The log counts its archive.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiskUsage {
+    pub data_file_bytes: u64,
+    pub index_files_bytes: u64,
+    pub overflow_bytes: u64,
+    pub wal_bytes: u64,
+}
+
+impl DiskUsage {
+    pub fn total_bytes(&self) -> u64 {
+        self.data_file_bytes + self.index_files_bytes + self.overflow_bytes + self.wal_bytes
+    }
+
+    /// The sizes followed by their total.
+    pub fn values(&self) -> [u64; 5] {
+        [
+            self.data_file_bytes,
+            self.index_files_bytes,
+            self.overflow_bytes,
+            self.wal_bytes,
+            self.total_bytes(),
+        ]
+    }
+
+    pub fn add(&mut self, other: &DiskUsage) {
+        self.data_file_bytes += other.data_file_bytes;
+        self.index_files_bytes += other.index_files_bytes;
+        self.overflow_bytes += other.overflow_bytes;
+        self.wal_bytes += other.wal_bytes;
+    }
+}
+
+/// The size of the file at `path`, 0 when there is none.
+fn file_size(path: &str) -> Result<u64, DurabilityError> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(DurabilityError::IoError(e)),
+    }
+}
+
+fn files_size(paths: &[String]) -> Result<u64, DurabilityError> {
+    paths
+        .iter()
+        .try_fold(0, |total, path| Ok(total + file_size(path)?))
+}
+
+/// The disk usage of the table `table`, from the sizes of its files.
+pub fn disk_usage(table: &str) -> Result<DiskUsage, DurabilityError> {
+    Ok(DiskUsage {
+        data_file_bytes: file_size(table)?,
+        index_files_bytes: files_size(&all_table_indexes(table)?)?,
+        overflow_bytes: files_size(&table_dictionaries(table)?)?,
+        wal_bytes: file_size(&wal_file(table))? + file_size(&wal_archive_file(table))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::{
+        index::{index_file, BTreeIndex},
+        table::{create_table, writeable_table_file, ColumnType, Row, Table, TableBuilder},
+        Durable,
+    };
+
+    #[test]
+    fn test_disk_usage() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column("name", ColumnType::Varchar, 20),
+        )
+        .unwrap();
+        let usage = disk_usage(&name).unwrap();
+        assert_eq!(
+            usage.data_file_bytes,
+            std::fs::metadata(&name).unwrap().len()
+        );
+        assert_eq!(usage.index_files_bytes, 0);
+        assert_eq!(usage.overflow_bytes, 0);
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let rows: Vec<Row> = (0..100)
+            .map(|i| Row {
+                data: vec![i.to_string().into_bytes(), b"Ada".to_vec()],
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+        table.flush(&file).unwrap();
+        let path = index_file(&name, "id");
+        BTreeIndex::build("idx_id", &table, &file, &["id"], None)
+            .unwrap()
+            .write(&path)
+            .unwrap();
+
+        let size = |path: &str| std::fs::metadata(path).map_or(0, |m| m.len());
+        let usage = disk_usage(&name).unwrap();
+        assert_eq!(usage.data_file_bytes, size(&name));
+        assert_eq!(usage.index_files_bytes, size(&path));
+        assert_eq!(
+            usage.wal_bytes,
+            size(&wal_file(&name)) + size(&wal_archive_file(&name))
+        );
+        assert_eq!(
+            usage.total_bytes(),
+            usage.data_file_bytes + usage.index_files_bytes + usage.wal_bytes
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::Read;
+
+use crate::durability::DurabilityError;
+
+/// The most bytes of the table file `DEBUG DUMP TABLE` shows.
+pub const MAX_DUMP_SIZE: u64 = 64 * 1024;
+const BYTES_PER_LINE: usize = 16;
+
+/// The table file `name` as `xxd` shows it, for debugging a corrupt table: a
+/// row with the name and the size of the file, then a row per 16 bytes with
+/// the offset in hex, the bytes in hex and the bytes as text, `.` standing in
+/// for those that are not printable. Only the first `MAX_DUMP_SIZE` bytes
+/// are read.
+pub fn dump_table(name: &str) -> Result<Vec<Vec<String>>, DurabilityError> {
+    let file = std::fs::File::open(name).map_err(DurabilityError::IoError)?;
+    let size = file.metadata().map_err(DurabilityError::IoError)?.len();
+    let mut bytes = vec![];
+    file.take(MAX_DUMP_SIZE)
+        .read_to_end(&mut bytes)
+        .map_err(DurabilityError::IoError)?;
+
+    let mut rows = vec![vec![name.to_string(), format!("{} bytes", size)]];
+    for (line, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let text: String = chunk
+            .iter()
+            .map(|b| match b.is_ascii_graphic() || *b == b' ' {
+                true => *b as char,
+                false => '.',
+            })
+            .collect();
+        rows.push(vec![
+            format!("{:08x}", line * BYTES_PER_LINE),
+            hex.join(" "),
+            text,
+        ]);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::table::{create_table, ColumnType, TableBuilder};
+
+    #[test]
+    fn test_dump_table() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column("name", ColumnType::Varchar, 16),
+        )
+        .unwrap();
+
+        let rows = dump_table(&name).unwrap();
+        let size = std::fs::metadata(&name).unwrap().len();
+        assert_eq!(rows[0], [name.clone(), format!("{} bytes", size)]);
+        assert_eq!(rows.len() as u64, 1 + size.div_ceil(16));
+
+        // The table name starts the header.
+        assert_eq!(rows[1][0], "00000000");
+        assert_eq!(rows[1][2], name[..16]);
+        let name_hex: Vec<String> = name.bytes().take(3).map(|b| format!("{:02x}", b)).collect();
+        assert!(rows[1][1].starts_with(&name_hex.join(" ")));
+        // The column count, no primary key, a base table, no compression and
+        // the name of the first column.
+        assert_eq!(
+            rows[5],
+            [
+                "00000040",
+                "02 00 00 00 ff 00 00 69 64 00 00 00 00 00 00 00",
+                ".......id......."
+            ]
+        );
+
+        assert!(dump_table(&format!("{}_missing", name)).is_err());
+    }
+
+    #[test]
+    fn test_dump_stops_at_max_size() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("large");
+        std::fs::write(&path, vec![b'a'; MAX_DUMP_SIZE as usize + 10]).unwrap();
+        let rows = dump_table(path.to_str().unwrap()).unwrap();
+        assert_eq!(rows[0][1], format!("{} bytes", MAX_DUMP_SIZE + 10));
+        assert_eq!(rows.len(), 1 + MAX_DUMP_SIZE as usize / 16);
+        assert_eq!(rows.last().unwrap()[0], "0000fff0");
+        assert_eq!(rows.last().unwrap()[2], "aaaaaaaaaaaaaaaa");
+    }
+}
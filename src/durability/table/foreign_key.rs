@@ -0,0 +1,114 @@
+use std::io::Write;
+
+use super::{table_exists, writeable_table_file, DurabilityError, Durable, Table};
+
+const NAME_SIZE: usize = 64;
+const RECORD_SIZE: usize = NAME_SIZE * 4;
+
+/// A `FOREIGN KEY (column) REFERENCES referenced_table (referenced_column)`
+/// constraint declared on `table`. The same record is stored in the `.fk` file
+/// of both tables so inserts can find their outgoing references and deletes
+/// can find the tables referencing them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKey {
+    pub table: String,
+    pub column: String,
+    pub referenced_table: String,
+    pub referenced_column: String,
+}
+
+impl ForeignKey {
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for name in [
+            &self.table,
+            &self.column,
+            &self.referenced_table,
+            &self.referenced_column,
+        ] {
+            let mut name_buffer = name.as_bytes().to_vec();
+            name_buffer.resize(NAME_SIZE, 0);
+            bytes.extend(name_buffer.iter());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let names: Vec<String> = bytes
+            .chunks_exact(NAME_SIZE)
+            .map(|name| {
+                let name = name.split(|b| *b == 0).next().unwrap_or_default();
+                String::from_utf8_lossy(name).to_string()
+            })
+            .collect();
+        ForeignKey {
+            table: names[0].clone(),
+            column: names[1].clone(),
+            referenced_table: names[2].clone(),
+            referenced_column: names[3].clone(),
+        }
+    }
+}
+
+pub fn foreign_key_file(table: &str) -> String {
+    format!("{}.fk", table)
+}
+
+pub fn read_foreign_keys(table: &str) -> Result<Vec<ForeignKey>, DurabilityError> {
+    let path = foreign_key_file(table);
+    if !table_exists(&path) {
+        return Ok(vec![]);
+    }
+
+    let data = std::fs::read(path).map_err(DurabilityError::IoError)?;
+    Ok(data
+        .chunks_exact(RECORD_SIZE)
+        .map(ForeignKey::from_bytes)
+        .collect())
+}
+
+pub fn write_foreign_key(foreign_key: &ForeignKey) -> Result<(), DurabilityError> {
+    let mut tables = vec![&foreign_key.table];
+    if foreign_key.referenced_table != foreign_key.table {
+        tables.push(&foreign_key.referenced_table);
+    }
+
+    for table in tables {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(foreign_key_file(table))
+            .map_err(DurabilityError::IoError)?;
+        file.write_all(&foreign_key.bytes())
+            .map_err(DurabilityError::IoError)?;
+    }
+
+    Ok(())
+}
+
+/// Scans `table` for a row whose `column` holds `value`. There are no indexes
+/// yet so this is always a full table scan.
+pub fn value_exists(table: &str, column: &str, value: &[u8]) -> Result<bool, DurabilityError> {
+    let mut file = writeable_table_file(table.to_string())?;
+    let table = Table::read_from_disk(&mut file)?;
+    let position = table.columns.iter().position(|c| c.name_str() == column);
+    let position = match position {
+        Some(position) => position,
+        None => {
+            return Err(DurabilityError::DbError(format!(
+                "Column {} does not exist",
+                column
+            )))
+        }
+    };
+
+    let length = table.columns[position].length as usize;
+    let value_length = value.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    if value_length > length {
+        return Ok(false);
+    }
+    let mut value = value[..value_length].to_vec();
+    value.resize(length, 0);
+
+    table.column_contains(&file, position, &value)
+}
@@ -1,59 +1,331 @@
-use super::{DurabilityError, Durable};
+use super::{
+    index::{all_table_indexes, rebuild_indexes},
+    wal::{checkpoint, replay_wal, truncate_archive, wal_archive_file, wal_file},
+    DurabilityError, Durable,
+};
 
+mod builder;
+mod checksum;
 mod column_definition;
 mod column_type;
+mod compression;
+mod decimal;
+mod delta;
+mod dictionary;
+mod disk_usage;
+mod dump;
+mod foreign_key;
+mod scanner;
+mod stats;
+#[allow(clippy::module_inception)]
 mod table;
+mod temp;
+mod timestamp;
+mod vacuum;
 
+pub use builder::TableBuilder;
 pub use column_definition::ColumnDefinition;
 pub use column_type::ColumnType;
-pub use table::{Page, Row, Table};
+pub use decimal::{compare_decimal, decode_decimal, MAX_PRECISION};
+pub use dictionary::table_dictionaries;
+pub use disk_usage::{disk_usage, DiskUsage};
+pub use dump::dump_table;
+pub use foreign_key::{foreign_key_file, write_foreign_key, ForeignKey};
+pub use scanner::{pages_read, ScanHint, TableScanner};
+pub use stats::{stats_file, ColumnStats};
+pub use table::{
+    set_max_page_size, Page, Row, Table, Upsert, DEFAULT_PAGE_SIZE, MATERIALIZED_VIEW,
+};
+pub use temp::{create_temp_table, drop_temp_table, drop_temp_tables, temp_table_file};
+pub use timestamp::{
+    current_timestamp, decode_timestamp, format_timestamp, parse_timestamp, CURRENT_TIMESTAMP,
+};
+pub use vacuum::{database_tables, is_database_file};
 
 pub fn writeable_table_file(name: String) -> Result<std::fs::File, DurabilityError> {
     let file = std::fs::OpenOptions::new()
         .read(true)
         .write(true)
         .open(name)
-        .map_err(|e| DurabilityError::IoError(e))?;
+        .map_err(DurabilityError::IoError)?;
 
     Ok(file)
 }
 
-pub fn create_table(name: String, columns: Vec<ColumnDefinition>) -> Result<(), String> {
+pub fn create_table(table: TableBuilder) -> Result<(), String> {
+    create_table_from(table.build()?)
+}
+
+/// Writes `table`, built but not yet on disk, as a new empty table, with the
+/// foreign keys its columns reference.
+pub fn create_table_from(mut table: Table) -> Result<(), String> {
+    let name = table.name_str().to_string();
     if table_exists(&name) {
         return Err(format!("Table {} already exists", name));
     }
 
+    let mut foreign_keys = vec![];
+    for column in table.columns.iter() {
+        let (referenced_table, referenced_column) = match &column.references {
+            Some(references) => references,
+            None => continue,
+        };
+        if !table_exists(referenced_table) {
+            return Err(format!("Table {} does not exist", referenced_table));
+        }
+        foreign_keys.push(ForeignKey {
+            table: name.clone(),
+            column: column.name_str().to_string(),
+            referenced_table: referenced_table.clone(),
+            referenced_column: referenced_column.clone(),
+        });
+    }
+
     let mut file = std::fs::OpenOptions::new()
         .write(true)
         .read(true)
         .create(true)
+        .truncate(true)
         .open(&name)
         .unwrap();
 
-    let mut table = Table::new(name, columns);
     if let Err(e) = table.write_to_disk(&mut file) {
         return Err(format!("Error creating table: {:?}", e));
     }
     if table.add_page(&mut file).is_err() {
         return Err("Error adding page to table".to_string());
     }
+    if let Err(e) = table.analyze(&file) {
+        return Err(format!("Error analyzing table: {:?}", e));
+    }
+
+    for foreign_key in foreign_keys.iter() {
+        if let Err(e) = write_foreign_key(foreign_key) {
+            return Err(format!("Error creating foreign key: {:?}", e));
+        }
+    }
 
     Ok(())
 }
 
+/// Writes `table` like `create_table_from` with `rows` in it, for tables
+/// holding the result of a query.
+pub fn create_table_with_rows(table: Table, rows: &[Row]) -> Result<(), String> {
+    let name = table.name_str().to_string();
+    create_table_from(table)?;
+    let mut file = writeable_table_file(name).map_err(|e| format!("{:?}", e))?;
+    let mut table = Table::read_from_disk(&mut file)?;
+    for batch in rows.chunks(1000) {
+        table
+            .add_rows(batch, &mut file)
+            .map_err(|e| format!("Error writing rows: {:?}", e))?;
+    }
+    Ok(())
+}
+
+/// The text of a fixed size name buffer up to its first null byte, all of it
+/// when the name fills the buffer. A name cut short in the middle of a
+/// character ends before that character.
+fn name_str(buffer: &[u8]) -> &str {
+    let length = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+    match std::str::from_utf8(&buffer[..length]) {
+        Ok(name) => name,
+        Err(e) => std::str::from_utf8(&buffer[..e.valid_up_to()]).unwrap_or_default(),
+    }
+}
+
 pub fn table_exists(name: &str) -> bool {
     std::path::Path::new(name).exists()
 }
 
+/// Removes the table and every file kept alongside it.
+pub fn drop_table(name: &str) -> Result<(), String> {
+    if !table_exists(name) {
+        return Err(format!("Table {} does not exist", name));
+    }
+
+    for path in files_of_table(name) {
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Error removing {}: {:?}", path, e)),
+        }
+    }
+    Ok(())
+}
+
+/// The file storing the result of the materialized view `name`.
+pub fn materialized_view_file(name: &str) -> String {
+    format!("{}.mv", name)
+}
+
+/// Stores `rows` as the result of the materialized view `name`, replacing
+/// the result of a previous refresh. The file is a table marked as a
+/// materialized view, with `columns` as its schema.
+pub fn write_materialized_view(
+    name: &str,
+    columns: Vec<ColumnDefinition>,
+    rows: &[Row],
+) -> Result<(), String> {
+    let path = materialized_view_file(name);
+    let mut table = TableBuilder::new(&path).columns(columns).build()?;
+    if table_exists(&path) {
+        drop_table(&path)?;
+    }
+    table.table_type = MATERIALIZED_VIEW;
+    create_table_with_rows(table, rows)
+}
+
+pub fn rename_table(
+    from: &str,
+    to: &str,
+    table: &mut Table,
+    file: &mut std::fs::File,
+) -> Result<(), String> {
+    if to.is_empty() || to.len() > 63 {
+        return Err(format!(
+            "Invalid table name {}, must be between 1 and 63 bytes",
+            to
+        ));
+    }
+
+    if table_exists(to) {
+        return Err(format!("Table {} already exists", to));
+    }
+
+    if let Err(e) = std::fs::rename(from, to) {
+        return Err(format!("Error renaming table file: {:?}", e));
+    }
+    table.rename(to, file)?;
+
+    // Records not checkpointed yet are replayed when the table is opened.
+    for (from_log, to_log) in [
+        (wal_file(from), wal_file(to)),
+        (wal_archive_file(from), wal_archive_file(to)),
+    ] {
+        if !table_exists(&from_log) {
+            continue;
+        }
+        if let Err(e) = std::fs::rename(&from_log, to_log) {
+            return Err(format!("Error renaming redo log {}: {:?}", from_log, e));
+        }
+    }
+
+    let indexes =
+        all_table_indexes(from).map_err(|e| format!("Error listing index files: {:?}", e))?;
+    for index_file in indexes {
+        let renamed = format!("{}{}", to, &index_file[from.len()..]);
+        if let Err(e) = std::fs::rename(&index_file, renamed) {
+            return Err(format!("Error renaming index file {}: {:?}", index_file, e));
+        }
+    }
+    let dictionaries =
+        table_dictionaries(from).map_err(|e| format!("Error listing dictionary files: {:?}", e))?;
+    for dictionary_file in dictionaries {
+        let renamed = format!("{}{}", to, &dictionary_file[from.len()..]);
+        if let Err(e) = std::fs::rename(&dictionary_file, renamed) {
+            return Err(format!(
+                "Error renaming dictionary file {}: {:?}",
+                dictionary_file, e
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Rebuilds the table as it was when the transaction with sequence `lsn`
+/// committed, by emptying it and replaying the archived redo log up to it.
+/// The log is checkpointed first and the transactions after `lsn` are
+/// dropped from the archive. Indexes are rebuilt. The caller keeps other
+/// writers out. Returns the number of records replayed.
+pub fn restore_to_lsn(
+    table: &mut Table,
+    file: &mut std::fs::File,
+    lsn: u64,
+) -> Result<usize, DurabilityError> {
+    let name = table.name_str().to_string();
+    let (_, last_lsn) = checkpoint(&name, file)?;
+    if lsn > last_lsn {
+        return Err(DurabilityError::DbError(format!(
+            "LSN {} is past the end of the log at {}",
+            lsn, last_lsn
+        )));
+    }
+
+    file.set_len(table.header_size())
+        .map_err(DurabilityError::IoError)?;
+    table.row_count = 0;
+    table.write_row_count_to_disk(file)?;
+    table.add_page(file)?;
+    let replayed = replay_wal(file, &wal_archive_file(&name), Some(lsn))?;
+    file.sync_all().map_err(DurabilityError::IoError)?;
+    truncate_archive(&name, lsn)?;
+    *table = Table::read_from_disk(file)?;
+
+    rebuild_indexes(table, file)?;
+    Ok(replayed)
+}
+
+/// The files holding a table's rows, foreign keys, redo log, stats, indexes
+/// and dictionaries. Only the table file, the indexes and the dictionaries are
+/// guaranteed to exist.
+pub fn table_files(table: &Table) -> Vec<String> {
+    files_of_table(table.name_str())
+}
+
+fn files_of_table(name: &str) -> Vec<String> {
+    let mut files = vec![
+        name.to_string(),
+        foreign_key_file(name),
+        wal_file(name),
+        wal_archive_file(name),
+        stats_file(name),
+    ];
+    files.extend(all_table_indexes(name).unwrap_or_default());
+    files.extend(table_dictionaries(name).unwrap_or_default());
+    files
+}
+
 #[cfg(test)]
 mod tests {
-    use std::env;
+    use std::{env, os::unix::fs::FileExt};
 
     use table::Row;
-    use tempfile::{env::temp_dir, tempdir};
+    use tempfile::tempdir;
+
+    use crate::durability::hash_index::{hash_index_file, HashIndex};
+    use crate::durability::index::{index_file, BTreeIndex};
+    use crate::query::predicate::{Operator, Predicate};
 
     use super::*;
 
+    #[test]
+    fn test_name_str() {
+        let short = ColumnDefinition::new("id".to_string(), ColumnType::Int, 11);
+        assert_eq!(short.name_str(), "id");
+
+        // 63 bytes keep the null terminator, 64 fill the buffer without one.
+        let name = "c".repeat(63);
+        let column = ColumnDefinition::new(name.clone(), ColumnType::Int, 11);
+        assert_eq!(column.name_str(), name);
+        let name = "c".repeat(64);
+        let column = ColumnDefinition::new(name.clone(), ColumnType::Int, 11);
+        assert_eq!(column.name_str(), name);
+
+        let name = "t".repeat(63);
+        let table = TableBuilder::new(&name)
+            .column_definition(short)
+            .build()
+            .unwrap();
+        assert_eq!(table.name_str(), name);
+
+        // A multi-byte character cut at the end of the buffer is dropped.
+        let mut buffer = [b'a'; 64];
+        buffer[63] = 0xc3;
+        assert_eq!(super::name_str(&buffer), "a".repeat(63));
+    }
+
     #[test]
     fn test_read_write_on_disk() {
         let tmp_dir = tempdir();
@@ -66,16 +338,16 @@ mod tests {
             .write(true)
             .read(true)
             .create(true)
-            .open(temp_file_path)
+            .truncate(true)
+            .open(&temp_file_path)
             .unwrap();
 
-        let mut table = Table::new(
-            "test_table".to_string(),
-            vec![
-                ColumnDefinition::new("id".to_string(), ColumnType::Int, 11),
-                ColumnDefinition::new("account_id".to_string(), ColumnType::Int, 11),
-            ],
-        );
+        // The table name is also the path its log is kept next to.
+        let mut table = TableBuilder::new(temp_file_path.to_str().unwrap())
+            .column("id", ColumnType::Int, 11)
+            .column("account_id", ColumnType::Int, 11)
+            .build()
+            .unwrap();
 
         table.write_to_disk(&mut file).unwrap();
         if let Err(e) = table.add_page(&mut file) {
@@ -95,7 +367,7 @@ mod tests {
         //add 3 rows
         for _ in 0..3 {
             let row_added = table.add_row(
-                Row {
+                &Row {
                     data: vec!["123".as_bytes().to_vec(), "1".as_bytes().to_vec()],
                 },
                 &mut file,
@@ -115,4 +387,1698 @@ mod tests {
         assert!(table.row_count == 3);
         tmp_dir.close().unwrap();
     }
+
+    #[test]
+    fn test_row_count_round_trip() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .read(true)
+            .create(true)
+            .truncate(true)
+            .open(&name)
+            .unwrap();
+        let mut table = TableBuilder::new(&name)
+            .column("id", ColumnType::Int, 11)
+            .build()
+            .unwrap();
+        // Past what 32 bits hold.
+        table.row_count = u32::MAX as u64 + 7;
+        table.write_to_disk(&mut file).unwrap();
+
+        let read = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(read.row_count, u32::MAX as u64 + 7);
+        assert_eq!(read.header_size(), table.header_size());
+        // Stored as 8 bytes between the columns and the stats.
+        let mut stored = [0; 8];
+        file.read_exact_at(&mut stored, table.row_count_offset())
+            .unwrap();
+        assert_eq!(u64::from_ne_bytes(stored), table.row_count);
+        assert_eq!(table.table_stats_offset(), table.row_count_offset() + 8);
+        assert_eq!(
+            table.header_size(),
+            table.table_stats_offset() + stats::TABLE_STATS_SIZE
+        );
+    }
+
+    #[test]
+    fn test_default_values() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        let mut email = ColumnDefinition::new("email".to_string(), ColumnType::Varchar, 32);
+        email.default_value = Some("unknown".as_bytes().to_vec());
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column_definition(email),
+        )
+        .unwrap();
+
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.columns[0].default_value, None);
+        assert_eq!(
+            table.columns[1].default_value,
+            Some("unknown".as_bytes().to_vec())
+        );
+
+        let row = table
+            .row_for_columns(&["id".to_string()], &["5".as_bytes().to_vec()])
+            .unwrap();
+        table.add_row(&row, &mut file).unwrap();
+        let row = table
+            .row_for_columns(
+                &["email".to_string(), "id".to_string()],
+                &["a@b.c".as_bytes().to_vec(), "6".as_bytes().to_vec()],
+            )
+            .unwrap();
+        table.add_row(&row, &mut file).unwrap();
+
+        let table = Table::read_from_disk(&mut file).unwrap();
+        let page = table.page_at(&file, 0).unwrap();
+        let rows = table.page_rows(&page);
+        assert_eq!(&rows[0].data[0][..2], b"5\0");
+        assert_eq!(&rows[0].data[1][..8], b"unknown\0");
+        assert_eq!(&rows[1].data[0][..2], b"6\0");
+        assert_eq!(&rows[1].data[1][..6], b"a@b.c\0");
+    }
+
+    #[test]
+    fn test_missing_column_without_default() {
+        let table = TableBuilder::new("users")
+            .column("id", ColumnType::Int, 11)
+            .column("email", ColumnType::Varchar, 32)
+            .build()
+            .unwrap();
+
+        assert!(table
+            .row_for_columns(&["id".to_string()], &["5".as_bytes().to_vec()])
+            .is_err());
+        assert!(table
+            .row_for_columns(&["missing".to_string()], &["5".as_bytes().to_vec()])
+            .is_err());
+    }
+
+    #[test]
+    fn test_unique_constraint() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        let mut email = ColumnDefinition::new("email".to_string(), ColumnType::Varchar, 32);
+        email.unique = true;
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column_definition(email),
+        )
+        .unwrap();
+
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        assert!(!table.columns[0].unique);
+        assert!(table.columns[1].unique);
+
+        for i in 0..5 {
+            let row = Row {
+                data: vec![
+                    "1".as_bytes().to_vec(),
+                    format!("user{}@example.com", i).as_bytes().to_vec(),
+                ],
+            };
+            table.add_row(&row, &mut file).unwrap();
+        }
+
+        let row = Row {
+            data: vec![
+                "2".as_bytes().to_vec(),
+                "user3@example.com".as_bytes().to_vec(),
+            ],
+        };
+        match table.add_row(&row, &mut file) {
+            Err(DurabilityError::ConstraintViolation(_)) => {}
+            result => panic!("Expected constraint violation, got {:?}", result),
+        }
+
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.row_count, 5);
+    }
+
+    #[test]
+    fn test_primary_key_constraint() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        let mut id = ColumnDefinition::new("id".to_string(), ColumnType::Int, 11);
+        id.primary_key = true;
+        create_table(
+            TableBuilder::new(&name)
+                .column("account_id", ColumnType::Int, 11)
+                .column_definition(id),
+        )
+        .unwrap();
+
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.primary_key_column(), Some(1));
+        assert!(table.columns[1].primary_key);
+
+        let row = Row {
+            data: vec!["1".as_bytes().to_vec(), "7".as_bytes().to_vec()],
+        };
+        table.add_row(&row, &mut file).unwrap();
+
+        match table.add_row(&row, &mut file) {
+            Err(DurabilityError::ConstraintViolation(_)) => {}
+            result => panic!("Expected constraint violation, got {:?}", result),
+        }
+
+        let row = Row {
+            data: vec!["1".as_bytes().to_vec(), vec![]],
+        };
+        match table.add_row(&row, &mut file) {
+            Err(DurabilityError::ConstraintViolation(_)) => {}
+            result => panic!("Expected constraint violation, got {:?}", result),
+        }
+
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.row_count, 1);
+    }
+
+    #[test]
+    fn test_upsert_row() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        let table = TableBuilder::new(&name)
+            .column("id", ColumnType::Int, 11)
+            .column("city", ColumnType::Varchar, 16)
+            .primary_key("id");
+        create_table(table).unwrap();
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let row = |id: &str, city: &str| Row {
+            data: vec![id.as_bytes().to_vec(), city.as_bytes().to_vec()],
+        };
+        for (id, city) in [("1", "Oslo"), ("2", "Bergen"), ("3", "Oslo")] {
+            assert_eq!(
+                table.upsert_row(&row(id, city), &mut file).unwrap(),
+                Upsert::Inserted
+            );
+        }
+        let index = BTreeIndex::build("idx_city", &table, &file, &["city"], None).unwrap();
+        index.write(&index_file(&name, "city")).unwrap();
+
+        assert_eq!(
+            table.upsert_row(&row("2", "Oslo"), &mut file).unwrap(),
+            Upsert::Replaced
+        );
+        assert_eq!(
+            table.upsert_row(&row("4", "Bergen"), &mut file).unwrap(),
+            Upsert::Inserted
+        );
+
+        let table = Table::read_from_disk(&mut file).unwrap();
+        let page = table.page_at(&file, 0).unwrap();
+        let cities: Vec<String> = table
+            .page_rows(&page)
+            .iter()
+            .map(|row| {
+                String::from_utf8_lossy(&row.data[1])
+                    .trim_end_matches('\0')
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(cities, ["Oslo", "Oslo", "Oslo", "Bergen"]);
+
+        let index = BTreeIndex::read(&index_file(&name, "city")).unwrap();
+        assert_eq!(index.lookup(b"Oslo"), [0, 1, 2]);
+        assert!(index.lookup(b"Bergen").is_empty());
+    }
+
+    #[test]
+    fn test_compressed_table() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        let table = TableBuilder::new(&name)
+            .column("id", ColumnType::Int, 11)
+            .column("email", ColumnType::Varchar, 255)
+            .primary_key("id")
+            .compression(true);
+        create_table(table).unwrap();
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        assert!(table.compression_enabled);
+        let row = |i: u64| Row {
+            data: vec![
+                i.to_string().into_bytes(),
+                format!("user{}@mail", i).into_bytes(),
+            ],
+        };
+        let rows: Vec<Row> = (0..300).map(row).collect();
+        table.add_rows(&rows, &mut file).unwrap();
+        table.add_row(&row(300), &mut file).unwrap();
+        assert!(table.page_count() > 1);
+        assert!(table.add_row(&row(7), &mut file).is_err());
+
+        table.replace_row(&mut file, 260, &row(1000)).unwrap();
+        let rows_per_page = table.page_size() / table.row_size();
+        table.delete_at(&mut file, 1, 270 - rows_per_page).unwrap();
+
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let ids: Vec<String> = (0..table.page_count())
+            .flat_map(|page| table.decode_rows_batch(&table.page_at(&file, page).unwrap()))
+            .map(|row| row[0].clone())
+            .collect();
+        let mut expected: Vec<String> = (0..=300).map(|i: u64| i.to_string()).collect();
+        expected[260] = "1000".to_string();
+        expected.remove(270);
+        assert_eq!(ids, expected);
+        assert_eq!(table.row_at(&file, 299).unwrap(), {
+            let mut row = row(299);
+            row.data[1].resize(255, 0);
+            row.data[0].resize(11, 0);
+            row
+        });
+        assert_eq!(
+            table.upsert_row(&row(1000), &mut file).unwrap(),
+            Upsert::Replaced
+        );
+
+        // The pages are stored in far fewer bytes than the rows take.
+        let slot_header = |page: u64| {
+            let mut header = [0; 5];
+            file.read_exact_at(&mut header, table.page_offset(page))
+                .unwrap();
+            header
+        };
+        for page in 0..table.page_count() {
+            let header = slot_header(page);
+            let size = u32::from_ne_bytes(header[1..].try_into().unwrap());
+            assert_eq!(header[0], 1);
+            assert!((size as u64) < table.page_size() / 10);
+        }
+    }
+
+    #[test]
+    fn test_row_at() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        let table = TableBuilder::new(&name)
+            .column("id", ColumnType::Int, 11)
+            .column("email", ColumnType::Varchar, 16);
+        create_table(table).unwrap();
+
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let rows: Vec<Row> = (0..100)
+            .map(|i| Row {
+                data: vec![
+                    i.to_string().into_bytes(),
+                    format!("user{}@mail", i).into_bytes(),
+                ],
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+        assert!(table.page_count() > 1);
+
+        let text = |value: &[u8]| {
+            String::from_utf8_lossy(value.split(|b| *b == 0).next().unwrap()).to_string()
+        };
+        for row_index in [0, 49, 99] {
+            let row = table.row_at(&file, row_index).unwrap();
+            assert_eq!(text(&row.data[0]), row_index.to_string());
+            assert_eq!(text(&row.data[1]), format!("user{}@mail", row_index));
+
+            let mut row_bytes = vec![0; table.row_size() as usize];
+            file.read_exact_at(&mut row_bytes, table.row_offset_bytes(row_index))
+                .unwrap();
+            assert_eq!(row_bytes, row.data.concat());
+        }
+        assert!(table.row_at(&file, 100).is_err());
+
+        let rows_per_page = table.page_size() / table.row_size();
+        table
+            .delete_at(&mut file, 49 / rows_per_page, 49 % rows_per_page)
+            .unwrap();
+        assert!(table.row_at(&file, 49).is_err());
+        assert!(table.row_at(&file, 50).is_ok());
+    }
+
+    #[test]
+    fn test_timestamp_column() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("events").to_str().unwrap().to_string();
+        let table = TableBuilder::new(&name)
+            .column("id", ColumnType::Int, 11)
+            .column("created_at", ColumnType::Timestamp, 8);
+        create_table(table).unwrap();
+
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.columns[1].column_type, ColumnType::Timestamp);
+        let columns = vec!["id".to_string(), "created_at".to_string()];
+        let rows: Vec<Row> = [
+            "'2023-12-31T23:59:59.999999Z'",
+            "'2024-01-15T10:30:00.123456Z'",
+            "CURRENT_TIMESTAMP",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(id, value)| {
+            let values = vec![id.to_string().into_bytes(), value.as_bytes().to_vec()];
+            table.row_for_columns(&columns, &values).unwrap()
+        })
+        .collect();
+        assert!(table
+            .row_for_columns(&columns, &[b"3".to_vec(), b"'2024-01-15'x".to_vec()])
+            .is_err());
+        table.add_rows(&rows, &mut file).unwrap();
+
+        let created_at = |row_index| {
+            let row = table.row_at(&file, row_index).unwrap();
+            assert_eq!(row.data[1].len(), 8);
+            decode_timestamp(&row.data[1]).unwrap()
+        };
+        assert_eq!(
+            format_timestamp(created_at(1)),
+            "2024-01-15T10:30:00.123456Z"
+        );
+        assert!(created_at(2) > created_at(1));
+
+        let predicate = Predicate::parse("created_at > '2024-01-01T00:00:00Z'").unwrap();
+        let matching: Vec<u64> = (0..3)
+            .filter(|row_index| {
+                let row = table.row_at(&file, *row_index).unwrap();
+                predicate.matches(&row, &table.columns).unwrap()
+            })
+            .collect();
+        assert_eq!(matching, vec![1, 2]);
+        assert!(table
+            .create_statement()
+            .unwrap()
+            .ends_with("(id INT 11, created_at TIMESTAMP)"));
+    }
+
+    #[test]
+    fn test_timestamp_first_column_is_not_a_tombstone() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("events").to_str().unwrap().to_string();
+        let table = TableBuilder::new(&name)
+            .column("created_at", ColumnType::Timestamp, 8)
+            .column("id", ColumnType::Int, 11);
+        create_table(table).unwrap();
+
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let columns = vec!["created_at".to_string(), "id".to_string()];
+        let created_at = [
+            "'2024-01-15T10:30:00.000255Z'",
+            "'1970-01-01T00:00:00.000255Z'",
+            "'9999-12-31T23:59:59.999999Z'",
+        ];
+        let rows: Vec<Row> = created_at
+            .iter()
+            .enumerate()
+            .map(|(id, value)| {
+                let values = vec![value.as_bytes().to_vec(), id.to_string().into_bytes()];
+                table.row_for_columns(&columns, &values).unwrap()
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+
+        let page = table.page_at(&file, 0).unwrap();
+        assert_eq!(table.decode_rows_batch(&page).len(), 3);
+        let column_type = &table.columns[0].column_type;
+        let stored: Vec<String> = table
+            .page_entries(&page)
+            .iter()
+            .map(|(_, row)| format!("'{}'", column_type.format(&row.data[0])))
+            .collect();
+        assert_eq!(stored, created_at);
+    }
+
+    #[test]
+    fn test_decimal_column() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("prices").to_str().unwrap().to_string();
+        let mut price = ColumnDefinition::new("price".to_string(), ColumnType::Decimal(10, 2), 8);
+        price.default_value = ColumnType::Decimal(10, 2).encode(b"9.99");
+        let table = TableBuilder::new(&name)
+            .column("id", ColumnType::Int, 11)
+            .column_definition(price);
+        create_table(table).unwrap();
+
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.columns[1].column_type, ColumnType::Decimal(10, 2));
+        assert_eq!(table.columns[1].length, 8);
+        let header_length = table.columns[1].bytes()[68..76].try_into().unwrap();
+        assert_eq!(u64::from_ne_bytes(header_length), (10 << 16) | 2);
+
+        let columns = vec!["id".to_string(), "price".to_string()];
+        let mut rows: Vec<Row> = ["123.45", "'0.1'", "-5"]
+            .iter()
+            .enumerate()
+            .map(|(id, value)| {
+                let values = vec![id.to_string().into_bytes(), value.as_bytes().to_vec()];
+                table.row_for_columns(&columns, &values).unwrap()
+            })
+            .collect();
+        rows.push(
+            table
+                .row_for_columns(&columns[..1], &[b"3".to_vec()])
+                .unwrap(),
+        );
+        assert!(table
+            .row_for_columns(&columns, &[b"4".to_vec(), b"1.234".to_vec()])
+            .is_err());
+        table.add_rows(&rows, &mut file).unwrap();
+
+        let price = |row_index| table.row_at(&file, row_index).unwrap().data[1].clone();
+        assert_eq!(decode_decimal(&price(0)), Some(12345));
+        let column_type = &table.columns[1].column_type;
+        let prices: Vec<String> = (0..4).map(|i| column_type.format(&price(i))).collect();
+        assert_eq!(prices, vec!["123.45", "0.10", "-5.00", "9.99"]);
+
+        let predicate = Predicate::parse("price > 9.989").unwrap();
+        let matching: Vec<u64> = (0..4)
+            .filter(|row_index| {
+                let row = table.row_at(&file, *row_index).unwrap();
+                predicate.matches(&row, &table.columns).unwrap()
+            })
+            .collect();
+        assert_eq!(matching, vec![0, 3]);
+        assert!(table
+            .create_statement()
+            .unwrap()
+            .ends_with("(id INT 11, price DECIMAL(10,2) DEFAULT '9.99')"));
+    }
+
+    #[test]
+    fn test_decimal_first_column_is_not_a_tombstone() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("prices").to_str().unwrap().to_string();
+        let table = TableBuilder::new(&name)
+            .column("price", ColumnType::Decimal(10, 2), 8)
+            .column("id", ColumnType::Int, 11);
+        create_table(table).unwrap();
+
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let columns = vec!["price".to_string(), "id".to_string()];
+        let prices = ["2.55", "-1", "-0.01", "0"];
+        let rows: Vec<Row> = prices
+            .iter()
+            .enumerate()
+            .map(|(id, value)| {
+                let values = vec![value.as_bytes().to_vec(), id.to_string().into_bytes()];
+                table.row_for_columns(&columns, &values).unwrap()
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+
+        let page = table.page_at(&file, 0).unwrap();
+        assert_eq!(table.decode_rows_batch(&page).len(), 4);
+        let column_type = &table.columns[0].column_type;
+        let stored: Vec<String> = table
+            .page_entries(&page)
+            .iter()
+            .map(|(_, row)| column_type.format(&row.data[0]))
+            .collect();
+        assert_eq!(stored, ["2.55", "-1.00", "-0.01", "0.00"]);
+    }
+
+    #[test]
+    fn test_upsert_row_without_primary_key() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(TableBuilder::new(&name).column("id", ColumnType::Int, 11)).unwrap();
+
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let row = Row {
+            data: vec![b"1".to_vec()],
+        };
+        assert!(matches!(
+            table.upsert_row(&row, &mut file),
+            Err(DurabilityError::DbError(_))
+        ));
+        assert_eq!(table.row_count, 0);
+    }
+
+    #[test]
+    fn test_table_without_primary_key() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(TableBuilder::new(&name).column("id", ColumnType::Int, 11)).unwrap();
+
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.primary_key_column(), None);
+
+        let row = Row { data: vec![vec![]] };
+        table.add_row(&row, &mut file).unwrap();
+        table.add_row(&row, &mut file).unwrap();
+    }
+
+    fn create_users_and_orders(tmp_dir: &std::path::Path) -> (String, String) {
+        let users = tmp_dir.join("users").to_str().unwrap().to_string();
+        let orders = tmp_dir.join("orders").to_str().unwrap().to_string();
+        create_table(TableBuilder::new(&users).column("id", ColumnType::Int, 11)).unwrap();
+
+        let mut user_id = ColumnDefinition::new("user_id".to_string(), ColumnType::Int, 11);
+        user_id.references = Some((users.clone(), "id".to_string()));
+        create_table(
+            TableBuilder::new(&orders)
+                .column("id", ColumnType::Int, 11)
+                .column_definition(user_id),
+        )
+        .unwrap();
+
+        (users, orders)
+    }
+
+    #[test]
+    fn test_foreign_key_insert_check() {
+        let tmp_dir = tempdir().unwrap();
+        let (users, orders) = create_users_and_orders(tmp_dir.path());
+
+        let mut users_file = writeable_table_file(users.clone()).unwrap();
+        let mut users_table = Table::read_from_disk(&mut users_file).unwrap();
+        let row = Row {
+            data: vec!["1".as_bytes().to_vec()],
+        };
+        users_table.add_row(&row, &mut users_file).unwrap();
+
+        let mut orders_file = writeable_table_file(orders.clone()).unwrap();
+        let mut orders_table = Table::read_from_disk(&mut orders_file).unwrap();
+        assert_eq!(
+            orders_table.columns[1].references,
+            Some((users.clone(), "id".to_string()))
+        );
+
+        let row = Row {
+            data: vec!["10".as_bytes().to_vec(), "1".as_bytes().to_vec()],
+        };
+        orders_table.add_row(&row, &mut orders_file).unwrap();
+
+        let row = Row {
+            data: vec!["11".as_bytes().to_vec(), "2".as_bytes().to_vec()],
+        };
+        match orders_table.add_row(&row, &mut orders_file) {
+            Err(DurabilityError::ForeignKeyViolation {
+                table,
+                column,
+                value,
+            }) => {
+                assert_eq!(table, users);
+                assert_eq!(column, "id");
+                assert_eq!(value, "2");
+            }
+            result => panic!("Expected foreign key violation, got {:?}", result),
+        }
+        assert_eq!(orders_table.row_count, 1);
+    }
+
+    #[test]
+    fn test_foreign_key_delete_check() {
+        let tmp_dir = tempdir().unwrap();
+        let (users, orders) = create_users_and_orders(tmp_dir.path());
+
+        let mut users_file = writeable_table_file(users.clone()).unwrap();
+        let mut users_table = Table::read_from_disk(&mut users_file).unwrap();
+        let referenced = Row {
+            data: vec!["1".as_bytes().to_vec()],
+        };
+        let unreferenced = Row {
+            data: vec!["2".as_bytes().to_vec()],
+        };
+        users_table.add_row(&referenced, &mut users_file).unwrap();
+        users_table.add_row(&unreferenced, &mut users_file).unwrap();
+
+        let mut orders_file = writeable_table_file(orders.clone()).unwrap();
+        let mut orders_table = Table::read_from_disk(&mut orders_file).unwrap();
+        let row = Row {
+            data: vec!["10".as_bytes().to_vec(), "1".as_bytes().to_vec()],
+        };
+        orders_table.add_row(&row, &mut orders_file).unwrap();
+
+        match users_table.check_delete(&referenced) {
+            Err(DurabilityError::ForeignKeyViolation {
+                table,
+                column,
+                value,
+            }) => {
+                assert_eq!(table, orders);
+                assert_eq!(column, "user_id");
+                assert_eq!(value, "1");
+            }
+            result => panic!("Expected foreign key violation, got {:?}", result),
+        }
+        users_table.check_delete(&unreferenced).unwrap();
+    }
+
+    #[test]
+    fn test_foreign_key_to_missing_table() {
+        let tmp_dir = tempdir().unwrap();
+        let orders = tmp_dir.path().join("orders").to_str().unwrap().to_string();
+        let mut user_id = ColumnDefinition::new("user_id".to_string(), ColumnType::Int, 11);
+        user_id.references = Some(("missing".to_string(), "id".to_string()));
+
+        assert!(create_table(TableBuilder::new(&orders).column_definition(user_id)).is_err());
+        assert!(!table_exists(&orders));
+    }
+
+    #[test]
+    fn test_check_constraint() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir
+            .path()
+            .join("products")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let mut price = ColumnDefinition::new("price".to_string(), ColumnType::Int, 11);
+        let mut check_expr = [0; 128];
+        check_expr[..9].copy_from_slice(b"price > 0");
+        price.check_expr = Some(check_expr);
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column_definition(price),
+        )
+        .unwrap();
+
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.columns[0].check_expr, None);
+        assert_eq!(table.columns[1].check_expr, Some(check_expr));
+
+        let row = Row {
+            data: vec!["-5".as_bytes().to_vec(), "10".as_bytes().to_vec()],
+        };
+        table.add_row(&row, &mut file).unwrap();
+
+        for price in ["0", "-1"] {
+            let row = Row {
+                data: vec!["1".as_bytes().to_vec(), price.as_bytes().to_vec()],
+            };
+            match table.add_row(&row, &mut file) {
+                Err(DurabilityError::CheckConstraintViolation { column, expr }) => {
+                    assert_eq!(column, "price");
+                    assert_eq!(expr, "price > 0");
+                }
+                result => panic!("Expected check constraint violation, got {:?}", result),
+            }
+        }
+        assert_eq!(table.row_count, 1);
+    }
+
+    #[test]
+    fn test_analyze_table() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 4)
+                .column("city", ColumnType::Varchar, 8),
+        )
+        .unwrap();
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let stats = table.load_stats().unwrap().unwrap();
+        assert_eq!(stats[0].distinct_count, 0);
+        assert_eq!(stats[1].min, vec![0; 8]);
+
+        for (id, city) in [("9", "Oslo"), ("10", "Bergen"), ("-3", ""), ("9", "Oslo")] {
+            let row = Row {
+                data: vec![id.as_bytes().to_vec(), city.as_bytes().to_vec()],
+            };
+            table.add_row(&row, &mut file).unwrap();
+        }
+        table.analyze(&file).unwrap();
+
+        let stats = Table::read_from_disk(&mut file)
+            .unwrap()
+            .load_stats()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            stats[0],
+            ColumnStats {
+                min: b"-3\0\0".to_vec(),
+                max: b"10\0\0".to_vec(),
+                distinct_count: 3,
+                null_count: 0,
+            }
+        );
+        assert_eq!(
+            stats[1],
+            ColumnStats {
+                min: b"Bergen\0\0".to_vec(),
+                max: b"Oslo\0\0\0\0".to_vec(),
+                distinct_count: 2,
+                null_count: 1,
+            }
+        );
+        assert_eq!(stats[1].estimated_matches(&Operator::Eq, 4), 1);
+        assert_eq!(stats[1].estimated_matches(&Operator::NotEq, 4), 2);
+    }
+
+    #[test]
+    fn test_analyze_column() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 4)
+                .column("city", ColumnType::Varchar, 8),
+        )
+        .unwrap();
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let rows: Vec<Row> = (0..301)
+            .map(|i| Row {
+                data: vec![i.to_string().into_bytes(), b"Oslo".to_vec()],
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+        table.delete_at(&mut file, 0, 0).unwrap();
+
+        let city = table.analyze_column(&file, "city").unwrap();
+        assert_eq!(city.row_count, 300);
+        assert_eq!(city.stats.distinct_count, 1);
+        assert!(city.suggest_dictionary);
+        // Nearly every value is distinct, and only Varchar columns qualify.
+        assert!(
+            !table
+                .analyze_column(&file, "id")
+                .unwrap()
+                .suggest_dictionary
+        );
+        assert!(table.analyze_column(&file, "missing").is_err());
+    }
+
+    #[test]
+    fn test_table_stats_timestamps() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(TableBuilder::new(&name).column("id", ColumnType::Int, 4)).unwrap();
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+
+        let created = table.stats(&file).unwrap();
+        assert_eq!(created.created_at, created.last_modified);
+        // `create_table` analyzes the empty table.
+        assert!(created.last_analyzed >= created.created_at);
+        assert_eq!(created.row_count, 0);
+        assert_eq!(created.total_data_bytes, 0);
+
+        // The timestamps are in seconds.
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        for id in ["1", "2"] {
+            let row = Row {
+                data: vec![id.as_bytes().to_vec()],
+            };
+            table.add_row(&row, &mut file).unwrap();
+        }
+        let modified = table.stats(&file).unwrap();
+        assert_eq!(modified.created_at, created.created_at);
+        assert!(modified.last_modified > modified.created_at);
+        assert!(modified.last_modified > modified.last_analyzed);
+        assert_eq!(modified.row_count, 2);
+        assert_eq!(modified.total_data_bytes, 2 * table.row_size());
+
+        table.analyze(&file).unwrap();
+        let analyzed = Table::read_from_disk(&mut file)
+            .unwrap()
+            .stats(&file)
+            .unwrap();
+        assert!(analyzed.last_analyzed >= analyzed.last_modified);
+        assert_eq!(analyzed.last_modified, modified.last_modified);
+        assert_eq!(analyzed.created_at, created.created_at);
+    }
+
+    #[test]
+    fn test_write_materialized_view() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir
+            .path()
+            .join("mv_active")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let columns = || vec![ColumnDefinition::new("id".to_string(), ColumnType::Int, 8)];
+        let rows = |ids: &[&str]| -> Vec<Row> {
+            ids.iter()
+                .map(|id| Row {
+                    data: vec![id.as_bytes().to_vec()],
+                })
+                .collect()
+        };
+        let read = || {
+            let mut file = writeable_table_file(materialized_view_file(&name)).unwrap();
+            let table = Table::read_from_disk(&mut file).unwrap();
+            let page = table.page_at(&file, 0).unwrap();
+            let ids: Vec<Vec<u8>> = table
+                .page_rows(&page)
+                .into_iter()
+                .map(|row| row.data[0].clone())
+                .collect();
+            (table.table_type, ids)
+        };
+
+        write_materialized_view(&name, columns(), &rows(&["1", "2", "3"])).unwrap();
+        let (table_type, ids) = read();
+        assert_eq!(table_type, MATERIALIZED_VIEW);
+        assert_eq!(
+            ids,
+            [b"1\0\0\0\0\0\0\0", b"2\0\0\0\0\0\0\0", b"3\0\0\0\0\0\0\0"]
+        );
+
+        // A refresh replaces the stored rows.
+        write_materialized_view(&name, columns(), &rows(&["4"])).unwrap();
+        assert_eq!(
+            read(),
+            (MATERIALIZED_VIEW, vec![b"4\0\0\0\0\0\0\0".to_vec()])
+        );
+
+        let base = tmp_dir.path().join("active").to_str().unwrap().to_string();
+        create_table(TableBuilder::new(&base).columns(columns())).unwrap();
+        let mut file = writeable_table_file(base).unwrap();
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.table_type, table::BASE_TABLE);
+    }
+
+    #[test]
+    fn test_rename_table() {
+        let tmp_dir = tempdir().unwrap();
+        let from = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        let to = tmp_dir
+            .path()
+            .join("customers")
+            .to_str()
+            .unwrap()
+            .to_string();
+        create_table(
+            TableBuilder::new(&from)
+                .column("id", ColumnType::Int, 11)
+                .column("account_id", ColumnType::Int, 11),
+        )
+        .unwrap();
+        std::fs::write(format!("{}.id.idx", from), b"index").unwrap();
+
+        let mut file = writeable_table_file(from.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let row = Row {
+            data: vec!["1".as_bytes().to_vec(), "2".as_bytes().to_vec()],
+        };
+        table.add_row(&row, &mut file).unwrap();
+
+        rename_table(&from, &to, &mut table, &mut file).unwrap();
+        assert!(!table_exists(&from));
+        assert!(!table_exists(&format!("{}.id.idx", from)));
+        assert!(table_exists(&format!("{}.id.idx", to)));
+        assert_eq!(table.name_str(), to);
+
+        let mut file = writeable_table_file(to.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.name_str(), to);
+        let row = Row {
+            data: vec!["3".as_bytes().to_vec(), "4".as_bytes().to_vec()],
+        };
+        table.add_row(&row, &mut file).unwrap();
+
+        let page = table.page_at(&file, 0).unwrap();
+        let rows = table.page_rows(&page);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(&rows[0].data[0][..1], b"1");
+        assert_eq!(&rows[1].data[0][..1], b"3");
+    }
+
+    #[test]
+    fn test_rename_table_to_existing_table() {
+        let tmp_dir = tempdir().unwrap();
+        let from = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        let to = tmp_dir
+            .path()
+            .join("customers")
+            .to_str()
+            .unwrap()
+            .to_string();
+        for name in [&from, &to] {
+            create_table(TableBuilder::new(name).column("id", ColumnType::Int, 11)).unwrap();
+        }
+
+        let mut file = writeable_table_file(from.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        assert!(rename_table(&from, &to, &mut table, &mut file).is_err());
+        assert!(table_exists(&from));
+        assert_eq!(table.name_str(), from);
+    }
+
+    #[test]
+    fn test_rename_column() {
+        let tmp_dir = tempdir().unwrap();
+        let temp_file_path = tmp_dir.path().join("test_table");
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .read(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_file_path)
+            .unwrap();
+
+        let mut table = TableBuilder::new(temp_file_path.to_str().unwrap())
+            .column("usr_id", ColumnType::Int, 11)
+            .column("account_id", ColumnType::Int, 11)
+            .build()
+            .unwrap();
+        table.write_to_disk(&mut file).unwrap();
+        table.add_page(&mut file).unwrap();
+        table
+            .add_row(
+                &Row {
+                    data: vec!["123".as_bytes().to_vec(), "1".as_bytes().to_vec()],
+                },
+                &mut file,
+            )
+            .unwrap();
+
+        table.rename_column("usr_id", "user_id", &mut file).unwrap();
+
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(&table.columns[0].name[..8], b"user_id\0");
+        assert_eq!(&table.columns[1].name[..11], b"account_id\0");
+        assert_eq!(table.row_count, 1);
+
+        let page = table.page_at(&file, 0).unwrap();
+        let rows = table.page_rows(&page);
+        assert_eq!(&rows[0].data[0][..3], b"123");
+        assert_eq!(&rows[0].data[1][..1], b"1");
+    }
+
+    #[test]
+    fn test_schema_change_by_another_process() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column("name", ColumnType::Varchar, 20),
+        )
+        .unwrap();
+        let row = |id: &str| Row {
+            data: vec![id.as_bytes().to_vec(), b"Ada".to_vec()],
+        };
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        table.add_row(&row("1"), &mut file).unwrap();
+
+        // Another process opens the table and renames a column.
+        let mut other_file = writeable_table_file(name.clone()).unwrap();
+        let mut other = Table::read_from_disk(&mut other_file).unwrap();
+        assert_eq!(other.schema_hash(), table.schema_hash());
+        other
+            .rename_column("name", "full_name", &mut other_file)
+            .unwrap();
+        assert_ne!(other.schema_hash(), table.schema_hash());
+
+        let cached = table.schema_hash();
+        match table.add_row(&row("2"), &mut file) {
+            Err(DurabilityError::SchemaMismatch {
+                cached_hash,
+                disk_hash,
+            }) => {
+                assert_eq!(cached_hash, cached);
+                assert_eq!(disk_hash, other.schema_hash());
+            }
+            result => panic!("Expected a schema mismatch, got {:?}", result),
+        }
+        assert!(matches!(
+            table.replace_row(&mut file, 0, &row("3")),
+            Err(DurabilityError::SchemaMismatch { .. })
+        ));
+        assert!(matches!(
+            table.delete_at(&mut file, 0, 0),
+            Err(DurabilityError::SchemaMismatch { .. })
+        ));
+
+        // Read again, the writes go through.
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        table.add_row(&row("2"), &mut file).unwrap();
+        assert_eq!(table.row_count, 2);
+        other.add_row(&row("3"), &mut other_file).unwrap();
+    }
+
+    #[test]
+    fn test_clone_schema() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column("name", ColumnType::Varchar, 20)
+                .primary_key("id"),
+        )
+        .unwrap();
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let row = Row {
+            data: vec![b"1".to_vec(), b"Ada".to_vec()],
+        };
+        table.add_row(&row, &mut file).unwrap();
+
+        let copy_name = tmp_dir
+            .path()
+            .join("users_copy")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let mut copy = table.clone_schema(&copy_name).unwrap();
+        assert_eq!(copy.name_str(), copy_name);
+        assert_eq!(copy.row_count, 0);
+        assert_eq!(copy.column_count, 2);
+        assert_eq!(copy.primary_key_column(), Some(0));
+        // The names differ, the columns do not.
+        assert_ne!(copy.name, table.name);
+        assert!(copy.is_schema_compatible(&table));
+        // Nothing is written until the caller does.
+        assert!(!table_exists(&copy_name));
+
+        let mut copy_file = std::fs::OpenOptions::new()
+            .write(true)
+            .read(true)
+            .create(true)
+            .truncate(true)
+            .open(&copy_name)
+            .unwrap();
+        copy.write_to_disk(&mut copy_file).unwrap();
+        copy.add_page(&mut copy_file).unwrap();
+        copy.add_row(&row, &mut copy_file).unwrap();
+        let copy = Table::read_from_disk(&mut copy_file).unwrap();
+        assert_eq!(copy.row_count, 1);
+        assert_eq!(copy.columns[1].name_str(), "name");
+        assert_eq!(table.row_count, 1);
+
+        assert!(table.clone_schema(&"a".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn test_is_schema_compatible() {
+        let table = |columns: &[(&str, ColumnType, u64)]| {
+            let mut builder = TableBuilder::new("test_table");
+            for (name, column_type, length) in columns {
+                builder = builder.column(name, column_type.clone(), *length);
+            }
+            builder.build().unwrap()
+        };
+        let users = table(&[
+            ("id", ColumnType::Int, 11),
+            ("name", ColumnType::Varchar, 20),
+        ]);
+        // Names do not matter.
+        let renamed = table(&[
+            ("user_id", ColumnType::Int, 11),
+            ("full_name", ColumnType::Varchar, 20),
+        ]);
+        assert!(users.is_schema_compatible(&renamed));
+        assert!(renamed.is_schema_compatible(&users));
+
+        let longer = table(&[
+            ("id", ColumnType::Int, 11),
+            ("name", ColumnType::Varchar, 30),
+        ]);
+        let retyped = table(&[
+            ("id", ColumnType::Float, 11),
+            ("name", ColumnType::Varchar, 20),
+        ]);
+        let reordered = table(&[
+            ("name", ColumnType::Varchar, 20),
+            ("id", ColumnType::Int, 11),
+        ]);
+        let extra = table(&[
+            ("id", ColumnType::Int, 11),
+            ("name", ColumnType::Varchar, 20),
+            ("age", ColumnType::Int, 3),
+        ]);
+        for other in [longer, retyped, reordered, extra] {
+            assert!(!users.is_schema_compatible(&other));
+            assert!(!other.is_schema_compatible(&users));
+        }
+    }
+
+    #[test]
+    fn test_rename_column_rejects_invalid_names() {
+        let tmp_dir = tempdir().unwrap();
+        let temp_file_path = tmp_dir.path().join("test_table");
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .read(true)
+            .create(true)
+            .truncate(true)
+            .open(temp_file_path)
+            .unwrap();
+
+        let mut table = TableBuilder::new("test_table")
+            .column("id", ColumnType::Int, 11)
+            .column("account_id", ColumnType::Int, 11)
+            .build()
+            .unwrap();
+        table.write_to_disk(&mut file).unwrap();
+
+        assert!(table
+            .rename_column("id", &"a".repeat(64), &mut file)
+            .is_err());
+        assert!(table.rename_column("missing", "other", &mut file).is_err());
+        assert!(table.rename_column("id", "account_id", &mut file).is_err());
+
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(&table.columns[0].name[..3], b"id\0");
+    }
+
+    #[test]
+    fn test_concurrent_inserts() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("events").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column("writer", ColumnType::Int, 11),
+        )
+        .unwrap();
+
+        let writers: Vec<_> = (0..2)
+            .map(|writer| {
+                let name = name.clone();
+                std::thread::spawn(move || {
+                    // Every thread has its own handle and its own stale row count.
+                    let mut file = writeable_table_file(name).unwrap();
+                    let mut table = Table::read_from_disk(&mut file).unwrap();
+                    for i in 0..50 {
+                        let row = Row {
+                            data: vec![i.to_string().into_bytes(), writer.to_string().into_bytes()],
+                        };
+                        table.add_row(&row, &mut file).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let mut file = writeable_table_file(name).unwrap();
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.row_count, 100);
+
+        let mut rows = vec![];
+        for i in 0..table.page_count() {
+            let page = table.page_at(&file, i).unwrap();
+            rows.extend(table.page_rows(&page));
+        }
+        for writer in ["0", "1"] {
+            let written = rows
+                .iter()
+                .filter(|row| row.data[1].split(|b| *b == 0).next() == Some(writer.as_bytes()))
+                .count();
+            assert_eq!(written, 50);
+        }
+    }
+
+    #[test]
+    fn test_add_rows_across_pages() {
+        let tmp_dir = tempdir().unwrap();
+        let rows: Vec<Row> = (0..100)
+            .map(|i| Row {
+                data: vec![i.to_string().into_bytes()],
+            })
+            .collect();
+        let mut tables = vec![];
+        for name in ["one_by_one", "batch"] {
+            let name = tmp_dir.path().join(name).to_str().unwrap().to_string();
+            create_table(TableBuilder::new(&name).column("id", ColumnType::Int, 11)).unwrap();
+            let mut file = writeable_table_file(name).unwrap();
+            let table = Table::read_from_disk(&mut file).unwrap();
+            tables.push((table, file));
+        }
+        for row in rows.iter() {
+            let (table, file) = &mut tables[0];
+            table.add_row(row, file).unwrap();
+        }
+        let (table, file) = &mut tables[1];
+        table.add_rows(&rows, file).unwrap();
+
+        let [(one_by_one, one_by_one_file), (batch, batch_file)] = &mut tables[..] else {
+            unreachable!()
+        };
+        assert_eq!(batch.row_count, 100);
+        assert!(batch.page_count() > 1);
+        let length = |file: &std::fs::File| file.metadata().unwrap().len();
+        assert_eq!(length(batch_file), length(one_by_one_file));
+        for page_number in 0..batch.page_count() {
+            let page = batch.page_at(batch_file, page_number).unwrap();
+            let expected = one_by_one.page_at(one_by_one_file, page_number).unwrap();
+            assert_eq!(batch.page_rows(&page), one_by_one.page_rows(&expected));
+        }
+    }
+
+    fn decode_rows(table: &Table, page: &Page) -> Vec<Vec<String>> {
+        table
+            .page_rows(page)
+            .iter()
+            .map(|row| {
+                row.data
+                    .iter()
+                    .map(|value| {
+                        let value: Vec<u8> = value.iter().copied().filter(|b| *b != 0).collect();
+                        String::from_utf8(value).unwrap()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn create_cities(dir: &std::path::Path, row_count: u64) -> (Table, std::fs::File) {
+        let name = dir.join("cities").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 8)
+                .column("name", ColumnType::Varchar, 12)
+                .column("country", ColumnType::Varchar, 12),
+        )
+        .unwrap();
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let rows: Vec<Row> = (0..row_count)
+            .map(|i| Row {
+                data: vec![
+                    i.to_string().into_bytes(),
+                    format!("city {}", i).into_bytes(),
+                    match i % 3 {
+                        0 => vec![],
+                        _ => b"Norway".to_vec(),
+                    },
+                ],
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+        (table, file)
+    }
+
+    #[test]
+    fn test_decode_rows_batch() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, file) = create_cities(tmp_dir.path(), 10);
+        assert!(table.page_count() > 1);
+
+        let last_page = table.page_at(&file, table.page_count() - 1).unwrap();
+        let decoded = table.decode_rows_batch(&last_page);
+        assert_eq!(decoded, decode_rows(&table, &last_page));
+        assert_eq!(decoded.last().unwrap(), &["9", "city 9", ""]);
+
+        for page_number in 0..table.page_count() {
+            let page = table.page_at(&file, page_number).unwrap();
+            assert_eq!(table.decode_rows_batch(&page), decode_rows(&table, &page));
+        }
+    }
+
+    #[test]
+    fn test_delete_at() {
+        let tmp_dir = tempdir().unwrap();
+        let (mut table, mut file) = create_cities(tmp_dir.path(), 10);
+        let name = table.name_str();
+        assert_eq!(table.page_size() / table.row_size(), 4);
+        let id_path = index_file(name, "id");
+        BTreeIndex::build("idx_id", &table, &file, &["id"], None)
+            .unwrap()
+            .write(&id_path)
+            .unwrap();
+        let name_path = hash_index_file(name, "name");
+        HashIndex::build("idx_name", &table, &file, "name")
+            .unwrap()
+            .write(&name_path)
+            .unwrap();
+
+        // Row 6 is the third row of the second page.
+        table.delete_at(&mut file, 1, 2).unwrap();
+        assert_eq!(table.row_count, 10);
+        let page = table.page_at(&file, 1).unwrap();
+        let ids: Vec<u64> = table.page_entries(&page).iter().map(|(i, _)| *i).collect();
+        assert_eq!(ids, [4, 5, 7]);
+        assert_eq!(table.page_rows(&page).len(), 3);
+        assert_eq!(
+            table.decode_rows_batch(&page),
+            [
+                ["4", "city 4", "Norway"],
+                ["5", "city 5", "Norway"],
+                ["7", "city 7", "Norway"]
+            ]
+        );
+        assert!(BTreeIndex::read(&id_path).unwrap().lookup(b"6").is_empty());
+        assert_eq!(BTreeIndex::read(&id_path).unwrap().lookup(b"7"), [7]);
+        let names = HashIndex::read(&name_path).unwrap();
+        assert!(names
+            .lookup(&names.key(b"city 6", &ColumnType::Varchar))
+            .is_empty());
+        assert_eq!(
+            table.find_row(&file, 0, b"7\0\0\0\0\0\0\0").unwrap(),
+            Some(7)
+        );
+
+        assert!(table.delete_at(&mut file, 1, 2).is_err());
+        assert!(table.delete_at(&mut file, 1, 4).is_err());
+        assert!(table.delete_at(&mut file, 2, 2).is_err());
+
+        // New rows go after the deleted one, which stays deleted on disk.
+        let row = Row {
+            data: vec![b"10".to_vec(), b"city 10".to_vec(), vec![]],
+        };
+        table.add_row(&row, &mut file).unwrap();
+        let table = Table::read_from_disk(&mut file).unwrap();
+        let ids: Vec<String> = (0..table.page_count())
+            .flat_map(|page_number| {
+                table.decode_rows_batch(&table.page_at(&file, page_number).unwrap())
+            })
+            .map(|row| row[0].clone())
+            .collect();
+        assert_eq!(ids, ["0", "1", "2", "3", "4", "5", "7", "8", "9", "10"]);
+    }
+
+    /// Disk usage and a full read of a 10MB Varchar table stored as it is
+    /// and compressed, run with
+    /// `cargo test --release bench_compression -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_compression() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp_dir = tempdir().unwrap();
+        let rows: Vec<Row> = (0..40_000)
+            .map(|i| Row {
+                data: vec![
+                    i.to_string().into_bytes(),
+                    format!("user{}@mail.example", i).into_bytes(),
+                ],
+            })
+            .collect();
+        for compression in [false, true] {
+            let name = tmp_dir.path().join(format!("users_{}", compression));
+            let name = name.to_str().unwrap().to_string();
+            create_table(
+                TableBuilder::new(&name)
+                    .column("id", ColumnType::Int, 11)
+                    .column("email", ColumnType::Varchar, 250)
+                    .compression(compression),
+            )
+            .unwrap();
+            let mut file = writeable_table_file(name).unwrap();
+            let mut table = Table::read_from_disk(&mut file).unwrap();
+            table.add_rows(&rows, &mut file).unwrap();
+            file.sync_all().unwrap();
+
+            let metadata = file.metadata().unwrap();
+            let start = std::time::Instant::now();
+            let read: usize = (0..table.page_count())
+                .map(|page| table.page_rows(&table.page_at(&file, page).unwrap()).len())
+                .sum();
+            let elapsed = start.elapsed();
+            println!(
+                "compression {}: {} bytes of rows, {} bytes on disk, {} rows read in {:?} ({:.1} MB/s)",
+                compression,
+                table.row_size() * table.row_count,
+                metadata.blocks() * 512,
+                read,
+                elapsed,
+                (table.row_size() * table.row_count) as f64 / elapsed.as_secs_f64() / 1e6
+            );
+        }
+    }
+
+    /// Decoding a page a row and a byte at a time against a column at a time,
+    /// run with `cargo test --release bench_decode_rows -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_decode_rows() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, file) = create_cities(tmp_dir.path(), 100_000);
+        let pages: Vec<Page> = (0..table.page_count())
+            .map(|page_number| table.page_at(&file, page_number).unwrap())
+            .collect();
+
+        let start = std::time::Instant::now();
+        let rows: usize = pages
+            .iter()
+            .map(|page| decode_rows(&table, page).len())
+            .sum();
+        println!("row at a time: {} rows in {:?}", rows, start.elapsed());
+        let start = std::time::Instant::now();
+        let rows: usize = pages
+            .iter()
+            .map(|page| table.decode_rows_batch(page).len())
+            .sum();
+        println!("column at a time: {} rows in {:?}", rows, start.elapsed());
+    }
+
+    /// 1000 single row inserts against one batch of 1000 rows, run with
+    /// `cargo test --release bench_batch_insert -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_batch_insert() {
+        let tmp_dir = tempdir().unwrap();
+        let rows: Vec<Row> = (0..1000)
+            .map(|i| Row {
+                data: vec![i.to_string().into_bytes(), b"Oslo".to_vec()],
+            })
+            .collect();
+        for batched in [false, true] {
+            let name = tmp_dir.path().join(format!("events_{}", batched));
+            let name = name.to_str().unwrap().to_string();
+            create_table(
+                TableBuilder::new(&name)
+                    .column("id", ColumnType::Int, 11)
+                    .column("city", ColumnType::Varchar, 16),
+            )
+            .unwrap();
+            let mut file = writeable_table_file(name).unwrap();
+            let mut table = Table::read_from_disk(&mut file).unwrap();
+
+            let start = std::time::Instant::now();
+            match batched {
+                true => table.add_rows(&rows, &mut file).unwrap(),
+                false => {
+                    for row in rows.iter() {
+                        table.add_row(row, &mut file).unwrap();
+                    }
+                }
+            }
+            println!(
+                "{}: 1000 rows in {:?}",
+                if batched { "batch" } else { "one by one" },
+                start.elapsed()
+            );
+        }
+    }
+
+    #[test]
+    fn test_buffered_writes() {
+        use crate::durability::wal::wal_size;
+
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("events").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column("city", ColumnType::Varchar, 16),
+        )
+        .unwrap();
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let row = |id: usize| Row {
+            data: vec![id.to_string().into_bytes(), b"Oslo".to_vec()],
+        };
+        let row_count_offset = table.row_count_offset();
+        let stored_row_count = |file: &std::fs::File| {
+            let mut row_count = [0; 8];
+            file.read_exact_at(&mut row_count, row_count_offset)
+                .unwrap();
+            u64::from_ne_bytes(row_count)
+        };
+        let ids = |table: &Table, file: &std::fs::File| -> Vec<String> {
+            (0..table.page_count())
+                .flat_map(|page| table.decode_rows_batch(&table.page_at(file, page).unwrap()))
+                .map(|row| row[0].clone())
+                .collect()
+        };
+
+        let wal_size_before = wal_size(&name);
+        table
+            .buffered(&mut file, |table, file| {
+                for id in 0..7 {
+                    table.add_row(&row(id), file)?;
+                }
+                // Held back from the table file, but already in the redo log.
+                assert_eq!(stored_row_count(file), 0);
+                assert!(wal_size(&name) > wal_size_before);
+
+                // Reads see the buffered rows.
+                assert_eq!(ids(table, file).len(), 7);
+                table.add_row(&row(7), file)
+            })
+            .unwrap();
+        assert_eq!(stored_row_count(&file), 8);
+
+        // A failed write still flushes the ones before it.
+        let failed = table.buffered(&mut file, |table, file| {
+            table.add_row(&row(8), file)?;
+            table.add_row(&Row { data: vec![] }, file)
+        });
+        assert!(failed.is_err());
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.row_count, 9);
+        let expected: Vec<String> = (0..9).map(|id| id.to_string()).collect();
+        assert_eq!(ids(&table, &file), expected);
+    }
+
+    #[test]
+    fn test_wal_recovery_after_crash() {
+        use crate::durability::wal::{recover, wal_file, Wal};
+
+        // Run again as a child process that commits a row to the log and
+        // exits before writing it to the table file.
+        if let Ok(name) = env::var("CITY_DB_WAL_CRASH_TABLE") {
+            let mut file = writeable_table_file(name.clone()).unwrap();
+            let table = Table::read_from_disk(&mut file).unwrap();
+            let mut wal = Wal::open(&name).unwrap();
+            wal.append(table.header_size(), &vec![0; table.page_size() as usize])
+                .unwrap();
+            wal.append(table.header_size(), b"42\0\0\0\0\0\0\0\0\0")
+                .unwrap();
+            wal.append(table.row_count_offset(), &1u64.to_ne_bytes())
+                .unwrap();
+            wal.commit().unwrap();
+            wal.append(table.row_count_offset(), &2u64.to_ne_bytes())
+                .unwrap();
+            std::process::exit(0);
+        }
+
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("events").to_str().unwrap().to_string();
+        create_table(TableBuilder::new(&name).column("id", ColumnType::Int, 11)).unwrap();
+
+        let status = std::process::Command::new(env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "durability::table::tests::test_wal_recovery_after_crash",
+            ])
+            .env("CITY_DB_WAL_CRASH_TABLE", &name)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(std::fs::metadata(wal_file(&name)).unwrap().len() > 0);
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.row_count, 1);
+        let page = table.page_at(&file, 0).unwrap();
+        let rows = table.page_rows(&page);
+        assert_eq!(rows[0].data[0], b"42\0\0\0\0\0\0\0\0\0");
+        assert_eq!(recover(&name, &file).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_stale_locks_removed_on_read() {
+        use crate::durability::lock_file::{lock_file, read_locks, LockRecord, LOCK_MODE_WRITE};
+
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(TableBuilder::new(&name).column("id", ColumnType::Int, 4)).unwrap();
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let row = Row {
+            data: vec![b"1".to_vec()],
+        };
+        table.add_row(&row, &mut file).unwrap();
+        // The lock is released once the row is written.
+        assert!(read_locks(&lock_file(&name)).unwrap().is_empty());
+
+        // A crash in the middle of a write leaves its lock behind. Neither a
+        // process that no longer runs nor an earlier process with the pid
+        // of this one holds a lock anymore.
+        let stale = |lock_id, holder_connection_id| LockRecord {
+            lock_id,
+            table_name: "users".to_string(),
+            row_offset: table.header_size(),
+            lock_mode: LOCK_MODE_WRITE,
+            holder_connection_id,
+            acquired_at: 0,
+        };
+        let bytes = [
+            stale(1, i32::MAX as u64).bytes(),
+            stale(2, std::process::id() as u64).bytes(),
+        ]
+        .concat();
+        std::fs::write(lock_file(&name), bytes).unwrap();
+        assert_eq!(read_locks(&lock_file(&name)).unwrap().len(), 2);
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        assert!(read_locks(&lock_file(&name)).unwrap().is_empty());
+        table.add_row(&row, &mut file).unwrap();
+        assert_eq!(table.row_count, 2);
+    }
+
+    #[test]
+    fn test_refresh_sees_rows_of_another_instance() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(TableBuilder::new(&name).column("id", ColumnType::Int, 4)).unwrap();
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let database = crate::durability::DatabaseConfig {
+            name: "city_db".to_string(),
+            file_path: tmp_dir.path().to_str().unwrap().to_string(),
+            auth_file: None,
+        };
+        let mut page_cache = std::collections::HashMap::new();
+        let mut execute = |query: &str, table: &mut Table, file: &mut std::fs::File| {
+            crate::get_result_set(
+                table,
+                file,
+                query.into(),
+                &mut page_cache,
+                &database,
+                &mut None,
+                &mut crate::config::Config::default(),
+                &mut std::io::empty(),
+            )
+            .rows
+        };
+        execute(
+            &format!("INSERT INTO {} (id) VALUES (1)", name),
+            &mut table,
+            &mut file,
+        );
+
+        let mut other_file = writeable_table_file(name.clone()).unwrap();
+        let mut other = Table::read_from_disk(&mut other_file).unwrap();
+        for id in ["2", "3"] {
+            let row = Row {
+                data: vec![id.as_bytes().to_vec()],
+            };
+            other.add_row(&row, &mut other_file).unwrap();
+        }
+
+        assert_eq!(table.row_count, 1);
+        assert_eq!(
+            execute("REFRESH", &mut table, &mut file),
+            [[format!("Refreshed table {}", name)]]
+        );
+        assert_eq!(table.row_count, 3);
+        assert_eq!(
+            execute(&format!("SELECT * FROM {}", name), &mut table, &mut file),
+            [["1"], ["2"], ["3"]]
+        );
+    }
 }
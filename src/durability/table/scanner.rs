@@ -0,0 +1,199 @@
+use std::{cell::Cell, ops::Range};
+
+use memmap::{Mmap, MmapOptions};
+
+use super::{Page, Table};
+
+/// Pages advised to the OS ahead of a sequential scan at a time.
+pub const PREFETCH_PAGES: u64 = 256;
+
+thread_local! {
+    /// Pages read through a scanner on this thread.
+    static PAGES_READ: Cell<u64> = const { Cell::new(0) };
+}
+
+/// The number of pages scanners read on this thread so far.
+pub fn pages_read() -> u64 {
+    PAGES_READ.with(Cell::get)
+}
+
+/// How a scan is going to visit the pages of a table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanHint {
+    /// Every page in order, the pages ahead are prefetched.
+    Sequential,
+    /// Pages in no predictable order, each one is loaded on demand.
+    Random,
+}
+
+/// Reads the pages of a table for a scan. A sequential scan maps the next
+/// `PREFETCH_PAGES` pages whenever it advances past the last prefetched one
+/// and advises the OS to read them ahead, so a page is usually in memory by
+/// the time it is read.
+pub struct TableScanner<'a> {
+    table: &'a Table,
+    file: &'a std::fs::File,
+    hint: ScanHint,
+    /// The prefetched pages and their mapping, kept alive until the scan
+    /// passes them as unmapping drops the advice.
+    prefetched: Option<(Range<u64>, Mmap)>,
+}
+
+impl<'a> TableScanner<'a> {
+    pub fn with_hint(table: &'a Table, file: &'a std::fs::File, hint: ScanHint) -> Self {
+        TableScanner {
+            table,
+            file,
+            hint,
+            prefetched: None,
+        }
+    }
+
+    /// The page at `page_number`, prefetching the pages after it first when
+    /// the scan is sequential.
+    pub fn page(&mut self, page_number: u64) -> Result<Page, String> {
+        let prefetched = matches!(
+            &self.prefetched,
+            Some((pages, _)) if pages.contains(&page_number)
+        );
+        if self.hint == ScanHint::Sequential && !prefetched {
+            self.prefetched = self.prefetch(page_number);
+        }
+        PAGES_READ.with(|pages| pages.set(pages.get() + 1));
+        Ok(self.table.page_at(self.file, page_number)?)
+    }
+
+    /// Maps the pages from `page_number` on and advises the OS to read them.
+    /// A failed prefetch only loses the hint, `None` then.
+    fn prefetch(&self, page_number: u64) -> Option<(Range<u64>, Mmap)> {
+        let end = (page_number + PREFETCH_PAGES).min(self.table.page_count());
+        let offset = self.table.page_offset(page_number);
+        let file_length = self.file.metadata().ok()?.len();
+        let length = ((end - page_number) * self.table.page_slot_size())
+            .min(file_length.checked_sub(offset)?);
+        if length == 0 {
+            return None;
+        }
+        let mmap = unsafe {
+            MmapOptions::new()
+                .len(length as usize)
+                .offset(offset)
+                .map(self.file)
+                .ok()?
+        };
+        advise_sequential(&mmap);
+        Some((page_number..end, mmap))
+    }
+}
+
+/// Tells the kernel the mapping is about to be read in order, so it reads
+/// ahead aggressively and starts loading it right away.
+#[cfg(target_os = "linux")]
+fn advise_sequential(mmap: &Mmap) {
+    // madvise wants a page aligned address, the mapping starts at the OS page
+    // holding the offset it was asked for.
+    let os_page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let start = mmap.as_ptr() as usize;
+    let aligned = start - start % os_page_size;
+    let length = mmap.len() + (start - aligned);
+    unsafe {
+        libc::madvise(aligned as *mut libc::c_void, length, libc::MADV_SEQUENTIAL);
+        libc::madvise(aligned as *mut libc::c_void, length, libc::MADV_WILLNEED);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise_sequential(_mmap: &Mmap) {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::{
+        table::{create_table, writeable_table_file, ColumnType, Row, TableBuilder},
+        Durable,
+    };
+
+    fn create_accounts(dir: &std::path::Path, row_count: u64) -> (Table, std::fs::File) {
+        let name = dir.join("accounts").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 8)
+                .column("balance", ColumnType::Int, 8),
+        )
+        .unwrap();
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let rows: Vec<Row> = (0..row_count)
+            .map(|id| Row {
+                data: vec![id.to_string().into_bytes(), b"100".to_vec()],
+            })
+            .collect();
+        for batch in rows.chunks(1000) {
+            table.add_rows(batch, &mut file).unwrap();
+        }
+        (table, file)
+    }
+
+    fn scan(table: &Table, file: &std::fs::File, hint: ScanHint) -> u64 {
+        let mut scanner = TableScanner::with_hint(table, file, hint);
+        (0..table.page_count())
+            .map(|page_number| {
+                let page = scanner.page(page_number).unwrap();
+                table.page_rows(&page).len() as u64
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_sequential_scan() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, file) = create_accounts(tmp_dir.path(), 5000);
+        assert!(table.page_count() > PREFETCH_PAGES * 2);
+
+        let mut scanner = TableScanner::with_hint(&table, &file, ScanHint::Sequential);
+        let page = scanner.page(0).unwrap();
+        assert_eq!(table.page_rows(&page)[0].data[0], b"0\0\0\0\0\0\0\0");
+        let prefetched = |scanner: &TableScanner| scanner.prefetched.as_ref().unwrap().0.clone();
+        assert_eq!(prefetched(&scanner), 0..PREFETCH_PAGES);
+        scanner.page(PREFETCH_PAGES - 1).unwrap();
+        assert_eq!(prefetched(&scanner), 0..PREFETCH_PAGES);
+        scanner.page(PREFETCH_PAGES).unwrap();
+        assert_eq!(prefetched(&scanner), PREFETCH_PAGES..PREFETCH_PAGES * 2);
+        // The last prefetch stops at the end of the table.
+        let last_page = table.page_count() - 1;
+        scanner.page(last_page).unwrap();
+        assert_eq!(prefetched(&scanner), last_page..last_page + 1);
+
+        let mut scanner = TableScanner::with_hint(&table, &file, ScanHint::Random);
+        scanner.page(5).unwrap();
+        assert!(scanner.prefetched.is_none());
+
+        assert_eq!(scan(&table, &file, ScanHint::Sequential), 5000);
+        assert_eq!(scan(&table, &file, ScanHint::Random), 5000);
+    }
+
+    /// Sequential scan throughput with and without prefetching, run with
+    /// `cargo test --release bench_sequential_scan -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_sequential_scan() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, file) = create_accounts(tmp_dir.path(), 100_000);
+        for hint in [ScanHint::Random, ScanHint::Sequential] {
+            let start = Instant::now();
+            let rows = scan(&table, &file, hint);
+            let elapsed = start.elapsed();
+            println!(
+                "{:?}: {} rows in {:?} ({:.0} rows/s)",
+                hint,
+                rows,
+                elapsed,
+                rows as f64 / elapsed.as_secs_f64()
+            );
+        }
+    }
+}
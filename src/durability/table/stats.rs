@@ -0,0 +1,277 @@
+use std::{
+    cmp::Ordering,
+    collections::HashSet,
+    os::unix::fs::FileExt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::durability::DurabilityError;
+use crate::query::predicate::Operator;
+
+use super::table::ROW_COUNT_SIZE;
+use super::{decode_decimal, decode_timestamp, ColumnType, Table};
+
+/// The size of the `TableStats` kept in the table header.
+pub const TABLE_STATS_SIZE: u64 = 32;
+
+pub fn stats_file(table: &str) -> String {
+    format!("{}.stats", table)
+}
+
+/// The current time in seconds since the Unix epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// What the table header records about the table as a whole, timestamps in
+/// seconds since the Unix epoch. Kept after the row count as `created_at`,
+/// `last_modified`, `last_analyzed` and `total_data_bytes`, `last_analyzed`
+/// is 0 until the table is analyzed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TableStats {
+    pub row_count: u64,
+    pub created_at: u64,
+    pub last_modified: u64,
+    pub last_analyzed: u64,
+    /// The bytes taken by the rows, deleted ones included as they keep
+    /// their slot.
+    pub total_data_bytes: u64,
+}
+
+/// The distribution of a column's values as of the last `ANALYZE TABLE`.
+/// `min` and `max` keep the fixed width of the column and are all zero when
+/// the column only holds nulls. Stored one after the other in column order
+/// in the `{table}.stats` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    pub min: Vec<u8>,
+    pub max: Vec<u8>,
+    pub distinct_count: u64,
+    pub null_count: u64,
+}
+
+/// The stats of a column along with the number of rows not deleted. A
+/// Varchar column holding fewer distinct values than 1% of the rows is
+/// suggested for dictionary encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnAnalysis {
+    pub stats: ColumnStats,
+    pub row_count: u64,
+    pub suggest_dictionary: bool,
+}
+
+impl ColumnStats {
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend(self.min.iter());
+        bytes.extend(self.max.iter());
+        bytes.extend(self.distinct_count.to_ne_bytes().iter());
+        bytes.extend(self.null_count.to_ne_bytes().iter());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8], length: usize) -> Self {
+        let read_u64 =
+            |offset: usize| u64::from_ne_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        ColumnStats {
+            min: bytes[..length].to_vec(),
+            max: bytes[length..length * 2].to_vec(),
+            distinct_count: read_u64(length * 2),
+            null_count: read_u64(length * 2 + 8),
+        }
+    }
+
+    /// Estimates how many of `row_count` rows match `column op literal`,
+    /// assuming values are evenly spread over the distinct values. Range
+    /// comparisons are assumed to match a third of the non null rows. Stats
+    /// taken before any value was inserted tell nothing, every non null row
+    /// is assumed to match.
+    pub fn estimated_matches(&self, operator: &Operator, row_count: u64) -> u64 {
+        let non_null = row_count.saturating_sub(self.null_count);
+        if self.distinct_count == 0 {
+            return non_null;
+        }
+        let per_value = non_null / self.distinct_count;
+        match operator {
+            Operator::Eq => per_value,
+            Operator::NotEq => non_null - per_value,
+            _ => non_null / 3,
+        }
+    }
+}
+
+impl Table {
+    /// The stats as last written to the table header.
+    pub fn stats(&self, file: &std::fs::File) -> Result<TableStats, DurabilityError> {
+        self.flush(file)?;
+        let mut bytes = [0; (ROW_COUNT_SIZE + TABLE_STATS_SIZE) as usize];
+        file.read_exact_at(&mut bytes, self.row_count_offset())
+            .map_err(DurabilityError::IoError)?;
+        let read_u64 =
+            |offset: usize| u64::from_ne_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        Ok(TableStats {
+            row_count: read_u64(0),
+            created_at: read_u64(8),
+            last_modified: read_u64(16),
+            last_analyzed: read_u64(24),
+            total_data_bytes: read_u64(32),
+        })
+    }
+
+    /// Records a change to the rows now, `row_count` being the row count
+    /// after it. Like the column stats these are not logged to the WAL.
+    pub(super) fn record_modified(
+        &self,
+        row_count: u64,
+        file: &std::fs::File,
+    ) -> Result<(), DurabilityError> {
+        let offset = self.table_stats_offset();
+        let total_data_bytes = row_count * self.row_size();
+        file.write_all_at(&unix_time().to_ne_bytes(), offset + 8)
+            .map_err(DurabilityError::IoError)?;
+        file.write_all_at(&total_data_bytes.to_ne_bytes(), offset + 24)
+            .map_err(DurabilityError::IoError)
+    }
+
+    /// Scans every row to compute the stats of each column and writes them to
+    /// the table's stats file.
+    pub fn analyze(&self, file: &std::fs::File) -> Result<Vec<ColumnStats>, DurabilityError> {
+        self.analyze_rows(file).map(|(stats, _)| stats)
+    }
+
+    /// Analyzes the table like `analyze` and reports on the column `name`,
+    /// `ANALYZE COLUMN table.column`.
+    pub fn analyze_column(
+        &self,
+        file: &std::fs::File,
+        name: &str,
+    ) -> Result<ColumnAnalysis, DurabilityError> {
+        let position = self
+            .columns
+            .iter()
+            .position(|column| column.name_str() == name)
+            .ok_or_else(|| format!("Column {} does not exist", name))?;
+        let (mut stats, row_count) = self.analyze_rows(file)?;
+        let stats = stats.swap_remove(position);
+        let column = &self.columns[position];
+        let suggest_dictionary = column.column_type == ColumnType::Varchar
+            && !column.dictionary_encoded
+            && stats.distinct_count * 100 < row_count;
+        Ok(ColumnAnalysis {
+            stats,
+            row_count,
+            suggest_dictionary,
+        })
+    }
+
+    /// The stats of every column and the number of rows they were taken
+    /// over, written to the stats file.
+    fn analyze_rows(
+        &self,
+        file: &std::fs::File,
+    ) -> Result<(Vec<ColumnStats>, u64), DurabilityError> {
+        let mut stats: Vec<ColumnStats> = self
+            .columns
+            .iter()
+            .map(|column| ColumnStats {
+                min: vec![0; column.length as usize],
+                max: vec![0; column.length as usize],
+                distinct_count: 0,
+                null_count: 0,
+            })
+            .collect();
+        let mut distinct: Vec<HashSet<Vec<u8>>> = vec![HashSet::new(); self.columns.len()];
+        let mut row_count = 0;
+        for page_number in 0..self.page_count() {
+            let page = self.page_at(file, page_number)?;
+            for row in self.page_rows(&page) {
+                row_count += 1;
+                for (i, value) in row.data.into_iter().enumerate() {
+                    let column_stats = &mut stats[i];
+                    if value.iter().all(|b| *b == 0) {
+                        column_stats.null_count += 1;
+                        continue;
+                    }
+                    let column_type = &self.columns[i].column_type;
+                    if distinct[i].is_empty()
+                        || compare_values(&value, &column_stats.min, column_type).is_lt()
+                    {
+                        column_stats.min = value.clone();
+                    }
+                    if distinct[i].is_empty()
+                        || compare_values(&value, &column_stats.max, column_type).is_gt()
+                    {
+                        column_stats.max = value.clone();
+                    }
+                    distinct[i].insert(value);
+                }
+            }
+        }
+        for ((column_stats, values), column) in stats.iter_mut().zip(distinct).zip(&self.columns) {
+            column_stats.distinct_count = values.len() as u64;
+            // Decoded values of dictionary encoded columns are longer than
+            // the column, only their start is kept.
+            column_stats.min.resize(column.length as usize, 0);
+            column_stats.max.resize(column.length as usize, 0);
+        }
+
+        let bytes: Vec<u8> = stats.iter().flat_map(|stats| stats.bytes()).collect();
+        std::fs::write(stats_file(self.name_str()), bytes).map_err(DurabilityError::IoError)?;
+        file.write_all_at(&unix_time().to_ne_bytes(), self.table_stats_offset() + 16)
+            .map_err(DurabilityError::IoError)?;
+        Ok((stats, row_count))
+    }
+
+    /// Reads the stats written by the last `analyze`, `None` if the table was
+    /// never analyzed.
+    pub fn load_stats(&self) -> Result<Option<Vec<ColumnStats>>, DurabilityError> {
+        let file = match std::fs::File::open(stats_file(self.name_str())) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(DurabilityError::IoError(e)),
+        };
+
+        let mut stats = vec![];
+        let mut offset = 0;
+        for column in self.columns.iter() {
+            let length = column.length as usize;
+            let mut bytes = vec![0; length * 2 + 16];
+            file.read_exact_at(&mut bytes, offset)
+                .map_err(DurabilityError::IoError)?;
+            offset += bytes.len() as u64;
+            stats.push(ColumnStats::from_bytes(&bytes, length));
+        }
+        Ok(Some(stats))
+    }
+}
+
+/// Orders two stored values of the same column, numerically for numeric
+/// columns and byte wise otherwise. Dates sort correctly as `YYYY-MM-DD`.
+fn compare_values(a: &[u8], b: &[u8], column_type: &ColumnType) -> Ordering {
+    let text = |value: &[u8]| {
+        let value = value.split(|b| *b == 0).next().unwrap_or_default();
+        String::from_utf8_lossy(value).trim().to_string()
+    };
+    let ordering = match column_type {
+        ColumnType::Int => match (text(a).parse::<i64>(), text(b).parse::<i64>()) {
+            (Ok(a), Ok(b)) => Some(a.cmp(&b)),
+            _ => None,
+        },
+        ColumnType::Float => match (text(a).parse::<f64>(), text(b).parse::<f64>()) {
+            (Ok(a), Ok(b)) => a.partial_cmp(&b),
+            _ => None,
+        },
+        ColumnType::Timestamp => match (decode_timestamp(a), decode_timestamp(b)) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => None,
+        },
+        ColumnType::Decimal(..) => match (decode_decimal(a), decode_decimal(b)) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => None,
+        },
+        ColumnType::Varchar | ColumnType::Date => None,
+    };
+    ordering.unwrap_or_else(|| a.cmp(b))
+}
@@ -1,16 +1,67 @@
-use std::os::unix::fs::FileExt;
+use std::{
+    collections::{btree_map::Entry, BTreeMap, HashSet},
+    os::unix::fs::FileExt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+};
 
 use memmap::Mmap;
+use memmap::MmapMut;
 use memmap::MmapOptions;
 
+use crate::buffer::WriteBuffer;
+use crate::concurrency::{lock_manager, TableLock};
+use crate::durability::hash_index::hash;
+use crate::durability::index::{index_file, index_key, reindex_row, unindex_row, BTreeIndex};
+use crate::durability::lock_file::{
+    acquire_lock, lock_file, release_lock, remove_stale_locks, LOCK_MODE_WRITE,
+};
+use crate::durability::wal::{recover, wal_enabled, Wal};
+use crate::durability::DurabilityError;
 use crate::durability::Durable;
+use crate::query::predicate::Predicate;
 
+use super::column_definition::{DELTA_ENCODED_FLAG, DICTIONARY_ENCODED_FLAG, UNIQUE_FLAG};
+use super::compression::{
+    decode_page, encode_page, slot_header, COMPRESSED_PAGE_SIZE, PAGE_SLOT_HEADER_SIZE,
+};
+use super::dictionary::{dictionary_file, Dictionary};
+use super::foreign_key::{read_foreign_keys, value_exists};
+use super::stats::{unix_time, TABLE_STATS_SIZE};
+use super::table_exists;
 use super::ColumnDefinition;
 use super::ColumnType;
+use super::TableBuilder;
 
-const MAX_PAGE_SIZE: u64 = 128;
+pub const DEFAULT_PAGE_SIZE: u64 = 128;
+/// The size pages are cut to, rounded down to a whole number of rows. Pages
+/// are not recorded in the table file, so the size can change between runs.
+static MAX_PAGE_SIZE: AtomicU64 = AtomicU64::new(DEFAULT_PAGE_SIZE);
 
-#[derive(Debug)]
+/// Sets the page size of every table for the rest of the process.
+pub fn set_max_page_size(size: u64) {
+    MAX_PAGE_SIZE.store(size, Ordering::Relaxed);
+}
+
+const PRIMARY_KEY_OFFSET: u64 = 68;
+const TABLE_TYPE_OFFSET: u64 = 69;
+const COMPRESSION_OFFSET: u64 = 70;
+const COLUMN_DEFINITION_OFFSET: u64 = 71;
+const NO_PRIMARY_KEY: u8 = 0xFF;
+/// The row count follows the column definitions as a native endian u64.
+pub(super) const ROW_COUNT_SIZE: u64 = 8;
+/// The `table_type` of a table rows are inserted into.
+pub const BASE_TABLE: u8 = 0;
+/// The `table_type` of a table holding the stored result of a materialized
+/// view, only ever rewritten whole by a refresh.
+pub const MATERIALIZED_VIEW: u8 = 1;
+/// The first byte of a deleted row, the rest of it is zeroed. No stored value
+/// starts with it as values are UTF-8 text.
+pub(super) const TOMBSTONE: u8 = 0xFF;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Row {
     pub data: Vec<Vec<u8>>,
 }
@@ -20,6 +71,14 @@ pub struct Table {
     pub column_count: u32,
     pub columns: Vec<ColumnDefinition>,
     pub row_count: u64,
+    pub primary_key: u8,
+    pub table_type: u8,
+    /// Whether every page is stored LZ4 compressed in a slot of its own, see
+    /// `page_offset`.
+    pub compression_enabled: bool,
+    /// The table file writes held back by `buffered`, `None` while writes
+    /// go straight to the file.
+    write_buffer: Mutex<Option<WriteBuffer>>,
 }
 
 pub struct Page {
@@ -27,42 +86,54 @@ pub struct Page {
     pub page_number: u64,
 }
 
+/// What `Table::upsert_row` did with a row.
+#[derive(Debug, PartialEq)]
+pub enum Upsert {
+    Inserted,
+    Replaced,
+}
+
 impl Table {
     pub fn new(name: String, columns: Vec<ColumnDefinition>) -> Self {
         let name_bytes = name.as_bytes();
         let mut name_buffer = [0; 64];
         name_buffer[..name_bytes.len()].copy_from_slice(name_bytes);
+        let primary_key = columns
+            .iter()
+            .position(|column| column.primary_key)
+            .map_or(NO_PRIMARY_KEY, |i| i as u8);
         Table {
             name: name_buffer,
             column_count: columns.len() as u32,
             columns,
             row_count: 0,
+            primary_key,
+            table_type: BASE_TABLE,
+            compression_enabled: false,
+            write_buffer: Mutex::new(None),
+        }
+    }
+
+    pub fn primary_key_column(&self) -> Option<usize> {
+        match self.primary_key {
+            NO_PRIMARY_KEY => None,
+            column => Some(column as usize),
         }
     }
 
     pub fn page_size(&self) -> u64 {
         let row_size = self.row_size();
-        if row_size < MAX_PAGE_SIZE {
-            MAX_PAGE_SIZE - (MAX_PAGE_SIZE % row_size)
+        let max_page_size = match self.compression_enabled {
+            true => COMPRESSED_PAGE_SIZE,
+            false => MAX_PAGE_SIZE.load(Ordering::Relaxed),
+        };
+        if row_size < max_page_size {
+            max_page_size - (max_page_size % row_size)
         } else {
             row_size
         }
     }
 
-    pub fn page_data(&self, file: &std::fs::File, page: u64) -> Result<Vec<u8>, String> {
-        let mmap = self.page_at(file, page);
-        if let Err(e) = mmap {
-            return Err(format!("Error getting page data: {:?}", e));
-        }
-
-        if let Err(e) = mmap {
-            return Err(format!("Error getting page data: {:?}", e));
-        }
-        let mmap = mmap.unwrap();
-
-        Ok(mmap.data.to_vec())
-    }
-
     pub fn page_count(&self) -> u64 {
         let row_size = self.row_size();
         let page_size = self.page_size();
@@ -74,42 +145,137 @@ impl Table {
         (row_size * row_count / page_size) + 1
     }
 
-    pub fn add_page(&mut self, file: &mut std::fs::File) -> Result<(), String> {
-        let page_size = self.page_size();
-        let page_offset: u64 = match self.row_count == 0 {
-            true => self.header_size(),
-            false => (self.header_size()) + ((self.page_count()) * page_size),
-        };
+    /// The page holding the row at `row_index`.
+    pub fn page_of_row(&self, row_index: u64) -> u64 {
+        row_index * self.row_size() / self.page_size()
+    }
 
-        let page = vec![0; page_size as usize];
-        if let Err(e) = file.write_all_at(&page, page_offset.into()) {
-            return Err(format!("Error adding page to table: {:?}", e));
+    /// Where the row at `row_index` starts in the table file. Pages hold a
+    /// whole number of rows, so rows follow each other across pages.
+    pub fn row_offset_bytes(&self, row_index: u64) -> u64 {
+        self.header_size() + self.row_size() * row_index
+    }
+
+    /// The row at `row_index`, read from the page holding it.
+    #[allow(dead_code)]
+    pub fn row_at(&self, file: &std::fs::File, row_index: u64) -> Result<Row, DurabilityError> {
+        if row_index >= self.row_count {
+            return Err(DurabilityError::DbError(format!(
+                "No row {} in table {}",
+                row_index,
+                self.name_str()
+            )));
         }
+        let page = self.page_at(file, self.page_of_row(row_index))?;
+        self.page_entries(&page)
+            .into_iter()
+            .find(|(index, _)| *index == row_index)
+            .map(|(_, row)| row)
+            .ok_or_else(|| DurabilityError::DbError(format!("Row {} is deleted", row_index)))
+    }
 
-        Ok(())
+    /// The bytes a page takes in the table file. Pages of compressed tables
+    /// are stored in slots as large as the page uncompressed with the slot
+    /// header ahead, so every page keeps its place in the file. Only the
+    /// stored bytes of a slot are written, the rest of it is left a hole.
+    pub fn page_slot_size(&self) -> u64 {
+        match self.compression_enabled {
+            true => PAGE_SLOT_HEADER_SIZE + self.page_size(),
+            false => self.page_size(),
+        }
+    }
+
+    /// Where the page starts in the table file.
+    pub fn page_offset(&self, page: u64) -> u64 {
+        self.header_size() + page * self.page_slot_size()
     }
 
-    pub fn page_at(&self, file: &std::fs::File, page: u64) -> Result<Page, String> {
+    fn next_page_offset(&self) -> u64 {
+        self.page_offset(self.page_count())
+    }
+
+    pub fn add_page(&mut self, file: &mut std::fs::File) -> Result<(), DurabilityError> {
+        let mut page = vec![0; self.page_size() as usize];
+        if self.compression_enabled {
+            page = encode_page(&page);
+        }
+        file.write_all_at(&page, self.next_page_offset())
+            .map_err(DurabilityError::IoError)
+    }
+
+    /// The page of a compressed table, decompressed. A slot past the end of
+    /// the file holds a page of zeros.
+    fn read_compressed_page(
+        &self,
+        file: &std::fs::File,
+        page: u64,
+    ) -> Result<Vec<u8>, DurabilityError> {
+        let offset = self.page_offset(page);
+        let mut header = [0; PAGE_SLOT_HEADER_SIZE as usize];
+        let (flag, size) = match file.read_exact_at(&mut header, offset) {
+            Ok(()) => slot_header(&header),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => (0, 0),
+            Err(e) => return Err(DurabilityError::IoError(e)),
+        };
+        let mut data = vec![0; size];
+        file.read_exact_at(&mut data, offset + PAGE_SLOT_HEADER_SIZE)
+            .map_err(DurabilityError::IoError)?;
+        Ok(decode_page(flag, &data, self.page_size() as usize)?)
+    }
+
+    /// Whether pages are stored other than as the rows they hold, so they
+    /// are read whole and decoded instead of mapped.
+    fn encoded_pages(&self) -> bool {
+        self.compression_enabled || self.has_delta_columns()
+    }
+
+    /// The rows of the page as `page_entries` reads them, decompressed and
+    /// with the values of delta encoded columns added up. The part of the
+    /// page past the end of the file holds zeros.
+    fn decoded_page(&self, file: &std::fs::File, page: u64) -> Result<Vec<u8>, DurabilityError> {
+        self.flush(file)?;
+        let mut data = match self.compression_enabled {
+            true => self.read_compressed_page(file, page)?,
+            false => {
+                let mut data = vec![0; self.page_size() as usize];
+                let mut read = 0;
+                while read < data.len() {
+                    match file.read_at(&mut data[read..], self.page_offset(page) + read as u64) {
+                        Ok(0) => break,
+                        Ok(length) => read += length,
+                        Err(e) => return Err(DurabilityError::IoError(e)),
+                    }
+                }
+                data
+            }
+        };
+        self.decode_deltas(&mut data)?;
+        Ok(data)
+    }
+
+    pub fn page_at(&self, file: &std::fs::File, page: u64) -> Result<Page, DurabilityError> {
         if page > self.page_count() {
-            return Err("Invalid page number".to_string());
+            return Err("Invalid page number".to_string().into());
         }
-        let offset = self.header_size() + (page * self.page_size());
+        self.flush(file)?;
+        if self.encoded_pages() {
+            let data = self.decoded_page(file, page)?;
+            let mut mmap = MmapMut::map_anon(data.len()).map_err(DurabilityError::IoError)?;
+            mmap.copy_from_slice(&data);
+            return Ok(Page {
+                data: mmap.make_read_only().map_err(DurabilityError::IoError)?,
+                page_number: page,
+            });
+        }
+        let offset = self.page_offset(page);
 
         let mmap = unsafe {
             MmapOptions::new()
                 .len(self.page_size() as usize)
                 .offset(offset)
                 .map(file)
-        };
-
-        if let Err(e) = mmap {
-            return Err(format!("Error mapping page to memory: {:?}", e));
         }
-
-        if let Err(e) = mmap {
-            return Err(format!("Error mapping page to memory: {:?}", e));
-        }
-        let mmap = mmap.unwrap();
+        .map_err(DurabilityError::IoError)?;
 
         Ok(Page {
             data: mmap,
@@ -117,13 +283,10 @@ impl Table {
         })
     }
 
-    pub fn page_rows(&self, page: &Page) -> Vec<Row> {
-        let mut rows = vec![];
-        let row_size = self.row_size() as usize;
-        let page_size = self.page_size() as usize;
-        let rows_in_page = page_size / row_size;
-
-        let row_count = if self.row_count as usize > rows_in_page {
+    /// The number of rows stored in the page.
+    pub(super) fn page_row_count(&self, page: &Page) -> usize {
+        let rows_in_page = (self.page_size() / self.row_size()) as usize;
+        if self.row_count as usize > rows_in_page {
             if page.page_number == self.page_count() - 1 {
                 self.row_count as usize % rows_in_page
             } else {
@@ -131,21 +294,72 @@ impl Table {
             }
         } else {
             self.row_count as usize
-        };
+        }
+    }
+
+    /// The rows stored in the page, deleted rows left out.
+    pub fn page_rows(&self, page: &Page) -> Vec<Row> {
+        self.page_entries(page)
+            .into_iter()
+            .map(|(_, row)| row)
+            .collect()
+    }
+
+    /// The rows stored in the page along with their index in the table,
+    /// deleted rows left out. Indexes refer to rows by that index.
+    pub fn page_entries(&self, page: &Page) -> Vec<(u64, Row)> {
+        let mut rows = vec![];
+        let row_size = self.row_size() as usize;
+        let row_count = self.page_row_count(page);
+        let first_row = page.page_number * (self.page_size() / self.row_size());
 
         for i in 0..row_count {
             let row_start = i * row_size;
             let row_end = row_start + row_size;
             let row_data = page.data[row_start..row_end].to_vec();
+            if row_data.first() == Some(&TOMBSTONE) {
+                continue;
+            }
             let mut row = vec![];
-            let mut j = 0;
+            let mut column_start = 0;
             for column in self.columns.iter() {
-                let column_start = column.length as usize * j;
                 let column_end = column_start + column.length as usize;
-                row.push(row_data[column_start..column_end].to_vec());
-                j += 1;
+                row.push(column.decoded_value(&row_data[column_start..column_end]));
+                column_start = column_end;
+            }
+            rows.push((first_row + i as u64, Row { data: row }));
+        }
+        rows
+    }
+
+    /// Decodes every row of the page to its values as text, each cut at its
+    /// first null byte. Goes a column at a time over all the rows instead of
+    /// a row at a time, so the inner loop reads the same slice of every row
+    /// and no `Row` is built in between.
+    pub fn decode_rows_batch(&self, page: &Page) -> Vec<Vec<String>> {
+        let row_size = self.row_size() as usize;
+        let row_data: Vec<&[u8]> = page
+            .data
+            .chunks_exact(row_size)
+            .take(self.page_row_count(page))
+            .filter(|row_data| row_data[0] != TOMBSTONE)
+            .collect();
+        let mut rows = vec![Vec::with_capacity(self.columns.len()); row_data.len()];
+        let mut column_start = 0;
+        for column in self.columns.iter() {
+            let column_end = column_start + column.length as usize;
+            for (row, row_data) in rows.iter_mut().zip(row_data.iter()) {
+                let value = &row_data[column_start..column_end];
+                if column.dictionary_encoded {
+                    row.push(
+                        String::from_utf8_lossy(&column.dictionary.decode(value)).into_owned(),
+                    );
+                    continue;
+                }
+                let length = value.iter().position(|b| *b == 0).unwrap_or(value.len());
+                row.push(String::from_utf8_lossy(&value[..length]).into_owned());
             }
-            rows.push(Row { data: row });
+            column_start = column_end;
         }
         rows
     }
@@ -157,126 +371,166 @@ impl Table {
     }
 
     pub fn header_size(&self) -> u64 {
-        68 + (self.column_count as u64 * ColumnDefinition::size()) + 8
+        COLUMN_DEFINITION_OFFSET
+            + self.column_definitions_size()
+            + ROW_COUNT_SIZE
+            + TABLE_STATS_SIZE
     }
 
-    pub fn last_page_at_limit(&self) -> bool {
-        let row_size = self.row_size();
-        let page_size = self.page_size();
-        let row_count = self.row_count;
-
-        (row_size * row_count) % page_size == 0
+    pub fn row_count_offset(&self) -> u64 {
+        COLUMN_DEFINITION_OFFSET + self.column_definitions_size()
     }
 
-    pub fn add_row(&mut self, row: &Row, file: &mut std::fs::File) -> Result<(), String> {
-        if row.data.len() != self.column_count as usize {
-            return Err(format!(
-                "Invalid row data expected {} columns got {} ",
-                self.column_count,
-                row.data.len()
-            ));
-        }
+    /// Where the `TableStats` are kept, right after the row count.
+    pub fn table_stats_offset(&self) -> u64 {
+        self.row_count_offset() + ROW_COUNT_SIZE
+    }
 
-        let mut row_bytes: Vec<u8> = vec![];
+    pub fn name_str(&self) -> &str {
+        super::name_str(&self.name)
+    }
 
-        for (i, column) in self.columns.iter().enumerate() {
-            if row.data[i].len() > column.length as usize {
-                return Err("Invalid column data".to_string());
-            }
+    /// Where the definition of the column at `position` starts.
+    pub fn column_definition_offset(&self, position: usize) -> u64 {
+        COLUMN_DEFINITION_OFFSET
+            + self.columns[..position]
+                .iter()
+                .fold(0, |acc, column| acc + column.size())
+    }
 
-            let resized_data = {
-                let mut data = row.data[i].clone();
-                data.resize(column.length as usize, 0);
-                data
-            };
+    pub fn column_definitions_size(&self) -> u64 {
+        self.columns
+            .iter()
+            .fold(0, |acc, column| acc + column.size())
+    }
 
-            row_bytes.extend(resized_data.iter());
+    /// An FNV-1a hash of the schema as the header stores it, the column
+    /// count and the name, type and length of every column.
+    pub fn schema_hash(&self) -> u64 {
+        let mut bytes = self.column_count.to_ne_bytes().to_vec();
+        for column in self.columns.iter() {
+            bytes.extend_from_slice(&column.name);
+            bytes.extend(column.column_type.bytes());
+            bytes.extend(
+                column
+                    .column_type
+                    .header_length(column.length)
+                    .to_ne_bytes(),
+            );
         }
+        hash(&bytes)
+    }
 
-        if row_bytes.len() != self.row_size() as usize {
-            return Err(format!(
-                "Invalid final row size got {}, expected {}",
-                row_bytes.len(),
-                self.row_size()
-            ));
+    /// An empty table named `new_name` with the columns of this one, for
+    /// creating a table like an existing one. Nothing is written, the
+    /// caller writes it with `write_to_disk` and `add_page`, or hands it to
+    /// `create_table_from`. The name is checked like `TableBuilder` does.
+    pub fn clone_schema(&self, new_name: &str) -> Result<Table, DurabilityError> {
+        let mut columns = self.columns.clone();
+        for column in columns.iter_mut() {
+            column.dictionary = Dictionary::default();
         }
+        Ok(TableBuilder::new(new_name).columns(columns).build()?)
+    }
 
-        if self.last_page_at_limit() && self.add_page(file).is_err() {
-            return Err("Error adding page to table".to_string());
-        }
+    /// Whether the rows of `other` fit this table as they are: the same
+    /// number of columns, of the same types and lengths in the same order.
+    /// Names are not compared.
+    pub fn is_schema_compatible(&self, other: &Table) -> bool {
+        self.columns.len() == other.columns.len()
+            && self
+                .columns
+                .iter()
+                .zip(other.columns.iter())
+                .all(|(column, other)| {
+                    column.column_type == other.column_type && column.length == other.length
+                })
+    }
 
-        if let Err(e) = file.write_all_at(
-            &row_bytes,
-            self.header_size() + (self.row_size() * self.row_count),
-        ) {
-            return Err(format!("Error writing row to disk: {:?}", e));
-        }
+    /// `schema_hash` of the header in `file`, reading the column names,
+    /// types and lengths and skipping the rest of the definitions.
+    fn read_schema_hash(file: &std::fs::File) -> Result<u64, DurabilityError> {
+        let mut column_count_buff: [u8; 4] = [0; 4];
+        file.read_exact_at(&mut column_count_buff, 64)
+            .map_err(DurabilityError::IoError)?;
+        let mut bytes = column_count_buff.to_vec();
 
-        self.row_count += 1;
-        if let Err(e) = self.write_row_count_to_disk(file) {
-            return Err(format!("Error updating table row count: {:?}", e));
-        }
+        let mut offset = COLUMN_DEFINITION_OFFSET;
+        for _ in 0..u32::from_ne_bytes(column_count_buff) {
+            // The name, the type code and the length stored in the header.
+            let mut column_buff: [u8; 76] = [0; 76];
+            file.read_exact_at(&mut column_buff, offset)
+                .map_err(DurabilityError::IoError)?;
+            bytes.extend_from_slice(&column_buff);
 
-        Ok(())
+            let code = u32::from_ne_bytes(column_buff[64..68].try_into().unwrap());
+            let header_length = u64::from_ne_bytes(column_buff[68..76].try_into().unwrap());
+            let (_, length) = ColumnType::from_header(code, header_length).ok_or_else(|| {
+                DurabilityError::DbError(format!("Invalid column type: {}", code))
+            })?;
+            // Past the default flag and value, the flags and the check.
+            offset += 76 + 1 + length + 1 + 128;
+        }
+        Ok(hash(&bytes))
     }
 
-    pub fn write_row_count_to_disk(&self, file: &mut std::fs::File) -> Result<(), String> {
-        if let Err(e) = file.write_all_at(
-            &self.row_count.to_ne_bytes(),
-            68 + (self.column_count as u64 * ColumnDefinition::size()),
-        ) {
-            return Err(format!("Error writing row count to disk: {:?}", e));
+    /// Refuses to write when another process changed the schema since the
+    /// table was read, the rows would go in with the old layout.
+    fn check_schema(&self, file: &std::fs::File) -> Result<(), DurabilityError> {
+        let cached_hash = self.schema_hash();
+        let disk_hash = Table::read_schema_hash(file)?;
+        if cached_hash != disk_hash {
+            return Err(DurabilityError::SchemaMismatch {
+                cached_hash,
+                disk_hash,
+            });
         }
+        Ok(())
+    }
 
+    /// Re-reads the header to see the rows and changes other processes
+    /// wrote, without the recovery `read_from_disk` runs when the table is
+    /// opened.
+    pub fn refresh(&mut self, file: &std::fs::File) -> Result<(), DurabilityError> {
+        *self = Table::read_header(file)?;
         Ok(())
     }
-}
 
-impl Durable for Table {
-    fn write_to_disk(&mut self, file: &mut std::fs::File) -> Result<(), super::DurabilityError> {
-        if let Err(e) = file.write_all_at(&self.name, 0) {
+    /// The table as its header in `file` describes it.
+    fn read_header(file: &std::fs::File) -> Result<Table, DurabilityError> {
+        let mut name_buff: [u8; 64] = [0; 64];
+
+        if let Err(e) = file.read_exact_at(&mut name_buff, 0) {
             return Err(super::DurabilityError::IoError(e));
         }
 
-        let column_count_bytes = self.column_count.to_ne_bytes();
-        if let Err(e) = file.write_all_at(&column_count_bytes, 64) {
+        let mut column_count_buff: [u8; 4] = [0; 4];
+        if let Err(e) = file.read_exact_at(&mut column_count_buff, 64) {
             return Err(super::DurabilityError::IoError(e));
         }
 
-        println!("Column count: {:?}", column_count_bytes);
+        let column_count = u32::from_ne_bytes(column_count_buff);
 
-        const COLUMN_DEFINITION_OFFSET: u64 = 68;
-        let mut offset = COLUMN_DEFINITION_OFFSET;
-        for column in &self.columns {
-            let bytes = column.bytes();
-            if let Err(e) = file.write_all_at(&bytes, offset) {
-                return Err(super::DurabilityError::IoError(e));
-            }
-            offset += ColumnDefinition::size();
+        let mut primary_key_buff: [u8; 1] = [0; 1];
+        if let Err(e) = file.read_exact_at(&mut primary_key_buff, PRIMARY_KEY_OFFSET) {
+            return Err(super::DurabilityError::IoError(e));
         }
+        let primary_key = primary_key_buff[0];
 
-        let _ = self.write_row_count_to_disk(file);
-        Ok(())
-    }
-
-    fn read_from_disk(file: &mut std::fs::File) -> Result<Self, super::DurabilityError>
-    where
-        Self: Sized,
-    {
-        let mut name_buff: [u8; 64] = [0; 64];
-
-        if let Err(e) = file.read_exact_at(&mut name_buff, 0) {
+        let mut table_type_buff: [u8; 1] = [0; 1];
+        if let Err(e) = file.read_exact_at(&mut table_type_buff, TABLE_TYPE_OFFSET) {
             return Err(super::DurabilityError::IoError(e));
         }
+        let table_type = table_type_buff[0];
 
-        let mut column_count_buff: [u8; 4] = [0; 4];
-        if let Err(e) = file.read_exact_at(&mut column_count_buff, 64) {
+        let mut compression_buff: [u8; 1] = [0; 1];
+        if let Err(e) = file.read_exact_at(&mut compression_buff, COMPRESSION_OFFSET) {
             return Err(super::DurabilityError::IoError(e));
         }
+        let compression_enabled = compression_buff[0] != 0;
 
-        let column_count = u32::from_ne_bytes(column_count_buff);
         //read the column definitions
-        let mut offset = 68;
+        let mut offset = COLUMN_DEFINITION_OFFSET;
         let mut columns = vec![];
         for _ in 0..column_count {
             let mut column_name_buff: [u8; 64] = [0; 64];
@@ -290,10 +544,19 @@ impl Durable for Table {
                 return Err(super::DurabilityError::IoError(e));
             }
             offset += 4;
-            let column_type = match u32::from_ne_bytes(column_type_buff) {
-                1 => ColumnType::Int,
-                2 => ColumnType::Varchar,
-                _ => {
+
+            let mut column_length_buff: [u8; 8] = [0; 8];
+            if let Err(e) = file.read_exact_at(&mut column_length_buff, offset) {
+                return Err(super::DurabilityError::IoError(e));
+            }
+            offset += 8;
+
+            let (column_type, column_length) = match ColumnType::from_header(
+                u32::from_ne_bytes(column_type_buff),
+                u64::from_ne_bytes(column_length_buff),
+            ) {
+                Some(column) => column,
+                None => {
                     return Err(super::DurabilityError::DbError(format!(
                         "Invalid column type: {}",
                         column_type_buff[0]
@@ -301,34 +564,909 @@ impl Durable for Table {
                 }
             };
 
-            let mut column_length_buff: [u8; 8] = [0; 8];
-            if let Err(e) = file.read_exact_at(&mut column_length_buff, offset) {
+            let mut default_flag_buff: [u8; 1] = [0; 1];
+            if let Err(e) = file.read_exact_at(&mut default_flag_buff, offset) {
                 return Err(super::DurabilityError::IoError(e));
             }
-            offset += 8;
+            offset += 1;
+
+            let mut default_value_buff = vec![0; column_length as usize];
+            if let Err(e) = file.read_exact_at(&mut default_value_buff, offset) {
+                return Err(super::DurabilityError::IoError(e));
+            }
+            offset += column_length;
+
+            let mut flags_buff: [u8; 1] = [0; 1];
+            if let Err(e) = file.read_exact_at(&mut flags_buff, offset) {
+                return Err(super::DurabilityError::IoError(e));
+            }
+            offset += 1;
 
-            let column_length = u64::from_ne_bytes(column_length_buff);
+            let mut check_expr_buff: [u8; 128] = [0; 128];
+            if let Err(e) = file.read_exact_at(&mut check_expr_buff, offset) {
+                return Err(super::DurabilityError::IoError(e));
+            }
+            offset += 128;
+            let check_expr = match check_expr_buff[0] {
+                0 => None,
+                _ => Some(check_expr_buff),
+            };
+
+            let default_value = match default_flag_buff[0] {
+                0 => None,
+                _ => {
+                    let value_length = default_value_buff
+                        .iter()
+                        .rposition(|b| *b != 0)
+                        .map_or(0, |i| i + 1);
+                    default_value_buff.truncate(value_length);
+                    Some(default_value_buff)
+                }
+            };
 
             columns.push(ColumnDefinition {
                 name: column_name_buff,
                 column_type,
                 length: column_length,
+                default_value,
+                unique: flags_buff[0] & UNIQUE_FLAG != 0,
+                delta_encoded: flags_buff[0] & DELTA_ENCODED_FLAG != 0,
+                dictionary_encoded: flags_buff[0] & DICTIONARY_ENCODED_FLAG != 0,
+                check_expr,
+                primary_key: columns.len() == primary_key as usize,
+                references: None,
+                dictionary: Dictionary::default(),
             });
         }
 
         let row_count = {
-            let mut row_count_buff: [u8; 8] = [0; 8];
+            let mut row_count_buff = [0; ROW_COUNT_SIZE as usize];
             if let Err(e) = file.read_exact_at(&mut row_count_buff, offset) {
                 return Err(super::DurabilityError::IoError(e));
             }
             u64::from_ne_bytes(row_count_buff)
         };
 
-        Ok(Table {
+        let name = String::from_utf8_lossy(name_buff.split(|b| *b == 0).next().unwrap_or_default())
+            .to_string();
+        for foreign_key in read_foreign_keys(&name)? {
+            if foreign_key.table != name {
+                continue;
+            }
+            let column = columns
+                .iter_mut()
+                .find(|c| c.name_str() == foreign_key.column);
+            if let Some(column) = column {
+                column.references =
+                    Some((foreign_key.referenced_table, foreign_key.referenced_column));
+            }
+        }
+
+        let mut table = Table {
             name: name_buff,
             column_count,
             columns,
             row_count,
-        })
+            primary_key,
+            table_type,
+            compression_enabled,
+            write_buffer: Mutex::new(None),
+        };
+        table.read_dictionaries()?;
+        Ok(table)
+    }
+
+    /// Appends `row`, see `add_rows`.
+    pub fn add_row(&mut self, row: &Row, file: &mut std::fs::File) -> Result<(), DurabilityError> {
+        self.add_rows(std::slice::from_ref(row), file)
+    }
+
+    /// Overwrites the row holding the same primary key as `row`, found
+    /// through the index on the primary key when there is one, or appends
+    /// `row` when no row holds it.
+    pub fn upsert_row(
+        &mut self,
+        row: &Row,
+        file: &mut std::fs::File,
+    ) -> Result<Upsert, DurabilityError> {
+        let column = self.primary_key_column().ok_or_else(|| {
+            DurabilityError::DbError(format!("Table {} has no primary key", self.name_str()))
+        })?;
+        let length = self.columns[column].length as usize;
+        let key = match row.data.get(column) {
+            Some(key) if key.len() <= length && row.data.len() == self.columns.len() => {
+                let mut key = key.clone();
+                key.resize(length, 0);
+                key
+            }
+            // Rejected with the reason by `add_row`.
+            _ => return self.add_row(row, file).map(|()| Upsert::Inserted),
+        };
+
+        match self.find_primary_key_row(file, &key)? {
+            Some(row_index) => self
+                .replace_row(file, row_index, row)
+                .map(|()| Upsert::Replaced),
+            None => self.add_row(row, file).map(|()| Upsert::Inserted),
+        }
+    }
+
+    /// Overwrites the row at `row_index` with `row`, checked against the
+    /// constraints of the other rows, and updates the indexes for it.
+    pub fn replace_row(
+        &mut self,
+        file: &mut std::fs::File,
+        row_index: u64,
+        row: &Row,
+    ) -> Result<(), DurabilityError> {
+        self.check_schema(file)?;
+        self.add_dictionary_entries(std::slice::from_ref(row))?;
+        let name = self.name_str();
+        let locks = lock_manager();
+        locks.acquire_write_lock(name, row_index);
+        let result = self.read_row(file, row_index).and_then(|old_row| {
+            let row_bytes = self.row_bytes(row, |column_index, value| {
+                let found = self.find_row(file, column_index, value)?;
+                Ok(found.is_some() && found != Some(row_index))
+            })?;
+            let offset = self.row_offset_bytes(row_index);
+            self.write_logged(&[(offset, row_bytes)], file)?;
+            self.record_modified(self.row_count, file)?;
+            reindex_row(self, row_index, &old_row, row)
+        });
+        locks.release_lock(name, row_index);
+        result
+    }
+
+    /// The index of the row holding `key` in the primary key column, which
+    /// must be padded to the column length.
+    fn find_primary_key_row(
+        &self,
+        file: &std::fs::File,
+        key: &[u8],
+    ) -> Result<Option<u64>, DurabilityError> {
+        let column = self.primary_key_column().unwrap();
+        let path = index_file(self.name_str(), self.columns[column].name_str());
+        if !table_exists(&path) {
+            return self.find_row(file, column, key);
+        }
+
+        let index = BTreeIndex::read(&path)?;
+        // A partial index may not hold the row.
+        if index.predicate.is_some() {
+            return self.find_row(file, column, key);
+        }
+        let candidates = index
+            .lookup(&index_key(key, &self.columns[column].column_type))
+            .iter()
+            .copied()
+            .chain(index.indexed_rows..self.row_count);
+        for row_index in candidates.filter(|row_index| *row_index < self.row_count) {
+            if self.read_row(file, row_index)?.data[column] == key {
+                return Ok(Some(row_index));
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_row(&self, file: &std::fs::File, row_index: u64) -> Result<Row, DurabilityError> {
+        let mut row_bytes = vec![0; self.row_size() as usize];
+        if self.encoded_pages() {
+            let page_number = self.page_of_row(row_index);
+            let start = (self.row_size() * row_index - page_number * self.page_size()) as usize;
+            let page = self.decoded_page(file, page_number)?;
+            let end = start + row_bytes.len();
+            row_bytes.copy_from_slice(&page[start..end]);
+        } else {
+            self.flush(file)?;
+            file.read_exact_at(&mut row_bytes, self.row_offset_bytes(row_index))
+                .map_err(DurabilityError::IoError)?;
+        }
+
+        let mut data = vec![];
+        let mut column_start = 0;
+        for column in self.columns.iter() {
+            let column_end = column_start + column.length as usize;
+            data.push(column.decoded_value(&row_bytes[column_start..column_end]));
+            column_start = column_end;
+        }
+        Ok(Row { data })
+    }
+
+    /// Appends `rows` with a single write of the table file and a single
+    /// commit of the redo log. The table is locked exclusively while the rows
+    /// are checked and written, either every row is appended or none is.
+    pub fn add_rows(
+        &mut self,
+        rows: &[Row],
+        file: &mut std::fs::File,
+    ) -> Result<(), DurabilityError> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        self.check_schema(file)?;
+        let name = self.name_str().to_string();
+        let locks = lock_manager();
+        locks.acquire_table_lock(&name, TableLock::Exclusive);
+        let result = self.append_rows_locked(rows, file);
+        locks.release_table_lock(&name);
+        result
+    }
+
+    /// `append_rows` while a lock on the appended rows is recorded in the
+    /// lock file, where a crash in between leaves it for `read_from_disk` to
+    /// remove.
+    fn append_rows_locked(
+        &mut self,
+        rows: &[Row],
+        file: &std::fs::File,
+    ) -> Result<(), DurabilityError> {
+        let name = self.name_str();
+        let row_offset =
+            self.header_size() + self.row_size() * self.read_row_count_from_disk(file)?;
+        let path = lock_file(name);
+        let lock_id = acquire_lock(&path, name, row_offset, LOCK_MODE_WRITE)?;
+        let result = self.append_rows(rows, file);
+        release_lock(&path, lock_id)?;
+        result
+    }
+
+    fn append_rows(&mut self, rows: &[Row], file: &std::fs::File) -> Result<(), DurabilityError> {
+        self.row_count = self.read_row_count_from_disk(file)?;
+        self.add_dictionary_entries(rows)?;
+
+        // Unique values are collected in one scan instead of one per row, so
+        // duplicates within the batch are caught as well.
+        let constrained: Vec<bool> = (0..self.columns.len())
+            .map(|i| self.primary_key_column() == Some(i) || self.columns[i].unique)
+            .collect();
+        let mut values: Vec<HashSet<Vec<u8>>> = vec![HashSet::new(); self.columns.len()];
+        if constrained.contains(&true) {
+            for page_number in 0..self.page_count() {
+                let page = self.page_at(file, page_number)?;
+                for row in self.page_rows(&page) {
+                    for (i, value) in row.data.into_iter().enumerate() {
+                        if constrained[i] {
+                            values[i].insert(value);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(rows.len() * self.row_size() as usize);
+        for row in rows {
+            bytes.extend(self.row_bytes(row, |column_index, value| {
+                Ok(!values[column_index].insert(value.to_vec()))
+            })?);
+        }
+
+        // Pad up to the end of the last page so it can be mapped whole.
+        let start = self.header_size() + (self.row_size() * self.row_count);
+        let row_count = self.row_count + rows.len() as u64;
+        let page_count = (self.row_size() * row_count / self.page_size()) + 1;
+        bytes.resize(
+            (self.header_size() + page_count * self.page_size() - start) as usize,
+            0,
+        );
+
+        self.write_logged(
+            &[
+                (start, bytes),
+                (self.row_count_offset(), row_count.to_ne_bytes().to_vec()),
+            ],
+            file,
+        )?;
+        self.record_modified(row_count, file)?;
+        self.row_count = row_count;
+        Ok(())
+    }
+
+    /// Checks `row` against the table's constraints and lays it out as
+    /// stored. `is_duplicate` tells whether a value of a primary key or
+    /// unique column is already taken.
+    fn row_bytes(
+        &self,
+        row: &Row,
+        mut is_duplicate: impl FnMut(usize, &[u8]) -> Result<bool, DurabilityError>,
+    ) -> Result<Vec<u8>, DurabilityError> {
+        if row.data.len() != self.column_count as usize {
+            return Err(DurabilityError::DbError(format!(
+                "Invalid row data expected {} columns got {} ",
+                self.column_count,
+                row.data.len()
+            )));
+        }
+
+        let mut row_bytes: Vec<u8> = vec![];
+
+        for (i, column) in self.columns.iter().enumerate() {
+            let resized_data = match column.dictionary_encoded {
+                true => column.dictionary.encode(&row.data[i]),
+                false if row.data[i].len() > column.length as usize => None,
+                false => {
+                    let mut data = row.data[i].clone();
+                    data.resize(column.length as usize, 0);
+                    Some(data)
+                }
+            };
+            let Some(resized_data) = resized_data else {
+                return Err(DurabilityError::DbError("Invalid column data".to_string()));
+            };
+
+            if self.primary_key_column() == Some(i) {
+                if row.data[i].iter().all(|b| *b == 0) {
+                    return Err(DurabilityError::ConstraintViolation(format!(
+                        "Primary key column {} cannot be null",
+                        column.name_str()
+                    )));
+                }
+                if is_duplicate(i, &resized_data)? {
+                    return Err(DurabilityError::ConstraintViolation(format!(
+                        "Duplicate value for primary key column {}",
+                        column.name_str()
+                    )));
+                }
+            }
+
+            if let Some((referenced_table, referenced_column)) = &column.references {
+                let value = &row.data[i];
+                if !value.iter().all(|b| *b == 0)
+                    && !value_exists(referenced_table, referenced_column, value)?
+                {
+                    return Err(DurabilityError::ForeignKeyViolation {
+                        table: referenced_table.clone(),
+                        column: referenced_column.clone(),
+                        value: String::from_utf8_lossy(value).to_string(),
+                    });
+                }
+            }
+
+            if let Some(check_expr) = &column.check_expr {
+                let expr = check_expr.split(|b| *b == 0).next().unwrap_or_default();
+                let expr = String::from_utf8_lossy(expr).to_string();
+                let passed = Predicate::parse(&expr)
+                    .is_some_and(|predicate| predicate.evaluate(&row.data[i], &column.column_type));
+                if !passed {
+                    return Err(DurabilityError::CheckConstraintViolation {
+                        column: column.name_str().to_string(),
+                        expr,
+                    });
+                }
+            }
+
+            if column.unique && is_duplicate(i, &resized_data)? {
+                return Err(DurabilityError::ConstraintViolation(format!(
+                    "Duplicate value for unique column {}",
+                    column.name_str()
+                )));
+            }
+
+            row_bytes.extend(resized_data.iter());
+        }
+
+        if row_bytes.len() != self.row_size() as usize {
+            return Err(DurabilityError::DbError(format!(
+                "Invalid final row size got {}, expected {}",
+                row_bytes.len(),
+                self.row_size()
+            )));
+        }
+        Ok(row_bytes)
+    }
+
+    /// Writes the new page, the row and the row count through the redo log.
+    /// Logs `(offset, data)` writes to the table's redo log before applying
+    /// them, so a crash part way through is repaired by `read_from_disk`. The
+    /// table file is not synced, the log is only emptied by a checkpoint.
+    /// Nothing is logged while the redo log is turned off.
+    pub(super) fn write_logged(
+        &self,
+        writes: &[(u64, Vec<u8>)],
+        file: &std::fs::File,
+    ) -> Result<(), DurabilityError> {
+        let mut writes = writes.to_vec();
+        if self.has_delta_columns() {
+            writes = self.delta_encoded_writes(&writes, file)?;
+        }
+        if self.compression_enabled {
+            writes = self.compressed_writes(&writes, file)?;
+        }
+        if wal_enabled() {
+            let mut wal = Wal::open(self.name_str())?;
+            for (offset, data) in writes.iter() {
+                wal.append(*offset, data)?;
+            }
+            wal.commit()?;
+        }
+
+        if let Some(buffer) = self.lock_write_buffer().as_mut() {
+            let mut full = false;
+            for (offset, data) in writes.iter() {
+                full |= buffer.push(*offset, data);
+            }
+            if full {
+                buffer.flush(file).map_err(DurabilityError::IoError)?;
+            }
+            return Ok(());
+        }
+        for (offset, data) in writes.iter() {
+            file.write_all_at(data, *offset)
+                .map_err(DurabilityError::IoError)?;
+        }
+        Ok(())
+    }
+
+    fn lock_write_buffer(&self) -> MutexGuard<'_, Option<WriteBuffer>> {
+        self.write_buffer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Runs `write` with the table file writes it makes held back in a
+    /// `WriteBuffer`, so rows added one at a time reach the file in a few
+    /// large writes. The buffer is flushed once it is full and after
+    /// `write`, even when it failed as the redo log already holds the
+    /// writes. Reads in between flush it first. The redo log is written as
+    /// each write is made, a crash before the flush is repaired from it.
+    pub fn buffered<T>(
+        &mut self,
+        file: &mut std::fs::File,
+        write: impl FnOnce(&mut Table, &mut std::fs::File) -> Result<T, DurabilityError>,
+    ) -> Result<T, DurabilityError> {
+        let outermost = {
+            let mut buffer = self.lock_write_buffer();
+            let outermost = buffer.is_none();
+            buffer.get_or_insert_with(WriteBuffer::default);
+            outermost
+        };
+        let result = write(self, file);
+        if !outermost {
+            return result;
+        }
+        let flushed = self.flush(file);
+        *self.lock_write_buffer() = None;
+        let result = result?;
+        flushed.map(|()| result)
+    }
+
+    /// Writes out the writes `buffered` holds back.
+    pub fn flush(&self, file: &std::fs::File) -> Result<(), DurabilityError> {
+        match self.lock_write_buffer().as_mut() {
+            Some(buffer) if !buffer.is_empty() => buffer
+                .flush(file)
+                .map(|_| ())
+                .map_err(DurabilityError::IoError),
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies `writes`, at the offsets the rows would have stored as they
+    /// are, to the pages they touch. A page is read with `read_page` unless a
+    /// write covers it whole. Returns the writes to the header and the
+    /// changed pages.
+    #[allow(clippy::type_complexity)]
+    fn page_writes(
+        &self,
+        writes: &[(u64, Vec<u8>)],
+        mut read_page: impl FnMut(u64) -> Result<Vec<u8>, DurabilityError>,
+    ) -> Result<(Vec<(u64, Vec<u8>)>, BTreeMap<u64, Vec<u8>>), DurabilityError> {
+        let header_size = self.header_size();
+        let page_size = self.page_size() as usize;
+        let mut header_writes = vec![];
+        let mut pages: BTreeMap<u64, Vec<u8>> = BTreeMap::new();
+        for (offset, data) in writes.iter() {
+            if *offset < header_size {
+                header_writes.push((*offset, data.clone()));
+                continue;
+            }
+            let mut position = offset - header_size;
+            let mut data = data.as_slice();
+            while !data.is_empty() {
+                let page_number = position / page_size as u64;
+                let start = (position % page_size as u64) as usize;
+                let length = data.len().min(page_size - start);
+                let page = match pages.entry(page_number) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) if length == page_size => entry.insert(vec![0; page_size]),
+                    Entry::Vacant(entry) => entry.insert(read_page(page_number)?),
+                };
+                page[start..start + length].copy_from_slice(&data[..length]);
+                data = &data[length..];
+                position += length as u64;
+            }
+        }
+        Ok((header_writes, pages))
+    }
+
+    /// The writes of a table with delta encoded columns for `writes` of the
+    /// rows as read. Every page they touch is decoded, changed and encoded
+    /// again whole, the values after a changed or deleted row depend on it.
+    fn delta_encoded_writes(
+        &self,
+        writes: &[(u64, Vec<u8>)],
+        file: &std::fs::File,
+    ) -> Result<Vec<(u64, Vec<u8>)>, DurabilityError> {
+        let (header_writes, pages) =
+            self.page_writes(writes, |page| self.decoded_page(file, page))?;
+        let mut encoded = vec![];
+        for (page_number, mut page) in pages {
+            self.encode_deltas(&mut page)?;
+            encoded.push((self.header_size() + page_number * self.page_size(), page));
+        }
+        encoded.extend(header_writes);
+        Ok(encoded)
+    }
+
+    /// The writes of a compressed table for `writes` at the offsets the rows
+    /// would have uncompressed, `row_offset_bytes`. Every page they touch is
+    /// read, changed and written back whole to its slot. The redo log holds
+    /// these writes, so replaying it needs no table.
+    fn compressed_writes(
+        &self,
+        writes: &[(u64, Vec<u8>)],
+        file: &std::fs::File,
+    ) -> Result<Vec<(u64, Vec<u8>)>, DurabilityError> {
+        let (header_writes, pages) =
+            self.page_writes(writes, |page| self.read_compressed_page(file, page))?;
+        Ok(pages
+            .into_iter()
+            .map(|(page_number, page)| (self.page_offset(page_number), encode_page(&page)))
+            .chain(header_writes)
+            .collect())
+    }
+
+    fn read_row_count_from_disk(&self, file: &std::fs::File) -> Result<u64, DurabilityError> {
+        let mut row_count = [0; ROW_COUNT_SIZE as usize];
+        file.read_exact_at(&mut row_count, self.row_count_offset())
+            .map_err(DurabilityError::IoError)?;
+        // Rows appended one at a time keep buffering their writes.
+        if let Some(buffer) = self.lock_write_buffer().as_ref() {
+            buffer.overlay(self.row_count_offset(), &mut row_count);
+        }
+        Ok(u64::from_ne_bytes(row_count))
+    }
+
+    pub fn column_contains(
+        &self,
+        file: &std::fs::File,
+        column_index: usize,
+        value: &[u8],
+    ) -> Result<bool, DurabilityError> {
+        Ok(self.find_row(file, column_index, value)?.is_some())
+    }
+
+    /// The index of the first row holding `value` in the column, found by
+    /// scanning every page.
+    pub fn find_row(
+        &self,
+        file: &std::fs::File,
+        column_index: usize,
+        value: &[u8],
+    ) -> Result<Option<u64>, DurabilityError> {
+        for i in 0..self.page_count() {
+            let page = self.page_at(file, i)?;
+            for (row_index, row) in self.page_entries(&page) {
+                if row.data[column_index] == value {
+                    return Ok(Some(row_index));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Deletes the row at `row_within_page` of `page`, the location an index
+    /// lookup gives, by overwriting it with a tombstone and dropping it from
+    /// every index of the table. The row keeps its slot, so `row_count`
+    /// still counts it: rows are addressed by their position, which would
+    /// shift for every row after it otherwise, and new rows are appended
+    /// after the last slot.
+    pub fn delete_at(
+        &mut self,
+        file: &mut std::fs::File,
+        page: u64,
+        row_within_page: u64,
+    ) -> Result<(), DurabilityError> {
+        let rows_per_page = self.page_size() / self.row_size();
+        let row_index = page * rows_per_page + row_within_page;
+        if row_within_page >= rows_per_page || row_index >= self.row_count {
+            return Err(format!(
+                "No row {} in page {} of table {}",
+                row_within_page,
+                page,
+                self.name_str()
+            )
+            .into());
+        }
+        self.check_schema(file)?;
+
+        let name = self.name_str();
+        let locks = lock_manager();
+        locks.acquire_write_lock(name, row_index);
+        let result = self.read_row(file, row_index).and_then(|row| {
+            if row.data.first().and_then(|value| value.first()) == Some(&TOMBSTONE) {
+                return Err(format!(
+                    "Row {} in page {} is already deleted",
+                    row_within_page, page
+                )
+                .into());
+            }
+            self.check_delete(&row)?;
+
+            let mut tombstone = vec![0; self.row_size() as usize];
+            tombstone[0] = TOMBSTONE;
+            self.write_logged(&[(self.row_offset_bytes(row_index), tombstone)], file)?;
+            self.record_modified(self.row_count, file)?;
+            unindex_row(self, row_index, &row)
+        });
+        locks.release_lock(name, row_index);
+        result
+    }
+
+    pub fn write_row_count_to_disk(&self, file: &mut std::fs::File) -> Result<(), DurabilityError> {
+        file.write_all_at(&self.row_count.to_ne_bytes(), self.row_count_offset())
+            .map_err(DurabilityError::IoError)
+    }
+
+    /// The `CREATE TABLE` statement recreating the table with its
+    /// constraints and foreign keys.
+    pub fn create_statement(&self) -> Result<String, DurabilityError> {
+        let name = self.name_str();
+        let mut definitions: Vec<String> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(position, column)| {
+                let mut definition =
+                    format!("{} {}", column.name_str(), column.column_type.sql_name());
+                if !column.column_type.is_binary() {
+                    definition.push_str(&format!(" {}", column.length));
+                }
+                if self.primary_key_column() == Some(position) {
+                    definition.push_str(" PRIMARY KEY");
+                }
+                if let Some(default_value) = &column.default_value {
+                    let default_value = column.column_type.format(default_value);
+                    definition.push_str(&format!(" DEFAULT '{}'", default_value));
+                }
+                if column.unique {
+                    definition.push_str(" UNIQUE");
+                }
+                if column.dictionary_encoded {
+                    definition.push_str(" DICTIONARY");
+                }
+                if let Some(check_expr) = &column.check_expr {
+                    definition.push_str(&format!(" CHECK ({})", super::name_str(check_expr)));
+                }
+                definition
+            })
+            .collect();
+        for foreign_key in read_foreign_keys(name)? {
+            if foreign_key.table == name {
+                definitions.push(format!(
+                    "FOREIGN KEY ({}) REFERENCES {} ({})",
+                    foreign_key.column, foreign_key.referenced_table, foreign_key.referenced_column
+                ));
+            }
+        }
+        Ok(format!(
+            "CREATE TABLE {} ({})",
+            name,
+            definitions.join(", ")
+        ))
+    }
+
+    /// Checks that no other table still references `row` through a foreign
+    /// key. Meant to be called before the row is deleted.
+    pub fn check_delete(&self, row: &Row) -> Result<(), DurabilityError> {
+        let name = self.name_str();
+
+        for foreign_key in read_foreign_keys(name)? {
+            if foreign_key.referenced_table != name {
+                continue;
+            }
+
+            let position = self
+                .columns
+                .iter()
+                .position(|c| c.name_str() == foreign_key.referenced_column);
+            let value = match position {
+                Some(position) => &row.data[position],
+                None => continue,
+            };
+
+            if value_exists(&foreign_key.table, &foreign_key.column, value)? {
+                return Err(DurabilityError::ForeignKeyViolation {
+                    table: foreign_key.table,
+                    column: foreign_key.column,
+                    value: String::from_utf8_lossy(value)
+                        .trim_end_matches('\0')
+                        .to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn row_for_columns(
+        &self,
+        columns: &[String],
+        values: &[Vec<u8>],
+    ) -> Result<Row, DurabilityError> {
+        if columns.len() != values.len() {
+            return Err(format!(
+                "Invalid row data expected {} values got {}",
+                columns.len(),
+                values.len()
+            )
+            .into());
+        }
+
+        for name in columns {
+            let exists = self.columns.iter().any(|column| column.name_str() == name);
+            if !exists {
+                return Err(format!("Column {} does not exist", name).into());
+            }
+        }
+
+        let mut data = vec![];
+        for column in self.columns.iter() {
+            match columns.iter().position(|c| c == column.name_str()) {
+                Some(i) if column.column_type.is_binary() => {
+                    let value = column.column_type.encode(&values[i]).ok_or_else(|| {
+                        format!(
+                            "Invalid {} value {} for column {}",
+                            column.column_type.sql_name(),
+                            String::from_utf8_lossy(&values[i]),
+                            column.name_str()
+                        )
+                    })?;
+                    data.push(value);
+                }
+                Some(i) => data.push(values[i].clone()),
+                None => match &column.default_value {
+                    Some(default_value) => data.push(default_value.clone()),
+                    None => {
+                        return Err(
+                            format!("Column {} has no default value", column.name_str()).into()
+                        )
+                    }
+                },
+            }
+        }
+
+        Ok(Row { data })
+    }
+
+    pub fn rename(&mut self, name: &str, file: &mut std::fs::File) -> Result<(), DurabilityError> {
+        let name_bytes = name.as_bytes();
+        if name_bytes.is_empty() || name_bytes.len() > 63 {
+            return Err(format!(
+                "Invalid table name {}, must be between 1 and 63 bytes",
+                name
+            )
+            .into());
+        }
+
+        let mut name_buffer = [0; 64];
+        name_buffer[..name_bytes.len()].copy_from_slice(name_bytes);
+        file.write_all_at(&name_buffer, 0)
+            .map_err(DurabilityError::IoError)?;
+
+        self.name = name_buffer;
+        Ok(())
+    }
+
+    pub fn rename_column(
+        &mut self,
+        old_name: &str,
+        new_name: &str,
+        file: &mut std::fs::File,
+    ) -> Result<(), DurabilityError> {
+        let new_name_bytes = new_name.as_bytes();
+        if new_name_bytes.is_empty() || new_name_bytes.len() > 63 {
+            return Err(format!(
+                "Invalid column name {}, must be between 1 and 63 bytes",
+                new_name
+            )
+            .into());
+        }
+
+        let column_named = |name: &str| {
+            self.columns
+                .iter()
+                .position(|column| column.name_str() == name)
+        };
+        if column_named(new_name).is_some() {
+            return Err(format!("Column {} already exists", new_name).into());
+        }
+
+        let position =
+            column_named(old_name).ok_or_else(|| format!("Column {} does not exist", old_name))?;
+
+        let mut name_buffer = [0; 64];
+        name_buffer[..new_name_bytes.len()].copy_from_slice(new_name_bytes);
+
+        if self.columns[position].dictionary_encoded {
+            std::fs::rename(
+                dictionary_file(self.name_str(), old_name),
+                dictionary_file(self.name_str(), new_name),
+            )
+            .or_else(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(DurabilityError::IoError(e)),
+            })?;
+        }
+        file.write_all_at(&name_buffer, self.column_definition_offset(position))
+            .map_err(DurabilityError::IoError)?;
+
+        self.columns[position].name = name_buffer;
+        Ok(())
+    }
+}
+
+impl Durable for Table {
+    fn write_to_disk(&mut self, file: &mut std::fs::File) -> Result<(), super::DurabilityError> {
+        if let Err(e) = file.write_all_at(&self.name, 0) {
+            return Err(super::DurabilityError::IoError(e));
+        }
+
+        let column_count_bytes = self.column_count.to_ne_bytes();
+        if let Err(e) = file.write_all_at(&column_count_bytes, 64) {
+            return Err(super::DurabilityError::IoError(e));
+        }
+
+        if let Err(e) = file.write_all_at(&[self.primary_key], PRIMARY_KEY_OFFSET) {
+            return Err(super::DurabilityError::IoError(e));
+        }
+
+        if let Err(e) = file.write_all_at(&[self.table_type], TABLE_TYPE_OFFSET) {
+            return Err(super::DurabilityError::IoError(e));
+        }
+
+        let compression = [self.compression_enabled as u8];
+        if let Err(e) = file.write_all_at(&compression, COMPRESSION_OFFSET) {
+            return Err(super::DurabilityError::IoError(e));
+        }
+
+        let mut offset = COLUMN_DEFINITION_OFFSET;
+        for column in &self.columns {
+            let bytes = column.bytes();
+            if let Err(e) = file.write_all_at(&bytes, offset) {
+                return Err(super::DurabilityError::IoError(e));
+            }
+            offset += column.size();
+        }
+
+        let _ = self.write_row_count_to_disk(file);
+
+        // Created and last modified now, never analyzed and empty.
+        let now = unix_time();
+        let stats: Vec<u8> = [now, now, 0, 0]
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+        if let Err(e) = file.write_all_at(&stats, self.table_stats_offset()) {
+            return Err(super::DurabilityError::IoError(e));
+        }
+        Ok(())
+    }
+
+    fn read_from_disk(file: &mut std::fs::File) -> Result<Self, super::DurabilityError>
+    where
+        Self: Sized,
+    {
+        let mut name_buff: [u8; 64] = [0; 64];
+
+        if let Err(e) = file.read_exact_at(&mut name_buff, 0) {
+            return Err(super::DurabilityError::IoError(e));
+        }
+
+        // Finish any write that was committed to the log before a crash.
+        let name = name_buff.split(|b| *b == 0).next().unwrap_or_default();
+        recover(&String::from_utf8_lossy(name), file)?;
+        remove_stale_locks(&lock_file(&String::from_utf8_lossy(name)))?;
+
+        Table::read_header(file)
     }
 }
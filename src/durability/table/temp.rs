@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+
+use crate::concurrency::ConnectionId;
+
+use super::{
+    create_table_with_rows, drop_table, table_exists, ColumnDefinition, Row, TableBuilder,
+};
+
+/// The directory holding the temporary tables of a connection, in the temp
+/// directory of the OS so they stay out of the database directory. The
+/// process id keeps apart the connections of servers running side by side.
+fn temp_directory(connection_id: ConnectionId) -> PathBuf {
+    std::env::temp_dir().join(format!("cargo_db_{}_{}", std::process::id(), connection_id))
+}
+
+/// The file of the temporary table `name` of a connection, `_temp_name`.
+pub fn temp_table_file(connection_id: ConnectionId, name: &str) -> String {
+    temp_directory(connection_id)
+        .join(format!("_temp_{}", name))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Creates the temporary table `name` of a connection with `columns` as its
+/// schema and `rows` in it, `SELECT INTO TEMP TABLE`.
+pub fn create_temp_table(
+    connection_id: ConnectionId,
+    name: &str,
+    columns: Vec<ColumnDefinition>,
+    rows: &[Row],
+) -> Result<(), String> {
+    let path = temp_table_file(connection_id, name);
+    if table_exists(&path) {
+        return Err(format!("Temporary table {} already exists", name));
+    }
+    std::fs::create_dir_all(temp_directory(connection_id))
+        .map_err(|e| format!("Error creating temporary table: {:?}", e))?;
+    create_table_with_rows(TableBuilder::new(&path).columns(columns).build()?, rows)
+}
+
+/// Drops the temporary table `name` of a connection, `DROP TEMP TABLE`.
+pub fn drop_temp_table(connection_id: ConnectionId, name: &str) -> Result<(), String> {
+    let path = temp_table_file(connection_id, name);
+    if !table_exists(&path) {
+        return Err(format!("Temporary table {} does not exist", name));
+    }
+    drop_table(&path)
+}
+
+/// Drops every temporary table of a connection, once it closes.
+pub fn drop_temp_tables(connection_id: ConnectionId) -> std::io::Result<()> {
+    match std::fs::remove_dir_all(temp_directory(connection_id)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::durability::table::{writeable_table_file, ColumnType, Table};
+    use crate::durability::Durable;
+
+    #[test]
+    fn test_temp_table() {
+        // Out of the way of the connections of the server tests.
+        let connection_id = u64::MAX;
+        let columns = vec![ColumnDefinition::new("id".to_string(), ColumnType::Int, 4)];
+        let rows: Vec<Row> = (0..10)
+            .map(|i| Row {
+                data: vec![i.to_string().into_bytes()],
+            })
+            .collect();
+        create_temp_table(connection_id, "recent", columns.clone(), &rows).unwrap();
+        let path = temp_table_file(connection_id, "recent");
+        assert!(path.starts_with(std::env::temp_dir().to_str().unwrap()));
+        assert!(path.ends_with("/_temp_recent"));
+        let table =
+            Table::read_from_disk(&mut writeable_table_file(path.clone()).unwrap()).unwrap();
+        assert_eq!(table.row_count, 10);
+        assert!(create_temp_table(connection_id, "recent", columns.clone(), &rows).is_err());
+
+        drop_temp_table(connection_id, "recent").unwrap();
+        assert!(!table_exists(&path));
+        assert!(drop_temp_table(connection_id, "recent").is_err());
+
+        create_temp_table(connection_id, "recent", columns, &rows).unwrap();
+        drop_temp_tables(connection_id).unwrap();
+        assert!(!table_exists(&path));
+        assert!(!temp_directory(connection_id).exists());
+        drop_temp_tables(connection_id).unwrap();
+    }
+}
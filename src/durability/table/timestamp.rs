@@ -0,0 +1,201 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::query::{expression::parse_date, unquote};
+
+pub const CURRENT_TIMESTAMP: &str = "CURRENT_TIMESTAMP";
+
+const MICROS_PER_DAY: u64 = 86_400_000_000;
+
+/// Microseconds since the Unix epoch, the value of `CURRENT_TIMESTAMP`.
+pub fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as u64
+}
+
+/// Formats microseconds since the Unix epoch as `YYYY-MM-DDTHH:MM:SS.ffffffZ`
+/// in UTC.
+pub fn format_timestamp(micros: u64) -> String {
+    let (year, month, day) = civil_from_days((micros / MICROS_PER_DAY) as i64);
+    let seconds_of_day = micros % MICROS_PER_DAY / 1_000_000;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        micros % 1_000_000
+    )
+}
+
+/// Parses `YYYY-MM-DDTHH:MM:SS[.ffffff]Z`, or a date alone for its midnight,
+/// into microseconds since the Unix epoch. Times before the epoch are
+/// rejected.
+pub fn parse_timestamp(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (date, time) = match value.split_once('T') {
+        Some((date, time)) => (date, Some(time.strip_suffix('Z')?)),
+        None => (value, None),
+    };
+    let (year, month, day) = parse_date(date)?;
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+
+    let time_micros = match time {
+        None => 0,
+        Some(time) => {
+            let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+            let mut parts = time.splitn(3, ':');
+            let hours: u64 = parse_digits(parts.next()?, 2)?;
+            let minutes: u64 = parse_digits(parts.next()?, 2)?;
+            let seconds: u64 = parse_digits(parts.next()?, 2)?;
+            if hours > 23 || minutes > 59 || seconds > 59 || fraction.len() > 6 {
+                return None;
+            }
+            let micros = match fraction {
+                "" => 0,
+                fraction => {
+                    parse_digits(fraction, fraction.len())? * 10u64.pow(6 - fraction.len() as u32)
+                }
+            };
+            (hours * 3600 + minutes * 60 + seconds) * 1_000_000 + micros
+        }
+    };
+    Some(days * MICROS_PER_DAY + time_micros)
+}
+
+fn parse_digits(value: &str, length: usize) -> Option<u64> {
+    if value.len() != length || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// The stored form of a `Timestamp` value given as an ISO-8601 string,
+/// quoted or not, or as `CURRENT_TIMESTAMP`: its microseconds big-endian
+/// with the sign bit flipped, so it never starts with the byte 0xFF
+/// marking deleted rows. An empty value stays null.
+pub fn encode_timestamp(value: &[u8]) -> Option<Vec<u8>> {
+    let value = value.split(|b| *b == 0).next().unwrap_or_default();
+    let value = std::str::from_utf8(value).ok()?.trim();
+    let micros = match unquote(value) {
+        "" => return Some(vec![]),
+        CURRENT_TIMESTAMP => current_timestamp(),
+        value => parse_timestamp(value)?,
+    };
+    Some((micros ^ SIGN_BIT).to_be_bytes().to_vec())
+}
+
+const SIGN_BIT: u64 = 1 << 63;
+
+/// Reads a stored `Timestamp` value, `None` when it is null. The value may
+/// be missing its zero padding, like default values read from the header.
+pub fn decode_timestamp(value: &[u8]) -> Option<u64> {
+    if value.len() > 8 || value.iter().all(|b| *b == 0) {
+        return None;
+    }
+    let mut bytes = [0; 8];
+    bytes[..value.len()].copy_from_slice(value);
+    Some(u64::from_be_bytes(bytes) ^ SIGN_BIT)
+}
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// Converts a proleptic Gregorian date to days since 1970-01-01, the inverse
+/// of `civil_from_days`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * month_index + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp_roundtrip() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00.000000Z");
+        let micros = parse_timestamp("2024-01-15T10:30:00.123456Z").unwrap();
+        assert_eq!(micros, 1_705_314_600_123_456);
+        assert_eq!(format_timestamp(micros), "2024-01-15T10:30:00.123456Z");
+        assert_eq!(
+            format_timestamp(parse_timestamp("2024-02-29T23:59:59Z").unwrap()),
+            "2024-02-29T23:59:59.000000Z"
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-01"),
+            parse_timestamp("2024-01-01T00:00:00.000Z")
+        );
+
+        let now = current_timestamp();
+        assert_eq!(parse_timestamp(&format_timestamp(now)), Some(now));
+        for days in [0, 59, 365, 11_016, 19_782, 47_482] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn test_encoded_timestamp_never_starts_with_0xff() {
+        // 1_705_314_600_000_255 has 0xFF as its low byte.
+        for value in [
+            "1970-01-01",
+            "2024-01-15T10:30:00.000255Z",
+            "9999-12-31T23:59:59.999999Z",
+        ] {
+            let encoded = encode_timestamp(value.as_bytes()).unwrap();
+            assert_ne!(encoded[0], 0xFF, "{}", value);
+            assert_eq!(decode_timestamp(&encoded), parse_timestamp(value));
+        }
+        assert_eq!(encode_timestamp(b""), Some(vec![]));
+        assert_eq!(decode_timestamp(&[0; 8]), None);
+    }
+
+    #[test]
+    fn test_invalid_timestamps() {
+        assert_eq!(parse_timestamp("2024-01-15T10:30:00"), None);
+        assert_eq!(parse_timestamp("2024-01-15T24:00:00Z"), None);
+        assert_eq!(parse_timestamp("2024-01-15T10:30Z"), None);
+        assert_eq!(parse_timestamp("2024-01-15T10:30:00.1234567Z"), None);
+        assert_eq!(parse_timestamp("1969-12-31T23:59:59Z"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn test_encode_timestamp() {
+        let encoded = encode_timestamp(b"'2024-01-15T10:30:00.123456Z'").unwrap();
+        assert_eq!(encoded.len(), 8);
+        assert_eq!(decode_timestamp(&encoded), Some(1_705_314_600_123_456));
+        assert_eq!(encode_timestamp(b""), Some(vec![]));
+        assert_eq!(decode_timestamp(&[0; 8]), None);
+        assert_eq!(encode_timestamp(b"'2024-13-01T00:00:00Z'"), None);
+
+        let before = current_timestamp();
+        let now = decode_timestamp(&encode_timestamp(b"CURRENT_TIMESTAMP").unwrap()).unwrap();
+        assert!(now >= before && now <= current_timestamp());
+    }
+}
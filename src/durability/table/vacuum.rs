@@ -0,0 +1,211 @@
+use std::{io::Read, path::Path};
+
+use crate::concurrency::{lock_manager, TableLock};
+use crate::durability::{index::rebuild_indexes, wal::checkpoint, DurabilityError};
+
+use super::table::TOMBSTONE;
+use super::{name_str, Table};
+
+/// The tables in `directory`, told apart from the other files kept there by
+/// their header starting with the name they are found under. The database
+/// file starts the same way and is found along with them.
+pub fn database_tables(directory: &str) -> Result<Vec<String>, DurabilityError> {
+    let mut tables = vec![];
+    for entry in std::fs::read_dir(directory).map_err(DurabilityError::IoError)? {
+        let entry = entry.map_err(DurabilityError::IoError)?;
+        if !entry
+            .file_type()
+            .map_err(DurabilityError::IoError)?
+            .is_file()
+        {
+            continue;
+        }
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let name = match directory {
+            "." => file_name,
+            _ => format!("{}/{}", directory.trim_end_matches('/'), file_name),
+        };
+        let mut header = [0; 64];
+        let read = std::fs::File::open(&name).and_then(|mut file| file.read_exact(&mut header));
+        if read.is_ok() && name_str(&header) == name {
+            tables.push(name);
+        }
+    }
+    tables.sort();
+    Ok(tables)
+}
+
+impl Table {
+    /// Rewrites the table without its deleted rows, `VACUUM`, and cuts the
+    /// file down to the pages left. The rows and the new row count go
+    /// through the redo log as a single commit, so the table is either
+    /// compacted whole or left as it was, and the log is checkpointed after.
+    /// Rows move to fill the gaps, every index of the table is rebuilt
+    /// after. Returns the size of the file before and after.
+    pub fn vacuum(&mut self, file: &mut std::fs::File) -> Result<(u64, u64), DurabilityError> {
+        let name = self.name_str().to_string();
+        let locks = lock_manager();
+        locks.acquire_table_lock(&name, TableLock::Exclusive);
+        let result = self.vacuum_locked(file);
+        locks.release_table_lock(&name);
+        result
+    }
+
+    fn vacuum_locked(&mut self, file: &std::fs::File) -> Result<(u64, u64), DurabilityError> {
+        self.flush(file)?;
+        self.refresh(file)?;
+        let size = file.metadata().map_err(DurabilityError::IoError)?.len();
+
+        let row_size = self.row_size() as usize;
+        let mut bytes = vec![];
+        for page_number in 0..self.page_count() {
+            let page = self.page_at(file, page_number)?;
+            let live = page
+                .data
+                .chunks_exact(row_size)
+                .take(self.page_row_count(&page))
+                .filter(|row| row[0] != TOMBSTONE);
+            for row in live {
+                bytes.extend_from_slice(row);
+            }
+        }
+        let row_count = (bytes.len() / row_size) as u64;
+        if row_count == self.row_count {
+            return Ok((size, size));
+        }
+
+        // Padded to the end of the last page like appended rows.
+        let page_count = (self.row_size() * row_count / self.page_size()) + 1;
+        bytes.resize((page_count * self.page_size()) as usize, 0);
+        self.write_logged(
+            &[
+                (self.header_size(), bytes),
+                (self.row_count_offset(), row_count.to_ne_bytes().to_vec()),
+            ],
+            file,
+        )?;
+        self.flush(file)?;
+        self.row_count = row_count;
+        self.record_modified(row_count, file)?;
+        // Replaying the writes before the compaction would grow the file
+        // back, the redo log is emptied once it is cut down.
+        file.set_len(self.page_offset(page_count))
+            .map_err(DurabilityError::IoError)?;
+        checkpoint(self.name_str(), file)?;
+        rebuild_indexes(self, file)?;
+
+        let compacted = file.metadata().map_err(DurabilityError::IoError)?.len();
+        Ok((size, compacted))
+    }
+}
+
+/// Whether `name` is the file of the database called `database`, which
+/// `database_tables` cannot tell from a table.
+pub fn is_database_file(name: &str, database: &str) -> bool {
+    Path::new(name).file_name() == Some(database.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::{
+        index::{index_file, BTreeIndex},
+        table::{create_table, writeable_table_file, ColumnType, Row, TableBuilder},
+        Durable,
+    };
+
+    fn create_users(dir: &Path, name: &str, rows: usize) -> (Table, std::fs::File) {
+        let name = dir.join(name).to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column("name", ColumnType::Varchar, 20),
+        )
+        .unwrap();
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let rows: Vec<Row> = (0..rows)
+            .map(|i| Row {
+                data: vec![
+                    i.to_string().into_bytes(),
+                    format!("user {}", i).into_bytes(),
+                ],
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+        (table, file)
+    }
+
+    /// Deletes the rows with an odd id.
+    fn delete_odd_rows(table: &mut Table, file: &mut std::fs::File) {
+        let rows_per_page = table.page_size() / table.row_size();
+        for row_index in (1..table.row_count).step_by(2) {
+            table
+                .delete_at(file, row_index / rows_per_page, row_index % rows_per_page)
+                .unwrap();
+        }
+    }
+
+    fn ids(table: &Table, file: &std::fs::File) -> Vec<String> {
+        (0..table.page_count())
+            .flat_map(|page| table.decode_rows_batch(&table.page_at(file, page).unwrap()))
+            .map(|row| row[0].clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_vacuum() {
+        let tmp_dir = tempdir().unwrap();
+        let (mut table, mut file) = create_users(tmp_dir.path(), "users", 1000);
+        let id_path = index_file(table.name_str(), "id");
+        BTreeIndex::build("idx_id", &table, &file, &["id"], None)
+            .unwrap()
+            .write(&id_path)
+            .unwrap();
+        delete_odd_rows(&mut table, &mut file);
+        let size = file.metadata().unwrap().len();
+
+        let (before, after) = table.vacuum(&mut file).unwrap();
+        assert_eq!(before, size);
+        assert_eq!(after, file.metadata().unwrap().len());
+        // Half the rows are gone, so are about half the pages.
+        let header_size = table.header_size();
+        assert!(after - header_size <= (before - header_size) / 2 + table.page_size());
+        assert_eq!(table.row_count, 500);
+        let even: Vec<String> = (0..1000).step_by(2).map(|i: usize| i.to_string()).collect();
+        assert_eq!(ids(&table, &file), even);
+        // Read back from disk, the rows moved and the index follows them.
+        let table = Table::read_from_disk(&mut file).unwrap();
+        assert_eq!(table.row_count, 500);
+        assert_eq!(ids(&table, &file), even);
+        assert_eq!(BTreeIndex::read(&id_path).unwrap().lookup(b"10"), [5]);
+        assert!(BTreeIndex::read(&id_path).unwrap().lookup(b"11").is_empty());
+
+        // Nothing left to reclaim.
+        let mut table = table;
+        assert_eq!(table.vacuum(&mut file).unwrap(), (after, after));
+    }
+
+    #[test]
+    fn test_database_tables() {
+        let tmp_dir = tempdir().unwrap();
+        let directory = tmp_dir.path().to_str().unwrap();
+        let (mut orders, mut file) = create_users(tmp_dir.path(), "orders", 10);
+        create_users(tmp_dir.path(), "users", 10);
+        delete_odd_rows(&mut orders, &mut file);
+        std::fs::write(tmp_dir.path().join("notes"), b"orders").unwrap();
+
+        let tables = database_tables(directory).unwrap();
+        assert_eq!(
+            tables,
+            [
+                format!("{}/orders", directory),
+                format!("{}/users", directory)
+            ]
+        );
+        assert!(is_database_file(&tables[1], "users"));
+        assert!(!is_database_file(&tables[1], "orders"));
+    }
+}
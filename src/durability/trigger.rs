@@ -0,0 +1,315 @@
+use std::os::unix::fs::FileExt;
+
+use super::{DatabaseConfig, DurabilityError};
+
+const NAME_SIZE: usize = 64;
+const BODY_SIZE: usize = 512;
+const RECORD_SIZE: usize = NAME_SIZE + 1 + NAME_SIZE + BODY_SIZE;
+
+/// The change to a table a trigger runs after.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerEvent {
+    Insert = 1,
+    Update = 2,
+    Delete = 3,
+}
+
+impl TriggerEvent {
+    fn from_u8(value: u8) -> Option<TriggerEvent> {
+        match value {
+            1 => Some(TriggerEvent::Insert),
+            2 => Some(TriggerEvent::Update),
+            3 => Some(TriggerEvent::Delete),
+            _ => None,
+        }
+    }
+}
+
+/// SQL run after every statement making a change of `event` to `table`.
+/// `body` is either the SQL itself or the path of a `.sql` file holding it.
+/// Stored in the `{database}.triggers` file as the name, the event as a
+/// byte, the table name and the body, each padded with zeros.
+#[derive(Debug, PartialEq)]
+pub struct Trigger {
+    pub name: String,
+    pub event: TriggerEvent,
+    pub table: String,
+    pub body: String,
+}
+
+impl Trigger {
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = self.name.as_bytes().to_vec();
+        bytes.resize(NAME_SIZE, 0);
+        bytes.push(self.event as u8);
+        bytes.extend(self.table.as_bytes());
+        bytes.resize(NAME_SIZE + 1 + NAME_SIZE, 0);
+        bytes.extend(self.body.as_bytes());
+        bytes.resize(RECORD_SIZE, 0);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let text = |bytes: &[u8]| {
+            let text = bytes.split(|b| *b == 0).next().unwrap_or_default();
+            String::from_utf8_lossy(text).to_string()
+        };
+        Some(Trigger {
+            name: text(&bytes[..NAME_SIZE]),
+            event: TriggerEvent::from_u8(bytes[NAME_SIZE])?,
+            table: text(&bytes[NAME_SIZE + 1..NAME_SIZE + 1 + NAME_SIZE]),
+            body: text(&bytes[NAME_SIZE + 1 + NAME_SIZE..RECORD_SIZE]),
+        })
+    }
+}
+
+pub fn triggers_file(database: &DatabaseConfig) -> String {
+    format!("{}/{}.triggers", database.file_path, database.name)
+}
+
+fn open_triggers_file(path: &str) -> Result<std::fs::File, DurabilityError> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(DurabilityError::IoError)
+}
+
+fn read_triggers(file: &std::fs::File) -> Result<Vec<Trigger>, DurabilityError> {
+    let length = file.metadata().map_err(DurabilityError::IoError)?.len();
+    let mut data = vec![0; length as usize];
+    file.read_exact_at(&mut data, 0)
+        .map_err(DurabilityError::IoError)?;
+    data.chunks_exact(RECORD_SIZE)
+        .map(|record| {
+            Trigger::from_bytes(record)
+                .ok_or_else(|| DurabilityError::DbError("Invalid triggers file".to_string()))
+        })
+        .collect()
+}
+
+pub fn create_trigger(path: &str, trigger: Trigger) -> Result<(), DurabilityError> {
+    for (kind, name) in [("trigger", &trigger.name), ("table", &trigger.table)] {
+        if name.is_empty() || name.len() > 63 {
+            return Err(DurabilityError::DbError(format!(
+                "Invalid {} name {}, must be between 1 and 63 bytes",
+                kind, name
+            )));
+        }
+    }
+    if trigger.body.len() > BODY_SIZE {
+        return Err(DurabilityError::DbError(format!(
+            "The body of trigger {} is longer than {} bytes",
+            trigger.name, BODY_SIZE
+        )));
+    }
+
+    let file = open_triggers_file(path)?;
+    let triggers = read_triggers(&file)?;
+    if triggers
+        .iter()
+        .any(|existing| existing.name == trigger.name)
+    {
+        return Err(DurabilityError::DbError(format!(
+            "Trigger {} already exists",
+            trigger.name
+        )));
+    }
+
+    let offset = (triggers.len() * RECORD_SIZE) as u64;
+    file.write_all_at(&trigger.bytes(), offset)
+        .map_err(DurabilityError::IoError)
+}
+
+pub fn drop_trigger(path: &str, name: &str) -> Result<(), DurabilityError> {
+    let file = open_triggers_file(path)?;
+    let mut triggers = read_triggers(&file)?;
+    let position = triggers
+        .iter()
+        .position(|trigger| trigger.name == name)
+        .ok_or_else(|| DurabilityError::DbError(format!("Trigger {} does not exist", name)))?;
+    triggers.remove(position);
+
+    let bytes: Vec<u8> = triggers
+        .iter()
+        .flat_map(|trigger| trigger.bytes())
+        .collect();
+    file.set_len(0).map_err(DurabilityError::IoError)?;
+    file.write_all_at(&bytes, 0)
+        .map_err(DurabilityError::IoError)
+}
+
+/// The triggers to run after `event` on `table`, in the order they were
+/// created.
+pub fn table_triggers(
+    path: &str,
+    table: &str,
+    event: TriggerEvent,
+) -> Result<Vec<Trigger>, DurabilityError> {
+    Ok(read_triggers(&open_triggers_file(path)?)?
+        .into_iter()
+        .filter(|trigger| trigger.table == table && trigger.event == event)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        config::Config,
+        durability::{
+            table::{create_table, writeable_table_file, ColumnType, Table, TableBuilder},
+            Durable,
+        },
+        get_result_set,
+    };
+
+    fn trigger(name: &str, event: TriggerEvent, table: &str) -> Trigger {
+        Trigger {
+            name: name.to_string(),
+            event,
+            table: table.to_string(),
+            body: format!("INSERT INTO audit_log (event) VALUES ('{}')", name),
+        }
+    }
+
+    #[test]
+    fn test_create_and_drop_trigger() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("test.triggers");
+        let path = path.to_str().unwrap();
+
+        create_trigger(path, trigger("log_users", TriggerEvent::Insert, "users")).unwrap();
+        create_trigger(path, trigger("log_orders", TriggerEvent::Insert, "orders")).unwrap();
+        create_trigger(path, trigger("log_deletes", TriggerEvent::Delete, "users")).unwrap();
+        assert!(create_trigger(path, trigger("log_users", TriggerEvent::Update, "users")).is_err());
+        assert!(create_trigger(path, trigger("log", TriggerEvent::Insert, "")).is_err());
+        let mut long = trigger("long", TriggerEvent::Insert, "users");
+        long.body = "x".repeat(BODY_SIZE + 1);
+        assert!(create_trigger(path, long).is_err());
+
+        assert_eq!(
+            table_triggers(path, "users", TriggerEvent::Insert).unwrap(),
+            [trigger("log_users", TriggerEvent::Insert, "users")]
+        );
+        assert_eq!(
+            table_triggers(path, "users", TriggerEvent::Delete).unwrap(),
+            [trigger("log_deletes", TriggerEvent::Delete, "users")]
+        );
+
+        drop_trigger(path, "log_users").unwrap();
+        assert!(drop_trigger(path, "log_users").is_err());
+        assert!(table_triggers(path, "users", TriggerEvent::Insert)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            table_triggers(path, "orders", TriggerEvent::Insert).unwrap(),
+            [trigger("log_orders", TriggerEvent::Insert, "orders")]
+        );
+    }
+
+    #[test]
+    fn test_trigger_writes_audit_row() {
+        let tmp_dir = tempdir().unwrap();
+        let path = |name: &str| tmp_dir.path().join(name).to_str().unwrap().to_string();
+        let (users, audit_log) = (path("users"), path("audit_log"));
+        create_table(TableBuilder::new(&users).column("id", ColumnType::Int, 8)).unwrap();
+        create_table(TableBuilder::new(&audit_log).column("event", ColumnType::Varchar, 16))
+            .unwrap();
+        let mut file = writeable_table_file(users.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let database = DatabaseConfig {
+            name: "city_db".to_string(),
+            file_path: tmp_dir.path().to_str().unwrap().to_string(),
+            auth_file: None,
+        };
+        let mut config = Config::default();
+        let mut execute = |query: &str| {
+            get_result_set(
+                &mut table,
+                &mut file,
+                query.into(),
+                &mut HashMap::new(),
+                &database,
+                &mut None,
+                &mut config,
+                &mut std::io::empty(),
+            )
+            .rows
+        };
+        let audit_rows = || {
+            let mut file = writeable_table_file(audit_log.clone()).unwrap();
+            let table = Table::read_from_disk(&mut file).unwrap();
+            let page = table.page_at(&file, 0).unwrap();
+            table
+                .page_rows(&page)
+                .into_iter()
+                .map(|row| {
+                    String::from_utf8_lossy(&row.data[0])
+                        .trim_matches('\0')
+                        .to_string()
+                })
+                .collect::<Vec<String>>()
+        };
+
+        std::fs::write(
+            path("log_insert.sql"),
+            format!("INSERT INTO {} (event) VALUES ('from file')", audit_log),
+        )
+        .unwrap();
+        assert_eq!(
+            execute(&format!(
+                "CREATE TRIGGER audit AFTER INSERT ON {} EXECUTE 'SELECT id FROM NEW WHERE id > 0; INSERT INTO {} (event) VALUES (''insert'')'",
+                users, audit_log
+            )),
+            [["Created trigger audit"]]
+        );
+        assert_eq!(
+            execute(&format!(
+                "CREATE TRIGGER audit_file AFTER INSERT ON {} EXECUTE '{}'",
+                users,
+                path("log_insert.sql")
+            )),
+            [["Created trigger audit_file"]]
+        );
+
+        assert_eq!(
+            execute(&format!("INSERT INTO {} (id) VALUES (1) (2)", users)),
+            [["Inserting 2 row(s)"]]
+        );
+        assert_eq!(audit_rows(), ["'insert'", "'from file'"]);
+
+        // A trigger writing to its own table does not fire itself again.
+        execute("DROP TRIGGER audit_file");
+        execute(&format!(
+            "CREATE TRIGGER copy AFTER INSERT ON {} EXECUTE 'INSERT INTO {} (id) VALUES (9)'",
+            users, users
+        ));
+        execute(&format!("INSERT INTO {} (id) VALUES (3)", users));
+        assert_eq!(
+            execute(&format!("SELECT * FROM {}", users)),
+            [["1"], ["2"], ["3"], ["9"]]
+        );
+        assert_eq!(audit_rows(), ["'insert'", "'from file'", "'insert'"]);
+
+        // The body reads the inserted rows from NEW.
+        execute("DROP TRIGGER copy");
+        execute(&format!(
+            "CREATE TRIGGER broken AFTER INSERT ON {} EXECUTE 'SELECT missing FROM NEW'",
+            users
+        ));
+        assert_eq!(
+            execute(&format!("INSERT INTO {} (id) VALUES (4)", users)),
+            [
+                vec!["Inserting 1 row(s)"],
+                vec!["Trigger broken failed: Column missing does not exist"]
+            ]
+        );
+    }
+}
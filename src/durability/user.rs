@@ -0,0 +1,129 @@
+use super::DurabilityError;
+
+/// The bcrypt cost of the password hashes written by `create_user`.
+const PASSWORD_COST: u32 = 10;
+
+/// The users allowed to connect to the server, one `username:bcrypt_hash`
+/// line each. Lines that are blank or have no `:` are skipped.
+fn read_users(path: &str) -> Result<Vec<(String, String)>, DurabilityError> {
+    let data = match std::fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(DurabilityError::IoError(e)),
+    };
+    Ok(data
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, hash)| (name.trim().to_string(), hash.trim().to_string()))
+        .collect())
+}
+
+fn write_users(path: &str, users: &[(String, String)]) -> Result<(), DurabilityError> {
+    let data: String = users
+        .iter()
+        .map(|(name, hash)| format!("{}:{}\n", name, hash))
+        .collect();
+    std::fs::write(path, data).map_err(DurabilityError::IoError)
+}
+
+pub fn create_user(path: &str, name: &str, password: &str) -> Result<(), DurabilityError> {
+    if name.is_empty() || name.contains(|c: char| c == ':' || c.is_whitespace()) {
+        return Err(DurabilityError::DbError(format!(
+            "Invalid user name {}, must not be empty or contain ':' or spaces",
+            name
+        )));
+    }
+    let mut users = read_users(path)?;
+    if users.iter().any(|(existing, _)| existing == name) {
+        return Err(DurabilityError::DbError(format!(
+            "User {} already exists",
+            name
+        )));
+    }
+
+    let hash = bcrypt::hash(password, PASSWORD_COST)
+        .map_err(|e| DurabilityError::DbError(format!("{:?}", e)))?;
+    users.push((name.to_string(), hash));
+    write_users(path, &users)
+}
+
+pub fn drop_user(path: &str, name: &str) -> Result<(), DurabilityError> {
+    let mut users = read_users(path)?;
+    let position = users
+        .iter()
+        .position(|(existing, _)| existing == name)
+        .ok_or_else(|| DurabilityError::DbError(format!("User {} does not exist", name)))?;
+    users.remove(position);
+    write_users(path, &users)
+}
+
+/// Checks `username:password` against the auth file, the user name when the
+/// password matches and `None` otherwise.
+pub fn authenticate(path: &str, credentials: &str) -> Result<Option<String>, DurabilityError> {
+    let Some((name, password)) = credentials.split_once(':') else {
+        return Ok(None);
+    };
+    let verified = read_users(path)?
+        .iter()
+        .find(|(existing, _)| existing == name)
+        .is_some_and(|(_, hash)| bcrypt::verify(password, hash).unwrap_or(false));
+    Ok(verified.then(|| name.to_string()))
+}
+
+/// Whether `name` administers the server, the first user of the auth file.
+/// Only they create and drop users and grant or revoke columns, and grants
+/// do not restrict them.
+pub fn is_admin(path: &str, name: &str) -> Result<bool, DurabilityError> {
+    Ok(read_users(path)?
+        .first()
+        .is_some_and(|(admin, _)| admin == name))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_create_authenticate_and_drop_user() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("users.auth");
+        let path = path.to_str().unwrap();
+
+        assert_eq!(authenticate(path, "alice:secret").unwrap(), None);
+        create_user(path, "alice", "secret").unwrap();
+        create_user(path, "bob", "pass:word").unwrap();
+        assert!(create_user(path, "alice", "other").is_err());
+        assert!(create_user(path, "", "secret").is_err());
+        assert!(create_user(path, "eve:admin", "secret").is_err());
+
+        let data = std::fs::read_to_string(path).unwrap();
+        assert!(data.starts_with("alice:$2b$"));
+        assert!(!data.contains("secret"));
+
+        assert_eq!(
+            authenticate(path, "alice:secret").unwrap(),
+            Some("alice".to_string())
+        );
+        assert_eq!(
+            authenticate(path, "bob:pass:word").unwrap(),
+            Some("bob".to_string())
+        );
+        assert_eq!(authenticate(path, "alice:wrong").unwrap(), None);
+        assert_eq!(authenticate(path, "alice").unwrap(), None);
+        assert_eq!(authenticate(path, "carol:secret").unwrap(), None);
+
+        assert!(is_admin(path, "alice").unwrap());
+        assert!(!is_admin(path, "bob").unwrap());
+
+        drop_user(path, "alice").unwrap();
+        assert!(drop_user(path, "alice").is_err());
+        assert_eq!(authenticate(path, "alice:secret").unwrap(), None);
+        assert_eq!(
+            authenticate(path, "bob:pass:word").unwrap(),
+            Some("bob".to_string())
+        );
+        assert!(is_admin(path, "bob").unwrap());
+    }
+}
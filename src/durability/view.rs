@@ -0,0 +1,168 @@
+use std::os::unix::fs::FileExt;
+
+use super::{DatabaseConfig, DurabilityError};
+
+const NAME_SIZE: usize = 64;
+const QUERY_SIZE: usize = 1024;
+const RECORD_SIZE: usize = NAME_SIZE + QUERY_SIZE;
+
+/// A named SELECT run whenever the view is read. Stored in the
+/// `{database}.views` file as the name and the query text, both padded with
+/// zeros to their fixed size.
+#[derive(Debug, PartialEq)]
+pub struct View {
+    pub name: String,
+    pub query: String,
+}
+
+impl View {
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = self.name.as_bytes().to_vec();
+        bytes.resize(NAME_SIZE, 0);
+        bytes.extend(self.query.as_bytes());
+        bytes.resize(RECORD_SIZE, 0);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let text = |bytes: &[u8]| {
+            let text = bytes.split(|b| *b == 0).next().unwrap_or_default();
+            String::from_utf8_lossy(text).to_string()
+        };
+        View {
+            name: text(&bytes[..NAME_SIZE]),
+            query: text(&bytes[NAME_SIZE..RECORD_SIZE]),
+        }
+    }
+}
+
+pub fn views_file(database: &DatabaseConfig) -> String {
+    format!("{}/{}.views", database.file_path, database.name)
+}
+
+/// Where the queries of materialized views are kept, in the same format as
+/// the views. Their results are stored in tables of their own.
+pub fn materialized_views_file(database: &DatabaseConfig) -> String {
+    format!(
+        "{}/{}.materialized_views",
+        database.file_path, database.name
+    )
+}
+
+fn open_views_file(path: &str) -> Result<std::fs::File, DurabilityError> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(DurabilityError::IoError)
+}
+
+fn read_views(file: &std::fs::File) -> Result<Vec<View>, DurabilityError> {
+    let length = file.metadata().map_err(DurabilityError::IoError)?.len();
+    let mut data = vec![0; length as usize];
+    file.read_exact_at(&mut data, 0)
+        .map_err(DurabilityError::IoError)?;
+    Ok(data
+        .chunks_exact(RECORD_SIZE)
+        .map(View::from_bytes)
+        .collect())
+}
+
+pub fn create_view(path: &str, name: &str, query: &str) -> Result<(), DurabilityError> {
+    if name.is_empty() || name.len() > 63 {
+        return Err(DurabilityError::DbError(format!(
+            "Invalid view name {}, must be between 1 and 63 bytes",
+            name
+        )));
+    }
+    if query.len() > QUERY_SIZE {
+        return Err(DurabilityError::DbError(format!(
+            "The query of view {} is longer than {} bytes",
+            name, QUERY_SIZE
+        )));
+    }
+
+    let file = open_views_file(path)?;
+    let views = read_views(&file)?;
+    if views.iter().any(|view| view.name == name) {
+        return Err(DurabilityError::DbError(format!(
+            "View {} already exists",
+            name
+        )));
+    }
+
+    let view = View {
+        name: name.to_string(),
+        query: query.to_string(),
+    };
+    let offset = (views.len() * RECORD_SIZE) as u64;
+    file.write_all_at(&view.bytes(), offset)
+        .map_err(DurabilityError::IoError)
+}
+
+pub fn drop_view(path: &str, name: &str) -> Result<(), DurabilityError> {
+    let file = open_views_file(path)?;
+    let mut views = read_views(&file)?;
+    let position = views
+        .iter()
+        .position(|view| view.name == name)
+        .ok_or_else(|| DurabilityError::DbError(format!("View {} does not exist", name)))?;
+    views.remove(position);
+
+    let bytes: Vec<u8> = views.iter().flat_map(|view| view.bytes()).collect();
+    file.set_len(0).map_err(DurabilityError::IoError)?;
+    file.write_all_at(&bytes, 0)
+        .map_err(DurabilityError::IoError)
+}
+
+/// The view named `name`, `None` when there is no such view.
+pub fn find_view(path: &str, name: &str) -> Result<Option<View>, DurabilityError> {
+    Ok(read_views(&open_views_file(path)?)?
+        .into_iter()
+        .find(|view| view.name == name))
+}
+
+/// The names of every view in the order they were created.
+pub fn view_names(path: &str) -> Result<Vec<String>, DurabilityError> {
+    Ok(read_views(&open_views_file(path)?)?
+        .into_iter()
+        .map(|view| view.name)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_create_find_and_drop_view() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("test.views");
+        let path = path.to_str().unwrap();
+
+        assert_eq!(find_view(path, "rich").unwrap(), None);
+        create_view(path, "rich", "SELECT * FROM accounts WHERE balance > 100").unwrap();
+        create_view(path, "ids", "SELECT id FROM accounts").unwrap();
+        assert!(create_view(path, "ids", "SELECT * FROM accounts").is_err());
+        assert!(create_view(path, "long", &"x".repeat(QUERY_SIZE + 1)).is_err());
+        assert!(create_view(path, "", "SELECT * FROM accounts").is_err());
+
+        assert_eq!(
+            find_view(path, "rich").unwrap().unwrap().query,
+            "SELECT * FROM accounts WHERE balance > 100"
+        );
+        assert_eq!(view_names(path).unwrap(), ["rich", "ids"]);
+
+        drop_view(path, "rich").unwrap();
+        assert!(drop_view(path, "rich").is_err());
+        assert_eq!(find_view(path, "rich").unwrap(), None);
+        assert_eq!(
+            find_view(path, "ids").unwrap().unwrap().query,
+            "SELECT id FROM accounts"
+        );
+    }
+}
@@ -0,0 +1,498 @@
+use std::{
+    collections::HashSet,
+    io::Write,
+    os::unix::fs::FileExt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    thread,
+};
+
+use super::DurabilityError;
+use crate::concurrency::{lock_manager, TableLock};
+
+const DATA: u8 = 0x01;
+const COMMIT: u8 = 0x02;
+/// type, sequence, table_offset and data_len.
+const RECORD_HEADER_SIZE: usize = 1 + 8 + 8 + 4;
+const CRC_SIZE: usize = 4;
+
+/// Size the log may grow to before writes trigger a checkpoint.
+pub const DEFAULT_AUTO_CHECKPOINT_SIZE: u64 = 1024 * 1024;
+
+static WAL_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turns the redo log on or off for the rest of the process. Without it
+/// writes go straight to the table files and a crash can leave a write
+/// half done.
+pub fn set_wal_enabled(enabled: bool) {
+    WAL_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn wal_enabled() -> bool {
+    WAL_ENABLED.load(Ordering::Relaxed)
+}
+
+/// A record of the redo log kept next to a table in `{table}.wal`. Data
+/// records hold bytes to write at an offset of the table file and only take
+/// effect once a commit record with the same or a later sequence follows
+/// them. Every record ends with a CRC32 of its other fields so a torn write
+/// at the end of the log is detected and ignored.
+#[derive(Debug, PartialEq)]
+pub enum LogRecord {
+    Data {
+        sequence: u64,
+        table_offset: u64,
+        data: Vec<u8>,
+    },
+    Commit {
+        sequence: u64,
+    },
+}
+
+impl LogRecord {
+    pub fn bytes(&self) -> Vec<u8> {
+        let (record_type, sequence, table_offset, data) = match self {
+            LogRecord::Data {
+                sequence,
+                table_offset,
+                data,
+            } => (DATA, *sequence, *table_offset, data.as_slice()),
+            LogRecord::Commit { sequence } => (COMMIT, *sequence, 0, [].as_slice()),
+        };
+
+        let mut bytes = Vec::with_capacity(RECORD_HEADER_SIZE + data.len() + CRC_SIZE);
+        bytes.push(record_type);
+        bytes.extend(sequence.to_le_bytes());
+        bytes.extend(table_offset.to_le_bytes());
+        bytes.extend((data.len() as u32).to_le_bytes());
+        bytes.extend(data);
+        bytes.extend(crc32(&bytes).to_le_bytes());
+        bytes
+    }
+
+    /// Decodes the record at the start of `bytes` and returns it with its
+    /// length, `None` if it is truncated or fails its checksum.
+    fn from_bytes(bytes: &[u8]) -> Option<(LogRecord, usize)> {
+        if bytes.len() < RECORD_HEADER_SIZE {
+            return None;
+        }
+        let read_u64 =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        let sequence = read_u64(1);
+        let table_offset = read_u64(9);
+        let data_len = u32::from_le_bytes(bytes[17..21].try_into().unwrap()) as usize;
+
+        let length = RECORD_HEADER_SIZE + data_len + CRC_SIZE;
+        if bytes.len() < length {
+            return None;
+        }
+        let crc = u32::from_le_bytes(bytes[length - CRC_SIZE..length].try_into().unwrap());
+        if crc != crc32(&bytes[..length - CRC_SIZE]) {
+            return None;
+        }
+
+        let record = match bytes[0] {
+            DATA => LogRecord::Data {
+                sequence,
+                table_offset,
+                data: bytes[RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + data_len].to_vec(),
+            },
+            COMMIT => LogRecord::Commit { sequence },
+            _ => return None,
+        };
+        Some((record, length))
+    }
+}
+
+pub fn wal_file(table: &str) -> String {
+    format!("{}.wal", table)
+}
+
+/// Where checkpoints move the committed records of the table's log, so the
+/// table can be rebuilt up to any sequence by `RESTORE FROM WAL`.
+pub fn wal_archive_file(table: &str) -> String {
+    format!("{}.wal.archive", table)
+}
+
+/// The size of the table's log in bytes, 0 when it has none.
+pub fn wal_size(table: &str) -> u64 {
+    std::fs::metadata(wal_file(table)).map_or(0, |metadata| metadata.len())
+}
+
+/// Appends records to the redo log of a table. Sequences are the log
+/// sequence numbers (LSNs) of the records, they keep increasing for the
+/// lifetime of the table.
+pub struct Wal {
+    table: String,
+    file: std::fs::File,
+    sequence: u64,
+}
+
+impl Wal {
+    pub fn open(table: &str) -> Result<Wal, DurabilityError> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(wal_file(table))
+            .map_err(DurabilityError::IoError)?;
+        let sequence = read_records(&file)?
+            .iter()
+            .map(|record| match record {
+                LogRecord::Data { sequence, .. } | LogRecord::Commit { sequence } => *sequence,
+            })
+            .max()
+            .unwrap_or(0);
+        Ok(Wal {
+            table: table.to_string(),
+            file,
+            sequence,
+        })
+    }
+
+    pub fn append(&mut self, table_offset: u64, data: &[u8]) -> Result<(), DurabilityError> {
+        self.sequence += 1;
+        let record = LogRecord::Data {
+            sequence: self.sequence,
+            table_offset,
+            data: data.to_vec(),
+        };
+        self.file
+            .write_all(&record.bytes())
+            .map_err(DurabilityError::IoError)
+    }
+
+    /// Commits every record appended so far, they are durable once this
+    /// returns.
+    pub fn commit(&mut self) -> Result<(), DurabilityError> {
+        let record = LogRecord::Commit {
+            sequence: self.sequence,
+        };
+        self.file
+            .write_all(&record.bytes())
+            .map_err(DurabilityError::IoError)?;
+        self.file.sync_data().map_err(DurabilityError::IoError)
+    }
+
+    /// Empties the log once its records have been applied to the table file
+    /// and the table file is synced. The committed records are appended to
+    /// the archive first. A commit record of the last sequence is kept so
+    /// sequences keep increasing across checkpoints.
+    pub fn checkpoint(&mut self) -> Result<(), DurabilityError> {
+        let mut archived = vec![];
+        for (sequence, records) in transactions(read_records(&self.file)?) {
+            for record in records.into_iter().chain([LogRecord::Commit { sequence }]) {
+                archived.extend(record.bytes());
+            }
+        }
+        if !archived.is_empty() {
+            let mut archive = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(wal_archive_file(&self.table))
+                .map_err(DurabilityError::IoError)?;
+            archive
+                .write_all(&archived)
+                .map_err(DurabilityError::IoError)?;
+            archive.sync_data().map_err(DurabilityError::IoError)?;
+        }
+
+        self.file.set_len(0).map_err(DurabilityError::IoError)?;
+        if self.sequence > 0 {
+            self.commit()?;
+        }
+        self.file.sync_all().map_err(DurabilityError::IoError)
+    }
+}
+
+/// Syncs the table file, then empties the table's log. Writers have to be
+/// kept out with an exclusive table lock. Returns the number of data records
+/// that were checkpointed and the sequence the log stopped at.
+pub fn checkpoint(table: &str, file: &std::fs::File) -> Result<(usize, u64), DurabilityError> {
+    file.sync_all().map_err(DurabilityError::IoError)?;
+    let mut wal = Wal::open(table)?;
+    let records = read_records(&wal.file)?
+        .iter()
+        .filter(|record| matches!(record, LogRecord::Data { .. }))
+        .count();
+    wal.checkpoint()?;
+    Ok((records, wal.sequence))
+}
+
+/// Checkpoints the table on another thread once its writers are done, unless
+/// a checkpoint of it is already running in the background.
+pub fn checkpoint_in_background(table: String) {
+    static RUNNING: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    let running = RUNNING.get_or_init(Mutex::default);
+    if !running
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(table.clone())
+    {
+        return;
+    }
+
+    thread::spawn(move || {
+        lock_manager().acquire_table_lock(&table, TableLock::Exclusive);
+        let checkpointed = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&table)
+            .map_err(DurabilityError::IoError)
+            .and_then(|file| checkpoint(&table, &file));
+        lock_manager().release_table_lock(&table);
+        if let Err(e) = checkpointed {
+            println!(
+                "Warning: background checkpoint of {} failed: {:?}",
+                table, e
+            );
+        }
+        running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&table);
+    });
+}
+
+fn read_records(file: &std::fs::File) -> Result<Vec<LogRecord>, DurabilityError> {
+    let length = file.metadata().map_err(DurabilityError::IoError)?.len();
+    let mut data = vec![0; length as usize];
+    file.read_exact_at(&mut data, 0)
+        .map_err(DurabilityError::IoError)?;
+
+    let mut records = vec![];
+    let mut rest = data.as_slice();
+    while let Some((record, length)) = LogRecord::from_bytes(rest) {
+        records.push(record);
+        rest = &rest[length..];
+    }
+    Ok(records)
+}
+
+/// Groups records into transactions, the data records a commit record
+/// covers along with the commit's sequence. Data records no commit covers
+/// are dropped, and so are commits without data records.
+fn transactions(records: Vec<LogRecord>) -> Vec<(u64, Vec<LogRecord>)> {
+    let mut transactions = vec![];
+    let mut pending = vec![];
+    for record in records {
+        match record {
+            LogRecord::Data { .. } => pending.push(record),
+            LogRecord::Commit { sequence } => {
+                let committed: Vec<LogRecord> = pending
+                    .drain(..)
+                    .filter(|record| {
+                        matches!(record, LogRecord::Data { sequence: data_sequence, .. } if *data_sequence <= sequence)
+                    })
+                    .collect();
+                if !committed.is_empty() {
+                    transactions.push((sequence, committed));
+                }
+            }
+        }
+    }
+    transactions
+}
+
+/// Replays the committed transactions of the log at `wal_file` onto `file`
+/// in order, stopping before the first one committed after `stop_at_lsn`.
+/// Returns the number of data records replayed.
+pub fn replay_wal(
+    file: &std::fs::File,
+    wal_file: &str,
+    stop_at_lsn: Option<u64>,
+) -> Result<usize, DurabilityError> {
+    let log = match std::fs::File::open(wal_file) {
+        Ok(log) => log,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(DurabilityError::IoError(e)),
+    };
+
+    let mut replayed = 0;
+    for (sequence, records) in transactions(read_records(&log)?) {
+        if stop_at_lsn.is_some_and(|stop_at_lsn| sequence > stop_at_lsn) {
+            break;
+        }
+        for record in records {
+            if let LogRecord::Data {
+                table_offset, data, ..
+            } = record
+            {
+                file.write_all_at(&data, table_offset)
+                    .map_err(DurabilityError::IoError)?;
+                replayed += 1;
+            }
+        }
+    }
+    Ok(replayed)
+}
+
+/// Drops the archived transactions committed after `lsn`, once the table
+/// has been restored to it.
+pub fn truncate_archive(table: &str, lsn: u64) -> Result<(), DurabilityError> {
+    let path = wal_archive_file(table);
+    let archive = match std::fs::File::open(&path) {
+        Ok(archive) => archive,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(DurabilityError::IoError(e)),
+    };
+
+    let mut kept = vec![];
+    for (sequence, records) in transactions(read_records(&archive)?) {
+        if sequence > lsn {
+            break;
+        }
+        for record in records.into_iter().chain([LogRecord::Commit { sequence }]) {
+            kept.extend(record.bytes());
+        }
+    }
+    std::fs::write(&path, kept).map_err(DurabilityError::IoError)
+}
+
+/// Replays the committed records of the table's log onto `file`, then
+/// empties the log. Returns the number of data records replayed.
+pub fn recover(table: &str, file: &std::fs::File) -> Result<usize, DurabilityError> {
+    let path = wal_file(table);
+    if !std::path::Path::new(&path).exists() {
+        return Ok(0);
+    }
+
+    let replayed = replay_wal(file, &path, None)?;
+    if replayed > 0 {
+        file.sync_data().map_err(DurabilityError::IoError)?;
+    }
+    Wal::open(table)?.checkpoint()?;
+    Ok(replayed)
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !crc32_update(!0, bytes)
+}
+
+/// Feeds `bytes` to a CRC32 computed a part at a time, which starts from
+/// `!0` and is inverted once every part went through.
+pub fn crc32_update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_record_roundtrip() {
+        for record in [
+            LogRecord::Data {
+                sequence: 7,
+                table_offset: 1024,
+                data: b"row data".to_vec(),
+            },
+            LogRecord::Commit { sequence: 7 },
+        ] {
+            let bytes = record.bytes();
+            assert_eq!(LogRecord::from_bytes(&bytes), Some((record, bytes.len())));
+        }
+
+        let mut bytes = LogRecord::Commit { sequence: 1 }.bytes();
+        bytes[3] ^= 0xFF;
+        assert_eq!(LogRecord::from_bytes(&bytes), None);
+        let bytes = LogRecord::Commit { sequence: 1 }.bytes();
+        assert_eq!(LogRecord::from_bytes(&bytes[..bytes.len() - 1]), None);
+    }
+
+    #[test]
+    fn test_recover_replays_committed_records_only() {
+        let tmp_dir = tempdir().unwrap();
+        let table = tmp_dir.path().join("events").to_str().unwrap().to_string();
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(&table)
+            .unwrap();
+        file.write_all_at(b"..........", 0).unwrap();
+
+        let mut wal = Wal::open(&table).unwrap();
+        wal.append(0, b"ab").unwrap();
+        wal.append(4, b"cd").unwrap();
+        wal.commit().unwrap();
+        wal.append(8, b"ef").unwrap();
+        // A torn record at the end of the log is ignored.
+        wal.file.write_all(&[DATA, 1, 2]).unwrap();
+
+        assert_eq!(recover(&table, &file).unwrap(), 2);
+        assert_eq!(std::fs::read(&table).unwrap(), b"ab..cd....");
+        assert_eq!(recover(&table, &file).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_replay_wal_up_to_lsn() {
+        let tmp_dir = tempdir().unwrap();
+        let table = tmp_dir.path().join("events").to_str().unwrap().to_string();
+        let file = std::fs::File::create(&table).unwrap();
+
+        let mut wal = Wal::open(&table).unwrap();
+        for (offset, data) in [(0, b"ab"), (2, b"cd"), (4, b"ef")] {
+            wal.append(offset, data).unwrap();
+            wal.commit().unwrap();
+        }
+        assert_eq!(wal.sequence, 3);
+        checkpoint(&table, &file).unwrap();
+
+        std::fs::write(&table, b"......").unwrap();
+        assert_eq!(
+            replay_wal(&file, &wal_archive_file(&table), Some(2)).unwrap(),
+            2
+        );
+        assert_eq!(std::fs::read(&table).unwrap(), b"abcd..");
+
+        truncate_archive(&table, 2).unwrap();
+        std::fs::write(&table, b"......").unwrap();
+        assert_eq!(
+            replay_wal(&file, &wal_archive_file(&table), None).unwrap(),
+            2
+        );
+        assert_eq!(std::fs::read(&table).unwrap(), b"abcd..");
+    }
+
+    #[test]
+    fn test_checkpoint_empties_log() {
+        let tmp_dir = tempdir().unwrap();
+        let table = tmp_dir.path().join("events").to_str().unwrap().to_string();
+        let file = std::fs::File::create(&table).unwrap();
+
+        let mut wal = Wal::open(&table).unwrap();
+        wal.append(0, b"ab").unwrap();
+        wal.append(2, b"cd").unwrap();
+        wal.commit().unwrap();
+        wal.append(4, b"ef").unwrap();
+        wal.commit().unwrap();
+
+        assert_eq!(checkpoint(&table, &file).unwrap(), (3, 3));
+        let wal = Wal::open(&table).unwrap();
+        assert_eq!(
+            read_records(&wal.file).unwrap(),
+            [LogRecord::Commit { sequence: 3 }]
+        );
+        assert_eq!(wal.sequence, 3);
+        assert_eq!(checkpoint(&table, &file).unwrap(), (0, 3));
+    }
+}
@@ -0,0 +1,3400 @@
+//! A database of fixed-size rows kept in a file per table, queried with SQL
+//! through the REPL or the server, or from Rust through `Database`.
+
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Read,
+    str,
+};
+
+use cache::query_cache;
+use concurrency::{explicit_locks, lock_manager, ConnectionId, TableLock};
+use config::{json_array, Config, OutputFormat};
+use durability::{
+    backup::{backup, restore},
+    copy::{
+        copy_binary, copy_table, export_binary, fit_rows, import_binary, select_rows,
+        skip_binary_rows,
+    },
+    grant::{grant, grants_file, permitted_columns, revoke, user_grants, Grant, GrantOperation},
+    hash_index::{hash, HashIndex},
+    index::{
+        describe_indexes, find_index, indexed_tables, rebuild_index, rebuild_indexes, BTreeIndex,
+        IndexKind,
+    },
+    merge::merge_rows,
+    procedure::{create_procedure, find_procedure, procedures_file},
+    sequence::{create_sequence, drop_sequence, next_value, restart_sequence, sequences_file},
+    skiplist::range_rows,
+    table::{
+        create_table, create_temp_table, database_tables, disk_usage, drop_table, drop_temp_table,
+        drop_temp_tables, dump_table, is_database_file, materialized_view_file, pages_read,
+        rename_table, restore_to_lsn, set_max_page_size, table_exists, table_files,
+        temp_table_file, write_materialized_view, writeable_table_file, DiskUsage, Page, ScanHint,
+        Table, TableBuilder, TableScanner, Upsert, MATERIALIZED_VIEW,
+    },
+    trigger::{create_trigger, drop_trigger, table_triggers, triggers_file, Trigger, TriggerEvent},
+    user::{create_user, drop_user, is_admin},
+    view::{
+        create_view, drop_view, find_view, materialized_views_file, view_names, views_file, View,
+    },
+    wal::{checkpoint, checkpoint_in_background, set_wal_enabled, wal_size},
+    write_checkpoint_lsn, DatabaseConfig, DurabilityError, Durable,
+};
+use logging::{query_log_file, redact_passwords, QueryLogger};
+use optimizer::{ExecutionStats, QueryOptimizer, QueryPlan};
+use procedure::run_procedure;
+use query::{
+    aggregate::{has_aggregate, is_streamable, project_aggregates, StreamingAggregator},
+    expression::SelectExpr,
+    predicate::Predicate,
+    prepared::PreparedQuery,
+    split_literals, split_outside_quotes,
+    window::{has_window_function, project_windows},
+    ColumnDefinitionList, ColumnList, Filter, Query, QuerySource, Scope,
+};
+use slow_query_log::{slow_query_log_file, SlowQueryLog};
+use transaction::{Mutation, Transaction};
+
+mod api;
+mod buffer;
+mod cache;
+mod concurrency;
+mod config;
+mod durability;
+mod logging;
+mod optimizer;
+mod procedure;
+mod query;
+mod repl;
+mod server;
+mod slow_query_log;
+mod transaction;
+
+pub use api::{
+    ColumnDefinition, ColumnType, Database, DatabaseTable, DeleteBuilder, InsertBuilder, Operator,
+    Row, SelectBuilder,
+};
+
+/// Renders every value of `row` as text, binary values through their
+/// column type.
+fn stringify_result(row: &Row, column_defifnitions: &[ColumnDefinition]) -> Vec<String> {
+    let mut result = Vec::new();
+    for (i, column) in row.data.iter().enumerate() {
+        match column_defifnitions.get(i).map(|c| &c.column_type) {
+            Some(column_type) if column_type.is_binary() => {
+                result.push(column_type.format(column));
+                continue;
+            }
+            _ => {}
+        }
+        let mut buffer: Vec<u8> = vec![];
+        for byte in column.iter() {
+            if *byte == 0 {
+                continue;
+            }
+            buffer.push(*byte);
+        }
+        let string = str::from_utf8(&buffer).unwrap();
+        result.push(string.to_string());
+    }
+    result
+}
+
+fn project_row(row: Row, scope: &Scope, columns: &[ColumnDefinition]) -> Result<Row, String> {
+    match scope {
+        Scope::Expressions(expressions) => {
+            let data = expressions
+                .iter()
+                .map(|expression| expression.evaluate(&row, columns))
+                .collect::<Result<Vec<Vec<u8>>, String>>()?;
+            Ok(Row { data })
+        }
+        _ => Ok(row),
+    }
+}
+
+/// The columns of the rows `project_row` returns for `scope`, only their
+/// types are meaningful for expressions.
+fn projected_columns(scope: &Scope, columns: &[ColumnDefinition]) -> Vec<ColumnDefinition> {
+    match scope {
+        Scope::Expressions(expressions) | Scope::Grouped(expressions, _) => expressions
+            .iter()
+            .map(|expression| {
+                ColumnDefinition::new(String::new(), expression.value_type(columns), 0)
+            })
+            .collect(),
+        _ => columns.to_vec(),
+    }
+}
+
+/// Analyzes the table after `rows` rows were written when `auto_analyze` is
+/// on, once `auto_analyze_interval_rows` rows were written since the session
+/// last did.
+fn auto_analyze(
+    table: &Table,
+    file: &File,
+    config: &mut Config,
+    rows: u64,
+) -> Result<(), DurabilityError> {
+    if !config.auto_analyze || rows == 0 {
+        return Ok(());
+    }
+    config.rows_since_analyze += rows;
+    if config.rows_since_analyze < config.auto_analyze_interval_rows {
+        return Ok(());
+    }
+    config.rows_since_analyze = 0;
+    table.analyze(file).map(|_| ())
+}
+
+fn is_open_table(table: &Table, name: &str) -> bool {
+    table.name_str() == name
+}
+
+/// `LOCK TABLE` for the connection of the session. It does not touch the
+/// open table, so the server runs it without holding the table and the
+/// connection holding the lock can still reach it to unlock.
+fn lock_table(table: &str, mode: TableLock, config: &Config) -> Result<String, String> {
+    let timeout = std::time::Duration::from_secs(config.lock_timeout);
+    explicit_locks().lock(table, mode, config.connection_id, timeout)?;
+    let mode = match mode {
+        TableLock::Shared => "READ",
+        TableLock::Exclusive => "WRITE",
+    };
+    Ok(format!("Locked table {} for {}", table, mode))
+}
+
+fn unlock_table(table: &str, config: &Config) -> Result<String, String> {
+    explicit_locks().unlock(table, config.connection_id)?;
+    Ok(format!("Unlocked table {}", table))
+}
+
+#[derive(Clone)]
+struct ResultSet {
+    rows: Vec<Vec<String>>,
+    execution_time: u128,
+    execution_status: u8,
+}
+
+impl IntoIterator for ResultSet {
+    type Item = Vec<String>;
+    type IntoIter = std::vec::IntoIter<Vec<String>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rows.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ResultSet {
+    type Item = &'a Vec<String>;
+    type IntoIter = std::slice::Iter<'a, Vec<String>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rows.iter()
+    }
+}
+
+fn resolve_sequence_values(
+    values: &[Vec<u8>],
+    database: &DatabaseConfig,
+) -> Result<Vec<Vec<u8>>, DurabilityError> {
+    values
+        .iter()
+        .map(|value| match query::nextval_sequence(value) {
+            Some(name) => next_value(&sequences_file(database), &name)
+                .map(|value| value.to_string().into_bytes()),
+            None => Ok(value.clone()),
+        })
+        .collect()
+}
+
+/// Drops cached pages in no particular order until at most `size` are left.
+fn evict_pages(page_cache: &mut HashMap<String, Page>, size: usize) {
+    while page_cache.len() > size {
+        let page_number = page_cache.keys().next().unwrap().clone();
+        page_cache.remove(&page_number);
+    }
+}
+
+/// The page from the page cache, read through `scanner` when it is not
+/// cached. The cache holds at most `page_cache_size` pages.
+fn cached_page<'c>(
+    page_cache: &'c mut HashMap<String, Page>,
+    page_cache_size: usize,
+    scanner: &mut TableScanner,
+    page_number: u64,
+) -> &'c Page {
+    let key = page_number.to_string();
+    if !page_cache.contains_key(&key) {
+        evict_pages(page_cache, page_cache_size.saturating_sub(1));
+    }
+    page_cache
+        .entry(key)
+        .or_insert_with(|| scanner.page(page_number).unwrap())
+}
+
+/// Reads the rows a plan visits through the page cache, in table order.
+/// Sequential scans prefetch the pages ahead of the one they read.
+fn plan_rows(
+    table: &Table,
+    file: &File,
+    page_cache: &mut HashMap<String, Page>,
+    page_cache_size: usize,
+    plan: &QueryPlan,
+) -> Vec<Row> {
+    let hint = match plan {
+        QueryPlan::SeqScan { .. } => ScanHint::Sequential,
+        _ => ScanHint::Random,
+    };
+    let mut scanner = TableScanner::with_hint(table, file, hint);
+    let mut cached_rows = |page_number: u64| {
+        table.page_entries(cached_page(
+            page_cache,
+            page_cache_size,
+            &mut scanner,
+            page_number,
+        ))
+    };
+
+    let mut row_indexes: Vec<u64> = match plan {
+        QueryPlan::SeqScan { .. } => {
+            return (0..table.page_count())
+                .flat_map(cached_rows)
+                .map(|(_, row)| row)
+                .collect()
+        }
+        QueryPlan::IndexScan {
+            rows,
+            unindexed_from,
+            ..
+        } => rows
+            .iter()
+            .copied()
+            .chain(*unindexed_from..table.row_count)
+            .collect(),
+        QueryPlan::SkipListScan {
+            position,
+            operator,
+            literal,
+            ..
+        } => match range_rows(table, file, *position, operator, literal) {
+            Ok(rows) => rows,
+            Err(e) => {
+                println!("Warning: scanning the table, skip list failed: {:?}", e);
+                return (0..table.page_count())
+                    .flat_map(cached_rows)
+                    .map(|(_, row)| row)
+                    .collect();
+            }
+        },
+    };
+    row_indexes.retain(|row_index| *row_index < table.row_count);
+    row_indexes.sort_unstable();
+    row_indexes.dedup();
+
+    // The rows are read locked so a row being replaced or deleted is read
+    // once the write is done, never half way through. Taken in row order,
+    // and writers only lock one row, so they can not deadlock with them.
+    let name = table.name_str();
+    let locks = lock_manager();
+    for row_index in row_indexes.iter() {
+        locks.acquire_read_lock(name, *row_index);
+    }
+    let mut page_rows = vec![];
+    let mut current_page = None;
+    let mut result = vec![];
+    for row_index in row_indexes.iter().copied() {
+        let page_number = table.page_of_row(row_index);
+        if current_page != Some(page_number) {
+            page_rows = cached_rows(page_number);
+            current_page = Some(page_number);
+        }
+        // Deleted rows are missing from the page.
+        if let Ok(position) = page_rows.binary_search_by_key(&row_index, |(index, _)| *index) {
+            result.push(page_rows[position].1.clone());
+        }
+    }
+    for row_index in row_indexes {
+        locks.release_lock(name, row_index);
+    }
+    result
+}
+
+/// Every row of the table as text, decoded a page at a time through the
+/// page cache. Serves `SELECT *` without a filter.
+fn table_strings(
+    table: &Table,
+    file: &File,
+    page_cache: &mut HashMap<String, Page>,
+    page_cache_size: usize,
+) -> Vec<Vec<String>> {
+    let mut scanner = TableScanner::with_hint(table, file, ScanHint::Sequential);
+    (0..table.page_count())
+        .flat_map(|page_number| {
+            table.decode_rows_batch(cached_page(
+                page_cache,
+                page_cache_size,
+                &mut scanner,
+                page_number,
+            ))
+        })
+        .collect()
+}
+
+/// The rows of the table `filter` matches, read through the plan the
+/// optimizer picks for it.
+fn matching_rows(
+    table: &Table,
+    file: &File,
+    page_cache: &mut HashMap<String, Page>,
+    page_cache_size: usize,
+    filter: &Filter,
+) -> Result<Vec<Row>, String> {
+    let plan = QueryOptimizer::new(table)
+        .map(|optimizer| optimizer.plan_select(filter))
+        .map_err(|e| format!("{:?}", e))?;
+    let rows = plan_rows(table, file, page_cache, page_cache_size, &plan);
+    filter_rows(rows, filter, &table.columns)
+}
+
+fn filter_rows(
+    rows: Vec<Row>,
+    filter: &Filter,
+    columns: &[ColumnDefinition],
+) -> Result<Vec<Row>, String> {
+    let predicates = match filter {
+        Filter::Where(predicates) => predicates,
+        _ => return Ok(rows),
+    };
+    let mut matching = vec![];
+    for row in rows {
+        if row_matches(&row, predicates, columns)? {
+            matching.push(row);
+        }
+    }
+    Ok(matching)
+}
+
+fn row_matches(
+    row: &Row,
+    predicates: &[Predicate],
+    columns: &[ColumnDefinition],
+) -> Result<bool, String> {
+    predicates
+        .iter()
+        .map(|predicate| predicate.matches(row, columns))
+        .find(|matched| matched != &Ok(true))
+        .unwrap_or(Ok(true))
+}
+
+/// Computes a select list made of aggregates only over the rows of the table
+/// `filter` matches, feeding them to a `StreamingAggregator` as they are
+/// read. A sequential scan goes a page at a time through the page cache, so
+/// the rows are never collected, an index scan only reads the rows the index
+/// points at.
+fn stream_aggregates(
+    table: &Table,
+    file: &File,
+    page_cache: &mut HashMap<String, Page>,
+    page_cache_size: usize,
+    expressions: &[SelectExpr],
+    filter: &Filter,
+) -> Result<Row, String> {
+    let mut aggregator = StreamingAggregator::new(expressions, &table.columns)?;
+    let predicates = match filter {
+        Filter::Where(predicates) => predicates.as_slice(),
+        _ => &[],
+    };
+    let plan = QueryOptimizer::new(table)
+        .map(|optimizer| optimizer.plan_select(filter))
+        .map_err(|e| format!("{:?}", e))?;
+    if let QueryPlan::SeqScan { .. } = plan {
+        let mut scanner = TableScanner::with_hint(table, file, ScanHint::Sequential);
+        for page_number in 0..table.page_count() {
+            let page = cached_page(page_cache, page_cache_size, &mut scanner, page_number);
+            for (_, row) in table.page_entries(page) {
+                if row_matches(&row, predicates, &table.columns)? {
+                    aggregator.add(&row)?;
+                }
+            }
+        }
+    } else {
+        for row in plan_rows(table, file, page_cache, page_cache_size, &plan) {
+            if row_matches(&row, predicates, &table.columns)? {
+                aggregator.add(&row)?;
+            }
+        }
+    }
+    Ok(aggregator.finish())
+}
+
+/// Runs a SELECT against the open table or one of the common table
+/// expressions in `ctes`, which have the columns of the open table.
+fn select(
+    table: &Table,
+    file: &File,
+    page_cache: &mut HashMap<String, Page>,
+    page_cache_size: usize,
+    (query_source, scope, filter): (QuerySource, Scope, Filter),
+    ctes: &HashMap<String, Vec<Row>>,
+) -> Result<Vec<Vec<String>>, String> {
+    if let Scope::Invalid = scope {
+        return Err("Invalid select expressions".to_string());
+    }
+    if let Filter::Invalid = filter {
+        return Err("Invalid where clause".to_string());
+    }
+    let rows = match query_source {
+        QuerySource::Table(_) => {
+            if let (Scope::All, Filter::All) = (&scope, &filter) {
+                return Ok(table_strings(table, file, page_cache, page_cache_size));
+            }
+            if let Scope::Expressions(expressions) = &scope {
+                if is_streamable(expressions) {
+                    let row = stream_aggregates(
+                        table,
+                        file,
+                        page_cache,
+                        page_cache_size,
+                        expressions,
+                        &filter,
+                    )?;
+                    let columns = projected_columns(&scope, &table.columns);
+                    return Ok(vec![stringify_result(&row, &columns)]);
+                }
+            }
+            matching_rows(table, file, page_cache, page_cache_size, &filter)?
+        }
+        QuerySource::Cte(name) => match ctes.get(&name) {
+            Some(rows) => filter_rows(rows.clone(), &filter, &table.columns)?,
+            None => return Err(format!("Common table expression {} does not exist", name)),
+        },
+        QuerySource::Invalid => return Err("Invalid query source".to_string()),
+        _ => return Err("Query source not supported".to_string()),
+    };
+    project_rows(rows, &scope, &table.columns)
+}
+
+/// The select list of every row, rendered as strings.
+fn project_rows(
+    rows: Vec<Row>,
+    scope: &Scope,
+    columns: &[ColumnDefinition],
+) -> Result<Vec<Vec<String>>, String> {
+    let rows = match scope {
+        Scope::Expressions(expressions) if has_window_function(expressions) => {
+            project_windows(rows, expressions, columns)?
+        }
+        Scope::Expressions(expressions) if has_aggregate(expressions) => {
+            project_aggregates(rows, expressions, &[], columns)?
+        }
+        Scope::Grouped(expressions, group_by) => {
+            project_aggregates(rows, expressions, group_by, columns)?
+        }
+        _ => rows
+            .into_iter()
+            .map(|row| project_row(row, scope, columns))
+            .collect::<Result<Vec<Row>, String>>()?,
+    };
+    let columns = projected_columns(scope, columns);
+    Ok(rows
+        .iter()
+        .map(|row| stringify_result(row, &columns))
+        .collect())
+}
+
+/// Runs a SELECT on the open table through `plan` like `select` does,
+/// timing the scan, the filter and the projection of the rows.
+fn explain_analyze(
+    table: &Table,
+    file: &File,
+    page_cache: &mut HashMap<String, Page>,
+    page_cache_size: usize,
+    plan: &QueryPlan,
+    (scope, filter): (Scope, Filter),
+) -> Result<(Vec<Vec<String>>, ExecutionStats), String> {
+    if let Scope::Invalid = scope {
+        return Err("Invalid select expressions".to_string());
+    }
+    if let Filter::Invalid = filter {
+        return Err("Invalid where clause".to_string());
+    }
+    let pages_before = pages_read();
+    let scan_start = std::time::Instant::now();
+    let rows = plan_rows(table, file, page_cache, page_cache_size, plan);
+    let scan_time_us = scan_start.elapsed().as_micros();
+    let pages_read = pages_read() - pages_before;
+    let rows_evaluated = rows.len() as u64;
+
+    let filter_start = std::time::Instant::now();
+    let rows = filter_rows(rows, &filter, &table.columns)?;
+    let filter_time_us = filter_start.elapsed().as_micros();
+
+    let serialize_start = std::time::Instant::now();
+    let rows = project_rows(rows, &scope, &table.columns)?;
+    let serialize_time_us = serialize_start.elapsed().as_micros();
+    let stats = ExecutionStats {
+        pages_read,
+        rows_evaluated,
+        rows_returned: rows.len() as u64,
+        scan_time_us,
+        filter_time_us,
+        serialize_time_us,
+    };
+    Ok((rows, stats))
+}
+
+/// Runs a SELECT reading a view. The view's query runs against the open
+/// table first, a `SELECT *` view is then read like a common table
+/// expression. Any other view can only be read whole, as its rows no longer
+/// have the columns of the open table.
+fn select_view(
+    table: &Table,
+    file: &File,
+    page_cache: &mut HashMap<String, Page>,
+    page_cache_size: usize,
+    view: View,
+    (scope, filter): (Scope, Filter),
+) -> Result<Vec<Vec<String>>, String> {
+    let view_query = std::panic::catch_unwind(|| Query::from(&view.query))
+        .map_err(|_| format!("Invalid query of view {}", view.name))?;
+    match (view_query, &scope, &filter) {
+        (Query::Select(QuerySource::Table(_), Scope::All, Filter::Invalid), _, _) => {
+            Err("Invalid where clause".to_string())
+        }
+        (Query::Select(QuerySource::Table(_), Scope::All, view_filter), _, _) => {
+            let rows = matching_rows(table, file, page_cache, page_cache_size, &view_filter)?;
+            select(
+                table,
+                file,
+                page_cache,
+                page_cache_size,
+                (QuerySource::Cte(view.name.clone()), scope, filter),
+                &HashMap::from([(view.name, rows)]),
+            )
+        }
+        (view_query, Scope::All, Filter::All) => {
+            set_operation(table, file, page_cache, page_cache_size, view_query)
+                .map(|(_, rows)| rows)
+        }
+        _ => Err(format!(
+            "View {} has a select list, it can only be read with SELECT * and no WHERE clause",
+            view.name
+        )),
+    }
+}
+
+/// The table `name` read from disk, for queries on a table other than the
+/// open one.
+fn open_table(name: &str) -> Result<(Table, File), String> {
+    let mut file = writeable_table_file(name.to_string())
+        .map_err(|_| format!("Table {} does not exist", name))?;
+    let table = Table::read_from_disk(&mut file)?;
+    Ok((table, file))
+}
+
+/// The temporary table `name` of the connection, `None` when it has none.
+fn open_temp_table(connection_id: ConnectionId, name: &str) -> Option<(Table, File)> {
+    let mut file = writeable_table_file(temp_table_file(connection_id, name)).ok()?;
+    let table = Table::read_from_disk(&mut file).ok()?;
+    Some((table, file))
+}
+
+/// Stores the rows of `source_query` in the temporary table `name` of the
+/// connection, with the columns of the source the select list names. The
+/// select reads the open table, another temporary table of the connection
+/// or any other table. Returns the number of rows stored.
+fn select_into_temp(
+    table: &Table,
+    file: &File,
+    page_cache: &mut HashMap<String, Page>,
+    config: &Config,
+    name: &str,
+    source_query: Query,
+) -> Result<usize, String> {
+    let source = match &source_query {
+        Query::Select(QuerySource::Table(source), ..) => source.clone(),
+        _ => return Err("SELECT INTO TEMP TABLE only supports a SELECT from a table".to_string()),
+    };
+    let opened = match is_open_table(table, &source) {
+        true => None,
+        false => match open_temp_table(config.connection_id, &source) {
+            Some(opened) => Some(opened),
+            None => Some(open_table(&source)?),
+        },
+    };
+    let (columns, rows) = match &opened {
+        None => materialize_query(
+            table,
+            file,
+            page_cache,
+            config.page_cache_size,
+            source_query,
+        )?,
+        // The page cache holds pages of the open table only.
+        Some((source, source_file)) => materialize_query(
+            source,
+            source_file,
+            &mut HashMap::new(),
+            config.page_cache_size,
+            source_query,
+        )?,
+    };
+    create_temp_table(config.connection_id, name, columns, &rows)?;
+    Ok(rows.len())
+}
+
+/// The rows the `SELECT *` of an `INSERT ... SELECT` reads, from the open
+/// table or any other, fitted to the columns of `destination`.
+fn insert_select_rows(
+    table: &Table,
+    file: &File,
+    destination: &Table,
+    source_query: Query,
+) -> Result<Vec<Row>, String> {
+    let (source, filter) = match source_query {
+        Query::Select(QuerySource::Table(source), Scope::All, filter) => (source, filter),
+        _ => return Err("INSERT ... SELECT only supports SELECT * FROM a table".to_string()),
+    };
+    let predicates = match filter {
+        Filter::All => vec![],
+        Filter::Where(predicates) => predicates,
+        Filter::Invalid => return Err("Invalid where clause".to_string()),
+    };
+    let opened = match is_open_table(table, &source) {
+        true => None,
+        false => Some(open_table(&source)?),
+    };
+    let (source, source_file) = opened
+        .as_ref()
+        .map_or((table, file), |(source, file)| (source, file));
+    let rows = select_rows(source, source_file, &predicates)?;
+    Ok(fit_rows(rows, source, destination)?)
+}
+
+/// The table storing the result of the materialized view `name`, `None`
+/// when there is no such view.
+fn open_materialized_view(name: &str) -> Option<(Table, File)> {
+    let mut file = writeable_table_file(materialized_view_file(name)).ok()?;
+    let table = Table::read_from_disk(&mut file).ok()?;
+    (table.table_type == MATERIALIZED_VIEW).then_some((table, file))
+}
+
+/// Runs the query of a materialized view against the open table and returns
+/// its rows along with the columns of the table storing them, each one wide
+/// enough for its longest value. The rows are stored as rendered, so binary
+/// columns become `Varchar`.
+fn materialize(
+    table: &Table,
+    file: &File,
+    page_cache: &mut HashMap<String, Page>,
+    page_cache_size: usize,
+    view_query: &str,
+) -> Result<(Vec<ColumnDefinition>, Vec<Row>), String> {
+    let query = std::panic::catch_unwind(|| Query::from(view_query))
+        .map_err(|_| "Invalid query".to_string())?;
+    materialize_query(table, file, page_cache, page_cache_size, query)
+}
+
+/// `materialize` for a query already parsed.
+fn materialize_query(
+    table: &Table,
+    file: &File,
+    page_cache: &mut HashMap<String, Page>,
+    page_cache_size: usize,
+    query: Query,
+) -> Result<(Vec<ColumnDefinition>, Vec<Row>), String> {
+    let mut columns = output_columns(&query, &table.columns)?;
+    let (_, rows) = set_operation(table, file, page_cache, page_cache_size, query)?;
+    for column in columns.iter_mut().filter(|c| c.column_type.is_binary()) {
+        column.column_type = ColumnType::Varchar;
+    }
+    for row in rows.iter() {
+        for (column, value) in columns.iter_mut().zip(row) {
+            column.length = column.length.max(value.len() as u64);
+        }
+    }
+    let rows = rows
+        .into_iter()
+        .map(|row| Row {
+            data: row.into_iter().map(String::into_bytes).collect(),
+        })
+        .collect();
+    Ok((columns, rows))
+}
+
+/// The columns of the rows `query` returns, named after the columns of the
+/// open table they are read from. Constraints are not carried over.
+fn output_columns(
+    query: &Query,
+    columns: &[ColumnDefinition],
+) -> Result<Vec<ColumnDefinition>, String> {
+    let column_name = |column: &ColumnDefinition| column.name_str().to_string();
+    let output_column = |column: &ColumnDefinition| {
+        ColumnDefinition::new(
+            column_name(column),
+            column.column_type.clone(),
+            column.length,
+        )
+    };
+    match query {
+        Query::Select(_, Scope::All, _) => Ok(columns.iter().map(output_column).collect()),
+        Query::Select(_, Scope::Expressions(expressions) | Scope::Grouped(expressions, _), _) => {
+            Ok(expressions
+                .iter()
+                .enumerate()
+                .map(|(i, expression)| {
+                    let source = match expression {
+                        SelectExpr::Column(name) => {
+                            columns.iter().find(|c| column_name(c) == *name)
+                        }
+                        _ => None,
+                    };
+                    match source {
+                        Some(column) => output_column(column),
+                        None => ColumnDefinition::new(
+                            format!("column{}", i + 1),
+                            expression.value_type(columns),
+                            1,
+                        ),
+                    }
+                })
+                .collect())
+        }
+        Query::Union { left, .. } | Query::Intersect { left, .. } | Query::Except { left, .. } => {
+            output_columns(left, columns)
+        }
+        _ => Err("Materialized views only store SELECT queries".to_string()),
+    }
+}
+
+thread_local! {
+    /// Set while the body of a trigger runs on this thread, the statements
+    /// of a trigger do not fire triggers themselves.
+    static FIRING_TRIGGER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs the triggers on `event` of the open table after a statement made
+/// that change to `rows`. The bodies run within the caller's transaction and
+/// read the changed rows from the `NEW` pseudo-table.
+#[allow(clippy::too_many_arguments)]
+fn fire_triggers(
+    table: &mut Table,
+    file: &mut File,
+    page_cache: &mut HashMap<String, Page>,
+    database: &DatabaseConfig,
+    transaction: &mut Option<Transaction>,
+    config: &mut Config,
+    input: &mut dyn Read,
+    (event, rows): (TriggerEvent, Vec<Row>),
+) -> Result<(), String> {
+    if FIRING_TRIGGER.with(Cell::get) {
+        return Ok(());
+    }
+    let triggers = table_triggers(&triggers_file(database), table.name_str(), event)
+        .map_err(|e| format!("{:?}", e))?;
+    FIRING_TRIGGER.with(|firing| firing.set(true));
+    let fired = triggers.iter().try_for_each(|trigger| {
+        run_trigger(
+            table,
+            file,
+            page_cache,
+            database,
+            transaction,
+            config,
+            input,
+            trigger,
+            &rows,
+        )
+        .map_err(|e| format!("Trigger {} failed: {}", trigger.name, e))
+    });
+    FIRING_TRIGGER.with(|firing| firing.set(false));
+    fired
+}
+
+/// Runs the statements of a trigger body one after the other, stopping at
+/// the first one that fails. A body ending in `.sql` is the path of the file
+/// holding them. An INSERT into a table other than the open one opens that
+/// table for the write, which cannot join an open transaction.
+#[allow(clippy::too_many_arguments)]
+fn run_trigger(
+    table: &mut Table,
+    file: &mut File,
+    page_cache: &mut HashMap<String, Page>,
+    database: &DatabaseConfig,
+    transaction: &mut Option<Transaction>,
+    config: &mut Config,
+    input: &mut dyn Read,
+    trigger: &Trigger,
+    rows: &[Row],
+) -> Result<(), String> {
+    let body = match trigger.body.ends_with(".sql") {
+        true => std::fs::read_to_string(&trigger.body)
+            .map_err(|e| format!("Error reading {}: {:?}", trigger.body, e))?,
+        false => trigger.body.clone(),
+    };
+    for statement in split_outside_quotes(&body, ';') {
+        if statement.is_empty() {
+            continue;
+        }
+        let query = std::panic::catch_unwind(|| Query::from(&statement))
+            .map_err(|_| format!("Invalid query {}", statement))?;
+        let result_set = match query {
+            Query::Select(QuerySource::Table(name), scope, filter) if name == "NEW" => {
+                select(
+                    table,
+                    file,
+                    page_cache,
+                    config.page_cache_size,
+                    (QuerySource::Cte(name.clone()), scope, filter),
+                    &HashMap::from([(name, rows.to_vec())]),
+                )?;
+                continue;
+            }
+            Query::Insert(QuerySource::IntoTable(ref name), ..)
+                if !is_open_table(table, name) && transaction.is_some() =>
+            {
+                return Err(format!(
+                    "Cannot insert into {} inside a transaction, only the open table can be",
+                    name
+                ));
+            }
+            Query::Insert(QuerySource::IntoTable(ref name), ..) if !is_open_table(table, name) => {
+                let mut target_file = writeable_table_file(name.clone())
+                    .map_err(|_| format!("Table {} does not exist", name))?;
+                let mut target =
+                    Table::read_from_disk(&mut target_file).map_err(|e| format!("{:?}", e))?;
+                get_result_set(
+                    &mut target,
+                    &mut target_file,
+                    query,
+                    &mut HashMap::new(),
+                    database,
+                    transaction,
+                    config,
+                    input,
+                )
+            }
+            query => get_result_set(
+                table,
+                file,
+                query,
+                page_cache,
+                database,
+                transaction,
+                config,
+                input,
+            ),
+        };
+        if result_set.execution_status != 1 {
+            return Err(result_set.rows.concat().join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Runs the statements of the schema file at `path` one after the other,
+/// stopping at the first one that fails. Every statement is checked to be a
+/// CREATE TABLE, CREATE INDEX or CREATE VIEW before any runs. An index on a
+/// table other than the open one opens that table for it. Returns how many
+/// ran, along with the error when one failed.
+#[allow(clippy::too_many_arguments)]
+fn import_schema(
+    table: &mut Table,
+    file: &mut File,
+    page_cache: &mut HashMap<String, Page>,
+    database: &DatabaseConfig,
+    transaction: &mut Option<Transaction>,
+    config: &mut Config,
+    input: &mut dyn Read,
+    path: &str,
+) -> Result<usize, (usize, String)> {
+    let schema = std::fs::read_to_string(path)
+        .map_err(|e| (0, format!("Error reading {}: {:?}", path, e)))?;
+    let mut queries = vec![];
+    for statement in split_outside_quotes(&schema, ';') {
+        if statement.is_empty() {
+            continue;
+        }
+        let keyword = statement.split_whitespace().next().unwrap_or_default();
+        if ["INSERT", "UPDATE", "DELETE", "MERGE"].contains(&keyword) {
+            return Err((
+                0,
+                "Data manipulation not allowed in schema import".to_string(),
+            ));
+        }
+        match std::panic::catch_unwind(|| Query::from(&statement)) {
+            Ok(
+                query @ (Query::CreateTable { .. }
+                | Query::CreateIndex { .. }
+                | Query::CreateView { .. }),
+            ) => queries.push(query),
+            Ok(_) => {
+                return Err((
+                    0,
+                    format!(
+                        "Only CREATE TABLE, CREATE INDEX and CREATE VIEW are allowed in schema import, got {}",
+                        statement
+                    ),
+                ))
+            }
+            Err(_) => return Err((0, format!("Invalid query {}", statement))),
+        }
+    }
+
+    let count = queries.len();
+    for (imported, query) in queries.into_iter().enumerate() {
+        let result_set = match query {
+            Query::CreateIndex {
+                table: ref name, ..
+            } if !is_open_table(table, name) => match open_table(name) {
+                Ok((mut target, mut target_file)) => get_result_set(
+                    &mut target,
+                    &mut target_file,
+                    query,
+                    &mut HashMap::new(),
+                    database,
+                    transaction,
+                    config,
+                    input,
+                ),
+                Err(e) => return Err((imported, e)),
+            },
+            query => get_result_set(
+                table,
+                file,
+                query,
+                page_cache,
+                database,
+                transaction,
+                config,
+                input,
+            ),
+        };
+        if result_set.execution_status != 1 {
+            return Err((imported, result_set.rows.concat().join(", ")));
+        }
+    }
+    Ok(count)
+}
+
+/// Rebuilds every index of `tables` one table after the other, `REINDEX`,
+/// each held exclusively while its indexes are rebuilt. Returns a row per
+/// table with its number of indexes, the rows they hold, counted once per
+/// index, and the time it took, then one with the totals.
+fn reindex_tables(
+    table: &Table,
+    file: &File,
+    tables: &[String],
+) -> Result<Vec<Vec<String>>, String> {
+    let start = std::time::Instant::now();
+    let mut rows = vec![];
+    let (mut index_count, mut row_count) = (0, 0);
+    for name in tables {
+        let table_start = std::time::Instant::now();
+        let opened = match is_open_table(table, name) {
+            true => None,
+            false => Some(open_table(name)?),
+        };
+        let (target, target_file) = opened
+            .as_ref()
+            .map_or((table, file), |(target, target_file)| (target, target_file));
+        lock_manager().acquire_table_lock(name, TableLock::Exclusive);
+        let rebuilt = rebuild_indexes(target, target_file);
+        lock_manager().release_table_lock(name);
+        let rebuilt = rebuilt.map_err(|e| format!("{:?}", e))?;
+
+        let indexed: u64 = rebuilt.iter().map(|(_, entries)| entries).sum();
+        index_count += rebuilt.len();
+        row_count += indexed;
+        rows.push(vec![
+            name.clone(),
+            format!("{} index(es)", rebuilt.len()),
+            format!("{} rows indexed", indexed),
+            format!("{:?}", table_start.elapsed()),
+        ]);
+    }
+    rows.push(vec![
+        "Total".to_string(),
+        format!("{} index(es)", index_count),
+        format!("{} rows indexed", row_count),
+        format!("{:?}", start.elapsed()),
+    ]);
+    Ok(rows)
+}
+
+/// A row per index of the table `name`, `EXPLAIN INDEXES`: its name, its
+/// columns, its kind, whether it is unique, the size of its file and its
+/// number of entries. An index is unique when its single column holds
+/// unique values, the primary key or a `UNIQUE` column.
+fn explain_indexes(table: &Table, name: &str) -> Result<Vec<Vec<String>>, String> {
+    let opened = match is_open_table(table, name) {
+        true => None,
+        false => Some(open_table(name)?),
+    };
+    let target = opened.as_ref().map_or(table, |(target, _)| target);
+    let indexes = describe_indexes(name).map_err(|e| format!("{:?}", e))?;
+    if indexes.is_empty() {
+        return Ok(vec![vec!["No indexes found for table".to_string()]]);
+    }
+
+    let is_unique = |column: &str| {
+        let position = target.columns.iter().position(|c| c.name_str() == column);
+        position.is_some_and(|position| {
+            target.primary_key_column() == Some(position) || target.columns[position].unique
+        })
+    };
+    Ok(indexes
+        .into_iter()
+        .map(|index| {
+            let unique = matches!(index.columns.as_slice(), [column] if is_unique(column));
+            let kind = match index.kind {
+                IndexKind::BTree => "btree",
+                IndexKind::Hash => "hash",
+            };
+            vec![
+                index.name,
+                index.columns.join(","),
+                kind.to_string(),
+                if unique { "yes" } else { "no" }.to_string(),
+                index.file_size.to_string(),
+                index.entry_count.to_string(),
+            ]
+        })
+        .collect())
+}
+
+/// Vacuums every table of the database one after the other, `COMPACT
+/// DATABASE`, pushing a row per table with the size of its file before and
+/// after and the bytes recovered, then one with the totals. Each table is
+/// compacted whole or not at all, when one fails those before it stay
+/// compacted and their rows are kept ahead of the error.
+fn compact_database(
+    table: &mut Table,
+    file: &mut File,
+    page_cache: &mut HashMap<String, Page>,
+    database: &DatabaseConfig,
+    rows: &mut Vec<Vec<String>>,
+) -> Result<(), String> {
+    let tables = database_tables(&database.file_path).map_err(|e| format!("{:?}", e))?;
+    let (mut total_before, mut total_after) = (0, 0);
+    for name in tables {
+        if is_database_file(&name, &database.name) {
+            continue;
+        }
+        let vacuumed = match is_open_table(table, &name) {
+            true => {
+                page_cache.clear();
+                table.vacuum(file)
+            }
+            false => {
+                let (mut target, mut target_file) = open_table(&name)?;
+                target.vacuum(&mut target_file)
+            }
+        };
+        let (before, after) = vacuumed.map_err(|e| format!("{:?}", e))?;
+        total_before += before;
+        total_after += after;
+        rows.push(vec![
+            name,
+            format!("{} bytes", before),
+            format!("{} bytes", after),
+            format!("{} bytes recovered", before - after),
+        ]);
+    }
+    rows.push(vec![
+        "Total".to_string(),
+        format!("{} bytes", total_before),
+        format!("{} bytes", total_after),
+        format!("{} bytes recovered", total_before - total_after),
+    ]);
+    Ok(())
+}
+
+/// The sizes of the files of every table of the database, `SHOW DISK
+/// USAGE`, a row per table with the bytes of its data file, indexes,
+/// overflow and log and their total, then a `TOTAL` row. The writes
+/// buffered for the open table are flushed first so its file is counted
+/// whole.
+fn show_disk_usage(
+    table: &Table,
+    file: &File,
+    database: &DatabaseConfig,
+) -> Result<Vec<Vec<String>>, String> {
+    table.flush(file).map_err(|e| format!("{:?}", e))?;
+    let tables = database_tables(&database.file_path).map_err(|e| format!("{:?}", e))?;
+    let mut rows = vec![];
+    let mut total = DiskUsage::default();
+    for name in tables {
+        if is_database_file(&name, &database.name) {
+            continue;
+        }
+        let usage = disk_usage(&name).map_err(|e| format!("{:?}", e))?;
+        total.add(&usage);
+        let mut row = vec![name];
+        row.extend(usage.values().iter().map(|bytes| bytes.to_string()));
+        rows.push(row);
+    }
+    let mut row = vec!["TOTAL".to_string()];
+    row.extend(total.values().iter().map(|bytes| bytes.to_string()));
+    rows.push(row);
+    Ok(rows)
+}
+
+/// Runs both sides of a `UNION`, `INTERSECT` or `EXCEPT` and returns the
+/// number of columns of its rows along with them. The columns are the ones
+/// of the left side.
+fn set_operation(
+    table: &Table,
+    file: &File,
+    page_cache: &mut HashMap<String, Page>,
+    page_cache_size: usize,
+    query: Query,
+) -> Result<(usize, Vec<Vec<String>>), String> {
+    enum Operation {
+        Union { distinct: bool },
+        Intersect,
+        Except,
+    }
+    let (operation, left, right) = match query {
+        Query::Select(query_source, scope, filter) => {
+            let column_count = match &scope {
+                Scope::Expressions(expressions) | Scope::Grouped(expressions, _) => {
+                    expressions.len()
+                }
+                _ => table.columns.len(),
+            };
+            let rows = select(
+                table,
+                file,
+                page_cache,
+                page_cache_size,
+                (query_source, scope, filter),
+                &HashMap::new(),
+            )?;
+            return Ok((column_count, rows));
+        }
+        Query::Union {
+            left,
+            right,
+            distinct,
+        } => (Operation::Union { distinct }, left, right),
+        Query::Intersect { left, right } => (Operation::Intersect, left, right),
+        Query::Except { left, right } => (Operation::Except, left, right),
+        _ => return Err("Set operations only combine SELECT queries".to_string()),
+    };
+    let (column_count, left_rows) = set_operation(table, file, page_cache, page_cache_size, *left)?;
+    let (right_column_count, right_rows) =
+        set_operation(table, file, page_cache, page_cache_size, *right)?;
+    if column_count != right_column_count {
+        return Err(format!(
+            "Each query of a set operation must have the same number of columns, got {} and {}",
+            column_count, right_column_count
+        ));
+    }
+
+    let mut seen = HashSet::new();
+    let rows = match operation {
+        Operation::Union { distinct } => {
+            let mut rows = left_rows;
+            rows.extend(right_rows);
+            if distinct {
+                rows.retain(|row| seen.insert(row.clone()));
+            }
+            rows
+        }
+        Operation::Intersect => {
+            let left_rows: HashSet<Vec<String>> = left_rows.into_iter().collect();
+            right_rows
+                .into_iter()
+                .filter(|row| left_rows.contains(row) && seen.insert(row.clone()))
+                .collect()
+        }
+        Operation::Except => {
+            let right_rows: HashSet<Vec<String>> = right_rows.into_iter().collect();
+            left_rows
+                .into_iter()
+                .filter(|row| !right_rows.contains(row) && seen.insert(row.clone()))
+                .collect()
+        }
+    };
+    Ok((column_count, rows))
+}
+
+/// Runs a query against the table. `input` is the stream the query was read
+/// from, `COPY ... FROM STDIN` reads its rows from it. SELECT results are
+/// served from the query cache while they are fresh, every query that may
+/// change the table drops its cached results. Columns the user of the
+/// connection was not granted are stripped from the result.
+#[allow(clippy::too_many_arguments)]
+fn get_result_set(
+    table: &mut Table,
+    file: &mut File,
+    query: Query,
+    page_cache: &mut HashMap<String, Page>,
+    database: &DatabaseConfig,
+    transaction: &mut Option<Transaction>,
+    config: &mut Config,
+    input: &mut dyn Read,
+) -> ResultSet {
+    let start_time = std::time::Instant::now();
+    let restricted = match restricted_columns(&query, table, database, config) {
+        Ok(restricted) => restricted,
+        Err(message) => return result_set(vec![vec![message]], start_time, 0),
+    };
+    let mut result_set = cached_result_set(
+        table,
+        file,
+        query,
+        page_cache,
+        database,
+        transaction,
+        config,
+        input,
+    );
+    if !restricted.is_empty() && result_set.execution_status == 1 {
+        for row in result_set.rows.iter_mut() {
+            *row = std::mem::take(row)
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| !restricted.contains(i))
+                .map(|(_, value)| value)
+                .collect();
+        }
+    }
+    result_set
+}
+
+/// Whether the user of the connection is the administrator, as anyone is
+/// without authentication.
+pub fn is_administrator(database: &DatabaseConfig, config: &Config) -> Result<bool, String> {
+    match (&config.user, &database.auth_file) {
+        (Some(user), Some(auth_file)) => is_admin(auth_file, user).map_err(|e| format!("{:?}", e)),
+        _ => Ok(true),
+    }
+}
+
+/// An error unless the user of the connection is the administrator, for the
+/// statements only they may run.
+pub fn require_admin(
+    statement: &str,
+    database: &DatabaseConfig,
+    config: &Config,
+) -> Result<(), String> {
+    match is_administrator(database, config)? {
+        true => Ok(()),
+        false => Err(format!("Permission denied for {}", statement)),
+    }
+}
+
+/// The output columns of `query` the user of the connection was not granted,
+/// they are stripped from its result. An error when the user may not run the
+/// query at all. Columns are only stripped from a `SELECT` of the table, any
+/// other query reading a table with columns the user was not granted is
+/// denied. Nothing is checked without an authenticated user, or for the
+/// administrator, who alone manages users and grants.
+fn restricted_columns(
+    query: &Query,
+    table: &Table,
+    database: &DatabaseConfig,
+    config: &Config,
+) -> Result<Vec<usize>, String> {
+    let (Some(user), Some(auth_file)) = (&config.user, &database.auth_file) else {
+        return Ok(vec![]);
+    };
+    if is_admin(auth_file, user).map_err(|e| format!("{:?}", e))? {
+        return Ok(vec![]);
+    }
+    let grants_file = grants_file(database);
+    let statement = match query {
+        Query::CreateUser { .. } => Some("CREATE USER"),
+        Query::DropUser(_) => Some("DROP USER"),
+        Query::Grant { .. } => Some("GRANT"),
+        Query::Revoke { .. } => Some("REVOKE"),
+        _ => None,
+    };
+    if let Some(statement) = statement {
+        return Err(format!("Permission denied for {}", statement));
+    }
+    for name in read_tables(query) {
+        // A name the executor does not know runs against the open table.
+        let name = match is_open_table(table, &name) || is_other_source(&name, database, config) {
+            true => name,
+            false => table.name_str().to_string(),
+        };
+        let operation = GrantOperation::Select;
+        match permitted_columns(&grants_file, user, &name, operation) {
+            Ok(Some(permitted)) if !permitted.iter().any(|column| column == "*") => {
+                return Err(format!(
+                    "Permission denied for {} on {}",
+                    operation.name(),
+                    name
+                ))
+            }
+            Ok(_) => {}
+            Err(e) => return Err(format!("{:?}", e)),
+        }
+    }
+
+    let (name, operation) = match query {
+        Query::Select(QuerySource::Table(name), ..) => (name, GrantOperation::Select),
+        Query::Insert(QuerySource::IntoTable(name), ..)
+        | Query::Upsert(QuerySource::IntoTable(name), ..)
+        | Query::InsertSelect {
+            dest_table: name, ..
+        }
+        | Query::Merge { target: name, .. } => (name, GrantOperation::Insert),
+        _ => return Ok(vec![]),
+    };
+    // The executor runs an unknown name against the open table, whose grants
+    // would not be the ones checked. Only a `SELECT` reads temporary tables.
+    let known = match operation {
+        GrantOperation::Select => is_other_source(name, database, config),
+        GrantOperation::Insert => is_view(name, database),
+    };
+    if !is_open_table(table, name) && !known {
+        return Err(format!("Table {} does not exist", name));
+    }
+    let permitted = match permitted_columns(&grants_file, user, name, operation) {
+        Ok(Some(permitted)) => permitted,
+        Ok(None) => return Ok(vec![]),
+        Err(e) => return Err(format!("{:?}", e)),
+    };
+    if permitted.iter().any(|column| column == "*") {
+        return Ok(vec![]);
+    }
+    let denied = || format!("Permission denied for {} on {}", operation.name(), name);
+
+    match query {
+        Query::Insert(_, ColumnList::Columns(columns), ..)
+        | Query::Upsert(_, ColumnList::Columns(columns), ..) => {
+            match columns.iter().find(|column| !permitted.contains(column)) {
+                Some(column) => Err(format!("{} column {}", denied(), column)),
+                None => Ok(vec![]),
+            }
+        }
+        // Every column of the table may be written.
+        Query::InsertSelect { .. } | Query::Merge { .. } => Err(denied()),
+        // The columns of a view are only known once it ran.
+        Query::Select(..) if !is_open_table(table, name) => Err(denied()),
+        Query::Select(..) => {
+            let columns = output_columns(query, &table.columns)?;
+            let restricted: Vec<usize> = columns
+                .iter()
+                .enumerate()
+                .filter(|(_, column)| {
+                    !permitted
+                        .iter()
+                        .any(|permitted| permitted == column.name_str())
+                })
+                .map(|(i, _)| i)
+                .collect();
+            match restricted.len() == columns.len() {
+                true => Err(denied()),
+                false => Ok(restricted),
+            }
+        }
+        _ => Err(denied()),
+    }
+}
+
+/// Whether `name` is a view or a materialized view.
+fn is_view(name: &str, database: &DatabaseConfig) -> bool {
+    open_materialized_view(name).is_some()
+        || matches!(find_view(&views_file(database), name), Ok(Some(_)))
+}
+
+/// Whether a `SELECT` of `name` reads something other than the open table, a
+/// temporary table of the connection or a view.
+fn is_other_source(name: &str, database: &DatabaseConfig, config: &Config) -> bool {
+    table_exists(&temp_table_file(config.connection_id, name)) || is_view(name, database)
+}
+
+/// The tables `query` reads rows of other than through a `SELECT` of the
+/// table, whose result `restricted_columns` can strip.
+fn read_tables(query: &Query) -> Vec<String> {
+    let shared = |query: &Query| -> Vec<String> {
+        query
+            .table_accesses()
+            .into_iter()
+            .filter(|(_, lock)| *lock == TableLock::Shared)
+            .map(|(name, _)| name.to_string())
+            .collect()
+    };
+    match query {
+        Query::Select(..) => vec![],
+        Query::CreateMaterializedView { query, .. } => {
+            std::panic::catch_unwind(|| Query::from(query.as_str()))
+                .map_or(vec![], |query| shared(&query))
+        }
+        query => shared(query),
+    }
+}
+
+/// `get_result_set` once the grants were checked.
+#[allow(clippy::too_many_arguments)]
+fn cached_result_set(
+    table: &mut Table,
+    file: &mut File,
+    query: Query,
+    page_cache: &mut HashMap<String, Page>,
+    database: &DatabaseConfig,
+    transaction: &mut Option<Transaction>,
+    config: &mut Config,
+    input: &mut dyn Read,
+) -> ResultSet {
+    let start_time = std::time::Instant::now();
+    let name = table.name_str().to_string();
+    let cache_key = match &query {
+        // Temporary tables are only seen by their connection.
+        Query::Select(QuerySource::Table(source), ..)
+            if table_exists(&temp_table_file(config.connection_id, source)) =>
+        {
+            None
+        }
+        // Keyed by the fingerprint of the parsed query, spacing and comments
+        // do not matter.
+        Query::Select(..) => {
+            let (fingerprint, literals) = split_literals(&format!("{:?}", query));
+            Some((hash(fingerprint.as_bytes()), literals))
+        }
+        Query::Explain(_)
+        | Query::ExplainAnalyze(_)
+        | Query::ExplainJson(_)
+        | Query::Show(_)
+        | Query::ShowViews
+        | Query::ShowProcesslist
+        | Query::ShowConfig
+        | Query::ShowDiskUsage
+        | Query::FlushQueryLog
+        | Query::ShowQueryLog(_)
+        | Query::ClearQueryLog
+        | Query::Kill(_)
+        | Query::LockTable { .. }
+        | Query::UnlockTable(_)
+        | Query::ShowTableStats(_)
+        | Query::ShowCreateTable(_)
+        | Query::DebugDump(_)
+        | Query::Checksum(_)
+        | Query::ExplainIndexes(_)
+        | Query::ShowGrants(_)
+        | Query::Prepare { .. }
+        | Query::Execute { .. }
+        | Query::Set { .. }
+        | Query::With { .. }
+        | Query::Union { .. }
+        | Query::Intersect { .. }
+        | Query::Except { .. } => None,
+        _ => {
+            query_cache().lock().unwrap().invalidate(&name);
+            None
+        }
+    };
+    if let Some((key, literals)) = &cache_key {
+        let cached = query_cache()
+            .lock()
+            .unwrap()
+            .get(*key, literals, &name, table.row_count);
+        if let Some(cached) = cached {
+            return result_set(cached.rows, start_time, cached.execution_status);
+        }
+    }
+
+    let result_set = run_query(
+        table,
+        file,
+        query,
+        page_cache,
+        database,
+        transaction,
+        config,
+        input,
+    );
+    if let Some((key, literals)) = cache_key.filter(|_| result_set.execution_status == 1) {
+        let mut cache = query_cache().lock().unwrap();
+        cache.insert(key, literals, &name, table.row_count, result_set.clone());
+    }
+    result_set
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_query(
+    table: &mut Table,
+    file: &mut File,
+    query: Query,
+    page_cache: &mut HashMap<String, Page>,
+    database: &DatabaseConfig,
+    transaction: &mut Option<Transaction>,
+    config: &mut Config,
+    input: &mut dyn Read,
+) -> ResultSet {
+    let mut result_rows: Vec<Vec<String>> = Vec::new();
+    let start_time = std::time::Instant::now();
+    let mut status: u8 = 0;
+    // The rows a statement changed, passed to the triggers on the change.
+    let mut changed_rows: Option<(TriggerEvent, Vec<Row>)> = None;
+    match query {
+        Query::Select(query_source, scope, filter) => {
+            // A name other than the open table's may be a view.
+            let view_name = match &query_source {
+                QuerySource::Table(name) if !is_open_table(table, name) => Some(name.clone()),
+                _ => None,
+            };
+            // Temporary tables of the connection come before views.
+            let materialized = view_name.as_deref().and_then(|name| {
+                open_temp_table(config.connection_id, name).or_else(|| open_materialized_view(name))
+            });
+            let view = match (&view_name, &materialized) {
+                (Some(name), None) => {
+                    find_view(&views_file(database), name).map_err(|e| format!("{:?}", e))
+                }
+                _ => Ok(None),
+            };
+            let selected = view.and_then(|view| match (materialized, view) {
+                // The page cache holds pages of the open table only.
+                (Some((view_table, view_file)), _) => select(
+                    &view_table,
+                    &view_file,
+                    &mut HashMap::new(),
+                    config.page_cache_size,
+                    (query_source, scope, filter),
+                    &HashMap::new(),
+                ),
+                (None, Some(view)) => select_view(
+                    table,
+                    file,
+                    page_cache,
+                    config.page_cache_size,
+                    view,
+                    (scope, filter),
+                ),
+                (None, None) => select(
+                    table,
+                    file,
+                    page_cache,
+                    config.page_cache_size,
+                    (query_source, scope, filter),
+                    &HashMap::new(),
+                ),
+            });
+            match selected {
+                Ok(rows) => {
+                    result_rows = rows;
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![e]);
+                }
+            }
+        }
+        Query::Union { .. } | Query::Intersect { .. } | Query::Except { .. } => {
+            match set_operation(table, file, page_cache, config.page_cache_size, query) {
+                Ok((_, rows)) => {
+                    result_rows = rows;
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![e]);
+                }
+            }
+        }
+        Query::With { name, cte, query } => {
+            // The rows are materialized with the schema of the open table,
+            // so only whole rows of it can be named.
+            let materialized = match *cte {
+                Query::Select(QuerySource::Table(_), Scope::All, Filter::Invalid) => {
+                    Err("Invalid where clause".to_string())
+                }
+                Query::Select(QuerySource::Table(_), Scope::All, filter) => {
+                    matching_rows(table, file, page_cache, config.page_cache_size, &filter)
+                }
+                _ => Err("WITH only supports SELECT * FROM a table".to_string()),
+            };
+            let selected = materialized.and_then(|rows| match *query {
+                Query::Select(query_source, scope, filter) => select(
+                    table,
+                    file,
+                    page_cache,
+                    config.page_cache_size,
+                    (query_source, scope, filter),
+                    &HashMap::from([(name, rows)]),
+                ),
+                _ => Err("WITH must be followed by a SELECT".to_string()),
+            });
+            match selected {
+                Ok(rows) => {
+                    result_rows = rows;
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![e]);
+                }
+            }
+        }
+        Query::Insert(QuerySource::IntoTable(name), ..)
+        | Query::Upsert(QuerySource::IntoTable(name), ..)
+        | Query::InsertSelect {
+            dest_table: name, ..
+        }
+        | Query::Merge { target: name, .. }
+            if matches!(find_view(&views_file(database), &name), Ok(Some(_))) =>
+        {
+            result_rows.push(vec![format!(
+                "Cannot insert into view {}, views are read-only",
+                name
+            )]);
+        }
+        Query::Insert(QuerySource::IntoTable(name), ..)
+        | Query::Upsert(QuerySource::IntoTable(name), ..)
+        | Query::InsertSelect {
+            dest_table: name, ..
+        }
+        | Query::Merge { target: name, .. }
+            if open_materialized_view(&name).is_some() =>
+        {
+            result_rows.push(vec![format!(
+                "Cannot insert into materialized view {}, it only changes on refresh",
+                name
+            )]);
+        }
+        Query::Insert(_, _, _, Some(Scope::Invalid)) => {
+            result_rows.push(vec!["Invalid returning expressions".to_string()]);
+        }
+        Query::Insert(query_source, column_list, value_list, returning) => match query_source {
+            QuerySource::IntoTable(_) => match column_list {
+                query::ColumnList::Columns(columns) => match value_list {
+                    query::ValueList::Values(row_data) => {
+                        let num_inserting = row_data.len();
+                        let message = format!("Inserting {} row(s)", num_inserting);
+                        let rows: Result<Vec<Row>, String> = row_data
+                            .iter()
+                            .map(|values| {
+                                let values = resolve_sequence_values(values, database)
+                                    .map_err(|e| format!("{:?}", e))?;
+                                Ok(table.row_for_columns(&columns, &values)?)
+                            })
+                            .collect();
+                        // The RETURNING values come from the rows as they are
+                        // written, defaults and sequence values included.
+                        let rows = rows.and_then(|rows| {
+                            let returned = match &returning {
+                                Some(scope) => {
+                                    let columns = projected_columns(scope, &table.columns);
+                                    rows.iter()
+                                        .map(|row| {
+                                            project_row(row.clone(), scope, &table.columns)
+                                                .map(|row| stringify_result(&row, &columns))
+                                        })
+                                        .collect::<Result<Vec<Vec<String>>, String>>()?
+                                }
+                                None => vec![vec![message]],
+                            };
+                            Ok((rows, returned))
+                        });
+                        match rows {
+                            Ok((rows, returned)) if transaction.is_some() => {
+                                let pending = transaction.as_mut().unwrap();
+                                result_rows.extend(returned);
+                                for row in rows.iter() {
+                                    pending.push(Mutation::Insert(row.clone()));
+                                }
+                                changed_rows = Some((TriggerEvent::Insert, rows));
+                                status = 1;
+                            }
+                            Ok((rows, returned)) => {
+                                let inserted = table.add_rows(&rows, file).and_then(|()| {
+                                    auto_analyze(table, file, config, rows.len() as u64)
+                                });
+                                match inserted {
+                                    Ok(()) => {
+                                        result_rows.extend(returned);
+                                        changed_rows = Some((TriggerEvent::Insert, rows));
+                                        status = 1;
+                                    }
+                                    Err(e) if returning.is_some() => {
+                                        result_rows.push(vec![format!("{:?}", e)]);
+                                    }
+                                    Err(e) => {
+                                        result_rows.extend(returned);
+                                        result_rows.push(vec![format!("{:?}", e)]);
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                result_rows.push(vec![e]);
+                            }
+                        }
+                    }
+                    query::ValueList::Invalid => {
+                        result_rows.push(vec!["Invalid value list".to_string()]);
+                    }
+                },
+                query::ColumnList::Invalid => {
+                    result_rows.push(vec!["Invalid column list".to_string()]);
+                }
+            },
+            QuerySource::Invalid => {
+                result_rows.push(vec!["Invalid query source".to_string()]);
+            }
+            _ => {
+                result_rows.push(vec!["Query source not supported".to_string()]);
+            }
+        },
+        Query::Upsert(..) if table.primary_key_column().is_none() => {
+            result_rows.push(vec![format!(
+                "INSERT OR REPLACE requires a primary key on table {}",
+                table.name_str()
+            )]);
+        }
+        Query::Upsert(
+            QuerySource::IntoTable(_),
+            query::ColumnList::Columns(columns),
+            query::ValueList::Values(row_data),
+        ) => {
+            let rows: Result<Vec<Row>, String> = row_data
+                .iter()
+                .map(|values| {
+                    let values = resolve_sequence_values(values, database)
+                        .map_err(|e| format!("{:?}", e))?;
+                    Ok(table.row_for_columns(&columns, &values)?)
+                })
+                .collect();
+            match rows {
+                Ok(rows) if transaction.is_some() => {
+                    let transaction = transaction.as_mut().unwrap();
+                    result_rows.push(vec![format!("Upserting {} row(s)", rows.len())]);
+                    for row in rows {
+                        transaction.push(Mutation::Upsert(row));
+                    }
+                    status = 1;
+                }
+                Ok(rows) => {
+                    let (mut inserted, mut replaced) = (0, 0);
+                    status = 1;
+                    for row in rows.iter() {
+                        match table.upsert_row(row, file) {
+                            Ok(Upsert::Inserted) => inserted += 1,
+                            Ok(Upsert::Replaced) => replaced += 1,
+                            Err(e) => {
+                                result_rows.push(vec![format!("{:?}", e)]);
+                                status = 0;
+                                break;
+                            }
+                        }
+                    }
+                    let written = (inserted + replaced) as u64;
+                    if let Err(e) = auto_analyze(table, file, config, written) {
+                        result_rows.push(vec![format!("{:?}", e)]);
+                        status = 0;
+                    }
+                    if status == 1 {
+                        result_rows.extend(upsert_messages(inserted, replaced));
+                    }
+                }
+                Err(e) => {
+                    result_rows.push(vec![e]);
+                }
+            }
+        }
+        Query::Upsert(QuerySource::IntoTable(_), query::ColumnList::Columns(_), _) => {
+            result_rows.push(vec!["Invalid value list".to_string()]);
+        }
+        Query::Upsert(QuerySource::IntoTable(_), _, _) => {
+            result_rows.push(vec!["Invalid column list".to_string()]);
+        }
+        Query::Upsert(..) => {
+            result_rows.push(vec!["Invalid query source".to_string()]);
+        }
+        Query::AlterTableRenameColumn {
+            table: table_name, ..
+        } if !is_open_table(table, &table_name) => {
+            result_rows.push(vec![format!("Table {} does not exist", table_name)]);
+        }
+        Query::AlterTableRenameColumn {
+            table: _,
+            old_name,
+            new_name,
+        } => match table.rename_column(&old_name, &new_name, file) {
+            Ok(()) => {
+                result_rows.push(vec![format!("Renamed column {} to {}", old_name, new_name)]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![e.to_string()]);
+            }
+        },
+        Query::CreateTable {
+            table: table_name,
+            if_not_exists: true,
+            ..
+        } if table_exists(&table_name) => {
+            result_rows.push(vec!["Table already exists, skipped".to_string()]);
+            status = 1;
+        }
+        Query::CreateTable {
+            table: table_name,
+            columns,
+            ..
+        } => match columns {
+            ColumnDefinitionList::Definitions(columns) => {
+                match create_table(TableBuilder::new(&table_name).columns(columns)) {
+                    Ok(()) => {
+                        result_rows.push(vec![format!("Created table {}", table_name)]);
+                        status = 1;
+                    }
+                    Err(e) => {
+                        result_rows.push(vec![e]);
+                    }
+                }
+            }
+            ColumnDefinitionList::Invalid => {
+                result_rows.push(vec!["Invalid column definitions".to_string()]);
+            }
+        },
+        Query::DropTable {
+            table: table_name,
+            if_exists: true,
+        } if !table_exists(&table_name) => {
+            result_rows.push(vec!["Table does not exist, skipped".to_string()]);
+            status = 1;
+        }
+        Query::DropTable {
+            table: table_name, ..
+        } if table_name == table.name_str() => {
+            result_rows.push(vec![format!("Cannot drop the open table {}", table_name)]);
+        }
+        Query::DropTable {
+            table: table_name, ..
+        } => {
+            lock_manager().acquire_table_lock(&table_name, TableLock::Exclusive);
+            let dropped = drop_table(&table_name);
+            lock_manager().release_table_lock(&table_name);
+            match dropped {
+                Ok(()) => {
+                    query_cache().lock().unwrap().invalidate(&table_name);
+                    result_rows.push(vec![format!("Dropped table {}", table_name)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![e]);
+                }
+            }
+        }
+        Query::CreateSequence {
+            name,
+            start,
+            increment,
+        } => match create_sequence(&sequences_file(database), &name, start, increment) {
+            Ok(()) => {
+                result_rows.push(vec![format!("Created sequence {}", name)]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::CreateProcedure { name, body } => {
+            match create_procedure(&procedures_file(database), &name, &body) {
+                Ok(()) => {
+                    result_rows.push(vec![format!("Created procedure {}", name)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::CreateView { name, .. } if table_exists(&name) => {
+            result_rows.push(vec![format!("Table {} already exists", name)]);
+        }
+        Query::CreateView { name, query } => {
+            match create_view(&views_file(database), &name, &query) {
+                Ok(()) => {
+                    result_rows.push(vec![format!("Created view {}", name)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::SelectIntoTemp {
+            dest_table,
+            source_query,
+        } => match select_into_temp(table, file, page_cache, config, &dest_table, *source_query) {
+            Ok(rows) => {
+                result_rows.push(vec![format!(
+                    "Created temporary table {} with {}",
+                    dest_table,
+                    row_count_label(rows)
+                )]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![e]);
+            }
+        },
+        Query::DropTempTable(name) => match drop_temp_table(config.connection_id, &name) {
+            Ok(()) => {
+                result_rows.push(vec![format!("Dropped temporary table {}", name)]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![e]);
+            }
+        },
+        Query::DropView(name) => match drop_view(&views_file(database), &name) {
+            Ok(()) => {
+                result_rows.push(vec![format!("Dropped view {}", name)]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::ShowViews => match view_names(&views_file(database)) {
+            Ok(names) => {
+                result_rows = names.into_iter().map(|name| vec![name]).collect();
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::CreateMaterializedView { name, .. } if table_exists(&name) => {
+            result_rows.push(vec![format!("Table {} already exists", name)]);
+        }
+        Query::CreateMaterializedView { name, query } => {
+            let created = materialize(table, file, page_cache, config.page_cache_size, &query)
+                .and_then(|(columns, rows)| {
+                    create_view(&materialized_views_file(database), &name, &query)
+                        .map_err(|e| format!("{:?}", e))?;
+                    write_materialized_view(&name, columns, &rows)
+                });
+            match created {
+                Ok(()) => {
+                    result_rows.push(vec![format!("Created materialized view {}", name)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![e]);
+                }
+            }
+        }
+        Query::RefreshMaterializedView(name) => {
+            let refreshed = find_view(&materialized_views_file(database), &name)
+                .map_err(|e| format!("{:?}", e))
+                .and_then(|view| {
+                    view.ok_or_else(|| format!("Materialized view {} does not exist", name))
+                })
+                .and_then(|view| {
+                    materialize(table, file, page_cache, config.page_cache_size, &view.query)
+                })
+                .and_then(|(columns, rows)| write_materialized_view(&name, columns, &rows));
+            match refreshed {
+                Ok(()) => {
+                    result_rows.push(vec![format!("Refreshed materialized view {}", name)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![e]);
+                }
+            }
+        }
+        Query::CreateTrigger {
+            name,
+            event,
+            table: table_name,
+            body,
+        } => {
+            let trigger = Trigger {
+                name: name.clone(),
+                event,
+                table: table_name,
+                body,
+            };
+            match create_trigger(&triggers_file(database), trigger) {
+                Ok(()) => {
+                    result_rows.push(vec![format!("Created trigger {}", name)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::DropTrigger { name } => match drop_trigger(&triggers_file(database), &name) {
+            Ok(()) => {
+                result_rows.push(vec![format!("Dropped trigger {}", name)]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::CreateUser { .. } | Query::DropUser(_) if database.auth_file.is_none() => {
+            result_rows.push(vec![
+                "No auth file, start with --auth-file to manage users".to_string()
+            ]);
+        }
+        Query::CreateUser { name, password } => {
+            let path = database.auth_file.as_deref().unwrap();
+            match create_user(path, &name, &password) {
+                Ok(()) => {
+                    result_rows.push(vec![format!("Created user {}", name)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::DropUser(name) => match drop_user(database.auth_file.as_deref().unwrap(), &name) {
+            Ok(()) => {
+                result_rows.push(vec![format!("Dropped user {}", name)]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::Prepare { name, sql } => {
+            match std::panic::catch_unwind(|| Query::from(sql.as_str())) {
+                Ok(query) => {
+                    config
+                        .prepared
+                        .insert(name.clone(), PreparedQuery::new(query));
+                    result_rows.push(vec![format!("Prepared {}", name)]);
+                    status = 1;
+                }
+                Err(_) => {
+                    result_rows.push(vec!["Invalid query".to_string()]);
+                }
+            }
+        }
+        Query::Execute { name, args } => {
+            let bound = match config.prepared.get(&name) {
+                Some(prepared) => prepared.bind(&args),
+                None => Err(format!("Prepared statement {} does not exist", name)),
+            };
+            match bound {
+                Ok(query) => {
+                    let result_set = get_result_set(
+                        table,
+                        file,
+                        query,
+                        page_cache,
+                        database,
+                        transaction,
+                        config,
+                        input,
+                    );
+                    result_rows = result_set.rows;
+                    status = result_set.execution_status;
+                }
+                Err(e) => {
+                    result_rows.push(vec![e]);
+                }
+            }
+        }
+        Query::Grant {
+            operation,
+            columns,
+            table: table_name,
+            user,
+        } => {
+            let granted = Grant {
+                user: user.clone(),
+                table: table_name.clone(),
+                operation,
+                columns,
+            };
+            match grant(&grants_file(database), granted) {
+                Ok(()) => {
+                    result_rows.push(vec![format!(
+                        "Granted {} on {} to {}",
+                        operation.name(),
+                        table_name,
+                        user
+                    )]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::Revoke {
+            operation,
+            table: table_name,
+            user,
+        } => match revoke(&grants_file(database), &user, &table_name, operation) {
+            Ok(()) => {
+                result_rows.push(vec![format!(
+                    "Revoked {} on {} from {}",
+                    operation.name(),
+                    table_name,
+                    user
+                )]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::ShowGrants(user) => match user_grants(&grants_file(database), &user) {
+            Ok(grants) => {
+                result_rows = grants
+                    .into_iter()
+                    .map(|grant| {
+                        vec![
+                            grant.table,
+                            grant.operation.name().to_string(),
+                            grant.columns.join(", "),
+                        ]
+                    })
+                    .collect();
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::CallProcedure { name, args } => {
+            let called = find_procedure(&procedures_file(database), &name)
+                .map_err(|e| format!("{:?}", e))
+                .and_then(|procedure| {
+                    run_procedure(&name, &procedure.body, &args, |sql| {
+                        let query = match std::panic::catch_unwind(|| Query::from(sql)) {
+                            Ok(query) => query,
+                            Err(_) => return Ok(vec![vec!["Invalid query".to_string()]]),
+                        };
+                        // The sandbox of the script keeps it away from files.
+                        let touches_files = match &query {
+                            Query::Execute { name, .. } => config
+                                .prepared
+                                .get(name)
+                                .is_some_and(|prepared| prepared.touches_files()),
+                            query => query.touches_files(),
+                        };
+                        if touches_files {
+                            return Err(format!("Procedures cannot touch files, {} refused", sql));
+                        }
+                        Ok(get_result_set(
+                            table,
+                            file,
+                            query,
+                            page_cache,
+                            database,
+                            transaction,
+                            config,
+                            input,
+                        )
+                        .rows)
+                    })
+                    .map_err(|e| format!("Procedure {} failed: {}", name, e))
+                });
+            match called {
+                Ok(rows) if rows.is_empty() => {
+                    result_rows.push(vec![format!("Called procedure {}", name)]);
+                    status = 1;
+                }
+                Ok(rows) => {
+                    result_rows = rows;
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![e]);
+                }
+            }
+        }
+        Query::DropSequence(name) => match drop_sequence(&sequences_file(database), &name) {
+            Ok(()) => {
+                result_rows.push(vec![format!("Dropped sequence {}", name)]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::AlterSequenceRestart { name, value } => {
+            match restart_sequence(&sequences_file(database), &name, value) {
+                Ok(()) => {
+                    result_rows.push(vec![format!("Restarted sequence {}", name)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::RenameTable { from, .. } if !is_open_table(table, &from) => {
+            result_rows.push(vec![format!("Table {} does not exist", from)]);
+        }
+        Query::RenameTable { from, to } => match rename_table(&from, &to, table, file) {
+            Ok(()) => {
+                result_rows.push(vec![format!("Renamed table {} to {}", from, to)]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![e]);
+            }
+        },
+        Query::Begin if transaction.is_some() => {
+            result_rows.push(vec!["Transaction already started".to_string()]);
+        }
+        Query::Begin => {
+            *transaction = Some(Transaction::default());
+            result_rows.push(vec!["Started transaction".to_string()]);
+            status = 1;
+        }
+        Query::Commit
+        | Query::Rollback
+        | Query::Savepoint(_)
+        | Query::RollbackToSavepoint(_)
+        | Query::ReleaseSavepoint(_)
+            if transaction.is_none() =>
+        {
+            result_rows.push(vec!["No transaction in progress".to_string()]);
+        }
+        Query::Commit => {
+            // Mutations are applied in order, one that fails stops the commit
+            // and leaves the ones before it in place.
+            let mutations = transaction.take().unwrap().into_mutations();
+            let mutation_count = mutations.len();
+            status = 1;
+            // Writes of the mutations reach the table file together.
+            let applied = table.buffered(file, |table, file| {
+                for mutation in mutations {
+                    match mutation {
+                        Mutation::Insert(row) => table.add_row(&row, file)?,
+                        Mutation::Upsert(row) => table.upsert_row(&row, file).map(|_| ())?,
+                    }
+                }
+                Ok(())
+            });
+            if let Err(e) = applied {
+                result_rows.push(vec![format!("{:?}", e)]);
+                status = 0;
+            }
+            if status == 1 {
+                if let Err(e) = auto_analyze(table, file, config, mutation_count as u64) {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                    status = 0;
+                }
+            }
+            if status == 1 {
+                result_rows.push(vec![format!("Committed {} mutation(s)", mutation_count)]);
+            }
+        }
+        Query::Rollback => {
+            *transaction = None;
+            result_rows.push(vec!["Rolled back transaction".to_string()]);
+            status = 1;
+        }
+        Query::Savepoint(name) => {
+            transaction.as_mut().unwrap().savepoint(&name);
+            result_rows.push(vec![format!("Created savepoint {}", name)]);
+            status = 1;
+        }
+        Query::RollbackToSavepoint(name) => {
+            match transaction.as_mut().unwrap().rollback_to(&name) {
+                Ok(()) => {
+                    result_rows.push(vec![format!("Rolled back to savepoint {}", name)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![e]);
+                }
+            }
+        }
+        Query::ReleaseSavepoint(name) => match transaction.as_mut().unwrap().release(&name) {
+            Ok(()) => {
+                result_rows.push(vec![format!("Released savepoint {}", name)]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![e]);
+            }
+        },
+        Query::Backup(path) => {
+            // Writers wait for the copy so every file is from the same point
+            // in time, readers can keep going.
+            let name = table.name_str();
+            let mut files = table_files(table);
+            files.push(sequences_file(database));
+            lock_manager().acquire_table_lock(name, TableLock::Shared);
+            let backed_up = backup(&path, &files);
+            lock_manager().release_table_lock(name);
+            match backed_up {
+                Ok(count) => {
+                    result_rows.push(vec![format!("Backed up {} file(s) to {}", count, path)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::Restore(path) => {
+            let name = table.name_str().to_string();
+            lock_manager().acquire_table_lock(&name, TableLock::Exclusive);
+            let restored = restore(&path, &database.file_path).and_then(|files| {
+                page_cache.clear();
+                *table = Table::read_from_disk(file)?;
+                Ok(files.len())
+            });
+            lock_manager().release_table_lock(&name);
+            match restored {
+                Ok(count) => {
+                    result_rows.push(vec![format!("Restored {} file(s) from {}", count, path)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::RestoreFromWal { lsn } => {
+            let name = table.name_str().to_string();
+            lock_manager().acquire_table_lock(&name, TableLock::Exclusive);
+            page_cache.clear();
+            let restored = restore_to_lsn(table, file, lsn);
+            lock_manager().release_table_lock(&name);
+            match restored {
+                Ok(replayed) => {
+                    result_rows.push(vec![format!(
+                        "Restored {} to LSN {}, replayed {} WAL record(s)",
+                        name, lsn, replayed
+                    )]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::ShowTableStats(name) if !is_open_table(table, &name) => {
+            result_rows.push(vec![format!("Table {} does not exist", name)]);
+        }
+        Query::ShowTableStats(_) => match table.stats(file) {
+            Ok(stats) => {
+                result_rows.push(
+                    [
+                        stats.row_count,
+                        stats.total_data_bytes,
+                        stats.created_at,
+                        stats.last_modified,
+                        stats.last_analyzed,
+                    ]
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect(),
+                );
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::ShowCreateTable(name) => {
+            let statement = match is_open_table(table, &name) {
+                true => table.create_statement().map_err(String::from),
+                false => open_table(&name).and_then(|(table, _)| Ok(table.create_statement()?)),
+            };
+            match statement {
+                Ok(statement) => {
+                    result_rows.push(vec![statement]);
+                    status = 1;
+                }
+                Err(e) => result_rows.push(vec![e]),
+            }
+        }
+        Query::ImportSchema(path) => match import_schema(
+            table,
+            file,
+            page_cache,
+            database,
+            transaction,
+            config,
+            input,
+            &path,
+        ) {
+            Ok(count) => {
+                result_rows.push(vec![format!("Imported {} object(s) from {}", count, path)]);
+                status = 1;
+            }
+            Err((count, e)) => {
+                result_rows.push(vec![format!(
+                    "{}, {} object(s) imported before the failure",
+                    e, count
+                )]);
+            }
+        },
+        Query::Checksum(name) => {
+            let checksum = match is_open_table(table, &name) {
+                true => table.checksum(file).map_err(|e| format!("{:?}", e)),
+                false => open_table(&name).and_then(|(table, file)| {
+                    table.checksum(&file).map_err(|e| format!("{:?}", e))
+                }),
+            };
+            match checksum {
+                Ok(checksum) => {
+                    result_rows.push(vec![format!("{:08x}", checksum)]);
+                    status = 1;
+                }
+                Err(e) => result_rows.push(vec![e]),
+            }
+        }
+        Query::DebugDump(name) if !table_exists(&name) => {
+            result_rows.push(vec![format!("Table {} does not exist", name)]);
+        }
+        Query::DebugDump(name) => match dump_table(&name) {
+            Ok(rows) => {
+                result_rows.extend(rows);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::Analyze(name) if !is_open_table(table, &name) => {
+            result_rows.push(vec![format!("Table {} does not exist", name)]);
+        }
+        Query::Analyze(name) => match table.analyze(file) {
+            Ok(_) => {
+                result_rows.push(vec![format!("Analyzed table {}", name)]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::AnalyzeColumn {
+            table: table_name, ..
+        } if !is_open_table(table, &table_name) => {
+            result_rows.push(vec![format!("Table {} does not exist", table_name)]);
+        }
+        Query::AnalyzeColumn { column, .. } => match table.analyze_column(file, &column) {
+            Ok(analysis) => {
+                let suggestion = match analysis.suggest_dictionary {
+                    true => "Suggest DICTIONARY encoding",
+                    false => "No suggestion",
+                };
+                result_rows.push(vec![
+                    column,
+                    analysis.stats.distinct_count.to_string(),
+                    analysis.row_count.to_string(),
+                    suggestion.to_string(),
+                ]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::CreateIndex {
+            table: table_name, ..
+        } if !is_open_table(table, &table_name) => {
+            result_rows.push(vec![format!("Table {} does not exist", table_name)]);
+        }
+        Query::CreateIndex {
+            name,
+            table: table_name,
+            columns,
+            kind,
+            predicate,
+        } => {
+            let path = kind.file(&table_name, &columns.join(","));
+            let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+            let created = match (table_exists(&path), kind) {
+                (true, _) => Err(DurabilityError::DbError(format!(
+                    "Column {} is already indexed",
+                    columns.join(", ")
+                ))),
+                (false, IndexKind::Hash) if columns.len() > 1 => Err(DurabilityError::DbError(
+                    "A hash index covers a single column".to_string(),
+                )),
+                (false, IndexKind::Hash) if predicate.is_some() => Err(DurabilityError::DbError(
+                    "A hash index can not be partial".to_string(),
+                )),
+                (false, IndexKind::Hash) => HashIndex::build(&name, table, file, columns[0])
+                    .and_then(|index| index.write(&path)),
+                (false, IndexKind::BTree) => {
+                    BTreeIndex::build(&name, table, file, &columns, predicate.as_deref())
+                        .and_then(|index| index.write(&path))
+                }
+            };
+            // The optimizer needs fresh stats to consider the index.
+            let created = created.and_then(|()| table.analyze(file));
+            match created {
+                Ok(_) => {
+                    result_rows.push(vec![format!("Created index {}", name)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::RebuildIndex {
+            table: table_name, ..
+        }
+        | Query::RebuildAllIndexes(table_name)
+            if !is_open_table(table, &table_name) =>
+        {
+            result_rows.push(vec![format!("Table {} does not exist", table_name)]);
+        }
+        Query::RebuildIndex {
+            index_name,
+            table: table_name,
+        } => match find_index(&table_name, &index_name) {
+            Ok(Some((path, kind))) => {
+                lock_manager().acquire_table_lock(&table_name, TableLock::Exclusive);
+                let rebuilt = rebuild_index(table, file, &path, kind);
+                lock_manager().release_table_lock(&table_name);
+                match rebuilt {
+                    Ok((name, entries)) => {
+                        result_rows.push(vec![format!(
+                            "Rebuilt index {} with {} entries",
+                            name, entries
+                        )]);
+                        status = 1;
+                    }
+                    Err(e) => {
+                        result_rows.push(vec![format!("{:?}", e)]);
+                    }
+                }
+            }
+            Ok(None) => {
+                result_rows.push(vec![format!("Index {} does not exist", index_name)]);
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::RebuildAllIndexes(table_name) => {
+            lock_manager().acquire_table_lock(&table_name, TableLock::Exclusive);
+            let rebuilt = rebuild_indexes(table, file);
+            lock_manager().release_table_lock(&table_name);
+            match rebuilt {
+                Ok(rebuilt) => {
+                    for (name, entries) in rebuilt {
+                        result_rows.push(vec![format!(
+                            "Rebuilt index {} with {} entries",
+                            name, entries
+                        )]);
+                    }
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::ReindexAll => {
+            let reindexed = indexed_tables(&database.file_path)
+                .map_err(|e| format!("{:?}", e))
+                .and_then(|tables| reindex_tables(table, file, &tables));
+            match reindexed {
+                Ok(rows) => {
+                    result_rows.extend(rows);
+                    status = 1;
+                }
+                Err(e) => result_rows.push(vec![e]),
+            }
+        }
+        Query::ExplainIndexes(name) => match explain_indexes(table, &name) {
+            Ok(rows) => {
+                result_rows.extend(rows);
+                status = 1;
+            }
+            Err(e) => result_rows.push(vec![e]),
+        },
+        Query::ShowDiskUsage => match show_disk_usage(table, file, database) {
+            Ok(rows) => {
+                result_rows.extend(rows);
+                status = 1;
+            }
+            Err(e) => result_rows.push(vec![e]),
+        },
+        Query::CompactDatabase => {
+            match compact_database(table, file, page_cache, database, &mut result_rows) {
+                Ok(()) => status = 1,
+                Err(e) => result_rows.push(vec![e]),
+            }
+        }
+        Query::Reindex(name) => match reindex_tables(table, file, &[name]) {
+            Ok(rows) => {
+                result_rows.extend(rows);
+                status = 1;
+            }
+            Err(e) => result_rows.push(vec![e]),
+        },
+        Query::CompressColumn {
+            table: table_name, ..
+        } if !is_open_table(table, &table_name) => {
+            result_rows.push(vec![format!("Table {} does not exist", table_name)]);
+        }
+        Query::CompressColumn { column, .. } => match table.set_delta_encoded(file, &column) {
+            Ok(()) => {
+                result_rows.push(vec![format!("Delta encoded column {}", column)]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::ExplainAnalyze(query) => match *query {
+            Query::Select(QuerySource::Table(name), scope, filter)
+                if is_open_table(table, &name) =>
+            {
+                let analyzed = QueryOptimizer::new(table)
+                    .map(|optimizer| optimizer.plan_select(&filter))
+                    .map_err(|e| format!("{:?}", e))
+                    .and_then(|plan| {
+                        explain_analyze(
+                            table,
+                            file,
+                            page_cache,
+                            config.page_cache_size,
+                            &plan,
+                            (scope, filter),
+                        )
+                        .map(|(rows, stats)| (plan, rows, stats))
+                    });
+                match analyzed {
+                    Ok((plan, rows, stats)) => {
+                        result_rows = rows;
+                        result_rows.push(vec!["Plan".to_string(), plan.describe()]);
+                        result_rows.extend(stats.rows());
+                        status = 1;
+                    }
+                    Err(e) => {
+                        result_rows.push(vec![e]);
+                    }
+                }
+            }
+            _ => {
+                result_rows.push(vec![
+                    "EXPLAIN ANALYZE only supports SELECT on the open table".to_string(),
+                ]);
+            }
+        },
+        Query::Explain(ref explained) | Query::ExplainJson(ref explained) => {
+            match QueryOptimizer::new(table).map(|optimizer| optimizer.plan(explained)) {
+                Ok(Some(plan)) => {
+                    result_rows.push(vec![match query {
+                        Query::ExplainJson(_) => plan.to_json(),
+                        _ => plan.describe(),
+                    }]);
+                    status = 1;
+                }
+                Ok(None) => {
+                    result_rows.push(vec!["EXPLAIN only supports SELECT".to_string()]);
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::Set { name, value } => match config.set(&name, &value) {
+            Ok(()) => {
+                evict_pages(page_cache, config.page_cache_size);
+                result_rows.push(vec![format!(
+                    "Set {} to {}",
+                    name,
+                    config.get(&name).unwrap()
+                )]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![e]);
+            }
+        },
+        Query::CopyBinary(name) if !is_open_table(table, &name) || transaction.is_some() => {
+            match skip_binary_rows(input) {
+                Ok(()) if transaction.is_some() => {
+                    result_rows.push(vec!["COPY is not allowed in a transaction".to_string()]);
+                }
+                Ok(()) => {
+                    result_rows.push(vec![format!("Table {} does not exist", name)]);
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", DurabilityError::IoError(e))]);
+                }
+            }
+        }
+        Query::CopyBinary(_) => {
+            let copied = copy_binary(table, file, input).and_then(|copied| {
+                auto_analyze(table, file, config, copied)?;
+                Ok(copied)
+            });
+            match copied {
+                Ok(copied) => {
+                    result_rows.push(vec![format!(
+                        "Copied {} row(s) in {} us",
+                        copied,
+                        start_time.elapsed().as_micros()
+                    )]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::CopyBinaryTo { table: name, .. } | Query::CopyBinaryFrom { table: name, .. }
+            if !is_open_table(table, &name) =>
+        {
+            result_rows.push(vec![format!("Table {} does not exist", name)]);
+        }
+        Query::CopyTable {
+            source,
+            destination,
+            filter,
+        } => {
+            let predicates = match filter {
+                Filter::Where(predicates) => predicates,
+                _ => vec![],
+            };
+            let copied = match is_open_table(table, &source) {
+                true => copy_table(table, file, &destination, &predicates).map_err(String::from),
+                false => open_table(&source).and_then(|(source, file)| {
+                    Ok(copy_table(&source, &file, &destination, &predicates)?)
+                }),
+            };
+            match copied {
+                Ok(copied) => {
+                    result_rows.push(vec![format!(
+                        "Copied {} row(s) from {} to {}",
+                        copied, source, destination
+                    )]);
+                    status = 1;
+                }
+                Err(e) => result_rows.push(vec![e]),
+            }
+        }
+        Query::Merge { .. } if transaction.is_some() => {
+            result_rows.push(vec!["MERGE is not allowed in a transaction".to_string()]);
+        }
+        Query::Merge {
+            target,
+            source,
+            join_condition,
+            matched_action,
+            not_matched_action,
+        } => {
+            let open = is_open_table(table, &target);
+            let merge =
+                |target: &mut Table, file: &mut File, source: &Table, source_file: &File| {
+                    let source_rows = select_rows(source, source_file, &[])?;
+                    merge_rows(
+                        target,
+                        file,
+                        source,
+                        &source_rows,
+                        &join_condition,
+                        matched_action.as_deref(),
+                        not_matched_action.as_ref(),
+                    )
+                };
+            let merged = match (open, is_open_table(table, &source)) {
+                (true, _) => open_table(&source).and_then(|(source, source_file)| {
+                    let merged = merge(table, file, &source, &source_file)?;
+                    auto_analyze(table, file, config, merged.inserted.len() as u64)?;
+                    Ok(merged)
+                }),
+                (false, true) => open_table(&target).and_then(|(mut target, mut target_file)| {
+                    Ok(merge(&mut target, &mut target_file, table, file)?)
+                }),
+                (false, false) => open_table(&target).and_then(|(mut target, mut target_file)| {
+                    let (source, source_file) = open_table(&source)?;
+                    Ok(merge(&mut target, &mut target_file, &source, &source_file)?)
+                }),
+            };
+            match merged {
+                Ok(merged) => {
+                    result_rows.extend(merge_messages(merged.updated, merged.inserted.len()));
+                    if open {
+                        changed_rows = Some((TriggerEvent::Insert, merged.inserted));
+                    }
+                    status = 1;
+                }
+                Err(e) => result_rows.push(vec![e]),
+            }
+        }
+        Query::InsertSelect { .. } if transaction.is_some() => {
+            result_rows.push(vec![
+                "INSERT ... SELECT is not allowed in a transaction".to_string()
+            ]);
+        }
+        Query::InsertSelect {
+            dest_table,
+            source_query,
+        } => {
+            let open = is_open_table(table, &dest_table);
+            let inserted = match open {
+                true => insert_select_rows(table, file, table, *source_query).and_then(|rows| {
+                    table.add_rows(&rows, file)?;
+                    auto_analyze(table, file, config, rows.len() as u64)?;
+                    Ok(rows)
+                }),
+                false => open_table(&dest_table).and_then(|(mut destination, mut dest_file)| {
+                    let rows = insert_select_rows(table, file, &destination, *source_query)?;
+                    destination.add_rows(&rows, &mut dest_file)?;
+                    Ok(rows)
+                }),
+            };
+            match inserted {
+                Ok(rows) => {
+                    result_rows.push(vec![format!(
+                        "Inserted {} row(s) into {}",
+                        rows.len(),
+                        dest_table
+                    )]);
+                    if open {
+                        changed_rows = Some((TriggerEvent::Insert, rows));
+                    }
+                    status = 1;
+                }
+                Err(e) => result_rows.push(vec![e]),
+            }
+        }
+        Query::CopyBinaryFrom { .. } if transaction.is_some() => {
+            result_rows.push(vec!["COPY is not allowed in a transaction".to_string()]);
+        }
+        Query::CopyBinaryTo { table: name, path } => {
+            // Writers wait so the file holds the table at one point in time.
+            lock_manager().acquire_table_lock(&name, TableLock::Shared);
+            let exported = export_binary(table, file, &path);
+            lock_manager().release_table_lock(&name);
+            match exported {
+                Ok(exported) => {
+                    result_rows.push(vec![format!("Copied {} row(s) to {}", exported, path)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::CopyBinaryFrom { path, .. } => {
+            let copied = import_binary(table, file, &path).and_then(|copied| {
+                auto_analyze(table, file, config, copied)?;
+                Ok(copied)
+            });
+            match copied {
+                Ok(copied) => {
+                    result_rows.push(vec![format!(
+                        "Copied {} row(s) in {} us",
+                        copied,
+                        start_time.elapsed().as_micros()
+                    )]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::LockTable { table, mode } => match lock_table(&table, mode, config) {
+            Ok(message) => {
+                result_rows.push(vec![message]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![e]);
+            }
+        },
+        Query::UnlockTable(table) => match unlock_table(&table, config) {
+            Ok(message) => {
+                result_rows.push(vec![message]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![e]);
+            }
+        },
+        Query::ShowProcesslist | Query::Kill(_) => {
+            result_rows.push(vec!["Only available in server mode".to_string()]);
+        }
+        Query::FlushQueryLog | Query::ShowQueryLog(_) | Query::ClearQueryLog => {
+            result_rows.push(vec!["Only available as a statement of its own".to_string()]);
+        }
+        Query::Refresh => match table.refresh(file) {
+            Ok(()) => {
+                // Pages cached before may have been rewritten.
+                page_cache.clear();
+                result_rows.push(vec![format!("Refreshed table {}", table.name_str())]);
+                status = 1;
+            }
+            Err(e) => {
+                result_rows.push(vec![format!("{:?}", e)]);
+            }
+        },
+        Query::Checkpoint => {
+            let name = table.name_str();
+            lock_manager().acquire_table_lock(name, TableLock::Exclusive);
+            let checkpointed = checkpoint(name, file);
+            lock_manager().release_table_lock(name);
+            match checkpointed.and_then(|(records, lsn)| {
+                write_checkpoint_lsn(database, lsn)?;
+                Ok(records)
+            }) {
+                Ok(records) => {
+                    result_rows.push(vec![format!("Checkpointed {} WAL record(s)", records)]);
+                    status = 1;
+                }
+                Err(e) => {
+                    result_rows.push(vec![format!("{:?}", e)]);
+                }
+            }
+        }
+        Query::ShowConfig => {
+            for (name, value) in config.file_settings() {
+                result_rows.push(vec![name.to_string(), value]);
+            }
+            status = 1;
+        }
+        Query::Show(None) => {
+            for (name, value) in config.all() {
+                result_rows.push(vec![name.to_string(), value]);
+            }
+            status = 1;
+        }
+        Query::Show(Some(name)) => match config.get(&name) {
+            Some(value) => {
+                result_rows.push(vec![value]);
+                status = 1;
+            }
+            None => {
+                result_rows.push(vec![format!("Unknown variable {}", name)]);
+            }
+        },
+    }
+
+    if let Some(changed_rows) = changed_rows {
+        let fired = fire_triggers(
+            table,
+            file,
+            page_cache,
+            database,
+            transaction,
+            config,
+            input,
+            changed_rows,
+        );
+        if let Err(e) = fired {
+            result_rows.push(vec![e]);
+            status = 0;
+        }
+    }
+
+    let name = table.name_str();
+    if config.wal_autocheckpoint > 0 && wal_size(name) > config.wal_autocheckpoint {
+        checkpoint_in_background(name.to_string());
+    }
+    result_set(result_rows, start_time, status)
+}
+
+/// Reports how many rows an `INSERT OR REPLACE` inserted and replaced.
+fn upsert_messages(inserted: usize, replaced: usize) -> Vec<Vec<String>> {
+    let mut messages = vec![];
+    if inserted > 0 || replaced == 0 {
+        messages.push(vec![format!("{} inserted", row_count_label(inserted))]);
+    }
+    if replaced > 0 {
+        messages.push(vec![format!("{} replaced", row_count_label(replaced))]);
+    }
+    messages
+}
+
+/// Reports how many rows a `MERGE` updated and inserted.
+fn merge_messages(updated: u64, inserted: usize) -> Vec<Vec<String>> {
+    vec![
+        vec![format!("{} updated", row_count_label(updated as usize))],
+        vec![format!("{} inserted", row_count_label(inserted))],
+    ]
+}
+
+fn row_count_label(count: usize) -> String {
+    match count {
+        1 => "1 row".to_string(),
+        count => format!("{} rows", count),
+    }
+}
+
+fn result_set(rows: Vec<Vec<String>>, start_time: std::time::Instant, status: u8) -> ResultSet {
+    ResultSet {
+        rows,
+        execution_time: start_time.elapsed().as_micros(),
+        execution_status: status,
+    }
+}
+/// The table the REPL and the server open.
+const ACCOUNT_TABLE: &str = "account_tbl";
+const DATABASE_NAME: &str = "city_db";
+
+/// The configuration file of the database, `{name}.conf` next to its tables.
+fn config_file(database: &str) -> String {
+    format!("{}.conf", database)
+}
+
+/// Loads the configuration file at `path` over the defaults, which are used
+/// as is without one, and applies the settings that hold for the whole
+/// process.
+fn load_config(path: &str) -> Result<Config, DurabilityError> {
+    let config = match Config::load_from_file(path) {
+        Err(DurabilityError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            Config::default()
+        }
+        config => config?,
+    };
+    set_wal_enabled(config.wal_enabled);
+    set_max_page_size(config.default_page_size);
+    Ok(config)
+}
+
+/// Creates the account table at `name` unless it already exists.
+fn prep_db(name: &str) -> Result<(), String> {
+    if !table_exists(name) {
+        create_table(
+            TableBuilder::new(name)
+                .column("id", ColumnType::Int, 11)
+                .column("account_id", ColumnType::Int, 11),
+        )?;
+    }
+    Ok(())
+}
+
+fn prep_table(file: &mut File) -> Table {
+    Table::read_from_disk(file).unwrap()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn execute_query(
+    query: &String,
+    table: &mut Table,
+    file: &mut File,
+    page_cache: &mut HashMap<String, Page>,
+    database: &DatabaseConfig,
+    transaction: &mut Option<Transaction>,
+    slow_query_log: &SlowQueryLog,
+    query_log: &QueryLogger,
+    config: &mut Config,
+) {
+    let query_text = &redact_passwords(query);
+    let query: Query = query.into();
+    let started = std::time::Instant::now();
+    let result_set = match query_log.execute(&query) {
+        Some(Ok(rows)) => result_set(rows, started, 1),
+        Some(Err(e)) => result_set(vec![vec![e]], started, 0),
+        None => get_result_set(
+            table,
+            file,
+            query,
+            page_cache,
+            database,
+            transaction,
+            config,
+            &mut std::io::stdin(),
+        ),
+    };
+    if let Err(e) = query_log.record(
+        query_text,
+        result_set.execution_time,
+        result_set.execution_status == 1,
+    ) {
+        println!("Failed to write query log: {}", e);
+    }
+    if let Err(e) = slow_query_log.record(
+        query_text,
+        result_set.execution_time,
+        config.slow_query_threshold,
+    ) {
+        println!("Failed to write slow query log: {}", e);
+    }
+    let result_set_size = result_set.rows.len();
+    for row in &result_set {
+        match config.output_format {
+            OutputFormat::Text => println!("{:?}", row),
+            OutputFormat::Json => println!("{}", json_array(row)),
+        }
+    }
+
+    println!(
+        "Execution time: {:?}, Execution status: {:?}, Row(s) {:?}",
+        result_set.execution_time, result_set.execution_status, result_set_size
+    );
+}
+
+/// Runs the database the way the command line asks: a server with
+/// `--server`, otherwise a REPL reading stdin, or the named pipe given with
+/// `--pipe`.
+pub fn run() {
+    let mut config = load_config(&config_file(DATABASE_NAME)).unwrap();
+    prep_db(ACCOUNT_TABLE).unwrap();
+    let mut file = writeable_table_file(ACCOUNT_TABLE.to_string()).unwrap();
+    let mut table = prep_table(&mut file);
+    let mut page_cache: HashMap<String, Page> = HashMap::new();
+    let args: Vec<String> = std::env::args().collect();
+    let auth_file = args
+        .iter()
+        .position(|arg| arg == "--auth-file")
+        .map(|i| args.get(i + 1).expect("Missing auth file").clone());
+    let database = DatabaseConfig {
+        name: DATABASE_NAME.to_string(),
+        file_path: ".".to_string(),
+        auth_file,
+    };
+
+    if args.iter().any(|arg| arg == "--server") {
+        let socket = args
+            .iter()
+            .position(|arg| arg == "--socket")
+            .map(|i| args.get(i + 1).expect("Missing socket path"));
+        if let Some(socket) = socket {
+            server::serve_unix(socket, table, database, config).unwrap();
+            return;
+        }
+
+        let port = match args.iter().position(|arg| arg == "--port") {
+            Some(i) => args
+                .get(i + 1)
+                .and_then(|port| port.parse().ok())
+                .expect("Invalid port"),
+            None => 5432,
+        };
+        server::serve_tcp(port, table, database, config).unwrap();
+        return;
+    }
+
+    let mut transaction = None;
+    let slow_query_log = SlowQueryLog::new(slow_query_log_file(&database));
+    let query_log = QueryLogger::new(query_log_file(&database));
+    let execute = |query: &String| {
+        println!("Executing {}", query);
+        execute_query(
+            query,
+            &mut table,
+            &mut file,
+            &mut page_cache,
+            &database,
+            &mut transaction,
+            &slow_query_log,
+            &query_log,
+            &mut config,
+        );
+    };
+    let pipe = args
+        .iter()
+        .position(|arg| arg == "--pipe")
+        .map(|i| args.get(i + 1).expect("Missing pipe path"));
+    match pipe {
+        Some(path) => {
+            let pipe = std::fs::OpenOptions::new()
+                .read(true)
+                .open(path)
+                .expect("Failed to open pipe");
+            repl::run_repl(std::io::BufReader::new(pipe), repl::AtEnd::Wait, execute);
+        }
+        None => repl::run_repl(repl::stdin(), repl::AtEnd::Exit, execute),
+    }
+    if let Err(e) = drop_temp_tables(config.connection_id) {
+        println!("Failed to drop temporary tables: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_prep_db_twice() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join(ACCOUNT_TABLE);
+        let name = name.to_str().unwrap();
+
+        prep_db(name).unwrap();
+        let created = std::fs::read(name).unwrap();
+        prep_db(name).unwrap();
+        assert_eq!(std::fs::read(name).unwrap(), created);
+    }
+
+    #[test]
+    fn test_stream_aggregates_with_small_page_cache() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("orders").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 11)
+                .column("amount", ColumnType::Int, 11),
+        )
+        .unwrap();
+        let mut file = writeable_table_file(name).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let rows: Vec<Row> = (1..=20_000)
+            .map(|i| Row {
+                data: vec![
+                    i.to_string().into_bytes(),
+                    (i % 100).to_string().into_bytes(),
+                ],
+            })
+            .collect();
+        for batch in rows.chunks(1000) {
+            table.add_rows(batch, &mut file).unwrap();
+        }
+
+        // The cache holds a handful of the pages of the table.
+        let page_cache_size = 4;
+        assert!(table.page_count() > 1000);
+        let mut page_cache = HashMap::new();
+        let Query::Select(query_source, scope, filter) = Query::from(
+            "SELECT COUNT(*), SUM(id), AVG(amount), MIN(amount), MAX(id) FROM orders \
+             WHERE amount > 49",
+        ) else {
+            panic!("Invalid query");
+        };
+        let rows = select(
+            &table,
+            &file,
+            &mut page_cache,
+            page_cache_size,
+            (query_source, scope, filter),
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(rows, [["10000", "100245000", "74.5", "50", "19999"]]);
+        assert!(page_cache.len() <= page_cache_size);
+    }
+
+    #[test]
+    fn test_show_disk_usage() {
+        let tmp_dir = tempdir().unwrap();
+        let directory = tmp_dir.path().to_str().unwrap().to_string();
+        let mut tables = vec![];
+        for (name, row_count) in [("orders", 2000), ("users", 10)] {
+            let name = format!("{}/{}", directory, name);
+            create_table(
+                TableBuilder::new(&name)
+                    .column("id", ColumnType::Int, 11)
+                    .column("name", ColumnType::Varchar, 20),
+            )
+            .unwrap();
+            let mut file = writeable_table_file(name.clone()).unwrap();
+            let mut table = Table::read_from_disk(&mut file).unwrap();
+            let rows: Vec<Row> = (0..row_count)
+                .map(|i| Row {
+                    data: vec![i.to_string().into_bytes(), b"Ada".to_vec()],
+                })
+                .collect();
+            table.add_rows(&rows, &mut file).unwrap();
+            tables.push((name, table, file));
+        }
+        let database = DatabaseConfig {
+            name: "db".to_string(),
+            file_path: directory,
+            auth_file: None,
+        };
+        let (_, table, file) = &tables[0];
+
+        let rows = show_disk_usage(table, file, &database).unwrap();
+        let size = |path: &str| std::fs::metadata(path).map_or(0, |m| m.len());
+        let mut total = 0;
+        for (row, (name, _, _)) in rows.iter().zip(tables.iter()) {
+            let wal = size(&format!("{}.wal", name)) + size(&format!("{}.wal.archive", name));
+            let expected = size(name) + wal;
+            assert_eq!(row[0], *name);
+            assert_eq!(row[1], size(name).to_string());
+            assert_eq!(row[2..4], ["0", "0"]);
+            assert_eq!(row[4], wal.to_string());
+            assert_eq!(row[5], expected.to_string());
+            total += expected;
+        }
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[2][0], "TOTAL");
+        assert_eq!(rows[2][5], total.to_string());
+        assert!(size(&tables[0].0) > size(&tables[1].0));
+    }
+
+    #[test]
+    fn test_index_scan_waits_for_row_writer() {
+        let tmp_dir = tempdir().unwrap();
+        let name = tmp_dir.path().join("users").to_str().unwrap().to_string();
+        create_table(TableBuilder::new(&name).column("id", ColumnType::Int, 11)).unwrap();
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let rows: Vec<Row> = (0..10)
+            .map(|i| Row {
+                data: vec![i.to_string().into_bytes()],
+            })
+            .collect();
+        table.add_rows(&rows, &mut file).unwrap();
+        let plan = QueryPlan::IndexScan {
+            table: name.clone(),
+            kind: IndexKind::BTree,
+            index: "idx_id".to_string(),
+            keys: vec![],
+            rows: vec![3],
+            unindexed_from: table.row_count,
+            cost: optimizer::Cost {
+                estimated_rows: 1,
+                pages: 1,
+            },
+        };
+
+        let locks = lock_manager();
+        locks.acquire_write_lock(&name, 3);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let rows = plan_rows(&table, &file, &mut HashMap::new(), 16, &plan);
+                sender.send(rows.len()).unwrap();
+            });
+            // The reader waits for the writer of row 3.
+            let waited = receiver.recv_timeout(std::time::Duration::from_millis(200));
+            assert!(waited.is_err());
+            locks.release_lock(&name, 3);
+            assert_eq!(receiver.recv().unwrap(), 1);
+        });
+        // Every read lock is released after.
+        locks.acquire_write_lock(&name, 3);
+        locks.release_lock(&name, 3);
+    }
+
+    #[test]
+    fn test_result_set_iterators() {
+        let rows = vec![
+            vec!["1".to_string(), "Oslo".to_string()],
+            vec!["2".to_string(), "Bergen".to_string()],
+            vec!["3".to_string(), "Oslo".to_string()],
+        ];
+        let result_set = result_set(rows, std::time::Instant::now(), 1);
+
+        let ids: Vec<&str> = (&result_set)
+            .into_iter()
+            .filter(|row| row[1] == "Oslo")
+            .map(|row| row[0].as_str())
+            .collect();
+        assert_eq!(ids, ["1", "3"]);
+        let mut count = 0;
+        for row in &result_set {
+            assert_eq!(row.len(), 2);
+            count += 1;
+        }
+        assert_eq!(count, 3);
+
+        let cities: Vec<String> = result_set
+            .into_iter()
+            .map(|mut row| row.remove(1))
+            .filter(|city| city != "Oslo")
+            .collect();
+        assert_eq!(cities, ["Bergen"]);
+    }
+}
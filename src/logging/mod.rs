@@ -0,0 +1,277 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    durability::{
+        table::{current_timestamp, format_timestamp},
+        DatabaseConfig,
+    },
+    query::Query,
+};
+
+/// The buffered lines are written out once there are this many of them.
+const FLUSH_EVERY_WRITES: usize = 100;
+/// Or once this long has passed since the last flush.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// The lines `SHOW QUERY LOG` returns without `LAST n`.
+pub const DEFAULT_LAST_LINES: usize = 10;
+
+/// The query log of the database, `{name}.querylog` next to its tables.
+pub fn query_log_file(database: &DatabaseConfig) -> String {
+    format!("{}/{}.querylog", database.file_path, database.name)
+}
+
+/// `query` with the quoted literal after every `PASSWORD` keyword replaced
+/// by `'***'`, so the logs never keep the password of a
+/// `CREATE USER ... WITH PASSWORD '...'`.
+pub fn redact_passwords(query: &str) -> String {
+    const KEYWORD: &str = "PASSWORD";
+    // Upper casing ASCII keeps every byte offset.
+    let upper = query.to_ascii_uppercase();
+    let bytes = query.as_bytes();
+    let mut redacted = String::new();
+    let mut copied = 0;
+    let mut searched = 0;
+    while let Some(found) = upper[searched..].find(KEYWORD) {
+        let keyword_end = searched + found + KEYWORD.len();
+        let literal = query.len() - query[keyword_end..].trim_start().len();
+        searched = keyword_end;
+        if bytes.get(literal) != Some(&b'\'') {
+            continue;
+        }
+        // A doubled quote is a quote inside the literal.
+        let mut end = literal + 1;
+        loop {
+            match bytes.get(end) {
+                None => break,
+                Some(b'\'') if bytes.get(end + 1) == Some(&b'\'') => end += 2,
+                Some(b'\'') => {
+                    end += 1;
+                    break;
+                }
+                Some(_) => end += 1,
+            }
+        }
+        redacted.push_str(&query[copied..literal]);
+        redacted.push_str("'***'");
+        copied = end;
+        searched = end;
+    }
+    redacted.push_str(&query[copied..]);
+    redacted
+}
+
+struct LogWriter {
+    writer: BufWriter<File>,
+    pending: usize,
+    last_flush: Instant,
+}
+
+/// Appends every executed query to a log file, one
+/// `timestamp\tduration_us\tstatus\tquery_text` line each with the status
+/// `OK` or `ERR`. Lines are buffered and written out every
+/// `FLUSH_EVERY_WRITES` queries or `FLUSH_INTERVAL`, whichever comes first,
+/// checked as queries are logged. Shared by every connection of the server,
+/// the file is opened on the first query.
+pub struct QueryLogger {
+    path: String,
+    writer: Mutex<Option<LogWriter>>,
+}
+
+impl QueryLogger {
+    pub fn new(path: String) -> Self {
+        QueryLogger {
+            path,
+            writer: Mutex::new(None),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<LogWriter>> {
+        self.writer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn record(&self, query: &str, execution_time: u128, ok: bool) -> std::io::Result<()> {
+        let line = format!(
+            "{}\t{}\t{}\t{}\n",
+            format_timestamp(current_timestamp()),
+            execution_time,
+            if ok { "OK" } else { "ERR" },
+            query.replace(['\n', '\r', '\t'], " ")
+        );
+        let mut writer = self.lock();
+        if writer.is_none() {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            *writer = Some(LogWriter {
+                writer: BufWriter::new(file),
+                pending: 0,
+                last_flush: Instant::now(),
+            });
+        }
+        let log = writer.as_mut().unwrap();
+        log.writer.write_all(line.as_bytes())?;
+        log.pending += 1;
+        if log.pending >= FLUSH_EVERY_WRITES || log.last_flush.elapsed() >= FLUSH_INTERVAL {
+            flush(log)?;
+        }
+        Ok(())
+    }
+
+    /// Writes out the buffered lines, `FLUSH QUERY LOG`.
+    pub fn flush(&self) -> std::io::Result<()> {
+        match self.lock().as_mut() {
+            Some(log) => flush(log),
+            None => Ok(()),
+        }
+    }
+
+    /// The last `count` lines of the log, buffered ones included, oldest
+    /// first.
+    pub fn last(&self, count: usize) -> std::io::Result<Vec<String>> {
+        self.flush()?;
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let lines: Vec<&str> = contents.lines().collect();
+        let start = lines.len().saturating_sub(count);
+        Ok(lines[start..].iter().map(|line| line.to_string()).collect())
+    }
+
+    /// Empties the log, dropping the buffered lines, `CLEAR QUERY LOG`.
+    pub fn clear(&self) -> std::io::Result<()> {
+        let mut writer = self.lock();
+        // Dropping the writer would write out its buffer first.
+        if let Some(log) = writer.take() {
+            let _ = log.writer.into_parts();
+        }
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&self.path)
+        {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs the queries managing the log, `None` for every other query.
+    pub fn execute(&self, query: &Query) -> Option<Result<Vec<Vec<String>>, String>> {
+        let rows = match query {
+            Query::FlushQueryLog => self
+                .flush()
+                .map(|()| vec![vec!["Flushed query log".to_string()]]),
+            Query::ShowQueryLog(count) => self.last(*count).map(|lines| {
+                lines
+                    .iter()
+                    .map(|line| line.splitn(4, '\t').map(str::to_string).collect())
+                    .collect()
+            }),
+            Query::ClearQueryLog => self
+                .clear()
+                .map(|()| vec![vec!["Cleared query log".to_string()]]),
+            _ => return None,
+        };
+        Some(rows.map_err(|e| format!("Failed to access query log: {}", e)))
+    }
+}
+
+fn flush(log: &mut LogWriter) -> std::io::Result<()> {
+    log.writer.flush()?;
+    log.pending = 0;
+    log.last_flush = Instant::now();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::table::parse_timestamp;
+
+    fn fields(line: &str) -> Vec<&str> {
+        line.splitn(4, '\t').collect()
+    }
+
+    #[test]
+    fn test_record_every_query() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("city_db.querylog");
+        let log = QueryLogger::new(path.to_str().unwrap().to_string());
+
+        let before = current_timestamp();
+        log.record("SELECT *\nFROM users", 11, true).unwrap();
+        log.record("SELECT\tmissing FROM users", 250, false)
+            .unwrap();
+        // Buffered until flushed.
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        log.flush().unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<Vec<&str>> = contents.lines().map(fields).collect();
+        assert_eq!(lines.len(), 2);
+        let logged_at = parse_timestamp(lines[0][0]).unwrap();
+        assert!(logged_at >= before && logged_at <= current_timestamp());
+        assert_eq!(lines[0][1..], ["11", "OK", "SELECT * FROM users"]);
+        assert_eq!(lines[1][1..], ["250", "ERR", "SELECT missing FROM users"]);
+
+        for i in 0..FLUSH_EVERY_WRITES {
+            log.record(&format!("SELECT {}", i), 1, true).unwrap();
+        }
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 2 + FLUSH_EVERY_WRITES);
+    }
+
+    #[test]
+    fn test_redact_passwords() {
+        assert_eq!(
+            redact_passwords("CREATE USER bob WITH PASSWORD 'hunter2'"),
+            "CREATE USER bob WITH PASSWORD '***'"
+        );
+        assert_eq!(
+            redact_passwords("create user bob with password  'it''s; secret' ;"),
+            "create user bob with password  '***' ;"
+        );
+        assert_eq!(
+            redact_passwords("SELECT password FROM users WHERE name = 'bob'"),
+            "SELECT password FROM users WHERE name = 'bob'"
+        );
+        assert_eq!(
+            redact_passwords("ALTER USER bob PASSWORD 'x"),
+            "ALTER USER bob PASSWORD '***'"
+        );
+    }
+
+    #[test]
+    fn test_last_and_clear() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("city_db.querylog");
+        let log = QueryLogger::new(path.to_str().unwrap().to_string());
+        assert_eq!(log.last(10).unwrap(), Vec::<String>::new());
+        log.clear().unwrap();
+
+        for i in 0..12 {
+            log.record(&format!("SELECT {}", i), i, i % 2 == 0).unwrap();
+        }
+        let last = log.last(DEFAULT_LAST_LINES).unwrap();
+        assert_eq!(last.len(), 10);
+        assert_eq!(fields(&last[0])[1..], ["2", "OK", "SELECT 2"]);
+        assert_eq!(fields(&last[9])[1..], ["11", "ERR", "SELECT 11"]);
+
+        log.record("SELECT 12", 1, true).unwrap();
+        log.clear().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        log.record("SELECT 13", 1, true).unwrap();
+        let last = log.last(10).unwrap();
+        assert_eq!(last.len(), 1);
+        assert_eq!(fields(&last[0])[3], "SELECT 13");
+    }
+}
@@ -0,0 +1,894 @@
+use crate::{
+    config::json_string,
+    durability::{
+        hash_index::HashIndex,
+        index::{index_key, table_indexes, BTreeIndex, IndexKind},
+        skiplist::SKIP_LIST_MAX_ROWS,
+        table::{ColumnStats, ColumnType, Table},
+        DurabilityError,
+    },
+    query::{
+        expression::SelectExpr,
+        predicate::{Operator, Predicate},
+        unquote, Filter, Query, QuerySource,
+    },
+};
+
+/// An index scan reads rows one page at a time in no particular order, it is
+/// only chosen when it is expected to match fewer than 1 in this many rows.
+const INDEX_SCAN_MAX_SELECTIVITY: u64 = 10;
+
+/// The estimated cost of a plan, shown by `EXPLAIN`.
+#[derive(Debug, PartialEq)]
+pub struct Cost {
+    /// Rows expected to match the `WHERE` clause.
+    pub estimated_rows: u64,
+    /// Pages expected to be read from the table file.
+    pub pages: u64,
+}
+
+/// What running a query through its plan took, returned by
+/// `EXPLAIN ANALYZE` below the rows of the query.
+#[derive(Debug, PartialEq, Default)]
+pub struct ExecutionStats {
+    /// Pages read from the table file, pages found in the page cache are not
+    /// counted.
+    pub pages_read: u64,
+    /// Rows the plan read and the `WHERE` clause was evaluated against.
+    pub rows_evaluated: u64,
+    pub rows_returned: u64,
+    pub scan_time_us: u128,
+    pub filter_time_us: u128,
+    /// Projecting the select list and rendering the values.
+    pub serialize_time_us: u128,
+}
+
+impl ExecutionStats {
+    /// One labeled row per stat.
+    pub fn rows(&self) -> Vec<Vec<String>> {
+        [
+            ("Pages read", self.pages_read as u128),
+            ("Rows evaluated", self.rows_evaluated as u128),
+            ("Rows returned", self.rows_returned as u128),
+            ("Scan time (us)", self.scan_time_us),
+            ("Filter time (us)", self.filter_time_us),
+            ("Serialize time (us)", self.serialize_time_us),
+        ]
+        .iter()
+        .map(|(label, value)| vec![label.to_string(), value.to_string()])
+        .collect()
+    }
+}
+
+/// The column, its position and the literal of a `column = literal`
+/// predicate.
+type Equality<'a> = (&'a String, usize, &'a String);
+
+/// The index an index scan searches.
+enum SearchedIndex {
+    BTree(BTreeIndex),
+    Hash(HashIndex),
+}
+
+/// How the executor reads the rows of a `SELECT`. The `WHERE` clause is
+/// still checked against every row any plan produces.
+#[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum QueryPlan {
+    /// Reads every row. `predicates` are the `column operator literal`
+    /// comparisons of the `WHERE` clause, the filter applied to the rows.
+    SeqScan {
+        table: String,
+        predicates: Vec<(String, Operator, String)>,
+        cost: Cost,
+    },
+    /// Reads the indexed rows holding `keys`, the value of each of the leading
+    /// columns of the index searched, followed by every row appended since
+    /// the index was built starting at `unindexed_from`.
+    IndexScan {
+        table: String,
+        kind: IndexKind,
+        index: String,
+        keys: Vec<(String, Vec<u8>)>,
+        rows: Vec<u64>,
+        unindexed_from: u64,
+        cost: Cost,
+    },
+    /// Reads the rows the skip list over the column at `position` finds for
+    /// `column operator literal`.
+    SkipListScan {
+        table: String,
+        column: String,
+        position: usize,
+        operator: Operator,
+        literal: String,
+        cost: Cost,
+    },
+}
+
+impl QueryPlan {
+    pub fn describe(&self) -> String {
+        match self {
+            QueryPlan::SeqScan { table, cost, .. } => format!(
+                "Seq Scan on {} (rows={} pages={})",
+                table, cost.estimated_rows, cost.pages
+            ),
+            QueryPlan::IndexScan {
+                table,
+                kind,
+                index,
+                keys,
+                cost,
+                ..
+            } => {
+                let keys: Vec<String> = keys
+                    .iter()
+                    .map(|(column, key)| format!("{} = {}", column, String::from_utf8_lossy(key)))
+                    .collect();
+                let scan = match kind {
+                    IndexKind::BTree => "Index Scan",
+                    IndexKind::Hash => "Hash Index Scan",
+                };
+                format!(
+                    "{} using {} on {} ({}) (rows={} pages={})",
+                    scan,
+                    index,
+                    table,
+                    keys.join(" AND "),
+                    cost.estimated_rows,
+                    cost.pages
+                )
+            }
+            QueryPlan::SkipListScan {
+                table,
+                column,
+                operator,
+                literal,
+                cost,
+                ..
+            } => format!(
+                "Skip List Scan on {} ({} {} {}) (rows={} pages={})",
+                table,
+                column,
+                operator.symbol(),
+                literal,
+                cost.estimated_rows,
+                cost.pages
+            ),
+        }
+    }
+
+    /// The plan as a JSON object for tools reading `EXPLAIN FORMAT=JSON`.
+    pub fn to_json(&self) -> String {
+        let (plan, table, cost) = match self {
+            QueryPlan::SeqScan { table, cost, .. } => ("FullTableScan", table, cost),
+            QueryPlan::IndexScan { table, cost, .. } => ("IndexScan", table, cost),
+            QueryPlan::SkipListScan { table, cost, .. } => ("SkipListScan", table, cost),
+        };
+        let mut fields = vec![
+            format!("\"plan\":{}", json_string(plan)),
+            format!("\"table\":{}", json_string(table)),
+            format!("\"estimated_rows\":{}", cost.estimated_rows),
+            format!("\"pages\":{}", cost.pages),
+        ];
+        match self {
+            QueryPlan::SeqScan { predicates, .. } => {
+                let predicates: Vec<String> = predicates
+                    .iter()
+                    .map(|(column, operator, literal)| {
+                        predicate_json(column, operator.symbol(), unquote(literal))
+                    })
+                    .collect();
+                match predicates.len() {
+                    0 => {}
+                    1 => fields.push(format!("\"predicate\":{}", predicates[0])),
+                    _ => fields.push(format!(
+                        "\"predicate\":{{\"and\":[{}]}}",
+                        predicates.join(",")
+                    )),
+                }
+            }
+            QueryPlan::IndexScan {
+                kind, index, keys, ..
+            } => {
+                let kind = match kind {
+                    IndexKind::BTree => "btree",
+                    IndexKind::Hash => "hash",
+                };
+                let keys: Vec<String> = keys
+                    .iter()
+                    .map(|(column, key)| predicate_json(column, "=", &String::from_utf8_lossy(key)))
+                    .collect();
+                fields.push(format!("\"index_name\":{}", json_string(index)));
+                fields.push(format!("\"index_type\":{}", json_string(kind)));
+                fields.push(format!("\"seek_key\":[{}]", keys.join(",")));
+            }
+            QueryPlan::SkipListScan {
+                column,
+                operator,
+                literal,
+                ..
+            } => fields.push(format!(
+                "\"predicate\":{}",
+                predicate_json(column, operator.symbol(), unquote(literal))
+            )),
+        }
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+fn predicate_json(column: &str, operator: &str, value: &str) -> String {
+    format!(
+        "{{\"col\":{},\"op\":{},\"val\":{}}}",
+        json_string(column),
+        json_string(operator),
+        json_string(value)
+    )
+}
+
+/// Picks the plan for a query from the table's column stats and the indexes
+/// on its columns. Without stats every `SELECT` is a full scan.
+pub struct QueryOptimizer<'a> {
+    table: &'a Table,
+    stats: Option<Vec<ColumnStats>>,
+}
+
+impl<'a> QueryOptimizer<'a> {
+    pub fn new(table: &'a Table) -> Result<QueryOptimizer<'a>, DurabilityError> {
+        Ok(QueryOptimizer {
+            table,
+            stats: table.load_stats()?,
+        })
+    }
+
+    /// `None` for queries that do not read rows.
+    pub fn plan(&self, query: &Query) -> Option<QueryPlan> {
+        match query {
+            Query::Select(QuerySource::Table(_), _, filter) => Some(self.plan_select(filter)),
+            _ => None,
+        }
+    }
+
+    /// Estimates are made assuming the columns are independent. An index is
+    /// searched when `column = literal` predicates cover its leading columns,
+    /// the index covering the most of them wins. The estimates only look at
+    /// the columns and operators, never the literals, so queries with the
+    /// same `fingerprint_query` get the same plan until the stats change.
+    /// Partial indexes are the exception, whether their predicate is implied
+    /// depends on the literals.
+    pub fn plan_select(&self, filter: &Filter) -> QueryPlan {
+        let table = self.table.name_str().to_string();
+        let row_count = self.table.row_count;
+        let seq_scan = |estimated_rows| QueryPlan::SeqScan {
+            table: table.clone(),
+            predicates: match filter {
+                Filter::Where(predicates) => predicates
+                    .iter()
+                    .filter_map(|predicate| match &predicate.expr {
+                        SelectExpr::Column(column) => Some((
+                            column.clone(),
+                            predicate.operator,
+                            predicate.literal.clone(),
+                        )),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => vec![],
+            },
+            cost: Cost {
+                estimated_rows,
+                pages: self.table.page_count(),
+            },
+        };
+
+        let predicates = match filter {
+            Filter::Where(predicates) => predicates,
+            _ => return seq_scan(row_count),
+        };
+        let stats = match &self.stats {
+            Some(stats) => stats,
+            None => return seq_scan(row_count),
+        };
+        let scale = |rows: u64, matches: u64| rows.saturating_mul(matches) / row_count.max(1);
+
+        let mut estimated_rows = row_count;
+        let mut equalities: Vec<Equality> = vec![];
+        let mut range = None;
+        for predicate in predicates {
+            let (position, column) = match &predicate.expr {
+                SelectExpr::Column(column) => match self.column_position(column) {
+                    Some(position) => (position, column),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let matches = stats[position].estimated_matches(&predicate.operator, row_count);
+            estimated_rows = scale(estimated_rows, matches);
+            match predicate.operator {
+                Operator::Eq => equalities.push((column, position, &predicate.literal)),
+                Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq => {
+                    range = range.or(Some((column, position, predicate, matches)));
+                }
+                Operator::NotEq => {}
+            }
+        }
+        // Without a usable index a range predicate on a small table is
+        // answered by the table's skip list.
+        let fallback = match range {
+            Some((column, position, predicate, matches)) if row_count <= SKIP_LIST_MAX_ROWS => {
+                QueryPlan::SkipListScan {
+                    table: table.clone(),
+                    column: column.clone(),
+                    position,
+                    operator: predicate.operator,
+                    literal: predicate.literal.clone(),
+                    cost: Cost {
+                        estimated_rows,
+                        pages: matches.min(self.table.page_count()),
+                    },
+                }
+            }
+            _ => seq_scan(estimated_rows),
+        };
+        if equalities.is_empty() {
+            return fallback;
+        }
+
+        // A hash index finds the rows of one value in a single bucket chain,
+        // it is preferred over a B-tree covering no more columns.
+        let searched = match (
+            self.btree_index(&table, &equalities, predicates),
+            self.hash_index(&table, &equalities),
+        ) {
+            (Some((index, covered)), _) if covered.len() > 1 => {
+                (SearchedIndex::BTree(index), covered)
+            }
+            (_, Some((index, equality))) => (SearchedIndex::Hash(index), vec![equality]),
+            (Some((index, covered)), None) => (SearchedIndex::BTree(index), covered),
+            (None, None) => return fallback,
+        };
+        let (index, covered) = searched;
+
+        let index_rows = covered.iter().fold(row_count, |rows, equality| {
+            let (_, position, _) = equalities[*equality];
+            scale(
+                rows,
+                stats[position].estimated_matches(&Operator::Eq, row_count),
+            )
+        });
+        if index_rows * INDEX_SCAN_MAX_SELECTIVITY >= row_count {
+            return fallback;
+        }
+
+        let keys: Vec<(String, Vec<u8>)> = covered
+            .into_iter()
+            .map(|equality| {
+                let (column, position, literal) = equalities[equality];
+                let key = index_key(
+                    unquote(literal).as_bytes(),
+                    &self.table.columns[position].column_type,
+                );
+                (column.clone(), key)
+            })
+            .collect();
+        let values: Vec<Vec<u8>> = keys.iter().map(|(_, key)| key.clone()).collect();
+        let (kind, name, indexed_rows, rows) = match index {
+            SearchedIndex::BTree(index) => {
+                let key = index.key(&values);
+                let rows = match values.len() == index.columns.len() {
+                    true => index.lookup(&key).to_vec(),
+                    false => index.lookup_prefix(&key),
+                };
+                (IndexKind::BTree, index.name, index.indexed_rows, rows)
+            }
+            SearchedIndex::Hash(index) => {
+                let key = index.key(&values[0], &ColumnType::Varchar);
+                let rows = index.lookup(&key);
+                (IndexKind::Hash, index.name, index.indexed_rows, rows)
+            }
+        };
+        let unindexed_pages =
+            self.table.page_count() - self.table.page_of_row(indexed_rows.min(row_count));
+        QueryPlan::IndexScan {
+            table,
+            kind,
+            index: name,
+            keys,
+            rows,
+            unindexed_from: indexed_rows,
+            cost: Cost {
+                estimated_rows,
+                pages: index_rows + unindexed_pages,
+            },
+        }
+    }
+
+    /// The B-tree index with the most leading columns covered by equalities,
+    /// with the equality covering each of them. A partial index is only
+    /// considered when one of `predicates` implies its predicate, otherwise
+    /// it may be missing rows the query wants.
+    fn btree_index(
+        &self,
+        table: &str,
+        equalities: &[Equality],
+        predicates: &[Predicate],
+    ) -> Option<(BTreeIndex, Vec<usize>)> {
+        let mut best: Option<(BTreeIndex, Vec<usize>)> = None;
+        for index_path in self.index_files(table, IndexKind::BTree) {
+            let index = match BTreeIndex::read(&index_path) {
+                Ok(index) => index,
+                Err(e) => {
+                    println!("Warning: ignoring index {}: {:?}", index_path, e);
+                    continue;
+                }
+            };
+            if let Some(index_predicate) = &index.predicate {
+                let implied = Predicate::parse(index_predicate).is_some_and(|index_predicate| {
+                    predicates
+                        .iter()
+                        .any(|p| p.implies(&index_predicate, &self.table.columns))
+                });
+                if !implied {
+                    continue;
+                }
+            }
+            let covered: Vec<usize> = index
+                .columns
+                .iter()
+                .map_while(|column| {
+                    equalities
+                        .iter()
+                        .position(|(name, _, _)| **name == column.name)
+                })
+                .collect();
+            if !covered.is_empty()
+                && best
+                    .as_ref()
+                    .is_none_or(|(_, best)| covered.len() > best.len())
+            {
+                best = Some((index, covered));
+            }
+        }
+        best
+    }
+
+    /// The first hash index over a column with an equality, with that
+    /// equality.
+    fn hash_index(&self, table: &str, equalities: &[Equality]) -> Option<(HashIndex, usize)> {
+        for index_path in self.index_files(table, IndexKind::Hash) {
+            let index = match HashIndex::read(&index_path) {
+                Ok(index) => index,
+                Err(e) => {
+                    println!("Warning: ignoring index {}: {:?}", index_path, e);
+                    continue;
+                }
+            };
+            if let Some(equality) = equalities
+                .iter()
+                .position(|(name, _, _)| **name == index.column)
+            {
+                return Some((index, equality));
+            }
+        }
+        None
+    }
+
+    fn index_files(&self, table: &str, kind: IndexKind) -> Vec<String> {
+        table_indexes(table, kind).unwrap_or_else(|e| {
+            println!("Warning: ignoring indexes of {}: {:?}", table, e);
+            vec![]
+        })
+    }
+
+    fn column_position(&self, name: &str) -> Option<usize> {
+        self.table.columns.iter().position(|c| c.name_str() == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::durability::{
+        hash_index::hash_index_file,
+        index::index_file,
+        table::{create_table, writeable_table_file, ColumnType, Row, TableBuilder},
+        Durable,
+    };
+    use crate::query::fingerprint_query;
+
+    /// A table of `row_count` rows with a distinct `id` and a `city` out of
+    /// two, analyzed and with an index on both columns when `indexed`.
+    fn create_users(dir: &std::path::Path, row_count: u64, indexed: bool) -> (Table, String) {
+        let name = dir.join("users").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("id", ColumnType::Int, 4)
+                .column("city", ColumnType::Varchar, 8),
+        )
+        .unwrap();
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        for id in 0..row_count {
+            let city = if id % 2 == 0 { "Oslo" } else { "Bergen" };
+            let row = Row {
+                data: vec![id.to_string().into_bytes(), city.as_bytes().to_vec()],
+            };
+            table.add_row(&row, &mut file).unwrap();
+        }
+        table.analyze(&file).unwrap();
+        if indexed {
+            for (index, column) in [("idx_id", "id"), ("idx_city", "city")] {
+                BTreeIndex::build(index, &table, &file, &[column], None)
+                    .unwrap()
+                    .write(&index_file(&name, column))
+                    .unwrap();
+            }
+        }
+        (table, name)
+    }
+
+    fn plan(table: &Table, query: &str) -> QueryPlan {
+        QueryOptimizer::new(table)
+            .unwrap()
+            .plan(&Query::from(query))
+            .unwrap()
+    }
+
+    #[test]
+    fn selective_predicate_uses_index() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, name) = create_users(tmp_dir.path(), 40, true);
+
+        match plan(&table, "SELECT * FROM users WHERE id = 7") {
+            QueryPlan::IndexScan {
+                table,
+                index,
+                keys,
+                rows,
+                unindexed_from,
+                cost,
+                ..
+            } => {
+                assert_eq!(table, name);
+                assert_eq!(index, "idx_id");
+                assert_eq!(keys, vec![("id".to_string(), b"7".to_vec())]);
+                assert_eq!(rows, vec![7]);
+                assert_eq!(unindexed_from, 40);
+                assert_eq!(cost.estimated_rows, 1);
+            }
+            plan => panic!("Expected an index scan, got {:?}", plan),
+        }
+    }
+
+    #[test]
+    fn unselective_predicate_scans_table() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, _) = create_users(tmp_dir.path(), 40, true);
+
+        for (query, estimated_rows) in [
+            ("SELECT * FROM users WHERE city = 'Oslo'", 20),
+            ("SELECT * FROM users WHERE id != 7", 39),
+            ("SELECT * FROM users", 40),
+        ] {
+            match plan(&table, query) {
+                QueryPlan::SeqScan { cost, .. } => {
+                    assert_eq!(cost.estimated_rows, estimated_rows);
+                    assert_eq!(cost.pages, table.page_count());
+                }
+                plan => panic!("Expected a seq scan for {}, got {:?}", query, plan),
+            }
+        }
+    }
+
+    #[test]
+    fn range_predicate_uses_skip_list() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, name) = create_users(tmp_dir.path(), 40, false);
+
+        let plan = plan(&table, "SELECT * FROM users WHERE id > 7 AND city = 'Oslo'");
+        assert_eq!(
+            plan.describe(),
+            format!("Skip List Scan on {} (id > 7) (rows=6 pages=5)", name)
+        );
+    }
+
+    #[test]
+    fn plan_to_json() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, name) = create_users(tmp_dir.path(), 40, true);
+        let json = |query| -> serde_json::Value {
+            serde_json::from_str(&plan(&table, query).to_json()).unwrap()
+        };
+
+        let index_scan = json("SELECT * FROM users WHERE id = 7");
+        assert_eq!(index_scan["plan"], "IndexScan");
+        assert_eq!(index_scan["table"], name.as_str());
+        assert_eq!(index_scan["estimated_rows"], 1);
+        assert_eq!(index_scan["index_name"], "idx_id");
+        assert_eq!(index_scan["index_type"], "btree");
+        assert_eq!(
+            index_scan["seek_key"],
+            serde_json::json!([{"col": "id", "op": "=", "val": "7"}])
+        );
+
+        let seq_scan = json("SELECT * FROM users WHERE city = 'Oslo'");
+        assert_eq!(seq_scan["plan"], "FullTableScan");
+        assert_eq!(seq_scan["estimated_rows"], 20);
+        assert_eq!(seq_scan["pages"], table.page_count());
+        assert_eq!(
+            seq_scan["predicate"],
+            serde_json::json!({"col": "city", "op": "=", "val": "Oslo"})
+        );
+        let seq_scan = json("SELECT * FROM users WHERE city = 'Oslo' AND id != 7");
+        assert_eq!(
+            seq_scan["predicate"],
+            serde_json::json!({"and": [
+                {"col": "city", "op": "=", "val": "Oslo"},
+                {"col": "id", "op": "!=", "val": "7"}
+            ]})
+        );
+        assert!(json("SELECT * FROM users").get("predicate").is_none());
+
+        let unindexed_dir = tempdir().unwrap();
+        let (table, _) = create_users(unindexed_dir.path(), 40, false);
+        let skip_list_scan: serde_json::Value =
+            serde_json::from_str(&plan(&table, "SELECT * FROM users WHERE id > 7").to_json())
+                .unwrap();
+        assert_eq!(skip_list_scan["plan"], "SkipListScan");
+        assert_eq!(
+            skip_list_scan["predicate"],
+            serde_json::json!({"col": "id", "op": ">", "val": "7"})
+        );
+    }
+
+    #[test]
+    fn small_table_scans_table() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, _) = create_users(tmp_dir.path(), 5, true);
+        assert!(matches!(
+            plan(&table, "SELECT * FROM users WHERE id = 3"),
+            QueryPlan::SeqScan { .. }
+        ));
+    }
+
+    #[test]
+    fn missing_index_scans_table() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, _) = create_users(tmp_dir.path(), 40, false);
+        assert!(matches!(
+            plan(&table, "SELECT * FROM users WHERE id = 7"),
+            QueryPlan::SeqScan { .. }
+        ));
+    }
+
+    /// 80 people with 20 last names of 4 people each, who have one of 4
+    /// first names, and an index on both names.
+    fn create_people(dir: &std::path::Path) -> Table {
+        let name = dir.join("people").to_str().unwrap().to_string();
+        create_table(
+            TableBuilder::new(&name)
+                .column("last_name", ColumnType::Varchar, 8)
+                .column("first_name", ColumnType::Varchar, 8),
+        )
+        .unwrap();
+
+        let mut file = writeable_table_file(name.clone()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        for id in 0..80 {
+            let row = Row {
+                data: vec![
+                    format!("L{}", id / 4).into_bytes(),
+                    format!("F{}", id % 4).into_bytes(),
+                ],
+            };
+            table.add_row(&row, &mut file).unwrap();
+        }
+        table.analyze(&file).unwrap();
+        BTreeIndex::build(
+            "idx_last_first",
+            &table,
+            &file,
+            &["last_name", "first_name"],
+            None,
+        )
+        .unwrap()
+        .write(&index_file(&name, "last_name,first_name"))
+        .unwrap();
+        table
+    }
+
+    #[test]
+    fn composite_index_matches_both_columns() {
+        let tmp_dir = tempdir().unwrap();
+        let table = create_people(tmp_dir.path());
+
+        let plan = plan(
+            &table,
+            "SELECT * FROM people WHERE first_name = 'F1' AND last_name = 'L3'",
+        );
+        assert_eq!(
+            plan.describe(),
+            format!(
+                "Index Scan using idx_last_first on {} (last_name = L3 AND first_name = F1) (rows=1 pages=2)",
+                table.name_str()
+            )
+        );
+        match plan {
+            QueryPlan::IndexScan { rows, .. } => assert_eq!(rows, vec![13]),
+            plan => panic!("Expected an index scan, got {:?}", plan),
+        }
+    }
+
+    #[test]
+    fn composite_index_matches_leading_column() {
+        let tmp_dir = tempdir().unwrap();
+        let table = create_people(tmp_dir.path());
+
+        match plan(&table, "SELECT * FROM people WHERE last_name = 'L3'") {
+            QueryPlan::IndexScan {
+                keys, rows, cost, ..
+            } => {
+                assert_eq!(keys, vec![("last_name".to_string(), b"L3".to_vec())]);
+                assert_eq!(rows, vec![12, 13, 14, 15]);
+                assert_eq!(cost.estimated_rows, 4);
+            }
+            plan => panic!("Expected an index scan, got {:?}", plan),
+        }
+
+        // Without the leading column the index is of no use.
+        assert!(matches!(
+            plan(&table, "SELECT * FROM people WHERE first_name = 'F1'"),
+            QueryPlan::SeqScan { .. }
+        ));
+    }
+
+    #[test]
+    fn hash_index_preferred_for_equality() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, name) = create_users(tmp_dir.path(), 40, true);
+        let file = writeable_table_file(name.clone()).unwrap();
+        HashIndex::build("hidx_id", &table, &file, "id")
+            .unwrap()
+            .write(&hash_index_file(&name, "id"))
+            .unwrap();
+
+        let plan = plan(&table, "SELECT * FROM users WHERE id = 07");
+        assert_eq!(
+            plan.describe(),
+            format!(
+                "Hash Index Scan using hidx_id on {} (id = 7) (rows=1 pages=2)",
+                name
+            )
+        );
+        match plan {
+            QueryPlan::IndexScan { rows, .. } => assert_eq!(rows, vec![7]),
+            plan => panic!("Expected an index scan, got {:?}", plan),
+        }
+    }
+
+    #[test]
+    fn partial_index_needs_implied_predicate() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, name) = create_users(tmp_dir.path(), 40, false);
+        let file = writeable_table_file(name.clone()).unwrap();
+        BTreeIndex::build("idx_oslo", &table, &file, &["id"], Some("city = 'Oslo'"))
+            .unwrap()
+            .write(&index_file(&name, "id"))
+            .unwrap();
+
+        match plan(&table, "SELECT * FROM users WHERE id = 8 AND city = 'Oslo'") {
+            QueryPlan::IndexScan { index, rows, .. } => {
+                assert_eq!(index, "idx_oslo");
+                assert_eq!(rows, vec![8]);
+            }
+            plan => panic!("Expected an index scan, got {:?}", plan),
+        }
+        // Without the city the index may miss rows.
+        assert!(matches!(
+            plan(&table, "SELECT * FROM users WHERE id = 7"),
+            QueryPlan::SeqScan { .. }
+        ));
+    }
+
+    #[test]
+    fn same_fingerprint_same_plan() {
+        let tmp_dir = tempdir().unwrap();
+        let (table, _) = create_users(tmp_dir.path(), 40, true);
+
+        let (first, second) = (
+            "SELECT * FROM users WHERE id = 7",
+            "SELECT * FROM users WHERE id = 31",
+        );
+        assert_eq!(fingerprint_query(first), fingerprint_query(second));
+        match (plan(&table, first), plan(&table, second)) {
+            (
+                QueryPlan::IndexScan {
+                    index, cost, rows, ..
+                },
+                QueryPlan::IndexScan {
+                    index: other_index,
+                    cost: other_cost,
+                    rows: other_rows,
+                    ..
+                },
+            ) => {
+                assert_eq!(index, other_index);
+                assert_eq!(cost, other_cost);
+                assert_eq!((rows, other_rows), (vec![7], vec![31]));
+            }
+            plans => panic!("Expected index scans, got {:?}", plans),
+        }
+
+        match (
+            plan(&table, "SELECT * FROM users WHERE city = 'Oslo'"),
+            plan(&table, "SELECT * FROM users WHERE city = 'Bergen'"),
+        ) {
+            (
+                QueryPlan::SeqScan { cost, .. },
+                QueryPlan::SeqScan {
+                    cost: other_cost, ..
+                },
+            ) => {
+                assert_eq!(cost, other_cost)
+            }
+            plans => panic!("Expected sequential scans, got {:?}", plans),
+        }
+    }
+
+    /// The labeled stats below the rows of an `EXPLAIN ANALYZE`.
+    fn explain_analyze(name: &str, query: &str) -> (Vec<Vec<String>>, Vec<(String, u128)>) {
+        let mut file = writeable_table_file(name.to_string()).unwrap();
+        let mut table = Table::read_from_disk(&mut file).unwrap();
+        let mut rows = crate::get_result_set(
+            &mut table,
+            &mut file,
+            Query::from(format!("EXPLAIN ANALYZE {}", query).as_str()),
+            &mut std::collections::HashMap::new(),
+            &crate::durability::DatabaseConfig {
+                name: "city_db".to_string(),
+                file_path: ".".to_string(),
+                auth_file: None,
+            },
+            &mut None,
+            &mut crate::config::Config::default(),
+            &mut std::io::empty(),
+        )
+        .rows;
+        let stats = rows
+            .split_off(rows.len() - 6)
+            .into_iter()
+            .map(|row| (row[0].clone(), row[1].parse().unwrap()))
+            .collect();
+        assert_eq!(rows.pop().unwrap()[0], "Plan");
+        (rows, stats)
+    }
+
+    #[test]
+    fn explain_analyze_reports_execution_stats() {
+        let tmp_dir = tempdir().unwrap();
+        let (_, name) = create_users(tmp_dir.path(), 2000, false);
+        let (rows, stats) = explain_analyze(
+            &name,
+            &format!("SELECT id FROM {} WHERE city = 'Bergen'", name),
+        );
+        assert_eq!(rows.len(), 1000);
+        assert_eq!(rows[0], ["1"]);
+        let stat = |label: &str| stats.iter().find(|(l, _)| l == label).unwrap().1;
+        assert!(stat("Pages read") > 0);
+        assert_eq!(stat("Rows evaluated"), 2000);
+        assert_eq!(stat("Rows returned"), 1000);
+        assert!(stat("Scan time (us)") > 0);
+        assert!(stat("Filter time (us)") > 0);
+
+        let empty_dir = tempdir().unwrap();
+        let (_, name) = create_users(empty_dir.path(), 0, false);
+        let (rows, stats) = explain_analyze(&name, &format!("SELECT * FROM {}", name));
+        assert!(rows.is_empty());
+        for label in ["Pages read", "Rows evaluated", "Rows returned"] {
+            assert_eq!(stats.iter().find(|(l, _)| l == label).unwrap().1, 0);
+        }
+    }
+}
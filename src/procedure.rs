@@ -0,0 +1,131 @@
+use mlua::{Lua, LuaOptions, MultiValue, StdLib, Value};
+
+/// Lua functions the base library loads that read files.
+const FILE_FUNCTIONS: [&str; 3] = ["dofile", "loadfile", "load"];
+
+/// Runs the Lua body of a procedure with `args` as its `...`. The script
+/// gets the table, string, math and utf8 libraries but nothing that reaches
+/// files, processes or the network, and a `db` table whose `db.execute(sql)`
+/// runs a query through `execute` and returns its rows as an array of arrays
+/// of strings, or raises the error of a query `execute` refuses. What the
+/// script returns is the result of the call: an array of rows, an array of
+/// values as one row each, or a single value.
+pub fn run_procedure(
+    name: &str,
+    body: &str,
+    args: &[String],
+    mut execute: impl FnMut(&str) -> Result<Vec<Vec<String>>, String>,
+) -> Result<Vec<Vec<String>>, String> {
+    let libraries = StdLib::TABLE | StdLib::STRING | StdLib::MATH | StdLib::UTF8;
+    let lua = Lua::new_with(libraries, LuaOptions::default()).map_err(|e| e.to_string())?;
+    let mut run = || -> mlua::Result<Vec<Vec<String>>> {
+        let globals = lua.globals();
+        for function in FILE_FUNCTIONS {
+            globals.set(function, Value::Nil)?;
+        }
+        lua.scope(|scope| {
+            let db = lua.create_table()?;
+            let db_execute = scope.create_function_mut(|lua, sql: String| {
+                let rows = execute(&sql)
+                    .map_err(mlua::Error::RuntimeError)?
+                    .into_iter()
+                    .map(|row| lua.create_sequence_from(row))
+                    .collect::<mlua::Result<Vec<_>>>()?;
+                lua.create_sequence_from(rows)
+            })?;
+            db.set("execute", db_execute)?;
+            globals.set("db", db)?;
+
+            let args = args
+                .iter()
+                .map(|arg| lua.create_string(arg).map(Value::String))
+                .collect::<mlua::Result<MultiValue>>()?;
+            let returned: Value = lua.load(body).set_name(name).call(args)?;
+            Ok(result_rows(returned))
+        })
+    };
+    run().map_err(|e| e.to_string())
+}
+
+fn result_rows(returned: Value) -> Vec<Vec<String>> {
+    match returned {
+        Value::Nil => vec![],
+        Value::Table(table) => table
+            .sequence_values::<Value>()
+            .filter_map(Result::ok)
+            .map(|value| match value {
+                Value::Table(row) => row
+                    .sequence_values::<Value>()
+                    .filter_map(Result::ok)
+                    .map(|value| value_string(&value))
+                    .collect(),
+                value => vec![value_string(&value)],
+            })
+            .collect(),
+        value => vec![vec![value_string(&value)]],
+    }
+}
+
+fn value_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.to_string_lossy().to_string(),
+        Value::Integer(value) => value.to_string(),
+        Value::Number(value) => value.to_string(),
+        Value::Boolean(value) => value.to_string(),
+        Value::Nil => String::new(),
+        value => format!("{:?}", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_procedure() {
+        let mut executed = vec![];
+        let rows = run_procedure(
+            "add_user",
+            "local id, city = ...
+            db.execute('INSERT ' .. id .. ' ' .. city)
+            local rows = db.execute('SELECT')
+            return { { rows[1][1], #rows }, { 'done' } }",
+            &["7".to_string(), "Oslo".to_string()],
+            |sql| {
+                executed.push(sql.to_string());
+                Ok(vec![vec!["7".to_string(), "Oslo".to_string()]])
+            },
+        )
+        .unwrap();
+        assert_eq!(executed, ["INSERT 7 Oslo", "SELECT"]);
+        assert_eq!(rows, [vec!["7", "1"], vec!["done"]]);
+
+        let run = |body: &str| run_procedure("proc", body, &[], |_| Ok(vec![]));
+        assert_eq!(run("return 42").unwrap(), [["42"]]);
+        assert_eq!(run("return { 'a', 'b' }").unwrap(), [["a"], ["b"]]);
+        assert!(run("").unwrap().is_empty());
+        assert!(run("error('failed')").unwrap_err().contains("failed"));
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let run = |body: &str| {
+            run_procedure("proc", body, &[], |sql| match sql.starts_with("COPY") {
+                true => Err("Procedures cannot touch files".to_string()),
+                false => Ok(vec![]),
+            })
+        };
+        for body in [
+            "io.open('/etc/passwd')",
+            "os.execute('ls')",
+            "dofile('/etc/passwd')",
+            "loadfile('/etc/passwd')",
+            "require('socket')",
+            "load('return 1')",
+            "db.execute('COPY account_tbl TO ''/tmp/accounts'' BINARY')",
+        ] {
+            assert!(run(body).is_err(), "{} ran", body);
+        }
+        assert!(run("db.execute('SELECT')").is_ok());
+    }
+}
@@ -0,0 +1,537 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
+
+use crate::durability::{
+    skiplist::ordered_key,
+    table::{ColumnDefinition, ColumnType, Row},
+};
+
+use super::expression::{parse_number, SelectExpr};
+
+/// A function of the select list computed over the rows of a group, every
+/// row the SELECT returns when there is no GROUP BY.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AggregateFunction {
+    /// `COUNT(*)` counts the rows, `COUNT(column)` the non null values.
+    Count(Option<String>),
+    /// The number of distinct non null values of the column. Every distinct
+    /// value is kept in memory while counting, O(distinct values × column
+    /// length).
+    CountDistinct(String),
+    Sum(String),
+    Avg(String),
+    Min(String),
+    Max(String),
+}
+
+impl AggregateFunction {
+    /// Parses `COUNT(*)`, `COUNT([DISTINCT] column)`, `SUM(column)`,
+    /// `AVG(column)`, `MIN(column)` and `MAX(column)`.
+    pub fn parse(expression: &str) -> Option<AggregateFunction> {
+        let (name, argument) = expression.strip_suffix(')')?.split_once('(')?;
+        if (name, argument.trim()) == ("COUNT", "*") {
+            return Some(AggregateFunction::Count(None));
+        }
+        let (distinct, argument) = match argument.strip_prefix("DISTINCT ") {
+            Some(argument) => (true, argument),
+            None => (false, argument),
+        };
+        let column = match SelectExpr::parse(argument)? {
+            SelectExpr::Column(column) => column,
+            _ => return None,
+        };
+        match (name, distinct) {
+            ("COUNT", false) => Some(AggregateFunction::Count(Some(column))),
+            ("COUNT", true) => Some(AggregateFunction::CountDistinct(column)),
+            ("SUM", false) => Some(AggregateFunction::Sum(column)),
+            ("AVG", false) => Some(AggregateFunction::Avg(column)),
+            ("MIN", false) => Some(AggregateFunction::Min(column)),
+            ("MAX", false) => Some(AggregateFunction::Max(column)),
+            _ => None,
+        }
+    }
+
+    fn column(&self) -> Option<&str> {
+        match self {
+            AggregateFunction::Count(column) => column.as_deref(),
+            AggregateFunction::CountDistinct(column)
+            | AggregateFunction::Sum(column)
+            | AggregateFunction::Avg(column)
+            | AggregateFunction::Min(column)
+            | AggregateFunction::Max(column) => Some(column),
+        }
+    }
+
+    /// The type of the value of the function: a sum is an Int over Int
+    /// values, the extremes keep the type of their column.
+    pub fn value_type(&self, columns: &[ColumnDefinition]) -> ColumnType {
+        let column_type = || {
+            columns
+                .iter()
+                .find(|c| Some(c.name_str()) == self.column())
+                .map_or(ColumnType::Varchar, |c| c.column_type.clone())
+        };
+        match self {
+            AggregateFunction::Count(_) | AggregateFunction::CountDistinct(_) => ColumnType::Int,
+            AggregateFunction::Sum(_) => match column_type() {
+                ColumnType::Int => ColumnType::Int,
+                _ => ColumnType::Float,
+            },
+            AggregateFunction::Avg(_) => ColumnType::Float,
+            AggregateFunction::Min(_) | AggregateFunction::Max(_) => column_type(),
+        }
+    }
+
+    /// The value of the function over `rows`.
+    pub fn evaluate(&self, rows: &[&Row], columns: &[ColumnDefinition]) -> Result<Vec<u8>, String> {
+        let mut state = AggregateState::new(self, columns)?;
+        for row in rows {
+            state.add(row, columns)?;
+        }
+        Ok(state.finish())
+    }
+}
+
+/// The running value of an aggregate.
+#[derive(Debug)]
+enum Accumulator {
+    Count(u64),
+    Distinct(HashSet<Vec<u8>>),
+    IntSum(Option<i128>),
+    FloatSum(Option<f64>),
+    Avg {
+        total: f64,
+        count: u64,
+    },
+    /// The sort key of the extreme value so far, and the value.
+    Min(Option<(Vec<u8>, Vec<u8>)>),
+    Max(Option<(Vec<u8>, Vec<u8>)>),
+}
+
+/// An aggregate updated a row at a time, holding its running value instead
+/// of the rows.
+#[derive(Debug)]
+struct AggregateState {
+    /// The position of the column the function reads, `None` for `COUNT(*)`.
+    position: Option<usize>,
+    accumulator: Accumulator,
+}
+
+impl AggregateState {
+    fn new(function: &AggregateFunction, columns: &[ColumnDefinition]) -> Result<Self, String> {
+        let position = function
+            .column()
+            .map(|column| column_position(columns, column))
+            .transpose()?;
+        let column_type = position.map(|position| &columns[position].column_type);
+        let numeric = |name: &str| match column_type {
+            Some(ColumnType::Int | ColumnType::Float) => Ok(()),
+            column_type => Err(format!(
+                "{} expects a number, got {:?}",
+                name,
+                column_type.unwrap()
+            )),
+        };
+        let accumulator = match function {
+            AggregateFunction::Count(_) => Accumulator::Count(0),
+            AggregateFunction::CountDistinct(_) => Accumulator::Distinct(HashSet::new()),
+            AggregateFunction::Sum(_) => {
+                numeric("SUM")?;
+                match column_type {
+                    Some(ColumnType::Int) => Accumulator::IntSum(None),
+                    _ => Accumulator::FloatSum(None),
+                }
+            }
+            AggregateFunction::Avg(_) => {
+                numeric("AVG")?;
+                Accumulator::Avg {
+                    total: 0.0,
+                    count: 0,
+                }
+            }
+            AggregateFunction::Min(_) => Accumulator::Min(None),
+            AggregateFunction::Max(_) => Accumulator::Max(None),
+        };
+        Ok(AggregateState {
+            position,
+            accumulator,
+        })
+    }
+
+    /// Adds the value of `row` to the running value, nulls are left out.
+    fn add(&mut self, row: &Row, columns: &[ColumnDefinition]) -> Result<(), String> {
+        let Some(position) = self.position else {
+            if let Accumulator::Count(count) = &mut self.accumulator {
+                *count += 1;
+            }
+            return Ok(());
+        };
+        let value = value(&row.data[position]);
+        if value.is_empty() {
+            return Ok(());
+        }
+        let column_type = &columns[position].column_type;
+        match &mut self.accumulator {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Distinct(values) => {
+                if !values.contains(value) {
+                    values.insert(value.to_vec());
+                }
+            }
+            Accumulator::IntSum(total) => {
+                *total = Some(total.unwrap_or(0) + parse_number::<i64>(value)? as i128);
+            }
+            Accumulator::FloatSum(total) => {
+                *total = Some(total.unwrap_or(0.0) + parse_number::<f64>(value)?);
+            }
+            Accumulator::Avg { total, count } => {
+                *total += parse_number::<f64>(value)?;
+                *count += 1;
+            }
+            Accumulator::Min(extreme) => keep_extreme(extreme, value, column_type, Ordering::Less),
+            Accumulator::Max(extreme) => {
+                keep_extreme(extreme, value, column_type, Ordering::Greater)
+            }
+        }
+        Ok(())
+    }
+
+    /// The value of the aggregate over the rows added, null for a sum, an
+    /// average or an extreme of no values.
+    fn finish(self) -> Vec<u8> {
+        match self.accumulator {
+            Accumulator::Count(count) => Some(count.to_string().into_bytes()),
+            Accumulator::Distinct(values) => Some(values.len().to_string().into_bytes()),
+            Accumulator::IntSum(total) => total.map(|total| total.to_string().into_bytes()),
+            Accumulator::FloatSum(total) => total.map(|total| total.to_string().into_bytes()),
+            Accumulator::Avg { count: 0, .. } => None,
+            Accumulator::Avg { total, count } => {
+                Some((total / count as f64).to_string().into_bytes())
+            }
+            Accumulator::Min(extreme) | Accumulator::Max(extreme) => {
+                extreme.map(|(_, value)| value)
+            }
+        }
+        .unwrap_or_default()
+    }
+}
+
+/// Replaces `extreme` with `value` when it sorts `ordering` of it. Values
+/// compare as their column type does, those that do not parse as it are
+/// left out.
+fn keep_extreme(
+    extreme: &mut Option<(Vec<u8>, Vec<u8>)>,
+    value: &[u8],
+    column_type: &ColumnType,
+    ordering: Ordering,
+) {
+    let Some(key) = ordered_key(value, column_type) else {
+        return;
+    };
+    if extreme
+        .as_ref()
+        .is_none_or(|(kept, _)| key.cmp(kept) == ordering)
+    {
+        *extreme = Some((key, value.to_vec()));
+    }
+}
+
+/// Computes the aggregates of a select list made of aggregates only a row
+/// at a time, so a scan can feed it rows as it reads them instead of
+/// collecting them first. Only `COUNT(DISTINCT)` grows with the rows.
+#[derive(Debug)]
+pub struct StreamingAggregator<'a> {
+    columns: &'a [ColumnDefinition],
+    states: Vec<AggregateState>,
+}
+
+impl<'a> StreamingAggregator<'a> {
+    pub fn new(
+        expressions: &[SelectExpr],
+        columns: &'a [ColumnDefinition],
+    ) -> Result<Self, String> {
+        let states = expressions
+            .iter()
+            .map(|expression| match expression {
+                SelectExpr::Aggregate(function) => AggregateState::new(function, columns),
+                _ => Err("Only aggregates can be streamed".to_string()),
+            })
+            .collect::<Result<Vec<AggregateState>, String>>()?;
+        Ok(StreamingAggregator { columns, states })
+    }
+
+    pub fn add(&mut self, row: &Row) -> Result<(), String> {
+        for state in self.states.iter_mut() {
+            state.add(row, self.columns)?;
+        }
+        Ok(())
+    }
+
+    /// The row of the aggregates over the rows added.
+    pub fn finish(self) -> Row {
+        Row {
+            data: self
+                .states
+                .into_iter()
+                .map(AggregateState::finish)
+                .collect(),
+        }
+    }
+}
+
+/// Whether the select list is made of aggregates only, which
+/// `StreamingAggregator` computes without holding the rows.
+pub fn is_streamable(expressions: &[SelectExpr]) -> bool {
+    expressions
+        .iter()
+        .all(|expression| matches!(expression, SelectExpr::Aggregate(_)))
+}
+/// The value up to its first null byte, empty for null.
+fn value(value: &[u8]) -> &[u8] {
+    value.split(|b| *b == 0).next().unwrap_or_default()
+}
+
+fn column_position(columns: &[ColumnDefinition], name: &str) -> Result<usize, String> {
+    columns
+        .iter()
+        .position(|c| c.name_str() == name)
+        .ok_or_else(|| format!("Column {} does not exist", name))
+}
+
+pub fn has_aggregate(expressions: &[SelectExpr]) -> bool {
+    expressions
+        .iter()
+        .any(|expression| matches!(expression, SelectExpr::Aggregate(_)))
+}
+
+/// Projects `rows` through a select list holding aggregates, a row per
+/// distinct value of the `group_by` columns in the order they first appear,
+/// or a single row for all of them without a GROUP BY. A column of the
+/// select list must be one of the `group_by` columns, other expressions are
+/// evaluated on the first row of the group.
+pub fn project_aggregates(
+    rows: Vec<Row>,
+    expressions: &[SelectExpr],
+    group_by: &[String],
+    columns: &[ColumnDefinition],
+) -> Result<Vec<Row>, String> {
+    for expression in expressions {
+        if let SelectExpr::Column(name) = expression {
+            if !group_by.contains(name) {
+                return Err(format!(
+                    "Column {} must appear in the GROUP BY clause",
+                    name
+                ));
+            }
+        }
+    }
+    let positions = group_by
+        .iter()
+        .map(|name| column_position(columns, name))
+        .collect::<Result<Vec<usize>, String>>()?;
+
+    let mut groups: Vec<Vec<&Row>> = vec![];
+    let mut group_of: HashMap<Vec<&[u8]>, usize> = HashMap::new();
+    for row in rows.iter() {
+        let key: Vec<&[u8]> = positions
+            .iter()
+            .map(|position| value(&row.data[*position]))
+            .collect();
+        let group = *group_of.entry(key).or_insert_with(|| {
+            groups.push(vec![]);
+            groups.len() - 1
+        });
+        groups[group].push(row);
+    }
+    // Aggregates over no rows still make a row, unless grouped.
+    if groups.is_empty() && group_by.is_empty() {
+        groups.push(vec![]);
+    }
+
+    groups
+        .iter()
+        .map(|group| {
+            let data = expressions
+                .iter()
+                .map(|expression| match (expression, group.first()) {
+                    (SelectExpr::Aggregate(function), _) => function.evaluate(group, columns),
+                    (expression, Some(row)) => expression.evaluate(row, columns),
+                    (_, None) => Ok(vec![]),
+                })
+                .collect::<Result<Vec<Vec<u8>>, String>>()?;
+            Ok(Row { data })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns() -> Vec<ColumnDefinition> {
+        vec![
+            ColumnDefinition::new("account_id".to_string(), ColumnType::Int, 4),
+            ColumnDefinition::new("product_id".to_string(), ColumnType::Int, 4),
+        ]
+    }
+
+    fn rows(values: &[(&str, &str)]) -> Vec<Row> {
+        values
+            .iter()
+            .map(|(account_id, product_id)| Row {
+                data: vec![
+                    account_id.as_bytes().to_vec(),
+                    product_id.as_bytes().to_vec(),
+                ],
+            })
+            .collect()
+    }
+
+    fn project(rows: Vec<Row>, select: &str, group_by: &[&str]) -> Vec<Vec<String>> {
+        let expressions: Vec<SelectExpr> = select
+            .split(", ")
+            .map(|expression| SelectExpr::parse(expression).unwrap())
+            .collect();
+        let group_by: Vec<String> = group_by.iter().map(|c| c.to_string()).collect();
+        project_aggregates(rows, &expressions, &group_by, &columns())
+            .unwrap()
+            .into_iter()
+            .map(|row| {
+                row.data
+                    .into_iter()
+                    .map(|value| String::from_utf8(value).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_aggregates() {
+        assert_eq!(
+            AggregateFunction::parse("COUNT(DISTINCT product_id)"),
+            Some(AggregateFunction::CountDistinct("product_id".to_string()))
+        );
+        assert_eq!(
+            AggregateFunction::parse("COUNT(*)"),
+            Some(AggregateFunction::Count(None))
+        );
+        assert_eq!(
+            AggregateFunction::parse("COUNT(product_id)"),
+            Some(AggregateFunction::Count(Some("product_id".to_string())))
+        );
+        assert_eq!(
+            AggregateFunction::parse("MAX(product_id)"),
+            Some(AggregateFunction::Max("product_id".to_string()))
+        );
+        assert_eq!(AggregateFunction::parse("SUM(DISTINCT product_id)"), None);
+        assert_eq!(AggregateFunction::parse("SUM(*)"), None);
+        assert_eq!(AggregateFunction::parse("MEDIAN(product_id)"), None);
+        assert_eq!(AggregateFunction::parse("COUNT(DISTINCT 'a')"), None);
+        assert_eq!(AggregateFunction::parse("COUNT(DISTINCT )"), None);
+        assert!(has_aggregate(&[
+            SelectExpr::parse("account_id").unwrap(),
+            SelectExpr::parse("COUNT(DISTINCT product_id)").unwrap()
+        ]));
+    }
+
+    #[test]
+    fn count_distinct() {
+        let orders = || {
+            rows(&[
+                ("1", "10"),
+                ("2", "10"),
+                ("1", "11"),
+                ("1", "10"),
+                ("3", ""),
+                ("2", "12\0\0"),
+                ("2", "12"),
+                ("1", "12"),
+            ])
+        };
+        // Nulls are not counted, padding does not make values different.
+        assert_eq!(
+            project(orders(), "COUNT(DISTINCT product_id)", &[]),
+            [["3"]]
+        );
+        assert_eq!(
+            project(orders(), "COUNT(DISTINCT account_id)", &[]),
+            [["3"]]
+        );
+        assert_eq!(
+            project(
+                orders(),
+                "account_id, COUNT(DISTINCT product_id)",
+                &["account_id"]
+            ),
+            [["1", "3"], ["2", "2"], ["3", "0"]]
+        );
+        assert_eq!(project(vec![], "COUNT(DISTINCT product_id)", &[]), [["0"]]);
+        assert!(project(vec![], "COUNT(DISTINCT product_id)", &["account_id"]).is_empty());
+
+        let expressions = vec![
+            SelectExpr::parse("product_id").unwrap(),
+            SelectExpr::parse("COUNT(DISTINCT account_id)").unwrap(),
+        ];
+        let grouped = project_aggregates(orders(), &expressions, &[], &columns());
+        assert!(grouped.is_err());
+        let missing = [SelectExpr::parse("COUNT(DISTINCT missing)").unwrap()];
+        assert!(project_aggregates(orders(), &missing, &[], &columns()).is_err());
+    }
+
+    #[test]
+    fn streaming_aggregates() {
+        let orders = rows(&[
+            ("1", "10"),
+            ("2", "-3"),
+            ("3", ""),
+            ("4", "100"),
+            ("5", "7"),
+        ]);
+        let expressions: Vec<SelectExpr> = [
+            "COUNT(*)",
+            "COUNT(product_id)",
+            "SUM(product_id)",
+            "AVG(account_id)",
+            "MIN(product_id)",
+            "MAX(product_id)",
+            "COUNT(DISTINCT account_id)",
+        ]
+        .iter()
+        .map(|expression| SelectExpr::parse(expression).unwrap())
+        .collect();
+        assert!(is_streamable(&expressions));
+        let columns = columns();
+
+        let mut aggregator = StreamingAggregator::new(&expressions, &columns).unwrap();
+        let empty = StreamingAggregator::new(&expressions, &columns).unwrap();
+        for row in orders.iter() {
+            aggregator.add(row).unwrap();
+        }
+        // Numbers compare as numbers, nulls are left out.
+        let values = ["5", "4", "114", "3", "-3", "100", "5"];
+        let expected: Vec<Vec<u8>> = values.iter().map(|v| v.as_bytes().to_vec()).collect();
+        assert_eq!(aggregator.finish().data, expected);
+        // The same as computed over the collected rows.
+        let projected = project_aggregates(orders, &expressions, &[], &columns).unwrap();
+        assert_eq!(projected[0].data, expected);
+        // A sum, an average or an extreme of no values is null.
+        let values = ["0", "0", "", "", "", "", "0"];
+        let expected: Vec<Vec<u8>> = values.iter().map(|v| v.as_bytes().to_vec()).collect();
+        assert_eq!(empty.finish().data, expected);
+
+        let mut aggregator = StreamingAggregator::new(&expressions[2..3], &columns).unwrap();
+        assert!(aggregator.add(&rows(&[("1", "ten")])[0]).is_err());
+        let names = [ColumnDefinition::new(
+            "product_id".to_string(),
+            ColumnType::Varchar,
+            8,
+        )];
+        assert!(StreamingAggregator::new(&expressions[2..3], &names).is_err());
+        assert!(StreamingAggregator::new(&expressions[4..5], &names).is_ok());
+        let mixed = [SelectExpr::parse("account_id").unwrap()];
+        assert!(!is_streamable(&mixed));
+        assert!(StreamingAggregator::new(&mixed, &columns).is_err());
+    }
+}
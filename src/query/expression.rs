@@ -0,0 +1,579 @@
+use crate::durability::table::{ColumnDefinition, ColumnType, Row};
+
+use super::{
+    aggregate::AggregateFunction, parse_column_type, split_outside_quotes, unquote,
+    window::WindowFunction,
+};
+
+/// An expression in the column list of a `SELECT`, evaluated once per row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectExpr {
+    Column(String),
+    Literal(Vec<u8>),
+    Coalesce(Box<SelectExpr>, Box<SelectExpr>),
+    Cast {
+        expr: Box<SelectExpr>,
+        target_type: ColumnType,
+    },
+    Upper(Box<SelectExpr>),
+    Lower(Box<SelectExpr>),
+    Trim(Box<SelectExpr>),
+    Abs(Box<SelectExpr>),
+    Mod(Box<SelectExpr>, Box<SelectExpr>),
+    /// Computed over all the rows instead of one, see `window::project_windows`.
+    Window(WindowFunction),
+    /// Computed over a group of rows, see `aggregate::project_aggregates`.
+    Aggregate(AggregateFunction),
+}
+
+impl SelectExpr {
+    pub fn parse(expression: &str) -> Option<SelectExpr> {
+        let expression = expression.trim();
+        if expression.is_empty() {
+            return None;
+        }
+
+        if expression.starts_with('\'') {
+            let literal = unquote(expression);
+            if literal.len() == expression.len() {
+                return None;
+            }
+            return Some(SelectExpr::Literal(literal.as_bytes().to_vec()));
+        }
+
+        if expression.parse::<i64>().is_ok() {
+            return Some(SelectExpr::Literal(expression.as_bytes().to_vec()));
+        }
+
+        if let Some(cast) = expression
+            .strip_prefix("CAST(")
+            .and_then(|cast| cast.strip_suffix(')'))
+        {
+            let (expr, target_type) = cast.rsplit_once(" AS ")?;
+            return Some(SelectExpr::Cast {
+                expr: Box::new(SelectExpr::parse(expr)?),
+                target_type: parse_column_type(target_type.trim())?,
+            });
+        }
+
+        if let Some(function) = WindowFunction::parse(expression) {
+            return Some(SelectExpr::Window(function));
+        }
+
+        if let Some(function) = AggregateFunction::parse(expression) {
+            return Some(SelectExpr::Aggregate(function));
+        }
+
+        if let Some((function, arguments)) = parse_function_call(expression) {
+            let mut arguments = arguments.into_iter();
+            return match function {
+                "COALESCE" => {
+                    let value = arguments.next()?;
+                    let default = arguments.next()?;
+                    if arguments.next().is_some() {
+                        return None;
+                    }
+                    Some(SelectExpr::Coalesce(Box::new(value), Box::new(default)))
+                }
+                "UPPER" | "LOWER" | "TRIM" => {
+                    let value = Box::new(arguments.next()?);
+                    if arguments.next().is_some() {
+                        return None;
+                    }
+                    match function {
+                        "UPPER" => Some(SelectExpr::Upper(value)),
+                        "LOWER" => Some(SelectExpr::Lower(value)),
+                        _ => Some(SelectExpr::Trim(value)),
+                    }
+                }
+                "ABS" => {
+                    let value = arguments.next()?;
+                    if arguments.next().is_some() {
+                        return None;
+                    }
+                    Some(SelectExpr::Abs(Box::new(value)))
+                }
+                "MOD" => {
+                    let dividend = arguments.next()?;
+                    let divisor = arguments.next()?;
+                    if arguments.next().is_some() {
+                        return None;
+                    }
+                    Some(SelectExpr::Mod(Box::new(dividend), Box::new(divisor)))
+                }
+                _ => None,
+            };
+        }
+
+        if !expression
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return None;
+        }
+        Some(SelectExpr::Column(expression.to_string()))
+    }
+
+    /// Evaluates the expression against a row. Values keep the fixed width
+    /// zero padding of the column they came from, an all zero value is
+    /// treated as null.
+    pub fn evaluate(&self, row: &Row, columns: &[ColumnDefinition]) -> Result<Vec<u8>, String> {
+        match self {
+            SelectExpr::Column(name) => {
+                let position = columns.iter().position(|c| c.name_str() == name);
+                match position {
+                    Some(position) => Ok(row.data[position].clone()),
+                    None => Err(format!("Column {} does not exist", name)),
+                }
+            }
+            SelectExpr::Literal(value) => Ok(value.clone()),
+            SelectExpr::Coalesce(value, default) => {
+                let value = value.evaluate(row, columns)?;
+                if is_null(&value) {
+                    default.evaluate(row, columns)
+                } else {
+                    Ok(value)
+                }
+            }
+            SelectExpr::Cast { expr, target_type } => {
+                let value = expr.evaluate(row, columns)?;
+                Ok(cast(&value, &expr.value_type(columns), target_type))
+            }
+            SelectExpr::Upper(expr) => map_text(expr, "UPPER", row, columns, |text| {
+                text.split('\0').next().unwrap_or_default().to_uppercase()
+            }),
+            SelectExpr::Lower(expr) => map_text(expr, "LOWER", row, columns, |text| {
+                text.split('\0').next().unwrap_or_default().to_lowercase()
+            }),
+            SelectExpr::Trim(expr) => map_text(expr, "TRIM", row, columns, |text| {
+                text.trim_matches(|c: char| c.is_whitespace() || c == '\0')
+                    .to_string()
+            }),
+            SelectExpr::Abs(expr) => {
+                let column_type = expr.value_type(columns);
+                if column_type != ColumnType::Int && column_type != ColumnType::Float {
+                    return Err(format!("ABS expects a number, got {:?}", column_type));
+                }
+
+                let value = expr.evaluate(row, columns)?;
+                if is_null(&value) {
+                    return Ok(vec![]);
+                }
+                match column_type {
+                    ColumnType::Int => {
+                        let value = parse_number::<i64>(&value)?;
+                        let value = value
+                            .checked_abs()
+                            .ok_or_else(|| format!("ABS of {} overflowed", value))?;
+                        Ok(value.to_string().into_bytes())
+                    }
+                    ColumnType::Float => {
+                        Ok(parse_number::<f64>(&value)?.abs().to_string().into_bytes())
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            SelectExpr::Mod(dividend, divisor) => {
+                let value_type = self.value_type(columns);
+                for expr in [dividend, divisor] {
+                    let column_type = expr.value_type(columns);
+                    if column_type != ColumnType::Int && column_type != ColumnType::Float {
+                        return Err(format!("MOD expects numbers, got {:?}", column_type));
+                    }
+                }
+
+                let dividend = dividend.evaluate(row, columns)?;
+                let divisor = divisor.evaluate(row, columns)?;
+                if is_null(&dividend) || is_null(&divisor) {
+                    return Ok(vec![]);
+                }
+                let remainder = match value_type {
+                    ColumnType::Int => {
+                        let dividend = parse_number::<i64>(&dividend)?;
+                        let divisor = parse_number::<i64>(&divisor)?;
+                        (divisor != 0).then(|| dividend.wrapping_rem(divisor).to_string())
+                    }
+                    _ => {
+                        let dividend = parse_number::<f64>(&dividend)?;
+                        let divisor = parse_number::<f64>(&divisor)?;
+                        (divisor != 0.0).then(|| (dividend % divisor).to_string())
+                    }
+                };
+                match remainder {
+                    Some(remainder) => Ok(remainder.into_bytes()),
+                    None => {
+                        println!("Warning: MOD by zero, using null");
+                        Ok(vec![])
+                    }
+                }
+            }
+            SelectExpr::Window(_) => {
+                Err("Window functions are only allowed in the select list".to_string())
+            }
+            SelectExpr::Aggregate(_) => {
+                Err("Aggregate functions are only allowed in the select list".to_string())
+            }
+        }
+    }
+
+    /// The type of the values produced by the expression. Literals are Int
+    /// when they parse as one and Varchar otherwise.
+    pub fn value_type(&self, columns: &[ColumnDefinition]) -> ColumnType {
+        match self {
+            SelectExpr::Column(name) => columns
+                .iter()
+                .find(|c| c.name_str() == name)
+                .map_or(ColumnType::Varchar, |c| c.column_type.clone()),
+            SelectExpr::Literal(value) => {
+                let is_int = std::str::from_utf8(value).is_ok_and(|v| v.parse::<i64>().is_ok());
+                match is_int {
+                    true => ColumnType::Int,
+                    false => ColumnType::Varchar,
+                }
+            }
+            SelectExpr::Coalesce(value, _) => value.value_type(columns),
+            SelectExpr::Cast { target_type, .. } => target_type.clone(),
+            SelectExpr::Upper(_) | SelectExpr::Lower(_) | SelectExpr::Trim(_) => {
+                ColumnType::Varchar
+            }
+            SelectExpr::Abs(value) => value.value_type(columns),
+            SelectExpr::Mod(dividend, divisor) => {
+                match (dividend.value_type(columns), divisor.value_type(columns)) {
+                    (ColumnType::Float, _) | (_, ColumnType::Float) => ColumnType::Float,
+                    _ => ColumnType::Int,
+                }
+            }
+            SelectExpr::Window(_) => ColumnType::Int,
+            SelectExpr::Aggregate(function) => function.value_type(columns),
+        }
+    }
+}
+
+pub(super) fn parse_number<T: std::str::FromStr>(value: &[u8]) -> Result<T, String> {
+    let text = value.split(|b| *b == 0).next().unwrap_or_default();
+    let text = String::from_utf8_lossy(text);
+    text.trim()
+        .parse::<T>()
+        .map_err(|_| format!("{} is not a number", text))
+}
+
+/// Applies a string function to a Varchar value. The result is padded back
+/// to the width of the input so it keeps the declared length of its column.
+fn map_text(
+    expr: &SelectExpr,
+    function: &str,
+    row: &Row,
+    columns: &[ColumnDefinition],
+    f: impl Fn(&str) -> String,
+) -> Result<Vec<u8>, String> {
+    if expr.value_type(columns) != ColumnType::Varchar {
+        return Err(format!("{} expects a Varchar argument", function));
+    }
+
+    let value = expr.evaluate(row, columns)?;
+    let text = std::str::from_utf8(&value).map_err(|e| e.to_string())?;
+    let mut result = f(text).into_bytes();
+    if result.len() < value.len() {
+        result.resize(value.len(), 0);
+    }
+    Ok(result)
+}
+
+/// Parses a `YYYY-MM-DD` date into its year, month and day.
+pub fn parse_date(value: &str) -> Option<(i64, u32, u32)> {
+    let mut parts = value.trim().splitn(3, '-');
+    let year = parts.next()?;
+    let month = parts.next()?;
+    let day = parts.next()?;
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    date_from_parts(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+}
+
+fn date_from_parts(year: i64, month: u32, day: u32) -> Option<(i64, u32, u32)> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some((year, month, day))
+}
+
+/// Converts a value between column types. Values that cannot be converted
+/// become null and a warning is logged instead of failing the query.
+fn cast(value: &[u8], from: &ColumnType, to: &ColumnType) -> Vec<u8> {
+    if is_null(value) {
+        return vec![];
+    }
+    // Binary values are converted through their text.
+    match (from, to) {
+        (from, to) if from == to => return value.to_vec(),
+        (from, to) if from.is_binary() => {
+            return cast(from.format(value).as_bytes(), &ColumnType::Varchar, to);
+        }
+        (_, to) if to.is_binary() => {
+            return to.encode(value).unwrap_or_else(|| {
+                println!(
+                    "Warning: cannot cast {:?} from {:?} to {:?}, using null",
+                    String::from_utf8_lossy(value),
+                    from,
+                    to
+                );
+                vec![]
+            })
+        }
+        _ => {}
+    }
+
+    let text = value.split(|b| *b == 0).next().unwrap_or_default();
+    let text = String::from_utf8_lossy(text);
+    let text = text.trim();
+    let converted = match (from, to) {
+        (from, to) if from == to => Some(text.to_string()),
+        (_, ColumnType::Varchar) => Some(text.to_string()),
+        (ColumnType::Varchar, ColumnType::Int) => text.parse::<i64>().ok().map(|v| v.to_string()),
+        (ColumnType::Varchar, ColumnType::Float) => text.parse::<f64>().ok().map(|v| v.to_string()),
+        (ColumnType::Date, ColumnType::Int) => parse_date(text)
+            .map(|(year, month, day)| (year * 10000 + month as i64 * 100 + day as i64).to_string()),
+        (ColumnType::Int, ColumnType::Date) => text
+            .parse::<i64>()
+            .ok()
+            .filter(|v| *v >= 0)
+            .and_then(|v| date_from_parts(v / 10000, (v / 100 % 100) as u32, (v % 100) as u32))
+            .map(|(year, month, day)| format!("{:04}-{:02}-{:02}", year, month, day)),
+        _ => None,
+    };
+
+    match converted {
+        Some(converted) => converted.into_bytes(),
+        None => {
+            println!(
+                "Warning: cannot cast {:?} from {:?} to {:?}, using null",
+                text, from, to
+            );
+            vec![]
+        }
+    }
+}
+
+pub fn is_null(value: &[u8]) -> bool {
+    value.iter().all(|b| *b == 0)
+}
+
+/// Splits `NAME(arg, ...)` into the function name and its parsed arguments.
+fn parse_function_call(expression: &str) -> Option<(&str, Vec<SelectExpr>)> {
+    let open = expression.find('(')?;
+    let function = expression[..open].trim();
+    if function.is_empty() || !function.chars().all(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+
+    let arguments = expression[open + 1..].strip_suffix(')')?;
+    let arguments = split_outside_quotes(arguments, ',')
+        .iter()
+        .map(|argument| SelectExpr::parse(argument))
+        .collect::<Option<Vec<SelectExpr>>>()?;
+    Some((function, arguments))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cast, SelectExpr};
+    use crate::durability::table::{ColumnDefinition, ColumnType, Row};
+
+    fn columns() -> Vec<ColumnDefinition> {
+        vec![
+            ColumnDefinition::new("id".to_string(), ColumnType::Int, 11),
+            ColumnDefinition::new("email".to_string(), ColumnType::Varchar, 16),
+        ]
+    }
+
+    #[test]
+    fn parse_select_expr() {
+        assert_eq!(
+            SelectExpr::parse("email"),
+            Some(SelectExpr::Column("email".to_string()))
+        );
+        assert_eq!(
+            SelectExpr::parse("'no-email'"),
+            Some(SelectExpr::Literal(b"no-email".to_vec()))
+        );
+        assert_eq!(
+            SelectExpr::parse("COALESCE(email, 'no-email')"),
+            Some(SelectExpr::Coalesce(
+                Box::new(SelectExpr::Column("email".to_string())),
+                Box::new(SelectExpr::Literal(b"no-email".to_vec()))
+            ))
+        );
+        assert_eq!(SelectExpr::parse("COALESCE(email)"), None);
+        assert_eq!(SelectExpr::parse("UNKNOWN(email)"), None);
+        assert_eq!(SelectExpr::parse("'unterminated"), None);
+    }
+
+    #[test]
+    fn evaluate_coalesce() {
+        let expr = SelectExpr::parse("COALESCE(email, 'no-email')").unwrap();
+
+        let mut email = b"a@b.c".to_vec();
+        email.resize(16, 0);
+        let row = Row {
+            data: vec![b"1".to_vec(), email.clone()],
+        };
+        assert_eq!(expr.evaluate(&row, &columns()).unwrap(), email);
+
+        let row = Row {
+            data: vec![b"2".to_vec(), vec![0; 16]],
+        };
+        assert_eq!(expr.evaluate(&row, &columns()).unwrap(), b"no-email");
+
+        let expr = SelectExpr::parse("COALESCE(missing, 'x')").unwrap();
+        assert!(expr.evaluate(&row, &columns()).is_err());
+    }
+
+    #[test]
+    fn parse_string_functions() {
+        assert_eq!(
+            SelectExpr::parse("UPPER(TRIM(email))"),
+            Some(SelectExpr::Upper(Box::new(SelectExpr::Trim(Box::new(
+                SelectExpr::Column("email".to_string())
+            )))))
+        );
+        assert_eq!(
+            SelectExpr::parse("LOWER('ABC')"),
+            Some(SelectExpr::Lower(Box::new(SelectExpr::Literal(
+                b"ABC".to_vec()
+            ))))
+        );
+        assert_eq!(SelectExpr::parse("UPPER(email, id)"), None);
+        assert_eq!(SelectExpr::parse("TRIM()"), None);
+    }
+
+    #[test]
+    fn evaluate_string_functions() {
+        let mut email = b"  Ab\xc3\xa9 ".to_vec();
+        email.resize(16, 0);
+        let row = Row {
+            data: vec![b"1".to_vec(), email],
+        };
+
+        let evaluate = |expression: &str| {
+            let value = SelectExpr::parse(expression)
+                .unwrap()
+                .evaluate(&row, &columns())
+                .unwrap();
+            assert_eq!(value.len(), 16);
+            String::from_utf8(value)
+                .unwrap()
+                .trim_end_matches('\0')
+                .to_string()
+        };
+        assert_eq!(evaluate("UPPER(email)"), "  AB\u{c9} ");
+        assert_eq!(evaluate("LOWER(email)"), "  ab\u{e9} ");
+        assert_eq!(evaluate("TRIM(email)"), "Ab\u{e9}");
+        assert_eq!(evaluate("UPPER(TRIM(email))"), "AB\u{c9}");
+
+        let expr = SelectExpr::parse("UPPER(id)").unwrap();
+        assert!(expr.evaluate(&row, &columns()).is_err());
+    }
+
+    #[test]
+    fn parse_math_functions() {
+        assert_eq!(
+            SelectExpr::parse("MOD(id, 10)"),
+            Some(SelectExpr::Mod(
+                Box::new(SelectExpr::Column("id".to_string())),
+                Box::new(SelectExpr::Literal(b"10".to_vec()))
+            ))
+        );
+        assert_eq!(
+            SelectExpr::parse("ABS(MOD(id, 10))"),
+            Some(SelectExpr::Abs(Box::new(SelectExpr::Mod(
+                Box::new(SelectExpr::Column("id".to_string())),
+                Box::new(SelectExpr::Literal(b"10".to_vec()))
+            ))))
+        );
+        assert_eq!(SelectExpr::parse("MOD(id)"), None);
+        assert_eq!(SelectExpr::parse("ABS(id, 1)"), None);
+    }
+
+    #[test]
+    fn evaluate_math_functions() {
+        let columns = vec![
+            ColumnDefinition::new("balance".to_string(), ColumnType::Int, 11),
+            ColumnDefinition::new("rate".to_string(), ColumnType::Float, 11),
+            ColumnDefinition::new("name".to_string(), ColumnType::Varchar, 8),
+        ];
+        let row = Row {
+            data: vec![b"-17\0\0".to_vec(), b"-2.5\0".to_vec(), b"bob".to_vec()],
+        };
+        let evaluate = |expression: &str| {
+            SelectExpr::parse(expression)
+                .unwrap()
+                .evaluate(&row, &columns)
+        };
+
+        assert_eq!(evaluate("ABS(balance)").unwrap(), b"17");
+        assert_eq!(evaluate("ABS(rate)").unwrap(), b"2.5");
+        assert_eq!(evaluate("MOD(balance, 5)").unwrap(), b"-2");
+        assert_eq!(evaluate("MOD(rate, 2)").unwrap(), b"-0.5");
+        assert!(evaluate("ABS(name)").is_err());
+        assert!(evaluate("MOD(name, 2)").is_err());
+    }
+
+    #[test]
+    fn mod_by_zero_produces_null() {
+        let columns = vec![ColumnDefinition::new("id".to_string(), ColumnType::Int, 11)];
+        let row = Row {
+            data: vec![b"7".to_vec()],
+        };
+        let expr = SelectExpr::parse("MOD(id, 0)").unwrap();
+        assert_eq!(expr.evaluate(&row, &columns).unwrap(), b"");
+        let expr = SelectExpr::parse("MOD(CAST(id AS FLOAT), 0)").unwrap();
+        assert_eq!(expr.evaluate(&row, &columns).unwrap(), b"");
+    }
+
+    #[test]
+    fn parse_cast() {
+        assert_eq!(
+            SelectExpr::parse("CAST(amount AS VARCHAR)"),
+            Some(SelectExpr::Cast {
+                expr: Box::new(SelectExpr::Column("amount".to_string())),
+                target_type: ColumnType::Varchar,
+            })
+        );
+        assert_eq!(SelectExpr::parse("CAST(amount AS BLOB)"), None);
+        assert_eq!(SelectExpr::parse("CAST(amount)"), None);
+    }
+
+    #[test]
+    fn cast_between_types() {
+        use ColumnType::*;
+
+        assert_eq!(cast(b"42\0\0", &Int, &Varchar), b"42");
+        assert_eq!(cast(b" 42", &Varchar, &Int), b"42");
+        assert_eq!(cast(b"1.5", &Float, &Varchar), b"1.5");
+        assert_eq!(cast(b"2.50", &Varchar, &Float), b"2.5");
+        assert_eq!(cast(b"2023-01-31", &Date, &Int), b"20230131");
+        assert_eq!(cast(b"20230131", &Int, &Date), b"2023-01-31");
+        assert_eq!(cast(b"\0\0", &Varchar, &Int), b"");
+    }
+
+    #[test]
+    fn invalid_cast_produces_null() {
+        use ColumnType::*;
+
+        assert_eq!(cast(b"alice", &Varchar, &Int), b"");
+        assert_eq!(cast(b"alice", &Varchar, &Float), b"");
+        assert_eq!(cast(b"20231341", &Int, &Date), b"");
+        assert_eq!(cast(b"2023-01-31", &Date, &Float), b"");
+    }
+
+    #[test]
+    fn evaluate_cast() {
+        let expr = SelectExpr::parse("CAST(id AS VARCHAR)").unwrap();
+        let row = Row {
+            data: vec![b"7\0\0".to_vec(), vec![0; 16]],
+        };
+        assert_eq!(expr.evaluate(&row, &columns()).unwrap(), b"7");
+        assert_eq!(expr.value_type(&columns()), ColumnType::Varchar);
+    }
+}
@@ -1,32 +1,144 @@
-use std::{
-    fs::File,
-    io::{BufRead, BufReader, Bytes, Read},
+use std::io::{BufRead, BufReader, Read};
+
+use crate::concurrency::TableLock;
+use crate::durability::{
+    grant::GrantOperation,
+    index::IndexKind,
+    table::{ColumnDefinition, ColumnType, CURRENT_TIMESTAMP},
+    trigger::TriggerEvent,
 };
+use crate::logging::DEFAULT_LAST_LINES;
+
+pub mod aggregate;
+pub mod expression;
+pub mod predicate;
+pub mod prepared;
+pub mod window;
 
-#[derive(Debug)]
+use expression::SelectExpr;
+use predicate::Predicate;
+
+#[derive(Debug, Clone)]
 pub enum Scope {
     All,
+    Expressions(Vec<SelectExpr>),
+    /// A select list with a `GROUP BY` on the columns that follow it.
+    Grouped(Vec<SelectExpr>, Vec<String>),
+    Invalid,
+}
+
+impl From<&mut Vec<u8>> for Scope {
+    fn from(query: &mut Vec<u8>) -> Self {
+        let expressions = pop_until_keyword(query, "FROM");
+        if expressions.is_empty() || expressions == "*" {
+            return Scope::All;
+        }
+
+        let expressions = split_outside_quotes(&expressions, ',')
+            .iter()
+            .map(|expression| SelectExpr::parse(expression))
+            .collect::<Option<Vec<SelectExpr>>>();
+        match expressions {
+            Some(expressions) => Scope::Expressions(expressions),
+            None => Scope::Invalid,
+        }
+    }
+}
+
+impl Scope {
+    /// The scope grouped by the comma separated `columns` of a `GROUP BY`,
+    /// which needs a select list.
+    fn grouped(self, columns: &str) -> Scope {
+        let columns = split_outside_quotes(columns, ',')
+            .iter()
+            .map(|column| match SelectExpr::parse(column)? {
+                SelectExpr::Column(column) => Some(column),
+                _ => None,
+            })
+            .collect::<Option<Vec<String>>>();
+        match (self, columns) {
+            (Scope::Expressions(expressions), Some(columns)) => {
+                Scope::Grouped(expressions, columns)
+            }
+            _ => Scope::Invalid,
+        }
+    }
+}
+
+/// The optional `WHERE` clause of a `SELECT`, predicates joined by `AND`.
+#[derive(Debug, Clone)]
+pub enum Filter {
+    All,
+    Where(Vec<Predicate>),
+    Invalid,
+}
+
+impl From<&mut Vec<u8>> for Filter {
+    fn from(query: &mut Vec<u8>) -> Self {
+        match pop_word(query).as_str() {
+            "" => Filter::All,
+            "WHERE" => {
+                let clause = String::from_utf8_lossy(query).to_string();
+                query.clear();
+                match split_conjunction(&clause)
+                    .into_iter()
+                    .map(Predicate::parse)
+                    .collect()
+                {
+                    Some(predicates) => Filter::Where(predicates),
+                    None => Filter::Invalid,
+                }
+            }
+            _ => Filter::Invalid,
+        }
+    }
+}
+
+/// Splits a clause on the `AND`s outside of quotes.
+fn split_conjunction(clause: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (i, c) in clause.char_indices() {
+        if c == '\'' {
+            in_quotes = !in_quotes;
+        } else if !in_quotes && clause[i..].starts_with(" AND ") {
+            parts.push(&clause[start..i]);
+            start = i + " AND ".len();
+        }
+    }
+    parts.push(&clause[start..]);
+    parts
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum QuerySource {
     Table(String),
     IntoTable(String),
+    /// A common table expression named by the `WITH` clause of the query.
+    Cte(String),
     Invalid,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ColumnList {
     Columns(Vec<String>),
     Invalid,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ValueList {
     Values(Vec<Vec<Vec<u8>>>),
     Invalid,
 }
 
+/// A value a `MERGE` writes, a column of the source row or a literal.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeValue {
+    Source(String),
+    Literal(Vec<u8>),
+}
+
 impl From<&mut Vec<u8>> for ColumnList {
     fn from(query: &mut Vec<u8>) -> Self {
         let columns = pop_string_inside_parenthesis(query);
@@ -39,10 +151,602 @@ impl From<&mut Vec<u8>> for ColumnList {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub enum ColumnDefinitionList {
+    Definitions(Vec<ColumnDefinition>),
+    Invalid,
+}
+
+impl From<&mut Vec<u8>> for ColumnDefinitionList {
+    fn from(query: &mut Vec<u8>) -> Self {
+        let definitions = pop_string_inside_balanced_parenthesis(query);
+        let mut columns = vec![];
+        let mut foreign_keys = vec![];
+        for definition in split_outside_quotes(&definitions, ',') {
+            if definition.starts_with("FOREIGN ") {
+                foreign_keys.push(definition);
+                continue;
+            }
+            match parse_column_definition(&definition) {
+                Some(column) => columns.push(column),
+                None => return ColumnDefinitionList::Invalid,
+            }
+        }
+        for foreign_key in foreign_keys {
+            if parse_foreign_key(&foreign_key, &mut columns).is_none() {
+                return ColumnDefinitionList::Invalid;
+            }
+        }
+        let primary_keys = columns.iter().filter(|column| column.primary_key).count();
+        if columns.is_empty() || columns.len() > 255 || primary_keys > 1 {
+            return ColumnDefinitionList::Invalid;
+        }
+        ColumnDefinitionList::Definitions(columns)
+    }
+}
+
+fn parse_column_definition(definition: &str) -> Option<ColumnDefinition> {
+    let tokens = split_outside_quotes(definition, ' ');
+    let mut tokens = tokens.iter().filter(|token| !token.is_empty());
+
+    let name = tokens.next()?;
+    let column_type = parse_column_type(tokens.next()?)?;
+    let length: u64 = match column_type.is_binary() {
+        true => 8,
+        false => tokens.next()?.parse().ok()?,
+    };
+    if name.len() > 63 || length == 0 {
+        return None;
+    }
+
+    let mut column = ColumnDefinition::new(name.to_string(), column_type, length);
+    while let Some(token) = tokens.next() {
+        match token.as_str() {
+            "DEFAULT" if column.column_type.is_binary() => {
+                let value = tokens.next()?;
+                // It would be frozen at the time of the CREATE TABLE.
+                if value == CURRENT_TIMESTAMP {
+                    return None;
+                }
+                column.default_value = Some(column.column_type.encode(value.as_bytes())?);
+            }
+            "DEFAULT" => {
+                let value = unquote(tokens.next()?);
+                if value.len() as u64 > length {
+                    return None;
+                }
+                let valid = match column.column_type {
+                    ColumnType::Int => value.parse::<i64>().is_ok(),
+                    ColumnType::Float => value.parse::<f64>().is_ok(),
+                    ColumnType::Date => expression::parse_date(value).is_some(),
+                    ColumnType::Varchar => true,
+                    ColumnType::Timestamp | ColumnType::Decimal(..) => unreachable!(),
+                };
+                if !valid {
+                    return None;
+                }
+                column.default_value = Some(value.as_bytes().to_vec());
+            }
+            "UNIQUE" => column.unique = true,
+            "DICTIONARY" => column.dictionary_encoded = true,
+            "CHECK" => {
+                let expr = unparenthesize(tokens.next()?)?;
+                let predicate = Predicate::parse(expr)?;
+                if predicate.expr != SelectExpr::Column(name.to_string()) || expr.len() > 128 {
+                    return None;
+                }
+                let mut check_expr = [0; 128];
+                check_expr[..expr.len()].copy_from_slice(expr.as_bytes());
+                column.check_expr = Some(check_expr);
+            }
+            "PRIMARY" => {
+                if tokens.next()? != "KEY" {
+                    return None;
+                }
+                column.primary_key = true;
+            }
+            _ => return None,
+        }
+    }
+    Some(column)
+}
+
+fn parse_column_type(column_type: &str) -> Option<ColumnType> {
+    match column_type {
+        "INT" => Some(ColumnType::Int),
+        "VARCHAR" => Some(ColumnType::Varchar),
+        "FLOAT" => Some(ColumnType::Float),
+        "DATE" => Some(ColumnType::Date),
+        "TIMESTAMP" => Some(ColumnType::Timestamp),
+        column_type => {
+            let arguments = unparenthesize(column_type.strip_prefix("DECIMAL")?.trim_start())?;
+            let (precision, scale) = arguments.split_once(',')?;
+            Some(ColumnType::Decimal(
+                precision.trim().parse().ok()?,
+                scale.trim().parse().ok()?,
+            ))
+        }
+    }
+}
+
+fn parse_foreign_key(definition: &str, columns: &mut [ColumnDefinition]) -> Option<()> {
+    let tokens = split_outside_quotes(definition, ' ');
+    let mut tokens = tokens.iter().filter(|token| !token.is_empty());
+    if tokens.next()? != "FOREIGN" || tokens.next()? != "KEY" {
+        return None;
+    }
+    let column_name = unparenthesize(tokens.next()?)?;
+    if tokens.next()? != "REFERENCES" {
+        return None;
+    }
+    let referenced_table = tokens.next()?.to_string();
+    let referenced_column = unparenthesize(tokens.next()?)?.to_string();
+    if tokens.next().is_some() {
+        return None;
+    }
+
+    let column = columns
+        .iter_mut()
+        .find(|column| column.name_str() == column_name)?;
+    column.references = Some((referenced_table, referenced_column));
+    Some(())
+}
+
+fn unparenthesize(value: &str) -> Option<&str> {
+    value
+        .strip_prefix('(')
+        .and_then(|value| value.strip_suffix(')'))
+        .map(|value| value.trim())
+}
+
+pub fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+        .unwrap_or(value)
+}
+
+/// The query with its literals replaced by `?`, the same for queries that
+/// only differ in their literals. Single quoted strings and numbers not part
+/// of a name are literals, runs of whitespace become a single space.
+pub fn fingerprint_query(query: &str) -> String {
+    split_literals(query).0
+}
+
+/// The fingerprint of the query along with the literals it replaced, in the
+/// order they appear.
+pub fn split_literals(query: &str) -> (String, Vec<String>) {
+    let chars: Vec<char> = query.trim().chars().collect();
+    let mut fingerprint = String::new();
+    let mut literals = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let in_name = fingerprint.ends_with(|c: char| c.is_alphanumeric() || c == '_');
+        match chars[i] {
+            '\'' => {
+                i += 1;
+                while i < chars.len() {
+                    i += 1;
+                    if chars[i - 1] == '\'' {
+                        // A doubled quote is part of the string.
+                        if chars.get(i) != Some(&'\'') {
+                            break;
+                        }
+                        i += 1;
+                    }
+                }
+            }
+            c if c.is_ascii_digit() && !in_name => {
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+            }
+            c if c.is_whitespace() => {
+                while i < chars.len() && chars[i].is_whitespace() {
+                    i += 1;
+                }
+                fingerprint.push(' ');
+                continue;
+            }
+            c => {
+                fingerprint.push(c);
+                i += 1;
+                continue;
+            }
+        }
+        literals.push(chars[start..i].iter().collect());
+        fingerprint.push('?');
+    }
+    (fingerprint, literals)
+}
+
+pub fn split_outside_quotes(value: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![];
+    let mut part = String::new();
+    let mut in_quotes = false;
+    let mut depth = 0;
+    for c in value.chars() {
+        match c {
+            '\'' => in_quotes = !in_quotes,
+            '(' if !in_quotes => depth += 1,
+            ')' if !in_quotes => depth -= 1,
+            _ if c == separator && !in_quotes && depth == 0 => {
+                parts.push(part.trim().to_string());
+                part = String::new();
+                continue;
+            }
+            _ => {}
+        }
+        part.push(c);
+    }
+    parts.push(part.trim().to_string());
+    parts
+}
+
+#[derive(Debug, Clone)]
 pub enum Query {
-    Select(QuerySource, Scope),
-    Insert(QuerySource, ColumnList, ValueList),
+    Select(QuerySource, Scope, Filter),
+    /// The last field is the `RETURNING` clause, the columns of the inserted
+    /// rows to return instead of a count.
+    Insert(QuerySource, ColumnList, ValueList, Option<Scope>),
+    /// `INSERT OR REPLACE`, replacing the rows with the same primary key.
+    Upsert(QuerySource, ColumnList, ValueList),
+    /// `MERGE INTO target USING source ON target.a = source.b`, updates the
+    /// target rows a source row matches and inserts the source rows matching
+    /// none.
+    Merge {
+        target: String,
+        source: String,
+        /// The target column and the source column holding the same value.
+        join_condition: (String, String),
+        /// `WHEN MATCHED THEN UPDATE SET`, the target columns and their new
+        /// values.
+        matched_action: Option<Vec<(String, MergeValue)>>,
+        /// `WHEN NOT MATCHED THEN INSERT`, the target columns and their
+        /// values.
+        not_matched_action: Option<(Vec<String>, Vec<MergeValue>)>,
+    },
+    /// `INSERT INTO dest_table SELECT * FROM source ...`, appends the rows
+    /// the select reads as they are stored.
+    InsertSelect {
+        dest_table: String,
+        source_query: Box<Query>,
+    },
+    AlterTableRenameColumn {
+        table: String,
+        old_name: String,
+        new_name: String,
+    },
+    CreateTable {
+        table: String,
+        columns: ColumnDefinitionList,
+        /// `IF NOT EXISTS`, an existing table is left alone instead of
+        /// failing the query.
+        if_not_exists: bool,
+    },
+    DropTable {
+        table: String,
+        /// `IF EXISTS`, a missing table is not an error.
+        if_exists: bool,
+    },
+    RenameTable {
+        from: String,
+        to: String,
+    },
+    CreateSequence {
+        name: String,
+        start: i64,
+        increment: i64,
+    },
+    DropSequence(String),
+    AlterSequenceRestart {
+        name: String,
+        value: Option<i64>,
+    },
+    Begin,
+    Commit,
+    Rollback,
+    Savepoint(String),
+    RollbackToSavepoint(String),
+    ReleaseSavepoint(String),
+    Backup(String),
+    Restore(String),
+    /// `RESTORE FROM WAL AT LSN n`.
+    RestoreFromWal {
+        lsn: u64,
+    },
+    Set {
+        name: String,
+        value: String,
+    },
+    /// `COPY table FROM STDIN BINARY`, the rows follow the query on the
+    /// input stream.
+    CopyBinary(String),
+    /// `CREATE PROCEDURE name AS 'lua code'`, quotes inside the code are
+    /// doubled.
+    CreateProcedure {
+        name: String,
+        body: String,
+    },
+    /// `left UNION [ALL] right`, `UNION` drops duplicate rows. Chains of set
+    /// operations are evaluated left to right, each nesting on the left.
+    Union {
+        left: Box<Query>,
+        right: Box<Query>,
+        distinct: bool,
+    },
+    /// `left INTERSECT right`, the distinct rows of `left` also in `right`.
+    /// There is no `INTERSECT ALL` keeping the duplicates yet.
+    Intersect {
+        left: Box<Query>,
+        right: Box<Query>,
+    },
+    /// `left EXCEPT right`, the distinct rows of `left` not in `right`.
+    /// There is no `EXCEPT ALL` keeping the duplicates yet.
+    Except {
+        left: Box<Query>,
+        right: Box<Query>,
+    },
+    /// `CREATE VIEW name AS SELECT ...`, the query text is stored as is and
+    /// run whenever the view is read.
+    CreateView {
+        name: String,
+        query: String,
+    },
+    DropView(String),
+    ShowViews,
+    /// `SHOW PROCESSLIST`, the connections of the server.
+    ShowProcesslist,
+    /// `SHOW CONFIG`, the settings of the database configuration file.
+    ShowConfig,
+    /// `FLUSH QUERY LOG`
+    FlushQueryLog,
+    /// `SHOW QUERY LOG [LAST n]`, the last lines of the query log.
+    ShowQueryLog(usize),
+    /// `CLEAR QUERY LOG`
+    ClearQueryLog,
+    /// `KILL connection_id`
+    Kill(u64),
+    /// `LOCK TABLE name READ|WRITE`, held by the connection until it unlocks
+    /// the table or disconnects.
+    LockTable {
+        table: String,
+        mode: TableLock,
+    },
+    /// `UNLOCK TABLE name`
+    UnlockTable(String),
+    /// `CREATE MATERIALIZED VIEW name AS SELECT ...`, the result is stored
+    /// when the view is created and read instead of running the query.
+    CreateMaterializedView {
+        name: String,
+        query: String,
+    },
+    /// `REFRESH MATERIALIZED VIEW name`, runs the query again and replaces
+    /// the stored result.
+    RefreshMaterializedView(String),
+    /// `CREATE TRIGGER name AFTER INSERT|UPDATE|DELETE ON table EXECUTE
+    /// 'sql'`, the body is inline SQL or the path of a `.sql` file. Quotes
+    /// inside the body are doubled.
+    CreateTrigger {
+        name: String,
+        event: TriggerEvent,
+        table: String,
+        body: String,
+    },
+    DropTrigger {
+        name: String,
+    },
+    /// `CREATE USER name WITH PASSWORD 'password'`, adds the user to the
+    /// auth file.
+    CreateUser {
+        name: String,
+        password: String,
+    },
+    DropUser(String),
+    /// `GRANT SELECT|INSERT (id, name) ON table TO user`, every column
+    /// without the list.
+    Grant {
+        operation: GrantOperation,
+        columns: Vec<String>,
+        table: String,
+        user: String,
+    },
+    /// `REVOKE SELECT|INSERT ON table FROM user`, takes back every column.
+    Revoke {
+        operation: GrantOperation,
+        table: String,
+        user: String,
+    },
+    /// `SHOW GRANTS FOR user`
+    ShowGrants(String),
+    /// `PREPARE name AS 'sql'`, parses the query for `EXECUTE` to run with
+    /// the `?` placeholders filled in. Quotes inside it are doubled.
+    Prepare {
+        name: String,
+        sql: String,
+    },
+    /// `EXECUTE name (42, 'Ann')`, the literals for the placeholders in
+    /// order.
+    Execute {
+        name: String,
+        args: Vec<String>,
+    },
+    /// `WITH name AS (SELECT ...) query`, the rows of the inner select are
+    /// read by `query` through `QuerySource::Cte(name)`.
+    With {
+        name: String,
+        cte: Box<Query>,
+        query: Box<Query>,
+    },
+    /// `CALL name(arg, 'arg')`, every argument is passed to the procedure as
+    /// a string.
+    CallProcedure {
+        name: String,
+        args: Vec<String>,
+    },
+    /// `COPY table TO 'path' BINARY`
+    CopyBinaryTo {
+        table: String,
+        path: String,
+    },
+    /// `COPY table FROM 'path' BINARY`
+    CopyBinaryFrom {
+        table: String,
+        path: String,
+    },
+    /// `COPY TABLE source TO destination [WHERE ...]`, a new table holding
+    /// the rows of `source` matching the filter.
+    CopyTable {
+        source: String,
+        destination: String,
+        filter: Filter,
+    },
+    Checkpoint,
+    /// `REFRESH`, re-reads the header of the open table from disk to see the
+    /// rows other processes wrote.
+    Refresh,
+    /// `SHOW name`, `None` for `SHOW ALL`.
+    Show(Option<String>),
+    /// `SHOW TABLE STATS name`
+    ShowTableStats(String),
+    /// `SHOW CREATE TABLE name`, the statement that recreates the table.
+    ShowCreateTable(String),
+    Analyze(String),
+    /// `ANALYZE COLUMN table.column`, analyzes the table and reports the
+    /// distinct values of the column against its rows.
+    AnalyzeColumn {
+        table: String,
+        column: String,
+    },
+    /// An index over one column or, keyed by their values in order, several.
+    /// A partial index only holds the rows matching `predicate`.
+    CreateIndex {
+        name: String,
+        table: String,
+        columns: Vec<String>,
+        kind: IndexKind,
+        predicate: Option<String>,
+    },
+    Explain(Box<Query>),
+    /// `EXPLAIN ANALYZE query`, runs the query and returns its rows followed
+    /// by the plan and what running it took.
+    ExplainAnalyze(Box<Query>),
+    /// `EXPLAIN FORMAT=JSON query`, the plan as a single JSON row.
+    ExplainJson(Box<Query>),
+    /// Rebuilds an index from a scan of the table.
+    RebuildIndex {
+        index_name: String,
+        table: String,
+    },
+    RebuildAllIndexes(String),
+    /// `COMPRESS COLUMN column ON table`, delta encodes an Int column and
+    /// rewrites the table with it.
+    CompressColumn {
+        table: String,
+        column: String,
+    },
+    /// `DEBUG DUMP TABLE table`, the bytes of the table file in hex.
+    DebugDump(String),
+    /// `IMPORT SCHEMA FROM 'schema.sql'`, runs the CREATE TABLE, CREATE
+    /// INDEX and CREATE VIEW statements of the file.
+    ImportSchema(String),
+    /// `TABLE CHECKSUM table`, a CRC32 of the rows of the table.
+    Checksum(String),
+    /// `REINDEX ALL`, rebuilds every index of every table of the database.
+    ReindexAll,
+    /// `REINDEX table`, rebuilds every index of the table, open or not.
+    Reindex(String),
+    /// `COMPACT DATABASE`, vacuums every table of the database.
+    CompactDatabase,
+    /// `EXPLAIN INDEXES FOR table`, the indexes of the table read from their
+    /// files.
+    ExplainIndexes(String),
+    /// `SELECT [columns] INTO TEMP TABLE dest_table FROM ...`, stores the
+    /// rows of the select in a table of the connection.
+    SelectIntoTemp {
+        dest_table: String,
+        source_query: Box<Query>,
+    },
+    /// `DROP TEMP TABLE name`.
+    DropTempTable(String),
+    /// `SHOW DISK USAGE`, the bytes the files of every table take.
+    ShowDiskUsage,
+}
+
+impl Query {
+    /// Whether the query reads or writes a file at a path it names, or dumps
+    /// a table file as it is on disk. Procedures may not run these.
+    pub fn touches_files(&self) -> bool {
+        match self {
+            Query::Backup(_)
+            | Query::Restore(_)
+            | Query::CopyBinaryTo { .. }
+            | Query::CopyBinaryFrom { .. }
+            | Query::ImportSchema(_)
+            | Query::DebugDump(_) => true,
+            Query::CreateTrigger { body, .. } => body.ends_with(".sql"),
+            Query::Explain(query) | Query::ExplainAnalyze(query) | Query::ExplainJson(query) => {
+                query.touches_files()
+            }
+            _ => false,
+        }
+    }
+
+    /// The tables the query reads, `Shared`, or writes, `Exclusive`, which
+    /// the `LOCK TABLE` of another connection can hold it back from.
+    pub fn table_accesses(&self) -> Vec<(&str, TableLock)> {
+        match self {
+            Query::Select(QuerySource::Table(table), ..)
+            | Query::CopyBinaryTo { table, .. }
+            | Query::DebugDump(table)
+            | Query::Checksum(table) => vec![(table, TableLock::Shared)],
+            Query::CopyTable {
+                source,
+                destination,
+                ..
+            } => vec![
+                (source, TableLock::Shared),
+                (destination, TableLock::Exclusive),
+            ],
+            Query::Insert(QuerySource::IntoTable(table), ..)
+            | Query::Upsert(QuerySource::IntoTable(table), ..)
+            | Query::CopyBinary(table)
+            | Query::CopyBinaryFrom { table, .. }
+            | Query::DropTable { table, .. }
+            | Query::RenameTable { from: table, .. }
+            | Query::CompressColumn { table, .. } => vec![(table, TableLock::Exclusive)],
+            Query::Union { left, right, .. }
+            | Query::Intersect { left, right }
+            | Query::Except { left, right } => {
+                let mut accesses = left.table_accesses();
+                accesses.extend(right.table_accesses());
+                accesses
+            }
+            Query::With { cte, query, .. } => {
+                let mut accesses = cte.table_accesses();
+                accesses.extend(query.table_accesses());
+                accesses
+            }
+            Query::Merge { target, source, .. } => {
+                vec![(source, TableLock::Shared), (target, TableLock::Exclusive)]
+            }
+            Query::InsertSelect {
+                dest_table,
+                source_query,
+            } => {
+                let mut accesses = source_query.table_accesses();
+                accesses.push((dest_table, TableLock::Exclusive));
+                accesses
+            }
+            Query::ExplainAnalyze(query)
+            | Query::SelectIntoTemp {
+                source_query: query,
+                ..
+            } => query.table_accesses(),
+            _ => vec![],
+        }
+    }
 }
 
 impl From<&mut Vec<u8>> for ValueList {
@@ -53,21 +757,88 @@ impl From<&mut Vec<u8>> for ValueList {
             return ValueList::Invalid;
         }
         let mut rows = vec![];
-        let mut value_string = pop_string_inside_parenthesis(query);
+        let mut value_string = pop_string_inside_balanced_parenthesis(query);
         while !value_string.is_empty() {
-            let columns: Vec<String> = value_string
-                .split(',')
-                .map(|s| s.trim().to_string())
-                .collect();
+            let columns: Vec<String> = split_outside_quotes(&value_string, ',');
 
             let columns: Vec<Vec<u8>> = columns.iter().map(|s| s.as_bytes().to_vec()).collect();
             rows.push(columns);
-            value_string = pop_string_inside_parenthesis(query);
+            value_string = pop_string_inside_balanced_parenthesis(query);
         }
         ValueList::Values(rows)
     }
 }
 
+/// Returns the sequence name when `value` is a `NEXTVAL('name')` token.
+pub fn nextval_sequence(value: &[u8]) -> Option<String> {
+    let value = std::str::from_utf8(value).ok()?.trim();
+    let name = value.strip_prefix("NEXTVAL")?.trim_start();
+    let name = unparenthesize(name)?;
+    let name = name.strip_prefix('\'')?.strip_suffix('\'')?;
+    Some(name.to_string())
+}
+
+/// Pops everything before `keyword` when it appears as a separate word outside
+/// of quotes and parenthesis, leaving the keyword at the front of the query.
+fn pop_until_keyword(query: &mut Vec<u8>, keyword: &str) -> String {
+    pop_until_keywords(query, &[keyword])
+}
+
+/// `pop_until_keyword` for the first of several keywords.
+fn pop_until_keywords(query: &mut Vec<u8>, keywords: &[&str]) -> String {
+    let mut in_quotes = false;
+    let mut depth = 0;
+    let mut end = query.len();
+    for (i, &c) in query.iter().enumerate() {
+        match c {
+            b'\'' => in_quotes = !in_quotes,
+            b'(' if !in_quotes => depth += 1,
+            b')' if !in_quotes => depth -= 1,
+            _ if !in_quotes && depth == 0 => {
+                let starts_word = i == 0 || query[i - 1] == b' ';
+                let is_keyword = |keyword: &&str| {
+                    query[i..].starts_with(keyword.as_bytes())
+                        && query.get(i + keyword.len()).is_none_or(|c| *c == b' ')
+                };
+                if starts_word && keywords.iter().any(is_keyword) {
+                    end = i;
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let popped: Vec<u8> = query.drain(..end).collect();
+    String::from_utf8_lossy(&popped).trim().to_string()
+}
+
+/// Pops `[SAVEPOINT] name`, the keyword is optional after `ROLLBACK TO` and
+/// `RELEASE`.
+fn pop_savepoint_name(query: &mut Vec<u8>) -> String {
+    let mut name = pop_word(query);
+    if name == "SAVEPOINT" {
+        name = pop_word(query);
+    }
+    if name.is_empty() || !query.is_empty() {
+        panic!("Invalid query");
+    }
+    name
+}
+
+/// Pops the rest of the query as a `'quoted'` path.
+fn pop_quoted_path(query: &mut Vec<u8>) -> String {
+    let path = String::from_utf8_lossy(query).trim().to_string();
+    query.clear();
+    match path
+        .strip_prefix('\'')
+        .and_then(|path| path.strip_suffix('\''))
+    {
+        Some(path) if !path.is_empty() => path.to_string(),
+        _ => panic!("Invalid query"),
+    }
+}
+
 fn pop_word(query: &mut Vec<u8>) -> String {
     let mut word = String::new();
     while let Some(&c) = query.first() {
@@ -81,6 +852,45 @@ fn pop_word(query: &mut Vec<u8>) -> String {
     word
 }
 
+/// Removes `clause` and the space after it when the query starts with them.
+fn pop_clause(query: &mut Vec<u8>, clause: &str) -> bool {
+    let present =
+        query.starts_with(clause.as_bytes()) && query.get(clause.len()).is_none_or(|&c| c == b' ');
+    if present {
+        query.drain(..(clause.len() + 1).min(query.len()));
+    }
+    present
+}
+
+fn pop_string_inside_balanced_parenthesis(query: &mut Vec<u8>) -> String {
+    let mut word = String::new();
+    let mut in_quotes = false;
+    let mut depth = 0;
+    while let Some(&c) = query.first() {
+        query.remove(0);
+        match c {
+            b'\'' => in_quotes = !in_quotes,
+            b'(' if !in_quotes => {
+                depth += 1;
+                if depth == 1 {
+                    continue;
+                }
+            }
+            b')' if !in_quotes => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            _ => {}
+        }
+        if depth > 0 {
+            word.push(c as char);
+        }
+    }
+    word
+}
+
 fn pop_string_inside_parenthesis(query: &mut Vec<u8>) -> String {
     let mut word = String::new();
     while let Some(&c) = query.first() {
@@ -113,30 +923,298 @@ impl From<&mut Vec<u8>> for QuerySource {
     }
 }
 
+/// Pops the `name AS SELECT ...` of a view definition, the query is the
+/// rest of the text.
+fn pop_view_definition(query: &mut Vec<u8>) -> (String, String) {
+    let name = pop_word(query);
+    if name.is_empty() || pop_word(query) != "AS" || !query.starts_with(b"SELECT ") {
+        panic!("Invalid query");
+    }
+    let view_query = String::from_utf8_lossy(query).trim().to_string();
+    query.clear();
+    (name, view_query)
+}
+
+/// Pops the `SELECT (id, name) ON table TO user` of a grant, `FROM user`
+/// when `revoke` is set. Without a column list the grant covers every
+/// column, as `*`.
+fn pop_grant(query: &mut Vec<u8>, revoke: bool) -> (GrantOperation, Vec<String>, String, String) {
+    let operation = match pop_word(query).as_str() {
+        "SELECT" => GrantOperation::Select,
+        "INSERT" => GrantOperation::Insert,
+        _ => panic!("Invalid query"),
+    };
+    let columns = match pop_until_keyword(query, "ON").trim() {
+        "" | "(*)" => vec!["*".to_string()],
+        columns => columns
+            .strip_prefix('(')
+            .and_then(|columns| columns.strip_suffix(')'))
+            .expect("Invalid query")
+            .split(',')
+            .map(|column| column.trim().to_string())
+            .collect(),
+    };
+    if pop_word(query) != "ON" || columns.iter().any(String::is_empty) {
+        panic!("Invalid query");
+    }
+    let table = pop_word(query);
+    let preposition = if revoke { "FROM" } else { "TO" };
+    if table.is_empty() || pop_word(query) != preposition {
+        panic!("Invalid query");
+    }
+    let user = pop_word(query);
+    if user.is_empty() || !query.is_empty() {
+        panic!("Invalid query");
+    }
+    (operation, columns, table, user)
+}
+
+/// Pops `INTO target USING source ON condition` followed by a
+/// `WHEN MATCHED THEN UPDATE SET` clause, a `WHEN NOT MATCHED THEN INSERT`
+/// clause or both. Columns are qualified with the name of their table, the
+/// source and the target must differ.
+fn pop_merge(query: &mut Vec<u8>) -> Query {
+    if pop_word(query) != "INTO" {
+        panic!("Invalid query");
+    }
+    let target = pop_word(query);
+    if target.is_empty() || pop_word(query) != "USING" {
+        panic!("Invalid query");
+    }
+    let source = pop_word(query);
+    if source.is_empty() || source == target || pop_word(query) != "ON" {
+        panic!("Invalid query");
+    }
+    let column = |value: &str, table: &str| {
+        value
+            .trim()
+            .strip_prefix(table)
+            .and_then(|column| column.strip_prefix('.'))
+            .filter(|column| !column.is_empty())
+            .map(str::to_string)
+    };
+    let target_column = |value: &str| column(value, &target).unwrap_or(value.trim().to_string());
+    let value = |value: &str| match column(value, &source) {
+        Some(column) => MergeValue::Source(column),
+        None if value.trim().starts_with('\'') || value.trim().parse::<f64>().is_ok() => {
+            MergeValue::Literal(unquote(value.trim()).as_bytes().to_vec())
+        }
+        None => panic!("Invalid query"),
+    };
+
+    let condition = pop_until_keyword(query, "WHEN");
+    let (left, right) = condition.split_once('=').expect("Invalid query");
+    let join_condition = match (column(left, &target), column(right, &source)) {
+        (Some(target), Some(source)) => (target, source),
+        _ => match (column(right, &target), column(left, &source)) {
+            (Some(target), Some(source)) => (target, source),
+            _ => panic!("Invalid query"),
+        },
+    };
+
+    let mut matched_action = None;
+    let mut not_matched_action = None;
+    while !query.is_empty() {
+        if pop_word(query) != "WHEN" {
+            panic!("Invalid query");
+        }
+        let matched = !pop_clause(query, "NOT");
+        if pop_word(query) != "MATCHED" || pop_word(query) != "THEN" {
+            panic!("Invalid query");
+        }
+        let mut action = pop_until_keyword(query, "WHEN").into_bytes();
+        match (matched, pop_word(&mut action).as_str()) {
+            (true, "UPDATE") if matched_action.is_none() && pop_clause(&mut action, "SET") => {
+                let assignments = split_outside_quotes(&String::from_utf8_lossy(&action), ',')
+                    .iter()
+                    .map(|assignment| {
+                        let (column, assigned) = assignment.split_once('=').expect("Invalid query");
+                        (target_column(column), value(assigned))
+                    })
+                    .collect();
+                matched_action = Some(assignments);
+            }
+            (false, "INSERT") if not_matched_action.is_none() => {
+                let columns = pop_until_keyword(&mut action, "VALUES");
+                if pop_word(&mut action) != "VALUES" {
+                    panic!("Invalid query");
+                }
+                let values = String::from_utf8_lossy(&action).trim().to_string();
+                let (columns, values) = match (unparenthesize(&columns), unparenthesize(&values)) {
+                    (Some(columns), Some(values)) => (
+                        split_outside_quotes(columns, ','),
+                        split_outside_quotes(values, ','),
+                    ),
+                    _ => panic!("Invalid query"),
+                };
+                if columns.len() != values.len() {
+                    panic!("Invalid query");
+                }
+                not_matched_action = Some((
+                    columns.iter().map(|column| target_column(column)).collect(),
+                    values.iter().map(|assigned| value(assigned)).collect(),
+                ));
+            }
+            _ => panic!("Invalid query"),
+        }
+    }
+    if matched_action.is_none() && not_matched_action.is_none() {
+        panic!("Invalid query");
+    }
+    Query::Merge {
+        target,
+        source,
+        join_condition,
+        matched_action,
+        not_matched_action,
+    }
+}
+
+/// Pops the rest of a `SELECT` up to the set operation joining it to the
+/// next one.
+fn pop_select(query: &mut Vec<u8>) -> Query {
+    let mut select = pop_until_keywords(query, &["UNION", "INTERSECT", "EXCEPT"]).into_bytes();
+    let scope = Scope::from(&mut select);
+    let query_source = QuerySource::from(&mut select);
+    let mut filter = pop_until_keyword(&mut select, "GROUP").into_bytes();
+    let filter = Filter::from(&mut filter);
+    let scope = match pop_clause(&mut select, "GROUP BY") {
+        true => scope.grouped(&String::from_utf8_lossy(&select)),
+        false if select.is_empty() => scope,
+        false => Scope::Invalid,
+    };
+    Query::Select(query_source, scope, filter)
+}
+
 impl From<&str> for Query {
     fn from(query: &str) -> Self {
-        let mut query = query.as_bytes().to_vec();
+        let mut query = strip_comments(query).trim().as_bytes().to_vec();
         Query::from(&mut query)
     }
 }
 
 impl From<&String> for Query {
     fn from(query: &String) -> Self {
-        let mut query = query.as_bytes().to_vec();
-        Query::from(&mut query)
+        Query::from(query.as_str())
+    }
+}
+
+/// Removes `-- line` and `/* block */` comments outside of quoted strings.
+/// Block comments nest and are replaced by a space so the words around them
+/// stay separated, line comments keep their terminating newline.
+pub fn strip_comments(query: &str) -> String {
+    let mut stripped = String::with_capacity(query.len());
+    let mut chars = query.chars().peekable();
+    let mut in_quotes = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => in_quotes = !in_quotes,
+            '-' if !in_quotes && chars.peek() == Some(&'-') => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+                continue;
+            }
+            '/' if !in_quotes && chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut depth = 1;
+                while depth > 0 {
+                    match (chars.next(), chars.peek()) {
+                        (Some('/'), Some('*')) => {
+                            chars.next();
+                            depth += 1;
+                        }
+                        (Some('*'), Some('/')) => {
+                            chars.next();
+                            depth -= 1;
+                        }
+                        (Some(_), _) => {}
+                        (None, _) => break,
+                    }
+                }
+                stripped.push(' ');
+                continue;
+            }
+            _ => {}
+        }
+        stripped.push(c);
     }
+    stripped
 }
 
+/// Reads up to and including the next space or `;`, empty at the end of the
+/// input.
 fn read_word<R: Read>(reader: &mut BufReader<R>) -> String {
-    let mut buf = vec![];
-    let _ = reader.read_until(b' ', &mut buf);
-    std::str::from_utf8(&buf).unwrap().to_string()
+    let mut word = vec![];
+    while let Ok(available) = reader.fill_buf() {
+        if available.is_empty() {
+            break;
+        }
+        match available.iter().position(|b| *b == b' ' || *b == b';') {
+            Some(end) => {
+                word.extend(&available[..=end]);
+                reader.consume(end + 1);
+                break;
+            }
+            None => {
+                let length = available.len();
+                word.extend(available);
+                reader.consume(length);
+            }
+        }
+    }
+    String::from_utf8_lossy(&word).to_string()
+}
+
+/// Reads a word at a time up to the `;` ending the statement, outside of
+/// quotes, or the end of the input. The rest of the input is left for the
+/// next statement.
+fn read_statement<R: Read>(reader: &mut BufReader<R>) -> String {
+    let mut statement = String::new();
+    let mut in_quotes = false;
+    loop {
+        let word = read_word(reader);
+        if word.is_empty() {
+            return statement;
+        }
+        in_quotes ^= word.matches('\'').count() % 2 == 1;
+        match word.strip_suffix(';') {
+            Some(word) if !in_quotes => {
+                statement.push_str(word);
+                return statement;
+            }
+            _ => statement.push_str(&word),
+        }
+    }
 }
 
+/// The offset of the `;` ending the first statement of `text`, outside of
+/// quotes like `read_statement` splits them.
+pub fn statement_end(text: &str) -> Option<usize> {
+    let mut in_quotes = false;
+    text.char_indices().find_map(|(i, c)| {
+        match c {
+            '\'' => in_quotes = !in_quotes,
+            ';' if !in_quotes => return Some(i),
+            _ => {}
+        }
+        None
+    })
+}
+
+/// Parses the next statement of the stream, see `read_statement`. `None`
+/// once only whitespace is left before the end of the input.
+pub fn read_query<R: Read>(reader: &mut BufReader<R>) -> Option<Query> {
+    let statement = read_statement(reader);
+    if statement.trim().is_empty() {
+        return None;
+    }
+    Some(Query::from(statement.as_str()))
+}
+
+/// Parses the next statement of the stream, see `read_query`. Panics at the
+/// end of the input like on any invalid query.
 impl<R: Read> From<&mut BufReader<R>> for Query {
     fn from(value: &mut BufReader<R>) -> Self {
-        read_word(value);
-        todo!()
+        read_query(value).unwrap_or_else(|| panic!("Invalid query"))
     }
 }
 
@@ -144,67 +1222,1607 @@ impl From<&mut Vec<u8>> for Query {
     fn from(query: &mut Vec<u8>) -> Self {
         const SELECT: &str = "SELECT";
         const INSERT: &str = "INSERT";
+        const ALTER: &str = "ALTER";
+        const RENAME: &str = "RENAME";
+        const CREATE: &str = "CREATE";
+        const DROP: &str = "DROP";
+        const BEGIN: &str = "BEGIN";
+        const COMMIT: &str = "COMMIT";
+        const ROLLBACK: &str = "ROLLBACK";
+        const SAVEPOINT: &str = "SAVEPOINT";
+        const RELEASE: &str = "RELEASE";
+        const BACKUP: &str = "BACKUP";
+        const RESTORE: &str = "RESTORE";
+        const SET: &str = "SET";
+        const SHOW: &str = "SHOW";
+        const COPY: &str = "COPY";
+        const CHECKPOINT: &str = "CHECKPOINT";
+        const ANALYZE: &str = "ANALYZE";
+        const EXPLAIN: &str = "EXPLAIN";
+        const REBUILD: &str = "REBUILD";
+        const CALL: &str = "CALL";
+        const WITH: &str = "WITH";
+        const MERGE: &str = "MERGE";
+        const REFRESH: &str = "REFRESH";
+        const GRANT: &str = "GRANT";
+        const PREPARE: &str = "PREPARE";
+        const EXECUTE: &str = "EXECUTE";
+        const REVOKE: &str = "REVOKE";
+        const KILL: &str = "KILL";
+        const LOCK: &str = "LOCK";
+        const UNLOCK: &str = "UNLOCK";
+        const FLUSH: &str = "FLUSH";
+        const CLEAR: &str = "CLEAR";
+        const COMPRESS: &str = "COMPRESS";
+        const DEBUG: &str = "DEBUG";
+        const IMPORT: &str = "IMPORT";
+        const TABLE: &str = "TABLE";
+        const REINDEX: &str = "REINDEX";
+        const COMPACT: &str = "COMPACT";
 
         let word = pop_word(query);
         match word.as_str() {
             SELECT => {
-                let query_source = QuerySource::from(query);
-                Query::Select(query_source, Scope::All)
+                // The select list is optional before INTO TEMP TABLE.
+                let mut rest = query.clone();
+                let columns = pop_until_keyword(&mut rest, "INTO");
+                if pop_clause(&mut rest, "INTO TEMP TABLE") {
+                    let dest_table = pop_word(&mut rest);
+                    if dest_table.is_empty() || !rest.starts_with(b"FROM ") {
+                        panic!("Invalid query");
+                    }
+                    query.clear();
+                    let source_query =
+                        format!("SELECT {} {}", columns, String::from_utf8_lossy(&rest));
+                    return Query::SelectIntoTemp {
+                        dest_table,
+                        source_query: Box::new(Query::from(&source_query)),
+                    };
+                }
+                let mut combined = pop_select(query);
+                loop {
+                    let operation = pop_word(query);
+                    if operation.is_empty() {
+                        break;
+                    }
+                    let distinct = !(operation == "UNION" && pop_clause(query, "ALL"));
+                    if pop_word(query) != SELECT {
+                        panic!("Invalid query");
+                    }
+                    let left = Box::new(combined);
+                    let right = Box::new(pop_select(query));
+                    combined = match operation.as_str() {
+                        "UNION" => Query::Union {
+                            left,
+                            right,
+                            distinct,
+                        },
+                        "INTERSECT" => Query::Intersect { left, right },
+                        "EXCEPT" => Query::Except { left, right },
+                        _ => panic!("Invalid query"),
+                    };
+                }
+                combined
             }
             INSERT => {
+                let upsert = query.starts_with(b"OR ");
+                if upsert {
+                    pop_word(query);
+                    if pop_word(query) != "REPLACE" {
+                        panic!("Invalid query");
+                    }
+                }
                 let query_source: QuerySource = query.into();
+                if let QuerySource::IntoTable(dest_table) = &query_source {
+                    if !upsert && query.starts_with(b"SELECT ") {
+                        return Query::InsertSelect {
+                            dest_table: dest_table.clone(),
+                            source_query: Box::new(Query::from(query)),
+                        };
+                    }
+                }
                 let column_list: ColumnList = query.into();
-                let data: ValueList = query.into();
-                Query::Insert(query_source, column_list, data)
+                let mut values = pop_until_keyword(query, "RETURNING").into_bytes();
+                let data: ValueList = (&mut values).into();
+                let returning = match pop_word(query).as_str() {
+                    "" => None,
+                    _ => Some(Scope::from(&mut *query)),
+                };
+                match (upsert, returning) {
+                    (true, None) => Query::Upsert(query_source, column_list, data),
+                    (true, Some(_)) => panic!("Invalid query"),
+                    (false, returning) => Query::Insert(query_source, column_list, data, returning),
+                }
             }
-            _ => panic!("Invalid query"),
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use std::{
-        borrow::{Borrow, BorrowMut},
-        io::BufReader,
-    };
-
-    use super::{Query, QuerySource};
-
-    #[test]
-    fn test_pop_word() {
-        let mut query = "SELECT * FROM users".as_bytes().to_vec();
-        let word = super::pop_word(query.borrow_mut());
-        assert_eq!(word, "SELECT");
-    }
-
-    #[test]
-    fn parse_query_source() {
-        let query_str = "FROM users";
-        let mut query = query_str.as_bytes().to_vec();
-        let query_source = QuerySource::from(query.borrow_mut());
-        match query_source {
-            QuerySource::Table(table) => {
-                assert_eq!(table, "users");
+            ALTER => {
+                match pop_word(query).as_str() {
+                    "TABLE" => {}
+                    "SEQUENCE" => {
+                        let name = pop_word(query);
+                        if pop_word(query) != "RESTART" {
+                            panic!("Invalid query");
+                        }
+                        let value = match pop_word(query).as_str() {
+                            "" => None,
+                            "WITH" => Some(pop_word(query).parse().expect("Invalid query")),
+                            _ => panic!("Invalid query"),
+                        };
+                        return Query::AlterSequenceRestart { name, value };
+                    }
+                    _ => panic!("Invalid query"),
+                }
+                let table = pop_word(query);
+                if pop_word(query) != "RENAME" || pop_word(query) != "COLUMN" {
+                    panic!("Invalid query");
+                }
+                let old_name = pop_word(query);
+                if pop_word(query) != "TO" {
+                    panic!("Invalid query");
+                }
+                let new_name = pop_word(query);
+                Query::AlterTableRenameColumn {
+                    table,
+                    old_name,
+                    new_name,
+                }
             }
-            _ => {
-                panic!("Invalid query source");
+            RENAME => {
+                if pop_word(query) != "TABLE" {
+                    panic!("Invalid query");
+                }
+                let from = pop_word(query);
+                if pop_word(query) != "TO" {
+                    panic!("Invalid query");
+                }
+                let to = pop_word(query);
+                Query::RenameTable { from, to }
             }
-        }
-    }
-
-    #[test]
-    fn parse_select_query() {
+            CREATE => {
+                let mut word = pop_word(query);
+                let kind = match word.as_str() {
+                    "HASH" => {
+                        word = pop_word(query);
+                        if word != "INDEX" {
+                            panic!("Invalid query");
+                        }
+                        IndexKind::Hash
+                    }
+                    _ => IndexKind::BTree,
+                };
+                match word.as_str() {
+                    "TABLE" => {}
+                    "INDEX" => {
+                        let name = pop_word(query);
+                        if pop_word(query) != "ON" {
+                            panic!("Invalid query");
+                        }
+                        let table = pop_word(query);
+                        let columns: Vec<String> = pop_string_inside_balanced_parenthesis(query)
+                            .split(',')
+                            .map(|column| column.trim().to_string())
+                            .collect();
+                        let rest = String::from_utf8_lossy(query).trim().to_string();
+                        query.clear();
+                        let predicate = match rest.strip_prefix("WHERE ") {
+                            Some(predicate) if Predicate::parse(predicate).is_some() => {
+                                Some(predicate.trim().to_string())
+                            }
+                            None if rest.is_empty() => None,
+                            _ => panic!("Invalid query"),
+                        };
+                        if name.is_empty()
+                            || columns.iter().any(|c| c.is_empty() || c.contains(' '))
+                        {
+                            panic!("Invalid query");
+                        }
+                        return Query::CreateIndex {
+                            name,
+                            table,
+                            columns,
+                            kind,
+                            predicate,
+                        };
+                    }
+                    "PROCEDURE" => {
+                        let name = pop_word(query);
+                        if name.is_empty() || pop_word(query) != "AS" {
+                            panic!("Invalid query");
+                        }
+                        let body = pop_quoted_path(query).replace("''", "'");
+                        return Query::CreateProcedure { name, body };
+                    }
+                    "VIEW" => {
+                        let (name, query) = pop_view_definition(query);
+                        return Query::CreateView { name, query };
+                    }
+                    "TRIGGER" => {
+                        let name = pop_word(query);
+                        if name.is_empty() || pop_word(query) != "AFTER" {
+                            panic!("Invalid query");
+                        }
+                        let event = match pop_word(query).as_str() {
+                            "INSERT" => TriggerEvent::Insert,
+                            "UPDATE" => TriggerEvent::Update,
+                            "DELETE" => TriggerEvent::Delete,
+                            _ => panic!("Invalid query"),
+                        };
+                        if pop_word(query) != "ON" {
+                            panic!("Invalid query");
+                        }
+                        let table = pop_word(query);
+                        if table.is_empty() || pop_word(query) != "EXECUTE" {
+                            panic!("Invalid query");
+                        }
+                        let body = pop_quoted_path(query).replace("''", "'");
+                        return Query::CreateTrigger {
+                            name,
+                            event,
+                            table,
+                            body,
+                        };
+                    }
+                    "USER" => {
+                        let name = pop_word(query);
+                        if name.is_empty()
+                            || pop_word(query) != "WITH"
+                            || pop_word(query) != "PASSWORD"
+                        {
+                            panic!("Invalid query");
+                        }
+                        let password = pop_quoted_path(query).replace("''", "'");
+                        return Query::CreateUser { name, password };
+                    }
+                    "MATERIALIZED" => {
+                        if pop_word(query) != "VIEW" {
+                            panic!("Invalid query");
+                        }
+                        let (name, query) = pop_view_definition(query);
+                        return Query::CreateMaterializedView { name, query };
+                    }
+                    "SEQUENCE" => {
+                        let name = pop_word(query);
+                        let mut start = 1;
+                        let mut increment = 1;
+                        loop {
+                            match pop_word(query).as_str() {
+                                "" => break,
+                                "START" => start = pop_word(query).parse().expect("Invalid query"),
+                                "INCREMENT" => {
+                                    increment = pop_word(query).parse().expect("Invalid query")
+                                }
+                                _ => panic!("Invalid query"),
+                            }
+                        }
+                        return Query::CreateSequence {
+                            name,
+                            start,
+                            increment,
+                        };
+                    }
+                    _ => panic!("Invalid query"),
+                }
+                let if_not_exists = pop_clause(query, "IF NOT EXISTS");
+                let table = pop_word(query);
+                let columns: ColumnDefinitionList = query.into();
+                Query::CreateTable {
+                    table,
+                    columns,
+                    if_not_exists,
+                }
+            }
+            DROP => match pop_word(query).as_str() {
+                "TEMP" => {
+                    let name = match pop_word(query).as_str() {
+                        "TABLE" => pop_word(query),
+                        _ => panic!("Invalid query"),
+                    };
+                    if name.is_empty() || !query.is_empty() {
+                        panic!("Invalid query");
+                    }
+                    Query::DropTempTable(name)
+                }
+                "TABLE" => {
+                    let if_exists = pop_clause(query, "IF EXISTS");
+                    let table = pop_word(query);
+                    if table.is_empty() {
+                        panic!("Invalid query");
+                    }
+                    Query::DropTable { table, if_exists }
+                }
+                "SEQUENCE" => Query::DropSequence(pop_word(query)),
+                "VIEW" => {
+                    let name = pop_word(query);
+                    if name.is_empty() {
+                        panic!("Invalid query");
+                    }
+                    Query::DropView(name)
+                }
+                "TRIGGER" => {
+                    let name = pop_word(query);
+                    if name.is_empty() || !query.is_empty() {
+                        panic!("Invalid query");
+                    }
+                    Query::DropTrigger { name }
+                }
+                "USER" => {
+                    let name = pop_word(query);
+                    if name.is_empty() || !query.is_empty() {
+                        panic!("Invalid query");
+                    }
+                    Query::DropUser(name)
+                }
+                _ => panic!("Invalid query"),
+            },
+            BEGIN => match pop_word(query).as_str() {
+                "" | "TRANSACTION" => Query::Begin,
+                _ => panic!("Invalid query"),
+            },
+            COMMIT => Query::Commit,
+            ROLLBACK => match pop_word(query).as_str() {
+                "" => Query::Rollback,
+                "TO" => Query::RollbackToSavepoint(pop_savepoint_name(query)),
+                _ => panic!("Invalid query"),
+            },
+            SAVEPOINT => Query::Savepoint(pop_savepoint_name(query)),
+            RELEASE => Query::ReleaseSavepoint(pop_savepoint_name(query)),
+            BACKUP => {
+                if pop_word(query) != "DATABASE" || pop_word(query) != "TO" {
+                    panic!("Invalid query");
+                }
+                Query::Backup(pop_quoted_path(query))
+            }
+            RESTORE => match pop_word(query).as_str() {
+                "DATABASE" => {
+                    if pop_word(query) != "FROM" {
+                        panic!("Invalid query");
+                    }
+                    Query::Restore(pop_quoted_path(query))
+                }
+                "FROM" => {
+                    if pop_word(query) != "WAL"
+                        || pop_word(query) != "AT"
+                        || pop_word(query) != "LSN"
+                    {
+                        panic!("Invalid query");
+                    }
+                    let lsn = pop_word(query).parse().expect("Invalid query");
+                    if !query.is_empty() {
+                        panic!("Invalid query");
+                    }
+                    Query::RestoreFromWal { lsn }
+                }
+                _ => panic!("Invalid query"),
+            },
+            SET => {
+                let assignment = String::from_utf8_lossy(query).to_string();
+                query.clear();
+                let (name, value) = assignment.split_once('=').expect("Invalid query");
+                let (name, value) = (name.trim(), unquote(value.trim()));
+                if name.is_empty() || name.contains(' ') || value.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::Set {
+                    name: name.to_lowercase(),
+                    value: value.to_string(),
+                }
+            }
+            COPY if query.starts_with(b"TABLE ") => {
+                query.drain(.."TABLE ".len());
+                let source = pop_word(query);
+                if source.is_empty() || pop_word(query) != "TO" {
+                    panic!("Invalid query");
+                }
+                let destination = pop_word(query);
+                let filter = Filter::from(&mut *query);
+                if destination.is_empty() || matches!(filter, Filter::Invalid) {
+                    panic!("Invalid query");
+                }
+                Query::CopyTable {
+                    source,
+                    destination,
+                    filter,
+                }
+            }
+            COPY => {
+                let table = pop_word(query);
+                let direction = pop_word(query);
+                let source = String::from_utf8_lossy(query).to_string();
+                let source = match source.strip_suffix(" BINARY") {
+                    Some(source) if !table.is_empty() => source.trim(),
+                    _ => panic!("Invalid query"),
+                };
+                match (direction.as_str(), source) {
+                    ("FROM", "STDIN") => Query::CopyBinary(table),
+                    ("FROM", _) => Query::CopyBinaryFrom {
+                        table,
+                        path: pop_quoted_path(&mut source.as_bytes().to_vec()),
+                    },
+                    ("TO", _) => Query::CopyBinaryTo {
+                        table,
+                        path: pop_quoted_path(&mut source.as_bytes().to_vec()),
+                    },
+                    _ => panic!("Invalid query"),
+                }
+            }
+            CALL => {
+                let call = String::from_utf8_lossy(query).trim().to_string();
+                query.clear();
+                let (name, args) = call
+                    .strip_suffix(')')
+                    .and_then(|call| call.split_once('('))
+                    .expect("Invalid query");
+                let name = name.trim().to_string();
+                if name.is_empty() || name.contains(' ') {
+                    panic!("Invalid query");
+                }
+                let args = match args.trim() {
+                    "" => vec![],
+                    args => split_outside_quotes(args, ',')
+                        .iter()
+                        .map(|arg| unquote(arg.trim()).to_string())
+                        .collect(),
+                };
+                Query::CallProcedure { name, args }
+            }
+            PREPARE => {
+                let name = pop_word(query);
+                if name.is_empty() || pop_word(query) != "AS" {
+                    panic!("Invalid query");
+                }
+                let sql = pop_quoted_path(query).replace("''", "'");
+                Query::Prepare { name, sql }
+            }
+            EXECUTE => {
+                let name = pop_word(query);
+                let args = String::from_utf8_lossy(query).trim().to_string();
+                query.clear();
+                let args = match args.as_str() {
+                    "" => vec![],
+                    args => {
+                        let args = args
+                            .strip_prefix('(')
+                            .and_then(|args| args.strip_suffix(')'))
+                            .expect("Invalid query");
+                        split_outside_quotes(args, ',')
+                            .iter()
+                            .map(|arg| arg.trim().to_string())
+                            .collect()
+                    }
+                };
+                if name.is_empty() || args.iter().any(String::is_empty) {
+                    panic!("Invalid query");
+                }
+                Query::Execute { name, args }
+            }
+            GRANT => {
+                let (operation, columns, table, user) = pop_grant(query, false);
+                Query::Grant {
+                    operation,
+                    columns,
+                    table,
+                    user,
+                }
+            }
+            REVOKE => {
+                let (operation, columns, table, user) = pop_grant(query, true);
+                if columns != ["*"] {
+                    panic!("Invalid query");
+                }
+                Query::Revoke {
+                    operation,
+                    table,
+                    user,
+                }
+            }
+            REFRESH if query.is_empty() => Query::Refresh,
+            REFRESH => {
+                if pop_word(query) != "MATERIALIZED" || pop_word(query) != "VIEW" {
+                    panic!("Invalid query");
+                }
+                let name = pop_word(query);
+                if name.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::RefreshMaterializedView(name)
+            }
+            CHECKPOINT if query.is_empty() => Query::Checkpoint,
+            LOCK => {
+                if pop_word(query) != "TABLE" {
+                    panic!("Invalid query");
+                }
+                let table = pop_word(query);
+                let mode = match pop_word(query).as_str() {
+                    "READ" => TableLock::Shared,
+                    "WRITE" => TableLock::Exclusive,
+                    _ => panic!("Invalid query"),
+                };
+                if table.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::LockTable { table, mode }
+            }
+            UNLOCK => {
+                if pop_word(query) != "TABLE" {
+                    panic!("Invalid query");
+                }
+                let table = pop_word(query);
+                if table.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::UnlockTable(table)
+            }
+            KILL => {
+                let id = pop_word(query)
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Invalid query"));
+                if !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::Kill(id)
+            }
+            FLUSH | CLEAR => {
+                if pop_word(query) != "QUERY" || pop_word(query) != "LOG" || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                match word.as_str() {
+                    FLUSH => Query::FlushQueryLog,
+                    _ => Query::ClearQueryLog,
+                }
+            }
+            SHOW if pop_clause(query, "QUERY LOG") => {
+                let count = match pop_word(query).as_str() {
+                    "" => DEFAULT_LAST_LINES,
+                    "LAST" => pop_word(query)
+                        .parse()
+                        .unwrap_or_else(|_| panic!("Invalid query")),
+                    _ => panic!("Invalid query"),
+                };
+                if !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::ShowQueryLog(count)
+            }
+            SHOW if pop_clause(query, "DISK USAGE") => {
+                if !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::ShowDiskUsage
+            }
+            SHOW if query.starts_with(b"GRANTS FOR ") => {
+                query.drain(.."GRANTS FOR ".len());
+                let user = pop_word(query);
+                if user.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::ShowGrants(user)
+            }
+            SHOW if query.starts_with(b"CREATE TABLE ") => {
+                query.drain(.."CREATE TABLE ".len());
+                let table = pop_word(query);
+                if table.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::ShowCreateTable(table)
+            }
+            SHOW if query.starts_with(b"TABLE STATS ") => {
+                query.drain(.."TABLE STATS ".len());
+                let table = pop_word(query);
+                if table.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::ShowTableStats(table)
+            }
+            SHOW => {
+                let name = pop_word(query);
+                if name.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                match name.as_str() {
+                    "ALL" => Query::Show(None),
+                    "VIEWS" => Query::ShowViews,
+                    "PROCESSLIST" => Query::ShowProcesslist,
+                    "CONFIG" => Query::ShowConfig,
+                    _ => Query::Show(Some(name.to_lowercase())),
+                }
+            }
+            ANALYZE => {
+                let target = pop_word(query);
+                let name = pop_word(query);
+                if name.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                match (target.as_str(), name.rsplit_once('.')) {
+                    ("TABLE", _) => Query::Analyze(name),
+                    ("COLUMN", Some((table, column)))
+                        if !table.is_empty() && !column.is_empty() =>
+                    {
+                        Query::AnalyzeColumn {
+                            table: table.to_string(),
+                            column: column.to_string(),
+                        }
+                    }
+                    _ => panic!("Invalid query"),
+                }
+            }
+            EXPLAIN if pop_clause(query, "INDEXES FOR") => {
+                let table = pop_word(query);
+                if table.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::ExplainIndexes(table)
+            }
+            EXPLAIN if pop_clause(query, "ANALYZE") => {
+                Query::ExplainAnalyze(Box::new(Query::from(query)))
+            }
+            EXPLAIN if pop_clause(query, "FORMAT=JSON") => {
+                Query::ExplainJson(Box::new(Query::from(query)))
+            }
+            EXPLAIN => Query::Explain(Box::new(Query::from(query))),
+            MERGE => pop_merge(query),
+            WITH => {
+                let name = pop_word(query);
+                if name.is_empty() || pop_word(query) != "AS" {
+                    panic!("Invalid query");
+                }
+                let cte = pop_string_inside_balanced_parenthesis(query);
+                let cte = Query::from(&mut cte.into_bytes());
+                if query.first() == Some(&b' ') {
+                    query.remove(0);
+                }
+                let query = match Query::from(query) {
+                    Query::Select(QuerySource::Table(table), scope, filter) if table == name => {
+                        Query::Select(QuerySource::Cte(table), scope, filter)
+                    }
+                    query => query,
+                };
+                Query::With {
+                    name,
+                    cte: Box::new(cte),
+                    query: Box::new(query),
+                }
+            }
+            REBUILD => {
+                let all = match pop_word(query).as_str() {
+                    "INDEX" => false,
+                    "ALL" if pop_word(query) == "INDEXES" => true,
+                    _ => panic!("Invalid query"),
+                };
+                let index_name = if all { String::new() } else { pop_word(query) };
+                if pop_word(query) != "ON" {
+                    panic!("Invalid query");
+                }
+                let table = pop_word(query);
+                if (!all && index_name.is_empty()) || table.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                match all {
+                    true => Query::RebuildAllIndexes(table),
+                    false => Query::RebuildIndex { index_name, table },
+                }
+            }
+            COMPRESS => {
+                if pop_word(query) != "COLUMN" {
+                    panic!("Invalid query");
+                }
+                let column = pop_word(query);
+                if pop_word(query) != "ON" {
+                    panic!("Invalid query");
+                }
+                let table = pop_word(query);
+                if column.is_empty() || table.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::CompressColumn { table, column }
+            }
+            DEBUG if pop_clause(query, "DUMP TABLE") => {
+                let table = pop_word(query);
+                if table.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::DebugDump(table)
+            }
+            IMPORT if pop_clause(query, "SCHEMA FROM") => {
+                Query::ImportSchema(pop_quoted_path(query))
+            }
+            TABLE if pop_clause(query, "CHECKSUM") => {
+                let table = pop_word(query);
+                if table.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                Query::Checksum(table)
+            }
+            REINDEX => {
+                let table = pop_word(query);
+                if table.is_empty() || !query.is_empty() {
+                    panic!("Invalid query");
+                }
+                match table.as_str() {
+                    "ALL" => Query::ReindexAll,
+                    _ => Query::Reindex(table),
+                }
+            }
+            COMPACT if pop_clause(query, "DATABASE") && query.is_empty() => Query::CompactDatabase,
+            _ => panic!("Invalid query"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::BorrowMut, io::BufReader};
+
+    use super::{
+        fingerprint_query, split_literals, Filter, GrantOperation, IndexKind, MergeValue, Query,
+        QuerySource, SelectExpr, TableLock, TriggerEvent, DEFAULT_LAST_LINES,
+    };
+
+    #[test]
+    fn test_pop_word() {
+        let mut query = "SELECT * FROM users".as_bytes().to_vec();
+        let word = super::pop_word(query.borrow_mut());
+        assert_eq!(word, "SELECT");
+    }
+
+    #[test]
+    fn parse_query_source() {
+        let query_str = "FROM users";
+        let mut query = query_str.as_bytes().to_vec();
+        let query_source = QuerySource::from(query.borrow_mut());
+        match query_source {
+            QuerySource::Table(table) => {
+                assert_eq!(table, "users");
+            }
+            _ => {
+                panic!("Invalid query source");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_select_query() {
         let query: Query = "SELECT FROM users".into();
         match query {
-            Query::Select(query_source, _scope) => match query_source {
-                QuerySource::Table(table) => {
-                    assert_eq!(table, "users");
+            Query::Select(query_source, _scope, _) => match query_source {
+                QuerySource::Table(table) => {
+                    assert_eq!(table, "users");
+                }
+                _ => {
+                    panic!("Invalid query source");
+                }
+            },
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_select_query_with_expressions() {
+        let query: Query = "SELECT id, COALESCE(email, 'no FROM here') FROM users".into();
+        match query {
+            Query::Select(QuerySource::Table(table), super::Scope::Expressions(expressions), _) => {
+                assert_eq!(table, "users");
+                assert_eq!(expressions.len(), 2);
+                assert_eq!(expressions[0], SelectExpr::Column("id".to_string()));
+                assert!(matches!(expressions[1], SelectExpr::Coalesce(_, _)));
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        let query: Query = "SELECT * FROM users".into();
+        assert!(matches!(
+            query,
+            Query::Select(QuerySource::Table(_), super::Scope::All, _)
+        ));
+        let query: Query = "SELECT COALESCE(email) FROM users".into();
+        assert!(matches!(
+            query,
+            Query::Select(QuerySource::Table(_), super::Scope::Invalid, _)
+        ));
+    }
+
+    #[test]
+    fn parse_select_query_with_group_by() {
+        match Query::from(
+            "SELECT account_id, COUNT(DISTINCT product_id) FROM orders WHERE id > 1 GROUP BY account_id",
+        ) {
+            Query::Select(QuerySource::Table(table), super::Scope::Grouped(expressions, group_by), Filter::Where(predicates)) => {
+                assert_eq!(table, "orders");
+                assert_eq!(
+                    expressions[1],
+                    SelectExpr::Aggregate(super::aggregate::AggregateFunction::CountDistinct(
+                        "product_id".to_string()
+                    ))
+                );
+                assert_eq!(group_by, ["account_id"]);
+                assert_eq!(predicates.len(), 1);
+            }
+            query => panic!("Unexpected query {:?}", query),
+        }
+
+        for query in [
+            "SELECT * FROM orders GROUP BY account_id",
+            "SELECT account_id FROM orders GROUP BY COUNT(id)",
+            "SELECT account_id FROM orders GROUP account_id",
+        ] {
+            assert!(matches!(
+                Query::from(query),
+                Query::Select(_, super::Scope::Invalid, _)
+            ));
+        }
+    }
+
+    #[test]
+    fn parse_select_into_temp() {
+        match Query::from("SELECT INTO TEMP TABLE recent FROM orders WHERE id > 1") {
+            Query::SelectIntoTemp {
+                dest_table,
+                source_query,
+            } => {
+                assert_eq!(dest_table, "recent");
+                assert!(matches!(
+                    *source_query,
+                    Query::Select(QuerySource::Table(table), super::Scope::All, Filter::Where(_)) if table == "orders"
+                ));
+            }
+            query => panic!("Unexpected query {:?}", query),
+        }
+        match Query::from("SELECT id, name INTO TEMP TABLE recent FROM orders") {
+            Query::SelectIntoTemp { source_query, .. } => assert!(matches!(
+                *source_query,
+                Query::Select(_, super::Scope::Expressions(expressions), Filter::All) if expressions.len() == 2
+            )),
+            query => panic!("Unexpected query {:?}", query),
+        }
+        assert!(matches!(
+            Query::from("DROP TEMP TABLE recent"),
+            Query::DropTempTable(name) if name == "recent"
+        ));
+        // Not a temporary table.
+        assert!(matches!(
+            Query::from("SELECT id FROM orders"),
+            Query::Select(_, super::Scope::Expressions(_), Filter::All)
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_select_into_temp_without_from() {
+        let _query = Query::from("SELECT INTO TEMP TABLE recent");
+    }
+
+    #[test]
+    fn parse_select_query_with_where() {
+        let query: Query = "SELECT name FROM users WHERE CAST(age AS VARCHAR) = '42'".into();
+        match query {
+            Query::Select(QuerySource::Table(table), _, Filter::Where(predicates)) => {
+                assert_eq!(table, "users");
+                assert_eq!(predicates.len(), 1);
+                assert!(matches!(predicates[0].expr, SelectExpr::Cast { .. }));
+                assert_eq!(predicates[0].literal, "'42'");
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        let query: Query =
+            "SELECT * FROM users WHERE last_name = 'Smith AND Sons' AND age > 3".into();
+        match query {
+            Query::Select(_, _, Filter::Where(predicates)) => {
+                assert_eq!(predicates.len(), 2);
+                assert_eq!(predicates[0].literal, "'Smith AND Sons'");
+                assert_eq!(predicates[1].expr, SelectExpr::Column("age".to_string()));
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        let query: Query = "SELECT * FROM users".into();
+        assert!(matches!(query, Query::Select(_, _, Filter::All)));
+        let query: Query = "SELECT * FROM users WHERE age".into();
+        assert!(matches!(query, Query::Select(_, _, Filter::Invalid)));
+        let query: Query = "SELECT * FROM users WHERE age > 3 AND name".into();
+        assert!(matches!(query, Query::Select(_, _, Filter::Invalid)));
+        let query: Query = "SELECT * FROM users LIMIT 1".into();
+        assert!(matches!(query, Query::Select(_, _, Filter::Invalid)));
+    }
+
+    #[test]
+    fn strip_comments() {
+        use super::strip_comments;
+
+        assert_eq!(strip_comments("-- just a comment"), "");
+        assert_eq!(
+            strip_comments("SELECT * FROM users -- all of them"),
+            "SELECT * FROM users "
+        );
+        assert_eq!(
+            strip_comments("SELECT * -- everything\nFROM users"),
+            "SELECT * \nFROM users"
+        );
+        assert_eq!(
+            strip_comments("SELECT /* the\ncolumns */* FROM users"),
+            "SELECT  * FROM users"
+        );
+        assert_eq!(
+            strip_comments("SELECT /* outer /* inner */ still outer */ id FROM users"),
+            "SELECT   id FROM users"
+        );
+        assert_eq!(
+            strip_comments("INSERT INTO users (name) VALUES ('a -- b /* c */')"),
+            "INSERT INTO users (name) VALUES ('a -- b /* c */')"
+        );
+        assert_eq!(strip_comments("SELECT 1 /* unterminated"), "SELECT 1  ");
+
+        let query: Query = "SELECT * FROM users /* comment */ -- another".into();
+        assert!(
+            matches!(query, Query::Select(QuerySource::Table(table), _, _) if table == "users")
+        );
+    }
+
+    #[test]
+    fn parse_transaction_queries() {
+        assert!(matches!(Query::from("BEGIN"), Query::Begin));
+        assert!(matches!(Query::from("BEGIN TRANSACTION"), Query::Begin));
+        assert!(matches!(Query::from("COMMIT"), Query::Commit));
+        assert!(matches!(Query::from("ROLLBACK"), Query::Rollback));
+        assert!(matches!(Query::from("SAVEPOINT sp1"), Query::Savepoint(name) if name == "sp1"));
+        assert!(matches!(
+            Query::from("ROLLBACK TO SAVEPOINT sp1"),
+            Query::RollbackToSavepoint(name) if name == "sp1"
+        ));
+        assert!(matches!(
+            Query::from("ROLLBACK TO sp1"),
+            Query::RollbackToSavepoint(name) if name == "sp1"
+        ));
+        assert!(matches!(
+            Query::from("RELEASE SAVEPOINT sp1"),
+            Query::ReleaseSavepoint(name) if name == "sp1"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_savepoint_without_name() {
+        let _query = Query::from("SAVEPOINT");
+    }
+
+    #[test]
+    fn parse_backup_queries() {
+        assert!(matches!(
+            Query::from("BACKUP DATABASE TO 'backups/city db.bak'"),
+            Query::Backup(path) if path == "backups/city db.bak"
+        ));
+        assert!(matches!(
+            Query::from("RESTORE DATABASE FROM 'backup.db'"),
+            Query::Restore(path) if path == "backup.db"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_backup_without_quotes() {
+        let _query = Query::from("BACKUP DATABASE TO backup.db");
+    }
+
+    #[test]
+    fn parse_set_query() {
+        match Query::from("SET SLOW_QUERY_THRESHOLD = 50000") {
+            Query::Set { name, value } => {
+                assert_eq!(name, "slow_query_threshold");
+                assert_eq!(value, "50000");
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_set_without_value() {
+        let _query = Query::from("SET SLOW_QUERY_THRESHOLD");
+    }
+
+    #[test]
+    fn parse_copy_query() {
+        assert!(matches!(
+            Query::from("COPY users FROM STDIN BINARY"),
+            Query::CopyBinary(table) if table == "users"
+        ));
+    }
+
+    #[test]
+    fn parse_procedure_queries() {
+        match Query::from("CREATE PROCEDURE add_user AS 'db.execute(''SELECT * FROM users'')'") {
+            Query::CreateProcedure { name, body } => {
+                assert_eq!(name, "add_user");
+                assert_eq!(body, "db.execute('SELECT * FROM users')");
+            }
+            query => panic!("Unexpected query {:?}", query),
+        }
+        match Query::from("CALL add_user(7, 'Oslo, Norway')") {
+            Query::CallProcedure { name, args } => {
+                assert_eq!(name, "add_user");
+                assert_eq!(args, ["7", "Oslo, Norway"]);
+            }
+            query => panic!("Unexpected query {:?}", query),
+        }
+        assert!(matches!(
+            Query::from("CALL noop()"),
+            Query::CallProcedure { args, .. } if args.is_empty()
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_call_without_parentheses() {
+        let _query = Query::from("CALL add_user");
+    }
+
+    #[test]
+    fn parse_copy_file_query() {
+        assert!(matches!(
+            Query::from("COPY users TO 'dumps/users.bin' BINARY"),
+            Query::CopyBinaryTo { table, path } if table == "users" && path == "dumps/users.bin"
+        ));
+        assert!(matches!(
+            Query::from("COPY users FROM 'users.bin' BINARY"),
+            Query::CopyBinaryFrom { table, path } if table == "users" && path == "users.bin"
+        ));
+    }
+
+    #[test]
+    fn parse_copy_table_query() {
+        assert!(matches!(
+            Query::from("COPY TABLE users TO users_backup"),
+            Query::CopyTable { source, destination, filter: Filter::All }
+                if source == "users" && destination == "users_backup"
+        ));
+        assert!(matches!(
+            Query::from("COPY TABLE users TO active_users WHERE active = 1"),
+            Query::CopyTable { filter: Filter::Where(predicates), .. } if predicates.len() == 1
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_copy_to_unquoted_path() {
+        let _query = Query::from("COPY users TO users.bin BINARY");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_copy_without_binary() {
+        let _query = Query::from("COPY users FROM STDIN");
+    }
+
+    #[test]
+    fn parse_restore_from_wal_query() {
+        assert!(matches!(
+            Query::from("RESTORE FROM WAL AT LSN 42"),
+            Query::RestoreFromWal { lsn: 42 }
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_restore_from_wal_without_lsn() {
+        let _query = Query::from("RESTORE FROM WAL AT LSN");
+    }
+
+    #[test]
+    fn parse_checkpoint_query() {
+        assert!(matches!(Query::from("CHECKPOINT"), Query::Checkpoint));
+    }
+
+    #[test]
+    fn parse_show_query() {
+        assert!(matches!(Query::from("SHOW ALL"), Query::Show(None)));
+        assert!(matches!(
+            Query::from("SHOW PAGE_CACHE_SIZE"),
+            Query::Show(Some(name)) if name == "page_cache_size"
+        ));
+    }
+
+    #[test]
+    fn parse_show_create_table_query() {
+        assert!(matches!(
+            Query::from("SHOW CREATE TABLE users"),
+            Query::ShowCreateTable(table) if table == "users"
+        ));
+    }
+
+    #[test]
+    fn parse_analyze_query() {
+        assert!(matches!(
+            Query::from("SHOW TABLE STATS users"),
+            Query::ShowTableStats(table) if table == "users"
+        ));
+        assert!(matches!(
+            Query::from("ANALYZE TABLE users"),
+            Query::Analyze(table) if table == "users"
+        ));
+        assert!(matches!(
+            Query::from("ANALYZE COLUMN users.city"),
+            Query::AnalyzeColumn { table, column } if table == "users" && column == "city"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_analyze_column_without_table() {
+        let _query = Query::from("ANALYZE COLUMN city");
+    }
+
+    #[test]
+    fn parse_create_index_query() {
+        match Query::from("CREATE INDEX idx_city ON users (city)") {
+            Query::CreateIndex {
+                name,
+                table,
+                columns,
+                kind,
+                predicate,
+            } => {
+                assert_eq!(name, "idx_city");
+                assert_eq!(table, "users");
+                assert_eq!(columns, vec!["city"]);
+                assert_eq!(kind, IndexKind::BTree);
+                assert_eq!(predicate, None);
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        match Query::from("CREATE INDEX idx_last_first ON users (last_name, first_name)") {
+            Query::CreateIndex { columns, .. } => {
+                assert_eq!(columns, vec!["last_name", "first_name"]);
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        match Query::from("CREATE INDEX idx_active_users ON users (id) WHERE active = 1") {
+            Query::CreateIndex {
+                columns, predicate, ..
+            } => {
+                assert_eq!(columns, vec!["id"]);
+                assert_eq!(predicate.unwrap(), "active = 1");
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        match Query::from("CREATE HASH INDEX idx_id ON users (id)") {
+            Query::CreateIndex {
+                name,
+                columns,
+                kind,
+                ..
+            } => {
+                assert_eq!(name, "idx_id");
+                assert_eq!(columns, vec!["id"]);
+                assert_eq!(kind, IndexKind::Hash);
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_rebuild_index_query() {
+        assert!(matches!(
+            Query::from("REBUILD INDEX idx_user_id ON users"),
+            Query::RebuildIndex { index_name, table } if index_name == "idx_user_id" && table == "users"
+        ));
+        assert!(matches!(
+            Query::from("REBUILD ALL INDEXES ON users"),
+            Query::RebuildAllIndexes(table) if table == "users"
+        ));
+    }
+
+    #[test]
+    fn parse_compress_column_query() {
+        assert!(matches!(
+            Query::from("COMPRESS COLUMN id ON users"),
+            Query::CompressColumn { table, column } if table == "users" && column == "id"
+        ));
+        let access = Query::from("COMPRESS COLUMN id ON users");
+        assert_eq!(access.table_accesses(), [("users", TableLock::Exclusive)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_compress_column_without_table() {
+        let _query = Query::from("COMPRESS COLUMN id");
+    }
+
+    #[test]
+    fn parse_debug_dump_query() {
+        assert!(matches!(
+            Query::from("DEBUG DUMP TABLE users"),
+            Query::DebugDump(table) if table == "users"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_debug_dump_without_table() {
+        let _query = Query::from("DEBUG DUMP TABLE");
+    }
+
+    #[test]
+    fn parse_import_schema_query() {
+        assert!(matches!(
+            Query::from("IMPORT SCHEMA FROM 'db/schema.sql'"),
+            Query::ImportSchema(path) if path == "db/schema.sql"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_import_schema_unquoted_path() {
+        let _query = Query::from("IMPORT SCHEMA FROM schema.sql");
+    }
+
+    #[test]
+    fn parse_table_checksum_query() {
+        assert!(matches!(
+            Query::from("TABLE CHECKSUM users"),
+            Query::Checksum(table) if table == "users"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_table_checksum_of_two_tables() {
+        let _query = Query::from("TABLE CHECKSUM users orders");
+    }
+
+    #[test]
+    fn parse_reindex_query() {
+        assert!(matches!(Query::from("REINDEX ALL"), Query::ReindexAll));
+        assert!(matches!(
+            Query::from("REINDEX users"),
+            Query::Reindex(table) if table == "users"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_reindex_without_table() {
+        let _query = Query::from("REINDEX");
+    }
+
+    #[test]
+    fn parse_compact_database_query() {
+        assert!(matches!(
+            Query::from("COMPACT DATABASE"),
+            Query::CompactDatabase
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_compact_database_with_name() {
+        let _query = Query::from("COMPACT DATABASE city_db");
+    }
+
+    #[test]
+    fn parse_explain_indexes_query() {
+        assert!(matches!(
+            Query::from("EXPLAIN INDEXES FOR users"),
+            Query::ExplainIndexes(table) if table == "users"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_explain_indexes_without_table() {
+        let _query = Query::from("EXPLAIN INDEXES FOR");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_rebuild_index_without_table() {
+        let _query = Query::from("REBUILD INDEX idx_user_id");
+    }
+
+    #[test]
+    fn parse_explain_query() {
+        match Query::from("EXPLAIN SELECT * FROM users WHERE id = 1") {
+            Query::Explain(query) => {
+                assert!(matches!(
+                    *query,
+                    Query::Select(QuerySource::Table(_), _, Filter::Where(_))
+                ));
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+        assert!(matches!(
+            Query::from("EXPLAIN ANALYZE SELECT * FROM users"),
+            Query::ExplainAnalyze(query) if matches!(*query, Query::Select(..))
+        ));
+        assert!(matches!(
+            Query::from("EXPLAIN FORMAT=JSON SELECT * FROM users WHERE id = 5"),
+            Query::ExplainJson(query) if matches!(*query, Query::Select(..))
+        ));
+    }
+
+    #[test]
+    fn parse_merge_query() {
+        let query = Query::from(
+            "MERGE INTO users USING staged ON staged.id = users.id \
+             WHEN MATCHED THEN UPDATE SET users.name = staged.name, users.visits = 0 \
+             WHEN NOT MATCHED THEN INSERT (id, name) VALUES (staged.id, 'new')",
+        );
+        match query {
+            Query::Merge {
+                target,
+                source,
+                join_condition,
+                matched_action,
+                not_matched_action,
+            } => {
+                assert_eq!((target.as_str(), source.as_str()), ("users", "staged"));
+                assert_eq!(join_condition, ("id".to_string(), "id".to_string()));
+                assert_eq!(
+                    matched_action.unwrap(),
+                    vec![
+                        ("name".to_string(), MergeValue::Source("name".to_string())),
+                        ("visits".to_string(), MergeValue::Literal(b"0".to_vec())),
+                    ]
+                );
+                let (columns, values) = not_matched_action.unwrap();
+                assert_eq!(columns, vec!["id", "name"]);
+                assert_eq!(
+                    values,
+                    vec![
+                        MergeValue::Source("id".to_string()),
+                        MergeValue::Literal(b"new".to_vec())
+                    ]
+                );
+            }
+            query => panic!("Expected MERGE, got {:?}", query),
+        }
+        assert!(matches!(
+            Query::from("MERGE INTO users USING staged ON users.id = staged.id WHEN MATCHED THEN UPDATE SET name = 'x'"),
+            Query::Merge { not_matched_action: None, .. }
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_merge_without_action() {
+        let _query = Query::from("MERGE INTO users USING staged ON users.id = staged.id");
+    }
+
+    #[test]
+    fn parse_insert_select_query() {
+        match Query::from(
+            "INSERT INTO orders_archive SELECT * FROM orders WHERE created_at < 20230101",
+        ) {
+            Query::InsertSelect {
+                dest_table,
+                source_query,
+            } => {
+                assert_eq!(dest_table, "orders_archive");
+                assert!(matches!(
+                    *source_query,
+                    Query::Select(QuerySource::Table(table), super::Scope::All, Filter::Where(predicates))
+                        if table == "orders" && predicates.len() == 1
+                ));
+            }
+            query => panic!("Expected INSERT ... SELECT, got {:?}", query),
+        }
+    }
+
+    #[test]
+    fn parse_insert_query() {
+        let query: Query = "INSERT INTO users (id, account_id) VALUES (1,2) (3,4)".into();
+        println!("{:?}", query);
+        match query {
+            Query::Insert(query_source, column_list, data, _) => {
+                match query_source {
+                    QuerySource::IntoTable(table) => {
+                        assert_eq!(table, "users");
+                    }
+                    _ => {
+                        panic!("Invalid query source");
+                    }
+                }
+                match column_list {
+                    super::ColumnList::Columns(columns) => {
+                        assert_eq!(columns, vec!["id", "account_id"]);
+                    }
+                    _ => {
+                        panic!("Invalid columns");
+                    }
+                }
+                match data {
+                    super::ValueList::Values(data) => {
+                        assert_eq!(data.len(), 2);
+                        assert_eq!(data[0], vec![b"1".to_vec(), b"2".to_vec()]);
+                        assert_eq!(data[1], vec![b"3".to_vec(), b"4".to_vec()]);
+                    }
+                    _ => {
+                        panic!("Invalid data");
+                    }
+                }
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_alter_table_rename_column_query() {
+        let query: Query = "ALTER TABLE users RENAME COLUMN usr_id TO user_id".into();
+        match query {
+            Query::AlterTableRenameColumn {
+                table,
+                old_name,
+                new_name,
+            } => {
+                assert_eq!(table, "users");
+                assert_eq!(old_name, "usr_id");
+                assert_eq!(new_name, "user_id");
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_rename_table_query() {
+        let query: Query = "RENAME TABLE users TO customers".into();
+        match query {
+            Query::RenameTable { from, to } => {
+                assert_eq!(from, "users");
+                assert_eq!(to, "customers");
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_create_table_query() {
+        let query: Query =
+            "CREATE TABLE users (id INT 11, email VARCHAR 32 DEFAULT 'unknown' UNIQUE, age INT 3 DEFAULT 0)"
+                .into();
+        match query {
+            Query::CreateTable {
+                table,
+                columns,
+                if_not_exists,
+            } => {
+                assert_eq!(table, "users");
+                assert!(!if_not_exists);
+                match columns {
+                    super::ColumnDefinitionList::Definitions(columns) => {
+                        assert_eq!(columns.len(), 3);
+                        assert_eq!(&columns[0].name[..3], b"id\0");
+                        assert_eq!(columns[0].length, 11);
+                        assert_eq!(columns[0].default_value, None);
+                        assert_eq!(&columns[1].name[..6], b"email\0");
+                        assert_eq!(columns[1].length, 32);
+                        assert_eq!(columns[1].default_value, Some(b"unknown".to_vec()));
+                        assert_eq!(columns[2].default_value, Some(b"0".to_vec()));
+                        assert!(!columns[0].unique);
+                        assert!(columns[1].unique);
+                    }
+                    _ => {
+                        panic!("Invalid column definitions");
+                    }
+                }
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_create_and_drop_table_if_exists() {
+        let query: Query = "CREATE TABLE IF NOT EXISTS users (id INT 11)".into();
+        match query {
+            Query::CreateTable {
+                table,
+                columns: super::ColumnDefinitionList::Definitions(columns),
+                if_not_exists,
+            } => {
+                assert_eq!(table, "users");
+                assert_eq!(columns.len(), 1);
+                assert!(if_not_exists);
+            }
+            _ => panic!("Invalid query"),
+        }
+
+        let query: Query = "DROP TABLE IF EXISTS users".into();
+        assert!(matches!(
+            query,
+            Query::DropTable { table, if_exists: true } if table == "users"
+        ));
+        let query: Query = "DROP TABLE users".into();
+        assert!(matches!(
+            query,
+            Query::DropTable { table, if_exists: false } if table == "users"
+        ));
+    }
+
+    #[test]
+    fn parse_create_table_query_with_timestamp() {
+        let query: Query =
+            "CREATE TABLE events (id INT 11, created_at TIMESTAMP DEFAULT '2024-01-15T10:30:00Z')"
+                .into();
+        match query {
+            Query::CreateTable {
+                columns: super::ColumnDefinitionList::Definitions(columns),
+                ..
+            } => {
+                assert_eq!(columns[1].column_type, super::ColumnType::Timestamp);
+                assert_eq!(columns[1].length, 8);
+                assert_eq!(
+                    columns[1]
+                        .column_type
+                        .format(columns[1].default_value.as_ref().unwrap()),
+                    "2024-01-15T10:30:00.000000Z"
+                );
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        for query in [
+            "CREATE TABLE events (created_at TIMESTAMP 8)",
+            "CREATE TABLE events (created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP)",
+        ] {
+            match Query::from(query) {
+                Query::CreateTable { columns, .. } => {
+                    assert!(matches!(columns, super::ColumnDefinitionList::Invalid));
                 }
                 _ => {
-                    panic!("Invalid query source");
+                    panic!("Invalid query");
                 }
-            },
+            }
+        }
+    }
+
+    #[test]
+    fn parse_create_table_query_with_decimal() {
+        let query: Query =
+            "CREATE TABLE prices (price DECIMAL(10, 2) DEFAULT '1.5', rate DECIMAL(4,4))".into();
+        match query {
+            Query::CreateTable {
+                columns: super::ColumnDefinitionList::Definitions(columns),
+                ..
+            } => {
+                assert_eq!(columns[0].column_type, super::ColumnType::Decimal(10, 2));
+                assert_eq!(columns[0].length, 8);
+                assert_eq!(
+                    columns[0]
+                        .column_type
+                        .format(columns[0].default_value.as_ref().unwrap()),
+                    "1.50"
+                );
+                assert_eq!(columns[1].column_type, super::ColumnType::Decimal(4, 4));
+            }
             _ => {
                 panic!("Invalid query");
             }
@@ -212,38 +2830,297 @@ mod tests {
     }
 
     #[test]
-    fn parse_insert_query() {
-        let query: Query = "INSERT INTO users (id, account_id) VALUES (1,2) (3,4)".into();
-        println!("{:?}", query);
+    fn parse_create_table_query_with_primary_key() {
+        let query: Query = "CREATE TABLE users (id INT 11 PRIMARY KEY, email VARCHAR 32)".into();
         match query {
-            Query::Insert(query_source, column_list, data) => {
-                match query_source {
-                    QuerySource::IntoTable(table) => {
-                        assert_eq!(table, "users");
-                    }
-                    _ => {
-                        panic!("Invalid query source");
-                    }
+            Query::CreateTable {
+                columns: super::ColumnDefinitionList::Definitions(columns),
+                ..
+            } => {
+                assert!(columns[0].primary_key);
+                assert!(!columns[1].primary_key);
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        let query: Query =
+            "CREATE TABLE users (id INT 11 PRIMARY KEY, email VARCHAR 32 PRIMARY KEY)".into();
+        match query {
+            Query::CreateTable { columns, .. } => {
+                assert!(matches!(columns, super::ColumnDefinitionList::Invalid));
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_create_table_query_with_foreign_key() {
+        let query: Query =
+            "CREATE TABLE orders (id INT 11, user_id INT 11, FOREIGN KEY (user_id) REFERENCES users (id))"
+                .into();
+        match query {
+            Query::CreateTable {
+                columns: super::ColumnDefinitionList::Definitions(columns),
+                ..
+            } => {
+                assert_eq!(columns.len(), 2);
+                assert_eq!(columns[0].references, None);
+                assert_eq!(
+                    columns[1].references,
+                    Some(("users".to_string(), "id".to_string()))
+                );
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        let query: Query =
+            "CREATE TABLE orders (id INT 11, FOREIGN KEY (user_id) REFERENCES users (id))".into();
+        match query {
+            Query::CreateTable { columns, .. } => {
+                assert!(matches!(columns, super::ColumnDefinitionList::Invalid));
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_create_table_query_with_check() {
+        let query: Query = "CREATE TABLE products (price INT 11 CHECK (price > 0))".into();
+        match query {
+            Query::CreateTable {
+                columns: super::ColumnDefinitionList::Definitions(columns),
+                ..
+            } => {
+                let check_expr = columns[0].check_expr.unwrap();
+                assert_eq!(&check_expr[..10], b"price > 0\0");
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        let invalid_queries = [
+            "CREATE TABLE products (price INT 11 CHECK (other > 0))",
+            "CREATE TABLE products (price INT 11 CHECK (price))",
+            "CREATE TABLE products (price INT 11 CHECK price > 0)",
+        ];
+        for query in invalid_queries {
+            let query: Query = query.into();
+            match query {
+                Query::CreateTable { columns, .. } => {
+                    assert!(matches!(columns, super::ColumnDefinitionList::Invalid));
                 }
-                match column_list {
-                    super::ColumnList::Columns(columns) => {
-                        assert_eq!(columns, vec!["id", "account_id"]);
-                    }
-                    _ => {
-                        panic!("Invalid columns");
-                    }
+                _ => {
+                    panic!("Invalid query");
                 }
-                match data {
-                    super::ValueList::Values(data) => {
-                        let expected: Vec<Vec<Vec<u8>>> = vec![
-                            vec!["1".as_bytes().to_vec(), "2".as_bytes().to_vec()],
-                            vec!["6".as_bytes().to_vec(), "4".as_bytes().to_vec()],
-                        ];
-                        assert_eq!(data, expected)
-                    }
-                    _ => {
-                        panic!("Invalid data");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_create_table_query_with_dictionary() {
+        let query: Query = "CREATE TABLE orders (id INT 11, status VARCHAR 2 DICTIONARY)".into();
+        match query {
+            Query::CreateTable {
+                columns: super::ColumnDefinitionList::Definitions(columns),
+                ..
+            } => {
+                assert!(!columns[0].dictionary_encoded);
+                assert!(columns[1].dictionary_encoded);
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_create_table_query_with_invalid_default() {
+        let invalid_queries = [
+            "CREATE TABLE users (id INT 11 DEFAULT abc)",
+            "CREATE TABLE users (email VARCHAR 3 DEFAULT 'unknown')",
+            "CREATE TABLE users (email VARCHAR 32 DEFAULT)",
+        ];
+        for query in invalid_queries {
+            let query: Query = query.into();
+            match query {
+                Query::CreateTable { columns, .. } => {
+                    assert!(matches!(columns, super::ColumnDefinitionList::Invalid));
+                }
+                _ => {
+                    panic!("Invalid query");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parse_sequence_queries() {
+        let query: Query = "CREATE SEQUENCE seq_events START 10 INCREMENT 5".into();
+        match query {
+            Query::CreateSequence {
+                name,
+                start,
+                increment,
+            } => {
+                assert_eq!(name, "seq_events");
+                assert_eq!(start, 10);
+                assert_eq!(increment, 5);
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        let query: Query = "CREATE SEQUENCE seq_events".into();
+        assert!(matches!(
+            query,
+            Query::CreateSequence {
+                start: 1,
+                increment: 1,
+                ..
+            }
+        ));
+
+        let query: Query = "ALTER SEQUENCE seq_events RESTART WITH 7".into();
+        assert!(matches!(
+            query,
+            Query::AlterSequenceRestart { value: Some(7), .. }
+        ));
+        let query: Query = "ALTER SEQUENCE seq_events RESTART".into();
+        assert!(matches!(
+            query,
+            Query::AlterSequenceRestart { value: None, .. }
+        ));
+
+        let query: Query = "DROP SEQUENCE seq_events".into();
+        match query {
+            Query::DropSequence(name) => assert_eq!(name, "seq_events"),
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_insert_query_with_nextval() {
+        let query: Query =
+            "INSERT INTO events (id, name) VALUES (NEXTVAL('seq_events'), 'a, b')".into();
+        match query {
+            Query::Insert(_, _, super::ValueList::Values(data), None) => {
+                assert_eq!(data[0].len(), 2);
+                assert_eq!(
+                    super::nextval_sequence(&data[0][0]),
+                    Some("seq_events".to_string())
+                );
+                assert_eq!(super::nextval_sequence(&data[0][1]), None);
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_insert_query_with_returning() {
+        let query: Query =
+            "INSERT INTO users (id, name) VALUES (1, 'Alice') (2, 'Bob') RETURNING id, name".into();
+        match query {
+            Query::Insert(
+                _,
+                _,
+                super::ValueList::Values(data),
+                Some(super::Scope::Expressions(expressions)),
+            ) => {
+                assert_eq!(data.len(), 2);
+                assert_eq!(data[1], vec![b"2".to_vec(), b"'Bob'".to_vec()]);
+                assert_eq!(expressions.len(), 2);
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        let query: Query = "INSERT INTO users (id) VALUES ('RETURNING') RETURNING *".into();
+        match query {
+            Query::Insert(_, _, super::ValueList::Values(data), Some(super::Scope::All)) => {
+                assert_eq!(data, vec![vec![b"'RETURNING'".to_vec()]]);
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_with_query() {
+        let query: Query =
+            "WITH active_users AS (SELECT FROM users WHERE status = 1) SELECT id FROM active_users"
+                .into();
+        match query {
+            Query::With { name, cte, query } => {
+                assert_eq!(name, "active_users");
+                assert!(matches!(
+                    *cte,
+                    Query::Select(QuerySource::Table(table), super::Scope::All, super::Filter::Where(_))
+                        if table == "users"
+                ));
+                assert!(matches!(
+                    *query,
+                    Query::Select(QuerySource::Cte(name), super::Scope::Expressions(_), super::Filter::All)
+                        if name == "active_users"
+                ));
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+
+        // Other tables keep their source.
+        let query: Query = "WITH a AS (SELECT * FROM users) SELECT * FROM users".into();
+        assert!(matches!(
+            query,
+            Query::With { query, .. } if matches!(*query, Query::Select(QuerySource::Table(_), _, _))
+        ));
+    }
+
+    #[test]
+    fn parse_union_query() {
+        let query: Query =
+            "SELECT id FROM users WHERE name = 'UNION' UNION ALL SELECT * FROM admins \
+                            UNION SELECT id FROM guests"
+                .into();
+        match query {
+            Query::Union {
+                left,
+                right,
+                distinct: true,
+            } => {
+                assert!(matches!(
+                    *right,
+                    Query::Select(QuerySource::Table(table), _, super::Filter::All) if table == "guests"
+                ));
+                match *left {
+                    Query::Union {
+                        left,
+                        right,
+                        distinct: false,
+                    } => {
+                        assert!(matches!(
+                            *left,
+                            Query::Select(_, _, super::Filter::Where(_))
+                        ));
+                        assert!(matches!(*right, Query::Select(_, super::Scope::All, _)));
                     }
+                    _ => panic!("Invalid query"),
                 }
             }
             _ => {
@@ -252,11 +3129,325 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_intersect_and_except_queries() {
+        let query: Query =
+            "SELECT id FROM users INTERSECT SELECT id FROM admins EXCEPT SELECT id FROM guests"
+                .into();
+        match query {
+            Query::Except { left, right } => {
+                assert!(matches!(*left, Query::Intersect { .. }));
+                assert!(matches!(
+                    *right,
+                    Query::Select(QuerySource::Table(table), _, _) if table == "guests"
+                ));
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_view_queries() {
+        match Query::from("CREATE VIEW rich AS SELECT id, name FROM users WHERE balance > 100") {
+            Query::CreateView { name, query } => {
+                assert_eq!(name, "rich");
+                assert_eq!(query, "SELECT id, name FROM users WHERE balance > 100");
+            }
+            query => panic!("Unexpected query {:?}", query),
+        }
+        assert!(matches!(Query::from("DROP VIEW rich"), Query::DropView(name) if name == "rich"));
+        assert!(matches!(Query::from("SHOW VIEWS"), Query::ShowViews));
+        assert!(matches!(Query::from("SHOW CONFIG"), Query::ShowConfig));
+        assert!(matches!(
+            Query::from("SHOW DISK USAGE"),
+            Query::ShowDiskUsage
+        ));
+        assert!(matches!(
+            Query::from("FLUSH QUERY LOG"),
+            Query::FlushQueryLog
+        ));
+        assert!(matches!(
+            Query::from("SHOW QUERY LOG LAST 3"),
+            Query::ShowQueryLog(3)
+        ));
+        assert!(matches!(
+            Query::from("SHOW QUERY LOG"),
+            Query::ShowQueryLog(count) if count == DEFAULT_LAST_LINES
+        ));
+        assert!(matches!(
+            Query::from("CLEAR QUERY LOG"),
+            Query::ClearQueryLog
+        ));
+        assert!(matches!(
+            Query::from("SHOW PROCESSLIST"),
+            Query::ShowProcesslist
+        ));
+        assert!(matches!(Query::from("KILL 3"), Query::Kill(3)));
+        assert!(matches!(Query::from("REFRESH"), Query::Refresh));
+        assert!(matches!(
+            Query::from("LOCK TABLE users WRITE"),
+            Query::LockTable { table, mode: TableLock::Exclusive } if table == "users"
+        ));
+        assert!(matches!(
+            Query::from("LOCK TABLE users READ"),
+            Query::LockTable {
+                mode: TableLock::Shared,
+                ..
+            }
+        ));
+        assert!(matches!(
+            Query::from("UNLOCK TABLE users"),
+            Query::UnlockTable(table) if table == "users"
+        ));
+
+        match Query::from(
+            "CREATE MATERIALIZED VIEW mv_active AS SELECT * FROM users WHERE active = 1",
+        ) {
+            Query::CreateMaterializedView { name, query } => {
+                assert_eq!(name, "mv_active");
+                assert_eq!(query, "SELECT * FROM users WHERE active = 1");
+            }
+            query => panic!("Unexpected query {:?}", query),
+        }
+        assert!(matches!(
+            Query::from("REFRESH MATERIALIZED VIEW mv_active"),
+            Query::RefreshMaterializedView(name) if name == "mv_active"
+        ));
+    }
+
+    #[test]
+    fn fingerprint_replaces_literals() {
+        assert_eq!(
+            fingerprint_query("SELECT * FROM users WHERE id = 1"),
+            "SELECT * FROM users WHERE id = ?"
+        );
+        assert_eq!(
+            fingerprint_query("SELECT * FROM users WHERE id = 1"),
+            fingerprint_query("SELECT * FROM users WHERE id = 999")
+        );
+        assert_eq!(
+            fingerprint_query("SELECT * FROM users WHERE name = 'Ann'"),
+            fingerprint_query("SELECT *  FROM users\nWHERE name = 'O''Brien, 42'")
+        );
+        assert_eq!(
+            fingerprint_query("INSERT INTO t2 (id,price) VALUES (10,2.50) (11,'x')"),
+            "INSERT INTO t2 (id,price) VALUES (?,?) (?,?)"
+        );
+        assert_ne!(
+            fingerprint_query("SELECT column1 FROM users"),
+            fingerprint_query("SELECT column2 FROM users")
+        );
+        assert_eq!(
+            split_literals("SELECT * FROM users WHERE id > 5 AND name = 'it''s'").1,
+            ["5", "'it''s'"]
+        );
+    }
+
+    #[test]
+    fn parse_trigger_queries() {
+        match Query::from(
+            "CREATE TRIGGER audit AFTER INSERT ON users EXECUTE 'INSERT INTO log (event) VALUES (''insert'')'",
+        ) {
+            Query::CreateTrigger {
+                name,
+                event,
+                table,
+                body,
+            } => {
+                assert_eq!(name, "audit");
+                assert_eq!(event, TriggerEvent::Insert);
+                assert_eq!(table, "users");
+                assert_eq!(body, "INSERT INTO log (event) VALUES ('insert')");
+            }
+            query => panic!("Unexpected query {:?}", query),
+        }
+        assert!(matches!(
+            Query::from("CREATE TRIGGER audit AFTER DELETE ON users EXECUTE 'log_delete.sql'"),
+            Query::CreateTrigger { event: TriggerEvent::Delete, body, .. } if body == "log_delete.sql"
+        ));
+        assert!(matches!(
+            Query::from("DROP TRIGGER audit"),
+            Query::DropTrigger { name } if name == "audit"
+        ));
+    }
+
+    #[test]
+    fn parse_user_queries() {
+        assert!(matches!(
+            Query::from("CREATE USER alice WITH PASSWORD 'it''s secret'"),
+            Query::CreateUser { name, password } if name == "alice" && password == "it's secret"
+        ));
+        assert!(matches!(
+            Query::from("DROP USER alice"),
+            Query::DropUser(name) if name == "alice"
+        ));
+    }
+
+    #[test]
+    fn parse_prepared_queries() {
+        assert!(matches!(
+            Query::from("PREPARE get_user AS 'SELECT * FROM users WHERE name = ''Ann'' AND id = ?'"),
+            Query::Prepare { name, sql }
+                if name == "get_user" && sql == "SELECT * FROM users WHERE name = 'Ann' AND id = ?"
+        ));
+        assert!(matches!(
+            Query::from("EXECUTE get_user (42, 'a, b')"),
+            Query::Execute { name, args } if name == "get_user" && args == ["42", "'a, b'"]
+        ));
+        assert!(matches!(
+            Query::from("EXECUTE all_users"),
+            Query::Execute { name, args } if name == "all_users" && args.is_empty()
+        ));
+    }
+
+    #[test]
+    fn parse_grant_queries() {
+        match Query::from("GRANT SELECT (id, name) ON users TO alice") {
+            Query::Grant {
+                operation,
+                columns,
+                table,
+                user,
+            } => {
+                assert_eq!(operation, GrantOperation::Select);
+                assert_eq!(columns, ["id", "name"]);
+                assert_eq!(table, "users");
+                assert_eq!(user, "alice");
+            }
+            query => panic!("Unexpected query {:?}", query),
+        }
+        assert!(matches!(
+            Query::from("GRANT INSERT ON users TO bob"),
+            Query::Grant { operation: GrantOperation::Insert, columns, .. } if columns == ["*"]
+        ));
+        assert!(matches!(
+            Query::from("REVOKE SELECT ON users FROM alice"),
+            Query::Revoke { operation: GrantOperation::Select, table, user }
+                if table == "users" && user == "alice"
+        ));
+        assert!(matches!(
+            Query::from("SHOW GRANTS FOR alice"),
+            Query::ShowGrants(user) if user == "alice"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_grant_without_user() {
+        let _query = Query::from("GRANT SELECT (id) ON users TO");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_trigger_before_insert() {
+        let _query = Query::from("CREATE TRIGGER audit BEFORE INSERT ON users EXECUTE 'log.sql'");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_refresh_without_materialized() {
+        let _query = Query::from("REFRESH VIEW mv_active");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_create_view_without_select() {
+        let _query = Query::from("CREATE VIEW rich AS DROP TABLE users");
+    }
+
+    #[test]
+    fn parse_upsert_query() {
+        match Query::from("INSERT OR REPLACE INTO users (id,name) VALUES (5,'Alice')") {
+            Query::Upsert(
+                QuerySource::IntoTable(table),
+                super::ColumnList::Columns(columns),
+                super::ValueList::Values(data),
+            ) => {
+                assert_eq!(table, "users");
+                assert_eq!(columns, vec!["id", "name"]);
+                assert_eq!(data, vec![vec![b"5".to_vec(), b"'Alice'".to_vec()]]);
+            }
+            _ => {
+                panic!("Invalid query");
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_insert_or_without_replace() {
+        let _query = Query::from("INSERT OR IGNORE INTO users (id) VALUES (5)");
+    }
+
     #[test]
     fn test_read_word_bufreader() {
         let data: &[u8] = "abcdef".as_bytes();
         let mut buf_reader = BufReader::new(data);
-        crate::query::read_word(&mut buf_reader);
-        assert_eq!(word, "SELECT".as_bytes());
+        let word = crate::query::read_word(&mut buf_reader);
+        assert_eq!(word.trim(), "abcdef");
+    }
+
+    #[test]
+    fn test_read_word_at_eof() {
+        let mut buf_reader = BufReader::new("".as_bytes());
+        assert_eq!(crate::query::read_word(&mut buf_reader), "");
+
+        let mut buf_reader = BufReader::new("abc".as_bytes());
+        assert_eq!(crate::query::read_word(&mut buf_reader), "abc");
+        assert_eq!(crate::query::read_word(&mut buf_reader), "");
+    }
+
+    #[test]
+    fn test_read_word_followed_by_space() {
+        let mut buf_reader = BufReader::new("SELECT * FROM users".as_bytes());
+        assert_eq!(crate::query::read_word(&mut buf_reader), "SELECT ");
+        assert_eq!(crate::query::read_word(&mut buf_reader), "* ");
+        assert_eq!(crate::query::read_word(&mut buf_reader).trim(), "FROM");
+        assert_eq!(crate::query::read_word(&mut buf_reader), "users");
+    }
+
+    #[test]
+    fn parse_query_from_bufreader() {
+        let mut buf_reader = BufReader::new(
+            "INSERT INTO users (id, name) VALUES (1,'a; b');\nSELECT * FROM users; DROP TABLE users"
+                .as_bytes(),
+        );
+        match Query::from(&mut buf_reader) {
+            Query::Insert(QuerySource::IntoTable(table), _, super::ValueList::Values(data), _) => {
+                assert_eq!(table, "users");
+                assert_eq!(data.len(), 1);
+                assert_eq!(data[0][1], b"'a; b'");
+            }
+            query => panic!("Invalid query {:?}", query),
+        }
+        match Query::from(&mut buf_reader) {
+            Query::Select(QuerySource::Table(table), ..) => assert_eq!(table, "users"),
+            query => panic!("Invalid query {:?}", query),
+        }
+        match Query::from(&mut buf_reader) {
+            Query::DropTable { table, .. } => assert_eq!(table, "users"),
+            query => panic!("Invalid query {:?}", query),
+        }
+        assert_eq!(crate::query::read_word(&mut buf_reader), "");
+    }
+
+    #[test]
+    fn read_query_at_eof() {
+        let mut buf_reader = BufReader::new("SELECT * FROM users;\n  ".as_bytes());
+        assert!(matches!(
+            super::read_query(&mut buf_reader),
+            Some(Query::Select(QuerySource::Table(table), ..)) if table == "users"
+        ));
+        assert!(super::read_query(&mut buf_reader).is_none());
+        assert!(super::read_query(&mut buf_reader).is_none());
+        assert!(super::read_query(&mut BufReader::new("".as_bytes())).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid query")]
+    fn parse_query_from_bufreader_at_eof() {
+        let _query = Query::from(&mut BufReader::new("".as_bytes()));
     }
 }
@@ -0,0 +1,275 @@
+use std::cmp::Ordering;
+
+use crate::durability::table::{
+    compare_decimal, decode_decimal, decode_timestamp, parse_timestamp, ColumnDefinition,
+    ColumnType, Row,
+};
+
+use super::{
+    expression::{parse_date, SelectExpr},
+    unquote,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl Operator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Eq => "=",
+            Operator::NotEq => "!=",
+            Operator::Lt => "<",
+            Operator::LtEq => "<=",
+            Operator::Gt => ">",
+            Operator::GtEq => ">=",
+        }
+    }
+
+    fn matches(&self, ordering: Ordering) -> bool {
+        match self {
+            Operator::Eq => ordering == Ordering::Equal,
+            Operator::NotEq => ordering != Ordering::Equal,
+            Operator::Lt => ordering == Ordering::Less,
+            Operator::LtEq => ordering != Ordering::Greater,
+            Operator::Gt => ordering == Ordering::Greater,
+            Operator::GtEq => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// An `expression op literal` comparison such as `price > 0`, used by both
+/// `CHECK` constraints and `WHERE` clauses.
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    pub expr: SelectExpr,
+    pub operator: Operator,
+    pub literal: String,
+}
+
+impl Predicate {
+    pub fn parse(expression: &str) -> Option<Predicate> {
+        const OPERATORS: [(&str, Operator); 6] = [
+            (">=", Operator::GtEq),
+            ("<=", Operator::LtEq),
+            ("!=", Operator::NotEq),
+            (">", Operator::Gt),
+            ("<", Operator::Lt),
+            ("=", Operator::Eq),
+        ];
+
+        let position = expression.find(['>', '<', '!', '='])?;
+        let (expr, rest) = expression.split_at(position);
+        let (token, operator) = OPERATORS
+            .into_iter()
+            .find(|(token, _)| rest.starts_with(token))?;
+
+        let literal = rest[token.len()..].trim();
+        if literal.is_empty() {
+            return None;
+        }
+
+        Some(Predicate {
+            expr: SelectExpr::parse(expr)?,
+            operator,
+            literal: literal.to_string(),
+        })
+    }
+
+    /// Evaluates the expression against a row and compares the result.
+    pub fn matches(&self, row: &Row, columns: &[ColumnDefinition]) -> Result<bool, String> {
+        let value = self.expr.evaluate(row, columns)?;
+        Ok(self.evaluate(&value, &self.expr.value_type(columns)))
+    }
+
+    /// Whether every row matching this predicate also matches `other`: both
+    /// are the same comparison, or this one is an equality whose literal
+    /// `other` accepts.
+    pub fn implies(&self, other: &Predicate, columns: &[ColumnDefinition]) -> bool {
+        if self.expr != other.expr {
+            return false;
+        }
+        let literal = unquote(&self.literal);
+        if self.operator == other.operator && literal == unquote(&other.literal) {
+            return true;
+        }
+        let value_type = other.expr.value_type(columns);
+        let value = value_type.encode(literal.as_bytes()).unwrap_or_default();
+        self.operator == Operator::Eq && other.evaluate(&value, &value_type)
+    }
+
+    /// Compares a stored value against the literal. Numeric columns are
+    /// compared numerically, anything that does not parse fails the predicate.
+    /// Timestamps are stored as numbers and compared to an ISO-8601 literal,
+    /// decimals as scaled integers compared to a literal of any scale.
+    pub fn evaluate(&self, value: &[u8], column_type: &ColumnType) -> bool {
+        let binary_ordering = match column_type {
+            ColumnType::Timestamp => Some(
+                match (
+                    decode_timestamp(value),
+                    parse_timestamp(unquote(&self.literal)),
+                ) {
+                    (Some(value), Some(literal)) => Some(value.cmp(&literal)),
+                    _ => None,
+                },
+            ),
+            ColumnType::Decimal(_, scale) => Some(
+                decode_decimal(value)
+                    .and_then(|value| compare_decimal(value, *scale, &self.literal)),
+            ),
+            _ => None,
+        };
+        if let Some(ordering) = binary_ordering {
+            return ordering.is_some_and(|ordering| self.operator.matches(ordering));
+        }
+        let value = value.split(|b| *b == 0).next().unwrap_or_default();
+        let value = match std::str::from_utf8(value) {
+            Ok(value) => value,
+            Err(_) => return false,
+        };
+
+        let ordering = match column_type {
+            ColumnType::Int => match (value.parse::<i64>(), self.literal.parse::<i64>()) {
+                (Ok(value), Ok(literal)) => value.cmp(&literal),
+                _ => return false,
+            },
+            ColumnType::Float => match (value.parse::<f64>(), self.literal.parse::<f64>()) {
+                (Ok(value), Ok(literal)) => match value.partial_cmp(&literal) {
+                    Some(ordering) => ordering,
+                    None => return false,
+                },
+                _ => return false,
+            },
+            ColumnType::Date => {
+                let literal = self
+                    .literal
+                    .strip_prefix('\'')
+                    .and_then(|literal| literal.strip_suffix('\''))
+                    .unwrap_or(&self.literal);
+                match (parse_date(value), parse_date(literal)) {
+                    (Some(value), Some(literal)) => value.cmp(&literal),
+                    _ => return false,
+                }
+            }
+            ColumnType::Varchar => {
+                let literal = self
+                    .literal
+                    .strip_prefix('\'')
+                    .and_then(|literal| literal.strip_suffix('\''))
+                    .unwrap_or(&self.literal);
+                value.cmp(literal)
+            }
+            ColumnType::Timestamp | ColumnType::Decimal(..) => {
+                unreachable!("binary values are compared above")
+            }
+        };
+
+        self.operator.matches(ordering)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Operator, Predicate};
+    use crate::durability::table::{ColumnDefinition, ColumnType, Row};
+    use crate::query::expression::SelectExpr;
+
+    #[test]
+    fn parse_predicate() {
+        let predicate = Predicate::parse("price >= 10").unwrap();
+        assert_eq!(predicate.expr, SelectExpr::Column("price".to_string()));
+        assert_eq!(predicate.operator, Operator::GtEq);
+        assert_eq!(predicate.literal, "10");
+
+        let predicate = Predicate::parse("name!='bob'").unwrap();
+        assert_eq!(predicate.expr, SelectExpr::Column("name".to_string()));
+        assert_eq!(predicate.operator, Operator::NotEq);
+        assert_eq!(predicate.literal, "'bob'");
+
+        assert!(Predicate::parse("price").is_none());
+        assert!(Predicate::parse("> 0").is_none());
+        assert!(Predicate::parse("price >").is_none());
+    }
+
+    #[test]
+    fn evaluate_int_predicate() {
+        let predicate = Predicate::parse("price > 0").unwrap();
+        assert!(predicate.evaluate(b"5\0\0", &ColumnType::Int));
+        assert!(!predicate.evaluate(b"0", &ColumnType::Int));
+        assert!(!predicate.evaluate(b"-3", &ColumnType::Int));
+        assert!(!predicate.evaluate(b"abc", &ColumnType::Int));
+        assert!(!predicate.evaluate(b"", &ColumnType::Int));
+    }
+
+    #[test]
+    fn evaluate_varchar_predicate() {
+        let predicate = Predicate::parse("name = 'bob'").unwrap();
+        assert!(predicate.evaluate(b"bob\0\0", &ColumnType::Varchar));
+        assert!(!predicate.evaluate(b"alice", &ColumnType::Varchar));
+
+        let predicate = Predicate::parse("name < 'm'").unwrap();
+        assert!(predicate.evaluate(b"alice", &ColumnType::Varchar));
+        assert!(!predicate.evaluate(b"zed", &ColumnType::Varchar));
+    }
+
+    #[test]
+    fn evaluate_float_and_date_predicates() {
+        let predicate = Predicate::parse("price > 1.5").unwrap();
+        assert!(predicate.evaluate(b"2.25", &ColumnType::Float));
+        assert!(!predicate.evaluate(b"1.5", &ColumnType::Float));
+
+        let predicate = Predicate::parse("created_at <= '2023-01-01'").unwrap();
+        assert!(predicate.evaluate(b"2022-12-31", &ColumnType::Date));
+        assert!(!predicate.evaluate(b"2023-01-02", &ColumnType::Date));
+    }
+
+    #[test]
+    fn predicate_implies() {
+        let columns = vec![ColumnDefinition::new(
+            "active".to_string(),
+            ColumnType::Int,
+            1,
+        )];
+        let index = Predicate::parse("active >= 1").unwrap();
+        assert!(Predicate::parse("active >= 1")
+            .unwrap()
+            .implies(&index, &columns));
+        assert!(Predicate::parse("active = 2")
+            .unwrap()
+            .implies(&index, &columns));
+        assert!(!Predicate::parse("active = 0")
+            .unwrap()
+            .implies(&index, &columns));
+        assert!(!Predicate::parse("active > 5")
+            .unwrap()
+            .implies(&index, &columns));
+        assert!(!Predicate::parse("id = 2")
+            .unwrap()
+            .implies(&index, &columns));
+    }
+
+    #[test]
+    fn match_predicate_with_cast() {
+        let columns = vec![ColumnDefinition::new(
+            "created_at".to_string(),
+            ColumnType::Date,
+            10,
+        )];
+        let predicate = Predicate::parse("CAST(created_at AS INT) > 20230101").unwrap();
+
+        let row = Row {
+            data: vec![b"2023-06-15".to_vec()],
+        };
+        assert!(predicate.matches(&row, &columns).unwrap());
+        let row = Row {
+            data: vec![b"2022-06-15".to_vec()],
+        };
+        assert!(!predicate.matches(&row, &columns).unwrap());
+    }
+}
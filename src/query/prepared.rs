@@ -0,0 +1,135 @@
+use super::{Filter, Query, ValueList};
+
+/// Marks a literal to be filled in by `EXECUTE`.
+const PLACEHOLDER: &str = "?";
+
+/// A query parsed once by `PREPARE` and run by every `EXECUTE` of it. The
+/// `?` literals of its `WHERE` clauses and `VALUES` are the placeholders,
+/// numbered in the order they appear.
+#[derive(Debug, Clone)]
+pub struct PreparedQuery {
+    query: Query,
+    placeholders: usize,
+}
+
+/// Where the value of a placeholder goes.
+enum Slot<'a> {
+    Literal(&'a mut String),
+    Value(&'a mut Vec<u8>),
+}
+
+/// The placeholders of the query in order.
+fn placeholder_slots(query: &mut Query) -> Vec<Slot<'_>> {
+    match query {
+        Query::Select(_, _, Filter::Where(predicates)) => predicates
+            .iter_mut()
+            .filter(|predicate| predicate.literal == PLACEHOLDER)
+            .map(|predicate| Slot::Literal(&mut predicate.literal))
+            .collect(),
+        Query::Insert(_, _, ValueList::Values(rows), _)
+        | Query::Upsert(_, _, ValueList::Values(rows)) => rows
+            .iter_mut()
+            .flatten()
+            .filter(|value| value.as_slice() == PLACEHOLDER.as_bytes())
+            .map(Slot::Value)
+            .collect(),
+        Query::Union { left, right, .. }
+        | Query::Intersect { left, right, .. }
+        | Query::Except { left, right, .. } => {
+            let mut slots = placeholder_slots(left);
+            slots.extend(placeholder_slots(right));
+            slots
+        }
+        Query::With { cte, query, .. } => {
+            let mut slots = placeholder_slots(cte);
+            slots.extend(placeholder_slots(query));
+            slots
+        }
+        Query::InsertSelect { source_query, .. } => placeholder_slots(source_query),
+        Query::Explain(query) | Query::ExplainAnalyze(query) | Query::ExplainJson(query) => {
+            placeholder_slots(query)
+        }
+        _ => vec![],
+    }
+}
+
+impl PreparedQuery {
+    pub fn new(mut query: Query) -> PreparedQuery {
+        let placeholders = placeholder_slots(&mut query).len();
+        PreparedQuery {
+            query,
+            placeholders,
+        }
+    }
+
+    /// Whether the query touches files, see `Query::touches_files`.
+    pub fn touches_files(&self) -> bool {
+        self.query.touches_files()
+    }
+
+    /// A copy of the query with `args` in place of its placeholders, the
+    /// literals as they would be written in the query.
+    pub fn bind(&self, args: &[String]) -> Result<Query, String> {
+        if args.len() != self.placeholders {
+            return Err(format!(
+                "Expected {} argument(s), got {}",
+                self.placeholders,
+                args.len()
+            ));
+        }
+        let mut query = self.query.clone();
+        for (slot, arg) in placeholder_slots(&mut query).into_iter().zip(args) {
+            match slot {
+                Slot::Literal(literal) => *literal = arg.clone(),
+                Slot::Value(value) => *value = arg.as_bytes().to_vec(),
+            }
+        }
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::QuerySource;
+
+    #[test]
+    fn test_bind_placeholders() {
+        let prepared =
+            PreparedQuery::new(Query::from("SELECT * FROM users WHERE id > ? AND name = ?"));
+        assert!(prepared.bind(&["1".to_string()]).is_err());
+
+        for (id, name) in [("1", "'Ann'"), ("42", "'Bob'")] {
+            match prepared.bind(&[id.to_string(), name.to_string()]).unwrap() {
+                Query::Select(QuerySource::Table(table), _, Filter::Where(predicates)) => {
+                    assert_eq!(table, "users");
+                    assert_eq!(predicates[0].literal, id);
+                    assert_eq!(predicates[1].literal, name);
+                }
+                query => panic!("Unexpected query {:?}", query),
+            }
+        }
+
+        let prepared = PreparedQuery::new(Query::from(
+            "INSERT INTO users (id, name) VALUES (?, 'x') (?, ?)",
+        ));
+        match prepared
+            .bind(&["1".to_string(), "2".to_string(), "'y'".to_string()])
+            .unwrap()
+        {
+            Query::Insert(_, _, ValueList::Values(rows), _) => {
+                assert_eq!(
+                    rows,
+                    [
+                        [b"1".to_vec(), b"'x'".to_vec()],
+                        [b"2".to_vec(), b"'y'".to_vec()]
+                    ]
+                );
+            }
+            query => panic!("Unexpected query {:?}", query),
+        }
+
+        let prepared = PreparedQuery::new(Query::from("SELECT * FROM users"));
+        assert!(prepared.bind(&[]).is_ok());
+    }
+}
@@ -0,0 +1,527 @@
+use std::cmp::Ordering;
+
+use crate::durability::{
+    skiplist::ordered_key,
+    table::{ColumnDefinition, Row},
+};
+
+use super::{expression::SelectExpr, split_outside_quotes};
+
+/// A function of the select list computed over every row the SELECT returns,
+/// taken in an order of its own instead of one row at a time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowFunction {
+    /// The 1-based position of the row in the window order.
+    RowNumber { order_by: Vec<(String, bool)> },
+    /// The position of the first row tied with the row, so ranks skip past
+    /// ties: 1, 1, 3.
+    Rank { order_by: Vec<(String, bool)> },
+    /// The number of distinct ORDER BY values up to the row, ranks without
+    /// gaps: 1, 1, 2.
+    DenseRank { order_by: Vec<(String, bool)> },
+    /// The bucket of the row when the rows are split into `buckets` groups
+    /// of the same size in window order, the first groups taking one row
+    /// more when the rows do not divide evenly.
+    Ntile {
+        buckets: u64,
+        order_by: Vec<(String, bool)>,
+    },
+    /// The value of `column` in the row `offset` rows before in window
+    /// order, `default` when there is no such row.
+    Lag {
+        column: String,
+        offset: usize,
+        default: Vec<u8>,
+        order_by: Vec<(String, bool)>,
+    },
+    /// `Lag` looking `offset` rows ahead.
+    Lead {
+        column: String,
+        offset: usize,
+        default: Vec<u8>,
+        order_by: Vec<(String, bool)>,
+    },
+}
+
+/// The ORDER BY values of a row as they compare, `None` for nulls.
+type SortKey = Vec<Option<Vec<u8>>>;
+
+impl WindowFunction {
+    /// Parses `ROW_NUMBER() OVER (ORDER BY column [ASC|DESC], ...)`, and the
+    /// same with `RANK()`, `DENSE_RANK()`, `NTILE(buckets)` or
+    /// `LAG(column[, offset[, default]])` and `LEAD`. Each ORDER BY column
+    /// comes with whether it is ascending. `NTILE` needs at least one bucket,
+    /// `LAG` and `LEAD` look one row away with a null default unless told
+    /// otherwise.
+    pub fn parse(expression: &str) -> Option<WindowFunction> {
+        let (function, window) = expression.split_once(" OVER ")?;
+        let window = window.trim().strip_prefix('(')?.strip_suffix(')')?;
+        let order_by = window.trim().strip_prefix("ORDER BY ")?;
+        let order_by = split_outside_quotes(order_by, ',')
+            .iter()
+            .map(|column| {
+                let (column, ascending) = match column.split_once(' ') {
+                    Some((column, "ASC")) => (column, true),
+                    Some((column, "DESC")) => (column, false),
+                    Some(_) => return None,
+                    None => (column.as_str(), true),
+                };
+                match SelectExpr::parse(column)? {
+                    SelectExpr::Column(column) => Some((column, ascending)),
+                    _ => None,
+                }
+            })
+            .collect::<Option<Vec<(String, bool)>>>()?;
+
+        match function.trim() {
+            "ROW_NUMBER()" => Some(WindowFunction::RowNumber { order_by }),
+            "RANK()" => Some(WindowFunction::Rank { order_by }),
+            "DENSE_RANK()" => Some(WindowFunction::DenseRank { order_by }),
+            function => {
+                let (name, arguments) = function.split_once('(')?;
+                let arguments = split_outside_quotes(arguments.strip_suffix(')')?, ',');
+                match (name, arguments.as_slice()) {
+                    ("NTILE", [buckets]) => {
+                        let buckets = buckets.parse().ok().filter(|buckets| *buckets > 0)?;
+                        Some(WindowFunction::Ntile { buckets, order_by })
+                    }
+                    ("LAG" | "LEAD", [column, rest @ ..]) if rest.len() <= 2 => {
+                        let column = match SelectExpr::parse(column)? {
+                            SelectExpr::Column(column) => column,
+                            _ => return None,
+                        };
+                        let offset = match rest.first() {
+                            Some(offset) => offset.parse().ok()?,
+                            None => 1,
+                        };
+                        let default = match rest.get(1).map(|default| SelectExpr::parse(default)) {
+                            Some(Some(SelectExpr::Literal(default))) => default,
+                            Some(_) => return None,
+                            None => vec![],
+                        };
+                        Some(match name {
+                            "LAG" => WindowFunction::Lag {
+                                column,
+                                offset,
+                                default,
+                                order_by,
+                            },
+                            _ => WindowFunction::Lead {
+                                column,
+                                offset,
+                                default,
+                                order_by,
+                            },
+                        })
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    pub fn order_by(&self) -> &[(String, bool)] {
+        match self {
+            WindowFunction::RowNumber { order_by }
+            | WindowFunction::Rank { order_by }
+            | WindowFunction::DenseRank { order_by }
+            | WindowFunction::Ntile { order_by, .. }
+            | WindowFunction::Lag { order_by, .. }
+            | WindowFunction::Lead { order_by, .. } => order_by,
+        }
+    }
+
+    /// The value of the function for each of `rows`, in the order of `rows`.
+    pub fn evaluate(
+        &self,
+        rows: &[Row],
+        columns: &[ColumnDefinition],
+    ) -> Result<Vec<Vec<u8>>, String> {
+        let (order, keys) = window_order(rows, columns, self.order_by())?;
+        let offset_column = match self {
+            WindowFunction::Lag { column, .. } | WindowFunction::Lead { column, .. } => {
+                Some(column_position(columns, column)?)
+            }
+            _ => None,
+        };
+        // The value of the offset column in the row at a position of the
+        // window order, the default past either end.
+        let value_at = |position: Option<usize>, default: &Vec<u8>| match (
+            position.and_then(|position| order.get(position)),
+            offset_column,
+        ) {
+            (Some(row), Some(column)) => rows[*row].data[column].clone(),
+            _ => default.clone(),
+        };
+
+        let mut values = vec![vec![]; rows.len()];
+        let (mut rank, mut dense_rank) = (0, 0);
+        for (position, row) in order.iter().copied().enumerate() {
+            let tied = position > 0 && keys[row] == keys[order[position - 1]];
+            if !tied {
+                rank = position + 1;
+                dense_rank += 1;
+            }
+            values[row] = match self {
+                WindowFunction::RowNumber { .. } => (position + 1).to_string().into_bytes(),
+                WindowFunction::Rank { .. } => rank.to_string().into_bytes(),
+                WindowFunction::DenseRank { .. } => dense_rank.to_string().into_bytes(),
+                WindowFunction::Ntile { buckets, .. } => {
+                    ntile_bucket(position as u64, rows.len() as u64, *buckets)
+                        .to_string()
+                        .into_bytes()
+                }
+                WindowFunction::Lag {
+                    offset, default, ..
+                } => value_at(position.checked_sub(*offset), default),
+                WindowFunction::Lead {
+                    offset, default, ..
+                } => value_at(position.checked_add(*offset), default),
+            };
+        }
+        Ok(values)
+    }
+}
+
+/// The 1-based bucket of the row at `position` of `row_count` rows split
+/// into `buckets` buckets, the first `row_count % buckets` holding a row more
+/// than the others.
+fn ntile_bucket(position: u64, row_count: u64, buckets: u64) -> u64 {
+    let size = row_count / buckets;
+    let larger = row_count % buckets;
+    let in_larger = larger * (size + 1);
+    match position < in_larger {
+        true => position / (size + 1) + 1,
+        false => larger + (position - in_larger) / size + 1,
+    }
+}
+
+/// The positions of `rows` sorted by `order_by`, ties kept in table order,
+/// along with the sort key of every row. Values compare as their column type
+/// does and nulls come first.
+fn window_order(
+    rows: &[Row],
+    columns: &[ColumnDefinition],
+    order_by: &[(String, bool)],
+) -> Result<(Vec<usize>, Vec<SortKey>), String> {
+    let positions = order_by
+        .iter()
+        .map(|(name, ascending)| Ok((column_position(columns, name)?, *ascending)))
+        .collect::<Result<Vec<(usize, bool)>, String>>()?;
+    let keys: Vec<SortKey> = rows
+        .iter()
+        .map(|row| {
+            positions
+                .iter()
+                .map(|(position, _)| {
+                    ordered_key(&row.data[*position], &columns[*position].column_type)
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut order: Vec<usize> = (0..rows.len()).collect();
+    order.sort_by(|a, b| {
+        positions
+            .iter()
+            .enumerate()
+            .map(|(i, (_, ascending))| match ascending {
+                true => keys[*a][i].cmp(&keys[*b][i]),
+                false => keys[*b][i].cmp(&keys[*a][i]),
+            })
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+    Ok((order, keys))
+}
+
+fn column_position(columns: &[ColumnDefinition], name: &str) -> Result<usize, String> {
+    columns
+        .iter()
+        .position(|c| c.name_str() == name)
+        .ok_or_else(|| format!("Column {} does not exist", name))
+}
+
+pub fn has_window_function(expressions: &[SelectExpr]) -> bool {
+    expressions
+        .iter()
+        .any(|expression| matches!(expression, SelectExpr::Window(_)))
+}
+
+/// Projects `rows` through a select list holding window functions. Every
+/// window function is computed over all the rows first, then the rows come
+/// out in the order of the first one.
+pub fn project_windows(
+    rows: Vec<Row>,
+    expressions: &[SelectExpr],
+    columns: &[ColumnDefinition],
+) -> Result<Vec<Row>, String> {
+    let mut windows = vec![];
+    for expression in expressions {
+        windows.push(match expression {
+            SelectExpr::Window(function) => Some(function.evaluate(&rows, columns)?),
+            _ => None,
+        });
+    }
+    let order_by = expressions
+        .iter()
+        .find_map(|expression| match expression {
+            SelectExpr::Window(function) => Some(function.order_by()),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    window_order(&rows, columns, order_by)?
+        .0
+        .into_iter()
+        .map(|position| {
+            let data = expressions
+                .iter()
+                .zip(windows.iter())
+                .map(|(expression, window)| match window {
+                    Some(values) => Ok(values[position].clone()),
+                    None => expression.evaluate(&rows[position], columns),
+                })
+                .collect::<Result<Vec<Vec<u8>>, String>>()?;
+            Ok(Row { data })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::durability::table::ColumnType;
+
+    fn columns() -> Vec<ColumnDefinition> {
+        vec![
+            ColumnDefinition::new("id".to_string(), ColumnType::Int, 4),
+            ColumnDefinition::new("name".to_string(), ColumnType::Varchar, 8),
+        ]
+    }
+
+    fn rows(values: &[(&str, &str)]) -> Vec<Row> {
+        values
+            .iter()
+            .map(|(id, name)| Row {
+                data: vec![id.as_bytes().to_vec(), name.as_bytes().to_vec()],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_window_function() {
+        assert_eq!(
+            WindowFunction::parse("ROW_NUMBER() OVER (ORDER BY id)"),
+            Some(WindowFunction::RowNumber {
+                order_by: vec![("id".to_string(), true)]
+            })
+        );
+        assert_eq!(
+            WindowFunction::parse("ROW_NUMBER() OVER (ORDER BY name DESC, id ASC)"),
+            Some(WindowFunction::RowNumber {
+                order_by: vec![("name".to_string(), false), ("id".to_string(), true)]
+            })
+        );
+        assert_eq!(WindowFunction::parse("ROW_NUMBER() OVER ()"), None);
+        assert_eq!(
+            WindowFunction::parse("ROW_NUMBER() OVER (ORDER BY id UP)"),
+            None
+        );
+        assert_eq!(WindowFunction::parse("ROW_NUMBER()"), None);
+        assert_eq!(WindowFunction::parse("COUNT() OVER (ORDER BY id)"), None);
+    }
+
+    #[test]
+    fn row_number() {
+        let rows = rows(&[("10", "c"), ("9", "a"), ("", "b"), ("100", "a")]);
+        let by = |order_by: &str| {
+            WindowFunction::parse(&format!("ROW_NUMBER() OVER (ORDER BY {})", order_by))
+                .unwrap()
+                .evaluate(&rows, &columns())
+                .unwrap()
+        };
+        // Numbers sort by value and nulls first.
+        assert_eq!(by("id"), [b"3", b"2", b"1", b"4"]);
+        assert_eq!(by("id DESC"), [b"2", b"3", b"4", b"1"]);
+        assert_eq!(by("name, id DESC"), [b"4", b"2", b"3", b"1"]);
+
+        let function = WindowFunction::parse("ROW_NUMBER() OVER (ORDER BY missing)").unwrap();
+        assert!(function.evaluate(&rows, &columns()).is_err());
+    }
+
+    #[test]
+    fn rank_and_dense_rank() {
+        // Ties at the start, in the middle and at the end.
+        let rows = rows(&[
+            ("1", "a"),
+            ("1", "b"),
+            ("2", "c"),
+            ("3", "d"),
+            ("3", "e"),
+            ("3", "f"),
+            ("4", "g"),
+            ("5", "h"),
+            ("5", "i"),
+        ]);
+        let evaluate = |expression: &str| {
+            WindowFunction::parse(expression)
+                .unwrap()
+                .evaluate(&rows, &columns())
+                .unwrap()
+                .into_iter()
+                .map(|value| String::from_utf8(value).unwrap())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            evaluate("RANK() OVER (ORDER BY id)"),
+            ["1", "1", "3", "4", "4", "4", "7", "8", "8"]
+        );
+        assert_eq!(
+            evaluate("DENSE_RANK() OVER (ORDER BY id)"),
+            ["1", "1", "2", "3", "3", "3", "4", "5", "5"]
+        );
+        assert_eq!(
+            evaluate("RANK() OVER (ORDER BY id DESC)"),
+            ["8", "8", "7", "4", "4", "4", "3", "1", "1"]
+        );
+        assert_eq!(
+            evaluate("DENSE_RANK() OVER (ORDER BY id DESC)"),
+            ["5", "5", "4", "3", "3", "3", "2", "1", "1"]
+        );
+        // Every row is distinct once the name breaks the ties.
+        assert_eq!(
+            evaluate("RANK() OVER (ORDER BY id, name)"),
+            evaluate("ROW_NUMBER() OVER (ORDER BY id)")
+        );
+    }
+
+    #[test]
+    fn ntile() {
+        let evaluate = |row_count: usize, buckets: u64| {
+            let values: Vec<(String, String)> = (0..row_count)
+                .rev()
+                .map(|id| (id.to_string(), String::new()))
+                .collect();
+            let values: Vec<(&str, &str)> = values
+                .iter()
+                .map(|(id, name)| (id.as_str(), name.as_str()))
+                .collect();
+            let rows = rows(&values);
+            let mut buckets =
+                WindowFunction::parse(&format!("NTILE({}) OVER (ORDER BY id)", buckets))
+                    .unwrap()
+                    .evaluate(&rows, &columns())
+                    .unwrap();
+            // Rows were given in descending order.
+            buckets.reverse();
+            buckets
+                .into_iter()
+                .map(|value| String::from_utf8(value).unwrap())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(evaluate(8, 4), ["1", "1", "2", "2", "3", "3", "4", "4"]);
+        assert_eq!(evaluate(4, 4), ["1", "2", "3", "4"]);
+        assert_eq!(
+            evaluate(10, 4),
+            ["1", "1", "1", "2", "2", "2", "3", "3", "4", "4"]
+        );
+        assert_eq!(evaluate(3, 4), ["1", "2", "3"]);
+        assert_eq!(evaluate(5, 1), ["1", "1", "1", "1", "1"]);
+
+        assert_eq!(WindowFunction::parse("NTILE(0) OVER (ORDER BY id)"), None);
+        assert_eq!(WindowFunction::parse("NTILE(-1) OVER (ORDER BY id)"), None);
+        assert_eq!(WindowFunction::parse("NTILE() OVER (ORDER BY id)"), None);
+    }
+
+    #[test]
+    fn lag_and_lead() {
+        assert_eq!(
+            WindowFunction::parse("LAG(name, 2, 'none') OVER (ORDER BY id)"),
+            Some(WindowFunction::Lag {
+                column: "name".to_string(),
+                offset: 2,
+                default: b"none".to_vec(),
+                order_by: vec![("id".to_string(), true)]
+            })
+        );
+        assert_eq!(
+            WindowFunction::parse("LEAD(name) OVER (ORDER BY id)"),
+            Some(WindowFunction::Lead {
+                column: "name".to_string(),
+                offset: 1,
+                default: vec![],
+                order_by: vec![("id".to_string(), true)]
+            })
+        );
+        assert_eq!(WindowFunction::parse("LAG() OVER (ORDER BY id)"), None);
+        assert_eq!(
+            WindowFunction::parse("LAG(name, -1) OVER (ORDER BY id)"),
+            None
+        );
+        assert_eq!(
+            WindowFunction::parse("LAG(name, 1, id) OVER (ORDER BY id)"),
+            None
+        );
+
+        let rows = rows(&[("4", "d"), ("2", "b"), ("1", "a"), ("3", "c"), ("5", "e")]);
+        let evaluate = |expression: &str| {
+            let mut values = WindowFunction::parse(expression)
+                .unwrap()
+                .evaluate(&rows, &columns())
+                .unwrap()
+                .into_iter()
+                .zip(rows.iter())
+                .collect::<Vec<(Vec<u8>, &Row)>>();
+            values.sort_by_key(|(_, row)| row.data[0].clone());
+            values
+                .into_iter()
+                .map(|(value, _)| String::from_utf8(value).unwrap())
+                .collect::<Vec<String>>()
+        };
+        // The first `offset` rows get the default from LAG, the last ones from LEAD.
+        assert_eq!(
+            evaluate("LAG(name, 2, '-') OVER (ORDER BY id)"),
+            ["-", "-", "a", "b", "c"]
+        );
+        assert_eq!(
+            evaluate("LEAD(name, 2, '-') OVER (ORDER BY id)"),
+            ["c", "d", "e", "-", "-"]
+        );
+        assert_eq!(
+            evaluate("LAG(name) OVER (ORDER BY id)"),
+            ["", "a", "b", "c", "d"]
+        );
+        assert_eq!(
+            evaluate("LEAD(id, 1, 0) OVER (ORDER BY id DESC)"),
+            ["0", "1", "2", "3", "4"]
+        );
+        assert_eq!(
+            evaluate("LAG(name, 5, '-') OVER (ORDER BY id)"),
+            ["-", "-", "-", "-", "-"]
+        );
+
+        let function = WindowFunction::parse("LAG(missing) OVER (ORDER BY id)").unwrap();
+        assert!(function.evaluate(&rows, &columns()).is_err());
+    }
+
+    #[test]
+    fn project_rows_in_window_order() {
+        let rows = rows(&[("3", "c"), ("1", "a"), ("2", "b")]);
+        let expressions = vec![
+            SelectExpr::parse("ROW_NUMBER() OVER (ORDER BY id DESC)").unwrap(),
+            SelectExpr::parse("name").unwrap(),
+        ];
+        assert!(has_window_function(&expressions));
+        let projected = project_windows(rows, &expressions, &columns()).unwrap();
+        let projected: Vec<Vec<Vec<u8>>> = projected.into_iter().map(|row| row.data).collect();
+        assert_eq!(
+            projected,
+            [
+                [b"1".to_vec(), b"c".to_vec()],
+                [b"2".to_vec(), b"b".to_vec()],
+                [b"3".to_vec(), b"a".to_vec()]
+            ]
+        );
+    }
+}
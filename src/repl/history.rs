@@ -0,0 +1,159 @@
+use std::{collections::VecDeque, io::Write, path::PathBuf};
+
+/// Queries kept in memory for `UP` / `DOWN` navigation.
+const HISTORY_SIZE: usize = 100;
+/// Queries kept in the history file between sessions.
+const HISTORY_FILE_SIZE: usize = 1000;
+
+/// The most recent queries, oldest first. Multiline queries are a single
+/// entry, their newlines are escaped in the history file so it stays one
+/// query per line.
+pub struct History {
+    entries: VecDeque<String>,
+    path: Option<PathBuf>,
+}
+
+pub fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".city_db_history"))
+}
+
+impl History {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut history = History {
+            entries: VecDeque::with_capacity(HISTORY_SIZE),
+            path,
+        };
+        let lines = history
+            .path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        for line in lines.lines().filter(|line| !line.is_empty()) {
+            history.push(unescape(line));
+        }
+        history
+    }
+
+    pub fn entries(&self) -> &VecDeque<String> {
+        &self.entries
+    }
+
+    /// Records a query and appends it to the history file, rewriting the file
+    /// once it grows past `HISTORY_FILE_SIZE` lines.
+    pub fn add(&mut self, query: &str) -> std::io::Result<()> {
+        let query = query.trim();
+        if query.is_empty() || self.entries.back().is_some_and(|last| last == query) {
+            return Ok(());
+        }
+        self.push(query.to_string());
+
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(file, "{}", escape(query))?;
+
+        let contents = std::fs::read_to_string(path)?;
+        let lines: Vec<&str> = contents.lines().collect();
+        if lines.len() > HISTORY_FILE_SIZE {
+            let mut kept = lines[lines.len() - HISTORY_FILE_SIZE..].join("\n");
+            kept.push('\n');
+            std::fs::write(path, kept)?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, query: String) {
+        if self.entries.len() == HISTORY_SIZE {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(query);
+    }
+}
+
+fn escape(query: &str) -> String {
+    query.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut query = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                query.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                query.push('\\');
+                chars.next();
+            }
+            _ => query.push(c),
+        }
+    }
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn escape_roundtrip() {
+        for query in ["SELECT *\nFROM t;", "SELECT '\\n' FROM t;", "a\\\\\nb"] {
+            assert!(!escape(query).contains('\n'));
+            assert_eq!(unescape(&escape(query)), query);
+        }
+    }
+
+    #[test]
+    fn history_keeps_last_entries() {
+        let mut history = History::load(None);
+        for i in 0..HISTORY_SIZE + 5 {
+            history.add(&format!("SELECT {};", i)).unwrap();
+        }
+        history.add("SELECT 104;").unwrap();
+        history.add("   ").unwrap();
+
+        assert_eq!(history.entries().len(), HISTORY_SIZE);
+        assert_eq!(history.entries()[0], "SELECT 5;");
+        assert_eq!(history.entries().back().unwrap(), "SELECT 104;");
+    }
+
+    #[test]
+    fn history_is_persisted() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join(".city_db_history");
+
+        let mut history = History::load(Some(path.clone()));
+        history.add("SELECT *\nFROM account_tbl;").unwrap();
+        history.add("SELECT id FROM account_tbl;").unwrap();
+
+        let history = History::load(Some(path));
+        assert_eq!(
+            history.entries(),
+            &["SELECT *\nFROM account_tbl;", "SELECT id FROM account_tbl;"]
+        );
+    }
+
+    #[test]
+    fn history_file_is_truncated() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join(".city_db_history");
+
+        let mut history = History::load(Some(path.clone()));
+        for i in 0..HISTORY_FILE_SIZE + 10 {
+            history.add(&format!("SELECT {};", i)).unwrap();
+        }
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), HISTORY_FILE_SIZE);
+        assert_eq!(contents.lines().next(), Some("SELECT 10;"));
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    collections::VecDeque,
+    io::{stdin, stdout, BufRead, IsTerminal, Read, Write},
+};
+
+use super::{complete_statements, history::history_file, is_command, History};
+
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const BACKSPACE: u8 = 0x08;
+const ESCAPE: u8 = 0x1b;
+const DELETE: u8 = 0x7f;
+
+/// The line being edited and where it sits in the history while browsing
+/// with `UP` / `DOWN`. `draft` keeps the unfinished line so `DOWN` past the
+/// newest entry brings it back.
+#[derive(Default)]
+struct LineBuffer {
+    text: Vec<u8>,
+    position: Option<usize>,
+    draft: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn previous(&mut self, entries: &VecDeque<String>) {
+        let position = match self.position {
+            None if entries.is_empty() => return,
+            None => {
+                self.draft = std::mem::take(&mut self.text);
+                entries.len() - 1
+            }
+            Some(0) => return,
+            Some(position) => position - 1,
+        };
+        self.position = Some(position);
+        self.text = entries[position].as_bytes().to_vec();
+    }
+
+    fn next(&mut self, entries: &VecDeque<String>) {
+        match self.position {
+            None => {}
+            Some(position) if position + 1 < entries.len() => {
+                self.position = Some(position + 1);
+                self.text = entries[position + 1].as_bytes().to_vec();
+            }
+            Some(_) => {
+                self.position = None;
+                self.text = std::mem::take(&mut self.draft);
+            }
+        }
+    }
+
+    /// Removes the last character, which may be several bytes long.
+    fn backspace(&mut self) {
+        while self.text.last().is_some_and(|b| b & 0xC0 == 0x80) {
+            self.text.pop();
+        }
+        self.text.pop();
+    }
+}
+
+/// Puts the terminal in non canonical mode without echo for as long as it is
+/// alive, the original settings are restored on drop.
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable() -> std::io::Result<Self> {
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let original = termios;
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(RawMode { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+pub fn is_interactive() -> bool {
+    stdin().is_terminal()
+}
+
+/// Reads one line of input, `None` at the end of input. On a terminal the
+/// line can be edited and `UP` / `DOWN` recall `history`, otherwise lines are
+/// read as they are.
+pub fn read_line(prompt: &str, history: &VecDeque<String>) -> std::io::Result<Option<String>> {
+    if !is_interactive() {
+        let mut line = String::new();
+        if stdin().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        return Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()));
+    }
+
+    let _raw_mode = RawMode::enable()?;
+    let mut stdin = stdin().lock();
+    let mut line = LineBuffer::default();
+    redraw(prompt, &line)?;
+    loop {
+        let mut byte = [0; 1];
+        if stdin.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        match byte[0] {
+            b'\r' | b'\n' => {
+                print!("\r\n");
+                stdout().flush()?;
+                return Ok(Some(String::from_utf8_lossy(&line.text).to_string()));
+            }
+            CTRL_D if line.text.is_empty() => {
+                print!("\r\n");
+                stdout().flush()?;
+                return Ok(None);
+            }
+            CTRL_C => line = LineBuffer::default(),
+            BACKSPACE | DELETE => line.backspace(),
+            ESCAPE => {
+                let mut sequence = [0; 2];
+                stdin.read_exact(&mut sequence)?;
+                match sequence {
+                    [b'[', b'A'] => line.previous(history),
+                    [b'[', b'B'] => line.next(history),
+                    _ => {}
+                }
+            }
+            byte if byte >= b' ' || byte == b'\t' => line.text.push(byte),
+            _ => {}
+        }
+        redraw(prompt, &line)?;
+    }
+}
+
+/// Stdin on a terminal, each line read with `read_line`. The prompt shows
+/// whether the line continues a query, `UP` / `DOWN` recall the queries of
+/// the history file and the ones read since.
+pub struct Terminal {
+    entries: VecDeque<String>,
+    pending: String,
+    line: Vec<u8>,
+    position: usize,
+}
+
+impl Terminal {
+    pub fn new() -> Self {
+        Terminal {
+            entries: History::load(history_file()).entries().clone(),
+            pending: String::new(),
+            line: vec![],
+            position: 0,
+        }
+    }
+}
+
+impl Read for Terminal {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let length = available.len().min(buf.len());
+        buf[..length].copy_from_slice(&available[..length]);
+        self.consume(length);
+        Ok(length)
+    }
+}
+
+impl BufRead for Terminal {
+    /// The rest of the line read last, or the next line once it was read.
+    /// Empty at the end of input.
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.position == self.line.len() {
+            let prompt = match self.pending.is_empty() {
+                true => "city_db> ",
+                false => "      -> ",
+            };
+            self.position = 0;
+            self.line = match read_line(prompt, &self.entries)? {
+                Some(line) => {
+                    if !self.pending.is_empty() || !is_command(&line) {
+                        for statement in complete_statements(&line, &mut self.pending) {
+                            if self.entries.back() != Some(&statement) {
+                                self.entries.push_back(statement);
+                            }
+                        }
+                    }
+                    format!("{}\n", line).into_bytes()
+                }
+                None => vec![],
+            };
+        }
+        Ok(&self.line[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position += amount;
+    }
+}
+
+/// Multiline history entries are shown on a single line.
+fn redraw(prompt: &str, line: &LineBuffer) -> std::io::Result<()> {
+    let text = String::from_utf8_lossy(&line.text).replace('\n', " ");
+    print!("\r\x1b[K{}{}", prompt, text);
+    stdout().flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::LineBuffer;
+
+    #[test]
+    fn navigate_history() {
+        let history = VecDeque::from(["SELECT 1;".to_string(), "SELECT\n2;".to_string()]);
+        let mut line = LineBuffer {
+            text: b"SEL".to_vec(),
+            ..Default::default()
+        };
+
+        line.next(&history);
+        assert_eq!(line.text, b"SEL");
+        line.previous(&history);
+        assert_eq!(line.text, b"SELECT\n2;");
+        line.previous(&history);
+        assert_eq!(line.text, b"SELECT 1;");
+        line.previous(&history);
+        assert_eq!(line.text, b"SELECT 1;");
+        line.next(&history);
+        assert_eq!(line.text, b"SELECT\n2;");
+        line.next(&history);
+        assert_eq!(line.text, b"SEL");
+
+        let mut line = LineBuffer::default();
+        line.previous(&VecDeque::new());
+        assert!(line.text.is_empty());
+    }
+
+    #[test]
+    fn backspace_removes_whole_characters() {
+        let mut line = LineBuffer {
+            text: "a\u{e9}".as_bytes().to_vec(),
+            ..Default::default()
+        };
+        line.backspace();
+        assert_eq!(line.text, b"a");
+        line.backspace();
+        line.backspace();
+        assert!(line.text.is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::BufRead;
+use std::time::Duration;
+
+use history::{history_file, History};
+use line_editor::Terminal;
+
+use crate::query::statement_end;
+
+mod history;
+mod line_editor;
+
+/// How long `run_repl` waits before reading again once every writer of the
+/// pipe has gone.
+const PIPE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Ends the session when it is the whole line and no query is pending.
+const EXIT_COMMAND: &str = ".exit";
+
+/// What `run_repl` does at the end of its input.
+#[derive(Clone, Copy, PartialEq)]
+pub enum AtEnd {
+    /// Returns, like at the end of stdin.
+    Exit,
+    /// Reads again, a named pipe gets a new writer after the last one left.
+    Wait,
+}
+
+/// Stdin for `run_repl`, read through the line editor on a terminal.
+pub fn stdin() -> Box<dyn BufRead> {
+    match line_editor::is_interactive() {
+        true => Box::new(Terminal::new()),
+        false => Box::new(std::io::stdin().lock()),
+    }
+}
+
+/// Reads queries from `reader`, calling `execute` with each `;` terminated
+/// query. A query may span several lines, it is recorded in the history as
+/// one entry. History is only kept in the history file for terminals.
+/// Returns on `.exit`, when reading fails and, unless told to `AtEnd::Wait`
+/// for more, at the end of input.
+pub fn run_repl(mut reader: impl BufRead, at_end: AtEnd, mut execute: impl FnMut(&String)) {
+    let interactive = at_end == AtEnd::Exit && line_editor::is_interactive();
+    let mut history = History::load(if interactive { history_file() } else { None });
+    let mut pending = String::new();
+    let mut line = vec![];
+    loop {
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) if at_end == AtEnd::Exit => return,
+            Ok(0) => {
+                std::thread::sleep(PIPE_POLL_INTERVAL);
+                continue;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                println!("Failed to read input: {}", e);
+                return;
+            }
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\n', '\r']);
+        if pending.is_empty() && text.trim() == EXIT_COMMAND {
+            return;
+        }
+        push_line(text, &mut pending, &mut history, &mut execute);
+        line.clear();
+    }
+}
+
+/// Whether `line` is a command of the REPL rather than part of a query,
+/// when no query is pending.
+fn is_command(line: &str) -> bool {
+    let line = line.trim();
+    line == EXIT_COMMAND || line.trim_end_matches(';') == "HISTORY"
+}
+
+/// Adds `line` to the `pending` query, returning the queries it completes
+/// with their `;`. A `;` inside quotes does not end a query.
+fn complete_statements(line: &str, pending: &mut String) -> Vec<String> {
+    if !pending.is_empty() {
+        pending.push('\n');
+    }
+    pending.push_str(line);
+    let mut statements = vec![];
+    while let Some(end) = statement_end(pending) {
+        let statement: String = pending.drain(..=end).collect();
+        statements.push(statement.trim().to_string());
+    }
+    if pending.trim().is_empty() {
+        pending.clear();
+    }
+    statements
+}
+
+/// Adds a line of input to the `pending` query, calling `execute` with each
+/// query it completes. `HISTORY` lists the queries read so far.
+fn push_line(
+    line: &str,
+    pending: &mut String,
+    history: &mut History,
+    execute: &mut impl FnMut(&String),
+) {
+    if pending.is_empty() && line.trim().trim_end_matches(';') == "HISTORY" {
+        for (i, query) in history.entries().iter().enumerate() {
+            println!("{:>4}  {}", i + 1, query.replace('\n', "\n      "));
+        }
+        return;
+    }
+
+    for statement in complete_statements(line, pending) {
+        if let Err(e) = history.add(&statement) {
+            println!("Failed to save history: {}", e);
+        }
+
+        let query = statement.trim_end_matches(';').trim().to_string();
+        if !query.is_empty() {
+            execute(&query);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn run_repl_until_exit() {
+        let input = "SELECT 1;\nSELECT\n2; SELECT 3\n;\n.exit\nSELECT 4;\n";
+        let mut queries = vec![];
+        run_repl(Cursor::new(input), AtEnd::Wait, |query| {
+            queries.push(query.clone())
+        });
+        assert_eq!(queries, ["SELECT 1", "SELECT\n2", "SELECT 3"]);
+
+        // Without `.exit` the end of input ends it, and within a query
+        // `.exit` is part of it.
+        let mut queries = vec![];
+        run_repl(Cursor::new("SELECT\n.exit\n;"), AtEnd::Exit, |query| {
+            queries.push(query.clone())
+        });
+        assert_eq!(queries, ["SELECT\n.exit"]);
+    }
+
+    #[test]
+    fn semicolon_in_string_literal() {
+        let mut pending = String::new();
+        assert_eq!(
+            complete_statements("CREATE PROCEDURE p AS 'local x = 1;", &mut pending),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            complete_statements("return x'; SELECT 'it''s;'; SELECT", &mut pending),
+            [
+                "CREATE PROCEDURE p AS 'local x = 1;\nreturn x';",
+                "SELECT 'it''s;';"
+            ]
+        );
+        assert_eq!(pending, " SELECT");
+    }
+}
@@ -0,0 +1,173 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+const QUERY: u8 = 0x01;
+const RESULT_ROW: u8 = 0x02;
+const DONE: u8 = 0x03;
+const ERROR: u8 = 0x04;
+const AUTH_REQUIRED: u8 = 0x05;
+const AUTH: u8 = 0x06;
+const AUTH_OK: u8 = 0x07;
+
+/// Largest payload accepted from a client, guards against allocating
+/// whatever a corrupt length prefix asks for.
+const MAX_PAYLOAD_SIZE: u32 = 16 * 1024 * 1024;
+
+/// A frame of the wire protocol: a little endian u32 payload length, a one
+/// byte message type and then the payload itself.
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Query(Vec<u8>),
+    /// Every column is prefixed with its own u32 length so values may contain
+    /// any byte.
+    ResultRow(Vec<Vec<u8>>),
+    /// Sent after the last row, with the execution time in microseconds.
+    Done {
+        execution_time: u128,
+    },
+    Error(String),
+    /// Sent by a server started with `--auth-file` when a connection opens
+    /// and for every message before the client authenticated.
+    AuthRequired,
+    /// The client's `username:password`.
+    Auth(Vec<u8>),
+    AuthOk,
+}
+
+impl Message {
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let (message_type, payload) = match self {
+            Message::Query(query) => (QUERY, query.clone()),
+            Message::ResultRow(columns) => {
+                let mut payload = vec![];
+                for column in columns {
+                    payload.extend((column.len() as u32).to_le_bytes());
+                    payload.extend(column);
+                }
+                (RESULT_ROW, payload)
+            }
+            Message::Done { execution_time } => (DONE, execution_time.to_le_bytes().to_vec()),
+            Message::Error(message) => (ERROR, message.as_bytes().to_vec()),
+            Message::AuthRequired => (AUTH_REQUIRED, vec![]),
+            Message::Auth(credentials) => (AUTH, credentials.clone()),
+            Message::AuthOk => (AUTH_OK, vec![]),
+        };
+
+        let mut frame = Vec::with_capacity(5 + payload.len());
+        frame.extend((payload.len() as u32).to_le_bytes());
+        frame.push(message_type);
+        frame.extend(payload);
+        writer.write_all(&frame)?;
+        writer.flush()
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Message> {
+        let mut header = [0; 5];
+        reader.read_exact(&mut header)?;
+        let length = u32::from_le_bytes(header[..4].try_into().unwrap());
+        if length > MAX_PAYLOAD_SIZE {
+            return Err(invalid_data(format!(
+                "Message of {} bytes is too large",
+                length
+            )));
+        }
+        let mut payload = vec![0; length as usize];
+        reader.read_exact(&mut payload)?;
+
+        match header[4] {
+            QUERY => Ok(Message::Query(payload)),
+            RESULT_ROW => {
+                let mut columns = vec![];
+                let mut rest = payload.as_slice();
+                while !rest.is_empty() {
+                    if rest.len() < 4 {
+                        return Err(invalid_data("Truncated column length".to_string()));
+                    }
+                    let (length, tail) = rest.split_at(4);
+                    let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+                    if tail.len() < length {
+                        return Err(invalid_data("Truncated column value".to_string()));
+                    }
+                    let (column, tail) = tail.split_at(length);
+                    columns.push(column.to_vec());
+                    rest = tail;
+                }
+                Ok(Message::ResultRow(columns))
+            }
+            DONE => {
+                let execution_time = payload
+                    .try_into()
+                    .map_err(|_| invalid_data("Invalid done message".to_string()))?;
+                Ok(Message::Done {
+                    execution_time: u128::from_le_bytes(execution_time),
+                })
+            }
+            ERROR => Ok(Message::Error(
+                String::from_utf8_lossy(&payload).to_string(),
+            )),
+            AUTH_REQUIRED => Ok(Message::AuthRequired),
+            AUTH => Ok(Message::Auth(payload)),
+            AUTH_OK => Ok(Message::AuthOk),
+            message_type => Err(invalid_data(format!(
+                "Unknown message type {:#04x}",
+                message_type
+            ))),
+        }
+    }
+}
+
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Message;
+
+    fn roundtrip(message: Message) {
+        let mut bytes = vec![];
+        message.write_to(&mut bytes).unwrap();
+        assert_eq!(Message::read_from(&mut bytes.as_slice()).unwrap(), message);
+    }
+
+    #[test]
+    fn roundtrip_messages() {
+        roundtrip(Message::Query(b"SELECT * FROM account_tbl".to_vec()));
+        roundtrip(Message::ResultRow(vec![
+            b"1".to_vec(),
+            vec![],
+            b"line\nbreak\0".to_vec(),
+        ]));
+        roundtrip(Message::ResultRow(vec![]));
+        roundtrip(Message::Done {
+            execution_time: 1234,
+        });
+        roundtrip(Message::Error("Invalid query".to_string()));
+        roundtrip(Message::AuthRequired);
+        roundtrip(Message::Auth(b"alice:secret".to_vec()));
+        roundtrip(Message::AuthOk);
+    }
+
+    #[test]
+    fn encode_frame_layout() {
+        let mut bytes = vec![];
+        Message::Query(b"abc".to_vec())
+            .write_to(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes, [3, 0, 0, 0, 0x01, b'a', b'b', b'c']);
+    }
+
+    #[test]
+    fn reject_invalid_frames() {
+        let unknown_type = [0, 0, 0, 0, 0x09];
+        assert!(Message::read_from(&mut unknown_type.as_slice()).is_err());
+
+        let truncated = [5, 0, 0, 0, 0x01, b'a'];
+        assert!(Message::read_from(&mut truncated.as_slice()).is_err());
+
+        let truncated_column = [3, 0, 0, 0, 0x02, 9, 0, 0];
+        assert!(Message::read_from(&mut truncated_column.as_slice()).is_err());
+
+        let too_large = [0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+        assert!(Message::read_from(&mut too_large.as_slice()).is_err());
+    }
+}
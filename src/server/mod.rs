@@ -0,0 +1,424 @@
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    io::{BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    os::unix::net::{UnixListener, UnixStream},
+    panic,
+    sync::{atomic::Ordering, Arc, Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use message::Message;
+use process::{kill, processlist_rows, ConnectionInfo, ProcessList};
+
+use crate::{
+    concurrency::explicit_locks,
+    config::{json_array, Config, OutputFormat},
+    durability::{
+        table::{drop_temp_tables, writeable_table_file, Page, Table},
+        user::authenticate,
+        DatabaseConfig,
+    },
+    get_result_set, is_administrator, lock_table,
+    logging::{query_log_file, redact_passwords, QueryLogger},
+    query::{self, Query},
+    require_admin,
+    slow_query_log::{slow_query_log_file, SlowQueryLog},
+    unlock_table,
+};
+
+mod message;
+mod process;
+
+/// Failed attempts to authenticate before the connection is closed.
+const MAX_AUTH_ATTEMPTS: usize = 3;
+
+/// A client stream the query loop reads queries from and writes results to.
+pub trait Connection: Read + Write + Send + Sized + 'static {
+    fn try_clone(&self) -> std::io::Result<Self>;
+    /// Where the client connects from, shown by `SHOW PROCESSLIST`.
+    fn peer_address(&self) -> String;
+    /// Closes both directions of the stream, used by `KILL`.
+    fn shutdown(&self) -> std::io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+
+    fn peer_address(&self) -> String {
+        self.peer_addr()
+            .map(|address| address.to_string())
+            .unwrap_or_default()
+    }
+
+    fn shutdown(&self) -> std::io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+impl Connection for UnixStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        UnixStream::try_clone(self)
+    }
+
+    fn peer_address(&self) -> String {
+        "local".to_string()
+    }
+
+    fn shutdown(&self) -> std::io::Result<()> {
+        UnixStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+/// Accepts connections on `port` and runs the `;` delimited queries they send
+/// against the table. Every query holds the table lock while it executes so
+/// concurrent inserts are serialized. Every connection starts from the
+/// variables of `config`.
+pub fn serve_tcp(
+    port: u16,
+    table: Table,
+    database: DatabaseConfig,
+    config: Config,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Listening on {}", listener.local_addr()?);
+    accept_connections(listener.incoming(), table, database, config)
+}
+
+static SOCKET_PATH: OnceLock<CString> = OnceLock::new();
+
+extern "C" fn remove_socket(_signal: libc::c_int) {
+    if let Some(path) = SOCKET_PATH.get() {
+        unsafe { libc::unlink(path.as_ptr()) };
+    }
+    unsafe { libc::_exit(0) };
+}
+
+/// Same as `serve_tcp` but listens on a Unix domain socket. A socket file left
+/// behind by a crash is removed on startup, and SIGINT or SIGTERM remove it
+/// before exiting.
+pub fn serve_unix(
+    path: &str,
+    table: Table,
+    database: DatabaseConfig,
+    config: Config,
+) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let listener = UnixListener::bind(path)?;
+
+    let c_path = CString::new(path).map_err(std::io::Error::other)?;
+    if SOCKET_PATH.set(c_path).is_ok() {
+        let handler = remove_socket as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        unsafe {
+            libc::signal(libc::SIGINT, handler);
+            libc::signal(libc::SIGTERM, handler);
+        }
+    }
+
+    println!("Listening on {}", path);
+    accept_connections(listener.incoming(), table, database, config)
+}
+
+fn accept_connections<C: Connection>(
+    incoming: impl Iterator<Item = std::io::Result<C>>,
+    table: Table,
+    database: DatabaseConfig,
+    config: Config,
+) -> std::io::Result<()> {
+    let table = Arc::new(Mutex::new(table));
+    let config = Arc::new(config);
+    let slow_query_log = Arc::new(SlowQueryLog::new(slow_query_log_file(&database)));
+    let query_log = Arc::new(QueryLogger::new(query_log_file(&database)));
+    let database = Arc::new(database);
+    let processes = ProcessList::default();
+    let mut next_connection_id = 1;
+    for stream in incoming {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+
+        let table = Arc::clone(&table);
+        let database = Arc::clone(&database);
+        let slow_query_log = Arc::clone(&slow_query_log);
+        let query_log = Arc::clone(&query_log);
+        let processes = Arc::clone(&processes);
+        let config = Arc::clone(&config);
+        let connection_id = next_connection_id;
+        next_connection_id += 1;
+        thread::spawn(move || {
+            let session = Session {
+                connection_id,
+                database: &database,
+                slow_query_log: &slow_query_log,
+                query_log: &query_log,
+                processes: &processes,
+                config: &config,
+            };
+            if let Err(e) = handle_connection(stream, &table, &session) {
+                println!("Connection closed: {}", e);
+            }
+            lock(&processes).remove(&connection_id);
+            explicit_locks().release_all(connection_id);
+            if let Err(e) = drop_temp_tables(connection_id) {
+                println!("Failed to drop temporary tables: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Asks the client for its `username:password` until it matches a user of the
+/// auth file, answering with `AuthOk`. Every other message counts as a failed
+/// attempt and is answered with `AuthRequired` again. Returns the user name,
+/// `None` once the attempts ran out.
+fn authenticate_connection<C: Connection>(
+    reader: &mut impl Read,
+    writer: &mut C,
+    auth_file: &str,
+) -> std::io::Result<Option<String>> {
+    Message::AuthRequired.write_to(writer)?;
+    for attempt in 1..=MAX_AUTH_ATTEMPTS {
+        if let Message::Auth(credentials) = Message::read_from(reader)? {
+            let credentials = String::from_utf8_lossy(&credentials);
+            match authenticate(auth_file, &credentials) {
+                Ok(Some(user)) => {
+                    Message::AuthOk.write_to(writer)?;
+                    return Ok(Some(user));
+                }
+                Ok(None) => {}
+                Err(e) => println!("Failed to read auth file: {:?}", e),
+            }
+        }
+        if attempt < MAX_AUTH_ATTEMPTS {
+            Message::AuthRequired.write_to(writer)?;
+        }
+    }
+    Message::Error("Authentication failed".to_string()).write_to(writer)?;
+    Ok(None)
+}
+
+/// What a connection shares with the rest of the server.
+struct Session<'a> {
+    connection_id: u64,
+    database: &'a DatabaseConfig,
+    slow_query_log: &'a SlowQueryLog,
+    query_log: &'a QueryLogger,
+    processes: &'a ProcessList,
+    /// The variables every connection starts from.
+    config: &'a Config,
+}
+
+/// Waits for the `LOCK TABLE` of other connections on the tables the query
+/// uses, before the query takes the open table so the holders can still run
+/// theirs.
+fn wait_for_tables(query: &Query, config: &Config) -> Result<(), String> {
+    let timeout = Duration::from_secs(config.lock_timeout);
+    for (table, access) in query.table_accesses() {
+        explicit_locks().wait_for_access(table, access, config.connection_id, timeout)?;
+    }
+    Ok(())
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Reads `Query` messages and answers each with one `ResultRow` message per
+/// row followed by `Done`. A query that cannot be parsed is answered with a
+/// single `Error` message instead. Every query is written to the query log,
+/// and those over the session's slow query threshold to the slow query log,
+/// once they have been answered, both with their passwords redacted. The
+/// connection is listed by `SHOW PROCESSLIST` once authenticated, and a
+/// `KILL` of it drops the result of the running query and disconnects.
+fn handle_connection<C: Connection>(
+    stream: C,
+    table: &Mutex<Table>,
+    session: &Session,
+) -> std::io::Result<()> {
+    let database = session.database;
+    let table_name = lock(table).name_str().to_string();
+    let mut file =
+        writeable_table_file(table_name).map_err(|e| std::io::Error::other(format!("{:?}", e)))?;
+    let mut page_cache: HashMap<String, Page> = HashMap::new();
+    let mut row_count = None;
+    let mut transaction = None;
+    let mut config = Config {
+        connection_id: session.connection_id,
+        ..session.config.clone()
+    };
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    if let Some(auth_file) = &database.auth_file {
+        match authenticate_connection(&mut reader, &mut writer, auth_file)? {
+            Some(user) => {
+                println!("User {} connected", user);
+                config.user = Some(user);
+            }
+            None => return Ok(()),
+        }
+    }
+    let cancelled = {
+        let stream = writer.try_clone()?;
+        let info = ConnectionInfo::new(
+            config.user.clone().unwrap_or_default(),
+            writer.peer_address(),
+            Box::new(move || {
+                let _ = stream.shutdown();
+            }),
+        );
+        let cancelled = Arc::clone(&info.cancelled);
+        lock(session.processes).insert(session.connection_id, info);
+        cancelled
+    };
+    let set_current_query = |query: Option<&str>| {
+        if let Some(info) = lock(session.processes).get_mut(&session.connection_id) {
+            info.current_query = query.map(str::to_string);
+            info.query_started = query.map(|_| Instant::now());
+        }
+    };
+    loop {
+        let payload = match Message::read_from(&mut reader) {
+            _ if cancelled.load(Ordering::SeqCst) => return Ok(()),
+            Ok(Message::Query(payload)) => payload,
+            Ok(message) => {
+                Message::Error(format!("Expected a query, got {:?}", message))
+                    .write_to(&mut writer)?;
+                continue;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let query_text = String::from_utf8_lossy(&payload).trim().to_string();
+        let query = query::strip_comments(&query_text);
+        let redacted_text = redact_passwords(&query_text);
+        let mut query = query.trim().as_bytes().to_vec();
+        if query.last() == Some(&b';') {
+            query.pop();
+        }
+        let query = match panic::catch_unwind(move || Query::from(&mut query)) {
+            Ok(query) => query,
+            Err(_) => {
+                Message::Error("Invalid query".to_string()).write_to(&mut writer)?;
+                continue;
+            }
+        };
+
+        set_current_query(Some(&redacted_text));
+        let started = Instant::now();
+        let single_row = |row: Result<String, String>| {
+            let ok = row.is_ok();
+            (
+                vec![vec![row.unwrap_or_else(|e| e)]],
+                started.elapsed().as_micros(),
+                ok,
+            )
+        };
+        // Users other than the administrator only see and kill their own
+        // connections.
+        let owner = || match is_administrator(database, &config) {
+            Ok(true) => Ok(None),
+            Ok(false) => Ok(config.user.as_deref()),
+            Err(e) => Err(e),
+        };
+        let (rows, execution_time, ok) = match query {
+            Query::ShowProcesslist => match owner() {
+                Ok(owner) => (
+                    processlist_rows(&lock(session.processes), owner),
+                    started.elapsed().as_micros(),
+                    true,
+                ),
+                Err(e) => single_row(Err(e)),
+            },
+            Query::Kill(id) => single_row(
+                owner()
+                    .and_then(|owner| kill(&lock(session.processes), id, owner))
+                    .map(|()| format!("Killed connection {}", id)),
+            ),
+            Query::LockTable { table, mode } => single_row(lock_table(&table, mode, &config)),
+            Query::UnlockTable(table) => single_row(unlock_table(&table, &config)),
+            Query::FlushQueryLog | Query::ShowQueryLog(_) | Query::ClearQueryLog => {
+                let statement = match query {
+                    Query::FlushQueryLog => "FLUSH QUERY LOG",
+                    Query::ShowQueryLog(_) => "SHOW QUERY LOG",
+                    _ => "CLEAR QUERY LOG",
+                };
+                match require_admin(statement, database, &config)
+                    .and_then(|()| session.query_log.execute(&query).unwrap())
+                {
+                    Ok(rows) => (rows, started.elapsed().as_micros(), true),
+                    Err(e) => single_row(Err(e)),
+                }
+            }
+            query => match wait_for_tables(&query, &config) {
+                Err(e) => single_row(Err(e)),
+                Ok(()) => {
+                    let mut table = lock(table);
+                    // Other processes may have written to the table file.
+                    if let Err(e) = table.refresh(&file) {
+                        println!("Failed to refresh table: {:?}", e);
+                    }
+                    // Other connections may have appended to pages cached here.
+                    if row_count != Some(table.row_count) {
+                        page_cache.clear();
+                    }
+                    row_count = Some(table.row_count);
+                    let result_set = get_result_set(
+                        &mut table,
+                        &mut file,
+                        query,
+                        &mut page_cache,
+                        database,
+                        &mut transaction,
+                        &mut config,
+                        &mut reader,
+                    );
+                    (
+                        result_set.rows,
+                        result_set.execution_time,
+                        result_set.execution_status == 1,
+                    )
+                }
+            },
+        };
+        set_current_query(None);
+
+        for row in rows {
+            if cancelled.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            let row = match config.output_format {
+                OutputFormat::Text => row,
+                OutputFormat::Json => vec![json_array(&row)],
+            };
+            let columns = row.into_iter().map(String::into_bytes).collect();
+            Message::ResultRow(columns).write_to(&mut writer)?;
+        }
+        if cancelled.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        Message::Done { execution_time }.write_to(&mut writer)?;
+        if let Err(e) = session.query_log.record(&redacted_text, execution_time, ok) {
+            println!("Failed to write query log: {}", e);
+        }
+        if let Err(e) = session.slow_query_log.record(
+            &redacted_text,
+            execution_time,
+            config.slow_query_threshold,
+        ) {
+            println!("Failed to write slow query log: {}", e);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+/// The characters of the current query `SHOW PROCESSLIST` shows.
+const QUERY_DISPLAY_LENGTH: usize = 64;
+
+/// The connections of the server by their id.
+pub type ProcessList = Arc<Mutex<HashMap<u64, ConnectionInfo>>>;
+
+/// What `SHOW PROCESSLIST` shows about a connection.
+pub struct ConnectionInfo {
+    pub username: String,
+    pub client_address: String,
+    /// The statement the connection is running, `None` between statements.
+    pub current_query: Option<String>,
+    pub query_started: Option<Instant>,
+    /// Set by `KILL`, the connection drops the result of its current query
+    /// and disconnects.
+    pub cancelled: Arc<AtomicBool>,
+    /// Shuts the stream down so a connection waiting for its next query
+    /// notices it was killed.
+    disconnect: Box<dyn Fn() + Send>,
+}
+
+impl ConnectionInfo {
+    pub fn new(
+        username: String,
+        client_address: String,
+        disconnect: Box<dyn Fn() + Send>,
+    ) -> ConnectionInfo {
+        ConnectionInfo {
+            username,
+            client_address,
+            current_query: None,
+            query_started: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            disconnect,
+        }
+    }
+}
+
+/// One row per connection in the order they connected: the id, the user, the
+/// client address, the current query and how long it has been running. Only
+/// the connections of `owner` when given.
+pub fn processlist_rows(
+    processes: &HashMap<u64, ConnectionInfo>,
+    owner: Option<&str>,
+) -> Vec<Vec<String>> {
+    let mut ids: Vec<&u64> = processes
+        .iter()
+        .filter(|(_, info)| owner.is_none_or(|owner| info.username == owner))
+        .map(|(id, _)| id)
+        .collect();
+    ids.sort();
+    ids.into_iter()
+        .map(|id| {
+            let info = &processes[id];
+            let query = info.current_query.as_deref().unwrap_or_default();
+            let duration = info
+                .query_started
+                .map(|started| started.elapsed().as_millis())
+                .unwrap_or_default();
+            vec![
+                id.to_string(),
+                info.username.clone(),
+                info.client_address.clone(),
+                query.chars().take(QUERY_DISPLAY_LENGTH).collect(),
+                duration.to_string(),
+            ]
+        })
+        .collect()
+}
+
+/// Cancels the connection `id` and disconnects it. Only a connection of
+/// `owner` when given.
+pub fn kill(
+    processes: &HashMap<u64, ConnectionInfo>,
+    id: u64,
+    owner: Option<&str>,
+) -> Result<(), String> {
+    let info = processes
+        .get(&id)
+        .ok_or_else(|| format!("Connection {} does not exist", id))?;
+    if owner.is_some_and(|owner| info.username != owner) {
+        return Err(format!("Permission denied for KILL of connection {}", id));
+    }
+    info.cancelled.store(true, Ordering::SeqCst);
+    (info.disconnect)();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_processlist_and_kill() {
+        let disconnected = Arc::new(AtomicBool::new(false));
+        let mut processes = HashMap::new();
+        let flag = Arc::clone(&disconnected);
+        let mut busy = ConnectionInfo::new(
+            "alice".to_string(),
+            "127.0.0.1:5000".to_string(),
+            Box::new(move || flag.store(true, Ordering::SeqCst)),
+        );
+        busy.current_query = Some(format!("SELECT * FROM {}", "x".repeat(100)));
+        busy.query_started = Some(Instant::now());
+        processes.insert(2, busy);
+        processes.insert(
+            1,
+            ConnectionInfo::new(String::new(), "local".to_string(), Box::new(|| {})),
+        );
+
+        let rows = processlist_rows(&processes, None);
+        assert_eq!(rows[0], ["1", "", "local", "", "0"]);
+        assert_eq!(rows[1][..3], ["2", "alice", "127.0.0.1:5000"]);
+        assert_eq!(rows[1][3].chars().count(), QUERY_DISPLAY_LENGTH);
+        let rows = processlist_rows(&processes, Some("alice"));
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0], "2");
+
+        assert!(kill(&processes, 3, None).is_err());
+        assert_eq!(
+            kill(&processes, 2, Some("bob")),
+            Err("Permission denied for KILL of connection 2".to_string())
+        );
+        assert!(!processes[&2].cancelled.load(Ordering::SeqCst));
+        kill(&processes, 2, Some("alice")).unwrap();
+        assert!(processes[&2].cancelled.load(Ordering::SeqCst));
+        assert!(disconnected.load(Ordering::SeqCst));
+        assert!(!processes[&1].cancelled.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    fs::File,
+    io::Write,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    durability::{hash_index::hash, table::format_timestamp, DatabaseConfig},
+    query::fingerprint_query,
+};
+
+pub const DEFAULT_THRESHOLD_US: u64 = 100_000;
+
+pub fn slow_query_log_file(database: &DatabaseConfig) -> String {
+    format!("{}/slow_queries.log", database.file_path)
+}
+
+/// Appends queries that ran for longer than the session's
+/// `slow_query_threshold` to a log file, one
+/// `timestamp|duration_us|fingerprint|query_text` line each. The fingerprint
+/// is the hash of `fingerprint_query` in hex, shared by the queries that only
+/// differ in their literals so they can be grouped. Shared by every connection of the server, the file is opened on
+/// the first slow query.
+pub struct SlowQueryLog {
+    path: String,
+    file: Mutex<Option<File>>,
+}
+
+impl SlowQueryLog {
+    pub fn new(path: String) -> Self {
+        SlowQueryLog {
+            path,
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Logs the query when `execution_time` is over `threshold_us`. Returns
+    /// whether it was logged.
+    pub fn record(
+        &self,
+        query: &str,
+        execution_time: u128,
+        threshold_us: u64,
+    ) -> std::io::Result<bool> {
+        if execution_time <= threshold_us as u128 {
+            return Ok(false);
+        }
+
+        let line = format!(
+            "{}|{}|{:016x}|{}\n",
+            iso8601(SystemTime::now()),
+            execution_time,
+            hash(fingerprint_query(query).as_bytes()),
+            query.replace(['\n', '\r'], " ")
+        );
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if file.is_none() {
+            *file = Some(
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+        let file = file.as_mut().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        Ok(true)
+    }
+}
+
+/// Formats a time as `YYYY-MM-DDTHH:MM:SS.ffffffZ` in UTC.
+fn iso8601(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format_timestamp(since_epoch.as_micros() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_iso8601() {
+        assert_eq!(iso8601(UNIX_EPOCH), "1970-01-01T00:00:00.000000Z");
+        let time = UNIX_EPOCH + Duration::from_micros(1_709_210_096_123_456);
+        assert_eq!(iso8601(time), "2024-02-29T12:34:56.123456Z");
+    }
+
+    #[test]
+    fn test_record_only_slow_queries() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("slow_queries.log");
+        let log = SlowQueryLog::new(path.to_str().unwrap().to_string());
+
+        assert!(!log
+            .record("SELECT * FROM users", 100_000, DEFAULT_THRESHOLD_US)
+            .unwrap());
+        assert!(!path.exists());
+
+        assert!(log.record("SELECT *\nFROM users", 11, 10).unwrap());
+        assert!(log.record("SELECT id FROM users", 250, 10).unwrap());
+        assert!(log
+            .record("SELECT id FROM users WHERE id = 1", 12, 10)
+            .unwrap());
+        assert!(log
+            .record("SELECT id FROM users WHERE id = 999", 13, 10)
+            .unwrap());
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<Vec<&str>> = contents
+            .lines()
+            .map(|line| line.splitn(4, '|').collect())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0][0].ends_with('Z'));
+        assert_eq!(lines[0][1], "11");
+        assert_eq!(lines[0][3], "SELECT * FROM users");
+        assert_eq!(lines[1][1], "250");
+        assert_eq!(lines[1][3], "SELECT id FROM users");
+        assert_ne!(lines[0][2], lines[1][2]);
+        // Queries differing in their literals are grouped by the fingerprint.
+        assert_eq!(lines[2][2], lines[3][2]);
+        assert_ne!(lines[1][2], lines[2][2]);
+    }
+}
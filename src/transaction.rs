@@ -0,0 +1,116 @@
+use crate::durability::table::Row;
+
+/// A change made inside a transaction, applied to the table on `COMMIT`.
+#[derive(Debug, PartialEq)]
+pub enum Mutation {
+    Insert(Row),
+    /// Replaces the row with the same primary key, or inserts when none.
+    Upsert(Row),
+}
+
+/// The state of an open transaction: the mutations waiting for `COMMIT` and
+/// the savepoints set so far, each remembering how many mutations were
+/// pending when it was created.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    mutations: Vec<Mutation>,
+    savepoints: Vec<(String, usize)>,
+}
+
+impl Transaction {
+    pub fn push(&mut self, mutation: Mutation) {
+        self.mutations.push(mutation);
+    }
+
+    /// Setting a savepoint with the name of an existing one replaces it.
+    pub fn savepoint(&mut self, name: &str) {
+        self.savepoints.retain(|(savepoint, _)| savepoint != name);
+        self.savepoints
+            .push((name.to_string(), self.mutations.len()));
+    }
+
+    /// Discards the mutations made after the savepoint along with the
+    /// savepoints set after it. The savepoint itself stays usable.
+    pub fn rollback_to(&mut self, name: &str) -> Result<(), String> {
+        let position = self.find_savepoint(name)?;
+        let (_, mutation_count) = self.savepoints[position];
+        self.mutations.truncate(mutation_count);
+        self.savepoints.truncate(position + 1);
+        Ok(())
+    }
+
+    /// Forgets the savepoint and the ones set after it, keeping the mutations.
+    pub fn release(&mut self, name: &str) -> Result<(), String> {
+        let position = self.find_savepoint(name)?;
+        self.savepoints.truncate(position);
+        Ok(())
+    }
+
+    pub fn into_mutations(self) -> Vec<Mutation> {
+        self.mutations
+    }
+
+    fn find_savepoint(&self, name: &str) -> Result<usize, String> {
+        self.savepoints
+            .iter()
+            .rposition(|(savepoint, _)| savepoint == name)
+            .ok_or_else(|| format!("Savepoint {} does not exist", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mutation, Transaction};
+    use crate::durability::table::Row;
+
+    fn insert(id: &str) -> Mutation {
+        Mutation::Insert(Row {
+            data: vec![id.as_bytes().to_vec()],
+        })
+    }
+
+    #[test]
+    fn rollback_to_savepoint() {
+        let mut transaction = Transaction::default();
+        transaction.push(insert("1"));
+        transaction.savepoint("a");
+        transaction.push(insert("2"));
+        transaction.savepoint("b");
+        transaction.push(insert("3"));
+
+        transaction.rollback_to("a").unwrap();
+        assert!(transaction.rollback_to("b").is_err());
+        transaction.push(insert("4"));
+        transaction.rollback_to("a").unwrap();
+        transaction.push(insert("5"));
+
+        assert_eq!(transaction.into_mutations(), vec![insert("1"), insert("5")]);
+    }
+
+    #[test]
+    fn duplicate_savepoint_overwrites() {
+        let mut transaction = Transaction::default();
+        transaction.savepoint("a");
+        transaction.push(insert("1"));
+        transaction.savepoint("a");
+        transaction.push(insert("2"));
+
+        transaction.rollback_to("a").unwrap();
+        assert_eq!(transaction.into_mutations(), vec![insert("1")]);
+    }
+
+    #[test]
+    fn release_savepoint() {
+        let mut transaction = Transaction::default();
+        transaction.savepoint("a");
+        transaction.push(insert("1"));
+        transaction.savepoint("b");
+        transaction.push(insert("2"));
+
+        transaction.release("a").unwrap();
+        assert!(transaction.rollback_to("a").is_err());
+        assert!(transaction.rollback_to("b").is_err());
+        assert!(transaction.release("a").is_err());
+        assert_eq!(transaction.into_mutations(), vec![insert("1"), insert("2")]);
+    }
+}
@@ -0,0 +1,33 @@
+use cargo_db::{ColumnDefinition, ColumnType, Database, Operator, Row};
+use tempfile::tempdir;
+
+#[test]
+fn test_embedded_database() {
+    let tmp_dir = tempdir().unwrap();
+    let mut db = Database::open(tmp_dir.path().join("mydb").to_str().unwrap()).unwrap();
+    let mut table = db
+        .create_table(
+            "users",
+            vec![
+                ColumnDefinition::new("id".to_string(), ColumnType::Int, 11),
+                ColumnDefinition::new("name".to_string(), ColumnType::Varchar, 20),
+            ],
+        )
+        .unwrap();
+    for (id, name) in [("1", "Ada"), ("2", "Grace")] {
+        table
+            .insert(Row {
+                data: vec![id.as_bytes().to_vec(), name.as_bytes().to_vec()],
+            })
+            .unwrap();
+    }
+
+    let rows = table
+        .select()
+        .columns(&["name"])
+        .where_op("id", Operator::Gt, b"1")
+        .run()
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].data, [b"Grace".to_vec()]);
+}
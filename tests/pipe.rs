@@ -0,0 +1,96 @@
+use std::{
+    ffi::CString,
+    io::{BufRead, BufReader, Write},
+    process::{Child, Command, Stdio},
+    sync::mpsc::{channel, Receiver},
+    time::Duration,
+};
+
+use tempfile::tempdir;
+
+struct Repl(Child);
+
+impl Drop for Repl {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// The lines of output up to the `Execution time` line ending a result.
+fn read_result(lines: &Receiver<String>) -> Vec<String> {
+    let mut result = vec![];
+    loop {
+        let line = lines
+            .recv_timeout(Duration::from_secs(10))
+            .expect("no result");
+        if line.starts_with("Execution time") {
+            return result;
+        }
+        result.push(line);
+    }
+}
+
+#[test]
+fn test_queries_from_fifo() {
+    let dir = tempdir().unwrap();
+    let fifo = dir.path().join("queries");
+    let path = CString::new(fifo.to_str().unwrap()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o600) }, 0);
+
+    let mut repl = Repl(
+        Command::new(env!("CARGO_BIN_EXE_cargo_db"))
+            .args(["--pipe", fifo.to_str().unwrap()])
+            .current_dir(dir.path())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap(),
+    );
+    let (sender, lines) = channel();
+    let stdout = BufReader::new(repl.0.stdout.take().unwrap());
+    std::thread::spawn(move || {
+        for line in stdout.lines() {
+            if sender.send(line.unwrap()).is_err() {
+                return;
+            }
+        }
+    });
+
+    // Opening the pipe waits for the reader.
+    let mut writer = std::fs::OpenOptions::new().write(true).open(&fifo).unwrap();
+    writer
+        .write_all(b"INSERT INTO account_tbl (id,account_id) VALUES (1,10);\nSELECT * FROM ")
+        .unwrap();
+    writer.write_all(b"account_tbl;\n").unwrap();
+    drop(writer);
+    assert!(read_result(&lines).contains(&r#"["Inserting 1 row(s)"]"#.to_string()));
+    assert!(read_result(&lines).contains(&r#"["1", "10"]"#.to_string()));
+
+    // Every writer is gone, the next one is still read from.
+    std::thread::sleep(Duration::from_millis(300));
+    assert!(repl.0.try_wait().unwrap().is_none());
+    let mut writer = std::fs::OpenOptions::new().write(true).open(&fifo).unwrap();
+    writer
+        .write_all(b"SELECT * FROM account_tbl WHERE id = 1;\n")
+        .unwrap();
+    assert!(read_result(&lines).contains(&r#"["1", "10"]"#.to_string()));
+
+    // `.exit` ends the session, dropping its temporary tables.
+    writer
+        .write_all(b"SELECT INTO TEMP TABLE recent FROM account_tbl;\n")
+        .unwrap();
+    assert!(read_result(&lines)
+        .contains(&r#"["Created temporary table recent with 1 row"]"#.to_string()));
+    let temp_directory = std::env::temp_dir().join(format!("cargo_db_{}_0", repl.0.id()));
+    assert!(temp_directory.exists());
+    writer.write_all(b".exit\n").unwrap();
+    drop(writer);
+    for _ in 0..100 {
+        if repl.0.try_wait().unwrap().is_some() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert!(repl.0.try_wait().unwrap().unwrap().success());
+    assert!(!temp_directory.exists());
+}
@@ -0,0 +1,2256 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    os::unix::net::UnixStream,
+    path::Path,
+    process::{Child, Command, Stdio},
+};
+
+use tempfile::tempdir;
+
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Starts the server in `dir` and waits until it reports where it listens.
+fn start_server(dir: &Path, args: &[&str]) -> (Server, String) {
+    let mut server = Server(
+        Command::new(env!("CARGO_BIN_EXE_cargo_db"))
+            .args(args)
+            .current_dir(dir)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap(),
+    );
+
+    let mut stdout = BufReader::new(server.0.stdout.take().unwrap());
+    loop {
+        let mut line = String::new();
+        assert!(stdout.read_line(&mut line).unwrap() > 0, "server exited");
+        if let Some(address) = line.trim().strip_prefix("Listening on ") {
+            // Keep draining the output so the server never blocks on a full pipe.
+            std::thread::spawn(move || std::io::copy(&mut stdout, &mut std::io::sink()));
+            return (server, address.to_string());
+        }
+    }
+}
+
+fn send_query(writer: &mut impl Write, query: &str) {
+    let mut frame = (query.len() as u32).to_le_bytes().to_vec();
+    frame.push(0x01);
+    frame.extend(query.as_bytes());
+    writer.write_all(&frame).unwrap();
+}
+
+/// Reads `ResultRow` frames until `Done` or `Error`, joining the columns of
+/// every row with tabs.
+fn read_result(reader: &mut impl Read) -> Vec<String> {
+    let mut rows = vec![];
+    loop {
+        let mut header = [0; 5];
+        reader.read_exact(&mut header).unwrap();
+        let length = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let mut payload = vec![0; length as usize];
+        reader.read_exact(&mut payload).unwrap();
+
+        match header[4] {
+            0x02 => {
+                let mut columns = vec![];
+                let mut rest = payload.as_slice();
+                while !rest.is_empty() {
+                    let length = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+                    columns.push(String::from_utf8(rest[4..4 + length].to_vec()).unwrap());
+                    rest = &rest[4 + length..];
+                }
+                rows.push(columns.join("\t"));
+            }
+            0x03 => return rows,
+            0x04 => {
+                rows.push(format!("Error: {}", String::from_utf8(payload).unwrap()));
+                return rows;
+            }
+            message_type => panic!("Unexpected message type {}", message_type),
+        }
+    }
+}
+
+#[test]
+fn test_server_executes_queries() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    send_query(
+        &mut stream,
+        "INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20);",
+    );
+    assert_eq!(read_result(&mut reader), vec!["Inserting 2 row(s)"]);
+
+    let mut other = TcpStream::connect(&address).unwrap();
+    let mut other_reader = BufReader::new(other.try_clone().unwrap());
+    send_query(&mut other, "SELECT * FROM account_tbl WHERE id > 1;");
+    assert_eq!(read_result(&mut other_reader), vec!["2\t20"]);
+
+    send_query(&mut stream, "SELECT * FROM account_tbl;");
+    assert_eq!(read_result(&mut reader), vec!["1\t10", "2\t20"]);
+
+    send_query(&mut stream, "BOGUS QUERY");
+    assert_eq!(read_result(&mut reader), vec!["Error: Invalid query"]);
+    send_query(&mut stream, "SELECT id FROM account_tbl");
+    assert_eq!(read_result(&mut reader), vec!["1", "2"]);
+}
+
+#[test]
+fn test_server_over_unix_socket() {
+    let tmp_dir = tempdir().unwrap();
+    let socket = tmp_dir.path().join("city_db.sock");
+    std::fs::write(&socket, b"left over").unwrap();
+
+    let (mut server, address) = start_server(
+        tmp_dir.path(),
+        &["--server", "--socket", socket.to_str().unwrap()],
+    );
+    assert_eq!(address, socket.to_str().unwrap());
+
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    send_query(
+        &mut stream,
+        "INSERT INTO account_tbl (id,account_id) VALUES (3,30);",
+    );
+    assert_eq!(read_result(&mut reader), vec!["Inserting 1 row(s)"]);
+    send_query(&mut stream, "SELECT * FROM account_tbl;");
+    assert_eq!(read_result(&mut reader), vec!["3\t30"]);
+
+    unsafe { libc::kill(server.0.id() as libc::pid_t, libc::SIGTERM) };
+    server.0.wait().unwrap();
+    assert!(!socket.exists());
+}
+
+#[test]
+fn test_rollback_to_savepoint() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("BEGIN");
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20) (3,30)");
+    assert_eq!(
+        execute("SAVEPOINT before_more"),
+        vec!["Created savepoint before_more"]
+    );
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (4,40) (5,50)");
+    assert_eq!(
+        execute("ROLLBACK TO SAVEPOINT before_more"),
+        vec!["Rolled back to savepoint before_more"]
+    );
+    // Nothing is written before the commit.
+    assert!(execute("SELECT * FROM account_tbl").is_empty());
+    assert_eq!(execute("COMMIT"), vec!["Committed 3 mutation(s)"]);
+
+    assert_eq!(
+        execute("SELECT * FROM account_tbl"),
+        vec!["1\t10", "2\t20", "3\t30"]
+    );
+    assert_eq!(
+        execute("ROLLBACK TO SAVEPOINT before_more"),
+        vec!["No transaction in progress"]
+    );
+}
+
+#[test]
+fn test_backup_and_restore() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20)");
+    assert_eq!(
+        execute("BACKUP DATABASE TO 'backup.db'"),
+        vec!["Backed up 3 file(s) to backup.db"]
+    );
+    assert!(tmp_dir.path().join("backup.db").exists());
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (3,30)");
+    assert_eq!(execute("SELECT id FROM account_tbl"), vec!["1", "2", "3"]);
+
+    assert_eq!(
+        execute("RESTORE DATABASE FROM 'backup.db'"),
+        vec!["Restored 3 file(s) from backup.db"]
+    );
+    assert_eq!(execute("SELECT * FROM account_tbl"), vec!["1\t10", "2\t20"]);
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (4,40)");
+    assert_eq!(execute("SELECT id FROM account_tbl"), vec!["1", "2", "4"]);
+}
+
+#[test]
+fn test_slow_query_log() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    let values: Vec<String> = (0..100).map(|i| format!("({},{})", i, i * 10)).collect();
+    execute(&format!(
+        "INSERT INTO account_tbl (id,account_id) VALUES {}",
+        values.join(" ")
+    ));
+    let log = tmp_dir.path().join("slow_queries.log");
+    assert!(!log.exists());
+
+    assert_eq!(
+        execute("SET SLOW_QUERY_THRESHOLD = 1"),
+        vec!["Set slow_query_threshold to 1"]
+    );
+    assert_eq!(execute("SELECT * FROM account_tbl").len(), 100);
+
+    let contents = std::fs::read_to_string(&log).unwrap();
+    let entry: Vec<&str> = contents.lines().last().unwrap().splitn(4, '|').collect();
+    assert!(entry[0].ends_with('Z'));
+    assert!(entry[1].parse::<u128>().unwrap() > 1);
+    assert_eq!(entry[2].len(), 16);
+    assert_eq!(entry[3], "SELECT * FROM account_tbl");
+
+    assert_eq!(
+        execute("SET SLOW_QUERY_THRESHOLD = soon"),
+        vec!["Invalid value soon for slow_query_threshold"]
+    );
+}
+
+#[test]
+fn test_query_log() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10)");
+    execute("SELECT * FROM account_tbl");
+    execute("SET SLOW_QUERY_THRESHOLD = soon");
+    assert_eq!(execute("FLUSH QUERY LOG"), vec!["Flushed query log"]);
+
+    let log = tmp_dir.path().join("city_db.querylog");
+    let contents = std::fs::read_to_string(&log).unwrap();
+    let entries: Vec<Vec<&str>> = contents
+        .lines()
+        .map(|line| line.splitn(4, '\t').collect())
+        .collect();
+    assert_eq!(entries.len(), 3);
+    assert!(entries[0][0].ends_with('Z'));
+    assert!(entries[1][1].parse::<u128>().is_ok());
+    assert_eq!(
+        entries[0][2..],
+        [
+            "OK",
+            "INSERT INTO account_tbl (id,account_id) VALUES (1,10)"
+        ]
+    );
+    assert_eq!(entries[1][2..], ["OK", "SELECT * FROM account_tbl"]);
+    assert_eq!(entries[2][2..], ["ERR", "SET SLOW_QUERY_THRESHOLD = soon"]);
+
+    let last = execute("SHOW QUERY LOG LAST 2");
+    assert_eq!(last.len(), 2);
+    assert!(last[0].ends_with("\tERR\tSET SLOW_QUERY_THRESHOLD = soon"));
+    assert!(last[1].ends_with("\tOK\tFLUSH QUERY LOG"));
+
+    assert_eq!(execute("CLEAR QUERY LOG"), vec!["Cleared query log"]);
+    let last = execute("SHOW QUERY LOG LAST 10");
+    assert_eq!(last.len(), 1);
+    assert!(last[0].ends_with("\tOK\tCLEAR QUERY LOG"));
+}
+
+#[test]
+fn test_index_scan() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    let values: Vec<String> = (0..40).map(|i| format!("({},{})", i, i % 2)).collect();
+    execute(&format!(
+        "INSERT INTO account_tbl (id,account_id) VALUES {}",
+        values.join(" ")
+    ));
+    assert_eq!(
+        execute("EXPLAIN SELECT * FROM account_tbl WHERE id = 7"),
+        vec!["Seq Scan on account_tbl (rows=40 pages=9)"]
+    );
+
+    assert_eq!(
+        execute("CREATE INDEX idx_id ON account_tbl (id)"),
+        vec!["Created index idx_id"]
+    );
+    assert_eq!(
+        execute("EXPLAIN SELECT * FROM account_tbl WHERE id = 7"),
+        vec!["Index Scan using idx_id on account_tbl (id = 7) (rows=1 pages=2)"]
+    );
+    let plan: Vec<serde_json::Value> =
+        execute("EXPLAIN FORMAT=JSON SELECT * FROM account_tbl WHERE id = 7")
+            .iter()
+            .map(|row| serde_json::from_str(row).unwrap())
+            .collect();
+    assert_eq!(
+        plan,
+        vec![serde_json::json!({
+            "plan": "IndexScan",
+            "table": "account_tbl",
+            "estimated_rows": 1,
+            "pages": 2,
+            "index_name": "idx_id",
+            "index_type": "btree",
+            "seek_key": [{"col": "id", "op": "=", "val": "7"}],
+        })]
+    );
+    assert_eq!(
+        execute("SELECT * FROM account_tbl WHERE id = 7"),
+        vec!["7\t1"]
+    );
+
+    // Rows inserted after the index was built are still found.
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (7,70) (41,1)");
+    assert_eq!(
+        execute("SELECT * FROM account_tbl WHERE id = 7"),
+        vec!["7\t1", "7\t70"]
+    );
+    assert_eq!(
+        execute("EXPLAIN SELECT * FROM account_tbl WHERE account_id = 1"),
+        vec!["Seq Scan on account_tbl (rows=21 pages=9)"]
+    );
+
+    // Range predicates on a small table go through the skip list, which is
+    // rebuilt once the table changes.
+    assert_eq!(
+        execute("EXPLAIN SELECT * FROM account_tbl WHERE id >= 38"),
+        vec!["Skip List Scan on account_tbl (id >= 38) (rows=14 pages=9)"]
+    );
+    assert_eq!(
+        execute("SELECT * FROM account_tbl WHERE id >= 38"),
+        vec!["38\t0", "39\t1", "41\t1"]
+    );
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (40,0)");
+    assert_eq!(
+        execute("SELECT * FROM account_tbl WHERE id >= 38 AND id < 41"),
+        vec!["38\t0", "39\t1", "40\t0"]
+    );
+}
+
+#[test]
+fn test_set_and_show() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    let values: Vec<String> = (0..20).map(|i| format!("({},{})", i, i * 10)).collect();
+    execute(&format!(
+        "INSERT INTO account_tbl (id,account_id) VALUES {}",
+        values.join(" ")
+    ));
+
+    assert_eq!(execute("SHOW page_cache_size"), vec!["1024"]);
+    assert_eq!(
+        execute("SET page_cache_size = 2"),
+        vec!["Set page_cache_size to 2"]
+    );
+    assert_eq!(execute("SHOW page_cache_size"), vec!["2"]);
+    // Pages evicted from the shrunk cache are read again.
+    assert_eq!(execute("SELECT * FROM account_tbl").len(), 20);
+    assert_eq!(
+        execute("SELECT id FROM account_tbl WHERE id = 19"),
+        vec!["19"]
+    );
+
+    assert_eq!(
+        execute("SET auto_analyze = on"),
+        vec!["Set auto_analyze to true"]
+    );
+    assert_eq!(
+        execute("SET output_format = 'json'"),
+        vec![r#"["Set output_format to json"]"#]
+    );
+    assert_eq!(
+        execute("SELECT * FROM account_tbl WHERE id = 3"),
+        vec![r#"["3","30"]"#]
+    );
+    assert_eq!(
+        execute("SHOW ALL"),
+        vec![
+            r#"["page_cache_size","2"]"#,
+            r#"["slow_query_threshold","100000"]"#,
+            r#"["output_format","json"]"#,
+            r#"["auto_analyze","true"]"#,
+            r#"["wal_autocheckpoint","1048576"]"#,
+            r#"["lock_timeout","30"]"#,
+        ]
+    );
+
+    assert_eq!(
+        execute("SET page_cache_size = -1"),
+        vec![r#"["Invalid value -1 for page_cache_size"]"#]
+    );
+    assert_eq!(
+        execute("SHOW work_mem"),
+        vec![r#"["Unknown variable work_mem"]"#]
+    );
+}
+
+#[test]
+fn test_upsert_requires_primary_key() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    assert_eq!(
+        execute("INSERT OR REPLACE INTO account_tbl (id,account_id) VALUES (1,10)"),
+        vec!["INSERT OR REPLACE requires a primary key on table account_tbl"]
+    );
+    assert!(execute("SELECT * FROM account_tbl").is_empty());
+}
+
+#[test]
+fn test_copy_from_stdin_binary() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    let mut rows = vec![];
+    for id in 0..1500 {
+        rows.extend(2u32.to_le_bytes());
+        for field in [id.to_string(), (id * 10).to_string()] {
+            rows.extend((field.len() as u32).to_le_bytes());
+            rows.extend(field.as_bytes());
+        }
+    }
+    rows.extend(0u32.to_le_bytes());
+
+    send_query(&mut stream, "COPY account_tbl FROM STDIN BINARY");
+    stream.write_all(&rows).unwrap();
+    let result = read_result(&mut reader);
+    assert_eq!(result.len(), 1);
+    assert!(result[0].starts_with("Copied 1500 row(s) in "));
+
+    send_query(&mut stream, "SELECT * FROM account_tbl WHERE id = 1499");
+    assert_eq!(read_result(&mut reader), vec!["1499\t14990"]);
+
+    // A refused copy still consumes its rows.
+    send_query(&mut stream, "COPY users FROM STDIN BINARY");
+    stream.write_all(&rows).unwrap();
+    assert_eq!(read_result(&mut reader), vec!["Table users does not exist"]);
+    send_query(&mut stream, "SELECT id FROM account_tbl WHERE id = 0");
+    assert_eq!(read_result(&mut reader), vec!["0"]);
+}
+
+#[test]
+fn test_copy_to_binary_file() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20)");
+    assert_eq!(
+        execute("COPY account_tbl TO 'accounts.bin' BINARY"),
+        vec!["Copied 2 row(s) to accounts.bin"]
+    );
+    assert!(tmp_dir.path().join("accounts.bin").exists());
+
+    let result = execute("COPY account_tbl FROM 'accounts.bin' BINARY");
+    assert!(result[0].starts_with("Copied 2 row(s) in "));
+    assert_eq!(
+        execute("SELECT * FROM account_tbl"),
+        vec!["1\t10", "2\t20", "1\t10", "2\t20"]
+    );
+    assert_eq!(
+        execute("COPY users TO 'users.bin' BINARY"),
+        vec!["Table users does not exist"]
+    );
+}
+
+#[test]
+fn test_checkpoint() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+    let wal = tmp_dir.path().join("account_tbl.wal");
+    // Only the commit record carrying the sequence over is left.
+    let checkpointed_size = 25;
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20)");
+    assert!(std::fs::metadata(&wal).unwrap().len() > checkpointed_size);
+    assert_eq!(execute("CHECKPOINT"), vec!["Checkpointed 2 WAL record(s)"]);
+    assert_eq!(std::fs::metadata(&wal).unwrap().len(), checkpointed_size);
+    assert_eq!(execute("CHECKPOINT"), vec!["Checkpointed 0 WAL record(s)"]);
+    assert!(tmp_dir.path().join("city_db").exists());
+
+    execute("SET wal_autocheckpoint = 100");
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (3,30) (4,40)");
+    for _ in 0..100 {
+        if std::fs::metadata(&wal).unwrap().len() == checkpointed_size {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(std::fs::metadata(&wal).unwrap().len(), checkpointed_size);
+    assert_eq!(
+        execute("SELECT id FROM account_tbl"),
+        vec!["1", "2", "3", "4"]
+    );
+}
+
+#[test]
+fn test_restore_from_wal() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    // Every insert logs its row, padded up to the end of its page, and the
+    // row count, at LSNs 1 and 2 for the first one.
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10)");
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (2,20)");
+    execute("CHECKPOINT");
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (3,30)");
+
+    assert_eq!(
+        execute("RESTORE FROM WAL AT LSN 4"),
+        vec!["Restored account_tbl to LSN 4, replayed 4 WAL record(s)"]
+    );
+    assert_eq!(execute("SELECT * FROM account_tbl"), vec!["1\t10", "2\t20"]);
+    assert_eq!(
+        execute("RESTORE FROM WAL AT LSN 7"),
+        vec!["DbError(\"LSN 7 is past the end of the log at 6\")"]
+    );
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (4,40)");
+    assert_eq!(
+        execute("RESTORE FROM WAL AT LSN 2"),
+        vec!["Restored account_tbl to LSN 2, replayed 2 WAL record(s)"]
+    );
+    assert_eq!(execute("SELECT * FROM account_tbl"), vec!["1\t10"]);
+}
+
+#[test]
+fn test_rebuild_index() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    let values: Vec<String> = (0..40).map(|i| format!("({},{})", i, i % 2)).collect();
+    execute(&format!(
+        "INSERT INTO account_tbl (id,account_id) VALUES {}",
+        values.join(" ")
+    ));
+    execute("CREATE INDEX idx_id ON account_tbl (id)");
+    execute("CREATE HASH INDEX idx_account_id ON account_tbl (account_id)");
+
+    // Keep only the header, which leaves an index that finds nothing.
+    let path = tmp_dir.path().join("account_tbl.id.idx");
+    let header_size = 64 + 4 + 64 + 8 + 8 + 128;
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes.truncate(header_size);
+    std::fs::write(&path, bytes).unwrap();
+    assert!(execute("SELECT * FROM account_tbl WHERE id = 7").is_empty());
+
+    assert_eq!(
+        execute("REBUILD INDEX idx_id ON account_tbl"),
+        vec!["Rebuilt index idx_id with 40 entries"]
+    );
+    assert_eq!(
+        execute("SELECT * FROM account_tbl WHERE id = 7"),
+        vec!["7\t1"]
+    );
+    assert_eq!(
+        execute("REBUILD INDEX idx_missing ON account_tbl"),
+        vec!["Index idx_missing does not exist"]
+    );
+    assert_eq!(
+        execute("REBUILD ALL INDEXES ON account_tbl"),
+        vec![
+            "Rebuilt index idx_id with 40 entries",
+            "Rebuilt index idx_account_id with 40 entries"
+        ]
+    );
+}
+
+#[test]
+fn test_call_procedure() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    assert_eq!(
+        execute(
+            "CREATE PROCEDURE add_account AS 'local id, account_id = ... \
+             db.execute(''INSERT INTO account_tbl (id,account_id) VALUES ('' .. id .. '','' .. account_id .. '')'') \
+             return db.execute(''SELECT * FROM account_tbl WHERE id = '' .. id)'"
+        ),
+        vec!["Created procedure add_account"]
+    );
+    assert_eq!(execute("CALL add_account(5, 50)"), vec!["5\t50"]);
+    assert_eq!(execute("CALL add_account(6, 60)"), vec!["6\t60"]);
+    assert_eq!(execute("SELECT id FROM account_tbl"), vec!["5", "6"]);
+    assert!(tmp_dir.path().join("city_db.procedures").exists());
+
+    assert_eq!(
+        execute("CALL missing()"),
+        vec!["DbError(\"Procedure missing does not exist\")"]
+    );
+    execute("CREATE PROCEDURE read_file AS 'return io.open(''city_db'')'");
+    let result = execute("CALL read_file()");
+    assert!(result[0].starts_with("Procedure read_file failed: "));
+
+    // Nor through the statements of the database that touch files.
+    execute(
+        "CREATE PROCEDURE copy_out AS \
+         'return db.execute(\"COPY account_tbl TO ''accounts.bin'' BINARY\")'",
+    );
+    let result = execute("CALL copy_out()");
+    assert!(result[0].starts_with("Procedure copy_out failed: "));
+    assert!(result[0].contains("Procedures cannot touch files"));
+    assert!(!tmp_dir.path().join("accounts.bin").exists());
+    execute("PREPARE dump AS 'DEBUG DUMP TABLE account_tbl'");
+    execute("CREATE PROCEDURE run_dump AS 'return db.execute(''EXECUTE dump'')'");
+    let result = execute("CALL run_dump()");
+    assert!(result[0].starts_with("Procedure run_dump failed: "));
+}
+
+#[test]
+fn test_create_and_drop_table_if_exists() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    assert_eq!(
+        execute("CREATE TABLE IF NOT EXISTS users (id INT 11)"),
+        vec!["Created table users"]
+    );
+    assert_eq!(
+        execute("CREATE TABLE IF NOT EXISTS users (id INT 11)"),
+        vec!["Table already exists, skipped"]
+    );
+    assert_eq!(
+        execute("CREATE TABLE users (id INT 11)"),
+        vec!["Table users already exists"]
+    );
+
+    assert_eq!(execute("DROP TABLE users"), vec!["Dropped table users"]);
+    assert!(!tmp_dir.path().join("users").exists());
+    assert_eq!(
+        execute("DROP TABLE IF EXISTS users"),
+        vec!["Table does not exist, skipped"]
+    );
+    assert_eq!(
+        execute("DROP TABLE users"),
+        vec!["Table users does not exist"]
+    );
+    assert_eq!(
+        execute("DROP TABLE account_tbl"),
+        vec!["Cannot drop the open table account_tbl"]
+    );
+}
+
+#[test]
+fn test_config_file() {
+    let tmp_dir = tempdir().unwrap();
+    std::fs::write(
+        tmp_dir.path().join("city_db.conf"),
+        "max_page_cache_size = 16\nslow_query_threshold_us = 250\nunknown_key = 1\n",
+    )
+    .unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    assert_eq!(
+        execute("SHOW CONFIG"),
+        vec![
+            "max_page_cache_size\t16",
+            "slow_query_threshold_us\t250",
+            "wal_enabled\ttrue",
+            "auto_analyze_interval_rows\t0",
+            "default_page_size\t128",
+        ]
+    );
+    assert_eq!(execute("SHOW page_cache_size"), vec!["16"]);
+    execute("SET page_cache_size = 32");
+    assert_eq!(execute("SHOW CONFIG")[0], "max_page_cache_size\t32");
+}
+
+#[test]
+fn test_show_create_table() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("CREATE TABLE cities (name VARCHAR 16 PRIMARY KEY)");
+    execute(
+        "CREATE TABLE users (id INT 11 PRIMARY KEY, email VARCHAR 32 DEFAULT 'none' UNIQUE, \
+         age INT 3 CHECK (age > 17), city VARCHAR 16, \
+         FOREIGN KEY (city) REFERENCES cities (name))",
+    );
+    let statement = execute("SHOW CREATE TABLE users");
+    assert_eq!(
+        statement,
+        vec![
+            "CREATE TABLE users (id INT 11 PRIMARY KEY, email VARCHAR 32 DEFAULT 'none' UNIQUE, \
+             age INT 3 CHECK (age > 17), city VARCHAR 16, \
+             FOREIGN KEY (city) REFERENCES cities (name))"
+        ]
+    );
+
+    assert_eq!(execute("DROP TABLE users"), vec!["Dropped table users"]);
+    assert_eq!(execute(&statement[0]), vec!["Created table users"]);
+    assert_eq!(execute("SHOW CREATE TABLE users"), statement);
+    assert_eq!(
+        execute("SHOW CREATE TABLE account_tbl"),
+        vec!["CREATE TABLE account_tbl (id INT 11, account_id INT 11)"]
+    );
+    assert_eq!(
+        execute("SHOW CREATE TABLE missing"),
+        vec!["Table missing does not exist"]
+    );
+}
+
+#[test]
+fn test_compress_column() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,500) (2,-20) (3,7)");
+    assert_eq!(
+        execute("COMPRESS COLUMN account_id ON account_tbl"),
+        vec!["Delta encoded column account_id"]
+    );
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (4,1000000) (5,3) (6,4)");
+    assert_eq!(
+        execute("SELECT * FROM account_tbl"),
+        vec!["1\t500", "2\t-20", "3\t7", "4\t1000000", "5\t3", "6\t4"]
+    );
+    assert_eq!(
+        execute("COMPRESS COLUMN account_id ON account_tbl"),
+        vec!["DbError(\"Column account_id is already delta encoded\")"]
+    );
+    assert_eq!(
+        execute("COMPRESS COLUMN id ON missing"),
+        vec!["Table missing does not exist"]
+    );
+}
+
+#[test]
+fn test_dictionary_encoded_column() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    assert_eq!(
+        execute("CREATE TABLE orders (id INT 11 PRIMARY KEY, status VARCHAR 2 DICTIONARY)"),
+        vec!["Created table orders"]
+    );
+    assert_eq!(
+        execute("SHOW CREATE TABLE orders"),
+        vec!["CREATE TABLE orders (id INT 11 PRIMARY KEY, status VARCHAR 2 DICTIONARY)"]
+    );
+    assert_eq!(
+        execute("CREATE TABLE labels (name VARCHAR 16 DICTIONARY)"),
+        vec!["Dictionary encoded column name must be of type VARCHAR with a length of 2"]
+    );
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,7) (2,7) (3,8)");
+    assert_eq!(
+        execute("ANALYZE COLUMN account_tbl.account_id"),
+        vec!["account_id\t2\t3\tNo suggestion"]
+    );
+    assert_eq!(
+        execute("ANALYZE COLUMN account_tbl.missing"),
+        vec!["DbError(\"Column missing does not exist\")"]
+    );
+    assert_eq!(
+        execute("ANALYZE COLUMN orders.status"),
+        vec!["Table orders does not exist"]
+    );
+}
+
+#[test]
+fn test_import_schema() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+    let schema = |name: &str, statements: &str| {
+        let path = tmp_dir.path().join(name);
+        std::fs::write(&path, statements).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+
+    let path = schema(
+        "schema.sql",
+        "CREATE TABLE customers (id INT 11 PRIMARY KEY, city VARCHAR 16);\n\
+         CREATE INDEX idx_city ON customers (city);\n\
+         CREATE INDEX idx_account ON account_tbl (account_id);\n\
+         CREATE VIEW big_accounts AS SELECT * FROM account_tbl WHERE account_id > 15;\n",
+    );
+    assert_eq!(
+        execute(&format!("IMPORT SCHEMA FROM '{}'", path)),
+        vec![format!("Imported 4 object(s) from {}", path)]
+    );
+    assert_eq!(
+        execute("SHOW CREATE TABLE customers"),
+        vec!["CREATE TABLE customers (id INT 11 PRIMARY KEY, city VARCHAR 16)"]
+    );
+    assert!(tmp_dir.path().join("customers.city.idx").exists());
+    assert_eq!(execute("SHOW VIEWS"), vec!["big_accounts"]);
+
+    // Nothing runs when the file holds data.
+    let path = schema(
+        "data.sql",
+        "CREATE TABLE orders (id INT 11);\nINSERT INTO orders (id) VALUES (1);",
+    );
+    assert_eq!(
+        execute(&format!("IMPORT SCHEMA FROM '{}'", path)),
+        vec!["Data manipulation not allowed in schema import, 0 object(s) imported before the failure"]
+    );
+    assert_eq!(
+        execute("SHOW CREATE TABLE orders"),
+        vec!["Table orders does not exist"]
+    );
+
+    let path = schema(
+        "again.sql",
+        "CREATE TABLE IF NOT EXISTS customers (id INT 11);\n\
+         CREATE TABLE orders (id INT 11);\n\
+         CREATE TABLE customers (id INT 11);\n\
+         CREATE TABLE products (id INT 11);",
+    );
+    let failed = execute(&format!("IMPORT SCHEMA FROM '{}'", path));
+    assert_eq!(failed.len(), 1);
+    assert!(failed[0].ends_with(", 2 object(s) imported before the failure"));
+    assert!(tmp_dir.path().join("orders").exists());
+    assert!(!tmp_dir.path().join("products").exists());
+}
+
+#[test]
+fn test_table_checksum() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    assert_eq!(execute("TABLE CHECKSUM account_tbl"), vec!["00000000"]);
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,7) (2,8)");
+    let checksum = execute("TABLE CHECKSUM account_tbl");
+    assert_eq!(checksum[0].len(), 8);
+    assert_ne!(checksum, vec!["00000000"]);
+    assert_eq!(execute("TABLE CHECKSUM account_tbl"), checksum);
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (3,9)");
+    assert_ne!(execute("TABLE CHECKSUM account_tbl"), checksum);
+
+    execute("CREATE TABLE orders (id INT 11)");
+    assert_eq!(execute("TABLE CHECKSUM orders"), vec!["00000000"]);
+    assert_eq!(
+        execute("TABLE CHECKSUM missing"),
+        vec!["Table missing does not exist"]
+    );
+}
+
+#[test]
+fn test_reindex_all() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+    // The rows without the time each took.
+    let counts = |rows: Vec<String>| -> Vec<String> {
+        rows.iter()
+            .map(|row| row.rsplit_once('\t').unwrap().0.to_string())
+            .collect()
+    };
+
+    let values: Vec<String> = (0..40).map(|i| format!("({},{})", i, i % 2)).collect();
+    execute(&format!(
+        "INSERT INTO account_tbl (id,account_id) VALUES {}",
+        values.join(" ")
+    ));
+    execute("CREATE INDEX idx_id ON account_tbl (id)");
+    execute("CREATE HASH INDEX idx_account_id ON account_tbl (account_id)");
+    let schema = tmp_dir.path().join("schema.sql");
+    std::fs::write(
+        &schema,
+        "CREATE TABLE customers (id INT 11, city VARCHAR 16);\n\
+         CREATE INDEX idx_city ON customers (city);",
+    )
+    .unwrap();
+    execute(&format!(
+        "IMPORT SCHEMA FROM '{}'",
+        schema.to_str().unwrap()
+    ));
+
+    // Keep only the header, which leaves an index that finds nothing.
+    let path = tmp_dir.path().join("account_tbl.id.idx");
+    let header_size = 64 + 4 + 64 + 8 + 8 + 128;
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes.truncate(header_size);
+    std::fs::write(&path, bytes).unwrap();
+    assert!(execute("SELECT * FROM account_tbl WHERE id = 7").is_empty());
+
+    assert_eq!(
+        counts(execute("REINDEX ALL")),
+        vec![
+            "account_tbl\t2 index(es)\t80 rows indexed",
+            "customers\t1 index(es)\t0 rows indexed",
+            "Total\t3 index(es)\t80 rows indexed",
+        ]
+    );
+    assert_eq!(
+        execute("SELECT * FROM account_tbl WHERE id = 7"),
+        vec!["7\t1"]
+    );
+    assert_eq!(
+        execute("SELECT * FROM account_tbl WHERE account_id = 1").len(),
+        20
+    );
+
+    assert_eq!(
+        counts(execute("REINDEX customers")),
+        vec![
+            "customers\t1 index(es)\t0 rows indexed",
+            "Total\t1 index(es)\t0 rows indexed",
+        ]
+    );
+    assert_eq!(
+        execute("REINDEX missing"),
+        vec!["Table missing does not exist"]
+    );
+}
+
+#[test]
+fn test_compact_database() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    let values: Vec<String> = (0..40).map(|i| format!("({},{})", i, i % 2)).collect();
+    execute(&format!(
+        "INSERT INTO account_tbl (id,account_id) VALUES {}",
+        values.join(" ")
+    ));
+    execute("CREATE TABLE customers (id INT 11, city VARCHAR 16)");
+    let size = |name: &str| std::fs::metadata(tmp_dir.path().join(name)).unwrap().len();
+    let (accounts, customers) = (size("account_tbl"), size("customers"));
+
+    // Rows cannot be deleted with a query, so nothing is recovered.
+    assert_eq!(
+        execute("COMPACT DATABASE"),
+        vec![
+            format!(
+                "account_tbl\t{0} bytes\t{0} bytes\t0 bytes recovered",
+                accounts
+            ),
+            format!(
+                "customers\t{0} bytes\t{0} bytes\t0 bytes recovered",
+                customers
+            ),
+            format!(
+                "Total\t{0} bytes\t{0} bytes\t0 bytes recovered",
+                accounts + customers
+            ),
+        ]
+    );
+    assert_eq!(execute("SELECT * FROM account_tbl").len(), 40);
+}
+
+#[test]
+fn test_explain_indexes() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+    let size = |name: &str| std::fs::metadata(tmp_dir.path().join(name)).unwrap().len();
+
+    assert_eq!(
+        execute("EXPLAIN INDEXES FOR account_tbl"),
+        vec!["No indexes found for table"]
+    );
+    let values: Vec<String> = (0..40).map(|i| format!("({},{})", i, i % 2)).collect();
+    execute(&format!(
+        "INSERT INTO account_tbl (id,account_id) VALUES {}",
+        values.join(" ")
+    ));
+    execute("CREATE INDEX idx_id ON account_tbl (id)");
+    execute("CREATE INDEX idx_id_account ON account_tbl (id, account_id) WHERE id > 9");
+    execute("CREATE HASH INDEX idx_account_id ON account_tbl (account_id)");
+    assert_eq!(
+        execute("EXPLAIN INDEXES FOR account_tbl"),
+        vec![
+            format!(
+                "idx_id_account\tid,account_id\tbtree\tno\t{}\t30",
+                size("account_tbl.id,account_id.idx")
+            ),
+            format!("idx_id\tid\tbtree\tno\t{}\t40", size("account_tbl.id.idx")),
+            format!(
+                "idx_account_id\taccount_id\thash\tno\t{}\t40",
+                size("account_tbl.account_id.hash")
+            ),
+        ]
+    );
+
+    // Another table, the index on its primary key is unique.
+    let schema = tmp_dir.path().join("schema.sql");
+    std::fs::write(
+        &schema,
+        "CREATE TABLE customers (id INT 11 PRIMARY KEY, city VARCHAR 16);\n\
+         CREATE INDEX idx_customer_id ON customers (id);",
+    )
+    .unwrap();
+    execute(&format!(
+        "IMPORT SCHEMA FROM '{}'",
+        schema.to_str().unwrap()
+    ));
+    assert_eq!(
+        execute("EXPLAIN INDEXES FOR customers"),
+        vec![format!(
+            "idx_customer_id\tid\tbtree\tyes\t{}\t0",
+            size("customers.id.idx")
+        )]
+    );
+    assert_eq!(
+        execute("EXPLAIN INDEXES FOR missing"),
+        vec!["Table missing does not exist"]
+    );
+}
+
+#[test]
+fn test_debug_dump_table() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    let size = std::fs::metadata(tmp_dir.path().join("account_tbl"))
+        .unwrap()
+        .len();
+    let dump = execute("DEBUG DUMP TABLE account_tbl");
+    assert_eq!(dump.len() as u64, 1 + size.div_ceil(16));
+    assert_eq!(dump[0], format!("account_tbl\t{} bytes", size));
+    assert_eq!(
+        dump[1],
+        "00000000\t61 63 63 6f 75 6e 74 5f 74 62 6c 00 00 00 00 00\taccount_tbl....."
+    );
+    assert_eq!(
+        execute("DEBUG DUMP TABLE missing"),
+        vec!["Table missing does not exist"]
+    );
+}
+
+#[test]
+fn test_insert_returning() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("CREATE SEQUENCE seq_accounts START 100");
+    assert_eq!(
+        execute(
+            "INSERT INTO account_tbl (id,account_id) VALUES (NEXTVAL('seq_accounts'),7) \
+             (NEXTVAL('seq_accounts'),8) RETURNING id"
+        ),
+        vec!["100", "101"]
+    );
+    assert_eq!(
+        execute("INSERT INTO account_tbl (id,account_id) VALUES (NEXTVAL('seq_accounts'),9) RETURNING *"),
+        vec!["102\t9"]
+    );
+    assert_eq!(
+        execute("INSERT INTO account_tbl (id,account_id) VALUES (5,10)"),
+        vec!["Inserting 1 row(s)"]
+    );
+    assert_eq!(
+        execute("INSERT INTO account_tbl (id,account_id) VALUES (6,11) RETURNING missing"),
+        vec!["Column missing does not exist"]
+    );
+    assert_eq!(
+        execute("SELECT id FROM account_tbl"),
+        vec!["100", "101", "102", "5"]
+    );
+}
+
+#[test]
+fn test_insert_select() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20) (3,30) (4,40)");
+    assert_eq!(
+        execute("INSERT INTO account_tbl SELECT * FROM account_tbl WHERE id >= 3"),
+        vec!["Inserted 2 row(s) into account_tbl"]
+    );
+    assert_eq!(
+        execute("SELECT * FROM account_tbl"),
+        vec!["1\t10", "2\t20", "3\t30", "4\t40", "3\t30", "4\t40"]
+    );
+    assert_eq!(
+        execute("INSERT INTO account_tbl SELECT id FROM account_tbl"),
+        vec!["INSERT ... SELECT only supports SELECT * FROM a table"]
+    );
+    assert_eq!(
+        execute("INSERT INTO account_tbl SELECT * FROM missing_tbl"),
+        vec!["Table missing_tbl does not exist"]
+    );
+}
+
+#[test]
+fn test_merge() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20)");
+    execute("CREATE TABLE staging (id INT 11, account_id INT 11)");
+
+    // Insert only, staging holds the accounts under their account id.
+    assert_eq!(
+        execute(
+            "MERGE INTO staging USING account_tbl ON staging.id = account_tbl.account_id \
+             WHEN NOT MATCHED THEN INSERT (id, account_id) \
+             VALUES (account_tbl.account_id, account_tbl.id)"
+        ),
+        vec!["0 rows updated", "2 rows inserted"]
+    );
+
+    // Update only.
+    assert_eq!(
+        execute(
+            "MERGE INTO account_tbl USING staging ON account_tbl.id = staging.account_id \
+             WHEN MATCHED THEN UPDATE SET account_tbl.account_id = 99"
+        ),
+        vec!["2 rows updated", "0 rows inserted"]
+    );
+    assert_eq!(execute("SELECT * FROM account_tbl"), vec!["1\t99", "2\t99"]);
+
+    // Mixed, staging row 10 matches and row 20 does not.
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (10,5)");
+    assert_eq!(
+        execute(
+            "MERGE INTO account_tbl USING staging ON account_tbl.id = staging.id \
+             WHEN MATCHED THEN UPDATE SET account_tbl.account_id = staging.account_id \
+             WHEN NOT MATCHED THEN INSERT (id, account_id) VALUES (staging.id, staging.account_id)"
+        ),
+        vec!["1 row updated", "1 row inserted"]
+    );
+    assert_eq!(
+        execute("SELECT * FROM account_tbl"),
+        vec!["1\t99", "2\t99", "10\t1", "20\t2"]
+    );
+
+    assert_eq!(
+        execute(
+            "MERGE INTO account_tbl USING staging ON account_tbl.id = staging.missing \
+             WHEN MATCHED THEN UPDATE SET account_id = 0"
+        ),
+        vec!["Column missing does not exist in table staging"]
+    );
+}
+
+#[test]
+fn test_with_cte() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    let values: Vec<String> = (0..10).map(|i| format!("({},{})", i, i % 3)).collect();
+    execute(&format!(
+        "INSERT INTO account_tbl (id,account_id) VALUES {}",
+        values.join(" ")
+    ));
+
+    let active = execute(
+        "WITH active AS (SELECT FROM account_tbl WHERE account_id = 1) \
+         SELECT id FROM active WHERE id > 3",
+    );
+    assert_eq!(active.len(), 2);
+    assert_eq!(active, vec!["4", "7"]);
+    assert_eq!(
+        execute(
+            "WITH active AS (SELECT * FROM account_tbl WHERE account_id = 0) SELECT * FROM active"
+        )
+        .len(),
+        4
+    );
+    assert_eq!(
+        execute("WITH active AS (SELECT id FROM account_tbl) SELECT * FROM active"),
+        vec!["WITH only supports SELECT * FROM a table"]
+    );
+}
+
+#[test]
+fn test_union() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20) (3,10)");
+
+    // Overlapping rows.
+    assert_eq!(
+        execute(
+            "SELECT account_id FROM account_tbl WHERE id < 3 \
+             UNION ALL SELECT account_id FROM account_tbl WHERE id > 1"
+        ),
+        vec!["10", "20", "20", "10"]
+    );
+    assert_eq!(
+        execute(
+            "SELECT account_id FROM account_tbl WHERE id < 3 \
+             UNION SELECT account_id FROM account_tbl WHERE id > 1"
+        ),
+        vec!["10", "20"]
+    );
+
+    // Non-overlapping rows.
+    assert_eq!(
+        execute("SELECT * FROM account_tbl WHERE id = 1 UNION ALL SELECT * FROM account_tbl WHERE id = 3"),
+        vec!["1\t10", "3\t10"]
+    );
+    assert_eq!(
+        execute(
+            "SELECT * FROM account_tbl WHERE id = 1 UNION SELECT * FROM account_tbl WHERE id = 3"
+        ),
+        vec!["1\t10", "3\t10"]
+    );
+
+    assert_eq!(
+        execute("SELECT id FROM account_tbl UNION SELECT * FROM account_tbl"),
+        vec!["Each query of a set operation must have the same number of columns, got 1 and 2"]
+    );
+}
+
+#[test]
+fn test_intersect_and_except() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    // account_id: 1 -> 10, 2 -> 20, 3 -> 10, 4 -> 30, 5 -> 20
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20) (3,10) (4,30) (5,20)");
+
+    // {10, 20, 10} and {10, 30, 20} share 10 and 20.
+    assert_eq!(
+        execute(
+            "SELECT account_id FROM account_tbl WHERE id < 4 \
+             INTERSECT SELECT account_id FROM account_tbl WHERE id > 2"
+        ),
+        vec!["10", "20"]
+    );
+    // {10, 20, 10} without {30, 20} leaves 10 once.
+    assert_eq!(
+        execute(
+            "SELECT account_id FROM account_tbl WHERE id < 4 \
+             EXCEPT SELECT account_id FROM account_tbl WHERE id > 3"
+        ),
+        vec!["10"]
+    );
+    assert_eq!(
+        execute(
+            "SELECT * FROM account_tbl WHERE id < 3 \
+             INTERSECT SELECT * FROM account_tbl WHERE id > 3"
+        ),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        execute(
+            "SELECT * FROM account_tbl WHERE id < 3 \
+             EXCEPT SELECT * FROM account_tbl WHERE id > 3"
+        ),
+        vec!["1\t10", "2\t20"]
+    );
+    assert_eq!(
+        execute("SELECT id FROM account_tbl EXCEPT SELECT * FROM account_tbl"),
+        vec!["Each query of a set operation must have the same number of columns, got 1 and 2"]
+    );
+}
+
+#[test]
+fn test_select_into_temp_table() {
+    let tmp_dir = tempdir().unwrap();
+    let (server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20) (3,30) (4,40)");
+    assert_eq!(
+        execute("SELECT INTO TEMP TABLE recent FROM account_tbl WHERE id > 2"),
+        vec!["Created temporary table recent with 2 rows"]
+    );
+    assert_eq!(execute("SELECT * FROM recent"), vec!["3\t30", "4\t40"]);
+    assert_eq!(
+        execute("SELECT account_id FROM recent WHERE id = 4"),
+        vec!["40"]
+    );
+    assert_eq!(
+        execute("SELECT account_id INTO TEMP TABLE accounts FROM account_tbl"),
+        vec!["Created temporary table accounts with 4 rows"]
+    );
+    assert_eq!(
+        execute("SELECT * FROM accounts"),
+        vec!["10", "20", "30", "40"]
+    );
+    // From another temporary table.
+    assert_eq!(
+        execute("SELECT INTO TEMP TABLE latest FROM recent WHERE id > 3"),
+        vec!["Created temporary table latest with 1 row"]
+    );
+    assert_eq!(execute("SELECT * FROM latest"), vec!["4\t40"]);
+    assert_eq!(
+        execute("SELECT INTO TEMP TABLE recent FROM account_tbl"),
+        vec!["Temporary table recent already exists"]
+    );
+    // Not in the database directory.
+    assert!(!tmp_dir.path().join("_temp_recent").exists());
+
+    // Other connections have temporary tables of their own.
+    let mut other = TcpStream::connect(&address).unwrap();
+    let mut other_reader = BufReader::new(other.try_clone().unwrap());
+    send_query(&mut other, "DROP TEMP TABLE recent");
+    assert_eq!(
+        read_result(&mut other_reader),
+        vec!["Temporary table recent does not exist"]
+    );
+
+    assert_eq!(
+        execute("DROP TEMP TABLE accounts"),
+        vec!["Dropped temporary table accounts"]
+    );
+    assert_eq!(
+        execute("DROP TEMP TABLE accounts"),
+        vec!["Temporary table accounts does not exist"]
+    );
+
+    // The rest go with the connection.
+    let directory = std::env::temp_dir().join(format!("cargo_db_{}_1", server.0.id()));
+    assert!(directory.join("_temp_recent").exists());
+    drop(stream);
+    drop(reader);
+    for _ in 0..100 {
+        if !directory.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(!directory.exists());
+}
+
+#[test]
+fn test_row_number() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (3,30) (1,20) (2,100) (4,20)");
+
+    assert_eq!(
+        execute("SELECT ROW_NUMBER() OVER (ORDER BY id), id FROM account_tbl"),
+        vec!["1\t1", "2\t2", "3\t3", "4\t4"]
+    );
+    assert_eq!(
+        execute("SELECT ROW_NUMBER() OVER (ORDER BY account_id DESC, id), id FROM account_tbl"),
+        vec!["1\t2", "2\t3", "3\t1", "4\t4"]
+    );
+    assert_eq!(
+        execute(
+            "SELECT ROW_NUMBER() OVER (ORDER BY account_id ASC), id FROM account_tbl WHERE id > 1"
+        ),
+        vec!["1\t4", "2\t3", "3\t2"]
+    );
+    assert_eq!(
+        execute("SELECT ROW_NUMBER() OVER (ORDER BY missing), id FROM account_tbl"),
+        vec!["Column missing does not exist"]
+    );
+}
+
+#[test]
+fn test_aggregates() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+    let aggregates = "SELECT COUNT(*), SUM(account_id), AVG(id), MIN(account_id), MAX(account_id) \
+                      FROM account_tbl";
+
+    assert_eq!(execute(aggregates), vec!["0\t\t\t\t"]);
+    execute("SET page_cache_size = 1");
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,30) (2,-5) (3,100) (4,15)");
+    assert_eq!(execute(aggregates), vec!["4\t140\t2.5\t-5\t100"]);
+    assert_eq!(
+        execute(&format!("{} WHERE id > 2", aggregates)),
+        vec!["2\t115\t3.5\t15\t100"]
+    );
+    assert_eq!(
+        execute("SELECT account_id, MAX(id) FROM account_tbl GROUP BY account_id"),
+        vec!["30\t1", "-5\t2", "100\t3", "15\t4"]
+    );
+}
+
+#[test]
+fn test_count_distinct() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    assert_eq!(
+        execute("SELECT COUNT(DISTINCT account_id) FROM account_tbl"),
+        vec!["0"]
+    );
+    execute(
+        "INSERT INTO account_tbl (id,account_id) VALUES (1,20) (2,10) (1,20) (3,20) (4,30) (2,10) (5,20)",
+    );
+
+    assert_eq!(
+        execute("SELECT COUNT(DISTINCT account_id) FROM account_tbl"),
+        vec!["3"]
+    );
+    assert_eq!(
+        execute(
+            "SELECT COUNT(DISTINCT id), COUNT(DISTINCT account_id) FROM account_tbl WHERE id > 1"
+        ),
+        vec!["4\t3"]
+    );
+    assert_eq!(
+        execute("SELECT account_id, COUNT(DISTINCT id) FROM account_tbl GROUP BY account_id"),
+        vec!["20\t3", "10\t1", "30\t1"]
+    );
+    assert_eq!(
+        execute("SELECT id, COUNT(DISTINCT account_id) FROM account_tbl"),
+        vec!["Column id must appear in the GROUP BY clause"]
+    );
+    assert_eq!(
+        execute("SELECT * FROM account_tbl GROUP BY account_id"),
+        vec!["Invalid select expressions"]
+    );
+}
+
+#[test]
+fn test_rank_and_dense_rank() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute(
+        "INSERT INTO account_tbl (id,account_id) VALUES (1,20) (2,10) (3,20) (4,30) (5,10) (6,20)",
+    );
+
+    assert_eq!(
+        execute(
+            "SELECT RANK() OVER (ORDER BY account_id), \
+             DENSE_RANK() OVER (ORDER BY account_id), id FROM account_tbl"
+        ),
+        vec!["1\t1\t2", "1\t1\t5", "3\t2\t1", "3\t2\t3", "3\t2\t6", "6\t3\t4"]
+    );
+}
+
+#[test]
+fn test_ntile() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    let values: Vec<String> = (1..=7).map(|i| format!("({},{})", i, 100 - i)).collect();
+    execute(&format!(
+        "INSERT INTO account_tbl (id,account_id) VALUES {}",
+        values.join(" ")
+    ));
+
+    assert_eq!(
+        execute("SELECT NTILE(3) OVER (ORDER BY account_id), id FROM account_tbl"),
+        vec!["1\t7", "1\t6", "1\t5", "2\t4", "2\t3", "3\t2", "3\t1"]
+    );
+    assert_eq!(
+        execute("SELECT NTILE(0) OVER (ORDER BY account_id), id FROM account_tbl"),
+        vec!["Invalid select expressions"]
+    );
+}
+
+#[test]
+fn test_lag_and_lead() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    let values: Vec<String> = (1..=4).map(|i| format!("({},{})", i, i * 10)).collect();
+    execute(&format!(
+        "INSERT INTO account_tbl (id,account_id) VALUES {}",
+        values.join(" ")
+    ));
+
+    assert_eq!(
+        execute(
+            "SELECT id, LAG(account_id, 1, 0) OVER (ORDER BY id), \
+             LEAD(account_id, 2, 0) OVER (ORDER BY id) FROM account_tbl"
+        ),
+        vec!["1\t0\t30", "2\t10\t40", "3\t20\t0", "4\t30\t0"]
+    );
+}
+
+/// Reads one frame and returns its message type and payload.
+fn read_message(reader: &mut impl Read) -> (u8, Vec<u8>) {
+    let mut header = [0; 5];
+    reader.read_exact(&mut header).unwrap();
+    let length = u32::from_le_bytes(header[..4].try_into().unwrap());
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload).unwrap();
+    (header[4], payload)
+}
+
+fn send_auth(writer: &mut impl Write, credentials: &str) {
+    let mut frame = (credentials.len() as u32).to_le_bytes().to_vec();
+    frame.push(0x06);
+    frame.extend(credentials.as_bytes());
+    writer.write_all(&frame).unwrap();
+}
+
+#[test]
+fn test_authentication() {
+    const AUTH_REQUIRED: u8 = 0x05;
+    const AUTH_OK: u8 = 0x07;
+    let tmp_dir = tempdir().unwrap();
+    // The bcrypt hash of "secret".
+    std::fs::write(
+        tmp_dir.path().join("users.auth"),
+        "alice:$2b$04$ahSj3B2oycNYUKAALfyI9eRIZjfVybOLup1VLC/wzEI.8T.OfqEBu\n",
+    )
+    .unwrap();
+    let (_server, address) = start_server(
+        tmp_dir.path(),
+        &["--server", "--port", "0", "--auth-file", "users.auth"],
+    );
+    let connect = || {
+        let stream = TcpStream::connect(&address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        assert_eq!(read_message(&mut reader), (AUTH_REQUIRED, vec![]));
+        (stream, reader)
+    };
+
+    let (mut stream, mut reader) = connect();
+    send_query(&mut stream, "SELECT * FROM account_tbl");
+    assert_eq!(read_message(&mut reader).0, AUTH_REQUIRED);
+    send_auth(&mut stream, "alice:wrong");
+    assert_eq!(read_message(&mut reader).0, AUTH_REQUIRED);
+    send_auth(&mut stream, "alice:secret");
+    assert_eq!(read_message(&mut reader).0, AUTH_OK);
+    send_query(
+        &mut stream,
+        "INSERT INTO account_tbl (id,account_id) VALUES (1,10)",
+    );
+    assert_eq!(read_result(&mut reader), vec!["Inserting 1 row(s)"]);
+    send_query(&mut stream, "SET SLOW_QUERY_THRESHOLD = 1");
+    read_result(&mut reader);
+    send_query(&mut stream, "CREATE USER bob WITH PASSWORD 'hunter2'");
+    assert_eq!(read_result(&mut reader), vec!["Created user bob"]);
+    send_query(&mut stream, "CREATE USER bob WITH PASSWORD 'other'");
+    assert_eq!(
+        read_result(&mut reader),
+        vec!["DbError(\"User bob already exists\")"]
+    );
+
+    // Only alice, the first user of the auth file, manages users.
+    let (mut bob, mut bob_reader) = connect();
+    send_auth(&mut bob, "bob:hunter2");
+    assert_eq!(read_message(&mut bob_reader).0, AUTH_OK);
+    send_query(&mut bob, "DROP USER bob");
+    assert_eq!(
+        read_result(&mut bob_reader),
+        vec!["Permission denied for DROP USER"]
+    );
+    send_query(&mut bob, "CREATE USER eve WITH PASSWORD 'x'");
+    assert_eq!(
+        read_result(&mut bob_reader),
+        vec!["Permission denied for CREATE USER"]
+    );
+    for statement in ["FLUSH QUERY LOG", "SHOW QUERY LOG", "CLEAR QUERY LOG"] {
+        send_query(&mut bob, statement);
+        assert_eq!(
+            read_result(&mut bob_reader),
+            vec![format!("Permission denied for {}", statement)]
+        );
+    }
+
+    // The query log keeps no passwords.
+    send_query(&mut stream, "SHOW QUERY LOG LAST 100");
+    let log = read_result(&mut reader);
+    assert!(log
+        .iter()
+        .any(|line| line.ends_with("\tCREATE USER bob WITH PASSWORD '***'")));
+    assert!(!log.iter().any(|line| line.contains("hunter2")));
+    let slow_log = std::fs::read_to_string(tmp_dir.path().join("slow_queries.log")).unwrap();
+    assert!(slow_log.contains("|CREATE USER bob WITH PASSWORD '***'"));
+    assert!(!slow_log.contains("hunter2"));
+
+    send_query(&mut stream, "DROP USER bob");
+    assert_eq!(read_result(&mut reader), vec!["Dropped user bob"]);
+    let auth = std::fs::read_to_string(tmp_dir.path().join("users.auth")).unwrap();
+    assert!(auth.starts_with("alice:") && !auth.contains("bob"));
+
+    // Three failed attempts close the connection.
+    let (mut stream, mut reader) = connect();
+    send_auth(&mut stream, "bob:hunter2");
+    assert_eq!(read_message(&mut reader).0, AUTH_REQUIRED);
+    send_auth(&mut stream, "alice:");
+    assert_eq!(read_message(&mut reader).0, AUTH_REQUIRED);
+    send_auth(&mut stream, "alice");
+    assert_eq!(
+        read_result(&mut reader),
+        vec!["Error: Authentication failed"]
+    );
+    assert_eq!(reader.read(&mut [0; 1]).unwrap(), 0);
+}
+
+#[test]
+fn test_column_grants() {
+    let tmp_dir = tempdir().unwrap();
+    // The bcrypt hash of "secret".
+    std::fs::write(
+        tmp_dir.path().join("users.auth"),
+        "alice:$2b$04$ahSj3B2oycNYUKAALfyI9eRIZjfVybOLup1VLC/wzEI.8T.OfqEBu\n",
+    )
+    .unwrap();
+    let (_server, address) = start_server(
+        tmp_dir.path(),
+        &["--server", "--port", "0", "--auth-file", "users.auth"],
+    );
+    let connect = |credentials: &str| {
+        let mut stream = TcpStream::connect(&address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        assert_eq!(read_message(&mut reader).0, 0x05);
+        send_auth(&mut stream, credentials);
+        assert_eq!(read_message(&mut reader).0, 0x07);
+        move |query: &str| {
+            send_query(&mut stream, query);
+            read_result(&mut reader)
+        }
+    };
+
+    // alice, the first user of the auth file, is not restricted by grants.
+    let mut alice = connect("alice:secret");
+    alice("INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20)");
+    alice("CREATE USER bob WITH PASSWORD 'hunter2'");
+    assert_eq!(
+        alice("GRANT SELECT (id) ON account_tbl TO bob"),
+        vec!["Granted SELECT on account_tbl to bob"]
+    );
+    assert_eq!(alice("SELECT * FROM account_tbl"), vec!["1\t10", "2\t20"]);
+
+    let mut bob = connect("bob:hunter2");
+    assert_eq!(bob("SELECT * FROM account_tbl"), vec!["1", "2"]);
+    assert_eq!(
+        bob("SELECT account_id, id FROM account_tbl WHERE id > 1"),
+        vec!["2"]
+    );
+    assert_eq!(
+        bob("SELECT account_id FROM account_tbl"),
+        vec!["Permission denied for SELECT on account_tbl"]
+    );
+
+    // bob can neither lift his restriction nor read the column another way.
+    assert_eq!(
+        bob("REVOKE SELECT ON account_tbl FROM bob"),
+        vec!["Permission denied for REVOKE"]
+    );
+    assert_eq!(
+        bob("GRANT SELECT (account_id) ON account_tbl TO bob"),
+        vec!["Permission denied for GRANT"]
+    );
+    for query in [
+        "DEBUG DUMP TABLE account_tbl",
+        "TABLE CHECKSUM account_tbl",
+        "COPY account_tbl TO 'accounts.bin' BINARY",
+        "COPY TABLE account_tbl TO accounts_copy",
+        "CREATE MATERIALIZED VIEW accounts_view AS SELECT account_id FROM account_tbl",
+        "WITH accounts AS (SELECT * FROM account_tbl) SELECT * FROM accounts",
+        "SELECT id FROM account_tbl UNION SELECT account_id FROM account_tbl",
+    ] {
+        assert_eq!(
+            bob(query),
+            vec!["Permission denied for SELECT on account_tbl"],
+            "{}",
+            query
+        );
+    }
+    assert!(!tmp_dir.path().join("accounts.bin").exists());
+    // A name that is not a table does not run against account_tbl unchecked.
+    assert_eq!(
+        bob("SELECT * FROM nosuch"),
+        vec!["Table nosuch does not exist"]
+    );
+    assert_eq!(
+        bob("SELECT id FROM nosuch UNION SELECT account_id FROM nosuch"),
+        vec!["Permission denied for SELECT on account_tbl"]
+    );
+    assert_eq!(
+        bob("INSERT INTO nosuch (id,account_id) VALUES (9,99)"),
+        vec!["Table nosuch does not exist"]
+    );
+
+    alice("GRANT INSERT (id) ON account_tbl TO bob");
+    assert_eq!(
+        bob("INSERT INTO account_tbl (id,account_id) VALUES (3,30)"),
+        vec!["Permission denied for INSERT on account_tbl column account_id"]
+    );
+    alice("GRANT INSERT (account_id) ON account_tbl TO bob");
+    assert_eq!(
+        bob("INSERT INTO account_tbl (id,account_id) VALUES (3,30)"),
+        vec!["Inserting 1 row(s)"]
+    );
+    assert_eq!(
+        bob("SHOW GRANTS FOR bob"),
+        vec![
+            "account_tbl\tSELECT\tid",
+            "account_tbl\tINSERT\tid, account_id"
+        ]
+    );
+
+    assert_eq!(
+        alice("REVOKE SELECT ON account_tbl FROM bob"),
+        vec!["Revoked SELECT on account_tbl from bob"]
+    );
+    assert_eq!(
+        bob("SELECT * FROM account_tbl"),
+        vec!["1\t10", "2\t20", "3\t30"]
+    );
+    assert_eq!(
+        bob("SHOW GRANTS FOR bob"),
+        vec!["account_tbl\tINSERT\tid, account_id"]
+    );
+}
+
+#[test]
+fn test_prepared_statements() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    assert_eq!(
+        execute("PREPARE add AS 'INSERT INTO account_tbl (id,account_id) VALUES (?,?)'"),
+        vec!["Prepared add"]
+    );
+    for (id, account_id) in [(1, 10), (2, 20), (3, 30)] {
+        assert_eq!(
+            execute(&format!("EXECUTE add ({}, {})", id, account_id)),
+            vec!["Inserting 1 row(s)"]
+        );
+    }
+
+    execute("PREPARE get AS 'SELECT account_id FROM account_tbl WHERE id = ?'");
+    assert_eq!(execute("EXECUTE get (1)"), vec!["10"]);
+    assert_eq!(execute("EXECUTE get (3)"), vec!["30"]);
+    assert_eq!(execute("EXECUTE get (4)"), Vec::<String>::new());
+    assert_eq!(
+        execute("EXECUTE get (1, 2)"),
+        vec!["Expected 1 argument(s), got 2"]
+    );
+    assert_eq!(
+        execute("EXECUTE missing (1)"),
+        vec!["Prepared statement missing does not exist"]
+    );
+
+    // Prepared statements belong to the connection that prepared them.
+    let mut other = TcpStream::connect(&address).unwrap();
+    let mut other_reader = BufReader::new(other.try_clone().unwrap());
+    send_query(&mut other, "EXECUTE get (1)");
+    assert_eq!(
+        read_result(&mut other_reader),
+        vec!["Prepared statement get does not exist"]
+    );
+}
+
+#[test]
+fn test_show_table_stats() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+    let stats = |rows: Vec<String>| -> Vec<u64> {
+        rows[0]
+            .split('\t')
+            .map(|value| value.parse().unwrap())
+            .collect()
+    };
+
+    let created = stats(execute("SHOW TABLE STATS account_tbl"));
+    assert_eq!(created[..2], [0, 0]);
+    assert_eq!(created[2], created[3]);
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20)");
+    let modified = stats(execute("SHOW TABLE STATS account_tbl"));
+    assert_eq!(modified[0], 2);
+    assert!(modified[1] > 0);
+    assert_eq!(modified[2], created[2]);
+    assert!(modified[3] >= created[3]);
+
+    assert_eq!(
+        execute("ANALYZE TABLE account_tbl"),
+        vec!["Analyzed table account_tbl"]
+    );
+    let analyzed = stats(execute("SHOW TABLE STATS account_tbl"));
+    assert!(analyzed[4] >= analyzed[3]);
+    assert_eq!(
+        execute("SHOW TABLE STATS users"),
+        vec!["Table users does not exist"]
+    );
+}
+
+#[test]
+fn test_views() {
+    let tmp_dir = tempdir().unwrap();
+    let values: Vec<String> = (1..=4).map(|i| format!("({},{})", i, i * 10)).collect();
+    {
+        let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+        let mut stream = TcpStream::connect(&address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut execute = |query: &str| {
+            send_query(&mut stream, query);
+            read_result(&mut reader)
+        };
+
+        execute(&format!(
+            "INSERT INTO account_tbl (id,account_id) VALUES {}",
+            values.join(" ")
+        ));
+        assert_eq!(
+            execute("CREATE VIEW big_accounts AS SELECT * FROM account_tbl WHERE account_id > 15"),
+            vec!["Created view big_accounts"]
+        );
+        assert_eq!(
+            execute("CREATE VIEW account_ids AS SELECT id FROM account_tbl"),
+            vec!["Created view account_ids"]
+        );
+        assert_eq!(
+            execute("SELECT id, account_id FROM big_accounts"),
+            execute("SELECT id, account_id FROM account_tbl WHERE account_id > 15")
+        );
+        assert_eq!(
+            execute("SELECT id FROM big_accounts WHERE account_id < 35"),
+            vec!["2", "3"]
+        );
+        assert_eq!(
+            execute("INSERT INTO big_accounts (id,account_id) VALUES (5,50)"),
+            vec!["Cannot insert into view big_accounts, views are read-only"]
+        );
+    }
+
+    // The views outlive the server.
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+    assert_eq!(execute("SHOW VIEWS"), vec!["big_accounts", "account_ids"]);
+    assert_eq!(
+        execute("SELECT * FROM account_ids"),
+        vec!["1", "2", "3", "4"]
+    );
+    assert_eq!(
+        execute("SELECT * FROM account_ids WHERE id > 1"),
+        vec![
+            "View account_ids has a select list, it can only be read with SELECT * and no WHERE clause"
+        ]
+    );
+    assert_eq!(
+        execute("DROP VIEW account_ids"),
+        vec!["Dropped view account_ids"]
+    );
+    assert_eq!(execute("SHOW VIEWS"), vec!["big_accounts"]);
+}
+
+#[test]
+fn test_materialized_view() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let mut stream = TcpStream::connect(&address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut execute = |query: &str| {
+        send_query(&mut stream, query);
+        read_result(&mut reader)
+    };
+
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (1,10) (2,20) (3,30)");
+    assert_eq!(
+        execute(
+            "CREATE MATERIALIZED VIEW mv_big AS SELECT id FROM account_tbl WHERE account_id > 15"
+        ),
+        vec!["Created materialized view mv_big"]
+    );
+    assert_eq!(execute("SELECT * FROM mv_big"), vec!["2", "3"]);
+    assert_eq!(execute("SELECT id FROM mv_big WHERE id > 2"), vec!["3"]);
+
+    // The stored result only changes on refresh.
+    execute("INSERT INTO account_tbl (id,account_id) VALUES (4,40)");
+    assert_eq!(execute("SELECT * FROM mv_big"), vec!["2", "3"]);
+    assert_eq!(
+        execute("REFRESH MATERIALIZED VIEW mv_big"),
+        vec!["Refreshed materialized view mv_big"]
+    );
+    assert_eq!(execute("SELECT * FROM mv_big"), vec!["2", "3", "4"]);
+
+    assert_eq!(
+        execute("INSERT INTO mv_big (id) VALUES (5)"),
+        vec!["Cannot insert into materialized view mv_big, it only changes on refresh"]
+    );
+    assert_eq!(
+        execute("REFRESH MATERIALIZED VIEW mv_missing"),
+        vec!["Materialized view mv_missing does not exist"]
+    );
+    assert!(tmp_dir.path().join("mv_big.mv").exists());
+}
+
+#[test]
+fn test_processlist_and_kill() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let connect = || {
+        let stream = TcpStream::connect(&address).unwrap();
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        (stream, reader)
+    };
+    let (mut first, mut first_reader) = connect();
+    send_query(&mut first, "SELECT * FROM account_tbl");
+    read_result(&mut first_reader);
+    let (mut second, mut second_reader) = connect();
+    let first_address = first.local_addr().unwrap().to_string();
+    let second_address = second.local_addr().unwrap().to_string();
+    let mut execute = |query: &str| {
+        send_query(&mut second, query);
+        read_result(&mut second_reader)
+    };
+
+    let processes = execute("SHOW PROCESSLIST");
+    assert_eq!(processes.len(), 2);
+    assert_eq!(processes[0], format!("1\t\t{}\t\t0", first_address));
+    assert!(processes[1].starts_with(&format!("2\t\t{}\tSHOW PROCESSLIST\t", second_address)));
+
+    assert_eq!(execute("KILL 1"), vec!["Killed connection 1"]);
+    assert_eq!(first_reader.read(&mut [0; 1]).unwrap_or_default(), 0);
+    let mut processes = execute("SHOW PROCESSLIST");
+    for _ in 0..50 {
+        if processes.len() == 1 {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        processes = execute("SHOW PROCESSLIST");
+    }
+    assert_eq!(processes.len(), 1);
+    assert!(processes[0].starts_with("2\t"));
+    assert_eq!(execute("KILL 9"), vec!["Connection 9 does not exist"]);
+}
+
+#[test]
+fn test_processlist_and_kill_as_user() {
+    let tmp_dir = tempdir().unwrap();
+    // The bcrypt hash of "secret".
+    std::fs::write(
+        tmp_dir.path().join("users.auth"),
+        "alice:$2b$04$ahSj3B2oycNYUKAALfyI9eRIZjfVybOLup1VLC/wzEI.8T.OfqEBu\n\
+         bob:$2b$04$ahSj3B2oycNYUKAALfyI9eRIZjfVybOLup1VLC/wzEI.8T.OfqEBu\n",
+    )
+    .unwrap();
+    let (_server, address) = start_server(
+        tmp_dir.path(),
+        &["--server", "--port", "0", "--auth-file", "users.auth"],
+    );
+    let connect = |credentials: &str| {
+        let mut stream = TcpStream::connect(&address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        assert_eq!(read_message(&mut reader).0, 0x05);
+        send_auth(&mut stream, credentials);
+        assert_eq!(read_message(&mut reader).0, 0x07);
+        move |query: &str| {
+            send_query(&mut stream, query);
+            read_result(&mut reader)
+        }
+    };
+
+    let mut alice = connect("alice:secret");
+    alice("SELECT * FROM account_tbl");
+    let mut bob = connect("bob:secret");
+    bob("SELECT * FROM account_tbl");
+    let mut bob_again = connect("bob:secret");
+    // A connection is listed once it answered its first query.
+    bob_again("SELECT * FROM account_tbl");
+    assert_eq!(alice("SHOW PROCESSLIST").len(), 3);
+
+    // bob only sees and kills his own connections.
+    let processes = bob("SHOW PROCESSLIST");
+    assert_eq!(processes.len(), 2);
+    assert!(processes
+        .iter()
+        .all(|row| row.split('\t').nth(1) == Some("bob")));
+    assert_eq!(
+        bob("KILL 1"),
+        vec!["Permission denied for KILL of connection 1"]
+    );
+    assert_eq!(bob("KILL 3"), vec!["Killed connection 3"]);
+    drop(bob_again);
+
+    assert_eq!(alice("KILL 2"), vec!["Killed connection 2"]);
+}
+
+#[test]
+fn test_lock_table() {
+    let tmp_dir = tempdir().unwrap();
+    let (_server, address) = start_server(tmp_dir.path(), &["--server", "--port", "0"]);
+    let connect = || {
+        let stream = TcpStream::connect(&address).unwrap();
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        (stream, reader)
+    };
+    let execute = |(stream, reader): &mut (TcpStream, BufReader<TcpStream>), query: &str| {
+        send_query(stream, query);
+        read_result(reader)
+    };
+    let answered = |(stream, _): &(TcpStream, BufReader<TcpStream>)| {
+        stream
+            .set_read_timeout(Some(std::time::Duration::from_millis(200)))
+            .unwrap();
+        let answered = stream.peek(&mut [0; 1]).is_ok();
+        stream.set_read_timeout(None).unwrap();
+        answered
+    };
+    let (mut first, mut second) = (connect(), connect());
+
+    assert_eq!(
+        execute(&mut first, "LOCK TABLE account_tbl WRITE"),
+        vec!["Locked table account_tbl for WRITE"]
+    );
+    assert_eq!(
+        execute(
+            &mut first,
+            "INSERT INTO account_tbl (id,account_id) VALUES (1,10)"
+        ),
+        vec!["Inserting 1 row(s)"]
+    );
+    // Readers wait for the write lock.
+    send_query(&mut second.0, "SELECT * FROM account_tbl");
+    assert!(!answered(&second));
+    assert_eq!(
+        execute(&mut first, "UNLOCK TABLE account_tbl"),
+        vec!["Unlocked table account_tbl"]
+    );
+    assert_eq!(read_result(&mut second.1), vec!["1\t10"]);
+    assert_eq!(
+        execute(&mut first, "UNLOCK TABLE account_tbl"),
+        vec!["Table account_tbl is not locked by this connection"]
+    );
+
+    // A read lock lets others read but not write.
+    execute(&mut first, "LOCK TABLE account_tbl READ");
+    assert_eq!(
+        execute(&mut second, "SELECT * FROM account_tbl"),
+        vec!["1\t10"]
+    );
+    execute(&mut second, "SET lock_timeout = 1");
+    assert_eq!(
+        execute(
+            &mut second,
+            "INSERT INTO account_tbl (id,account_id) VALUES (2,20)"
+        ),
+        vec!["Timed out waiting for account_tbl to be unlocked"]
+    );
+    assert_eq!(
+        execute(&mut second, "LOCK TABLE account_tbl WRITE"),
+        vec!["Timed out waiting for the lock on account_tbl"]
+    );
+
+    // Disconnecting releases the locks of the connection.
+    execute(&mut second, "SET lock_timeout = 30");
+    send_query(
+        &mut second.0,
+        "INSERT INTO account_tbl (id,account_id) VALUES (2,20)",
+    );
+    assert!(!answered(&second));
+    drop(first);
+    assert_eq!(read_result(&mut second.1), vec!["Inserting 1 row(s)"]);
+}